@@ -0,0 +1,137 @@
+//! Generators of which object to retrieve next, for benchmarking the cache with something closer
+//! to real traffic than retrieving every object in turn. Real issue traffic is heavily skewed
+//! towards a few popular issues and towards recent issues, uniform access overstates the cache
+//! miss rate.
+
+use std::collections::HashSet;
+use std::str::FromStr;
+use std::time::Instant;
+
+use rand::distributions::{Distribution, WeightedIndex};
+use rand::seq::SliceRandom;
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+
+use super::bench::Stats;
+use super::cache::Counters;
+use super::lite_monorepo::{error, LiteMonorepo};
+
+#[derive(Debug, Clone, Copy)]
+pub enum Pattern {
+    /// Every object is equally likely to be retrieved
+    Uniform,
+    /// The k'th most popular object is retrieved with probability proportional to 1/k^exponent
+    Zipf { exponent: f64 },
+    /// The probability of retrieving an object halves for every `half_life` fraction of the
+    /// objects which are more recent than it. E.g. with a half life of 0.1 the newest 10% of the
+    /// objects receive half of the requests.
+    RecentBiased { half_life: f64 },
+}
+
+/// The name of a pattern as given on the command line, the parameters are given separately
+#[derive(Debug, Clone, Copy)]
+pub enum PatternName {
+    Uniform,
+    Zipf,
+    RecentBiased,
+}
+
+#[derive(Debug, Error)]
+#[error("access pattern must be one of zipf, uniform, or recent-biased")]
+pub struct ParsePatternError {}
+
+impl FromStr for PatternName {
+    type Err = ParsePatternError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "uniform" => Ok(PatternName::Uniform),
+            "zipf" => Ok(PatternName::Zipf),
+            "recent-biased" => Ok(PatternName::RecentBiased),
+            _ => Err(ParsePatternError {}),
+        }
+    }
+}
+
+impl std::fmt::Display for Pattern {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Pattern::Uniform => write!(f, "uniform"),
+            Pattern::Zipf { exponent } => write!(f, "zipf (exponent {})", exponent),
+            Pattern::RecentBiased { half_life } => {
+                write!(f, "recent-biased (half life {})", half_life)
+            }
+        }
+    }
+}
+
+pub struct Sampler {
+    /// `order[k]` is the index of the object with the k'th largest weight
+    order: Vec<usize>,
+    weights: WeightedIndex<f64>,
+}
+
+impl Sampler {
+    /// A sampler of indices into a collection of objects. `by_recency` contains the index of every
+    /// object, newest first, and must not be empty. For patterns other than `RecentBiased` which
+    /// objects are popular is chosen at random using `rng`.
+    pub fn new<R: Rng>(pattern: Pattern, by_recency: &[usize], rng: &mut R) -> Sampler {
+        let n = by_recency.len();
+        let mut order = by_recency.to_vec();
+        let weights: Vec<f64> = match pattern {
+            Pattern::Uniform => vec![1.0; n],
+            Pattern::Zipf { exponent } => {
+                order.shuffle(rng);
+                (1..=n).map(|k| 1.0 / (k as f64).powf(exponent)).collect()
+            }
+            Pattern::RecentBiased { half_life } => (0..n)
+                .map(|k| 0.5f64.powf(k as f64 / (n as f64 * half_life)))
+                .collect(),
+        };
+        Sampler {
+            order,
+            weights: WeightedIndex::new(weights).expect("weights are positive and non-empty"),
+        }
+    }
+
+    pub fn sample<R: Rng>(&self, rng: &mut R) -> usize {
+        self.order[self.weights.sample(rng)]
+    }
+}
+
+pub struct ReplayReport {
+    pub latency: Option<Stats>,
+    pub counters: Counters,
+    /// The number of distinct objects which were retrieved
+    pub distinct: usize,
+}
+
+/// Perform `requests` retrievals of the objects in `by_recency` (newest first) following
+/// `pattern`. The same `seed` always produces the same sequence of retrievals.
+pub fn replay(
+    monorepo: &LiteMonorepo,
+    by_recency: &[cob::ObjectId],
+    pattern: Pattern,
+    requests: usize,
+    seed: u64,
+    use_cache: bool,
+) -> Result<ReplayReport, error::Retrieve> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let indices: Vec<usize> = (0..by_recency.len()).collect();
+    let sampler = Sampler::new(pattern, &indices, &mut rng);
+    monorepo.cache().reset_counters();
+    let mut samples = Vec::with_capacity(requests);
+    let mut distinct = HashSet::new();
+    for _ in 0..requests {
+        let id = &by_recency[sampler.sample(&mut rng)];
+        distinct.insert(*id);
+        let start = Instant::now();
+        monorepo.retrieve_issue(id, use_cache)?;
+        samples.push(start.elapsed());
+    }
+    Ok(ReplayReport {
+        latency: Stats::from_samples(samples),
+        counters: monorepo.cache().counters(),
+        distinct: distinct.len(),
+    })
+}
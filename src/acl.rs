@@ -0,0 +1,153 @@
+//! A prototype of application-level access control on top of cob. Issues imported with an ACL
+//! record, under `acl`, the URN of the only identity allowed to change each field, and under
+//! `actors`, the identity behind each automerge actor which has changed the document. cob itself
+//! doesn't enforce any of this, so [`check`] replays the history of an issue one change at a time
+//! to find changes which altered a field their author wasn't allowed to.
+//!
+//! The author of a change is whoever `actors` says is behind its automerge actor, and `actors` is
+//! part of the document, which anyone can change. A change which rewrites or removes the entry of
+//! an actor, or adds an entry for an actor other than its own, is reported as a violation of the
+//! `actors` field. That still leaves an actor free to claim any identity with its first change, so
+//! the check only holds against peers who record themselves honestly. Attributing each change to
+//! the key which signed its commit, as `crate::delegate_only` does, would close this, but needs
+//! the commits rather than the history alone.
+use rand::{seq::SliceRandom, SeedableRng};
+use thiserror::Error;
+
+use crate::lite_monorepo::{error, LiteMonorepo};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error("failed to load the history: {0}")]
+    Load(String),
+    #[error("failed to apply a change: {0}")]
+    Apply(String),
+}
+
+/// A change which altered a field it wasn't allowed to
+#[derive(Debug)]
+pub struct Violation {
+    pub field: String,
+    /// The identity which made the change, if the change recorded one
+    pub editor: Option<String>,
+    /// The identity which is allowed to change `field`
+    pub owner: String,
+}
+
+/// Replay `history` and return every change which violates the ACL of the document
+pub fn check(history: &[u8]) -> Result<Vec<Violation>, Error> {
+    let changes =
+        automerge::Change::load_document(history).map_err(|e| Error::Load(e.to_string()))?;
+    let mut backend = automerge::Backend::new();
+    let mut frontend = automerge::Frontend::new();
+    let mut previous = serde_json::Value::Null;
+    let mut violations = Vec::new();
+    for change in changes {
+        let actor = change.actor_id().to_hex_string();
+        let patch = backend
+            .apply_changes(vec![change])
+            .map_err(|e| Error::Apply(e.to_string()))?;
+        frontend
+            .apply_patch(patch)
+            .map_err(|e| Error::Apply(e.to_string()))?;
+        let current = frontend.state().to_json();
+        if let Some(acl) = current.get("acl").and_then(|a| a.as_object()) {
+            let identity = |doc: &serde_json::Value| {
+                doc.get("actors")
+                    .and_then(|a| a.get(&actor))
+                    .and_then(|u| u.as_str())
+                    .map(String::from)
+            };
+            // What the actor claimed before this change, if it had, so that a change can't
+            // vouch for itself by rewriting its own entry
+            let editor = identity(&previous).or_else(|| identity(&current));
+            if let Some(rewritten) = rewritten_actor(&previous, &current, &actor) {
+                violations.push(Violation {
+                    field: "actors".to_string(),
+                    editor: editor.clone(),
+                    owner: rewritten,
+                });
+            }
+            let editor = editor.as_deref();
+            for (field, owner) in acl {
+                let owner = owner.as_str().unwrap_or_default();
+                if previous.get(field) != current.get(field) && editor != Some(owner) {
+                    violations.push(Violation {
+                        field: field.clone(),
+                        editor: editor.map(|e| e.to_string()),
+                        owner: owner.to_string(),
+                    });
+                }
+            }
+        }
+        previous = current;
+    }
+    Ok(violations)
+}
+
+/// The identity recorded for an actor other than `actor` which the change from `previous` to
+/// `current` rewrote, removed or added, or for `actor` if its entry was rewritten or removed
+fn rewritten_actor(
+    previous: &serde_json::Value,
+    current: &serde_json::Value,
+    actor: &str,
+) -> Option<String> {
+    let (before, after) = (actors(previous), actors(current));
+    let identity = |identity: &serde_json::Value| identity.as_str().unwrap_or_default().to_string();
+    if let Some(before) = before {
+        for (id, recorded) in before {
+            if after.and_then(|a| a.get(id)) != Some(recorded) {
+                return Some(identity(recorded));
+            }
+        }
+    }
+    after?
+        .iter()
+        .find(|(id, _)| *id != actor && before.map_or(true, |b| !b.contains_key(*id)))
+        .map(|(_, recorded)| identity(recorded))
+}
+
+fn actors(doc: &serde_json::Value) -> Option<&serde_json::Map<String, serde_json::Value>> {
+    doc.get("actors").and_then(|a| a.as_object())
+}
+
+/// Have peers other than the creator change the titles of `count` randomly chosen issues which
+/// have an ACL. Returns the number of issues which were changed.
+pub fn inject_violations(
+    monorepo: &mut LiteMonorepo,
+    count: usize,
+    seed: u64,
+) -> Result<usize, Error> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let mut candidates: Vec<(cob::ObjectId, String)> = monorepo
+        .materialized_issues()?
+        .into_iter()
+        .filter(|i| i.document.get("acl").is_some())
+        .filter_map(|i| {
+            let author = i.document.get("author_urn")?.as_str()?.to_string();
+            Some((i.id, author))
+        })
+        .collect();
+    candidates.shuffle(&mut rng);
+
+    let mut peers = Vec::new();
+    for peer in monorepo.peer_ids().cloned().collect::<Vec<_>>() {
+        if let Some(urn) = monorepo.peer_urn(&peer)? {
+            peers.push((peer, urn.to_string()));
+        }
+    }
+
+    let mut injected = 0;
+    for (id, author) in candidates.into_iter().take(count) {
+        let others: Vec<_> = peers.iter().filter(|(_, urn)| *urn != author).collect();
+        if let Some((editor, _)) = others.choose(&mut rng) {
+            monorepo.edit_title(&id, editor, "title changed without permission")?;
+            injected += 1;
+        }
+    }
+    Ok(injected)
+}
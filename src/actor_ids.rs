@@ -0,0 +1,204 @@
+//! Automerge identifies the author of each change by an actor ID, and each change records the
+//! actors of the changes it depends on. Imports make each change with a fresh frontend, so every
+//! change has an actor of its own and a document has as many actors as it has changes. The
+//! actors of a document are one of the things automerge is known to scale with, in the size of
+//! saved documents and the work of applying changes, whereas in practice a peer would keep its
+//! actor ID from one change to the next.
+//!
+//! With `--actor-ids per-peer` each peer uses an actor ID derived from its peer ID for every
+//! change it makes. Automerge requires the changes of an actor to form a sequence, so this is
+//! only sound while no peer makes two changes to an object concurrently, which imports only do
+//! with `--concurrent-comments` when a peer has been assigned more than one GitHub user.
+//!
+//! `bench-actor-ids` builds the histories of issues under both policies without involving git
+//! and compares the actors per document, the size of the changes and of the saved documents, and
+//! the time taken to apply the changes.
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use link_identities::git::Urn;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::{
+    downloaded_issue::DownloadedIssue,
+    layout::Layout,
+    lite_monorepo::{add_comment_change, init_issue_change, materialize, Clock},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ActorIds {
+    /// A new actor for every change
+    Fresh,
+    /// The same actor for every change of a peer
+    PerPeer,
+}
+
+impl ActorIds {
+    pub fn all() -> [ActorIds; 2] {
+        [ActorIds::Fresh, ActorIds::PerPeer]
+    }
+}
+
+impl Default for ActorIds {
+    fn default() -> Self {
+        ActorIds::Fresh
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("expected fresh or per-peer")]
+pub struct ParseActorIdsError {}
+
+impl FromStr for ActorIds {
+    type Err = ParseActorIdsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "fresh" => Ok(ActorIds::Fresh),
+            "per-peer" => Ok(ActorIds::PerPeer),
+            _ => Err(ParseActorIdsError {}),
+        }
+    }
+}
+
+impl std::fmt::Display for ActorIds {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ActorIds::Fresh => write!(f, "fresh"),
+            ActorIds::PerPeer => write!(f, "per-peer"),
+        }
+    }
+}
+
+/// The actor ID of `peer`, the first 16 bytes of the hash of its ID so that it is the same size
+/// as the random actor IDs automerge generates
+pub fn actor_of(peer: &link_crypto::PeerId) -> automerge::ActorId {
+    actor_from_seed(peer.default_encoding().as_bytes())
+}
+
+/// A new actor for the `change`th change made by `peer` in a monorepo created with `seed`
+pub fn seeded_actor(seed: u64, peer: &link_crypto::PeerId, change: u64) -> automerge::ActorId {
+    actor_from_seed(format!("{}-{}-{}", seed, peer, change).as_bytes())
+}
+
+fn actor_from_seed(seed: &[u8]) -> automerge::ActorId {
+    automerge::ActorId::from_bytes(&Sha256::digest(seed)[..16])
+}
+
+/// The histories of `issues` under one policy
+pub struct Report {
+    pub actor_ids: ActorIds,
+    pub issues: usize,
+    pub changes: usize,
+    /// The sum over the issues of the distinct actors in each
+    pub actors: usize,
+    /// The changes as they are stored, one after the other
+    pub history_bytes: usize,
+    /// The documents saved in automerge's compressed format, in which each actor is written once
+    pub document_bytes: usize,
+    pub apply_time: Duration,
+}
+
+/// Build the histories of `issues`, without involving git, with fresh actors and with an actor per
+/// peer, where users are assigned to `peers` peers in the order they first appear
+pub fn compare(issues: &[DownloadedIssue], author_urn: &Urn, peers: usize) -> Vec<Report> {
+    let mut assignments: HashMap<&str, usize> = HashMap::new();
+    let mut peer_of = |user: Option<&str>| {
+        let next = assignments.len() % peers.max(1);
+        *assignments.entry(user.unwrap_or_default()).or_insert(next)
+    };
+    let mut authors = Vec::with_capacity(issues.len());
+    for issue in issues {
+        let creator = peer_of(issue.author_id.as_ref().map(|a| a.0.as_str()));
+        let commenters: Vec<usize> = issue
+            .comments
+            .iter()
+            .map(|c| peer_of(c.author_id.as_ref().map(|a| a.0.as_str())))
+            .collect();
+        authors.push((creator, commenters));
+    }
+    let peer_actors: Vec<automerge::ActorId> = (0..peers.max(1))
+        .map(|i| actor_from_seed(format!("peer-{}", i).as_bytes()))
+        .collect();
+
+    ActorIds::all()
+        .iter()
+        .map(|actor_ids| {
+            let actor = |peer: usize| match actor_ids {
+                ActorIds::Fresh => None,
+                ActorIds::PerPeer => Some(&peer_actors[peer]),
+            };
+            let mut report = Report {
+                actor_ids: *actor_ids,
+                issues: issues.len(),
+                changes: 0,
+                actors: 0,
+                history_bytes: 0,
+                document_bytes: 0,
+                apply_time: Duration::default(),
+            };
+            for (issue, (creator, commenters)) in issues.iter().zip(&authors) {
+                let init = init_issue_change(
+                    issue,
+                    author_urn,
+                    false,
+                    Layout::default(),
+                    Clock::default(),
+                    actor(*creator),
+                );
+                let mut history = init.as_ref().to_vec();
+                for (comment, commenter) in issue.comments.iter().zip(commenters) {
+                    let change = add_comment_change(
+                        comment,
+                        author_urn,
+                        &cob::History::Automerge(history.clone()),
+                        Clock::default(),
+                        actor(*commenter),
+                    );
+                    history.extend_from_slice(change.as_ref());
+                }
+
+                let changes = automerge::Change::load_document(&history).unwrap();
+                report.changes += changes.len();
+                report.actors += changes
+                    .iter()
+                    .map(|c| c.actor_id().clone())
+                    .collect::<HashSet<_>>()
+                    .len();
+                let mut backend = automerge::Backend::new();
+                backend.apply_changes(changes).unwrap();
+                report.document_bytes += backend.save().unwrap().len();
+                report.history_bytes += history.len();
+
+                let history = cob::History::Automerge(history);
+                let start = Instant::now();
+                materialize(&history);
+                report.apply_time += start.elapsed();
+            }
+            report
+        })
+        .collect()
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let per_issue = |n: usize| n as f64 / self.issues.max(1) as f64;
+        write!(
+            f,
+            "{:<9} {} changes, {:.1} actors per issue, history {} bytes ({:.0} per issue), saved {} bytes ({:.0} per issue), apply {:?} ({:?} per issue)",
+            self.actor_ids,
+            self.changes,
+            per_issue(self.actors),
+            self.history_bytes,
+            per_issue(self.history_bytes),
+            self.document_bytes,
+            per_issue(self.document_bytes),
+            self.apply_time,
+            self.apply_time / self.issues.max(1) as u32
+        )
+    }
+}
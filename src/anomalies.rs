@@ -0,0 +1,247 @@
+//! Flagging anomalies as they happen during long runs, rather than leaving them to be found in
+//! the timings afterwards. Every create, update and retrieval a [`LiteMonorepo`] makes while a
+//! [`Detector`] is set is fed to it, and it keeps rolling statistics of each operation over the
+//! last `window` of them:
+//!
+//! * a latency spike is an operation which took more than `sigma` standard deviations longer
+//!   than the mean of the window, once the window has enough samples to go on
+//! * a throughput collapse is a window of operations which completed at less than half the rate
+//!   of the windows before it
+//! * memory growth is the resident set size of the process growing to half as large again as it
+//!   was after the first window, and then each further quarter
+//!
+//! Anomalies are reported with when they happened and the object involved, at the end of the
+//! command and in `anomalies.jsonl` in the repository's storage root.
+//!
+//! [`LiteMonorepo`]: crate::lite_monorepo::LiteMonorepo
+use std::{
+    collections::{HashMap, VecDeque},
+    path::Path,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+
+pub const ANOMALIES_LOG: &str = "anomalies.jsonl";
+
+/// The fewest samples of an operation a latency is compared against
+const MIN_SAMPLES: usize = 30;
+
+/// The number of earlier windows the throughput of a window is compared against
+const THROUGHPUT_HISTORY: usize = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    /// How many standard deviations above the mean a latency must be to be a spike
+    pub sigma: f64,
+    /// The number of operations statistics are kept over
+    pub window: usize,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Kind {
+    LatencySpike {
+        operation: String,
+        latency_millis: f64,
+        mean_millis: f64,
+        stddev_millis: f64,
+    },
+    ThroughputCollapse {
+        per_sec: f64,
+        baseline_per_sec: f64,
+    },
+    MemoryGrowth {
+        rss_bytes: u64,
+        baseline_bytes: u64,
+    },
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Anomaly {
+    pub at: DateTime<Utc>,
+    /// The command which was running
+    pub command: String,
+    /// The object of the operation which showed the anomaly
+    pub object_id: Option<String>,
+    #[serde(flatten)]
+    pub kind: Kind,
+}
+
+pub struct Detector {
+    command: String,
+    options: Options,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    latencies: HashMap<String, VecDeque<f64>>,
+    /// Operations since the current window started, and when it started
+    in_window: usize,
+    window_started: Option<Instant>,
+    throughputs: VecDeque<f64>,
+    baseline_rss: Option<u64>,
+    last_flagged_rss: u64,
+    anomalies: Vec<Anomaly>,
+}
+
+impl Detector {
+    pub fn new(command: &str, options: Options) -> Detector {
+        Detector {
+            command: command.to_string(),
+            options,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    /// Record an `operation` on `object` which took `latency`
+    pub fn record(&self, operation: &str, object: Option<&cob::ObjectId>, latency: Duration) {
+        let mut state = self.state.lock().unwrap();
+        let now = Instant::now();
+        let window_started = *state.window_started.get_or_insert(now);
+        let mut found = Vec::new();
+
+        let millis = latency.as_secs_f64() * 1000.0;
+        let samples = state.latencies.entry(operation.to_string()).or_default();
+        if samples.len() >= MIN_SAMPLES.min(self.options.window) {
+            let (mean, stddev) = mean_and_stddev(samples);
+            if stddev > 0.0 && millis > mean + self.options.sigma * stddev {
+                found.push(Kind::LatencySpike {
+                    operation: operation.to_string(),
+                    latency_millis: millis,
+                    mean_millis: mean,
+                    stddev_millis: stddev,
+                });
+            }
+        }
+        samples.push_back(millis);
+        if samples.len() > self.options.window {
+            samples.pop_front();
+        }
+
+        state.in_window += 1;
+        if state.in_window >= self.options.window {
+            let per_sec = state.in_window as f64 / now.duration_since(window_started).as_secs_f64();
+            if state.throughputs.len() >= 3 {
+                let baseline =
+                    state.throughputs.iter().sum::<f64>() / state.throughputs.len() as f64;
+                if per_sec < baseline / 2.0 {
+                    found.push(Kind::ThroughputCollapse {
+                        per_sec,
+                        baseline_per_sec: baseline,
+                    });
+                }
+            }
+            state.throughputs.push_back(per_sec);
+            if state.throughputs.len() > THROUGHPUT_HISTORY {
+                state.throughputs.pop_front();
+            }
+            state.in_window = 0;
+            state.window_started = Some(now);
+
+            if let Some(rss) = resident_set_size() {
+                let baseline = *state.baseline_rss.get_or_insert(rss);
+                let threshold = (baseline as f64 * 1.5).max(state.last_flagged_rss as f64 * 1.25);
+                if rss as f64 > threshold {
+                    state.last_flagged_rss = rss;
+                    found.push(Kind::MemoryGrowth {
+                        rss_bytes: rss,
+                        baseline_bytes: baseline,
+                    });
+                }
+            }
+        }
+
+        for kind in found {
+            let anomaly = Anomaly {
+                at: Utc::now(),
+                command: self.command.clone(),
+                object_id: object.map(|id| id.to_string()),
+                kind,
+            };
+            verbose!("anomaly: {}", anomaly);
+            state.anomalies.push(anomaly);
+        }
+    }
+
+    /// The anomalies found so far, oldest first
+    pub fn anomalies(&self) -> Vec<Anomaly> {
+        self.state.lock().unwrap().anomalies.clone()
+    }
+}
+
+fn mean_and_stddev(samples: &VecDeque<f64>) -> (f64, f64) {
+    let n = samples.len() as f64;
+    let mean = samples.iter().sum::<f64>() / n;
+    let variance = samples.iter().map(|s| (s - mean).powi(2)).sum::<f64>() / n;
+    (mean, variance.sqrt())
+}
+
+/// The resident set size of this process, where `/proc` is available
+fn resident_set_size() -> Option<u64> {
+    let statm = crate::fs::read_to_string("/proc/self/statm").ok()?;
+    let pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+    Some(pages * 4096)
+}
+
+/// Append `anomalies` to the log at `path`
+pub fn record<P: AsRef<Path>>(path: P, anomalies: &[Anomaly]) -> Result<(), std::io::Error> {
+    // One append for them all, so that a failure part way through leaves none of them
+    let mut lines = Vec::new();
+    for anomaly in anomalies {
+        serde_json::to_writer(&mut lines, anomaly)?;
+        lines.push(b'\n');
+    }
+    crate::fs::append_line(path, &lines)?;
+    Ok(())
+}
+
+/// Print `anomalies` as the part of a report they make up
+pub fn report(anomalies: &[Anomaly]) -> String {
+    let mut report = format!("{} anomalies\n", anomalies.len());
+    for anomaly in anomalies {
+        report.push_str(&format!("  {}\n", anomaly));
+    }
+    report
+}
+
+impl std::fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ", self.at.to_rfc3339())?;
+        match &self.kind {
+            Kind::LatencySpike {
+                operation,
+                latency_millis,
+                mean_millis,
+                stddev_millis,
+            } => write!(
+                f,
+                "{} took {:.1}ms, mean {:.1}ms stddev {:.1}ms",
+                operation, latency_millis, mean_millis, stddev_millis
+            )?,
+            Kind::ThroughputCollapse {
+                per_sec,
+                baseline_per_sec,
+            } => write!(
+                f,
+                "throughput fell to {:.1}/s from {:.1}/s",
+                per_sec, baseline_per_sec
+            )?,
+            Kind::MemoryGrowth {
+                rss_bytes,
+                baseline_bytes,
+            } => write!(
+                f,
+                "resident memory grew to {} MiB from {} MiB",
+                rss_bytes / (1024 * 1024),
+                baseline_bytes / (1024 * 1024)
+            )?,
+        }
+        if let Some(object_id) = &self.object_id {
+            write!(f, " at {}", object_id)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,130 @@
+use std::collections::HashMap;
+use std::io::{Read, Write};
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use thiserror::Error;
+
+use super::downloaded_issue::DownloadedIssue;
+use super::fs;
+use super::lite_monorepo::MaterializedIssue;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+/// A `ColdStore` holds the materialized document and the raw automerge history of objects which
+/// have been removed from the hot ref namespace. Each object is stored as two gzipped files named
+/// after the object ID:
+///
+/// ```
+/// cold_store
+/// ├── 43b0d8816cd863b739f65363b54893efbede83b2.automerge.gz
+/// ├── 43b0d8816cd863b739f65363b54893efbede83b2.json.gz
+/// ...
+/// ```
+pub struct ColdStore {
+    dir: PathBuf,
+}
+
+impl ColdStore {
+    pub fn open(dir: PathBuf) -> Result<ColdStore, Error> {
+        if !fs::exists(&dir)? {
+            fs::create_dir_all(&dir)?;
+        }
+        Ok(ColdStore { dir })
+    }
+
+    pub fn store(&self, issue: &MaterializedIssue) -> Result<(), Error> {
+        let document = serde_json::to_vec(&issue.document)?;
+        write_compressed(self.document_path(&issue.id), &document)?;
+        write_compressed(self.history_path(&issue.id), &issue.history)?;
+        Ok(())
+    }
+
+    pub fn document(&self, id: &cob::ObjectId) -> Result<Option<serde_json::Value>, Error> {
+        let path = self.document_path(id);
+        if !fs::exists(&path)? {
+            return Ok(None);
+        }
+        let bytes = read_compressed(path)?;
+        Ok(Some(serde_json::from_slice(&bytes)?))
+    }
+
+    /// The number of objects in the store and the total compressed size in bytes
+    pub fn usage(&self) -> Result<(usize, u64), Error> {
+        let mut objects = 0;
+        let mut bytes = 0;
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            if entry.file_name().to_string_lossy().ends_with(".json.gz") {
+                objects += 1;
+            }
+            bytes += entry.metadata()?.len();
+        }
+        Ok((objects, bytes))
+    }
+
+    fn document_path(&self, id: &cob::ObjectId) -> PathBuf {
+        self.dir.join(format!("{}.json.gz", id))
+    }
+
+    fn history_path(&self, id: &cob::ObjectId) -> PathBuf {
+        self.dir.join(format!("{}.automerge.gz", id))
+    }
+}
+
+fn write_compressed(path: PathBuf, bytes: &[u8]) -> Result<(), std::io::Error> {
+    let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+    encoder.write_all(bytes)?;
+    fs::write_atomic(path, encoder.finish()?)
+}
+
+fn read_compressed(path: PathBuf) -> Result<Vec<u8>, std::io::Error> {
+    let mut decoder = GzDecoder::new(fs::open(path)?);
+    let mut bytes = Vec::new();
+    decoder.read_to_end(&mut bytes)?;
+    Ok(bytes)
+}
+
+/// Choose the issues which should be archived. An issue is a candidate if the downloaded issue it
+/// was imported from is closed and there has been no activity on it since `inactive_since`. At
+/// most `max_fraction` of all the issues will be chosen, oldest activity first.
+pub fn select<'a>(
+    issues: &'a [MaterializedIssue],
+    downloaded: &HashMap<u64, DownloadedIssue>,
+    inactive_since: DateTime<Utc>,
+    max_fraction: f64,
+) -> Vec<&'a MaterializedIssue> {
+    let mut candidates: Vec<(DateTime<Utc>, &MaterializedIssue)> = issues
+        .iter()
+        .filter_map(|i| {
+            let downloaded = downloaded.get(&i.github_issue_number()?)?;
+            if downloaded.state != "CLOSED" {
+                return None;
+            }
+            let last_activity = last_activity(downloaded);
+            if last_activity < inactive_since {
+                Some((last_activity, i))
+            } else {
+                None
+            }
+        })
+        .collect();
+    candidates.sort_by_key(|(last_activity, _)| *last_activity);
+    let limit = (issues.len() as f64 * max_fraction).floor() as usize;
+    candidates.into_iter().take(limit).map(|(_, i)| i).collect()
+}
+
+fn last_activity(issue: &DownloadedIssue) -> DateTime<Utc> {
+    issue
+        .comments
+        .iter()
+        .map(|c| c.updated_at.unwrap_or(c.created_at))
+        .fold(issue.created_at, std::cmp::max)
+}
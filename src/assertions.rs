@@ -0,0 +1,324 @@
+//! The checks a scenario's `assert` steps make. Each either holds or fails with a `Failure`
+//! saying what was expected, what was found and, for assertions about many objects, which objects
+//! were at fault, so that a failed scenario explains itself without rerunning it by hand.
+//!
+//! Assertions only read the state the earlier steps left behind. An assertion about a step which
+//! hasn't run yet, such as `converged` before any `replicate`, is an error in the scenario rather
+//! than a failure.
+use std::collections::HashMap;
+
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    bisect_perf::{Metric, Metrics},
+    lite_monorepo::{error, LiteMonorepo},
+    replication, replication_simulation,
+};
+
+/// The number of details of a failure to print before summarizing the rest
+const MAX_DETAILS: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error("there is no earlier {0} step to check")]
+    NoStep(&'static str),
+    #[error("there is no snapshot named {0}")]
+    NoSnapshot(String),
+    #[error("unknown metric {0}")]
+    UnknownMetric(String),
+}
+
+/// An expected number, either exactly or a range
+#[derive(Debug, Clone, Copy, Deserialize)]
+#[serde(untagged)]
+pub enum Count {
+    Exactly(usize),
+    Between {
+        #[serde(default)]
+        at_least: Option<usize>,
+        #[serde(default)]
+        at_most: Option<usize>,
+    },
+}
+
+impl Count {
+    fn matches(&self, n: usize) -> bool {
+        match self {
+            Count::Exactly(expected) => n == *expected,
+            Count::Between { at_least, at_most } => {
+                at_least.map(|l| n >= l).unwrap_or(true) && at_most.map(|m| n <= m).unwrap_or(true)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Assertion {
+    /// Every object converged on every node in the last `replicate` step
+    Converged,
+    /// The number of objects in the monorepo
+    Objects(Count),
+    /// The number of objects which fail to retrieve without the cache
+    Unretrievable(Count),
+    /// Every object retrieves to the same document as it did when the named snapshot was taken
+    DocumentsEqual(String),
+    /// A metric of the last `bench` step, in milliseconds, is within limits
+    Metric {
+        name: String,
+        #[serde(default)]
+        below: Option<f64>,
+        #[serde(default)]
+        above: Option<f64>,
+    },
+}
+
+/// The documents of every object which could be retrieved
+pub type Snapshot = HashMap<cob::ObjectId, serde_json::Value>;
+
+/// What the assertions can check
+pub struct Context<'a> {
+    pub monorepo: &'a LiteMonorepo,
+    pub replication: Option<&'a replication_simulation::Report>,
+    pub metrics: Option<&'a Metrics>,
+    pub snapshots: &'a HashMap<String, Snapshot>,
+}
+
+/// Why an assertion didn't hold
+#[derive(Debug)]
+pub struct Failure {
+    pub summary: String,
+    /// The objects or nodes at fault, one per line
+    pub details: Vec<String>,
+}
+
+impl Failure {
+    fn new(summary: String) -> Failure {
+        Failure {
+            summary,
+            details: Vec::new(),
+        }
+    }
+
+    fn with_details(summary: String, details: Vec<String>) -> Failure {
+        Failure { summary, details }
+    }
+}
+
+/// Take a snapshot of the documents in `monorepo`, leaving out objects which fail to retrieve
+pub fn snapshot(monorepo: &LiteMonorepo) -> Result<Snapshot, Error> {
+    let mut snapshot = Snapshot::new();
+    for id in replication::object_ids(monorepo.repo())? {
+        if let Ok(Some(doc)) = monorepo.retrieve_issue(&id, false) {
+            snapshot.insert(id, doc);
+        }
+    }
+    Ok(snapshot)
+}
+
+/// Check `assertion`, returning why it failed if it doesn't hold
+pub fn check(context: &Context<'_>, assertion: &Assertion) -> Result<Option<Failure>, Error> {
+    let failure = match assertion {
+        Assertion::Converged => {
+            let report = context.replication.ok_or(Error::NoStep("replicate"))?;
+            if report.is_ok() {
+                None
+            } else {
+                let node = |n: &Option<usize>| match n {
+                    Some(n) => format!("node {}", n),
+                    None => "the hub".to_string(),
+                };
+                let details = report
+                    .missing
+                    .iter()
+                    .map(|(n, id)| format!("{} is missing on {}", id, node(n)))
+                    .chain(
+                        report
+                            .mismatched
+                            .iter()
+                            .map(|(n, id)| format!("{} differs on {}", id, node(n))),
+                    )
+                    .collect();
+                Some(Failure::with_details(
+                    format!(
+                        "the replicas didn't converge: {} objects missing, {} differing",
+                        report.missing.len(),
+                        report.mismatched.len()
+                    ),
+                    details,
+                ))
+            }
+        }
+        Assertion::Objects(expected) => {
+            let objects = replication::object_ids(context.monorepo.repo())?.len();
+            if expected.matches(objects) {
+                None
+            } else {
+                Some(Failure::new(format!(
+                    "{} objects, expected {}",
+                    objects, expected
+                )))
+            }
+        }
+        Assertion::Unretrievable(expected) => {
+            let mut failed = Vec::new();
+            for id in replication::object_ids(context.monorepo.repo())? {
+                if let Err(e) = context.monorepo.retrieve_issue(&id, false) {
+                    failed.push(format!("{}: {}", id, e));
+                }
+            }
+            failed.sort();
+            if expected.matches(failed.len()) {
+                None
+            } else {
+                Some(Failure::with_details(
+                    format!(
+                        "{} objects failed to retrieve, expected {}",
+                        failed.len(),
+                        expected
+                    ),
+                    failed,
+                ))
+            }
+        }
+        Assertion::DocumentsEqual(name) => {
+            let before = context
+                .snapshots
+                .get(name)
+                .ok_or_else(|| Error::NoSnapshot(name.clone()))?;
+            let now = snapshot(context.monorepo)?;
+            let mut details = Vec::new();
+            for (id, doc) in before {
+                match now.get(id) {
+                    None => details.push(format!("{} no longer retrieves", id)),
+                    Some(now) => {
+                        if let Some(difference) = first_difference(doc, now, String::new()) {
+                            details.push(format!("{} differs at {}", id, difference));
+                        }
+                    }
+                }
+            }
+            for id in now.keys().filter(|id| !before.contains_key(id)) {
+                details.push(format!("{} is new since the snapshot", id));
+            }
+            details.sort();
+            if details.is_empty() {
+                None
+            } else {
+                Some(Failure::with_details(
+                    format!(
+                        "{} of {} objects differ from snapshot {}",
+                        details.len(),
+                        before.len(),
+                        name
+                    ),
+                    details,
+                ))
+            }
+        }
+        Assertion::Metric { name, below, above } => {
+            let metric: Metric = name
+                .parse()
+                .map_err(|_| Error::UnknownMetric(name.clone()))?;
+            let value = context.metrics.ok_or(Error::NoStep("bench"))?.get(metric);
+            let mut problems = Vec::new();
+            if let Some(below) = below {
+                if value >= *below {
+                    problems.push(format!("not below {}ms", below));
+                }
+            }
+            if let Some(above) = above {
+                if value <= *above {
+                    problems.push(format!("not above {}ms", above));
+                }
+            }
+            if problems.is_empty() {
+                None
+            } else {
+                Some(Failure::new(format!(
+                    "{} was {:.2}ms, {}",
+                    name,
+                    value,
+                    problems.join(" and ")
+                )))
+            }
+        }
+    };
+    Ok(failure)
+}
+
+/// The JSON pointer of the first place `a` and `b` differ, with the two values there
+fn first_difference(a: &serde_json::Value, b: &serde_json::Value, path: String) -> Option<String> {
+    use serde_json::Value;
+    match (a, b) {
+        (Value::Object(a), Value::Object(b)) => {
+            let mut keys: Vec<&String> = a.keys().chain(b.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            keys.into_iter().find_map(|key| {
+                let path = format!("{}/{}", path, key);
+                match (a.get(key), b.get(key)) {
+                    (Some(a), Some(b)) => first_difference(a, b, path),
+                    (a, b) => Some(describe(&path, a, b)),
+                }
+            })
+        }
+        (Value::Array(a), Value::Array(b)) => (0..a.len().max(b.len())).find_map(|i| {
+            let path = format!("{}/{}", path, i);
+            match (a.get(i), b.get(i)) {
+                (Some(a), Some(b)) => first_difference(a, b, path),
+                (a, b) => Some(describe(&path, a, b)),
+            }
+        }),
+        (a, b) if a == b => None,
+        (a, b) => Some(describe(&path, Some(a), Some(b))),
+    }
+}
+
+fn describe(path: &str, a: Option<&serde_json::Value>, b: Option<&serde_json::Value>) -> String {
+    let show = |v: Option<&serde_json::Value>| match v {
+        Some(v) => v.to_string(),
+        None => "nothing".to_string(),
+    };
+    let path = if path.is_empty() { "/" } else { path };
+    format!("{}: {} was {}", path, show(a), show(b))
+}
+
+impl std::fmt::Display for Count {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Count::Exactly(n) => write!(f, "{}", n),
+            Count::Between {
+                at_least: Some(l),
+                at_most: Some(m),
+            } => write!(f, "between {} and {}", l, m),
+            Count::Between {
+                at_least: Some(l),
+                at_most: None,
+            } => write!(f, "at least {}", l),
+            Count::Between {
+                at_least: None,
+                at_most: Some(m),
+            } => write!(f, "at most {}", m),
+            Count::Between { .. } => write!(f, "any number"),
+        }
+    }
+}
+
+impl std::fmt::Display for Failure {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{}", self.summary)?;
+        for detail in self.details.iter().take(MAX_DETAILS) {
+            writeln!(f, "    {}", detail)?;
+        }
+        if self.details.len() > MAX_DETAILS {
+            writeln!(f, "    and {} more", self.details.len() - MAX_DETAILS)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,133 @@
+//! An append-only record of every object created or updated through a [`LiteMonorepo`], so that
+//! what a multi-hour import did, and how long each operation took, can be looked into after the
+//! fact rather than from scrollback. Each create or update, whether it succeeded or not, is
+//! appended as a line to `audit.jsonl` in the root of the monorepo. Import workers on other
+//! threads append to the same file, each entry with a single write so that lines don't interleave.
+//!
+//! [`LiteMonorepo`]: crate::lite_monorepo::LiteMonorepo
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+pub const AUDIT_LOG: &str = "audit.jsonl";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Operation {
+    Create,
+    Update,
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    /// When the operation started
+    pub timestamp: DateTime<Utc>,
+    pub operation: Operation,
+    /// The number of the issue the operation was made for, if it was made by an import
+    pub issue: Option<u64>,
+    pub peer: String,
+    /// `None` for a create which failed
+    pub object_id: Option<String>,
+    /// The change at the tip of the peer's ref afterwards
+    pub commit: Option<String>,
+    pub duration_millis: u64,
+    /// Why the operation failed, if it did
+    pub error: Option<String>,
+}
+
+impl Entry {
+    pub fn duration(&self) -> Duration {
+        Duration::from_millis(self.duration_millis)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AuditLog {
+    path: PathBuf,
+}
+
+impl AuditLog {
+    pub fn new<P: AsRef<Path>>(path: P) -> AuditLog {
+        AuditLog {
+            path: path.as_ref().to_path_buf(),
+        }
+    }
+
+    pub fn append(&self, entry: &Entry) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        crate::fs::append_line(&self.path, &line)?;
+        Ok(())
+    }
+
+    /// Every entry, oldest first. Lines which can't be parsed, such as one cut short by a crash,
+    /// are skipped.
+    pub fn load(&self) -> Result<Vec<Entry>, Error> {
+        if !crate::fs::exists(&self.path)? {
+            return Ok(Vec::new());
+        }
+        Ok(crate::fs::read_to_string(&self.path)?
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+/// Which entries to show
+#[derive(Debug, Default)]
+pub struct Filter {
+    pub issue: Option<u64>,
+    pub object_id: Option<String>,
+    pub peer: Option<String>,
+    pub since: Option<DateTime<Utc>>,
+    pub failed: bool,
+}
+
+impl Filter {
+    pub fn matches(&self, entry: &Entry) -> bool {
+        self.issue.map_or(true, |issue| entry.issue == Some(issue))
+            && self
+                .object_id
+                .as_ref()
+                .map_or(true, |id| entry.object_id.as_ref() == Some(id))
+            && self.peer.as_ref().map_or(true, |peer| entry.peer == *peer)
+            && self.since.map_or(true, |since| entry.timestamp >= since)
+            && (!self.failed || entry.error.is_some())
+    }
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operation = match self.operation {
+            Operation::Create => "create",
+            Operation::Update => "update",
+        };
+        write!(
+            f,
+            "{} {} {} {} {} {} {:?}",
+            self.timestamp.to_rfc3339(),
+            operation,
+            self.issue.map_or("-".to_string(), |n| format!("#{}", n)),
+            self.object_id.as_deref().unwrap_or("-"),
+            self.peer,
+            self.commit.as_deref().unwrap_or("-"),
+            self.duration()
+        )?;
+        if let Some(error) = &self.error {
+            write!(f, " failed: {}", error)?;
+        }
+        Ok(())
+    }
+}
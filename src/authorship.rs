@@ -0,0 +1,227 @@
+//! Checking that every change was made by who it says it was. Each change commit has the commit
+//! of its author's `Person` identity as a parent and is signed by a key, so across the whole
+//! monorepo we check that
+//!
+//! * every change names an author, and is signed by a key which is a delegate of that author
+//! * the change at the tip of each peer's ref for an object was made by the identity the peer
+//!   presents at `rad/self`, as a peer only ever points its ref at a change it made itself
+//!
+//! Changes further down a peer's history are made by whoever the peer merged, so only the tips are
+//! checked against the namespace. An anomaly means a bug in the import, which assigns the wrong
+//! peer to a change, or a corruption test which forged one.
+use std::{
+    collections::HashMap,
+    convert::TryFrom,
+    time::{Duration, Instant},
+};
+
+use link_crypto::PeerId;
+use link_identities::{sign::Signatures, Person};
+use thiserror::Error;
+
+use crate::{identity_pins, lite_monorepo::LiteMonorepo, replication};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Identity(#[from] identity_pins::Error),
+}
+
+#[derive(Debug)]
+pub enum AnomalyKind {
+    /// None of the change's parents is a `Person` identity
+    NoAuthor,
+    /// The change has no signatures
+    Unsigned,
+    /// The change is signed by a key which isn't a delegate of its author
+    NotDelegate { key: String },
+    /// The tip of `peer`'s ref was made by someone other than the identity `peer` presents
+    WrongNamespace {
+        peer: PeerId,
+        presented: Option<String>,
+    },
+}
+
+#[derive(Debug)]
+pub struct Anomaly {
+    pub object: cob::ObjectId,
+    pub commit: git2::Oid,
+    pub author: Option<String>,
+    pub kind: AnomalyKind,
+}
+
+pub struct Report {
+    pub refs: usize,
+    pub changes: usize,
+    pub anomalies: Vec<Anomaly>,
+    pub elapsed: Duration,
+}
+
+/// Whether `commit` is a cob change rather than an identity
+fn is_change(commit: &git2::Commit<'_>) -> Result<bool, git2::Error> {
+    Ok(commit.tree()?.get_name("change").is_some())
+}
+
+struct Scanner<'a> {
+    repo: &'a git2::Repository,
+    /// Parents which have been tried as identities, and the identity if they were one
+    identities: HashMap<git2::Oid, Option<Person>>,
+    /// Changes which have been checked, with the URN of their author if they have one
+    checked: HashMap<git2::Oid, Option<String>>,
+    anomalies: Vec<Anomaly>,
+}
+
+impl<'a> Scanner<'a> {
+    fn identity(&mut self, oid: git2::Oid) -> Option<&Person> {
+        let repo = self.repo;
+        self.identities
+            .entry(oid)
+            .or_insert_with(|| {
+                let identities: link_identities::Identities<'_, Person> = repo.into();
+                identities.get(oid).ok()
+            })
+            .as_ref()
+    }
+
+    /// Check `tip` and every change it builds on which hasn't been checked yet, returning the
+    /// author of `tip`
+    fn check_history(
+        &mut self,
+        object: cob::ObjectId,
+        tip: git2::Oid,
+    ) -> Result<Option<String>, Error> {
+        let mut pending = vec![tip];
+        while let Some(oid) = pending.pop() {
+            if self.checked.contains_key(&oid) {
+                continue;
+            }
+            let commit = self.repo.find_commit(oid)?;
+            let mut author = None;
+            for parent in commit.parent_ids() {
+                if is_change(&self.repo.find_commit(parent)?)? {
+                    pending.push(parent);
+                } else if author.is_none() {
+                    author = self.identity(parent).cloned();
+                }
+            }
+            let anomaly = match (&author, Signatures::try_from(&commit).ok()) {
+                (None, _) => Some(AnomalyKind::NoAuthor),
+                (Some(_), None) => Some(AnomalyKind::Unsigned),
+                (Some(person), Some(signatures)) => signatures
+                    .iter()
+                    .map(|(key, _)| key)
+                    .find(|key| !person.delegations().iter().any(|d| d == *key))
+                    .map(|key| AnomalyKind::NotDelegate {
+                        key: PeerId::from(*key).to_string(),
+                    }),
+            };
+            let urn = author.map(|p| p.urn().to_string());
+            if let Some(kind) = anomaly {
+                self.anomalies.push(Anomaly {
+                    object,
+                    commit: oid,
+                    author: urn.clone(),
+                    kind,
+                });
+            }
+            self.checked.insert(oid, urn);
+        }
+        Ok(self.checked.get(&tip).cloned().flatten())
+    }
+}
+
+/// Check the authorship of every change in `monorepo`
+pub fn scan(monorepo: &LiteMonorepo) -> Result<Report, Error> {
+    let start = Instant::now();
+    let project = monorepo.project_urn();
+    let mut scanner = Scanner {
+        repo: monorepo.repo(),
+        identities: HashMap::new(),
+        checked: HashMap::new(),
+        anomalies: Vec::new(),
+    };
+    let mut presented: HashMap<PeerId, Option<String>> = HashMap::new();
+    let mut refs = 0;
+    for reference in monorepo.repo().references()? {
+        let reference = reference?;
+        let (name, tip) = match (reference.name(), reference.target()) {
+            (Some(name), Some(tip)) => (name, tip),
+            _ => continue,
+        };
+        let (peer, object) = match (
+            identity_pins::peer_of_ref(name),
+            replication::object_of_ref(name),
+        ) {
+            (Some(peer), Some(object)) => (peer, object),
+            _ => continue,
+        };
+        refs += 1;
+        let author = scanner.check_history(object, tip)?;
+        let presented = match presented.get(&peer) {
+            Some(urn) => urn.clone(),
+            None => {
+                let urn = identity_pins::presented_identity(monorepo.repo(), &project, &peer)?
+                    .map(|u| u.to_string());
+                presented.insert(peer, urn.clone());
+                urn
+            }
+        };
+        if author.is_some() && author != presented {
+            scanner.anomalies.push(Anomaly {
+                object,
+                commit: tip,
+                author,
+                kind: AnomalyKind::WrongNamespace { peer, presented },
+            });
+        }
+    }
+    Ok(Report {
+        refs,
+        changes: scanner.checked.len(),
+        anomalies: scanner.anomalies,
+        elapsed: start.elapsed(),
+    })
+}
+
+impl std::fmt::Display for Anomaly {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} change {}: ", self.object, self.commit)?;
+        let author = self.author.as_deref().unwrap_or("no author");
+        match &self.kind {
+            AnomalyKind::NoAuthor => write!(f, "names no author"),
+            AnomalyKind::Unsigned => write!(f, "by {} is not signed", author),
+            AnomalyKind::NotDelegate { key } => {
+                write!(
+                    f,
+                    "by {} is signed by {}, which is not their key",
+                    author, key
+                )
+            }
+            AnomalyKind::WrongNamespace { peer, presented } => write!(
+                f,
+                "by {} is the tip of the ref of {}, who presents {}",
+                author,
+                peer,
+                presented.as_deref().unwrap_or("no identity")
+            ),
+        }
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for anomaly in &self.anomalies {
+            writeln!(f, "{}", anomaly)?;
+        }
+        writeln!(
+            f,
+            "checked {} changes reachable from {} refs in {:?}, {} anomalies",
+            self.changes,
+            self.refs,
+            self.elapsed,
+            self.anomalies.len()
+        )
+    }
+}
@@ -0,0 +1,129 @@
+//! Coalescing bursts of events on an object into a single change. When issues are imported as
+//! they happen, importing every event as its own change grows the change graph by one commit per
+//! event, even when a handful of comments arrive within seconds of each other. Batching the
+//! events of each object and only importing once the object has been quiet for a while produces
+//! fewer changes, at the cost of a delay before each event becomes visible.
+//!
+//! There is no live import in this tool, so policies are evaluated by replaying the creation and
+//! comment timestamps of the downloaded issues as if they were arriving as events.
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::{bench::Stats, downloaded_issue::DownloadedIssue, GithubUserId};
+
+/// When to import the pending events of an object
+#[derive(Debug, Clone, Copy)]
+pub struct Policy {
+    /// Import once no event has arrived for the object for this long
+    pub window: Duration,
+    /// Import once the oldest pending event is this old, even if events are still arriving
+    pub max_delay: Option<Duration>,
+}
+
+struct Event {
+    at: DateTime<Utc>,
+    author: Option<GithubUserId>,
+}
+
+/// The outcome of replaying a corpus under one policy
+pub struct Report {
+    pub policy: Policy,
+    pub events: usize,
+    /// The number of changes which would have been created, one per batch
+    pub changes: usize,
+    /// How long after it happened each event was imported
+    pub latency: Option<Stats>,
+}
+
+/// The events of `issue` in the order they happened: its creation, comments, and label and state
+/// changes. Events without an author are skipped, as they are when importing.
+fn events(issue: &DownloadedIssue) -> Vec<Event> {
+    let mut events: Vec<Event> = std::iter::once(Event {
+        at: issue.created_at,
+        author: issue.author_id.clone(),
+    })
+    .chain(issue.comments.iter().map(|c| Event {
+        at: c.created_at,
+        author: c.author_id.clone(),
+    }))
+    .chain(issue.events.iter().map(|e| Event {
+        at: e.created_at,
+        author: e.actor_id.clone(),
+    }))
+    .filter(|e| e.author.is_some())
+    .collect();
+    events.sort_by_key(|e| e.at);
+    events
+}
+
+fn to_std(d: chrono::Duration) -> Duration {
+    d.to_std().unwrap_or_default()
+}
+
+/// Split `events` into batches under `policy` and record the latency of each event. A change has
+/// a single author, so a batch is also closed when an event by a different author arrives.
+fn batch(events: &[Event], policy: Policy, latencies: &mut Vec<Duration>) -> usize {
+    let mut batches = 0;
+    let mut start = 0;
+    while start < events.len() {
+        let first = &events[start];
+        let mut end = start + 1;
+        while end < events.len() {
+            let next = &events[end];
+            let quiet = to_std(next.at - events[end - 1].at) <= policy.window;
+            let fresh = policy
+                .max_delay
+                .map_or(true, |max| to_std(next.at - first.at) <= max);
+            if !(quiet && fresh && next.author == first.author) {
+                break;
+            }
+            end += 1;
+        }
+        let debounced = events[end - 1].at + chrono::Duration::from_std(policy.window).unwrap();
+        let imported_at = match policy.max_delay {
+            Some(max) => debounced.min(first.at + chrono::Duration::from_std(max).unwrap()),
+            None => debounced,
+        };
+        for event in &events[start..end] {
+            latencies.push(to_std(imported_at - event.at));
+        }
+        batches += 1;
+        start = end;
+    }
+    batches
+}
+
+/// Replay the events of `issues` under `policy`
+pub fn replay(issues: &[DownloadedIssue], policy: Policy) -> Report {
+    let mut latencies = Vec::new();
+    let mut changes = 0;
+    for issue in issues {
+        changes += batch(&events(issue), policy, &mut latencies);
+    }
+    Report {
+        policy,
+        events: latencies.len(),
+        changes,
+        latency: Stats::from_samples(latencies),
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "window={:?}", self.policy.window)?;
+        if let Some(max) = self.policy.max_delay {
+            write!(f, " max-delay={:?}", max)?;
+        }
+        let saved = 100.0 * (1.0 - self.changes as f64 / self.events.max(1) as f64);
+        write!(
+            f,
+            ": {} events in {} changes ({:.1}% fewer)",
+            self.events, self.changes, saved
+        )?;
+        if let Some(latency) = &self.latency {
+            write!(f, ", latency {}", latency)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,47 @@
+//! Shared pieces of the benchmarking commands
+
+use std::time::Duration;
+
+/// Summary statistics of a set of latency measurements
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Stats {
+    pub count: usize,
+    pub mean: Duration,
+    pub p50: Duration,
+    pub p95: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    /// Returns `None` if there are no samples
+    pub fn from_samples(mut samples: Vec<Duration>) -> Option<Stats> {
+        if samples.is_empty() {
+            return None;
+        }
+        samples.sort_unstable();
+        let total: Duration = samples.iter().sum();
+        let percentile = |p: f64| {
+            let index = ((samples.len() - 1) as f64 * p).round() as usize;
+            samples[index]
+        };
+        Some(Stats {
+            count: samples.len(),
+            mean: total / samples.len() as u32,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            p99: percentile(0.99),
+            max: *samples.last().unwrap(),
+        })
+    }
+}
+
+impl std::fmt::Display for Stats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "n={} mean={:?} p50={:?} p95={:?} p99={:?} max={:?}",
+            self.count, self.mean, self.p50, self.p95, self.p99, self.max
+        )
+    }
+}
@@ -0,0 +1,377 @@
+//! Finding the cob commit which made a benchmark slower. This crate is rebuilt against successive
+//! revisions of a local radicle-link checkout, using cargo path overrides so that neither the
+//! checkout nor `Cargo.toml` are changed, and each build runs `bench-metrics`, a fixed benchmark
+//! which prints its measurements as JSON. The revisions between a good and a bad commit are
+//! bisected along the first parent history until the first commit whose measurement is over the
+//! threshold is found.
+//!
+//! The revisions are checked out into a git worktree in the work directory, and the builds share
+//! a target directory there so that each only rebuilds what changed. Path overrides can't change
+//! the dependencies of the crates they replace, so a revision which adds or removes dependencies
+//! fails to build and stops the bisection.
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+    str::FromStr,
+    time::Instant,
+};
+
+use thiserror::Error;
+
+use crate::{
+    bench::Stats,
+    cob_api,
+    lite_monorepo::{error, LiteMonorepo},
+};
+
+/// The toolchain this crate builds with. Cargo is run from the work directory, where rustup
+/// wouldn't find the `rust-toolchain` file.
+const TOOLCHAIN: &str = include_str!("../rust-toolchain");
+
+/// The directories in the radicle-link checkout of the crates this crate depends on: cob,
+/// link-identities, link-crypto and radicle-git-ext
+const OVERRIDES: &[&str] = &["cob", "link-identities", "link-crypto", "git-ext"];
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Cache(#[from] crate::cache::Error),
+    #[error("`git {command}` failed: {stderr}")]
+    Git { command: String, stderr: String },
+    #[error("building against {0} failed")]
+    Build(String),
+    #[error("the benchmark failed against {revision}: {stderr}")]
+    Benchmark { revision: String, stderr: String },
+    #[error("there are no issues to benchmark")]
+    NoIssues,
+}
+
+/// A measurement taken by `bench-metrics`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Metric {
+    RetrieveMean,
+    RetrieveP50,
+    RetrieveP95,
+    List,
+}
+
+#[derive(Debug, Error)]
+#[error("metric must be one of retrieve_mean, retrieve_p50, retrieve_p95 or list")]
+pub struct ParseMetricError {}
+
+impl FromStr for Metric {
+    type Err = ParseMetricError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "retrieve_mean" => Ok(Metric::RetrieveMean),
+            "retrieve_p50" => Ok(Metric::RetrieveP50),
+            "retrieve_p95" => Ok(Metric::RetrieveP95),
+            "list" => Ok(Metric::List),
+            _ => Err(ParseMetricError {}),
+        }
+    }
+}
+
+impl std::fmt::Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Metric::RetrieveMean => write!(f, "retrieve_mean"),
+            Metric::RetrieveP50 => write!(f, "retrieve_p50"),
+            Metric::RetrieveP95 => write!(f, "retrieve_p95"),
+            Metric::List => write!(f, "list"),
+        }
+    }
+}
+
+/// The output of `bench-metrics`, in milliseconds
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Metrics {
+    pub retrieve_mean: f64,
+    pub retrieve_p50: f64,
+    pub retrieve_p95: f64,
+    pub list: f64,
+    /// The cob API the benchmark was built with, see `cob_api`
+    #[serde(default)]
+    pub cob_api: String,
+}
+
+impl Metrics {
+    pub fn get(&self, metric: Metric) -> f64 {
+        match metric {
+            Metric::RetrieveMean => self.retrieve_mean,
+            Metric::RetrieveP50 => self.retrieve_p50,
+            Metric::RetrieveP95 => self.retrieve_p95,
+            Metric::List => self.list,
+        }
+    }
+}
+
+fn millis(duration: std::time::Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// The fixed benchmark: retrieve the `requests` newest issues without the cache, then list every
+/// issue starting from an empty cache. The cache is emptied first as its entries may have been
+/// written by a build against another revision of cob, whose format may differ.
+pub fn measure(monorepo: &LiteMonorepo, requests: usize) -> Result<Metrics, Error> {
+    monorepo.cache().clear()?;
+    let ids = monorepo.issue_ids_by_recency()?;
+    let mut samples = Vec::new();
+    for id in ids.iter().take(requests) {
+        let start = Instant::now();
+        monorepo.retrieve_issue(id, false)?;
+        samples.push(start.elapsed());
+    }
+    let stats = Stats::from_samples(samples).ok_or(Error::NoIssues)?;
+    monorepo.cache().clear()?;
+    let start = Instant::now();
+    monorepo.list_issues()?;
+    Ok(Metrics {
+        retrieve_mean: millis(stats.mean),
+        retrieve_p50: millis(stats.p50),
+        retrieve_p95: millis(stats.p95),
+        list: millis(start.elapsed()),
+        cob_api: cob_api::VERSION.to_string(),
+    })
+}
+
+pub struct Options {
+    /// A checkout of radicle-link
+    pub cob_repo: PathBuf,
+    /// A revision whose measurement is within the threshold
+    pub good: String,
+    /// A later revision whose measurement is over the threshold
+    pub bad: String,
+    pub metric: Metric,
+    /// In milliseconds
+    pub threshold: f64,
+    /// Where the worktree and the builds are kept
+    pub work_dir: PathBuf,
+    /// The data directory and repository `bench-metrics` is run against
+    pub data_dir: PathBuf,
+    pub repo: String,
+    pub requests: usize,
+    /// The number of times the benchmark is run for each revision, the median is used
+    pub runs: usize,
+}
+
+/// The measurement of one revision
+pub struct Step {
+    pub revision: String,
+    pub summary: String,
+    pub value: f64,
+    pub regressed: bool,
+}
+
+pub enum Outcome {
+    /// The first revision whose measurement was over the threshold
+    Found { revision: String, summary: String },
+    /// The good revision was already over the threshold
+    GoodRegressed,
+    /// The bad revision was within the threshold
+    BadWithinThreshold,
+}
+
+/// Bisect the first parent history from `good` to `bad`, calling `on_step` with the measurement
+/// of each revision as it is taken
+pub fn bisect<F>(options: &Options, mut on_step: F) -> Result<Outcome, Error>
+where
+    F: FnMut(&Step),
+{
+    crate::fs::create_dir_all(&options.work_dir)?;
+    let good = rev_parse(&options.cob_repo, &options.good)?;
+    let bad = rev_parse(&options.cob_repo, &options.bad)?;
+    let range = format!("{}..{}", good, bad);
+    // Oldest first, ending with `bad`
+    let revisions: Vec<String> = git(
+        &options.cob_repo,
+        &["rev-list", "--reverse", "--first-parent", &range],
+    )?
+    .lines()
+    .map(|l| l.to_string())
+    .collect();
+    status!(
+        "{} revisions between {} and {}",
+        revisions.len(),
+        options.good,
+        options.bad
+    );
+
+    let mut regressed = |revision: &str| -> Result<bool, Error> {
+        let step = measure_revision(options, revision)?;
+        on_step(&step);
+        Ok(step.regressed)
+    };
+    if regressed(&good)? {
+        return Ok(Outcome::GoodRegressed);
+    }
+    if revisions.is_empty() || !regressed(&bad)? {
+        return Ok(Outcome::BadWithinThreshold);
+    }
+
+    // `revisions[hi]` is known to be over the threshold, and everything before `lo` within it
+    let (mut lo, mut hi) = (0, revisions.len() - 1);
+    while lo < hi {
+        let mid = lo + (hi - lo) / 2;
+        if regressed(&revisions[mid])? {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    Ok(Outcome::Found {
+        revision: revisions[hi].clone(),
+        summary: summary(&options.cob_repo, &revisions[hi])?,
+    })
+}
+
+fn measure_revision(options: &Options, revision: &str) -> Result<Step, Error> {
+    let worktree = checkout(options, revision)?;
+    build(options, &worktree, revision)?;
+    let binary = target_dir(options)
+        .join("release")
+        .join(env!("CARGO_PKG_NAME"));
+    let mut values = Vec::new();
+    for _ in 0..options.runs.max(1) {
+        let output = Command::new(&binary)
+            .arg("--quiet")
+            .arg("--data-dir")
+            .arg(&options.data_dir)
+            .arg("bench-metrics")
+            .arg(&options.repo)
+            .arg("--requests")
+            .arg(options.requests.to_string())
+            .output()?;
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        let metrics = match stdout.lines().last() {
+            Some(line) if output.status.success() => serde_json::from_str::<Metrics>(line)?,
+            _ => {
+                return Err(Error::Benchmark {
+                    revision: revision.to_string(),
+                    stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+                })
+            }
+        };
+        values.push(metrics.get(options.metric));
+    }
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let value = values[values.len() / 2];
+    Ok(Step {
+        revision: revision.to_string(),
+        summary: summary(&options.cob_repo, revision)?,
+        value,
+        regressed: value > options.threshold,
+    })
+}
+
+/// Check `revision` out into the worktree, creating it the first time
+fn checkout(options: &Options, revision: &str) -> Result<PathBuf, Error> {
+    let worktree = options.work_dir.join("radicle-link");
+    if crate::fs::exists(&worktree)? {
+        git(&worktree, &["checkout", "--quiet", "--detach", revision])?;
+    } else {
+        let path = worktree.to_string_lossy().to_string();
+        git(
+            &options.cob_repo,
+            &["worktree", "add", "--detach", &path, revision],
+        )?;
+    }
+    Ok(worktree)
+}
+
+fn target_dir(options: &Options) -> PathBuf {
+    options.work_dir.join("target")
+}
+
+/// Build this crate in release mode with the radicle-link crates overridden by those in
+/// `worktree`. Cargo's output passes through.
+fn build(options: &Options, worktree: &Path, revision: &str) -> Result<(), Error> {
+    let mut paths = Vec::new();
+    for dir in OVERRIDES {
+        let path = worktree.join(dir);
+        if crate::fs::exists(path.join("Cargo.toml"))? {
+            paths.push(serde_json::to_string(&path.to_string_lossy())?);
+        }
+    }
+    let config_dir = options.work_dir.join(".cargo");
+    crate::fs::create_dir_all(&config_dir)?;
+    crate::fs::write_atomic(
+        config_dir.join("config"),
+        format!("paths = [{}]\n", paths.join(", ")),
+    )?;
+
+    let manifest = Path::new(env!("CARGO_MANIFEST_DIR")).join("Cargo.toml");
+    let lockfile = manifest.with_file_name("Cargo.lock");
+    let lock = crate::fs::read(&lockfile).ok();
+    verbose!("building against {}", revision);
+    let status = Command::new("cargo")
+        .current_dir(&options.work_dir)
+        .env("RUSTUP_TOOLCHAIN", TOOLCHAIN.trim())
+        .arg("build")
+        .arg("--release")
+        .arg("--manifest-path")
+        .arg(&manifest)
+        .arg("--target-dir")
+        .arg(target_dir(options))
+        .status();
+    // Overriding dependencies can rewrite the lockfile, which belongs to the checkout of this crate
+    if let Some(lock) = lock {
+        if crate::fs::read(&lockfile).ok().as_ref() != Some(&lock) {
+            crate::fs::write_atomic(&lockfile, lock)?;
+        }
+    }
+    if status?.success() {
+        Ok(())
+    } else {
+        Err(Error::Build(revision.to_string()))
+    }
+}
+
+fn rev_parse(repo: &Path, revision: &str) -> Result<String, Error> {
+    Ok(git(repo, &["rev-parse", "--verify", revision])?
+        .trim()
+        .to_string())
+}
+
+fn summary(repo: &Path, revision: &str) -> Result<String, Error> {
+    Ok(git(repo, &["log", "-1", "--format=%s", revision])?
+        .trim()
+        .to_string())
+}
+
+fn git(repo: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = Command::new("git")
+        .arg("-C")
+        .arg(repo)
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Git {
+            command: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).to_string())
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} {:>10.2}ms {} {}",
+            &self.revision[..self.revision.len().min(10)],
+            self.value,
+            if self.regressed { "bad " } else { "good" },
+            self.summary
+        )
+    }
+}
@@ -0,0 +1,259 @@
+//! Attributing a field of a document to the changes which wrote it, like `git blame`. This is a
+//! query the radicle UI will need, and answering it from the automerge history means replaying
+//! the history one change at a time, so its cost grows with the length of the history.
+//!
+//! A field is addressed by a path of map keys and list indices separated by `/`, e.g.
+//! `comments/5/comment`. Indices are resolved in each version of the document as it is replayed,
+//! which follows the field as long as nothing is inserted before it in the list. For a
+//! text field each character is attributed to the change which inserted it, worked out by
+//! comparing the text before and after each change. A change which edits the text in several
+//! places is attributed everything between its first and last edit. Any other field is attributed
+//! to the last change which altered it.
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use link_crypto::PeerId;
+use serde_json::Value;
+use thiserror::Error;
+
+use crate::history_log::{self, Entry};
+
+#[derive(Debug, Error)]
+#[error("empty path")]
+pub struct ParseError;
+
+#[derive(Debug, Clone)]
+pub struct FieldPath(Vec<String>);
+
+impl FromStr for FieldPath {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let segments: Vec<String> = s
+            .split('/')
+            .filter(|s| !s.is_empty())
+            .map(String::from)
+            .collect();
+        if segments.is_empty() {
+            Err(ParseError)
+        } else {
+            Ok(FieldPath(segments))
+        }
+    }
+}
+
+impl std::fmt::Display for FieldPath {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0.join("/"))
+    }
+}
+
+impl FieldPath {
+    fn lookup<'a>(&self, doc: &'a Value) -> Option<&'a Value> {
+        self.0.iter().try_fold(doc, |v, segment| match v {
+            Value::Object(m) => m.get(segment),
+            Value::Array(a) => a.get(segment.parse::<usize>().ok()?),
+            _ => None,
+        })
+    }
+
+    /// Whether the field is text in `frontend`, whose state is `doc`
+    fn is_text(&self, frontend: &automerge::Frontend, doc: &Value) -> bool {
+        let mut path = automerge::Path::root();
+        let mut parent = doc;
+        for segment in &self.0 {
+            match parent {
+                Value::Array(a) => match segment.parse::<usize>() {
+                    Ok(i) if i < a.len() => {
+                        path = path.index(i as u32);
+                        parent = &a[i];
+                    }
+                    _ => return false,
+                },
+                Value::Object(m) => match m.get(segment) {
+                    Some(child) => {
+                        path = path.key(segment.as_str());
+                        parent = child;
+                    }
+                    None => return false,
+                },
+                _ => return false,
+            }
+        }
+        matches!(frontend.get_value(&path), Some(automerge::Value::Text(_)))
+    }
+}
+
+pub enum Attribution {
+    /// The field isn't in the final document. If it was removed, the change which removed it.
+    Missing { removed_by: Option<Entry> },
+    /// The change which last altered the field, and how many changes altered it in all
+    Value {
+        value: Value,
+        last: Entry,
+        changes: usize,
+    },
+    /// Runs of characters and the change which inserted them
+    Text(Vec<(Entry, String)>),
+}
+
+pub struct Report {
+    pub path: FieldPath,
+    pub attribution: Attribution,
+    /// The length of the history replayed
+    pub changes: usize,
+    pub elapsed: Duration,
+}
+
+/// The length in chars of the common prefix and of the common suffix of `a` and `b`, such that
+/// they don't overlap in either string
+fn common_ends(a: &[char], b: &[char]) -> (usize, usize) {
+    let prefix = a.iter().zip(b).take_while(|(x, y)| x == y).count();
+    let suffix = a[prefix..]
+        .iter()
+        .rev()
+        .zip(b[prefix..].iter().rev())
+        .take_while(|(x, y)| x == y)
+        .count();
+    (prefix, suffix)
+}
+
+/// Attribute the field at `path` in `history`. `peers` maps the URN of each peer's identity to
+/// the peer.
+pub fn blame(history: &[u8], path: FieldPath, peers: &HashMap<String, PeerId>) -> Report {
+    let start = Instant::now();
+    let mut entries: Vec<Entry> = Vec::new();
+    let mut last_changed: Option<usize> = None;
+    let mut times_changed = 0;
+    let mut value: Option<Value> = None;
+    let mut is_text = false;
+    // The index in `entries` of the change which inserted each character of the text
+    let mut origins: Vec<usize> = Vec::new();
+    let mut text: Vec<char> = Vec::new();
+
+    history_log::replay(history, peers, |entry, frontend, before, after| {
+        let index = entries.len();
+        entries.push(entry);
+        let (old, new) = (path.lookup(before), path.lookup(after));
+        if old == new {
+            return;
+        }
+        last_changed = Some(index);
+        times_changed += 1;
+        value = new.cloned();
+        is_text = new.is_some() && path.is_text(frontend, after);
+        let new_text: Vec<char> = match new.and_then(|v| v.as_str()) {
+            Some(s) if is_text => s.chars().collect(),
+            _ => {
+                origins.clear();
+                text.clear();
+                return;
+            }
+        };
+        let (prefix, suffix) = common_ends(&text, &new_text);
+        let inserted = new_text.len() - prefix - suffix;
+        let kept_suffix = origins.split_off(origins.len() - suffix);
+        origins.truncate(prefix);
+        origins.extend(std::iter::repeat(index).take(inserted));
+        origins.extend(kept_suffix);
+        text = new_text;
+    });
+
+    let attribution = match (last_changed, value) {
+        (None, _) => Attribution::Missing { removed_by: None },
+        (Some(last), None) => Attribution::Missing {
+            removed_by: Some(entries[last].clone()),
+        },
+        (Some(last), Some(_)) if is_text => {
+            let mut runs: Vec<(Entry, String)> = Vec::new();
+            let mut current: Option<(usize, String)> = None;
+            for (c, origin) in text.iter().zip(&origins) {
+                match &mut current {
+                    Some((o, run)) if o == origin => run.push(*c),
+                    _ => {
+                        if let Some((o, run)) = current.take() {
+                            runs.push((entries[o].clone(), run));
+                        }
+                        current = Some((*origin, c.to_string()));
+                    }
+                }
+            }
+            if let Some((o, run)) = current {
+                runs.push((entries[o].clone(), run));
+            }
+            if runs.is_empty() {
+                runs.push((entries[last].clone(), String::new()));
+            }
+            Attribution::Text(runs)
+        }
+        (Some(last), Some(value)) => Attribution::Value {
+            value,
+            last: entries[last].clone(),
+            changes: times_changed,
+        },
+    };
+    Report {
+        path,
+        attribution,
+        changes: entries.len(),
+        elapsed: start.elapsed(),
+    }
+}
+
+/// A short description of who made `entry` and when, for one line of output
+fn describe(entry: &Entry) -> String {
+    use chrono::TimeZone;
+    let who = match (&entry.peer, &entry.author_urn) {
+        (Some(peer), _) => peer.to_string(),
+        (None, Some(urn)) => urn.clone(),
+        (None, None) => format!("actor {}", entry.actor),
+    };
+    let when = chrono::Utc
+        .timestamp_millis_opt(entry.time)
+        .single()
+        .map(|t| t.to_rfc3339())
+        .unwrap_or_default();
+    format!(
+        "{} {} {}",
+        &entry.hash[..8.min(entry.hash.len())],
+        who,
+        when
+    )
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.attribution {
+            Attribution::Missing { removed_by: None } => {
+                writeln!(f, "{} was never set", self.path)?;
+            }
+            Attribution::Missing {
+                removed_by: Some(entry),
+            } => {
+                writeln!(f, "{} was removed by {}", self.path, describe(entry))?;
+            }
+            Attribution::Value {
+                value,
+                last,
+                changes,
+            } => {
+                writeln!(f, "{} = {}", self.path, value)?;
+                writeln!(
+                    f,
+                    "last set by {}, altered by {} changes",
+                    describe(last),
+                    changes
+                )?;
+            }
+            Attribution::Text(runs) => {
+                for (entry, run) in runs {
+                    writeln!(f, "{} {:?}", describe(entry), run)?;
+                }
+            }
+        }
+        writeln!(f, "replayed {} changes in {:?}", self.changes, self.elapsed)
+    }
+}
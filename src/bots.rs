@@ -0,0 +1,119 @@
+//! Bots, such as dependabot or a CI bot posting build results, write a large share of the
+//! comments in some repositories. Imported as they are, each bot is assigned a peer like any
+//! other user and the peers they land on end up with far more changes than any person would
+//! make. With `--bots` the comments of bots are changed before import:
+//!
+//! * `exclude` drops every comment by a bot
+//! * `collapse` keeps only the last of each run of consecutive comments by the same bot, as bots
+//!   tend to post a fresh status comment after each push
+//! * `dedicated-peer` makes every issue, comment and event by a bot the work of a single user,
+//!   [`BOTS_USER`], who is given a peer of their own which no other user is assigned afterwards
+//!
+//! A user is a bot if their login ends in `[bot]` or matches one of the `--bot-logins` globs.
+//! The same options have to be given when resuming an import, as how far an import of an issue
+//! got is counted in the comments and events left after these changes.
+use std::str::FromStr;
+
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use thiserror::Error;
+
+use crate::{downloaded_issue::DownloadedIssue, GithubUserId};
+
+/// The user every bot becomes with `dedicated-peer`, which isn't a valid GitHub login so can't
+/// clash with a real user
+pub const BOTS_USER: &str = "[bots]";
+
+/// The logins treated as bots unless `--bot-logins` is given
+pub const DEFAULT_LOGINS: &[&str] = &[
+    "*-bot",
+    "dependabot*",
+    "renovate*",
+    "github-actions*",
+    "codecov*",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    Exclude,
+    Collapse,
+    DedicatedPeer,
+}
+
+#[derive(Debug, Error)]
+#[error("expected exclude, collapse or dedicated-peer")]
+pub struct ParsePolicyError {}
+
+impl FromStr for Policy {
+    type Err = ParsePolicyError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "exclude" => Ok(Policy::Exclude),
+            "collapse" => Ok(Policy::Collapse),
+            "dedicated-peer" => Ok(Policy::DedicatedPeer),
+            _ => Err(ParsePolicyError {}),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Bots {
+    policy: Policy,
+    logins: GlobSet,
+}
+
+impl Bots {
+    pub fn new<S: AsRef<str>>(policy: Policy, logins: &[S]) -> Result<Bots, globset::Error> {
+        let mut builder = GlobSetBuilder::new();
+        for login in logins {
+            builder.add(Glob::new(login.as_ref())?);
+        }
+        Ok(Bots {
+            policy,
+            logins: builder.build()?,
+        })
+    }
+
+    pub fn is_bot(&self, user: &GithubUserId) -> bool {
+        user.0.ends_with("[bot]") || self.logins.is_match(&user.0)
+    }
+
+    fn is_bot_author(&self, user: &Option<GithubUserId>) -> bool {
+        user.as_ref().map(|u| self.is_bot(u)).unwrap_or(false)
+    }
+
+    /// `issue` as it should be imported
+    pub fn apply(&self, issue: &DownloadedIssue) -> DownloadedIssue {
+        let mut issue = issue.clone();
+        match self.policy {
+            Policy::Exclude => issue.comments.retain(|c| !self.is_bot_author(&c.author_id)),
+            Policy::Collapse => {
+                let mut comments = Vec::with_capacity(issue.comments.len());
+                let mut remaining = issue.comments.into_iter().peekable();
+                while let Some(comment) = remaining.next() {
+                    let superseded = self.is_bot_author(&comment.author_id)
+                        && remaining.peek().map(|next| &next.author_id) == Some(&comment.author_id);
+                    if !superseded {
+                        comments.push(comment);
+                    }
+                }
+                issue.comments = comments;
+            }
+            Policy::DedicatedPeer => {
+                let bots = |user: &mut Option<GithubUserId>| {
+                    if self.is_bot_author(user) {
+                        *user = Some(GithubUserId(BOTS_USER.to_string()));
+                    }
+                };
+                bots(&mut issue.author_id);
+                for comment in &mut issue.comments {
+                    bots(&mut comment.author_id);
+                }
+                for event in &mut issue.events {
+                    bots(&mut event.actor_id);
+                }
+            }
+        }
+        issue
+    }
+}
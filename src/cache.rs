@@ -0,0 +1,500 @@
+//! Bookkeeping for the `cob` cache directory. `cob` creates and updates the cache entries itself
+//! but never removes any, so left alone the cache grows with the number of objects ever
+//! retrieved. Here we keep track of which entries exist, how large they are, and when each was
+//! last accessed, so that the cache can be capped at a maximum size by evicting the least recently
+//! used entries.
+//!
+//! Entries are directories named after the object ID somewhere beneath the cache directory. Access
+//! times are persisted to a JSON file (`cache_access` in the monorepo root) when the cache is
+//! dropped, merged with what other handles on the monorepo saved in the meantime. Finding the
+//! entries means walking the whole cache directory, which is slow for large caches, so it is done
+//! once, the first time the cache is used. After that an entry cob writes is looked for only in
+//! the directories others were found in. cob writes entries whenever it is given the cache, so the
+//! monorepo reports every such call, not just retrievals, and the size cap is enforced after each.
+//!
+//! How long retrievals took, split into cache hits, cache misses and retrievals which didn't use
+//! the cache, is also kept, added to the totals in `cache_stats` in the monorepo root when the
+//! cache is dropped, so that how much the cache helps can be seen across runs.
+
+use std::collections::{BTreeSet, HashMap};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::{Mutex, MutexGuard};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+/// A number of bytes, parsed from strings like `5000`, `512K`, `100M` or `2G`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct ByteSize(pub u64);
+
+#[derive(Debug, Error)]
+#[error("sizes must be a number optionally followed by K, M, or G")]
+pub struct ParseByteSizeError {}
+
+impl FromStr for ByteSize {
+    type Err = ParseByteSizeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (digits, multiplier) = match s.chars().last().map(|c| c.to_ascii_uppercase()) {
+            Some('K') => (&s[..s.len() - 1], 1 << 10),
+            Some('M') => (&s[..s.len() - 1], 1 << 20),
+            Some('G') => (&s[..s.len() - 1], 1 << 30),
+            _ => (s, 1),
+        };
+        let n: u64 = digits.parse().map_err(|_| ParseByteSizeError {})?;
+        Ok(ByteSize(n * multiplier))
+    }
+}
+
+impl std::fmt::Display for ByteSize {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let n = self.0;
+        if n >= 1 << 30 && n % (1 << 30) == 0 {
+            write!(f, "{}G", n >> 30)
+        } else if n >= 1 << 20 && n % (1 << 20) == 0 {
+            write!(f, "{}M", n >> 20)
+        } else if n >= 1 << 10 && n % (1 << 10) == 0 {
+            write!(f, "{}K", n >> 10)
+        } else {
+            write!(f, "{}", n)
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    path: PathBuf,
+    size: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct Counters {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+/// The number of retrievals of one kind and how long they took in total
+#[derive(Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Timing {
+    pub count: u64,
+    pub total_micros: u64,
+}
+
+impl Timing {
+    fn add(&mut self, elapsed: Duration) {
+        self.count += 1;
+        self.total_micros += elapsed.as_micros() as u64;
+    }
+
+    fn merge(&mut self, other: &Timing) {
+        self.count += other.count;
+        self.total_micros += other.total_micros;
+    }
+
+    pub fn mean(&self) -> Option<Duration> {
+        if self.count == 0 {
+            None
+        } else {
+            Some(Duration::from_micros(self.total_micros / self.count))
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, serde::Serialize, serde::Deserialize)]
+pub struct Timings {
+    pub hits: Timing,
+    pub misses: Timing,
+    /// Retrievals which didn't use the cache
+    pub uncached: Timing,
+}
+
+impl Timings {
+    fn merge(&mut self, other: &Timings) {
+        self.hits.merge(&other.hits);
+        self.misses.merge(&other.misses);
+        self.uncached.merge(&other.uncached);
+    }
+}
+
+/// An entry of the cache, as seen by `Cache::entries`
+#[derive(Debug, Clone)]
+pub struct EntryInfo {
+    pub object_id: cob::ObjectId,
+    pub size: u64,
+    /// `None` if the entry hasn't been used since access times started being kept
+    pub last_accessed: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    entries: HashMap<cob::ObjectId, Entry>,
+    /// The directories entries have been found in, so that new entries can be found without
+    /// walking the whole cache
+    parents: BTreeSet<PathBuf>,
+    scanned: bool,
+    max_size: Option<u64>,
+    accessed: HashMap<String, DateTime<Utc>>,
+    /// Entries evicted by this handle and when, so that saving doesn't keep the access times
+    /// other handles saved for them
+    evicted: HashMap<String, DateTime<Utc>>,
+    /// When this handle last cleared the cache
+    cleared: Option<DateTime<Utc>>,
+    counters: Counters,
+    /// The timings of this process, not yet added to those saved
+    timings: Timings,
+}
+
+pub struct Cache {
+    dir: PathBuf,
+    index_path: PathBuf,
+    stats_path: PathBuf,
+    state: Mutex<State>,
+}
+
+impl Cache {
+    pub fn open(dir: PathBuf, index_path: PathBuf, stats_path: PathBuf) -> Result<Cache, Error> {
+        let accessed = saved_accessed(&index_path)?;
+        Ok(Cache {
+            dir,
+            index_path,
+            stats_path,
+            state: Mutex::new(State {
+                accessed,
+                ..State::default()
+            }),
+        })
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap()
+    }
+
+    /// Cap the cache at `max_size` bytes, evicting entries immediately if it is already larger
+    pub fn set_max_size(&self, max_size: Option<u64>) -> Result<(), Error> {
+        let mut state = self.state();
+        state.max_size = max_size;
+        self.enforce_max_size(&mut state, None)
+    }
+
+    pub fn max_size(&self) -> Option<u64> {
+        self.state().max_size
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.state().counters
+    }
+
+    pub fn reset_counters(&self) {
+        self.state().counters = Counters::default();
+    }
+
+    /// The number of entries and their total size in bytes
+    pub fn usage(&self) -> Result<(usize, u64), Error> {
+        let mut state = self.state();
+        self.ensure_scanned(&mut state)?;
+        Ok((
+            state.entries.len(),
+            state.entries.values().map(|e| e.size).sum(),
+        ))
+    }
+
+    /// Every entry, largest first
+    pub fn entries(&self) -> Result<Vec<EntryInfo>, Error> {
+        let mut state = self.state();
+        self.ensure_scanned(&mut state)?;
+        let mut entries: Vec<EntryInfo> = state
+            .entries
+            .iter()
+            .map(|(id, entry)| EntryInfo {
+                object_id: *id,
+                size: entry.size,
+                last_accessed: state.accessed.get(&id.to_string()).copied(),
+            })
+            .collect();
+        entries.sort_by_key(|e| std::cmp::Reverse(e.size));
+        Ok(entries)
+    }
+
+    /// Record that a retrieval of an object took `elapsed`. `hit` is whether the cache had an
+    /// entry for the object, or `None` if the cache wasn't used.
+    pub fn record_retrieval(&self, hit: Option<bool>, elapsed: Duration) {
+        let mut state = self.state();
+        match hit {
+            Some(true) => state.timings.hits.add(elapsed),
+            Some(false) => state.timings.misses.add(elapsed),
+            None => state.timings.uncached.add(elapsed),
+        }
+    }
+
+    /// The timings saved by previous runs together with those of this one
+    pub fn timings(&self) -> Result<Timings, Error> {
+        let mut timings = self.saved_timings()?;
+        timings.merge(&self.state().timings);
+        Ok(timings)
+    }
+
+    fn saved_timings(&self) -> Result<Timings, Error> {
+        if crate::fs::exists(&self.stats_path)? {
+            Ok(serde_json::from_slice(&crate::fs::read(&self.stats_path)?)?)
+        } else {
+            Ok(Timings::default())
+        }
+    }
+
+    /// When the cache was last used to retrieve an object
+    pub fn last_accessed(&self) -> Option<DateTime<Utc>> {
+        self.state().accessed.values().max().copied()
+    }
+
+    /// Remove every entry
+    pub fn clear(&self) -> Result<(), Error> {
+        let mut state = self.state();
+        if crate::fs::exists(&self.dir)? {
+            crate::fs::remove_dir_all(&self.dir)?;
+        }
+        crate::fs::create_dir_all(&self.dir)?;
+        state.entries.clear();
+        state.parents.clear();
+        state.accessed.clear();
+        state.evicted.clear();
+        state.cleared = Some(Utc::now());
+        state.scanned = true;
+        Ok(())
+    }
+
+    /// Call before retrieving `object_id` using the cache. Returns whether there was an entry
+    /// for the object.
+    pub fn before_access(&self, object_id: &cob::ObjectId) -> Result<bool, Error> {
+        let mut state = self.state();
+        self.ensure_scanned(&mut state)?;
+        let hit = state.entries.contains_key(object_id);
+        if hit {
+            state.counters.hits += 1;
+        } else {
+            state.counters.misses += 1;
+        }
+        state.accessed.insert(object_id.to_string(), Utc::now());
+        Ok(hit)
+    }
+
+    /// Call after any cob call given the cache for `object_id`, such as retrieving, creating or
+    /// updating it, this picks up the new or updated entry and evicts other entries if the cache
+    /// is now too large
+    pub fn after_access(&self, object_id: &cob::ObjectId) -> Result<(), Error> {
+        let mut state = self.state();
+        self.ensure_scanned(&mut state)?;
+        state.accessed.insert(object_id.to_string(), Utc::now());
+        self.refresh(&mut state, object_id)?;
+        self.enforce_max_size(&mut state, Some(object_id))
+    }
+
+    /// Call after a cob call given the cache for every object, such as retrieving all of them,
+    /// with the objects it returned
+    pub fn after_listing<'a>(
+        &self,
+        object_ids: impl IntoIterator<Item = &'a cob::ObjectId>,
+    ) -> Result<(), Error> {
+        let mut state = self.state();
+        self.ensure_scanned(&mut state)?;
+        let now = Utc::now();
+        for object_id in object_ids {
+            state.accessed.insert(object_id.to_string(), now);
+            self.refresh(&mut state, object_id)?;
+        }
+        self.enforce_max_size(&mut state, None)
+    }
+
+    /// Pick up the entry of `object_id`, which is looked for only in the directories other
+    /// entries have been found in
+    fn refresh(&self, state: &mut State, object_id: &cob::ObjectId) -> Result<(), Error> {
+        if let Some(path) = state.entries.get(object_id).map(|e| e.path.clone()) {
+            if crate::fs::exists(&path)? {
+                let size = dir_size(&path)?;
+                if let Some(e) = state.entries.get_mut(object_id) {
+                    e.size = size;
+                }
+            } else {
+                state.entries.remove(object_id);
+            }
+            return Ok(());
+        }
+        if state.parents.is_empty() {
+            // No entries have been found, so this is the first and the cache is all but empty
+            return self.rescan(state);
+        }
+        let name = object_id.to_string();
+        let candidates: Vec<PathBuf> = state.parents.iter().map(|p| p.join(&name)).collect();
+        for path in candidates {
+            if crate::fs::exists(&path)? {
+                let size = dir_size(&path)?;
+                state.entries.insert(*object_id, Entry { path, size });
+                break;
+            }
+        }
+        Ok(())
+    }
+
+    fn ensure_scanned(&self, state: &mut State) -> Result<(), Error> {
+        if !state.scanned {
+            self.rescan(state)?;
+        }
+        Ok(())
+    }
+
+    fn rescan(&self, state: &mut State) -> Result<(), Error> {
+        state.entries.clear();
+        state.parents.clear();
+        if crate::fs::exists(&self.dir)? {
+            scan(&self.dir, state)?;
+        }
+        state.scanned = true;
+        Ok(())
+    }
+
+    /// Evict least recently used entries until the cache fits, never evicting `keep`
+    fn enforce_max_size(
+        &self,
+        state: &mut State,
+        keep: Option<&cob::ObjectId>,
+    ) -> Result<(), Error> {
+        let max_size = match state.max_size {
+            Some(m) => m,
+            None => return Ok(()),
+        };
+        self.ensure_scanned(state)?;
+        let mut total: u64 = state.entries.values().map(|e| e.size).sum();
+        if total <= max_size {
+            return Ok(());
+        }
+        let mut by_age: Vec<(DateTime<Utc>, cob::ObjectId)> = state
+            .entries
+            .keys()
+            .filter(|id| Some(*id) != keep)
+            .map(|id| {
+                let accessed = state
+                    .accessed
+                    .get(&id.to_string())
+                    .cloned()
+                    .unwrap_or_else(|| chrono::MIN_DATETIME);
+                (accessed, *id)
+            })
+            .collect();
+        by_age.sort();
+        let now = Utc::now();
+        for (_, id) in by_age {
+            if total <= max_size {
+                break;
+            }
+            if let Some(entry) = state.entries.remove(&id) {
+                crate::fs::remove_dir_all(&entry.path)?;
+                total -= entry.size;
+                state.accessed.remove(&id.to_string());
+                state.evicted.insert(id.to_string(), now);
+                state.counters.evictions += 1;
+            }
+        }
+        Ok(())
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        let mut state = self.state();
+        // Merged with what other handles on the monorepo saved since this one was opened, so that
+        // concurrent handles don't lose each other's access times
+        let mut accessed = saved_accessed(&self.index_path)?;
+        if let Some(cleared) = state.cleared {
+            accessed.retain(|_, at| *at > cleared);
+        }
+        for (id, evicted) in &state.evicted {
+            if accessed.get(id).map_or(false, |at| at <= evicted) {
+                accessed.remove(id);
+            }
+        }
+        for (id, at) in &state.accessed {
+            let saved = accessed.entry(id.clone()).or_insert(*at);
+            if *saved < *at {
+                *saved = *at;
+            }
+        }
+        crate::fs::write_atomic(&self.index_path, serde_json::to_vec(&accessed)?)?;
+        // Added to what is saved now rather than when the cache was opened, so that the timings of
+        // import workers with caches of their own aren't lost
+        let timings = std::mem::take(&mut state.timings);
+        drop(state);
+        if timings.hits.count + timings.misses.count + timings.uncached.count == 0 {
+            return Ok(());
+        }
+        let mut saved = self.saved_timings()?;
+        saved.merge(&timings);
+        crate::fs::write_atomic(&self.stats_path, serde_json::to_vec(&saved)?)?;
+        Ok(())
+    }
+}
+
+impl Drop for Cache {
+    fn drop(&mut self) {
+        if let Err(e) = self.save() {
+            eprintln!("failed to save cache access times and timings: {}", e);
+        }
+    }
+}
+
+fn saved_accessed(index_path: &Path) -> Result<HashMap<String, DateTime<Utc>>, Error> {
+    if crate::fs::exists(index_path)? {
+        Ok(serde_json::from_slice(&crate::fs::read(index_path)?)?)
+    } else {
+        Ok(HashMap::new())
+    }
+}
+
+fn scan(dir: &Path, state: &mut State) -> Result<(), Error> {
+    for entry in crate::fs::read_dir(dir)? {
+        let entry = entry?;
+        if !entry.file_type()?.is_dir() {
+            continue;
+        }
+        let path = entry.path();
+        let object_id = entry
+            .file_name()
+            .to_str()
+            .and_then(|n| cob::ObjectId::from_str(n).ok());
+        match object_id {
+            Some(object_id) => {
+                let size = dir_size(&path)?;
+                state.parents.insert(dir.to_path_buf());
+                state.entries.insert(object_id, Entry { path, size });
+            }
+            None => scan(&path, state)?,
+        }
+    }
+    Ok(())
+}
+
+fn dir_size(path: &Path) -> Result<u64, std::io::Error> {
+    let mut size = 0;
+    for entry in crate::fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = entry.metadata()?;
+        if metadata.is_dir() {
+            size += dir_size(&entry.path())?;
+        } else {
+            size += metadata.len();
+        }
+    }
+    Ok(size)
+}
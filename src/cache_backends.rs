@@ -0,0 +1,403 @@
+//! Comparing cache backends under eviction pressure, to choose which a node should use by
+//! default. cob's own cache is file based, a directory of evaluated state for each object kept
+//! beneath the cache directory and capped by `crate::cache`. The alternative is a cache in a
+//! single SQLite database, [`SqliteCache`], which keeps the materialized document of each object
+//! along with the tips of the refs it was evaluated from, so that an entry is only used while no
+//! peer has changed the object since, and evicts the least recently used entries once the
+//! documents take up more than its maximum size.
+//!
+//! [`compare`] caps both at the same size and, starting from empty, replays the same sequence of
+//! retrievals following an access pattern through each. The first `warmup` retrievals fill the
+//! cache and aren't measured, so that the comparison is between warm caches rather than between
+//! how quickly each fills. For the rest it reports the hit rate, the latency percentiles and, on
+//! Linux, the IO the process did, read from `/proc/self/io`.
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use rand::SeedableRng;
+use rusqlite::OptionalExtension;
+use thiserror::Error;
+
+use crate::{
+    access_pattern::{Pattern, Sampler},
+    bench::Stats,
+    cache::{self, Counters},
+    lite_monorepo::{error, LiteMonorepo},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Cache(#[from] cache::Error),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    Refs(#[from] error::Delete),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Backend {
+    File,
+    Sqlite,
+}
+
+/// The IO the process did, as counted by the kernel
+#[derive(Debug, Default, Clone, Copy, serde::Serialize)]
+pub struct IoCounts {
+    /// Read and write system calls
+    pub reads: u64,
+    pub writes: u64,
+    /// Bytes fetched from and sent to the storage layer, which excludes reads served from the
+    /// page cache
+    pub read_bytes: u64,
+    pub write_bytes: u64,
+}
+
+impl IoCounts {
+    /// The counts of this process so far, where `/proc` is available
+    fn now() -> Option<IoCounts> {
+        let io = crate::fs::read_to_string("/proc/self/io").ok()?;
+        let mut counts = IoCounts::default();
+        for line in io.lines() {
+            let (key, value) = match line.split_once(':') {
+                Some((key, value)) => (key, value.trim().parse().ok()?),
+                None => continue,
+            };
+            match key {
+                "syscr" => counts.reads = value,
+                "syscw" => counts.writes = value,
+                "read_bytes" => counts.read_bytes = value,
+                "write_bytes" => counts.write_bytes = value,
+                _ => {}
+            }
+        }
+        Some(counts)
+    }
+
+    fn since(&self, earlier: &IoCounts) -> IoCounts {
+        IoCounts {
+            reads: self.reads.saturating_sub(earlier.reads),
+            writes: self.writes.saturating_sub(earlier.writes),
+            read_bytes: self.read_bytes.saturating_sub(earlier.read_bytes),
+            write_bytes: self.write_bytes.saturating_sub(earlier.write_bytes),
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct BackendReport {
+    pub backend: Backend,
+    pub latency: Option<Stats>,
+    pub counters: Counters,
+    pub io: Option<IoCounts>,
+    /// The number of entries and their total size in bytes at the end
+    pub usage: (usize, u64),
+}
+
+impl BackendReport {
+    pub fn hit_rate(&self) -> f64 {
+        let requests = self.counters.hits + self.counters.misses;
+        if requests == 0 {
+            0.0
+        } else {
+            self.counters.hits as f64 / requests as f64
+        }
+    }
+}
+
+#[derive(serde::Serialize)]
+pub struct Report {
+    pub max_size: u64,
+    pub requests: usize,
+    pub backends: Vec<BackendReport>,
+}
+
+impl Report {
+    /// The backend with the lowest 99th percentile latency
+    pub fn recommended(&self) -> Option<Backend> {
+        self.backends
+            .iter()
+            .filter_map(|b| b.latency.as_ref().map(|l| (l.p99, b.backend)))
+            .min_by_key(|(p99, _)| *p99)
+            .map(|(_, backend)| backend)
+    }
+}
+
+/// A cache of materialized documents in a SQLite database
+pub struct SqliteCache {
+    conn: rusqlite::Connection,
+    max_size: u64,
+    counters: Counters,
+}
+
+impl SqliteCache {
+    /// Open the cache at `path`, emptying it
+    pub fn create<P: AsRef<Path>>(path: P, max_size: u64) -> Result<SqliteCache, Error> {
+        let conn = rusqlite::Connection::open(path)?;
+        conn.execute_batch(
+            "DROP TABLE IF EXISTS entries;
+            CREATE TABLE entries (
+                object_id TEXT PRIMARY KEY,
+                tips TEXT NOT NULL,
+                document BLOB NOT NULL,
+                size INTEGER NOT NULL,
+                accessed INTEGER NOT NULL
+            );
+            CREATE INDEX entries_accessed ON entries (accessed);",
+        )?;
+        Ok(SqliteCache {
+            conn,
+            max_size,
+            counters: Counters::default(),
+        })
+    }
+
+    /// Retrieve `object_id` from `monorepo`, from the cache if the tips of its refs haven't moved
+    /// since it was cached
+    pub fn retrieve(
+        &mut self,
+        monorepo: &LiteMonorepo,
+        object_id: &cob::ObjectId,
+    ) -> Result<Option<serde_json::Value>, Error> {
+        let key = object_id.to_string();
+        let tips = tips(monorepo, object_id)?;
+        let now = chrono::Utc::now().timestamp_nanos();
+        let cached: Option<(String, Vec<u8>)> = self
+            .conn
+            .query_row(
+                "SELECT tips, document FROM entries WHERE object_id = ?1",
+                rusqlite::params![key],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        if let Some((cached_tips, document)) = cached {
+            if cached_tips == tips {
+                self.counters.hits += 1;
+                self.conn.execute(
+                    "UPDATE entries SET accessed = ?2 WHERE object_id = ?1",
+                    rusqlite::params![key, now],
+                )?;
+                return Ok(Some(serde_json::from_slice(&document)?));
+            }
+        }
+        self.counters.misses += 1;
+        let document = match monorepo.retrieve_issue(object_id, false)? {
+            Some(document) => document,
+            None => return Ok(None),
+        };
+        let bytes = serde_json::to_vec(&document)?;
+        self.conn.execute(
+            "INSERT OR REPLACE INTO entries (object_id, tips, document, size, accessed)
+            VALUES (?1, ?2, ?3, ?4, ?5)",
+            rusqlite::params![key, tips, bytes, bytes.len() as i64, now],
+        )?;
+        self.evict()?;
+        Ok(Some(document))
+    }
+
+    /// Remove the least recently used entries until the entries fit in the maximum size
+    fn evict(&mut self) -> Result<(), Error> {
+        loop {
+            let (size, oldest): (i64, Option<String>) = self.conn.query_row(
+                "SELECT (SELECT COALESCE(SUM(size), 0) FROM entries),
+                    (SELECT object_id FROM entries ORDER BY accessed LIMIT 1)",
+                rusqlite::params![],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )?;
+            match oldest {
+                Some(oldest) if size as u64 > self.max_size => {
+                    self.conn.execute(
+                        "DELETE FROM entries WHERE object_id = ?1",
+                        rusqlite::params![oldest],
+                    )?;
+                    self.counters.evictions += 1;
+                }
+                _ => return Ok(()),
+            }
+        }
+    }
+
+    pub fn counters(&self) -> Counters {
+        self.counters
+    }
+
+    pub fn reset_counters(&mut self) {
+        self.counters = Counters::default();
+    }
+
+    /// The number of entries and their total size in bytes
+    pub fn usage(&self) -> Result<(usize, u64), Error> {
+        let (entries, size): (i64, i64) = self.conn.query_row(
+            "SELECT COUNT(*), COALESCE(SUM(size), 0) FROM entries",
+            rusqlite::params![],
+            |row| Ok((row.get(0)?, row.get(1)?)),
+        )?;
+        Ok((entries as usize, size as u64))
+    }
+}
+
+/// The commits the refs of every peer for `object_id` point at, as a key which changes whenever
+/// any of them moves
+fn tips(monorepo: &LiteMonorepo, object_id: &cob::ObjectId) -> Result<String, Error> {
+    let mut tips = Vec::new();
+    for name in monorepo.issue_refs(object_id, true)? {
+        tips.push(monorepo.repo().refname_to_id(&name)?.to_string());
+    }
+    tips.sort();
+    Ok(tips.join(","))
+}
+
+/// Replay `warmup + requests` retrievals of the objects in `by_recency` (newest first) following
+/// `pattern` through each backend, both capped at `max_size` bytes, and measure the last
+/// `requests` of them. The SQLite cache is created at `sqlite_path`. The file cache of `monorepo`
+/// is emptied and left capped at `max_size`.
+#[allow(clippy::too_many_arguments)]
+pub fn compare(
+    monorepo: &LiteMonorepo,
+    by_recency: &[cob::ObjectId],
+    pattern: Pattern,
+    warmup: usize,
+    requests: usize,
+    seed: u64,
+    max_size: u64,
+    sqlite_path: &Path,
+) -> Result<Report, Error> {
+    let sequence = sequence(by_recency, pattern, seed, warmup + requests);
+    let (warm, measured) = sequence.split_at(warmup.min(sequence.len()));
+    let mut backends = Vec::new();
+
+    monorepo.cache().clear()?;
+    monorepo.cache().set_max_size(Some(max_size))?;
+    for id in warm {
+        monorepo.retrieve_issue(id, true)?;
+    }
+    monorepo.cache().reset_counters();
+    let (samples, io) = measure(measured, |id| {
+        monorepo.retrieve_issue(id, true)?;
+        Ok(())
+    })?;
+    backends.push(BackendReport {
+        backend: Backend::File,
+        latency: Stats::from_samples(samples),
+        counters: monorepo.cache().counters(),
+        io,
+        usage: monorepo.cache().usage()?,
+    });
+
+    let mut sqlite = SqliteCache::create(sqlite_path, max_size)?;
+    for id in warm {
+        sqlite.retrieve(monorepo, id)?;
+    }
+    sqlite.reset_counters();
+    let (samples, io) = measure(measured, |id| {
+        sqlite.retrieve(monorepo, id)?;
+        Ok(())
+    })?;
+    backends.push(BackendReport {
+        backend: Backend::Sqlite,
+        latency: Stats::from_samples(samples),
+        counters: sqlite.counters(),
+        io,
+        usage: sqlite.usage()?,
+    });
+
+    Ok(Report {
+        max_size,
+        requests,
+        backends,
+    })
+}
+
+/// The `count` objects `pattern` chooses, seeded as `access_pattern::replay` is so that the
+/// sequence is the same as `bench-access` makes
+fn sequence(
+    by_recency: &[cob::ObjectId],
+    pattern: Pattern,
+    seed: u64,
+    count: usize,
+) -> Vec<cob::ObjectId> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let indices: Vec<usize> = (0..by_recency.len()).collect();
+    let sampler = Sampler::new(pattern, &indices, &mut rng);
+    (0..count)
+        .map(|_| by_recency[sampler.sample(&mut rng)])
+        .collect()
+}
+
+/// Time `retrieve` for each of `ids`, and count the IO done meanwhile
+fn measure<F>(
+    ids: &[cob::ObjectId],
+    mut retrieve: F,
+) -> Result<(Vec<Duration>, Option<IoCounts>), Error>
+where
+    F: FnMut(&cob::ObjectId) -> Result<(), Error>,
+{
+    let mut samples = Vec::with_capacity(ids.len());
+    let before = IoCounts::now();
+    for id in ids {
+        let start = Instant::now();
+        retrieve(id)?;
+        samples.push(start.elapsed());
+    }
+    let io = match (IoCounts::now(), before) {
+        (Some(after), Some(before)) => Some(after.since(&before)),
+        _ => None,
+    };
+    Ok((samples, io))
+}
+
+impl std::fmt::Display for Backend {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Backend::File => write!(f, "file"),
+            Backend::Sqlite => write!(f, "sqlite"),
+        }
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} requests with each cache capped at {} bytes",
+            self.requests, self.max_size
+        )?;
+        for backend in &self.backends {
+            writeln!(
+                f,
+                "{}: hit rate {:.1}% ({} evictions, {} entries using {} bytes)",
+                backend.backend,
+                100.0 * backend.hit_rate(),
+                backend.counters.evictions,
+                backend.usage.0,
+                backend.usage.1
+            )?;
+            if let Some(latency) = &backend.latency {
+                writeln!(f, "  latency {}", latency)?;
+            }
+            match &backend.io {
+                Some(io) => writeln!(
+                    f,
+                    "  io {} reads, {} writes, {} bytes read, {} bytes written",
+                    io.reads, io.writes, io.read_bytes, io.write_bytes
+                )?,
+                None => writeln!(f, "  io not available")?,
+            }
+        }
+        if let Some(backend) = self.recommended() {
+            writeln!(f, "recommended: {} (lowest p99 latency)", backend)?;
+        }
+        Ok(())
+    }
+}
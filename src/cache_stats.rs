@@ -0,0 +1,224 @@
+//! How effective the cob cache of a monorepo is: how often retrievals hit it and how much faster
+//! they are when they do, how large its entries are, and how many of them haven't been used in a
+//! while. Hit and miss counts and latencies come from the timings `crate::cache` keeps of every
+//! retrieval, across runs. They only compare like with like if the same objects were retrieved
+//! with and without the cache, so `cache-stats --sample N` also retrieves N objects which have
+//! cache entries both ways and times them against each other.
+use std::time::{Duration, Instant};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::{
+    cache::{self, EntryInfo, Timings},
+    lite_monorepo::{error, LiteMonorepo},
+};
+
+/// The number of largest entries reported
+const LARGEST: usize = 5;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Cache(#[from] cache::Error),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+}
+
+pub struct Sizes {
+    pub mean: u64,
+    pub p50: u64,
+    pub p95: u64,
+    pub max: u64,
+}
+
+/// Objects retrieved with a warm cache and without it
+pub struct Sample {
+    pub objects: usize,
+    pub cached: Duration,
+    pub uncached: Duration,
+}
+
+impl Sample {
+    pub fn speedup(&self) -> Option<f64> {
+        if self.cached.is_zero() {
+            None
+        } else {
+            Some(self.uncached.as_secs_f64() / self.cached.as_secs_f64())
+        }
+    }
+}
+
+pub struct Report {
+    pub entries: usize,
+    pub bytes: u64,
+    pub sizes: Option<Sizes>,
+    pub largest: Vec<EntryInfo>,
+    /// Entries which haven't been used since access times started being kept
+    pub never_accessed: usize,
+    /// Entries which haven't been used for `stale_after`
+    pub stale: usize,
+    pub stale_bytes: u64,
+    pub stale_after: chrono::Duration,
+    pub least_recently_accessed: Option<DateTime<Utc>>,
+    pub timings: Timings,
+    pub sample: Option<Sample>,
+}
+
+impl Report {
+    /// How much faster a cache hit was than a retrieval without the cache, on average
+    pub fn speedup(&self) -> Option<f64> {
+        let hit = self.timings.hits.mean()?;
+        let uncached = self.timings.uncached.mean()?;
+        if hit.is_zero() {
+            return None;
+        }
+        Some(uncached.as_secs_f64() / hit.as_secs_f64())
+    }
+
+    pub fn hit_rate(&self) -> Option<f64> {
+        let requests = self.timings.hits.count + self.timings.misses.count;
+        if requests == 0 {
+            None
+        } else {
+            Some(self.timings.hits.count as f64 / requests as f64)
+        }
+    }
+}
+
+/// Report on the cache of `monorepo`, counting entries not used for `stale_after` as stale, and
+/// timing `sample` objects with cache entries with and without the cache
+pub fn stats(
+    monorepo: &LiteMonorepo,
+    stale_after: chrono::Duration,
+    sample: usize,
+) -> Result<Report, Error> {
+    let entries = monorepo.cache().entries()?;
+    let now = Utc::now();
+
+    let mut sizes: Vec<u64> = entries.iter().map(|e| e.size).collect();
+    sizes.sort_unstable();
+    let bytes: u64 = sizes.iter().sum();
+    let sizes = if sizes.is_empty() {
+        None
+    } else {
+        let percentile = |p: f64| sizes[((sizes.len() - 1) as f64 * p).round() as usize];
+        Some(Sizes {
+            mean: bytes / sizes.len() as u64,
+            p50: percentile(0.5),
+            p95: percentile(0.95),
+            max: *sizes.last().unwrap(),
+        })
+    };
+
+    let mut never_accessed = 0;
+    let mut stale = 0;
+    let mut stale_bytes = 0;
+    for entry in &entries {
+        match entry.last_accessed {
+            None => never_accessed += 1,
+            Some(at) if now - at > stale_after => {
+                stale += 1;
+                stale_bytes += entry.size;
+            }
+            Some(_) => {}
+        }
+    }
+
+    let sample = if sample > 0 && !entries.is_empty() {
+        let mut cached = Duration::default();
+        let mut uncached = Duration::default();
+        let mut objects = 0;
+        for entry in entries.iter().take(sample) {
+            // Once with the cache first, so that the timed retrieval finds the entry up to date
+            monorepo.retrieve_issue(&entry.object_id, true)?;
+            let start = Instant::now();
+            monorepo.retrieve_issue(&entry.object_id, true)?;
+            cached += start.elapsed();
+            let start = Instant::now();
+            monorepo.retrieve_issue(&entry.object_id, false)?;
+            uncached += start.elapsed();
+            objects += 1;
+        }
+        Some(Sample {
+            objects,
+            cached,
+            uncached,
+        })
+    } else {
+        None
+    };
+
+    Ok(Report {
+        entries: entries.len(),
+        bytes,
+        sizes,
+        largest: entries.iter().take(LARGEST).cloned().collect(),
+        never_accessed,
+        stale,
+        stale_bytes,
+        stale_after,
+        least_recently_accessed: entries.iter().filter_map(|e| e.last_accessed).min(),
+        timings: monorepo.cache().timings()?,
+        sample,
+    })
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} entries using {} bytes", self.entries, self.bytes)?;
+        if let Some(sizes) = &self.sizes {
+            writeln!(
+                f,
+                "entry size mean {} p50 {} p95 {} max {}",
+                sizes.mean, sizes.p50, sizes.p95, sizes.max
+            )?;
+        }
+        for entry in &self.largest {
+            writeln!(f, "  {} {} bytes", entry.object_id, entry.size)?;
+        }
+        writeln!(
+            f,
+            "{} entries ({} bytes) not used for {} days, {} never used",
+            self.stale,
+            self.stale_bytes,
+            self.stale_after.num_days(),
+            self.never_accessed
+        )?;
+        if let Some(at) = self.least_recently_accessed {
+            writeln!(f, "least recently used entry last used {}", at.to_rfc3339())?;
+        }
+        let mean = |t: &cache::Timing| {
+            t.mean()
+                .map_or_else(|| "-".to_string(), |m| format!("{:?}", m))
+        };
+        writeln!(
+            f,
+            "{} hits (mean {}), {} misses (mean {}), {} uncached (mean {})",
+            self.timings.hits.count,
+            mean(&self.timings.hits),
+            self.timings.misses.count,
+            mean(&self.timings.misses),
+            self.timings.uncached.count,
+            mean(&self.timings.uncached)
+        )?;
+        if let Some(rate) = self.hit_rate() {
+            writeln!(f, "hit rate {:.1}%", 100.0 * rate)?;
+        }
+        if let Some(speedup) = self.speedup() {
+            writeln!(f, "hits {:.1}x faster than uncached retrievals", speedup)?;
+        }
+        if let Some(sample) = &self.sample {
+            write!(
+                f,
+                "sampled {} objects: {:?} cached, {:?} uncached",
+                sample.objects, sample.cached, sample.uncached
+            )?;
+            match sample.speedup() {
+                Some(speedup) => writeln!(f, ", {:.1}x faster with the cache", speedup)?,
+                None => writeln!(f)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,237 @@
+//! Random fault injection during imports. With `--chaos p=<probability>` each point where an
+//! import can fail rolls the dice and, with the given probability, fails there:
+//!
+//! * a git error just before or just after each change is written, so that an issue is left
+//!   partially imported or its last change isn't recorded in the issue index
+//! * a killed worker thread, with `--jobs`, just after a worker imports an issue but before it
+//!   records that it did, as if the thread died
+//! * a delayed cache write, a pause after each change, when cob has written the change and its
+//!   cache entry but the import hasn't recorded its progress, which widens the window the other
+//!   faults can land in
+//!
+//! Afterwards the import is resumed without faults and the monorepo verified against the
+//! downloaded issues, so the run passes only if the monorepo was either consistent or cleanly
+//! resumable whatever failed.
+use std::{
+    collections::HashMap,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use thiserror::Error;
+
+use crate::{
+    download::IssueStorage,
+    lite_monorepo::{error, LiteMonorepo},
+    retry::Failure,
+    verify,
+};
+
+/// The longest a cache write is delayed
+const MAX_DELAY: Duration = Duration::from_millis(200);
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Load(#[from] crate::download::LoadError),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error(transparent)]
+    List(#[from] error::List),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Fault {
+    GitError,
+    KilledWorker,
+    DelayedCacheWrite,
+}
+
+/// How often to inject faults, parsed from `p=<probability>` optionally followed by `,seed=<n>`
+#[derive(Debug, Clone, Copy)]
+pub struct Options {
+    pub probability: f64,
+    pub seed: u64,
+}
+
+#[derive(Debug, Error)]
+#[error("expected p=<probability between 0 and 1>[,seed=<n>]")]
+pub struct ParseOptionsError {}
+
+impl FromStr for Options {
+    type Err = ParseOptionsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut options = Options {
+            probability: 0.0,
+            seed: 0,
+        };
+        let mut probability = None;
+        for part in s.split(',') {
+            match part.split_once('=') {
+                Some(("p", p)) => probability = p.parse().ok(),
+                Some(("seed", seed)) => {
+                    options.seed = seed.parse().map_err(|_| ParseOptionsError {})?
+                }
+                _ => return Err(ParseOptionsError {}),
+            }
+        }
+        match probability {
+            Some(p) if (0.0..=1.0).contains(&p) => options.probability = p,
+            _ => return Err(ParseOptionsError {}),
+        }
+        Ok(options)
+    }
+}
+
+/// The dice, shared by every handle on the monorepo taking part in an import
+pub struct Chaos {
+    probability: f64,
+    rng: Mutex<StdRng>,
+    injected: Mutex<HashMap<Fault, usize>>,
+}
+
+/// The panic payload of a worker killed by `Chaos::kill_worker`
+pub struct Killed;
+
+impl Chaos {
+    pub fn new(options: Options) -> Arc<Chaos> {
+        Arc::new(Chaos {
+            probability: options.probability,
+            rng: Mutex::new(StdRng::seed_from_u64(options.seed)),
+            injected: Mutex::new(HashMap::new()),
+        })
+    }
+
+    fn roll(&self, fault: Fault) -> bool {
+        let hit = self.rng.lock().unwrap().gen_bool(self.probability);
+        if hit {
+            *self.injected.lock().unwrap().entry(fault).or_default() += 1;
+        }
+        hit
+    }
+
+    /// Fail with a git error, sometimes. `at` says where for the error message.
+    pub fn git_error(&self, at: &str) -> Result<(), git2::Error> {
+        if self.roll(Fault::GitError) {
+            return Err(git2::Error::from_str(&format!(
+                "chaos: injected git error {}",
+                at
+            )));
+        }
+        Ok(())
+    }
+
+    /// Unwind the calling thread, sometimes, without running the panic hook so that nothing is
+    /// printed. The thread's join handle returns `Err` with `Killed` as the payload.
+    pub fn kill_worker(&self) {
+        if self.roll(Fault::KilledWorker) {
+            std::panic::resume_unwind(Box::new(Killed));
+        }
+    }
+
+    /// Pause for up to `MAX_DELAY`, sometimes
+    pub fn delay_cache_write(&self) {
+        if self.roll(Fault::DelayedCacheWrite) {
+            let millis = self
+                .rng
+                .lock()
+                .unwrap()
+                .gen_range(0..=MAX_DELAY.as_millis() as u64);
+            std::thread::sleep(Duration::from_millis(millis));
+        }
+    }
+
+    /// The number of each fault injected so far
+    pub fn injected(&self) -> HashMap<Fault, usize> {
+        self.injected.lock().unwrap().clone()
+    }
+}
+
+/// The state of the monorepo after an import with faults
+pub struct Report {
+    pub injected: HashMap<Fault, usize>,
+    /// The changes made resuming the import without faults
+    pub resumed_changes: u64,
+    pub verification: verify::Report,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.verification.affected.is_empty()
+    }
+}
+
+/// Resume the import of every issue in `storage` into `monorepo` without faults, then verify the
+/// monorepo against the downloaded issues
+pub fn resume_and_verify(
+    monorepo: &mut LiteMonorepo,
+    chaos: &Chaos,
+    storage: &dyn IssueStorage,
+    failures: &[Failure],
+) -> Result<Report, Error> {
+    monorepo.set_chaos(None);
+    monorepo.set_resume_imports(true);
+    let before = monorepo.changes_created();
+    let downloaded = storage.issues()?;
+    for issue in &downloaded {
+        monorepo.import_issue(issue)?;
+    }
+    let resumed_changes = monorepo.changes_created() - before;
+    let issues = monorepo.materialized_issues()?;
+    Ok(Report {
+        injected: chaos.injected(),
+        resumed_changes,
+        verification: verify::verify(&downloaded, &issues, failures),
+    })
+}
+
+impl std::fmt::Display for Fault {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Fault::GitError => write!(f, "git errors"),
+            Fault::KilledWorker => write!(f, "killed workers"),
+            Fault::DelayedCacheWrite => write!(f, "delayed cache writes"),
+        }
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut injected: Vec<_> = self.injected.iter().collect();
+        injected.sort();
+        let injected: Vec<String> = injected
+            .into_iter()
+            .map(|(fault, n)| format!("{} {}", n, fault))
+            .collect();
+        writeln!(
+            f,
+            "injected {}",
+            if injected.is_empty() {
+                "no faults".to_string()
+            } else {
+                injected.join(", ")
+            }
+        )?;
+        writeln!(
+            f,
+            "resuming without faults made {} changes",
+            self.resumed_changes
+        )?;
+        for affected in &self.verification.affected {
+            for problem in &affected.problems {
+                writeln!(f, "issue {}: {}", affected.number, problem)?;
+            }
+        }
+        if self.is_ok() {
+            writeln!(
+                f,
+                "all {} issues were imported faithfully",
+                self.verification.checked
+            )?;
+        }
+        Ok(())
+    }
+}
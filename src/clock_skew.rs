@@ -0,0 +1,33 @@
+//! Simulated clock skew between peers. Each peer's clock is offset by a fixed amount between
+//! `-max` and `+max`, chosen deterministically from the peer ID and a seed so that reimporting
+//! with the same seed skews each peer the same way. The offset is applied to the timestamps of
+//! the automerge changes a peer creates. The timestamps of the git commits are set by `cob` from
+//! the system clock, so they are not skewed.
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Clone, Copy)]
+pub struct ClockSkew {
+    max_millis: i64,
+    seed: u64,
+}
+
+impl ClockSkew {
+    pub fn new(max: std::time::Duration, seed: u64) -> ClockSkew {
+        ClockSkew {
+            max_millis: max.as_millis() as i64,
+            seed,
+        }
+    }
+
+    /// How far ahead (or behind, if negative) of the real time the clock of `peer` is
+    pub fn offset_millis(&self, peer: &link_crypto::PeerId) -> i64 {
+        if self.max_millis == 0 {
+            return 0;
+        }
+        let digest = Sha256::digest(format!("{}:{}", self.seed, peer).as_bytes());
+        let mut bytes = [0; 8];
+        bytes.copy_from_slice(&digest[..8]);
+        let span = 2 * self.max_millis as u64 + 1;
+        (u64::from_le_bytes(bytes) % span) as i64 - self.max_millis
+    }
+}
@@ -0,0 +1,133 @@
+//! The cob API as this crate uses it. cob's API changes as it develops, so the rest of the crate
+//! creates, updates and retrieves objects only through the functions here, and a migration to a
+//! new API only has to change this module. Building against a version of cob other than the
+//! `cob` dependency in `Cargo.toml` is done with a `[patch]` section or path overrides, as
+//! `bisect-perf` does.
+//!
+//! `VERSION` names the API a build uses, so that results can be told apart.
+//!
+//! The `cob-next` feature is meant to build against the next cob API alongside this one: an
+//! optional `cob` dependency renamed to `cob-next` and pinned to the revision being migrated to,
+//! and a `next` module here implementing the functions whose signatures changed, chosen with
+//! `#[cfg(feature = "cob-next")]`. No revision with a changed API exists yet to pin, so for now
+//! the feature stops the build rather than quietly building against the current API.
+use std::path::PathBuf;
+
+use either::Either;
+use link_crypto::SecretKey;
+use link_identities::{Person, Project};
+
+use crate::peer_refs_storage::{self, PeerRefsStorage};
+
+pub type CreateError = cob::error::Create<peer_refs_storage::Error>;
+pub type UpdateError = cob::error::Update<peer_refs_storage::Error>;
+pub type RetrieveError = cob::error::Retrieve<peer_refs_storage::Error>;
+
+#[cfg(feature = "cob-next")]
+compile_error!(
+    "no revision of the next cob API is pinned yet: add it as an optional `cob-next` dependency \
+     and implement `cob_api::next` against it"
+);
+
+/// The API of the `cob` dependency in `Cargo.toml`
+pub const VERSION: &str = "current";
+
+/// Create an object of `typename` whose first change is `history`, authored by `author`
+#[allow(clippy::too_many_arguments)]
+pub fn create_object(
+    storage: &PeerRefsStorage<'_>,
+    repo: &git2::Repository,
+    key: &SecretKey,
+    author: &Person,
+    project: &Project,
+    typename: &cob::TypeName,
+    schema: &serde_json::Value,
+    history: cob::History,
+    cache: Option<PathBuf>,
+) -> Result<cob::CollaborativeObject, CreateError> {
+    cob::create_object(
+        storage,
+        repo,
+        &(key.clone()).into(),
+        author,
+        Either::Right(project.clone()),
+        cob::NewObjectSpec {
+            history,
+            message: None,
+            typename: typename.clone(),
+            schema_json: schema.clone(),
+        },
+        cache,
+    )
+}
+
+/// Add `changes` to `object_id` as a change authored by `author`
+#[allow(clippy::too_many_arguments)]
+pub fn update_object(
+    storage: &PeerRefsStorage<'_>,
+    repo: &git2::Repository,
+    key: &SecretKey,
+    author: &Person,
+    project: &Project,
+    typename: &cob::TypeName,
+    object_id: &cob::ObjectId,
+    changes: cob::History,
+    cache: Option<PathBuf>,
+) -> Result<cob::CollaborativeObject, UpdateError> {
+    cob::update_object(
+        storage,
+        &(key.clone()).into(),
+        repo,
+        author,
+        Either::Right(project.clone()),
+        cob::UpdateObjectSpec {
+            object_id: *object_id,
+            typename: typename.clone(),
+            message: None,
+            changes,
+        },
+        cache,
+    )
+}
+
+pub fn retrieve_object<S>(
+    storage: &S,
+    repo: &git2::Repository,
+    project: &Project,
+    typename: &cob::TypeName,
+    object_id: &cob::ObjectId,
+    cache: Option<PathBuf>,
+) -> Result<Option<cob::CollaborativeObject>, RetrieveError>
+where
+    S: cob::RefsStorage<Error = peer_refs_storage::Error>,
+{
+    cob::retrieve_object(
+        storage,
+        repo,
+        Either::Right(project.clone()),
+        typename,
+        object_id,
+        cache,
+    )
+}
+
+/// Retrieve every object of `typename`. `storage` can be any refs storage which fails as
+/// [`PeerRefsStorage`] does, such as [`crate::memory_refs::MemoryRefsStorage`]
+pub fn retrieve_objects<S>(
+    storage: &S,
+    repo: &git2::Repository,
+    project: &Project,
+    typename: &cob::TypeName,
+    cache: Option<PathBuf>,
+) -> Result<Vec<cob::CollaborativeObject>, RetrieveError>
+where
+    S: cob::RefsStorage<Error = peer_refs_storage::Error>,
+{
+    cob::retrieve_objects(
+        storage,
+        repo,
+        Either::Right(project.clone()),
+        typename,
+        cache,
+    )
+}
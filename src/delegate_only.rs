@@ -0,0 +1,74 @@
+//! Evaluating an object using only the changes project delegates made. cob evaluates every change
+//! reachable from the refs it is given, so filtering by ref would still include a change made by
+//! anyone as soon as a delegate built on it, and would include every change of the local peer.
+//! Instead the change graph is walked from the tip of every ref for the object and only the
+//! changes signed by the key of a delegate are applied. A delegate's change which depends on a
+//! change of someone else is left out with it, as automerge can't apply it, and an object which
+//! wasn't created by a delegate has no document at all.
+use std::{collections::HashSet, convert::TryFrom};
+
+use link_crypto::PeerId;
+use link_identities::sign::Signatures;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error("failed to load change {commit}: {message}")]
+    Load { commit: git2::Oid, message: String },
+    #[error("failed to apply the changes of delegates: {0}")]
+    Apply(String),
+}
+
+/// The history made of the changes reachable from `tips` which are signed by one of `delegates`,
+/// or `None` if there are none which can be applied
+pub fn evaluate(
+    repo: &git2::Repository,
+    tips: impl IntoIterator<Item = git2::Oid>,
+    delegates: &HashSet<PeerId>,
+) -> Result<Option<cob::History>, Error> {
+    let mut pending: Vec<git2::Oid> = tips.into_iter().collect();
+    let mut seen = HashSet::new();
+    let mut changes = Vec::new();
+    while let Some(oid) = pending.pop() {
+        if !seen.insert(oid) {
+            continue;
+        }
+        let commit = repo.find_commit(oid)?;
+        let entry = match commit.tree()?.get_name("change") {
+            Some(entry) => entry,
+            // The identity of the author, which changes have as a parent
+            None => continue,
+        };
+        pending.extend(commit.parent_ids());
+        let by_delegate = Signatures::try_from(&commit)
+            .map(|signatures| {
+                signatures
+                    .iter()
+                    .any(|(key, _)| delegates.contains(&PeerId::from(*key)))
+            })
+            .unwrap_or(false);
+        if by_delegate {
+            let blob = repo.find_blob(entry.id())?;
+            let loaded =
+                automerge::Change::load_document(blob.content()).map_err(|e| Error::Load {
+                    commit: oid,
+                    message: e.to_string(),
+                })?;
+            changes.extend(loaded);
+        }
+    }
+
+    // Changes whose dependencies weren't applied are queued rather than applied, and left out of
+    // the saved history
+    let mut backend = automerge::Backend::new();
+    backend
+        .apply_changes(changes)
+        .map_err(|e| Error::Apply(e.to_string()))?;
+    if backend.get_changes(&[]).is_empty() {
+        return Ok(None);
+    }
+    let saved = backend.save().map_err(|e| Error::Apply(e.to_string()))?;
+    Ok(Some(cob::History::Automerge(saved)))
+}
@@ -0,0 +1,115 @@
+//! How deleting objects scales. `delete-object --count N` removes the refs of N objects one at a
+//! time, timing each, and times listing the objects and enumerating their refs with
+//! `type_references` before and after, so that the cost of a deletion can be compared with the
+//! number of objects and whether deleting many leaves listing any cheaper. The objects are the
+//! first N listed, which cob returns in no particular order.
+use std::{
+    collections::HashSet,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{
+    bench::Stats,
+    lite_monorepo::{error, LiteMonorepo},
+    peer_refs_storage,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Delete(#[from] error::Delete),
+    #[error(transparent)]
+    Refs(#[from] peer_refs_storage::Error),
+}
+
+/// How long listing and enumerating the objects took
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Listing {
+    pub objects: usize,
+    pub list: Duration,
+    pub type_references: Duration,
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Report {
+    pub before: Listing,
+    pub after: Listing,
+    /// The number of objects deleted and the refs removed with them
+    pub deleted: usize,
+    pub refs: usize,
+    /// How long each deletion took
+    pub latency: Option<Stats>,
+    /// Deleted objects which are still listed, through the refs of other peers
+    pub still_listed: usize,
+}
+
+/// Delete the refs of up to `count` objects of `monorepo`, of every peer if `all_peers`
+pub fn measure(monorepo: &LiteMonorepo, count: usize, all_peers: bool) -> Result<Report, Error> {
+    let (before, ids) = listing(monorepo)?;
+    let mut samples = Vec::new();
+    let mut refs = 0;
+    let deleted: Vec<cob::ObjectId> = ids.into_iter().take(count).collect();
+    for id in &deleted {
+        let start = Instant::now();
+        refs += monorepo.delete_issue(id, all_peers)?;
+        samples.push(start.elapsed());
+    }
+    let (after, remaining) = listing(monorepo)?;
+    let remaining: HashSet<cob::ObjectId> = remaining.into_iter().collect();
+    Ok(Report {
+        before,
+        after,
+        deleted: deleted.len(),
+        refs,
+        latency: Stats::from_samples(samples),
+        still_listed: deleted.iter().filter(|id| remaining.contains(id)).count(),
+    })
+}
+
+fn listing(monorepo: &LiteMonorepo) -> Result<(Listing, Vec<cob::ObjectId>), Error> {
+    let start = Instant::now();
+    let ids = monorepo.list_issue_ids()?;
+    let list = start.elapsed();
+    let (type_references, _) = monorepo.time_type_references()?;
+    Ok((
+        Listing {
+            objects: ids.len(),
+            list,
+            type_references,
+        },
+        ids,
+    ))
+}
+
+impl std::fmt::Display for Listing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} objects, listing took {:?}, type_references {:?}",
+            self.objects, self.list, self.type_references
+        )
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "before: {}", self.before)?;
+        writeln!(f, "deleted {} objects, {} refs", self.deleted, self.refs)?;
+        if let Some(latency) = &self.latency {
+            writeln!(f, "  per delete {}", latency)?;
+        }
+        writeln!(f, "after: {}", self.after)?;
+        if self.still_listed > 0 {
+            writeln!(
+                f,
+                "{} deleted objects are still listed via other peers' refs",
+                self.still_listed
+            )?;
+        }
+        Ok(())
+    }
+}
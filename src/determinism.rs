@@ -0,0 +1,287 @@
+//! Checks that importing is deterministic with `--seed`. The same downloaded issues are imported
+//! into two fresh monorepos created with the same seed, one after the other, and the peers, the
+//! assignment of users to them, the automerge history of each issue and the names of the refs
+//! are compared.
+//!
+//! Seeded monorepos stamp changes with the time of what they were made for, but cob and
+//! link-identities stamp commits with the time they are written, to the second, so the commits
+//! and the object IDs, which are the IDs of commits, differ between imports made at different
+//! times however deterministic the rest of the import is. They are counted but don't make the
+//! imports differ. The object IDs in ref names are replaced with the numbers of the issues before
+//! the names are compared.
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    downloaded_issue::DownloadedIssue,
+    lite_monorepo::{error, LiteMonorepo},
+    monorepo_config::{self, Config},
+    replication::object_of_ref,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Config(#[from] monorepo_config::Error),
+    #[error(transparent)]
+    Open(#[from] error::CreateOrOpen),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error("failed to load the history of issue {0}")]
+    History(u64),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// What an import produced
+struct Imported {
+    peers: BTreeSet<String>,
+    /// The peer each user was assigned, by login
+    assignments: BTreeMap<String, String>,
+    /// The automerge changes of each issue. cob orders changes made concurrently by their
+    /// commits, so the order is left out.
+    histories: BTreeMap<u64, BTreeSet<Vec<u8>>>,
+    /// The target of every ref, by its name with object IDs replaced by issue numbers
+    refs: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub issues: usize,
+    pub refs: usize,
+    /// Whether the monorepos have different peers
+    pub peers_differ: bool,
+    /// Users assigned different peers
+    pub assignments_differ: Vec<String>,
+    /// Issues whose automerge histories differ
+    pub histories_differ: Vec<u64>,
+    /// Refs in only one of the monorepos
+    pub refs_only_in_first: Vec<String>,
+    pub refs_only_in_second: Vec<String>,
+    /// Refs in both which point at different commits, which is expected of imports made at
+    /// different times
+    pub commits_differ: usize,
+}
+
+impl Report {
+    pub fn is_deterministic(&self) -> bool {
+        !self.peers_differ
+            && self.assignments_differ.is_empty()
+            && self.histories_differ.is_empty()
+            && self.refs_only_in_first.is_empty()
+            && self.refs_only_in_second.is_empty()
+    }
+}
+
+/// Import `issues` twice with `peers` peers whose keys are derived from `seed`, in temporary
+/// monorepos which are removed afterwards, and compare the results
+pub fn check(issues: &[DownloadedIssue], peers: usize, seed: u64) -> Result<Report, Error> {
+    let dir = std::env::temp_dir().join(format!(
+        "collab-stress-test-determinism-{}",
+        std::process::id()
+    ));
+    let result = import_both(&dir, issues, peers, seed);
+    crate::fs::remove_dir_all(&dir).ok();
+    let (first, second) = result?;
+
+    let mut report = Report {
+        issues: issues.len(),
+        refs: first.refs.len(),
+        ..Report::default()
+    };
+    report.peers_differ = first.peers != second.peers;
+    let users: BTreeSet<&String> = first
+        .assignments
+        .keys()
+        .chain(second.assignments.keys())
+        .collect();
+    for user in users {
+        if first.assignments.get(user) != second.assignments.get(user) {
+            report.assignments_differ.push(user.to_string());
+        }
+    }
+    let numbers: BTreeSet<&u64> = first
+        .histories
+        .keys()
+        .chain(second.histories.keys())
+        .collect();
+    for number in numbers {
+        if first.histories.get(number) != second.histories.get(number) {
+            report.histories_differ.push(*number);
+        }
+    }
+    let first_names: BTreeSet<&String> = first.refs.keys().collect();
+    let second_names: BTreeSet<&String> = second.refs.keys().collect();
+    report.refs_only_in_first = first_names
+        .difference(&second_names)
+        .map(|n| n.to_string())
+        .collect();
+    report.refs_only_in_second = second_names
+        .difference(&first_names)
+        .map(|n| n.to_string())
+        .collect();
+    for name in first_names.intersection(&second_names) {
+        if first.refs[*name] != second.refs[*name] {
+            report.commits_differ += 1;
+        }
+    }
+    Ok(report)
+}
+
+fn import_both(
+    dir: &Path,
+    issues: &[DownloadedIssue],
+    peers: usize,
+    seed: u64,
+) -> Result<(Imported, Imported), Error> {
+    let first = import(dir.join("first"), issues, peers, seed)?;
+    let second = import(dir.join("second"), issues, peers, seed)?;
+    Ok((first, second))
+}
+
+fn import(
+    root: PathBuf,
+    issues: &[DownloadedIssue],
+    peers: usize,
+    seed: u64,
+) -> Result<Imported, Error> {
+    Config {
+        peers,
+        seed: Some(seed),
+        ..Config::default()
+    }
+    .save(&root)?;
+    let mut monorepo = LiteMonorepo::create_or_open(&root)?;
+    for issue in issues {
+        monorepo.import_issue(issue)?;
+    }
+
+    let mut objects = BTreeMap::new();
+    let mut histories = BTreeMap::new();
+    for issue in issues {
+        if let Some(id) = monorepo.issue_object_id(issue.number) {
+            objects.insert(issue.number, id.to_string());
+            if let Some(history) = monorepo.issue_history(&id)? {
+                let changes = automerge::Change::load_document(&history)
+                    .map_err(|_| Error::History(issue.number))?;
+                histories.insert(
+                    issue.number,
+                    changes.iter().map(|c| c.raw_bytes().to_vec()).collect(),
+                );
+            }
+        }
+    }
+    let issues_by_object: HashMap<&String, u64> = objects
+        .iter()
+        .map(|(number, object)| (object, *number))
+        .collect();
+    let mut refs = BTreeMap::new();
+    for reference in monorepo.repo().references()? {
+        let reference = reference?;
+        let name = match reference.name() {
+            Some(name) => name,
+            None => continue,
+        };
+        let object = object_of_ref(name).map(|o| o.to_string());
+        let normalized = match object.as_ref().and_then(|o| issues_by_object.get(o)) {
+            Some(number) => name.replace(object.as_ref().unwrap(), &format!("issue-{}", number)),
+            None => name.to_string(),
+        };
+        let target = reference
+            .target()
+            .map(|t| t.to_string())
+            .or_else(|| reference.symbolic_target().map(|t| t.to_string()))
+            .unwrap_or_default();
+        refs.insert(normalized, target);
+    }
+    Ok(Imported {
+        peers: monorepo.peer_ids().map(|p| p.to_string()).collect(),
+        assignments: monorepo
+            .peer_assignments()
+            .into_iter()
+            .map(|(user, peer)| (user.0, peer.to_string()))
+            .collect(),
+        histories,
+        refs,
+    })
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "imported {} issues twice, {} refs",
+            self.issues, self.refs
+        )?;
+        if self.commits_differ > 0 {
+            writeln!(
+                f,
+                "{} refs point at different commits, as commits are stamped with the time they \
+                 are written",
+                self.commits_differ
+            )?;
+        }
+        if self.is_deterministic() {
+            return writeln!(
+                f,
+                "the imports made the same peers, assignments and changes"
+            );
+        }
+        let examples = |items: &[String]| {
+            let mut shown: Vec<&str> = items.iter().take(5).map(|s| s.as_str()).collect();
+            if items.len() > shown.len() {
+                shown.push("...");
+            }
+            shown.join(", ")
+        };
+        if self.peers_differ {
+            writeln!(f, "the monorepos have different peers")?;
+        }
+        if !self.assignments_differ.is_empty() {
+            writeln!(
+                f,
+                "{} users were assigned different peers: {}",
+                self.assignments_differ.len(),
+                examples(&self.assignments_differ)
+            )?;
+        }
+        if !self.histories_differ.is_empty() {
+            let numbers: Vec<String> = self
+                .histories_differ
+                .iter()
+                .map(|n| n.to_string())
+                .collect();
+            writeln!(
+                f,
+                "{} issues have different histories: {}",
+                numbers.len(),
+                examples(&numbers)
+            )?;
+        }
+        if !self.refs_only_in_first.is_empty() {
+            writeln!(
+                f,
+                "{} refs only in the first import: {}",
+                self.refs_only_in_first.len(),
+                examples(&self.refs_only_in_first)
+            )?;
+        }
+        if !self.refs_only_in_second.is_empty() {
+            writeln!(
+                f,
+                "{} refs only in the second import: {}",
+                self.refs_only_in_second.len(),
+                examples(&self.refs_only_in_second)
+            )?;
+        }
+        Ok(())
+    }
+}
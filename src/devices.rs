@@ -0,0 +1,84 @@
+//! Users with more than one device. Real users have a key on each of their devices, all of which
+//! are delegations of their `Person` identity, so that a change signed by any of them is theirs.
+//! A monorepo created with several devices per user groups its peers, in the order of their IDs,
+//! into users of that many peers each, the last with whatever is left over. The peers of a user
+//! share an identity and GitHub users are assigned to users rather than to peers, the first peer
+//! of each standing for it, while the changes of a GitHub user are made by each of the devices of
+//! their user in turn.
+//!
+//! The groups are kept in `devices.json` in the root of the monorepo, so that peers added later
+//! are grouped among themselves rather than changing the groups of the peers before them.
+//! Monorepos with a single device per user have no file, every peer being a user of its own.
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+};
+
+use link_crypto::PeerId;
+use thiserror::Error;
+
+pub const DEVICES: &str = "devices.json";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+pub struct Devices {
+    /// The peers of each user, the first standing for the user
+    groups: Vec<Vec<PeerId>>,
+    /// The index in `groups` of the user of each peer
+    user_of: HashMap<PeerId, usize>,
+}
+
+impl Devices {
+    /// Load the groups saved at `path` and group the peers in `peers`, which are in the order of
+    /// their IDs, which aren't in one yet into users of `per_user` peers each
+    pub fn load<'a, P: AsRef<Path>>(
+        path: P,
+        peers: impl Iterator<Item = &'a PeerId>,
+        per_user: usize,
+    ) -> Result<Devices, Error> {
+        let saved = crate::fs::exists(&path)?;
+        let mut groups: Vec<Vec<PeerId>> = if saved {
+            serde_json::from_slice(&crate::fs::read(&path)?)?
+        } else {
+            Vec::new()
+        };
+        let grouped: HashSet<PeerId> = groups.iter().flatten().copied().collect();
+        let ungrouped: Vec<PeerId> = peers.filter(|p| !grouped.contains(p)).copied().collect();
+        if !ungrouped.is_empty() {
+            groups.extend(ungrouped.chunks(per_user.max(1)).map(|c| c.to_vec()));
+            if saved || per_user > 1 {
+                crate::fs::write_atomic(&path, serde_json::to_vec(&groups)?)?;
+            }
+        }
+        let user_of = groups
+            .iter()
+            .enumerate()
+            .flat_map(|(i, group)| group.iter().map(move |peer| (*peer, i)))
+            .collect();
+        Ok(Devices { groups, user_of })
+    }
+
+    /// The peers of each user
+    pub fn groups(&self) -> impl Iterator<Item = &[PeerId]> {
+        self.groups.iter().map(|group| group.as_slice())
+    }
+
+    /// The peer standing for each user, which GitHub users are assigned to
+    pub fn users(&self) -> impl Iterator<Item = &PeerId> {
+        self.groups.iter().map(|group| &group[0])
+    }
+
+    /// The peers of the user `peer` is a device of
+    pub fn of<'a>(&'a self, peer: &'a PeerId) -> &'a [PeerId] {
+        match self.user_of.get(peer) {
+            Some(i) => &self.groups[*i],
+            None => std::slice::from_ref(peer),
+        }
+    }
+}
@@ -0,0 +1,343 @@
+//! What happens when the disk fills up during an import. The import should stop with an error
+//! and a checkpoint it can be resumed from, leaving every metadata file either as it was or
+//! completely rewritten, never half written, so that freeing some space and running it again
+//! carries on as if nothing happened.
+//!
+//! The test runs in a directory on a small filesystem of its own, such as a tmpfs or a loopback
+//! mount, which the caller creates, so that filling it doesn't fill anything else. A replica of
+//! the monorepo is created there along with a reserve file of a fixed size, and downloaded issues
+//! are imported until the filesystem runs out of space. Then
+//!
+//! * each metadata file of the replica must still parse, and the cob cache must agree with the
+//!   objects it caches
+//! * after deleting the reserve file, resuming from the checkpoint must carry on importing
+//! * the issues imported by the end must match the downloaded issues
+use std::{cell::Cell, path::Path};
+
+use indicatif::ProgressBar;
+use thiserror::Error;
+
+use crate::{
+    cache::ByteSize,
+    download::IssueStorage,
+    identity_pins::IdentityPins,
+    import,
+    issue_index::{self, ISSUE_INDEX},
+    lite_monorepo::{error, LiteMonorepo},
+    monorepo_config::Config,
+    peer_assignments::PeerAssignments,
+    replication,
+    tracking::Tracking,
+    verify,
+};
+
+/// The `errno` of "No space left on device"
+const ENOSPC: i32 = 28;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+    #[error(transparent)]
+    Load(#[from] crate::download::LoadError),
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Open(#[from] error::CreateOrOpen),
+    #[error("the import failed for a reason other than running out of space: {0}")]
+    Import(import::Error),
+}
+
+/// How one metadata file fared
+pub struct Check {
+    pub name: &'static str,
+    /// Why the file is damaged, if it is
+    pub problem: Option<String>,
+}
+
+pub struct Report {
+    /// The issue the import was importing when the disk filled, `None` if it never did
+    pub filled_at: Option<u64>,
+    /// The last issue completely imported before the disk filled
+    pub checkpoint: Option<u64>,
+    pub checks: Vec<Check>,
+    /// Why resuming failed, if it did
+    pub resume_error: Option<String>,
+    /// Whether the resumed import filled the disk again before importing everything
+    pub filled_again: bool,
+    /// The issues imported by the end, compared with the downloaded issues
+    pub verification: Option<verify::Report>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.filled_at.is_some()
+            && self.checks.iter().all(|c| c.problem.is_none())
+            && self.resume_error.is_none()
+            && self
+                .verification
+                .as_ref()
+                .map(|v| v.affected.is_empty())
+                .unwrap_or(false)
+    }
+}
+
+/// Import the issues in `storage` into a replica of `source` in `dir` until the filesystem `dir`
+/// is on fills up, holding back `reserve` bytes to free afterwards, then check the replica and
+/// resume
+pub fn run(
+    source: &LiteMonorepo,
+    storage: &dyn IssueStorage,
+    dir: &Path,
+    reserve: ByteSize,
+) -> Result<Report, Error> {
+    let root = dir.join("monorepo");
+    let reserve_path = dir.join("reserve");
+    crate::fs::write(&reserve_path, vec![0; reserve.0 as usize])?;
+    drop(replication::create_replica(source, &root)?);
+
+    let numbers = storage.issue_numbers()?;
+    let checkpoint = Cell::new(None);
+    let filled_at = match import(&root, storage, &numbers, &checkpoint)? {
+        Some(number) => number,
+        None => {
+            crate::fs::remove_file(&reserve_path)?;
+            return Ok(Report {
+                filled_at: None,
+                checkpoint: checkpoint.get(),
+                checks: Vec::new(),
+                resume_error: None,
+                filled_again: false,
+                verification: None,
+            });
+        }
+    };
+    status!(
+        "the disk filled importing issue {}, checkpoint {:?}",
+        filled_at,
+        checkpoint.get()
+    );
+    // Opening the monorepo to check it may write to it, so space is freed first. That doesn't
+    // change what the import left behind.
+    crate::fs::remove_file(&reserve_path)?;
+    status!("freed {}, checking the metadata and resuming", reserve);
+    let mut report = Report {
+        filled_at: Some(filled_at),
+        checkpoint: checkpoint.get(),
+        checks: check_metadata(&root),
+        resume_error: None,
+        filled_again: false,
+        verification: None,
+    };
+    let imported = match import(&root, storage, &numbers, &checkpoint) {
+        Ok(Some(number)) if checkpoint.get() == report.checkpoint => {
+            report.resume_error = Some(format!(
+                "the disk filled again importing issue {} without importing anything",
+                number
+            ));
+            return Ok(report);
+        }
+        Ok(Some(_)) => {
+            report.filled_again = true;
+            checkpoint.get()
+        }
+        Ok(None) => None,
+        Err(e) => {
+            report.resume_error = Some(e.to_string());
+            return Ok(report);
+        }
+    };
+    let monorepo = match LiteMonorepo::create_or_open(&root) {
+        Ok(monorepo) => monorepo,
+        Err(e) => {
+            report.resume_error = Some(e.to_string());
+            return Ok(report);
+        }
+    };
+    // Only the issues up to the checkpoint are expected if the disk filled again
+    let downloaded: Vec<_> = storage
+        .issues()?
+        .into_iter()
+        .filter(|issue| imported.map(|c| issue.number <= c).unwrap_or(true))
+        .collect();
+    let issues = monorepo.materialized_issues()?;
+    report.verification = Some(verify::verify(&downloaded, &issues, &[]));
+    Ok(report)
+}
+
+/// Import `numbers` into the monorepo at `root`, resuming from `checkpoint`, returning the issue
+/// being imported when the disk filled up, if it did
+fn import(
+    root: &Path,
+    storage: &dyn IssueStorage,
+    numbers: &[u64],
+    checkpoint: &Cell<Option<u64>>,
+) -> Result<Option<u64>, Error> {
+    let mut monorepo = LiteMonorepo::create_or_open(root)?;
+    monorepo.set_resume_imports(true);
+    match import::import_issues(
+        &mut monorepo,
+        storage,
+        numbers,
+        checkpoint,
+        &ProgressBar::hidden(),
+    ) {
+        Ok(()) => Ok(None),
+        Err(import::Error::Import { number, source }) if is_disk_full(&source) => Ok(Some(number)),
+        Err(e) => Err(Error::Import(e)),
+    }
+}
+
+/// Whether `e` or anything it wraps is an out of space error. cob and git2 only keep the message
+/// of the underlying error, so that is checked too.
+fn is_disk_full(e: &(dyn std::error::Error + 'static)) -> bool {
+    let mut next = Some(e);
+    while let Some(e) = next {
+        if let Some(io) = e.downcast_ref::<std::io::Error>() {
+            if io.raw_os_error() == Some(ENOSPC) {
+                return true;
+            }
+        }
+        if e.to_string().contains("No space left on device") {
+            return true;
+        }
+        next = e.source();
+    }
+    false
+}
+
+/// Check that each metadata file in the monorepo at `root` parses and that cached documents match
+/// the objects they were cached from
+fn check_metadata(root: &Path) -> Vec<Check> {
+    let check = |name: &'static str, result: Result<(), String>| Check {
+        name,
+        problem: result.err(),
+    };
+    let mut checks = vec![
+        check(
+            "peer_map",
+            PeerAssignments::load(root.join("peer_map"), std::iter::empty())
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        ),
+        check(ISSUE_INDEX, check_issue_index(&root.join(ISSUE_INDEX))),
+        check("cache_access", check_json(&root.join("cache_access"))),
+        check("cache_stats", check_json(&root.join("cache_stats"))),
+        check(
+            "tracking",
+            Tracking::load(root.join("tracking"))
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        ),
+        check(
+            "config.json",
+            Config::load(root).map(|_| ()).map_err(|e| e.to_string()),
+        ),
+        check(
+            "identity_pins.json",
+            IdentityPins::load(root.join("identity_pins.json"))
+                .map(|_| ())
+                .map_err(|e| e.to_string()),
+        ),
+    ];
+    // Whatever else is wrong, the monorepo has to open for the import to be resumed
+    match LiteMonorepo::create_or_open(root) {
+        Ok(monorepo) => checks.push(check("cob_cache", check_cache(&monorepo))),
+        Err(e) => checks.push(check("monorepo", Err(e.to_string()))),
+    }
+    checks
+}
+
+/// Every line of the issue index must parse. Loading the index tolerates a torn last line, in
+/// case of a crash, but running out of space shouldn't leave one.
+fn check_issue_index(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let contents = crate::fs::read_to_string(path).map_err(|e| e.to_string())?;
+    for (i, line) in contents.lines().enumerate() {
+        if let Err(e) = serde_json::from_str::<issue_index::Entry>(line) {
+            return Err(format!("line {}: {}", i + 1, e));
+        }
+    }
+    Ok(())
+}
+
+fn check_json(path: &Path) -> Result<(), String> {
+    if !path.exists() {
+        return Ok(());
+    }
+    let bytes = crate::fs::read(path).map_err(|e| e.to_string())?;
+    serde_json::from_slice::<serde_json::Value>(&bytes)
+        .map(|_| ())
+        .map_err(|e| e.to_string())
+}
+
+/// Retrieve every object with and without the cache, which must give the same document
+fn check_cache(monorepo: &LiteMonorepo) -> Result<(), String> {
+    let ids = replication::object_ids(monorepo.repo()).map_err(|e| e.to_string())?;
+    for id in ids {
+        let cached = monorepo
+            .retrieve_issue(&id, true)
+            .map_err(|e| format!("{}: {}", id, e))?;
+        let uncached = monorepo
+            .retrieve_issue(&id, false)
+            .map_err(|e| format!("{}: {}", id, e))?;
+        if cached != uncached {
+            return Err(format!("{}: the cached document differs", id));
+        }
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let filled_at = match self.filled_at {
+            Some(number) => number,
+            None => {
+                return writeln!(
+                    f,
+                    "every issue was imported without filling the disk, use a smaller filesystem"
+                )
+            }
+        };
+        writeln!(
+            f,
+            "the disk filled importing issue {}, {}",
+            filled_at,
+            match self.checkpoint {
+                Some(c) => format!("checkpoint at issue {}", c),
+                None => "before any issue was imported".to_string(),
+            }
+        )?;
+        for check in &self.checks {
+            match &check.problem {
+                None => writeln!(f, "  {}: ok", check.name)?,
+                Some(problem) => writeln!(f, "  {}: DAMAGED {}", check.name, problem)?,
+            }
+        }
+        if let Some(e) = &self.resume_error {
+            writeln!(f, "resuming failed: {}", e)?;
+        }
+        if self.filled_again {
+            writeln!(f, "the resumed import filled the disk again")?;
+        }
+        if let Some(verification) = &self.verification {
+            for affected in &verification.affected {
+                for problem in &affected.problems {
+                    writeln!(f, "issue {}: {}", affected.number, problem)?;
+                }
+            }
+            if verification.affected.is_empty() {
+                writeln!(
+                    f,
+                    "all {} issues imported by the end match the downloaded issues",
+                    verification.checked
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,14 @@
-use super::downloaded_issue::DownloadedIssue;
+use super::downloaded_issue::{DownloadedComment, DownloadedEvent, DownloadedIssue};
 use super::RepoName;
 
+use super::fs;
 use super::graphql;
+use super::object_store;
+use super::retry::Transient;
+use chrono::{DateTime, Utc};
 use futures::stream::StreamExt;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
 use std::sync::Arc;
 use thiserror::Error;
 use tokio::task::JoinError;
@@ -17,6 +23,8 @@ pub enum Error {
     Join(#[from] JoinError),
     #[error(transparent)]
     Graphql(#[from] graphql::Error),
+    #[error(transparent)]
+    Store(#[from] StoreError),
 }
 
 #[derive(Debug, Error)]
@@ -25,8 +33,154 @@ pub enum LoadError {
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    ObjectStore(#[from] object_store::Error),
+}
+
+#[derive(Debug, Error)]
+pub enum StoreError {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    ObjectStore(#[from] object_store::Error),
+}
+
+/// Somewhere to put downloaded issues, along with the cursor of the last page of issues we
+/// downloaded so that downloads can be resumed.
+///
+/// Every storage also keeps a manifest of the SHA-256 of each stored issue so that a corpus which
+/// has been copied between machines can be checked (see `verify_checksums`) before spending hours
+/// importing it.
+pub trait IssueStorage: Send + Sync {
+    /// The numbers of the downloaded issues in this storage, in ascending order
+    fn issue_numbers(&self) -> Result<Vec<u64>, LoadError>;
+    /// The serialized issue exactly as it was stored
+    fn load_raw(&self, number: u64) -> Result<Option<Vec<u8>>, LoadError>;
+    fn store_raw(&self, number: u64, bytes: &[u8]) -> Result<(), StoreError>;
+    /// The manifest of checksums, as hex encoded SHA-256 digests, by issue number
+    fn checksums(&self) -> Result<HashMap<u64, String>, LoadError>;
+    fn record_checksum(&self, number: u64, checksum: &str) -> Result<(), StoreError>;
+    fn save_cursor(&self, cursor: String) -> Result<(), std::io::Error>;
+    fn load_cursor(&self) -> Result<Option<String>, std::io::Error>;
+    /// Record that every issue updated before `at` has been synced
+    fn save_last_sync(&self, at: DateTime<Utc>) -> Result<(), std::io::Error>;
+    fn load_last_sync(&self) -> Result<Option<DateTime<Utc>>, std::io::Error>;
+
+    fn issue(&self, number: u64) -> Result<Option<DownloadedIssue>, LoadError> {
+        match self.load_raw(number)? {
+            Some(bytes) => Ok(Some(serde_json::from_slice(&bytes)?)),
+            None => Ok(None),
+        }
+    }
+
+    fn store(&self, issue: &DownloadedIssue) -> Result<(), StoreError> {
+        let bytes = serde_json::to_vec(issue)?;
+        self.store_raw(issue.number, &bytes)?;
+        self.record_checksum(issue.number, &sha256_hex(&bytes))
+    }
+
+    /// List downloaded issues in this storage
+    fn issues(&self) -> Result<Vec<DownloadedIssue>, LoadError> {
+        let mut issues = Vec::new();
+        for number in self.issue_numbers()? {
+            if let Some(issue) = self.issue(number)? {
+                issues.push(issue);
+            }
+        }
+        Ok(issues)
+    }
 }
 
+impl Transient for Error {
+    fn is_transient(&self) -> bool {
+        matches!(
+            self,
+            Error::Octocrab(_)
+                | Error::Graphql(graphql::Error::Http(_))
+                | Error::Graphql(graphql::Error::Status { .. })
+                | Error::Store(StoreError::ObjectStore(object_store::Error::Http(_)))
+        )
+    }
+}
+
+pub fn sha256_hex(bytes: &[u8]) -> String {
+    hex::encode(Sha256::digest(bytes))
+}
+
+/// Parse a last sync time as saved by `save_last_sync`
+pub fn parse_sync_time(s: &str) -> Result<DateTime<Utc>, std::io::Error> {
+    DateTime::parse_from_rfc3339(s.trim())
+        .map(|t| t.with_timezone(&Utc))
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+/// The outcome of comparing the stored issues with the checksum manifest
+#[derive(Debug, Default)]
+pub struct ChecksumReport {
+    pub verified: usize,
+    /// Issues whose contents don't match the manifest
+    pub mismatched: Vec<u64>,
+    /// Issues which are stored but have no entry in the manifest
+    pub unrecorded: Vec<u64>,
+    /// Issues which are in the manifest but not in the storage
+    pub missing: Vec<u64>,
+}
+
+impl ChecksumReport {
+    pub fn is_ok(&self) -> bool {
+        self.mismatched.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// Check every stored issue against the checksum manifest. If `record_missing` is true then
+/// issues which have no entry in the manifest (e.g. because they were downloaded before manifests
+/// existed) have their current checksum recorded.
+pub fn verify_checksums(
+    storage: &dyn IssueStorage,
+    record_missing: bool,
+) -> Result<ChecksumReport, StoreError> {
+    let mut manifest = storage.checksums().map_err(load_to_store)?;
+    let mut report = ChecksumReport::default();
+    for number in storage.issue_numbers().map_err(load_to_store)? {
+        let bytes = match storage.load_raw(number).map_err(load_to_store)? {
+            Some(b) => b,
+            None => continue,
+        };
+        let actual = sha256_hex(&bytes);
+        match manifest.remove(&number) {
+            Some(expected) if expected == actual => report.verified += 1,
+            Some(_) => report.mismatched.push(number),
+            None => {
+                if record_missing {
+                    storage.record_checksum(number, &actual)?;
+                }
+                report.unrecorded.push(number);
+            }
+        }
+    }
+    report.missing = manifest.into_iter().map(|(n, _)| n).collect();
+    report.missing.sort_unstable();
+    Ok(report)
+}
+
+fn load_to_store(e: LoadError) -> StoreError {
+    match e {
+        LoadError::Io(e) => StoreError::Io(e),
+        LoadError::Serde(e) => StoreError::Serde(e),
+        LoadError::Sqlite(e) => StoreError::Sqlite(e),
+        LoadError::ObjectStore(e) => StoreError::ObjectStore(e),
+    }
+}
+
+/// Stores each issue as a JSON file in `<dir>/issues`. The checksum manifest is
+/// `<dir>/manifest.sha256`, which is in the format used by `sha256sum` so it can also be checked
+/// with `sha256sum -c`.
 pub struct Storage {
     dir: std::path::PathBuf,
 }
@@ -34,63 +188,263 @@ pub struct Storage {
 impl Storage {
     pub fn new(storage_dir: std::path::PathBuf) -> Result<Storage, std::io::Error> {
         let issues_dir = &storage_dir.join("issues");
-        if !std::fs::try_exists(&issues_dir)? {
-            std::fs::create_dir_all(&issues_dir)?;
+        if !fs::exists(&issues_dir)? {
+            fs::create_dir_all(&issues_dir)?;
         }
         Ok(Storage { dir: storage_dir })
     }
 
-    /// List downloaded issues in this storage
-    pub(crate) fn issues(&self) -> Result<Vec<DownloadedIssue>, LoadError> {
-        if !std::fs::try_exists(&self.dir)? {
-            Ok(Vec::new())
-        } else {
-            let mut issues = Vec::new();
-            for file in std::fs::read_dir(&self.dir.join("issues"))? {
-                let bytes = std::fs::read(file?.path())?;
-                let issue: DownloadedIssue = serde_json::from_slice(&bytes[..])?;
-                issues.push(issue)
+    fn issue_path(&self, number: u64) -> std::path::PathBuf {
+        self.dir.join("issues").join(format!("{}.json", number))
+    }
+
+    fn manifest_path(&self) -> std::path::PathBuf {
+        self.dir.join("manifest.sha256")
+    }
+}
+
+impl IssueStorage for Storage {
+    fn issue_numbers(&self) -> Result<Vec<u64>, LoadError> {
+        if !fs::exists(&self.dir)? {
+            return Ok(Vec::new());
+        }
+        let mut numbers: Vec<u64> = fs::files(&self.dir.join("issues"))?
+            .iter()
+            .filter_map(|f| f.file_stem()?.to_str()?.parse().ok())
+            .collect();
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+
+    fn load_raw(&self, number: u64) -> Result<Option<Vec<u8>>, LoadError> {
+        let path = self.issue_path(number);
+        if !fs::exists(&path)? {
+            return Ok(None);
+        }
+        Ok(Some(fs::read(path)?))
+    }
+
+    fn store_raw(&self, number: u64, bytes: &[u8]) -> Result<(), StoreError> {
+        fs::write(self.issue_path(number), bytes)?;
+        Ok(())
+    }
+
+    fn checksums(&self) -> Result<HashMap<u64, String>, LoadError> {
+        let path = self.manifest_path();
+        if !fs::exists(&path)? {
+            return Ok(HashMap::new());
+        }
+        // The manifest is append only so later lines override earlier ones
+        let mut checksums = HashMap::new();
+        for line in fs::read_to_string(path)?.lines() {
+            let mut parts = line.splitn(2, "  ");
+            if let (Some(checksum), Some(name)) = (parts.next(), parts.next()) {
+                let number = name
+                    .strip_prefix("issues/")
+                    .and_then(|n| n.strip_suffix(".json"))
+                    .and_then(|n| n.parse().ok());
+                if let Some(number) = number {
+                    checksums.insert(number, checksum.to_string());
+                }
             }
-            Ok(issues)
         }
+        Ok(checksums)
     }
 
-    fn store(&self, issue: &DownloadedIssue) -> Result<(), std::io::Error> {
-        let issue_filename = format!("{}.json", issue.number);
-        let issue_path = self.dir.join("issues").join(issue_filename);
-        let output = serde_json::to_vec(issue)?;
-        std::fs::write(issue_path, &output)
+    fn record_checksum(&self, number: u64, checksum: &str) -> Result<(), StoreError> {
+        let line = format!("{}  issues/{}.json\n", checksum, number);
+        fs::append_line(self.manifest_path(), line.as_bytes())?;
+        Ok(())
     }
-}
 
-impl graphql::CursorCache for Arc<Storage> {
     fn save_cursor(&self, cursor: String) -> Result<(), std::io::Error> {
         let cursor_path = self.dir.join("last_cursor");
-        std::fs::write(cursor_path, &cursor)?;
+        fs::write_atomic(cursor_path, &cursor)?;
         Ok(())
     }
 
     fn load_cursor(&self) -> Result<Option<String>, std::io::Error> {
         let cursor_path = self.dir.join("last_cursor");
-        if std::fs::try_exists(&cursor_path)? {
-            Ok(Some(
-                std::fs::read_to_string(cursor_path)?.trim().to_string(),
-            ))
+        if fs::exists(&cursor_path)? {
+            Ok(Some(fs::read_to_string(cursor_path)?.trim().to_string()))
         } else {
             Ok(None)
         }
     }
+
+    fn save_last_sync(&self, at: DateTime<Utc>) -> Result<(), std::io::Error> {
+        fs::write_atomic(self.dir.join("last_sync"), at.to_rfc3339())
+    }
+
+    fn load_last_sync(&self) -> Result<Option<DateTime<Utc>>, std::io::Error> {
+        let path = self.dir.join("last_sync");
+        if fs::exists(&path)? {
+            Ok(Some(parse_sync_time(&fs::read_to_string(path)?)?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+impl graphql::CursorCache for Arc<dyn IssueStorage> {
+    fn save_cursor(&self, cursor: String) -> Result<(), std::io::Error> {
+        IssueStorage::save_cursor(self.as_ref(), cursor)
+    }
+
+    fn load_cursor(&self) -> Result<Option<String>, std::io::Error> {
+        IssueStorage::load_cursor(self.as_ref())
+    }
 }
 
-pub(crate) async fn download(
-    crab: octocrab::Octocrab,
+pub async fn download(
+    client: graphql::Client,
     repo: RepoName,
-    storage: Storage,
+    storage: Arc<dyn IssueStorage>,
 ) -> Result<(), Error> {
-    let storage = Arc::new(storage);
-    let mut stream = graphql::issues(crab, repo, Box::new(storage.clone()));
+    let mut stream = graphql::issues(client, repo, Box::new(storage.clone()));
     while let Some(issue) = stream.next().await {
-        storage.store(&issue?)?;
+        let issue = issue?;
+        storage.store(&issue)?;
+        verbose!(
+            "downloaded issue {} with {} comments and {} events",
+            issue.number,
+            issue.comments.len(),
+            issue.events.len()
+        );
     }
     Ok(())
 }
+
+/// Download a single issue into `storage`, replacing it if it was already downloaded. Returns
+/// `false` if the repository has no issue with this number.
+pub async fn download_one(
+    client: graphql::Client,
+    repo: RepoName,
+    number: u64,
+    storage: Arc<dyn IssueStorage>,
+) -> Result<bool, Error> {
+    match graphql::issue(client, repo, number).await? {
+        Some(issue) => {
+            storage.store(&issue)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// What `sync` did to the stored issues
+#[derive(Debug, Default)]
+pub struct SyncSummary {
+    /// The time which issues were synced from, `None` if every issue was
+    pub since: Option<DateTime<Utc>>,
+    /// Issues which hadn't been downloaded before
+    pub new: usize,
+    pub updated: usize,
+    pub comments_added: usize,
+    pub events_added: usize,
+    /// Comments which are stored but are no longer on github, and were kept
+    pub comments_kept: usize,
+}
+
+impl std::fmt::Display for SyncSummary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.since {
+            Some(since) => write!(f, "synced issues updated since {}: ", since.to_rfc3339())?,
+            None => write!(f, "synced every issue: ")?,
+        }
+        write!(
+            f,
+            "{} new, {} updated with {} new comments and {} new events",
+            self.new, self.updated, self.comments_added, self.events_added
+        )?;
+        if self.comments_kept > 0 {
+            write!(
+                f,
+                ", kept {} comments which were deleted on github",
+                self.comments_kept
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Download the issues updated on github since the last sync, or since `since` if it's given,
+/// and merge them into the stored issues. The last sync time is only recorded once every updated
+/// issue has been stored, so an interrupted sync starts over from the same time.
+///
+/// The freshly downloaded issue replaces the stored one, except that comments and events which
+/// are stored but which github no longer returns are kept. Imported objects can't lose comments,
+/// so dropping them from the corpus would make the two disagree.
+pub async fn sync(
+    client: graphql::Client,
+    repo: RepoName,
+    storage: Arc<dyn IssueStorage>,
+    since: Option<DateTime<Utc>>,
+) -> Result<SyncSummary, Error> {
+    // Anything updated while we're syncing is picked up next time
+    let started = Utc::now();
+    let since = match since {
+        Some(since) => Some(since),
+        None => storage.load_last_sync()?,
+    };
+    let mut summary = SyncSummary {
+        since,
+        ..SyncSummary::default()
+    };
+    let mut stream = graphql::updated_issues(client, repo, since);
+    while let Some(issue) = stream.next().await {
+        let mut issue = issue?;
+        let stored = storage.issue(issue.number).map_err(load_to_store)?;
+        match stored {
+            None => summary.new += 1,
+            Some(stored) => {
+                summary.updated += 1;
+                summary.comments_added += issue
+                    .comments
+                    .iter()
+                    .filter(|c| stored.comments.iter().all(|s| s.id != c.id))
+                    .count();
+                summary.events_added += issue
+                    .events
+                    .iter()
+                    .filter(|e| stored.events.iter().all(|s| s.id != e.id))
+                    .count();
+                summary.comments_kept += merge(&mut issue, stored);
+            }
+        }
+        storage.store(&issue)?;
+        verbose!(
+            "synced issue {} with {} comments and {} events",
+            issue.number,
+            issue.comments.len(),
+            issue.events.len()
+        );
+    }
+    storage.save_last_sync(started)?;
+    Ok(summary)
+}
+
+/// Add the comments and events of `stored` which are missing from `fresh` to it, returning the
+/// number of comments added
+fn merge(fresh: &mut DownloadedIssue, stored: DownloadedIssue) -> usize {
+    let missing_comments: Vec<DownloadedComment> = stored
+        .comments
+        .into_iter()
+        .filter(|s| fresh.comments.iter().all(|c| c.id != s.id))
+        .collect();
+    let kept = missing_comments.len();
+    if kept > 0 {
+        fresh.comments.extend(missing_comments);
+        fresh.comments.sort_by_key(|c| c.created_at);
+    }
+    let missing_events: Vec<DownloadedEvent> = stored
+        .events
+        .into_iter()
+        .filter(|s| fresh.events.iter().all(|e| e.id != s.id))
+        .collect();
+    if !missing_events.is_empty() {
+        fresh.events.extend(missing_events);
+        fresh.events.sort_by_key(|e| e.created_at);
+    }
+    kept
+}
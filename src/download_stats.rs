@@ -0,0 +1,151 @@
+//! A summary of the issues downloaded for a repository, to sanity check the corpus before
+//! importing it and to predict what importing it will cost.
+use std::collections::HashSet;
+
+use chrono::{DateTime, Utc};
+
+use crate::download::{IssueStorage, LoadError};
+
+/// Counts of values in power of two sized buckets: 0, 1, 2-3, 4-7, 8-15 and so on
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct Histogram {
+    buckets: Vec<u64>,
+}
+
+impl Histogram {
+    pub fn record(&mut self, value: u64) {
+        let bucket = (64 - value.leading_zeros()) as usize;
+        if self.buckets.len() <= bucket {
+            self.buckets.resize(bucket + 1, 0);
+        }
+        self.buckets[bucket] += 1;
+    }
+
+    fn bucket_range(bucket: usize) -> (u64, u64) {
+        match bucket {
+            0 => (0, 0),
+            b => (1 << (b - 1), (1 << b) - 1),
+        }
+    }
+}
+
+impl std::fmt::Display for Histogram {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let max = self.buckets.iter().copied().max().unwrap_or(0).max(1);
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            let (lo, hi) = Histogram::bucket_range(bucket);
+            let bar = "#".repeat((40 * count / max) as usize);
+            writeln!(f, "  {:>10}-{:<10} {:>8} {}", lo, hi, count, bar)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, serde::Serialize)]
+pub struct DownloadStats {
+    pub issues: u64,
+    pub comments: u64,
+    /// Issues and comments without an author are skipped when importing
+    pub anonymous_issues: u64,
+    pub anonymous_comments: u64,
+    /// Label and state changes
+    pub events: u64,
+    pub anonymous_events: u64,
+    pub distinct_authors: usize,
+    /// The total size of the bodies of every issue and comment
+    pub body_bytes: u64,
+    pub body_sizes: Histogram,
+    pub comments_per_issue: Histogram,
+    pub earliest: Option<DateTime<Utc>>,
+    pub latest: Option<DateTime<Utc>>,
+}
+
+impl DownloadStats {
+    /// The number of changes importing the corpus will create: one per authored issue plus one
+    /// per authored comment and event on those issues
+    pub fn changes(&self) -> u64 {
+        (self.issues - self.anonymous_issues)
+            + (self.comments - self.anonymous_comments)
+            + (self.events - self.anonymous_events)
+    }
+}
+
+/// Summarize every issue in `storage`, loading one issue at a time
+pub fn collect(storage: &dyn IssueStorage) -> Result<DownloadStats, LoadError> {
+    let mut stats = DownloadStats::default();
+    let mut authors = HashSet::new();
+    for number in storage.issue_numbers()? {
+        let issue = match storage.issue(number)? {
+            Some(issue) => issue,
+            None => continue,
+        };
+        stats.issues += 1;
+        match &issue.author_id {
+            Some(author) => {
+                authors.insert(author.clone());
+            }
+            None => stats.anonymous_issues += 1,
+        }
+        let body_len = issue.body.as_ref().map(|b| b.len()).unwrap_or(0) as u64;
+        stats.body_bytes += body_len;
+        stats.body_sizes.record(body_len);
+        stats.comments_per_issue.record(issue.comments.len() as u64);
+        let mut dates = vec![issue.created_at];
+        for comment in &issue.comments {
+            stats.comments += 1;
+            match &comment.author_id {
+                Some(author) => {
+                    authors.insert(author.clone());
+                }
+                None => stats.anonymous_comments += 1,
+            }
+            stats.body_bytes += comment.body.len() as u64;
+            stats.body_sizes.record(comment.body.len() as u64);
+            dates.push(comment.created_at);
+        }
+        for event in &issue.events {
+            stats.events += 1;
+            match &event.actor_id {
+                Some(actor) => {
+                    authors.insert(actor.clone());
+                }
+                None => stats.anonymous_events += 1,
+            }
+        }
+        for date in dates {
+            stats.earliest = Some(stats.earliest.map_or(date, |e| e.min(date)));
+            stats.latest = Some(stats.latest.map_or(date, |l| l.max(date)));
+        }
+    }
+    stats.distinct_authors = authors.len();
+    Ok(stats)
+}
+
+impl std::fmt::Display for DownloadStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} issues ({} without an author)",
+            self.issues, self.anonymous_issues
+        )?;
+        writeln!(
+            f,
+            "{} comments ({} without an author)",
+            self.comments, self.anonymous_comments
+        )?;
+        writeln!(
+            f,
+            "{} label and state changes ({} without an actor)",
+            self.events, self.anonymous_events
+        )?;
+        writeln!(f, "{} distinct authors", self.distinct_authors)?;
+        writeln!(f, "{} bytes of issue and comment bodies", self.body_bytes)?;
+        if let (Some(earliest), Some(latest)) = (self.earliest, self.latest) {
+            writeln!(f, "from {} to {}", earliest, latest)?;
+        }
+        writeln!(f, "body sizes in bytes:")?;
+        write!(f, "{}", self.body_sizes)?;
+        writeln!(f, "comments per issue:")?;
+        write!(f, "{}", self.comments_per_issue)
+    }
+}
@@ -2,8 +2,8 @@ use chrono::{DateTime, Utc};
 
 use crate::GithubUserId;
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub(crate) struct DownloadedIssue {
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct DownloadedIssue {
     pub id: String,
     pub number: u64,
     pub state: String,
@@ -12,13 +12,38 @@ pub(crate) struct DownloadedIssue {
     pub author_id: Option<GithubUserId>,
     pub comments: Vec<DownloadedComment>,
     pub created_at: DateTime<Utc>,
+    /// When the issue was last updated on github. Issues downloaded before this was recorded have
+    /// none.
+    #[serde(default)]
+    pub updated_at: Option<DateTime<Utc>>,
+    /// Label and state changes, oldest first. Issues downloaded before these were recorded have
+    /// none.
+    #[serde(default)]
+    pub events: Vec<DownloadedEvent>,
 }
 
-#[derive(Debug, serde::Deserialize, serde::Serialize)]
-pub(crate) struct DownloadedComment {
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct DownloadedComment {
     pub id: String,
     pub author_id: Option<GithubUserId>,
     pub body: String,
     pub created_at: DateTime<Utc>,
     pub updated_at: Option<DateTime<Utc>>,
 }
+
+#[derive(Debug, Clone, serde::Deserialize, serde::Serialize)]
+pub struct DownloadedEvent {
+    pub id: String,
+    pub actor_id: Option<GithubUserId>,
+    pub created_at: DateTime<Utc>,
+    pub kind: EventKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, serde::Deserialize, serde::Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum EventKind {
+    Labeled { label: String },
+    Unlabeled { label: String },
+    Closed,
+    Reopened,
+}
@@ -0,0 +1,270 @@
+//! Working out what the commands which change a repository's data would do, for `--dry-run`.
+//! Importing, deleting and archiving can't be undone and the datasets they act on can take days
+//! to build, so each of them can print what it would create or remove instead.
+//!
+//! An import is planned without opening the monorepo, as opening it creates it if it doesn't
+//! exist; the issue index and peer assignments are read directly instead. Deleting and archiving
+//! need the monorepo to find the refs of each object, so they only plan against one which already
+//! exists. Retrieving the objects to archive may still fill the cache.
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use either::Either;
+use thiserror::Error;
+
+use crate::{
+    downloaded_issue::DownloadedIssue,
+    issue_index::{self, IssueIndex},
+    maintain::Operation,
+    peer_assignments::{self, PeerAssignments},
+    repo_stats::Disk,
+    GithubUserId,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    IssueIndex(#[from] issue_index::Error),
+    #[error(transparent)]
+    PeerAssignments(#[from] peer_assignments::Error),
+}
+
+/// What importing the downloaded issues would do
+pub struct ImportPlan {
+    pub monorepo: PathBuf,
+    pub monorepo_exists: bool,
+    pub issues: usize,
+    /// Issues which would be skipped because their author's account was deleted
+    pub without_author: usize,
+    /// Issues which would be skipped because they were completely imported before
+    pub already_imported: usize,
+    /// Issues which were partially imported before and would be finished
+    pub resumed: usize,
+    /// Issues which were completely imported before and have gained comments or events since,
+    /// which an incremental import would add
+    pub updated: usize,
+    pub new_objects: usize,
+    pub changes: usize,
+    /// An upper bound, as several users may be assigned the same peer
+    pub refs: usize,
+    /// Users who would be assigned a peer for the first time
+    pub new_users: usize,
+    /// Issues in the index which would be imported again as new objects, as `--resume` wasn't
+    /// given
+    pub reimported: usize,
+}
+
+/// The author of each comment and event of `issue`, in the order `LiteMonorepo::import_issue`
+/// applies them
+fn update_authors(issue: &DownloadedIssue) -> Vec<Option<&GithubUserId>> {
+    let mut updates: Vec<Either<_, _>> = issue
+        .comments
+        .iter()
+        .map(Either::Left)
+        .chain(issue.events.iter().map(Either::Right))
+        .collect();
+    updates.sort_by_key(|u| match u {
+        Either::Left(comment) => comment.created_at,
+        Either::Right(event) => event.created_at,
+    });
+    updates
+        .into_iter()
+        .map(|u| match u {
+            Either::Left(comment) => comment.author_id.as_ref(),
+            Either::Right(event) => event.actor_id.as_ref(),
+        })
+        .collect()
+}
+
+/// Plan importing `issues` into the monorepo at `monorepo`. The new comments and events of an
+/// issue which would be imported incrementally are taken to be those after the position the last
+/// import got to, which is what they are unless comments were deleted on github.
+pub fn plan_import(
+    monorepo: &Path,
+    issues: &[DownloadedIssue],
+    resume: bool,
+    incremental: bool,
+) -> Result<ImportPlan, Error> {
+    let resume = resume || incremental;
+    let monorepo_exists = crate::fs::exists(monorepo)?;
+    let index = IssueIndex::read(monorepo.join(issue_index::ISSUE_INDEX))?;
+    let assignments = PeerAssignments::load(monorepo.join("peer_map"), std::iter::empty())?;
+    let mut plan = ImportPlan {
+        monorepo: monorepo.to_path_buf(),
+        monorepo_exists,
+        issues: issues.len(),
+        without_author: 0,
+        already_imported: 0,
+        resumed: 0,
+        updated: 0,
+        new_objects: 0,
+        changes: 0,
+        refs: 0,
+        new_users: 0,
+        reimported: 0,
+    };
+    let mut new_users = HashSet::new();
+    for issue in issues {
+        let author = match &issue.author_id {
+            Some(author) => author,
+            None => {
+                plan.without_author += 1;
+                continue;
+            }
+        };
+        let previous = index.get(issue.number);
+        if !resume && previous.is_some() {
+            plan.reimported += 1;
+        }
+        let authors = update_authors(issue);
+        let (remaining, new_object) = match previous {
+            Some(entry) if incremental && entry.complete && entry.applied < authors.len() => {
+                plan.updated += 1;
+                (&authors[entry.applied..], false)
+            }
+            Some(entry) if resume && entry.complete => {
+                plan.already_imported += 1;
+                continue;
+            }
+            Some(entry) if resume && entry.object_id().is_some() => {
+                plan.resumed += 1;
+                (&authors[entry.applied.min(authors.len())..], false)
+            }
+            _ => {
+                plan.new_objects += 1;
+                (&authors[..], true)
+            }
+        };
+        let mut writers: HashSet<&GithubUserId> = remaining.iter().flatten().copied().collect();
+        plan.changes += remaining.iter().flatten().count();
+        if new_object {
+            plan.changes += 1;
+            writers.insert(author);
+        }
+        plan.refs += writers.len();
+        new_users.extend(writers.into_iter().filter(|u| !assignments.is_assigned(u)));
+    }
+    plan.new_users = new_users.len();
+    Ok(plan)
+}
+
+impl std::fmt::Display for ImportPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.monorepo_exists {
+            writeln!(f, "would import into {}", self.monorepo.display())?;
+        } else {
+            writeln!(
+                f,
+                "would create a monorepo at {} and import into it",
+                self.monorepo.display()
+            )?;
+        }
+        writeln!(
+            f,
+            "{} downloaded issues: {} new objects, {} resumed, {} updated, {} already imported, {} \
+             without an author",
+            self.issues,
+            self.new_objects,
+            self.resumed,
+            self.updated,
+            self.already_imported,
+            self.without_author
+        )?;
+        writeln!(
+            f,
+            "would create {} changes and at most {} refs",
+            self.changes, self.refs
+        )?;
+        writeln!(
+            f,
+            "would assign a peer to {} github users who don't have one yet",
+            self.new_users
+        )?;
+        if self.reimported > 0 {
+            writeln!(
+                f,
+                "{} issues have been imported before and would be imported again as new objects, \
+                 use --resume to skip them or --incremental to add what they've gained",
+                self.reimported
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// The refs which would be removed for each object, and where archived objects would be stored
+pub struct RemovalPlan {
+    pub objects: Vec<(cob::ObjectId, Vec<String>)>,
+    pub cold_store: Option<PathBuf>,
+}
+
+impl std::fmt::Display for RemovalPlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut refs = 0;
+        for (id, names) in &self.objects {
+            match &self.cold_store {
+                Some(dir) => writeln!(
+                    f,
+                    "would archive {} to {} and {}",
+                    id,
+                    dir.join(format!("{}.json.gz", id)).display(),
+                    dir.join(format!("{}.automerge.gz", id)).display()
+                )?,
+                None => writeln!(f, "would delete the refs of {}", id)?,
+            }
+            for name in names {
+                writeln!(f, "  {}", name)?;
+            }
+            refs += names.len();
+        }
+        writeln!(
+            f,
+            "would remove {} refs of {} objects",
+            refs,
+            self.objects.len()
+        )
+    }
+}
+
+/// What running git maintenance on a monorepo would do, see [`crate::maintain::plan`]
+pub struct MaintenancePlan {
+    pub operation: Operation,
+    pub disk: Disk,
+    /// The number of unreachable loose objects which would be pruned
+    pub pruned: usize,
+    /// The legacy identities file and the number of peers in it, if opening the monorepo would
+    /// migrate them to refs and remove it
+    pub legacy_identities: Option<(PathBuf, usize)>,
+}
+
+impl std::fmt::Display for MaintenancePlan {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some((path, peers)) = &self.legacy_identities {
+            writeln!(
+                f,
+                "would migrate the identities of {} peers from {} to refs and remove it",
+                peers,
+                path.display()
+            )?;
+        }
+        writeln!(
+            f,
+            "would run {} on {} loose objects and {} packed in {} packs, {} bytes on disk",
+            self.operation,
+            self.disk.loose_objects,
+            self.disk.packed_objects,
+            self.disk.packs,
+            self.disk.total_bytes
+        )?;
+        match self.operation {
+            Operation::Repack => writeln!(f, "would prune nothing"),
+            Operation::Gc | Operation::GcAggressive => {
+                writeln!(f, "would prune {} unreachable loose objects", self.pruned)
+            }
+        }
+    }
+}
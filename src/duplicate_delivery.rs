@@ -0,0 +1,298 @@
+//! Delivering every ref update to a replica several times and in a random order, including
+//! earlier states of each object arriving after later ones, to check that the replica ends up
+//! with exactly the refs and documents of the original and to measure how much work the
+//! redundant deliveries cost. After each delivery the object is retrieved through the replica's
+//! cache, as a node would to update its view of the object, so that the cache sees every
+//! duplicate too.
+//!
+//! The replica pins the identity of each peer the first time it receives an update from it, and
+//! rejects updates from peers which later present a different identity. With `impersonate` set,
+//! once every update has been delivered some peers deliver their updates again while presenting
+//! a new identity, all of which should be rejected.
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use rand::{seq::SliceRandom, SeedableRng};
+use thiserror::Error;
+
+use crate::{
+    identity_pins::{self, IdentityPins, PinCheck},
+    lite_monorepo::{error, LiteMonorepo},
+    replication::{self, Delivery, RefUpdate},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    Pins(#[from] identity_pins::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+pub struct Options {
+    /// How many times each update is delivered
+    pub copies: usize,
+    /// How many earlier states of each object are delivered alongside its current state
+    pub history: usize,
+    /// Only deliver the updates of this many objects
+    pub objects: Option<usize>,
+    pub seed: u64,
+    pub network: replication::Network,
+    /// How many peers redeliver their updates under a different identity
+    pub impersonate: usize,
+}
+
+/// The work done by a set of deliveries
+#[derive(Debug, Default)]
+pub struct Cost {
+    pub deliveries: usize,
+    pub time: Duration,
+    pub objects: replication::CopyStats,
+}
+
+impl Cost {
+    fn add(&mut self, time: Duration, objects: replication::CopyStats) {
+        self.deliveries += 1;
+        self.time += time;
+        self.objects += objects;
+    }
+}
+
+impl std::fmt::Display for Cost {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let per_delivery = self.time / self.deliveries.max(1) as u32;
+        write!(
+            f,
+            "{} deliveries in {:?} ({:?} each), {} objects copied ({} bytes), {} already present",
+            self.deliveries,
+            self.time,
+            per_delivery,
+            self.objects.copied,
+            self.objects.bytes,
+            self.objects.present
+        )
+    }
+}
+
+pub struct Report {
+    pub first: Cost,
+    pub duplicate: Cost,
+    pub updates: HashMap<RefUpdate, usize>,
+    /// Refs whose target in the replica differs from the original
+    pub diverged_refs: Vec<String>,
+    /// Objects whose document in the replica, retrieved with or without the cache, differs from
+    /// the original
+    pub mismatched_objects: Vec<cob::ObjectId>,
+    /// How long after the first delivery every ref had reached its final state
+    pub converged_after: Option<Duration>,
+    /// The number of peers whose identities were pinned
+    pub pinned: usize,
+    /// Deliveries which were rejected because the peer presented a different identity than the
+    /// pinned one
+    pub pin_mismatches: Vec<(String, PinCheck)>,
+    /// Deliveries by impersonated peers which were accepted
+    pub impersonations_missed: usize,
+    pub impersonations_rejected: usize,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.diverged_refs.is_empty()
+            && self.mismatched_objects.is_empty()
+            && self.pin_mismatches.is_empty()
+            && self.impersonations_missed == 0
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.converged_after {
+            Some(t) => writeln!(f, "converged after {:?}", t)?,
+            None => writeln!(f, "never converged")?,
+        }
+        writeln!(f, "first deliveries: {}", self.first)?;
+        writeln!(f, "duplicate deliveries: {}", self.duplicate)?;
+        for update in &[
+            RefUpdate::Created,
+            RefUpdate::FastForwarded,
+            RefUpdate::AlreadyCurrent,
+            RefUpdate::Stale,
+        ] {
+            writeln!(
+                f,
+                "  {:?}: {}",
+                update,
+                self.updates.get(update).copied().unwrap_or(0)
+            )?;
+        }
+        for reference in &self.diverged_refs {
+            writeln!(f, "diverged: {}", reference)?;
+        }
+        for object in &self.mismatched_objects {
+            writeln!(f, "mismatched: {}", object)?;
+        }
+        writeln!(f, "pinned the identities of {} peers", self.pinned)?;
+        for (reference, check) in &self.pin_mismatches {
+            if let PinCheck::Mismatch { pinned, presented } = check {
+                writeln!(
+                    f,
+                    "identity mismatch: {} presented {} but {} is pinned",
+                    reference, presented, pinned
+                )?;
+            }
+        }
+        if self.impersonations_rejected + self.impersonations_missed > 0 {
+            writeln!(
+                f,
+                "impersonated deliveries: {} rejected, {} accepted",
+                self.impersonations_rejected, self.impersonations_missed
+            )?;
+        }
+        Ok(())
+    }
+}
+
+pub fn run(source: &LiteMonorepo, replica_root: &Path, options: &Options) -> Result<Report, Error> {
+    let replica = replication::create_replica(source, replica_root)?;
+    let mut deliveries = replication::deliveries(source.repo(), options.history)?;
+    if let Some(n) = options.objects {
+        let mut objects: Vec<cob::ObjectId> = deliveries
+            .iter()
+            .filter_map(Delivery::object_id)
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect();
+        objects.sort_by_key(|o| o.to_string());
+        objects.truncate(n);
+        let objects: HashSet<cob::ObjectId> = objects.into_iter().collect();
+        deliveries.retain(|d| d.object_id().map_or(false, |o| objects.contains(&o)));
+    }
+    let originals: HashMap<String, git2::Oid> = deliveries
+        .iter()
+        .filter_map(|d| {
+            let reference = source.repo().find_reference(&d.reference).ok()?;
+            Some((d.reference.clone(), reference.target()?))
+        })
+        .collect();
+
+    let mut schedule: Vec<&Delivery> = deliveries
+        .iter()
+        .flat_map(|d| std::iter::repeat(d).take(options.copies.max(1)))
+        .collect();
+    let mut rng = rand::rngs::StdRng::seed_from_u64(options.seed);
+    schedule.shuffle(&mut rng);
+
+    let mut delivered = HashSet::new();
+    let mut report = Report {
+        first: Cost::default(),
+        duplicate: Cost::default(),
+        updates: HashMap::new(),
+        diverged_refs: Vec::new(),
+        mismatched_objects: Vec::new(),
+        converged_after: None,
+        pinned: 0,
+        pin_mismatches: Vec::new(),
+        impersonations_missed: 0,
+        impersonations_rejected: 0,
+    };
+    let project = source.project_urn();
+    let mut pins = IdentityPins::default();
+    let mut presented = HashMap::new();
+    let mut pending: HashSet<&String> = originals.keys().collect();
+    let started = Instant::now();
+    for delivery in schedule {
+        if let Some(peer) = identity_pins::peer_of_ref(&delivery.reference) {
+            if !presented.contains_key(&peer) {
+                let urn = identity_pins::presented_identity(source.repo(), &project, &peer)?;
+                presented.insert(peer, urn);
+            }
+            if let Some(urn) = &presented[&peer] {
+                let check = pins.check(&peer, urn);
+                if let PinCheck::Mismatch { .. } = check {
+                    report
+                        .pin_mismatches
+                        .push((delivery.reference.clone(), check));
+                    continue;
+                }
+            }
+        }
+        let start = Instant::now();
+        let result =
+            replication::deliver(source.repo(), replica.repo(), delivery, &options.network)?;
+        if let Some(object_id) = delivery.object_id() {
+            replica.retrieve_issue(&object_id, true)?;
+        }
+        let elapsed = start.elapsed();
+        *report.updates.entry(result.update).or_default() += 1;
+        if originals.get(&delivery.reference) == Some(&delivery.commit)
+            && pending.remove(&delivery.reference)
+            && pending.is_empty()
+        {
+            report.converged_after = Some(started.elapsed());
+        }
+        if delivered.insert(delivery) {
+            report.first.add(elapsed, result.objects);
+        } else {
+            report.duplicate.add(elapsed, result.objects);
+        }
+    }
+
+    if options.impersonate > 0 {
+        let impersonator = git2::Repository::init_bare(replica_root.join("impersonator"))?;
+        let mut peers: Vec<_> = presented.keys().copied().collect();
+        peers.sort_by_key(|p| p.to_string());
+        peers.shuffle(&mut rng);
+        for peer in peers.into_iter().take(options.impersonate) {
+            let impostor = identity_pins::impersonate(&impersonator, &peer)?;
+            for delivery in deliveries.iter().filter(|d| {
+                originals.get(&d.reference) == Some(&d.commit)
+                    && identity_pins::peer_of_ref(&d.reference) == Some(peer)
+            }) {
+                match pins.check(&peer, &impostor) {
+                    PinCheck::Mismatch { .. } => report.impersonations_rejected += 1,
+                    _ => {
+                        report.impersonations_missed += 1;
+                        replication::deliver(
+                            source.repo(),
+                            replica.repo(),
+                            delivery,
+                            &options.network,
+                        )?;
+                    }
+                }
+            }
+        }
+    }
+    report.pinned = pins.len();
+    pins.save(replica_root.join("identity_pins.json"))?;
+
+    for (reference, original) in &originals {
+        let replicated = replica
+            .repo()
+            .find_reference(reference)
+            .ok()
+            .and_then(|r| r.target());
+        if replicated != Some(*original) {
+            report.diverged_refs.push(reference.clone());
+        }
+    }
+    report.diverged_refs.sort();
+    let objects: HashSet<cob::ObjectId> =
+        deliveries.iter().filter_map(Delivery::object_id).collect();
+    for object_id in objects {
+        let original = source.retrieve_issue(&object_id, false)?;
+        let cached = replica.retrieve_issue(&object_id, true)?;
+        let uncached = replica.retrieve_issue(&object_id, false)?;
+        if cached != original || uncached != original {
+            report.mismatched_objects.push(object_id);
+        }
+    }
+    Ok(report)
+}
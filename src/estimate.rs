@@ -0,0 +1,114 @@
+//! Predicting what importing a corpus will cost from the measurements of previous imports. Every
+//! import appends the number of changes it created, how long it took, and how much the monorepo
+//! grew to `import_runs.jsonl` in the data directory, so measurements from every repository
+//! contribute to the estimate.
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::{download_stats::DownloadStats, lite_monorepo::LiteMonorepo, repo_name::RepoName};
+
+/// The name of the file in the data directory which import measurements are recorded in
+pub const IMPORT_RUNS: &str = "import_runs.jsonl";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// The measurements of one import
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct ImportRun {
+    pub repo: String,
+    pub at: DateTime<Utc>,
+    pub changes: u64,
+    pub elapsed_secs: f64,
+    pub bytes_added: i64,
+    pub refs_added: i64,
+}
+
+/// Measures an import from the point it was created until [`Measurement::finish`] is called
+pub struct Measurement {
+    start: Instant,
+    bytes: u64,
+    refs: usize,
+}
+
+impl Measurement {
+    pub fn start(monorepo: &LiteMonorepo) -> Result<Measurement, Error> {
+        Ok(Measurement {
+            start: Instant::now(),
+            bytes: monorepo.git_size()?,
+            refs: monorepo.ref_count()?,
+        })
+    }
+
+    pub fn finish(self, repo: &RepoName, monorepo: &LiteMonorepo) -> Result<ImportRun, Error> {
+        Ok(ImportRun {
+            repo: repo.to_string(),
+            at: Utc::now(),
+            changes: monorepo.changes_created(),
+            elapsed_secs: self.start.elapsed().as_secs_f64(),
+            bytes_added: monorepo.git_size()? as i64 - self.bytes as i64,
+            refs_added: monorepo.ref_count()? as i64 - self.refs as i64,
+        })
+    }
+}
+
+pub fn record<P: AsRef<Path>>(path: P, run: &ImportRun) -> Result<(), Error> {
+    let mut line = serde_json::to_vec(run)?;
+    line.push(b'\n');
+    crate::fs::append_line(path, &line)?;
+    Ok(())
+}
+
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<ImportRun>, Error> {
+    if !crate::fs::exists(&path)? {
+        return Ok(Vec::new());
+    }
+    let contents = crate::fs::read_to_string(path)?;
+    contents
+        .lines()
+        .filter(|l| !l.trim().is_empty())
+        .map(|l| serde_json::from_str(l).map_err(Error::from))
+        .collect()
+}
+
+pub struct Estimate {
+    /// The number of runs the estimate is based on
+    pub runs: usize,
+    pub changes: u64,
+    pub time: Duration,
+    pub bytes: u64,
+    pub refs: u64,
+}
+
+/// Estimate the cost of importing the corpus summarized by `stats`. Returns `None` if none of
+/// `runs` created any changes.
+pub fn estimate(runs: &[ImportRun], stats: &DownloadStats) -> Option<Estimate> {
+    let measured_changes: u64 = runs.iter().map(|r| r.changes).sum();
+    if measured_changes == 0 {
+        return None;
+    }
+    let per_change = |total: f64| total / measured_changes as f64;
+    let secs_per_change = per_change(runs.iter().map(|r| r.elapsed_secs).sum());
+    let bytes_per_change = per_change(runs.iter().map(|r| r.bytes_added.max(0) as f64).sum());
+    let refs_per_change = per_change(runs.iter().map(|r| r.refs_added.max(0) as f64).sum());
+    let changes = stats.changes();
+    Some(Estimate {
+        runs: runs.len(),
+        changes,
+        time: Duration::from_secs_f64(secs_per_change * changes as f64),
+        bytes: (bytes_per_change * changes as f64) as u64,
+        refs: (refs_per_change * changes as f64) as u64,
+    })
+}
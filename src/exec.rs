@@ -0,0 +1,100 @@
+//! Running an external script on each materialized object, so that one-off analyses don't need
+//! changes to this crate. The script is given as a command template in which `{object_id}` is
+//! replaced with the ID of the object and `{json_path}` with the path of a file containing its
+//! materialized document. The command is run with `sh -c` and its output passes through.
+use std::{path::Path, str::FromStr};
+
+use thiserror::Error;
+
+use crate::lite_monorepo::MaterializedIssue;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Error)]
+#[error("the command must use {{object_id}} or {{json_path}}")]
+pub struct ParseError;
+
+/// A command template run for each object
+#[derive(Debug, Clone)]
+pub struct Hook(String);
+
+impl FromStr for Hook {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.contains("{object_id}") || s.contains("{json_path}") {
+            Ok(Hook(s.to_string()))
+        } else {
+            Err(ParseError)
+        }
+    }
+}
+
+/// Quote `s` for use as a single word in `sh`
+fn quote(s: &str) -> String {
+    format!("'{}'", s.replace('\'', r"'\''"))
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub ran: usize,
+    /// The objects the command didn't exit successfully for, with its exit code if it exited
+    pub failed: Vec<(cob::ObjectId, Option<i32>)>,
+}
+
+impl Hook {
+    /// Write the document of `issue` into `dir` and run the command on it
+    pub fn run(
+        &self,
+        issue: &MaterializedIssue,
+        dir: &Path,
+    ) -> Result<std::process::ExitStatus, Error> {
+        crate::fs::create_dir_all(dir)?;
+        let json_path = dir.join(format!("{}.json", issue.id));
+        crate::fs::write_atomic(&json_path, serde_json::to_vec_pretty(&issue.document)?)?;
+        let command = self
+            .0
+            .replace("{object_id}", &issue.id.to_string())
+            .replace("{json_path}", &quote(&json_path.to_string_lossy()));
+        Ok(std::process::Command::new("sh")
+            .arg("-c")
+            .arg(command)
+            .status()?)
+    }
+
+    /// Run the command on each of `issues` in turn
+    pub fn run_all(&self, issues: &[MaterializedIssue], dir: &Path) -> Result<Summary, Error> {
+        let mut summary = Summary::default();
+        for issue in issues {
+            summary.ran += 1;
+            let status = self.run(issue, dir)?;
+            if !status.success() {
+                summary.failed.push((issue.id, status.code()));
+            }
+        }
+        Ok(summary)
+    }
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (id, code) in &self.failed {
+            match code {
+                Some(code) => writeln!(f, "exec exited with {} for {}", code, id)?,
+                None => writeln!(f, "exec was killed for {}", id)?,
+            }
+        }
+        writeln!(
+            f,
+            "ran exec on {} objects, {} failed",
+            self.ran,
+            self.failed.len()
+        )
+    }
+}
@@ -0,0 +1,693 @@
+//! Exporting the imported issues and the measurements of previous runs as tables for analysis
+//! outside of this crate, e.g. with pandas or polars. Each table is written to its own file,
+//! as newline delimited JSON, Parquet or CSV.
+//!
+//! The tables are
+//!
+//! * `issues`: one row per object
+//! * `comments`: one row per comment, keyed by the object ID and the comment's github ID
+//! * `changes`: one row per automerge change in the history of each object
+//! * `import_runs`: the measurements of every import, see `crate::estimate`
+//! * `runs`: when each long running command ran, see `crate::runs`
+//!
+//! `export-sqlite` writes the same tables, and two more, into a single SQLite database instead,
+//! for ad-hoc SQL over the results of a stress test:
+//!
+//! * `peer_assignments`: the peer each GitHub user was assigned
+//! * `object_metrics`: one row per object, with how many creates and updates of it the audit log
+//!   (see `crate::audit`) records, how long they took, and how large the object is
+//!
+//! `export-metrics` writes a `metrics` table of its own, see [`retrieval_metrics`], with one row
+//! per object and the name of the repository in every row, so that the tables of repositories of
+//! different sizes can be concatenated to chart how cob scales.
+//!
+//! The documents of the objects can also be streamed on their own, as a line of JSON each, by
+//! [`stream_documents`], which retrieves one object at a time so that exporting a large monorepo
+//! doesn't hold every document in memory.
+use std::{collections::HashMap, fs::File, io::Write, path::Path, str::FromStr, sync::Arc};
+
+use chrono::{DateTime, Utc};
+use parquet::{
+    basic::Compression,
+    column::writer::ColumnWriter,
+    data_type::ByteArray,
+    file::{
+        properties::WriterProperties,
+        writer::{FileWriter, RowGroupWriter, SerializedFileWriter},
+    },
+    schema::parser::parse_message_type,
+};
+use serde_json::{json, Map, Value};
+use thiserror::Error;
+
+use crate::{
+    audit::{self, Operation},
+    estimate::ImportRun,
+    layout,
+    lite_monorepo::{self, LiteMonorepo, MaterializedIssue},
+    runs::Run,
+    GithubUserId,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+    #[error(transparent)]
+    List(#[from] lite_monorepo::error::List),
+    #[error(transparent)]
+    Retrieve(#[from] lite_monorepo::error::Retrieve),
+    #[error(transparent)]
+    Refs(#[from] lite_monorepo::error::Delete),
+}
+
+#[derive(Debug, Error)]
+#[error("unknown format {0}, expected ndjson, parquet or csv")]
+pub struct ParseFormatError(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Ndjson,
+    Parquet,
+    Csv,
+}
+
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ndjson" => Ok(Format::Ndjson),
+            "parquet" => Ok(Format::Parquet),
+            "csv" => Ok(Format::Csv),
+            other => Err(ParseFormatError(other.to_string())),
+        }
+    }
+}
+
+impl Format {
+    fn extension(&self) -> &'static str {
+        match self {
+            Format::Ndjson => "ndjson",
+            Format::Parquet => "parquet",
+            Format::Csv => "csv",
+        }
+    }
+}
+
+/// The type of a column. Every column is nullable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Str,
+    Int,
+    Float,
+    Bool,
+    /// Stored in rows as an RFC 3339 string, written to Parquet as milliseconds since the epoch
+    Timestamp,
+}
+
+impl Kind {
+    fn parquet_type(&self) -> &'static str {
+        match self {
+            Kind::Str => "BYTE_ARRAY",
+            Kind::Int | Kind::Timestamp => "INT64",
+            Kind::Float => "DOUBLE",
+            Kind::Bool => "BOOLEAN",
+        }
+    }
+
+    fn sqlite_type(&self) -> &'static str {
+        match self {
+            Kind::Str | Kind::Timestamp => "TEXT",
+            Kind::Int | Kind::Bool => "INTEGER",
+            Kind::Float => "REAL",
+        }
+    }
+
+    /// `value` as this kind of SQLite value. Timestamps are left as RFC 3339 strings, which
+    /// SQLite's date functions understand.
+    fn sqlite_value(&self, value: Option<&Value>) -> rusqlite::types::Value {
+        use rusqlite::types::Value as Sql;
+        let value = match value {
+            Some(value) if !value.is_null() => value,
+            _ => return Sql::Null,
+        };
+        match self {
+            Kind::Str | Kind::Timestamp => match value.as_str() {
+                Some(s) => Sql::Text(s.to_string()),
+                None => Sql::Text(value.to_string()),
+            },
+            Kind::Int => value.as_i64().map_or(Sql::Null, Sql::Integer),
+            Kind::Float => value.as_f64().map_or(Sql::Null, Sql::Real),
+            Kind::Bool => value
+                .as_bool()
+                .map_or(Sql::Null, |b| Sql::Integer(b as i64)),
+        }
+    }
+
+    fn parquet_annotation(&self) -> &'static str {
+        match self {
+            Kind::Str => " (UTF8)",
+            Kind::Timestamp => " (TIMESTAMP_MILLIS)",
+            _ => "",
+        }
+    }
+}
+
+pub struct Table {
+    name: &'static str,
+    columns: Vec<(&'static str, Kind)>,
+    rows: Vec<Map<String, Value>>,
+}
+
+impl Table {
+    fn new(name: &'static str, columns: &[(&'static str, Kind)]) -> Table {
+        Table {
+            name,
+            columns: columns.to_vec(),
+            rows: Vec::new(),
+        }
+    }
+
+    fn push(&mut self, row: Value) {
+        if let Value::Object(row) = row {
+            self.rows.push(row);
+        }
+    }
+
+    fn parquet_schema(&self) -> String {
+        let mut schema = format!("message {} {{\n", self.name);
+        for (name, kind) in &self.columns {
+            schema.push_str(&format!(
+                "  OPTIONAL {} {}{};\n",
+                kind.parquet_type(),
+                name,
+                kind.parquet_annotation()
+            ));
+        }
+        schema.push('}');
+        schema
+    }
+
+    fn write_ndjson(&self, path: &Path) -> Result<(), Error> {
+        let mut out = std::io::BufWriter::new(File::create(path)?);
+        for row in &self.rows {
+            serde_json::to_writer(&mut out, row)?;
+            writeln!(out)?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn write_csv(&self, path: &Path) -> Result<(), Error> {
+        let mut out = std::io::BufWriter::new(File::create(path)?);
+        let header: Vec<&str> = self.columns.iter().map(|(name, _)| *name).collect();
+        writeln!(out, "{}", header.join(","))?;
+        for row in &self.rows {
+            let fields: Vec<String> = self
+                .columns
+                .iter()
+                .map(|(name, _)| match row.get(*name) {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(s)) => csv_field(s),
+                    Some(other) => csv_field(&other.to_string()),
+                })
+                .collect();
+            writeln!(out, "{}", fields.join(","))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn write_parquet(&self, path: &Path) -> Result<(), Error> {
+        let schema = Arc::new(parse_message_type(&self.parquet_schema())?);
+        let properties = Arc::new(
+            WriterProperties::builder()
+                .set_compression(Compression::SNAPPY)
+                .build(),
+        );
+        let mut writer = SerializedFileWriter::new(File::create(path)?, schema, properties)?;
+        let mut row_group = writer.next_row_group()?;
+        let mut columns = self.columns.iter();
+        while let Some(mut column_writer) = row_group.next_column()? {
+            let (name, kind) = columns.next().expect("a column writer for every column");
+            let values: Vec<Option<&Value>> = self
+                .rows
+                .iter()
+                .map(|r| r.get(*name).filter(|v| !v.is_null()))
+                .collect();
+            let levels: Vec<i16> = values.iter().map(|v| v.is_some() as i16).collect();
+            match (&mut column_writer, kind) {
+                (ColumnWriter::ByteArrayColumnWriter(w), Kind::Str) => {
+                    let values: Vec<ByteArray> = values
+                        .iter()
+                        .flatten()
+                        .map(|v| ByteArray::from(v.as_str().unwrap_or_default()))
+                        .collect();
+                    w.write_batch(&values, Some(&levels), None)?;
+                }
+                (ColumnWriter::Int64ColumnWriter(w), Kind::Int) => {
+                    let values: Vec<i64> = values
+                        .iter()
+                        .flatten()
+                        .map(|v| v.as_i64().unwrap_or_default())
+                        .collect();
+                    w.write_batch(&values, Some(&levels), None)?;
+                }
+                (ColumnWriter::Int64ColumnWriter(w), Kind::Timestamp) => {
+                    let values: Vec<i64> = values
+                        .iter()
+                        .flatten()
+                        .map(|v| {
+                            v.as_str()
+                                .and_then(|s| DateTime::parse_from_rfc3339(s).ok())
+                                .map(|t| t.timestamp_millis())
+                                .unwrap_or_default()
+                        })
+                        .collect();
+                    w.write_batch(&values, Some(&levels), None)?;
+                }
+                (ColumnWriter::DoubleColumnWriter(w), Kind::Float) => {
+                    let values: Vec<f64> = values
+                        .iter()
+                        .flatten()
+                        .map(|v| v.as_f64().unwrap_or_default())
+                        .collect();
+                    w.write_batch(&values, Some(&levels), None)?;
+                }
+                (ColumnWriter::BoolColumnWriter(w), Kind::Bool) => {
+                    let values: Vec<bool> = values
+                        .iter()
+                        .flatten()
+                        .map(|v| v.as_bool().unwrap_or_default())
+                        .collect();
+                    w.write_batch(&values, Some(&levels), None)?;
+                }
+                _ => unreachable!("the schema is generated from the column kinds"),
+            }
+            row_group.close_column(column_writer)?;
+        }
+        writer.close_row_group(row_group)?;
+        writer.close()?;
+        Ok(())
+    }
+
+    /// Replace the table of the same name in `conn` with this one
+    fn write_sqlite(&self, conn: &rusqlite::Connection) -> Result<(), Error> {
+        let columns: Vec<String> = self
+            .columns
+            .iter()
+            .map(|(name, kind)| format!("\"{}\" {}", name, kind.sqlite_type()))
+            .collect();
+        conn.execute_batch(&format!(
+            "DROP TABLE IF EXISTS \"{name}\"; CREATE TABLE \"{name}\" ({columns});",
+            name = self.name,
+            columns = columns.join(", ")
+        ))?;
+        let placeholders: Vec<String> = (1..=self.columns.len())
+            .map(|i| format!("?{}", i))
+            .collect();
+        let mut insert = conn.prepare(&format!(
+            "INSERT INTO \"{}\" VALUES ({})",
+            self.name,
+            placeholders.join(", ")
+        ))?;
+        for row in &self.rows {
+            let values = self
+                .columns
+                .iter()
+                .map(|(name, kind)| kind.sqlite_value(row.get(*name)));
+            insert.execute(rusqlite::params_from_iter(values))?;
+        }
+        Ok(())
+    }
+
+    /// Write the table to `<dir>/<name>.<format>`
+    pub fn write(&self, dir: &Path, format: Format) -> Result<(), Error> {
+        crate::fs::create_dir_all(dir)?;
+        let path = dir.join(format!("{}.{}", self.name, format.extension()));
+        match format {
+            Format::Ndjson => self.write_ndjson(&path),
+            Format::Parquet => self.write_parquet(&path),
+            Format::Csv => self.write_csv(&path),
+        }
+    }
+}
+
+impl std::fmt::Display for Table {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {} rows", self.name, self.rows.len())
+    }
+}
+
+/// `field` quoted, if it needs to be, as RFC 4180 says
+fn csv_field(field: &str) -> String {
+    if field.contains(&[',', '"', '\n', '\r'][..]) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+fn rfc3339(millis: i64) -> Option<String> {
+    use chrono::TimeZone;
+    Utc.timestamp_millis_opt(millis)
+        .single()
+        .map(|t| t.to_rfc3339())
+}
+
+/// The `issues`, `comments` and `changes` tables of `issues`
+pub fn object_tables(issues: &[MaterializedIssue]) -> Vec<Table> {
+    let mut issue_table = Table::new(
+        "issues",
+        &[
+            ("object_id", Kind::Str),
+            ("github_number", Kind::Int),
+            ("title", Kind::Str),
+            ("author_urn", Kind::Str),
+            ("state", Kind::Str),
+            ("created_at", Kind::Timestamp),
+            ("comments", Kind::Int),
+            ("labels", Kind::Int),
+            ("changes", Kind::Int),
+            ("history_bytes", Kind::Int),
+        ],
+    );
+    let mut comment_table = Table::new(
+        "comments",
+        &[
+            ("object_id", Kind::Str),
+            ("github_number", Kind::Int),
+            ("github_id", Kind::Str),
+            ("commenter_urn", Kind::Str),
+            ("created_at", Kind::Timestamp),
+            ("body_bytes", Kind::Int),
+        ],
+    );
+    let mut change_table = Table::new(
+        "changes",
+        &[
+            ("object_id", Kind::Str),
+            ("hash", Kind::Str),
+            ("actor", Kind::Str),
+            ("seq", Kind::Int),
+            ("time", Kind::Timestamp),
+            ("deps", Kind::Int),
+            ("bytes", Kind::Int),
+        ],
+    );
+
+    for issue in issues {
+        let object_id = issue.id.to_string();
+        let number = issue.github_issue_number();
+        let doc = &issue.document;
+        let comments = layout::comments(doc);
+        let changes = automerge::Change::load_document(&issue.history).unwrap_or_default();
+        issue_table.push(json!({
+            "object_id": object_id,
+            "github_number": number,
+            "title": doc.get("title"),
+            "author_urn": doc.get("author_urn"),
+            "state": doc.get("state"),
+            "created_at": doc.get("created_at"),
+            "comments": comments.len(),
+            "labels": doc.get("labels").and_then(|l| l.as_object()).map(|l| l.len()),
+            "changes": changes.len(),
+            "history_bytes": issue.history.len(),
+        }));
+        for comment in &comments {
+            comment_table.push(json!({
+                "object_id": object_id,
+                "github_number": number,
+                "github_id": comment.github_id,
+                "commenter_urn": comment.commenter_urn,
+                "created_at": comment.created_at,
+                "body_bytes": comment.body.as_ref().map(|b| b.len()),
+            }));
+        }
+        for change in &changes {
+            change_table.push(json!({
+                "object_id": object_id,
+                "hash": change.hash.to_string(),
+                "actor": change.actor_id().to_hex_string(),
+                "seq": change.seq,
+                "time": rfc3339(change.time),
+                "deps": change.deps.len(),
+                "bytes": change.raw_bytes().len(),
+            }));
+        }
+    }
+    vec![issue_table, comment_table, change_table]
+}
+
+/// The `import_runs` and `runs` tables
+pub fn run_tables(import_runs: &[ImportRun], runs: &[Run]) -> Vec<Table> {
+    let mut import_table = Table::new(
+        "import_runs",
+        &[
+            ("repo", Kind::Str),
+            ("at", Kind::Timestamp),
+            ("changes", Kind::Int),
+            ("elapsed_secs", Kind::Float),
+            ("bytes_added", Kind::Int),
+            ("refs_added", Kind::Int),
+        ],
+    );
+    for run in import_runs {
+        import_table.push(json!({
+            "repo": run.repo,
+            "at": run.at.to_rfc3339(),
+            "changes": run.changes,
+            "elapsed_secs": run.elapsed_secs,
+            "bytes_added": run.bytes_added,
+            "refs_added": run.refs_added,
+        }));
+    }
+    let mut run_table = Table::new(
+        "runs",
+        &[
+            ("command", Kind::Str),
+            ("started_at", Kind::Timestamp),
+            ("finished_at", Kind::Timestamp),
+            ("succeeded", Kind::Bool),
+        ],
+    );
+    for run in runs {
+        run_table.push(json!({
+            "command": run.command,
+            "started_at": run.started_at.to_rfc3339(),
+            "finished_at": run.finished_at.to_rfc3339(),
+            "succeeded": run.succeeded,
+        }));
+    }
+    vec![import_table, run_table]
+}
+
+/// The `metrics` table of `monorepo`, the GitHub repository `repo` was imported from: for each
+/// object the number of changes in its history, the size of its history, the number of refs to
+/// it, and how long retrieving it took without the cache and then with the cache warmed by a
+/// retrieval beforehand
+pub fn retrieval_metrics(monorepo: &LiteMonorepo, repo: &str) -> Result<Table, Error> {
+    let mut table = Table::new(
+        "metrics",
+        &[
+            ("repo", Kind::Str),
+            ("objects", Kind::Int),
+            ("object_id", Kind::Str),
+            ("github_number", Kind::Int),
+            ("changes", Kind::Int),
+            ("history_bytes", Kind::Int),
+            ("refs", Kind::Int),
+            ("uncached_millis", Kind::Float),
+            ("cached_millis", Kind::Float),
+        ],
+    );
+    let object_ids = monorepo.list_issue_ids()?;
+    for object_id in &object_ids {
+        let history = match monorepo.issue_history(object_id)? {
+            Some(history) => history,
+            // Deleted since it was listed
+            None => continue,
+        };
+        let changes = automerge::Change::load_document(&history).unwrap_or_default();
+        let refs = monorepo.issue_refs(object_id, true)?;
+
+        let start = std::time::Instant::now();
+        let document = monorepo.retrieve_issue(object_id, false)?;
+        let uncached = start.elapsed();
+        monorepo.retrieve_issue(object_id, true)?;
+        let start = std::time::Instant::now();
+        monorepo.retrieve_issue(object_id, true)?;
+        let cached = start.elapsed();
+
+        table.push(json!({
+            "repo": repo,
+            "objects": object_ids.len(),
+            "object_id": object_id.to_string(),
+            "github_number": document
+                .as_ref()
+                .and_then(|d| d.get("github_issue_number"))
+                .and_then(|n| n.as_str())
+                .and_then(|n| n.parse::<u64>().ok()),
+            "changes": changes.len(),
+            "history_bytes": history.len(),
+            "refs": refs.len(),
+            "uncached_millis": uncached.as_secs_f64() * 1000.0,
+            "cached_millis": cached.as_secs_f64() * 1000.0,
+        }));
+    }
+    Ok(table)
+}
+
+/// Write the document of every object of `monorepo` to `out` as newline delimited JSON, each
+/// line an object of the form `{"object_id": ..., "document": ...}`. Returns the number of
+/// documents written.
+pub fn stream_documents<W: Write>(monorepo: &LiteMonorepo, mut out: W) -> Result<usize, Error> {
+    let mut written = 0;
+    for object_id in monorepo.list_issue_ids()? {
+        let document = match monorepo.retrieve_issue(&object_id, true)? {
+            Some(document) => document,
+            // Deleted since it was listed
+            None => continue,
+        };
+        serde_json::to_writer(
+            &mut out,
+            &json!({
+                "object_id": object_id.to_string(),
+                "document": document,
+            }),
+        )?;
+        writeln!(out)?;
+        written += 1;
+    }
+    out.flush()?;
+    Ok(written)
+}
+
+/// Write the documents of `issues`, already materialized, to `out` in the format of
+/// [`stream_documents`]. Returns the number of documents written.
+pub fn write_documents<W: Write>(issues: &[MaterializedIssue], mut out: W) -> Result<usize, Error> {
+    for issue in issues {
+        serde_json::to_writer(
+            &mut out,
+            &json!({
+                "object_id": issue.id.to_string(),
+                "document": issue.document,
+            }),
+        )?;
+        writeln!(out)?;
+    }
+    out.flush()?;
+    Ok(issues.len())
+}
+
+/// The `peer_assignments` table of `assignments`, each GitHub user and the peer they were assigned
+pub fn peer_table(assignments: &[(GithubUserId, link_crypto::PeerId)]) -> Table {
+    let mut table = Table::new(
+        "peer_assignments",
+        &[("github_user", Kind::Str), ("peer", Kind::Str)],
+    );
+    for (user, peer) in assignments {
+        table.push(json!({
+            "github_user": user.0,
+            "peer": peer.to_string(),
+        }));
+    }
+    table
+}
+
+/// The `object_metrics` table, the timings the audit log `entries` record for each of `issues`
+/// and their sizes
+pub fn metrics_table(issues: &[MaterializedIssue], entries: &[audit::Entry]) -> Table {
+    #[derive(Default)]
+    struct Timings {
+        creates: u64,
+        updates: u64,
+        failures: u64,
+        total_millis: u64,
+        max_millis: u64,
+        last_at: Option<DateTime<Utc>>,
+    }
+    let mut timings: HashMap<&str, Timings> = HashMap::new();
+    for entry in entries {
+        let object_id = match &entry.object_id {
+            Some(id) => id.as_str(),
+            None => continue,
+        };
+        let t = timings.entry(object_id).or_default();
+        match entry.operation {
+            Operation::Create => t.creates += 1,
+            Operation::Update => t.updates += 1,
+        }
+        if entry.error.is_some() {
+            t.failures += 1;
+        }
+        t.total_millis += entry.duration_millis;
+        t.max_millis = t.max_millis.max(entry.duration_millis);
+        t.last_at = t.last_at.max(Some(entry.timestamp));
+    }
+
+    let mut table = Table::new(
+        "object_metrics",
+        &[
+            ("object_id", Kind::Str),
+            ("github_number", Kind::Int),
+            ("creates", Kind::Int),
+            ("updates", Kind::Int),
+            ("failures", Kind::Int),
+            ("total_millis", Kind::Int),
+            ("max_millis", Kind::Int),
+            ("mean_millis", Kind::Float),
+            ("last_written_at", Kind::Timestamp),
+            ("changes", Kind::Int),
+            ("history_bytes", Kind::Int),
+            ("document_bytes", Kind::Int),
+        ],
+    );
+    for issue in issues {
+        let object_id = issue.id.to_string();
+        let t = timings.remove(object_id.as_str()).unwrap_or_default();
+        let operations = t.creates + t.updates;
+        let changes = automerge::Change::load_document(&issue.history).unwrap_or_default();
+        table.push(json!({
+            "object_id": object_id,
+            "github_number": issue.github_issue_number(),
+            "creates": t.creates,
+            "updates": t.updates,
+            "failures": t.failures,
+            "total_millis": t.total_millis,
+            "max_millis": t.max_millis,
+            "mean_millis": if operations > 0 {
+                Some(t.total_millis as f64 / operations as f64)
+            } else {
+                None
+            },
+            "last_written_at": t.last_at.map(|t| t.to_rfc3339()),
+            "changes": changes.len(),
+            "history_bytes": issue.history.len(),
+            "document_bytes": issue.document.to_string().len(),
+        }));
+    }
+    table
+}
+
+/// Write `tables` into the SQLite database at `path`, replacing any tables of the same names, in
+/// a single transaction
+pub fn write_sqlite(path: &Path, tables: &[Table]) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        crate::fs::create_dir_all(parent)?;
+    }
+    let mut conn = rusqlite::Connection::open(path)?;
+    let tx = conn.transaction()?;
+    for table in tables {
+        table.write_sqlite(&tx)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
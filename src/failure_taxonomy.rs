@@ -0,0 +1,129 @@
+//! The kinds of failure recorded in the failure log. Each failure is put in one of a fixed set of
+//! categories when it is recorded, so that how often each kind of failure happens can be followed
+//! from one version of cob to the next without reading through error messages. The names of the
+//! categories are part of the log's format and mustn't change.
+//!
+//! Errors reach the log as messages, having been wrapped by cob, git2 and reqwest along the way,
+//! so failures are classified by what their messages mention. Failures recorded before
+//! categories existed are classified the same way when they are read.
+use std::collections::BTreeMap;
+
+use crate::retry::Failure;
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, serde::Serialize, serde::Deserialize,
+)]
+#[serde(rename_all = "kebab-case")]
+pub enum Category {
+    /// GitHub's primary or secondary rate limits
+    RateLimit,
+    /// Connections failing or timing out
+    Network,
+    /// A change or document which doesn't match the schema of its type
+    SchemaRejection,
+    /// Signatures of commits or signed refs which don't verify
+    Signature,
+    /// Any other error from git, such as a held lock or a missing object
+    GitError,
+    /// A thread panicked
+    Panic,
+    Other,
+}
+
+impl Category {
+    pub fn all() -> [Category; 7] {
+        [
+            Category::RateLimit,
+            Category::Network,
+            Category::SchemaRejection,
+            Category::Signature,
+            Category::GitError,
+            Category::Panic,
+            Category::Other,
+        ]
+    }
+
+    /// The category of a failure with the message `error`
+    pub fn classify(error: &str) -> Category {
+        let error = error.to_lowercase();
+        let mentions = |words: &[&str]| words.iter().any(|w| error.contains(w));
+        if mentions(&["panic"]) {
+            Category::Panic
+        } else if mentions(&["rate limit", "ratelimit", "abuse detection"]) {
+            Category::RateLimit
+        } else if mentions(&["signature", "signed refs", "signed_refs"]) {
+            Category::Signature
+        } else if mentions(&["schema"]) {
+            Category::SchemaRejection
+        } else if mentions(&[
+            "connection",
+            "timed out",
+            "timeout",
+            "dns",
+            "error sending request",
+            "http",
+            "network",
+            "broken pipe",
+        ]) {
+            Category::Network
+        } else if mentions(&["git", ".lock", "reference", "odb", "object not found"]) {
+            Category::GitError
+        } else {
+            Category::Other
+        }
+    }
+}
+
+impl std::fmt::Display for Category {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Category::RateLimit => write!(f, "rate-limit"),
+            Category::Network => write!(f, "network"),
+            Category::SchemaRejection => write!(f, "schema-rejection"),
+            Category::Signature => write!(f, "signature"),
+            Category::GitError => write!(f, "git-error"),
+            Category::Panic => write!(f, "panic"),
+            Category::Other => write!(f, "other"),
+        }
+    }
+}
+
+/// The number of failures of each category, for each version of the cob API they were recorded
+/// with, see [`crate::cob_api::VERSION`]
+pub struct Trends(BTreeMap<String, BTreeMap<Category, usize>>);
+
+impl Trends {
+    pub fn of(failures: &[Failure]) -> Trends {
+        let mut trends: BTreeMap<String, BTreeMap<Category, usize>> = BTreeMap::new();
+        for failure in failures {
+            let version = failure
+                .cob_api
+                .clone()
+                .unwrap_or_else(|| "unknown".to_string());
+            *trends
+                .entry(version)
+                .or_default()
+                .entry(failure.category())
+                .or_default() += 1;
+        }
+        Trends(trends)
+    }
+}
+
+impl std::fmt::Display for Trends {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:<10}", "cob api")?;
+        for category in Category::all().iter() {
+            write!(f, " {:>16}", category.to_string())?;
+        }
+        writeln!(f)?;
+        for (version, counts) in &self.0 {
+            write!(f, "{:<10}", version)?;
+            for category in Category::all().iter() {
+                write!(f, " {:>16}", counts.get(category).copied().unwrap_or(0))?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
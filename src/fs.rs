@@ -0,0 +1,340 @@
+//! Filesystem helpers which paper over the differences between the platforms we might build a
+//! corpus on. Everything in the data directory is named after things we don't control (github
+//! owners and repository names, peer IDs, object IDs) so names are sanitized to be valid on
+//! Windows, and paths are converted to the extended length form there so that deeply nested
+//! monorepo files don't run into `MAX_PATH`. The default filesystems of Windows and macOS are
+//! case-insensitive, so names which differ only in case are made to differ in more than that,
+//! as the Go module cache does. Directory listings skip the metadata files that Finder and
+//! Explorer like to leave lying around.
+//!
+//! Everything outside this module reads and writes through these helpers rather than `std::fs`,
+//! so that every path gets the same treatment. Files which are written whole go through
+//! [`write_atomic`], and logs through [`append_line`], so that a run which is interrupted doesn't
+//! leave half a file behind.
+
+use std::path::{Path, PathBuf};
+
+/// Names which Windows reserves for devices, regardless of extension
+const RESERVED_NAMES: &[&str] = &[
+    "CON", "PRN", "AUX", "NUL", "COM1", "COM2", "COM3", "COM4", "COM5", "COM6", "COM7", "COM8",
+    "COM9", "LPT1", "LPT2", "LPT3", "LPT4", "LPT5", "LPT6", "LPT7", "LPT8", "LPT9",
+];
+
+/// Files created by the OS which should be ignored when listing a directory
+const OS_METADATA_FILES: &[&str] = &[".DS_Store", "Thumbs.db", "desktop.ini"];
+
+/// Turn `name` into a file name which is valid on every platform we support. Characters which are
+/// not allowed in Windows file names are replaced with `_`, as are trailing dots and spaces, and
+/// reserved device names have a `_` appended. Upper case letters are written as `!` followed by
+/// the lower case letter, so that names which differ only in case don't collide on a
+/// case-insensitive filesystem, and `!` itself is replaced with `_`.
+pub fn file_name(name: &str) -> String {
+    let mut sanitized = String::with_capacity(name.len());
+    for c in name.chars() {
+        match c {
+            '<' | '>' | ':' | '"' | '/' | '\\' | '|' | '?' | '*' | '!' => sanitized.push('_'),
+            c if c.is_control() => sanitized.push('_'),
+            c if c.is_uppercase() => {
+                sanitized.push('!');
+                sanitized.extend(c.to_lowercase());
+            }
+            c => sanitized.push(c),
+        }
+    }
+    if sanitized.ends_with('.') || sanitized.ends_with(' ') {
+        sanitized.pop();
+        sanitized.push('_');
+    }
+    let stem = sanitized.split('.').next().unwrap_or("");
+    if RESERVED_NAMES.iter().any(|r| r.eq_ignore_ascii_case(stem)) {
+        sanitized.insert(stem.len(), '_');
+    }
+    if sanitized.is_empty() {
+        sanitized.push('_');
+    }
+    sanitized
+}
+
+/// Convert `path` into a form which can exceed 260 characters on Windows. On other platforms the
+/// path is returned unchanged.
+#[cfg(windows)]
+pub fn long_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    let path = path.as_ref();
+    if path.as_os_str().len() < 240 || path.starts_with(r"\\?\") {
+        return path.to_path_buf();
+    }
+    match std::fs::canonicalize(path) {
+        // canonicalize already returns the extended length form
+        Ok(p) => p,
+        Err(_) => {
+            // The path doesn't exist yet, canonicalize the parent and re-attach the file name
+            match (path.parent(), path.file_name()) {
+                (Some(parent), Some(name)) => match std::fs::canonicalize(parent) {
+                    Ok(p) => p.join(name),
+                    Err(_) => path.to_path_buf(),
+                },
+                _ => path.to_path_buf(),
+            }
+        }
+    }
+}
+
+#[cfg(not(windows))]
+pub fn long_path<P: AsRef<Path>>(path: P) -> PathBuf {
+    path.as_ref().to_path_buf()
+}
+
+pub fn exists<P: AsRef<Path>>(path: P) -> Result<bool, std::io::Error> {
+    std::fs::try_exists(long_path(path))
+}
+
+pub fn create_dir_all<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
+    std::fs::create_dir_all(long_path(path))
+}
+
+pub fn read_dir<P: AsRef<Path>>(path: P) -> Result<std::fs::ReadDir, std::io::Error> {
+    std::fs::read_dir(long_path(path))
+}
+
+pub fn remove_file<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
+    std::fs::remove_file(long_path(path))
+}
+
+pub fn remove_dir_all<P: AsRef<Path>>(path: P) -> Result<(), std::io::Error> {
+    std::fs::remove_dir_all(long_path(path))
+}
+
+pub fn write<P: AsRef<Path>, C: AsRef<[u8]>>(path: P, contents: C) -> Result<(), std::io::Error> {
+    std::fs::write(long_path(path), contents)
+}
+
+/// Write `contents` to `path` via a temporary file alongside it, so that `path` is left as it was
+/// rather than half written if writing fails part way through, say because the disk is full
+pub fn write_atomic<P: AsRef<Path>, C: AsRef<[u8]>>(
+    path: P,
+    contents: C,
+) -> Result<(), std::io::Error> {
+    let path = long_path(path);
+    let mut tmp = path.clone().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    if let Err(e) = std::fs::write(&tmp, contents) {
+        std::fs::remove_file(&tmp).ok();
+        return Err(e);
+    }
+    std::fs::rename(tmp, path)
+}
+
+/// Append `line` to the file at `path`, creating it if necessary. If the write fails part way
+/// through the file is truncated to its previous length, so that a later append doesn't run on
+/// from half a line.
+pub fn append_line<P: AsRef<Path>>(path: P, line: &[u8]) -> Result<(), std::io::Error> {
+    use std::io::Write;
+
+    let mut file = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(long_path(path))?;
+    let len = file.metadata()?.len();
+    if let Err(e) = file.write_all(line) {
+        file.set_len(len).ok();
+        return Err(e);
+    }
+    Ok(())
+}
+
+pub fn copy<P: AsRef<Path>, Q: AsRef<Path>>(from: P, to: Q) -> Result<u64, std::io::Error> {
+    std::fs::copy(long_path(from), long_path(to))
+}
+
+pub fn metadata<P: AsRef<Path>>(path: P) -> Result<std::fs::Metadata, std::io::Error> {
+    std::fs::metadata(long_path(path))
+}
+
+pub fn canonicalize<P: AsRef<Path>>(path: P) -> Result<PathBuf, std::io::Error> {
+    std::fs::canonicalize(long_path(path))
+}
+
+/// Open `path` to read it as a stream, for files too large to read whole
+pub fn open<P: AsRef<Path>>(path: P) -> Result<std::fs::File, std::io::Error> {
+    std::fs::File::open(long_path(path))
+}
+
+/// Create `path` to write it as a stream, for output which isn't kept in the data directory and
+/// so doesn't need to be written atomically
+pub fn create<P: AsRef<Path>>(path: P) -> Result<std::fs::File, std::io::Error> {
+    std::fs::File::create(long_path(path))
+}
+
+pub fn read<P: AsRef<Path>>(path: P) -> Result<Vec<u8>, std::io::Error> {
+    std::fs::read(long_path(path))
+}
+
+pub fn read_to_string<P: AsRef<Path>>(path: P) -> Result<String, std::io::Error> {
+    std::fs::read_to_string(long_path(path))
+}
+
+/// The paths of the files in `dir`, skipping hidden files and OS metadata files
+pub fn files<P: AsRef<Path>>(dir: P) -> Result<Vec<PathBuf>, std::io::Error> {
+    let mut files = Vec::new();
+    for entry in std::fs::read_dir(long_path(dir))? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        if name.starts_with('.') || OS_METADATA_FILES.iter().any(|m| *m == name) {
+            continue;
+        }
+        if entry.file_type()?.is_file() {
+            files.push(entry.path());
+        }
+    }
+    Ok(files)
+}
+
+/// The total size of the files beneath `dir`
+pub fn dir_size<P: AsRef<Path>>(dir: P) -> Result<u64, std::io::Error> {
+    let mut size = 0;
+    for entry in std::fs::read_dir(long_path(dir))? {
+        let entry = entry?;
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            size += dir_size(entry.path())?;
+        } else if file_type.is_file() {
+            size += entry.metadata()?.len();
+        }
+    }
+    Ok(size)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A directory of its own for each test, removed when it's dropped
+    struct TempDir(PathBuf);
+
+    impl TempDir {
+        fn new(test: &str) -> TempDir {
+            let dir = std::env::temp_dir().join(format!(
+                "collab-stress-test-fs-{}-{}",
+                test,
+                std::process::id()
+            ));
+            std::fs::remove_dir_all(&dir).ok();
+            std::fs::create_dir_all(&dir).unwrap();
+            TempDir(dir)
+        }
+    }
+
+    impl Drop for TempDir {
+        fn drop(&mut self) {
+            std::fs::remove_dir_all(&self.0).ok();
+        }
+    }
+
+    #[test]
+    fn file_name_replaces_invalid_characters() {
+        assert_eq!(file_name("a<b>c:d\"e/f\\g|h?i*j"), "a_b_c_d_e_f_g_h_i_j");
+        assert_eq!(file_name("tab\there"), "tab_here");
+        assert_eq!(file_name("wow!"), "wow_");
+    }
+
+    #[test]
+    fn file_name_replaces_trailing_dots_and_spaces() {
+        assert_eq!(file_name("name."), "name_");
+        assert_eq!(file_name("name "), "name_");
+        assert_eq!(file_name(""), "_");
+    }
+
+    #[test]
+    fn file_name_escapes_reserved_names() {
+        assert_eq!(file_name("con"), "con_");
+        assert_eq!(file_name("nul.json"), "nul_.json");
+        assert_eq!(file_name("lpt1"), "lpt1_");
+        assert_eq!(file_name("console"), "console");
+    }
+
+    #[test]
+    fn file_name_leaves_ids_alone() {
+        let peer = "hyb1jukxajb5k1nf8mna4jpz1rdqsazybr3pm6tt5qacr66r64m9un";
+        assert_eq!(file_name(peer), peer);
+        assert_eq!(file_name("rust-lang"), "rust-lang");
+        assert_eq!(file_name("automerge-rs_1.0"), "automerge-rs_1.0");
+    }
+
+    #[test]
+    fn names_differing_in_case_dont_collide() {
+        let names = ["react", "React", "REACT", "rEact"];
+        let folded: std::collections::HashSet<String> =
+            names.iter().map(|n| file_name(n).to_lowercase()).collect();
+        assert_eq!(folded.len(), names.len());
+        assert_eq!(file_name("BurntSushi"), "!burnt!sushi");
+    }
+
+    #[test]
+    fn names_differing_in_case_are_separate_files() {
+        let dir = TempDir::new("case");
+        write(dir.0.join(file_name("Readme")), "upper").unwrap();
+        write(dir.0.join(file_name("readme")), "lower").unwrap();
+        assert_eq!(
+            read_to_string(dir.0.join(file_name("Readme"))).unwrap(),
+            "upper"
+        );
+        assert_eq!(
+            read_to_string(dir.0.join(file_name("readme"))).unwrap(),
+            "lower"
+        );
+        assert_eq!(files(&dir.0).unwrap().len(), 2);
+    }
+
+    #[cfg(not(windows))]
+    #[test]
+    fn long_path_is_unchanged_off_windows() {
+        let path = Path::new("/data").join("x".repeat(300));
+        assert_eq!(long_path(&path), path);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn long_path_uses_the_extended_form_for_long_paths() {
+        let dir = TempDir::new("long");
+        let path = dir.0.join("x".repeat(250));
+        let long = long_path(&path);
+        assert!(long.starts_with(r"\\?\"));
+        write(&path, "contents").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "contents");
+        let short = dir.0.join("short");
+        assert_eq!(long_path(&short), short);
+    }
+
+    #[test]
+    fn write_atomic_replaces_the_file() {
+        let dir = TempDir::new("atomic");
+        let path = dir.0.join("cursor");
+        write_atomic(&path, "first").unwrap();
+        write_atomic(&path, "second").unwrap();
+        assert_eq!(read_to_string(&path).unwrap(), "second");
+        assert_eq!(files(&dir.0).unwrap(), vec![path]);
+    }
+
+    #[test]
+    fn write_atomic_leaves_the_file_if_it_fails() {
+        let dir = TempDir::new("atomic-fail");
+        let path = dir.0.join("cursor");
+        write_atomic(&path, "first").unwrap();
+        // The temporary file can't be created in place of a directory
+        create_dir_all(dir.0.join("cursor.tmp")).unwrap();
+        assert!(write_atomic(&path, "second").is_err());
+        assert_eq!(read_to_string(&path).unwrap(), "first");
+    }
+
+    #[test]
+    fn files_skips_hidden_and_metadata_files() {
+        let dir = TempDir::new("files");
+        write(dir.0.join(".DS_Store"), "").unwrap();
+        write(dir.0.join("Thumbs.db"), "").unwrap();
+        write(dir.0.join("1.json"), "{}").unwrap();
+        create_dir_all(dir.0.join("sub")).unwrap();
+        assert_eq!(files(&dir.0).unwrap(), vec![dir.0.join("1.json")]);
+    }
+}
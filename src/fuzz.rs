@@ -0,0 +1,274 @@
+//! Fuzzing the loading of automerge changes with mutated copies of real histories. Each case takes
+//! the history of an imported issue, applies a random byte-level mutation to the payload of one
+//! of its changes, and loads and materializes the result the way `cob` does. Any result other
+//! than a clean error or a document is a crash.
+//!
+//! The header of every change carries a checksum of its payload, which would reject almost every
+//! mutation before the payload is decoded, so the checksum is recomputed after mutating.
+//!
+//! Each case is written to `current.automerge` in the output directory before it is run. A panic
+//! is caught and the case saved as a reproducer, but if the process is killed (e.g. for running
+//! out of memory) `current.automerge` is left behind as the reproducer instead.
+use std::path::{Path, PathBuf};
+
+use rand::{Rng, SeedableRng};
+use sha2::{Digest, Sha256};
+
+/// The first four bytes of every chunk in the automerge binary format
+const MAGIC_BYTES: [u8; 4] = [0x85, 0x6f, 0x4a, 0x83];
+
+#[derive(Debug, Clone, Copy)]
+pub enum Mutation {
+    FlipBit { offset: usize, bit: u8 },
+    SetByte { offset: usize, value: u8 },
+    InsertByte { offset: usize, value: u8 },
+    DeleteByte { offset: usize },
+    Truncate { len: usize },
+}
+
+impl std::fmt::Display for Mutation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Mutation::FlipBit { offset, bit } => write!(f, "flip bit {} of byte {}", bit, offset),
+            Mutation::SetByte { offset, value } => write!(f, "set byte {} to {}", offset, value),
+            Mutation::InsertByte { offset, value } => {
+                write!(f, "insert {} at byte {}", value, offset)
+            }
+            Mutation::DeleteByte { offset } => write!(f, "delete byte {}", offset),
+            Mutation::Truncate { len } => write!(f, "truncate to {} bytes", len),
+        }
+    }
+}
+
+impl Mutation {
+    fn random<R: Rng>(rng: &mut R, len: usize) -> Mutation {
+        let offset = rng.gen_range(0..len.max(1));
+        match rng.gen_range(0..5) {
+            0 => Mutation::FlipBit {
+                offset,
+                bit: rng.gen_range(0..8),
+            },
+            1 => Mutation::SetByte {
+                offset,
+                value: rng.gen(),
+            },
+            2 => Mutation::InsertByte {
+                offset,
+                value: rng.gen(),
+            },
+            3 => Mutation::DeleteByte { offset },
+            _ => Mutation::Truncate { len: offset },
+        }
+    }
+
+    fn apply(&self, payload: &mut Vec<u8>) {
+        match *self {
+            Mutation::FlipBit { offset, bit } if offset < payload.len() => {
+                payload[offset] ^= 1 << bit
+            }
+            Mutation::SetByte { offset, value } if offset < payload.len() => {
+                payload[offset] = value
+            }
+            Mutation::InsertByte { offset, value } => {
+                payload.insert(offset.min(payload.len()), value)
+            }
+            Mutation::DeleteByte { offset } if offset < payload.len() => {
+                payload.remove(offset);
+            }
+            Mutation::Truncate { len } => payload.truncate(len),
+            _ => {}
+        }
+    }
+}
+
+/// A change split into its chunk type and payload
+struct Chunk {
+    chunk_type: u8,
+    payload: Vec<u8>,
+}
+
+fn read_uleb(bytes: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0usize;
+    for (i, byte) in bytes.iter().enumerate().take(10) {
+        value |= ((byte & 0x7f) as usize) << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+fn write_uleb(mut value: usize, out: &mut Vec<u8>) {
+    loop {
+        let byte = (value & 0x7f) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+impl Chunk {
+    fn parse(bytes: &[u8]) -> Option<Chunk> {
+        if bytes.len() < 9 || bytes[..4] != MAGIC_BYTES {
+            return None;
+        }
+        let (len, len_bytes) = read_uleb(&bytes[9..])?;
+        let payload = bytes.get(9 + len_bytes..9 + len_bytes + len)?;
+        Some(Chunk {
+            chunk_type: bytes[8],
+            payload: payload.to_vec(),
+        })
+    }
+
+    /// Encode the chunk with a checksum which matches its (possibly mutated) payload
+    fn encode(&self) -> Vec<u8> {
+        let mut body = vec![self.chunk_type];
+        write_uleb(self.payload.len(), &mut body);
+        body.extend_from_slice(&self.payload);
+        let checksum = Sha256::digest(&body);
+        let mut bytes = MAGIC_BYTES.to_vec();
+        bytes.extend_from_slice(&checksum[..4]);
+        bytes.extend(body);
+        bytes
+    }
+}
+
+#[derive(Debug)]
+enum Outcome {
+    Rejected,
+    Accepted,
+    Panicked(String),
+}
+
+/// Load and materialize `history` as `cob` does, but without unwrapping
+fn evaluate(history: Vec<u8>) -> Outcome {
+    let result = std::panic::catch_unwind(|| {
+        let backend = match automerge::Backend::load(history) {
+            Ok(b) => b,
+            Err(_) => return false,
+        };
+        let patch = match backend.get_patch() {
+            Ok(p) => p,
+            Err(_) => return false,
+        };
+        let mut frontend = automerge::Frontend::new();
+        if frontend.apply_patch(patch).is_err() {
+            return false;
+        }
+        let _ = frontend.state().to_json();
+        true
+    });
+    match result {
+        Ok(true) => Outcome::Accepted,
+        Ok(false) => Outcome::Rejected,
+        Err(panic) => Outcome::Panicked(
+            panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "unknown panic".to_string()),
+        ),
+    }
+}
+
+pub struct Crash {
+    pub object_id: cob::ObjectId,
+    pub mutation: Mutation,
+    pub message: String,
+    /// The mutated history
+    pub path: PathBuf,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub cases: usize,
+    pub rejected: usize,
+    pub accepted: usize,
+    pub crashes: Vec<Crash>,
+}
+
+/// Run `iterations` mutated copies of `histories` and save crashing cases into `out_dir`
+pub fn fuzz(
+    histories: &[(cob::ObjectId, Vec<u8>)],
+    iterations: usize,
+    seed: u64,
+    out_dir: &Path,
+) -> Result<Report, std::io::Error> {
+    crate::fs::create_dir_all(out_dir)?;
+    let mut rng = rand::rngs::StdRng::seed_from_u64(seed);
+    let histories: Vec<(cob::ObjectId, Vec<Vec<u8>>)> = histories
+        .iter()
+        .filter_map(|(id, history)| {
+            let changes: Vec<Vec<u8>> = automerge::Change::load_document(history)
+                .ok()?
+                .iter()
+                .map(|c| c.raw_bytes().to_vec())
+                .collect();
+            if changes.is_empty() {
+                None
+            } else {
+                Some((*id, changes))
+            }
+        })
+        .collect();
+    let mut report = Report::default();
+    if histories.is_empty() {
+        return Ok(report);
+    }
+
+    // The default hook would print every caught panic
+    let hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(|_| {}));
+    let current = out_dir.join("current.automerge");
+    for _ in 0..iterations {
+        let (object_id, changes) = &histories[rng.gen_range(0..histories.len())];
+        let target = rng.gen_range(0..changes.len());
+        let mut chunk = match Chunk::parse(&changes[target]) {
+            Some(c) => c,
+            None => continue,
+        };
+        let mutation = Mutation::random(&mut rng, chunk.payload.len());
+        mutation.apply(&mut chunk.payload);
+        let mut history = Vec::new();
+        for (i, change) in changes.iter().enumerate() {
+            if i == target {
+                history.extend(chunk.encode());
+            } else {
+                history.extend_from_slice(change);
+            }
+        }
+
+        crate::fs::write_atomic(&current, &history)?;
+        report.cases += 1;
+        match evaluate(history.clone()) {
+            Outcome::Rejected => report.rejected += 1,
+            Outcome::Accepted => report.accepted += 1,
+            Outcome::Panicked(message) => {
+                let name = format!("crash-{}-{}", object_id, report.crashes.len());
+                let path = out_dir.join(format!("{}.automerge", name));
+                crate::fs::write_atomic(&path, &history)?;
+                crate::fs::write_atomic(
+                    out_dir.join(format!("{}.txt", name)),
+                    format!(
+                        "object: {}\nchange: {}\nmutation: {}\npanic: {}\n",
+                        object_id, target, mutation, message
+                    ),
+                )?;
+                report.crashes.push(Crash {
+                    object_id: *object_id,
+                    mutation,
+                    message,
+                    path,
+                });
+            }
+        }
+    }
+    std::panic::set_hook(hook);
+    if crate::fs::exists(&current)? {
+        crate::fs::remove_file(&current)?;
+    }
+    Ok(report)
+}
@@ -5,12 +5,16 @@ use futures::{StreamExt, TryStreamExt};
 use std::pin::Pin;
 
 use crate::{
-    downloaded_issue::{DownloadedComment, DownloadedIssue},
+    downloaded_issue::{DownloadedComment, DownloadedEvent, DownloadedIssue, EventKind},
     GithubUserId, RepoName,
 };
 
 static ISSUES_QUERY: &str = include_str!("./get_issues.graphql");
+static UPDATED_ISSUES_QUERY: &str = include_str!("./get_updated_issues.graphql");
 static ISSUE_COMMENTS_QUERY: &str = include_str!("./get_issue_comments.graphql");
+static ISSUE_QUERY: &str = include_str!("./get_issue.graphql");
+static ISSUE_TIMELINE_QUERY: &str = include_str!("./get_issue_timeline.graphql");
+static USER_QUERY: &str = include_str!("./get_user.graphql");
 
 #[derive(Clone, Debug, Deserialize)]
 struct GithubUserLoginWrapper {
@@ -42,7 +46,11 @@ struct GraphqlIssue {
     body: Option<String>,
     state: String,
     created_at: chrono::DateTime<chrono::Utc>,
+    /// Missing from responses cached before it was deserialized
+    updated_at: Option<chrono::DateTime<chrono::Utc>>,
     comments: GraphqlComments,
+    /// Missing from responses cached before the timeline was requested
+    timeline_items: Option<GraphqlTimeline>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -62,6 +70,30 @@ struct GraphqlComment {
     updated_at: Option<chrono::DateTime<chrono::Utc>>,
 }
 
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlTimeline {
+    nodes: Vec<GraphqlTimelineItem>,
+    page_info: PageInfo,
+}
+
+/// A labeled, unlabeled, closed or reopened event
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlTimelineItem {
+    #[serde(rename = "__typename")]
+    typename: String,
+    id: String,
+    actor: Option<GithubUserLoginWrapper>,
+    created_at: chrono::DateTime<chrono::Utc>,
+    label: Option<GraphqlLabel>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlLabel {
+    name: String,
+}
+
 #[derive(Debug, Deserialize)]
 struct GraphqlIssuesRepositoryWrapper {
     repository: GraphqlIssuesWrapper,
@@ -82,12 +114,38 @@ struct GraphqlCommentsIssueWrapper {
     issue: GraphqlIssueComments,
 }
 
+#[derive(Debug, Deserialize)]
+struct GraphqlIssueRepositoryWrapper {
+    repository: GraphqlIssueWrapper,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlIssueWrapper {
+    issue: Option<GraphqlIssue>,
+}
+
 #[derive(Debug, Deserialize)]
 #[serde(rename_all = "camelCase")]
 struct GraphqlIssueComments {
     comments: GraphqlComments,
 }
 
+#[derive(Debug, Deserialize)]
+struct GraphqlTimelineRepositoryWrapper {
+    repository: GraphqlTimelineIssueWrapper,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlTimelineIssueWrapper {
+    issue: GraphqlIssueTimeline,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GraphqlIssueTimeline {
+    timeline_items: GraphqlTimeline,
+}
+
 #[derive(Debug, Deserialize)]
 struct DataWrapper<T> {
     data: T,
@@ -98,9 +156,17 @@ pub enum Error {
     #[error(transparent)]
     Serde(#[from] serde_json::Error),
     #[error(transparent)]
-    Octo(#[from] octocrab::Error),
+    Http(#[from] reqwest::Error),
+    #[error("GitHub returned {status}: {body}")]
+    Status {
+        status: reqwest::StatusCode,
+        body: String,
+    },
     #[error(transparent)]
     Io(#[from] std::io::Error),
+    /// GitHub answers rate limits and bad queries with a successful status and a list of errors
+    #[error("GitHub returned errors: {0}")]
+    Response(String),
 }
 
 type IssueStreamResult<'a> = Result<
@@ -108,12 +174,43 @@ type IssueStreamResult<'a> = Result<
     Error,
 >;
 
+/// Which issues to page through
+enum Listing {
+    /// Every issue, in the order they were created
+    All,
+    /// Issues updated at or after a time, or every issue if it's `None`, least recently updated
+    /// first
+    UpdatedSince(Option<chrono::DateTime<chrono::Utc>>),
+}
+
 struct IssuesStreamState {
-    crab: octocrab::Octocrab,
+    client: Client,
     repo: RepoName,
+    listing: Listing,
     cursor_cache: Box<dyn CursorCache + Send>,
 }
 
+impl IssuesStreamState {
+    fn query(&self) -> &'static str {
+        match self.listing {
+            Listing::All => ISSUES_QUERY,
+            Listing::UpdatedSince(_) => UPDATED_ISSUES_QUERY,
+        }
+    }
+
+    fn variables(&self, after: Option<String>) -> serde_json::Value {
+        let mut vars = serde_json::json!({
+            "owner": self.repo.owner,
+            "name": self.repo.name,
+            "after": after
+        });
+        if let Listing::UpdatedSince(since) = &self.listing {
+            vars["since"] = serde_json::json!(since.map(|s| s.to_rfc3339()));
+        }
+        vars
+    }
+}
+
 enum PaginationState {
     Starting(IssuesStreamState),
     ProcessingPage(IssuesStreamState, Box<GraphqlIssues>),
@@ -125,34 +222,138 @@ enum PaginationState {
     Done,
 }
 
-pub(crate) trait CursorCache {
+/// An HTTP client for the GraphQL endpoint of GitHub, with the URL of the endpoint and the token
+/// to authorize with
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    graphql_url: String,
+    authorization: String,
+    response_cache: Option<ResponseCache>,
+}
+
+/// Raw GraphQL responses saved on disk, keyed by the SHA-256 of the query and its variables
+#[derive(Clone)]
+struct ResponseCache {
+    dir: std::path::PathBuf,
+    /// Make every request anyway, replacing the saved responses
+    refresh: bool,
+}
+
+impl Client {
+    /// A client for `api_url`, or for github.com if `api_url` is `None`. GitHub Enterprise Server
+    /// serves its REST API from `https://<host>/api/v3` and GraphQL from
+    /// `https://<host>/api/graphql`, and older versions only accept tokens in the `token` auth
+    /// scheme, which github.com accepts too. Requests go through `proxy` if it is given, and
+    /// otherwise through the proxy in the environment, if any. The proxy is set on this client
+    /// alone, so that it doesn't change how the rest of the process connects.
+    pub fn new(
+        token: &str,
+        api_url: Option<&str>,
+        proxy: Option<&str>,
+    ) -> Result<Client, reqwest::Error> {
+        let graphql_url = match api_url {
+            None => "https://api.github.com/graphql".to_string(),
+            Some(api_url) => {
+                let api_url = api_url.trim_end_matches('/');
+                match api_url.strip_suffix("/api/v3") {
+                    Some(host) => format!("{}/api/graphql", host),
+                    None => format!("{}/graphql", api_url),
+                }
+            }
+        };
+        // GitHub rejects requests without a user agent
+        let mut builder = reqwest::Client::builder().user_agent(concat!(
+            env!("CARGO_PKG_NAME"),
+            "/",
+            env!("CARGO_PKG_VERSION")
+        ));
+        if let Some(proxy) = proxy {
+            builder = builder.proxy(reqwest::Proxy::all(proxy)?);
+        }
+        Ok(Client {
+            http: builder.build()?,
+            graphql_url,
+            authorization: format!("token {}", token),
+            response_cache: None,
+        })
+    }
+
+    /// Save every response in `dir` and answer repeated requests from there instead of the API,
+    /// unless `refresh` is set
+    pub fn with_response_cache(
+        mut self,
+        dir: std::path::PathBuf,
+        refresh: bool,
+    ) -> Result<Client, std::io::Error> {
+        crate::fs::create_dir_all(&dir)?;
+        self.response_cache = Some(ResponseCache { dir, refresh });
+        Ok(self)
+    }
+}
+
+pub trait CursorCache {
     fn save_cursor(&self, cursor: String) -> Result<(), std::io::Error>;
     fn load_cursor(&self) -> Result<Option<String>, std::io::Error>;
 }
 
-pub(crate) fn issues(
-    crab: octocrab::Octocrab,
+/// Keeps no cursor, so that listing issues never disturbs the cursor of `issues`
+struct NoCursor;
+
+impl CursorCache for NoCursor {
+    fn save_cursor(&self, _cursor: String) -> Result<(), std::io::Error> {
+        Ok(())
+    }
+
+    fn load_cursor(&self) -> Result<Option<String>, std::io::Error> {
+        Ok(None)
+    }
+}
+
+pub fn issues(
+    client: Client,
+    repo: RepoName,
+    cursor_cache: Box<dyn CursorCache + Send>,
+) -> impl futures::stream::Stream<Item = Result<DownloadedIssue, Error>> {
+    issue_pages(client, repo, Listing::All, cursor_cache)
+}
+
+/// The issues which were updated at or after `since`, or every issue if it's `None`, least
+/// recently updated first
+pub fn updated_issues(
+    client: Client,
     repo: RepoName,
+    since: Option<chrono::DateTime<chrono::Utc>>,
+) -> impl futures::stream::Stream<Item = Result<DownloadedIssue, Error>> {
+    issue_pages(
+        client,
+        repo,
+        Listing::UpdatedSince(since),
+        Box::new(NoCursor),
+    )
+}
+
+fn issue_pages(
+    client: Client,
+    repo: RepoName,
+    listing: Listing,
     cursor_cache: Box<dyn CursorCache + Send>,
 ) -> impl futures::stream::Stream<Item = Result<DownloadedIssue, Error>> {
     let stream: Pin<Box<dyn futures::Stream<Item = IssueStreamResult> + std::marker::Send>> =
         futures::stream::try_unfold::<PaginationState, _, _, _>(
             PaginationState::Starting(IssuesStreamState {
-                crab,
+                client,
                 repo,
+                listing,
                 cursor_cache,
             }),
             async move |state| match state {
                 PaginationState::Starting(state) => {
                     let after = state.cursor_cache.load_cursor()?;
-                    println!("Getting after: {:?}", after);
-                    let vars = serde_json::json!({
-                        "owner": state.repo.owner,
-                        "name": state.repo.name,
-                        "after": after
-                    });
+                    verbose!("getting issues after {:?}", after);
+                    let vars = state.variables(after);
                     let first_page: DataWrapper<GraphqlIssuesRepositoryWrapper> =
-                        graphql_request(&state.crab, ISSUES_QUERY, vars).await?;
+                        graphql_request(&state.client, state.query(), vars).await?;
                     Ok(Some((
                         futures::stream::empty().boxed(),
                         PaginationState::ProcessingPage(
@@ -165,7 +366,7 @@ pub(crate) fn issues(
                 PaginationState::ProcessingPage(state, current_page) => {
                     let items = futures::stream::FuturesUnordered::new();
                     for issue in current_page.nodes {
-                        items.push(get_issue(state.crab.clone(), state.repo.clone(), issue))
+                        items.push(get_issue(state.client.clone(), state.repo.clone(), issue))
                     }
                     let items = items.boxed();
                     let next_state = if current_page.page_info.has_next_page {
@@ -188,13 +389,9 @@ pub(crate) fn issues(
                         state.cursor_cache.save_cursor(last)?;
                     }
                     let next_state = if let Some(end) = next_cursor {
-                        let vars = serde_json::json!({
-                            "owner": state.repo.owner,
-                            "name": state.repo.name,
-                            "after": end
-                        });
+                        let vars = state.variables(Some(end));
                         let next_page: DataWrapper<GraphqlIssuesRepositoryWrapper> =
-                            graphql_request(&state.crab, ISSUES_QUERY, vars).await?;
+                            graphql_request(&state.client, state.query(), vars).await?;
                         PaginationState::ProcessingPage(
                             state,
                             Box::new(next_page.data.repository.issues),
@@ -210,17 +407,66 @@ pub(crate) fn issues(
     stream.try_flatten().boxed()
 }
 
+/// Fetch a single issue with all of its comments. Returns `None` if the repository has no issue
+/// with this number.
+pub async fn issue(
+    client: Client,
+    repo: RepoName,
+    number: u64,
+) -> Result<Option<DownloadedIssue>, Error> {
+    let vars = serde_json::json!({
+        "owner": repo.owner,
+        "name": repo.name,
+        "number": number,
+    });
+    let response: DataWrapper<GraphqlIssueRepositoryWrapper> =
+        graphql_request(&client, ISSUE_QUERY, vars).await?;
+    match response.data.repository.issue {
+        Some(issue) => Ok(Some(get_issue(client, repo, issue).await?)),
+        None => Ok(None),
+    }
+}
+
+/// The profile of a GitHub user
+#[derive(Clone, Debug, PartialEq, Eq, serde::Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GithubProfile {
+    pub login: String,
+    /// The display name, if the user has set one
+    pub name: Option<String>,
+    pub avatar_url: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct GraphqlUserWrapper {
+    user: Option<GithubProfile>,
+}
+
+/// Fetch the profile of the user `login`. Bots and deleted users aren't users as far as the API
+/// is concerned, so they get a profile with only their login.
+pub async fn profile(client: &Client, login: &str) -> Result<GithubProfile, Error> {
+    let vars = serde_json::json!({ "login": login });
+    let response: DataWrapper<GraphqlUserWrapper> =
+        graphql_request(client, USER_QUERY, vars).await?;
+    Ok(response.data.user.unwrap_or_else(|| GithubProfile {
+        login: login.to_string(),
+        name: None,
+        avatar_url: None,
+    }))
+}
+
 async fn get_issue(
-    crab: octocrab::Octocrab,
+    client: Client,
     repo: RepoName,
     issue: GraphqlIssue,
 ) -> Result<DownloadedIssue, Error> {
-    let comments = comments(crab, repo, &issue).await?;
-    Ok(issue.into_downloaded(comments))
+    let comments = comments(client.clone(), repo.clone(), &issue).await?;
+    let events = events(client, repo, &issue).await?;
+    Ok(issue.into_downloaded(comments, events))
 }
 
 async fn comments(
-    crab: octocrab::Octocrab,
+    client: Client,
     repo: RepoName,
     issue: &GraphqlIssue,
 ) -> Result<Vec<DownloadedComment>, Error> {
@@ -228,7 +474,7 @@ async fn comments(
     let mut comments: Vec<DownloadedComment> =
         issue.comments.nodes.iter().map(|c| c.into()).collect();
     while page.has_next_page {
-        println!("loading additional comments for {}", issue.number);
+        verbose!("loading additional comments for {}", issue.number);
         let vars = serde_json::json!({
             "owner": repo.owner,
             "name": repo.name,
@@ -236,10 +482,10 @@ async fn comments(
             "after": page.end_cursor
         });
         let next_page: DataWrapper<GraphqlCommentsRepositoryWrapper> =
-            match graphql_request(&crab, ISSUE_COMMENTS_QUERY, vars).await {
+            match graphql_request(&client, ISSUE_COMMENTS_QUERY, vars).await {
                 Ok(p) => p,
                 Err(e) => {
-                    println!("Error whilst fetching comments for {}", issue.number);
+                    eprintln!("Error whilst fetching comments for {}", issue.number);
                     return Err(e.into());
                 }
             };
@@ -258,19 +504,93 @@ async fn comments(
     Ok(comments)
 }
 
-async fn graphql_request<R: octocrab::FromResponse>(
-    crab: &octocrab::Octocrab,
+async fn events(
+    client: Client,
+    repo: RepoName,
+    issue: &GraphqlIssue,
+) -> Result<Vec<DownloadedEvent>, Error> {
+    let timeline = match &issue.timeline_items {
+        Some(t) => t,
+        None => return Ok(Vec::new()),
+    };
+    let mut page = timeline.page_info.clone();
+    let mut events: Vec<DownloadedEvent> =
+        timeline.nodes.iter().filter_map(|e| e.to_event()).collect();
+    while page.has_next_page {
+        verbose!("loading additional events for {}", issue.number);
+        let vars = serde_json::json!({
+            "owner": repo.owner,
+            "name": repo.name,
+            "number": issue.number,
+            "after": page.end_cursor
+        });
+        let next_page: DataWrapper<GraphqlTimelineRepositoryWrapper> =
+            match graphql_request(&client, ISSUE_TIMELINE_QUERY, vars).await {
+                Ok(p) => p,
+                Err(e) => {
+                    eprintln!("Error whilst fetching events for {}", issue.number);
+                    return Err(e);
+                }
+            };
+        let timeline = next_page.data.repository.issue.timeline_items;
+        events.extend(timeline.nodes.iter().filter_map(|e| e.to_event()));
+        page = timeline.page_info;
+    }
+    Ok(events)
+}
+
+async fn graphql_request<R: serde::de::DeserializeOwned>(
+    client: &Client,
     query: &'static str,
     variables: serde_json::Value,
-) -> Result<R, octocrab::Error> {
-    crab.post(
-        "graphql",
-        Some(&serde_json::json! {{
-            "query": query,
-            "variables": variables
-        }}),
-    )
-    .await
+) -> Result<R, Error> {
+    let body = serde_json::json! {{
+        "query": query,
+        "variables": variables
+    }};
+    let cache_path = client.response_cache.as_ref().map(|cache| {
+        let key = crate::download::sha256_hex(&serde_json::to_vec(&body).unwrap());
+        cache.dir.join(format!("{}.json", key))
+    });
+    if let (Some(path), Some(cache)) = (&cache_path, &client.response_cache) {
+        if !cache.refresh && crate::fs::exists(path)? {
+            let bytes = crate::fs::read(path)?;
+            return Ok(serde_json::from_slice(&bytes)?);
+        }
+    }
+    let response = client
+        .http
+        .post(&client.graphql_url)
+        .header(reqwest::header::AUTHORIZATION, &client.authorization)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_vec(&body)?)
+        .send()
+        .await?;
+    let status = response.status();
+    if !status.is_success() {
+        let body = response.text().await?;
+        return Err(Error::Status { status, body });
+    }
+    let response: serde_json::Value = serde_json::from_slice(&response.bytes().await?)?;
+    if let Some(errors) = response.get("errors").filter(|e| !e.is_null()) {
+        let messages: Vec<String> = match errors.as_array() {
+            Some(errors) => errors
+                .iter()
+                .map(|e| match e.get("message").and_then(|m| m.as_str()) {
+                    Some(message) => message.to_string(),
+                    None => e.to_string(),
+                })
+                .collect(),
+            None => vec![errors.to_string()],
+        };
+        return Err(Error::Response(messages.join("; ")));
+    }
+    let parsed = R::deserialize(&response)?;
+    // Only responses which parsed are saved, so that a failure isn't replayed on every later run
+    if let Some(path) = &cache_path {
+        crate::fs::write_atomic(path, serde_json::to_vec(&response)?)?;
+    }
+    Ok(parsed)
 }
 
 impl From<GithubUserLoginWrapper> for GithubUserId {
@@ -291,8 +611,35 @@ impl From<&GraphqlComment> for DownloadedComment {
     }
 }
 
+impl GraphqlTimelineItem {
+    /// `None` for event types which aren't imported
+    fn to_event(&self) -> Option<DownloadedEvent> {
+        let kind = match self.typename.as_str() {
+            "LabeledEvent" => EventKind::Labeled {
+                label: self.label.as_ref()?.name.clone(),
+            },
+            "UnlabeledEvent" => EventKind::Unlabeled {
+                label: self.label.as_ref()?.name.clone(),
+            },
+            "ClosedEvent" => EventKind::Closed,
+            "ReopenedEvent" => EventKind::Reopened,
+            _ => return None,
+        };
+        Some(DownloadedEvent {
+            id: self.id.clone(),
+            actor_id: self.actor.clone().map(|a| a.into()),
+            created_at: self.created_at,
+            kind,
+        })
+    }
+}
+
 impl GraphqlIssue {
-    fn into_downloaded(self, comments: Vec<DownloadedComment>) -> DownloadedIssue {
+    fn into_downloaded(
+        self,
+        comments: Vec<DownloadedComment>,
+        events: Vec<DownloadedEvent>,
+    ) -> DownloadedIssue {
         DownloadedIssue {
             author_id: self.author.map(|a| a.into()),
             id: self.id,
@@ -301,7 +648,9 @@ impl GraphqlIssue {
             number: self.number,
             state: self.state,
             created_at: self.created_at,
+            updated_at: self.updated_at,
             title: self.title,
+            events,
         }
     }
 }
@@ -0,0 +1,192 @@
+//! A `git log` for a single object: each automerge change in its history with who made it, when,
+//! and a summary of what it changed. The summaries are worked out by replaying the history one
+//! change at a time and comparing the documents before and after each change.
+//!
+//! Issues imported with `--acl` record the identity behind each automerge actor under `actors`.
+//! Other issues don't, so the identity is inferred from what the change did: the author of the
+//! issue made the first change and a commenter the change which added their comment. Failing
+//! both, only the actor is known.
+use std::collections::{BTreeSet, HashMap};
+
+use link_crypto::PeerId;
+use serde_json::Value;
+
+use crate::layout;
+
+#[derive(Clone)]
+pub struct Entry {
+    pub hash: String,
+    pub actor: String,
+    pub author_urn: Option<String>,
+    pub peer: Option<PeerId>,
+    /// Milliseconds since the epoch, as recorded by the actor
+    pub time: i64,
+    pub summary: Vec<String>,
+}
+
+/// Replay `history` one change at a time, oldest first, calling `visit` with each change, the
+/// frontend after applying it and the documents before and after it. The `summary` of the entries
+/// passed to `visit` is empty. `peers` maps the URN of each peer's identity to the peer.
+pub fn replay<F>(history: &[u8], peers: &HashMap<String, PeerId>, mut visit: F)
+where
+    F: FnMut(Entry, &automerge::Frontend, &Value, &Value),
+{
+    let changes = automerge::Change::load_document(history).unwrap();
+    let mut backend = automerge::Backend::new();
+    let mut frontend = automerge::Frontend::new();
+    let mut previous = Value::Null;
+    for change in changes {
+        let actor = change.actor_id().to_hex_string();
+        let hash = change.hash.to_string();
+        let time = change.time;
+        let patch = backend.apply_changes(vec![change]).unwrap();
+        frontend.apply_patch(patch).unwrap();
+        let current = frontend.state().to_json();
+        let author_urn = author(&previous, &current, &actor);
+        let entry = Entry {
+            hash,
+            peer: author_urn.as_ref().and_then(|u| peers.get(u)).copied(),
+            author_urn,
+            actor,
+            time,
+            summary: Vec::new(),
+        };
+        visit(entry, &frontend, &previous, &current);
+        previous = current;
+    }
+}
+
+/// Describe each change of `history`, oldest first
+pub fn log(history: &[u8], peers: &HashMap<String, PeerId>) -> Vec<Entry> {
+    let mut entries = Vec::new();
+    replay(history, peers, |mut entry, _, before, after| {
+        entry.summary = summarize(before, after);
+        entries.push(entry);
+    });
+    entries
+}
+
+/// The identity which made the change taking `before` to `after`, if it can be told
+fn author(before: &Value, after: &Value, actor: &str) -> Option<String> {
+    let as_string = |v: Option<&Value>| v.and_then(|v| v.as_str()).map(String::from);
+    if let Some(urn) = as_string(after.get("actors").and_then(|a| a.get(actor))) {
+        return Some(urn);
+    }
+    if before.is_null() {
+        return as_string(after.get("author_urn"));
+    }
+    let (before, after) = (layout::comments(before), layout::comments(after));
+    if after.len() == before.len() + 1 {
+        return after
+            .into_iter()
+            .find(|a| before.iter().all(|b| b.github_id != a.github_id))
+            .and_then(|c| c.commenter_urn);
+    }
+    None
+}
+
+/// Whether `key` holds bookkeeping rather than content, or content summarized on its own
+fn summarized_separately(key: &str) -> bool {
+    matches!(key, "actors" | "comments" | "state" | "labels") || key.starts_with("comment_")
+}
+
+fn plural(n: usize, noun: &str) -> String {
+    if n == 1 {
+        format!("{} {}", n, noun)
+    } else {
+        format!("{} {}s", n, noun)
+    }
+}
+
+/// What changed between `before` and `after`, e.g. `+1 comment` or `state OPEN -> CLOSED`
+fn summarize(before: &Value, after: &Value) -> Vec<String> {
+    if before.is_null() {
+        let title = after.get("title").and_then(|t| t.as_str()).unwrap_or("");
+        return vec![format!("created {:?}", title)];
+    }
+    let mut summary = Vec::new();
+
+    let (before_comments, after_comments) = (layout::comments(before), layout::comments(after));
+    if after_comments.len() > before_comments.len() {
+        summary.push(format!(
+            "+{}",
+            plural(after_comments.len() - before_comments.len(), "comment")
+        ));
+    } else if after_comments.len() < before_comments.len() {
+        summary.push(format!(
+            "-{}",
+            plural(before_comments.len() - after_comments.len(), "comment")
+        ));
+    } else {
+        let edited = before_comments
+            .iter()
+            .zip(&after_comments)
+            .filter(|(b, a)| b.body != a.body)
+            .count();
+        if edited > 0 {
+            summary.push(format!("edited {}", plural(edited, "comment")));
+        }
+    }
+
+    if before.get("state") != after.get("state") {
+        let state = |d: &Value| {
+            d.get("state")
+                .and_then(|s| s.as_str())
+                .unwrap_or("none")
+                .to_string()
+        };
+        summary.push(format!("state {} -> {}", state(before), state(after)));
+    }
+
+    let labels = |d: &Value| -> BTreeSet<String> {
+        d.get("labels")
+            .and_then(|l| l.as_object())
+            .map(|l| l.keys().cloned().collect())
+            .unwrap_or_default()
+    };
+    let (before_labels, after_labels) = (labels(before), labels(after));
+    for added in after_labels.difference(&before_labels) {
+        summary.push(format!("+label {}", added));
+    }
+    for removed in before_labels.difference(&after_labels) {
+        summary.push(format!("-label {}", removed));
+    }
+
+    let keys: BTreeSet<&String> = before
+        .as_object()
+        .into_iter()
+        .chain(after.as_object())
+        .flat_map(|o| o.keys())
+        .collect();
+    for key in keys {
+        if !summarized_separately(key) && before.get(key) != after.get(key) {
+            summary.push(format!("changed {}", key));
+        }
+    }
+
+    if summary.is_empty() {
+        summary.push("no visible change".to_string());
+    }
+    summary
+}
+
+impl std::fmt::Display for Entry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "change {}", self.hash)?;
+        match (&self.peer, &self.author_urn) {
+            (Some(peer), Some(urn)) => writeln!(f, "Author: {} ({})", peer, urn)?,
+            (None, Some(urn)) => writeln!(f, "Author: {}", urn)?,
+            _ => {}
+        }
+        writeln!(f, "Actor:  {}", self.actor)?;
+        {
+            use chrono::TimeZone;
+            match chrono::Utc.timestamp_millis_opt(self.time).single() {
+                Some(time) => writeln!(f, "Date:   {}", time.to_rfc3339())?,
+                None => writeln!(f, "Date:   unknown ({})", self.time)?,
+            }
+        }
+        writeln!(f)?;
+        writeln!(f, "    {}", self.summary.join(", "))
+    }
+}
@@ -0,0 +1,134 @@
+//! Trust on first use pinning of peer identities. The first time a node receives anything from a
+//! peer it records the identity the peer presents, and every later delivery from the peer must
+//! present the same identity or be rejected. Identities are compared by URN, so revisions of an
+//! identity are accepted but a different identity claiming the same peer ID is not.
+//!
+//! Pins are stored per node as JSON, mapping each peer ID to the URN of its identity.
+use std::{collections::HashMap, path::Path, str::FromStr};
+
+use link_crypto::{PeerId, SecretKey};
+use link_identities::{
+    delegation::Direct,
+    git::{
+        error::{Load, Store},
+        Urn,
+    },
+    payload::{Person as PersonSubject, PersonPayload},
+    Person,
+};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    LoadIdentity(#[from] Load),
+    #[error(transparent)]
+    StoreIdentity(#[from] Store),
+}
+
+/// The outcome of checking the identity a peer presents against the pinned one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PinCheck {
+    /// The peer hadn't been seen before, its identity is now pinned
+    FirstSight,
+    Matches,
+    Mismatch {
+        pinned: String,
+        presented: String,
+    },
+}
+
+#[derive(Debug, Default, serde::Serialize, serde::Deserialize)]
+pub struct IdentityPins(HashMap<String, String>);
+
+impl IdentityPins {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<IdentityPins, Error> {
+        if !crate::fs::exists(&path)? {
+            return Ok(IdentityPins::default());
+        }
+        Ok(serde_json::from_slice(&crate::fs::read(path)?)?)
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        crate::fs::write_atomic(path, serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Check `presented` against the identity pinned for `peer`, pinning it if there is none
+    pub fn check(&mut self, peer: &PeerId, presented: &Urn) -> PinCheck {
+        let presented = presented.to_string();
+        match self.0.get(&peer.to_string()) {
+            None => {
+                self.0.insert(peer.to_string(), presented);
+                PinCheck::FirstSight
+            }
+            Some(pinned) if *pinned == presented => PinCheck::Matches,
+            Some(pinned) => PinCheck::Mismatch {
+                pinned: pinned.clone(),
+                presented,
+            },
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+/// The peer whose ref `reference` is, for refs under `refs/namespaces/<project>/refs/remotes/`
+/// and object refs in the flat layout, `refs/namespaces/<project>/refs/cob/<typename>/<oid>/<peer>`
+pub fn peer_of_ref(reference: &str) -> Option<PeerId> {
+    let peer = match reference.split_once("/refs/remotes/") {
+        Some((_, rest)) => rest.split('/').next()?,
+        None => {
+            let (_, rest) = reference.split_once("/refs/cob/")?;
+            rest.rsplit('/').next()?
+        }
+    };
+    PeerId::from_str(peer).ok()
+}
+
+/// The identity `peer` presents in the namespace of `project` in `repo`
+pub fn presented_identity(
+    repo: &git2::Repository,
+    project: &Urn,
+    peer: &PeerId,
+) -> Result<Option<Urn>, Error> {
+    let name = format!(
+        "refs/namespaces/{}/refs/remotes/{}/rad/self",
+        project.encode_id(),
+        peer
+    );
+    let oid = match repo.find_reference(&name) {
+        Ok(reference) => match reference.target() {
+            Some(oid) => oid,
+            None => return Ok(None),
+        },
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let identities: link_identities::Identities<'_, Person> = repo.into();
+    Ok(Some(identities.get(oid)?.urn()))
+}
+
+/// Create an identity in `repo` which has the same name as the identity of `peer` but a different
+/// key, as an impersonator would
+pub fn impersonate(repo: &git2::Repository, peer: &PeerId) -> Result<Urn, Error> {
+    let key = SecretKey::new();
+    let payload = PersonPayload::new(PersonSubject {
+        name: peer.to_string().into(),
+    });
+    let identities: link_identities::Identities<'_, Person> = repo.into();
+    let person = identities.create(payload, Direct::new(key.public()), &key)?;
+    Ok(person.urn())
+}
@@ -0,0 +1,423 @@
+use std::{
+    cell::Cell,
+    collections::HashSet,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use indicatif::ProgressBar;
+use thiserror::Error;
+
+use super::chaos;
+use super::download::{IssueStorage, LoadError};
+use super::downloaded_issue::DownloadedIssue;
+use super::lite_monorepo::{
+    error::{CreateOrOpen, Import as ImportError},
+    LiteMonorepo,
+};
+use super::object_store;
+use super::retry::Transient;
+use super::watchdog::{self, Heartbeat};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("failed to load issue {number}: {source}")]
+    Load { number: u64, source: LoadError },
+    #[error("failed to import issue {number}: {source}")]
+    Import { number: u64, source: ImportError },
+    #[error("failed to open the monorepo for a worker: {0}")]
+    OpenWorker(#[from] CreateOrOpen),
+    #[error("an import worker was killed by --chaos")]
+    WorkerKilled,
+    #[error("an import worker panicked while importing issue {0}")]
+    WorkerPanicked(u64),
+    #[error("failed to record a hung issue: {0}")]
+    RecordHung(std::io::Error),
+}
+
+impl Transient for Error {
+    fn is_transient(&self) -> bool {
+        match self {
+            Error::Load {
+                source: LoadError::ObjectStore(object_store::Error::Http(_)),
+                ..
+            } => true,
+            Error::Load { .. } => false,
+            // Faults injected by --chaos are retried so that a run resumes after each of them
+            Error::Import {
+                source: ImportError::Git(e),
+                ..
+            } => e.code() == git2::ErrorCode::Locked || e.message().starts_with("chaos:"),
+            Error::Import { source, .. } => is_locked(source),
+            Error::OpenWorker(_) => false,
+            Error::WorkerKilled => true,
+            Error::WorkerPanicked(_) => false,
+            Error::RecordHung(_) => false,
+        }
+    }
+}
+
+/// Whether `error` was caused by a git lock held by another process. cob's errors are searched by
+/// their sources, so a lock error cob doesn't expose as the source of its own isn't found, and
+/// isn't retried.
+fn is_locked(error: &ImportError) -> bool {
+    // `#[error(transparent)]` variants pass `source()` through to the source of the error they
+    // wrap, so the search starts from the wrapped error itself
+    let mut next: Option<&(dyn std::error::Error + 'static)> = match error {
+        ImportError::CobCreate(e) => Some(e),
+        ImportError::CobUpdate(e) => Some(e),
+        ImportError::CobRetrieve(e) => Some(e),
+        ImportError::PeerRefs(e) => Some(e),
+        _ => None,
+    };
+    while let Some(e) = next {
+        if let Some(git) = e.downcast_ref::<git2::Error>() {
+            if git.code() == git2::ErrorCode::Locked {
+                return true;
+            }
+        }
+        next = e.source();
+    }
+    false
+}
+
+/// Import the issues in `numbers` (which must be in ascending order) into `monorepo`, skipping
+/// any issues up to and including `checkpoint`. `checkpoint` is updated after each issue has been
+/// completely imported so that if this fails it can be called again to resume where it left off.
+pub fn import_issues(
+    monorepo: &mut LiteMonorepo,
+    storage: &dyn IssueStorage,
+    numbers: &[u64],
+    checkpoint: &Cell<Option<u64>>,
+    bar: &ProgressBar,
+) -> Result<(), Error> {
+    let remaining = numbers
+        .iter()
+        .filter(|n| checkpoint.get().map(|c| **n > c).unwrap_or(true));
+    for number in remaining {
+        let issue = match storage.issue(*number) {
+            Ok(Some(issue)) => issue,
+            Ok(None) => continue,
+            Err(source) => {
+                return Err(Error::Load {
+                    number: *number,
+                    source,
+                })
+            }
+        };
+        let start = Instant::now();
+        monorepo
+            .import_issue(&issue)
+            .map_err(|source| Error::Import {
+                number: *number,
+                source,
+            })?;
+        verbose!("imported issue {} in {:?}", number, start.elapsed());
+        checkpoint.set(Some(*number));
+        bar.inc(1);
+    }
+    Ok(())
+}
+
+/// Import the issues in `numbers` like [`import_issues`], but on `jobs` threads, each with its own
+/// handle on the monorepo. Issues are loaded from `storage` on the calling thread and handed to
+/// whichever worker is free next, so `numbers` are imported roughly but not exactly in order.
+/// Storage such as [`object_store::ObjectStoreStorage`] can only be used from the thread running
+/// the tokio runtime, so workers never load issues themselves.
+///
+/// `checkpoint` only advances past issues once every issue before them has been imported, so
+/// issues which were imported beyond it are recorded in `imported` instead, which must be passed
+/// again when resuming so that they aren't imported twice.
+pub fn import_issues_parallel(
+    monorepo: &mut LiteMonorepo,
+    storage: &dyn IssueStorage,
+    numbers: &[u64],
+    checkpoint: &Cell<Option<u64>>,
+    imported: &Mutex<HashSet<u64>>,
+    bar: &ProgressBar,
+    jobs: usize,
+) -> Result<(), Error> {
+    let remaining: Vec<u64> = {
+        let imported = imported.lock().unwrap();
+        numbers
+            .iter()
+            .copied()
+            .filter(|n| checkpoint.get().map(|c| *n > c).unwrap_or(true))
+            .filter(|n| !imported.contains(n))
+            .collect()
+    };
+    let failed = AtomicBool::new(false);
+    // Bounded so that loading doesn't get far ahead of importing. Once every worker has exited
+    // the receiver is dropped, and sending fails rather than blocking.
+    let (issues, received) = mpsc::sync_channel::<DownloadedIssue>(jobs.max(1));
+    let received = Arc::new(Mutex::new(received));
+    let (results, load_error): (Vec<Result<u64, Error>>, Option<Error>) =
+        crossbeam_utils::thread::scope(|s| {
+            let handles: Vec<_> = (0..jobs.max(1))
+                .map(|_| {
+                    let worker = monorepo.import_worker();
+                    let (received, failed) = (received.clone(), &failed);
+                    s.spawn(move |_| {
+                        let mut monorepo = worker.open()?;
+                        loop {
+                            let issue = match received.lock().unwrap().recv() {
+                                Ok(issue) => issue,
+                                Err(_) => break,
+                            };
+                            let number = issue.number;
+                            let start = Instant::now();
+                            if let Err(source) = monorepo.import_issue(&issue) {
+                                failed.store(true, Ordering::SeqCst);
+                                return Err(Error::Import { number, source });
+                            }
+                            if let Some(chaos) = monorepo.chaos() {
+                                chaos.kill_worker();
+                            }
+                            verbose!("imported issue {} in {:?}", number, start.elapsed());
+                            imported.lock().unwrap().insert(number);
+                            bar.inc(1);
+                        }
+                        Ok(monorepo.changes_created())
+                    })
+                })
+                .collect();
+            drop(received);
+
+            let mut load_error = None;
+            for number in remaining {
+                if failed.load(Ordering::SeqCst) {
+                    break;
+                }
+                match storage.issue(number) {
+                    Ok(Some(issue)) => {
+                        if issues.send(issue).is_err() {
+                            break;
+                        }
+                    }
+                    Ok(None) => {
+                        imported.lock().unwrap().insert(number);
+                        bar.inc(1);
+                    }
+                    Err(source) => {
+                        load_error = Some(Error::Load { number, source });
+                        break;
+                    }
+                }
+            }
+            drop(issues);
+
+            let results = handles
+                .into_iter()
+                .map(|h| match h.join() {
+                    Ok(result) => result,
+                    Err(payload) if payload.is::<chaos::Killed>() => Err(Error::WorkerKilled),
+                    Err(payload) => std::panic::resume_unwind(payload),
+                })
+                .collect();
+            (results, load_error)
+        })
+        .expect("import worker panicked");
+
+    let mut first_error = load_error;
+    for result in results {
+        match result {
+            Ok(changes) => monorepo.add_changes_created(changes),
+            Err(e) => first_error = first_error.or(Some(e)),
+        }
+    }
+    let imported = imported.lock().unwrap();
+    for number in numbers
+        .iter()
+        .filter(|n| checkpoint.get().map(|c| **n > c).unwrap_or(true))
+    {
+        if !imported.contains(number) {
+            break;
+        }
+        checkpoint.set(Some(*number));
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// How often the workers of [`import_issues_watched`] are checked on
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Give up on an issue once one of its cob operations has taken longer than `timeout`, recording
+/// it in the log at `log`, see [`crate::watchdog`]
+pub struct Watchdog {
+    pub timeout: Duration,
+    pub log: PathBuf,
+}
+
+struct Worker {
+    id: usize,
+    issues: mpsc::Sender<DownloadedIssue>,
+    heartbeat: Heartbeat,
+    /// The issue the worker is importing
+    current: Option<u64>,
+}
+
+/// Import the issues in `numbers` like [`import_issues_parallel`], on `jobs` threads, but skip
+/// any issue which one of the operations of importing it hangs on, as `watchdog` decides.
+/// Skipped issues count as imported, so that `checkpoint` moves past them.
+#[allow(clippy::too_many_arguments)]
+pub fn import_issues_watched(
+    monorepo: &mut LiteMonorepo,
+    storage: &dyn IssueStorage,
+    numbers: &[u64],
+    checkpoint: &Cell<Option<u64>>,
+    imported: &Mutex<HashSet<u64>>,
+    bar: &ProgressBar,
+    jobs: usize,
+    watchdog: &Watchdog,
+) -> Result<(), Error> {
+    let remaining: Vec<u64> = {
+        let imported = imported.lock().unwrap();
+        numbers
+            .iter()
+            .copied()
+            .filter(|n| checkpoint.get().map(|c| *n > c).unwrap_or(true))
+            .filter(|n| !imported.contains(n))
+            .collect()
+    };
+    let changes = Arc::new(AtomicU64::new(0));
+    let (results_tx, results) = mpsc::channel();
+    let mut spawned = 0;
+    let mut spawn = || {
+        spawned += 1;
+        spawn_worker(monorepo, spawned, results_tx.clone(), changes.clone())
+    };
+    let mut workers: Vec<Worker> = (0..jobs.max(1)).map(|_| spawn()).collect();
+    let mut remaining = remaining.into_iter();
+    let mut first_error = None;
+    loop {
+        for worker in workers.iter_mut().filter(|w| w.current.is_none()) {
+            if first_error.is_some() {
+                break;
+            }
+            let number = match remaining.next() {
+                Some(n) => n,
+                None => break,
+            };
+            match storage.issue(number) {
+                Ok(Some(issue)) => {
+                    worker.current = Some(number);
+                    worker.issues.send(issue).ok();
+                }
+                Ok(None) => {
+                    imported.lock().unwrap().insert(number);
+                    bar.inc(1);
+                }
+                Err(source) => first_error = Some(Error::Load { number, source }),
+            }
+        }
+        if workers.iter().all(|w| w.current.is_none()) {
+            break;
+        }
+
+        match results.recv_timeout(WATCH_INTERVAL) {
+            Ok((id, result)) => {
+                // Results from abandoned workers are for issues which were skipped
+                if let Some(worker) = workers.iter_mut().find(|w| w.id == id) {
+                    match (result, worker.current.take()) {
+                        (Ok(()), Some(number)) => {
+                            imported.lock().unwrap().insert(number);
+                            bar.inc(1);
+                        }
+                        (Ok(()), None) => {}
+                        (Err(e), _) => first_error = first_error.or(Some(e)),
+                    }
+                }
+            }
+            Err(mpsc::RecvTimeoutError::Timeout) => {}
+            Err(mpsc::RecvTimeoutError::Disconnected) => unreachable!("a sender is kept here"),
+        }
+
+        for i in 0..workers.len() {
+            let number = match workers[i].current {
+                Some(number) => number,
+                None => continue,
+            };
+            if let Some(hung) = workers[i].heartbeat.overdue(watchdog.timeout, number) {
+                bar.println(hung.to_string());
+                watchdog::record(&watchdog.log, &hung).map_err(Error::RecordHung)?;
+                imported.lock().unwrap().insert(number);
+                bar.inc(1);
+                // Dropping the worker's sender lets it exit if the operation ever returns
+                workers[i] = spawn();
+            }
+        }
+    }
+    drop(workers);
+    monorepo.add_changes_created(changes.load(Ordering::SeqCst));
+
+    let imported = imported.lock().unwrap();
+    for number in numbers
+        .iter()
+        .filter(|n| checkpoint.get().map(|c| **n > c).unwrap_or(true))
+    {
+        if !imported.contains(number) {
+            break;
+        }
+        checkpoint.set(Some(*number));
+    }
+    match first_error {
+        Some(e) => Err(e),
+        None => Ok(()),
+    }
+}
+
+/// A worker which imports the issues sent to it into its own handle on `monorepo` and sends
+/// back the result of each, tagged with `id`
+fn spawn_worker(
+    monorepo: &LiteMonorepo,
+    id: usize,
+    results: mpsc::Sender<(usize, Result<(), Error>)>,
+    changes: Arc<AtomicU64>,
+) -> Worker {
+    let worker = monorepo.import_worker();
+    let heartbeat = Heartbeat::default();
+    let (issues, received) = mpsc::channel::<DownloadedIssue>();
+    let worker_heartbeat = heartbeat.clone();
+    std::thread::spawn(move || {
+        let mut monorepo = match worker.open() {
+            Ok(monorepo) => monorepo,
+            Err(e) => {
+                results.send((id, Err(e.into()))).ok();
+                return;
+            }
+        };
+        monorepo.set_heartbeat(Some(worker_heartbeat));
+        for issue in received {
+            let number = issue.number;
+            let start = Instant::now();
+            let before = monorepo.changes_created();
+            let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                monorepo.import_issue(&issue)
+            }));
+            let result = match result {
+                Ok(result) => result.map_err(|source| Error::Import { number, source }),
+                Err(_) => Err(Error::WorkerPanicked(number)),
+            };
+            changes.fetch_add(monorepo.changes_created() - before, Ordering::SeqCst);
+            if result.is_ok() {
+                verbose!("imported issue {} in {:?}", number, start.elapsed());
+            }
+            if results.send((id, result)).is_err() {
+                break;
+            }
+        }
+    });
+    Worker {
+        id,
+        issues,
+        heartbeat,
+        current: None,
+    }
+}
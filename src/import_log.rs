@@ -0,0 +1,125 @@
+//! A record of the changes each issue was imported as, so that changes can be traced back to the
+//! GitHub issue, comment or event they came from without replaying the automerge history. When
+//! enabled, each change an import creates is appended as a line to
+//! `import_log/<issue number>.jsonl` in the root of the monorepo. Resumed and incremental imports
+//! append to the same file, so it always covers the whole history of the object.
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+pub const IMPORT_LOG: &str = "import_log";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+/// What on GitHub a change was made from
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Source {
+    /// The issue itself, which becomes the change creating the object
+    Issue,
+    Comment {
+        id: String,
+    },
+    Event {
+        id: String,
+    },
+}
+
+/// One change made by an import
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Change {
+    pub number: u64,
+    pub object_id: String,
+    /// The commit of the change, `None` if the peer's ref couldn't be found after making it
+    pub commit: Option<String>,
+    /// The peer which made the change
+    pub peer: String,
+    /// The URN of the identity of the peer
+    pub author_urn: Option<String>,
+    pub source: Source,
+}
+
+/// The changes an issue was imported as, oldest first
+pub struct Record {
+    pub number: u64,
+    pub object_id: Option<String>,
+    pub changes: Vec<Change>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ImportLog {
+    dir: PathBuf,
+}
+
+impl ImportLog {
+    /// The import log of the monorepo at `root`
+    pub fn new<P: AsRef<Path>>(root: P) -> ImportLog {
+        ImportLog {
+            dir: root.as_ref().join(IMPORT_LOG),
+        }
+    }
+
+    fn path(&self, number: u64) -> PathBuf {
+        self.dir.join(format!("{}.jsonl", number))
+    }
+
+    pub fn append(&self, change: &Change) -> Result<(), Error> {
+        crate::fs::create_dir_all(&self.dir)?;
+        let mut line = serde_json::to_vec(change)?;
+        line.push(b'\n');
+        crate::fs::append_line(self.path(change.number), &line)?;
+        Ok(())
+    }
+
+    /// The record of issue `number`, if any of it was imported with the log enabled
+    pub fn load(&self, number: u64) -> Result<Option<Record>, Error> {
+        let path = self.path(number);
+        if !crate::fs::exists(&path)? {
+            return Ok(None);
+        }
+        let mut changes = Vec::new();
+        for line in crate::fs::read_to_string(path)?.lines() {
+            // The last line is incomplete if we crashed whilst writing it
+            if let Ok(change) = serde_json::from_str::<Change>(line) {
+                changes.push(change);
+            }
+        }
+        Ok(Some(Record {
+            number,
+            object_id: changes.last().map(|c| c.object_id.clone()),
+            changes,
+        }))
+    }
+}
+
+impl std::fmt::Display for Record {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "issue {} imported as {}",
+            self.number,
+            self.object_id.as_deref().unwrap_or("nothing")
+        )?;
+        for change in &self.changes {
+            let source = match &change.source {
+                Source::Issue => "issue".to_string(),
+                Source::Comment { id } => format!("comment {}", id),
+                Source::Event { id } => format!("event {}", id),
+            };
+            writeln!(
+                f,
+                "{} {} {}",
+                change.commit.as_deref().unwrap_or("unknown commit"),
+                change.peer,
+                source
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,301 @@
+//! A prototype of an alternative ref layout, in which each peer has a single ref per type name
+//! pointing at an index of its objects, rather than a ref per object. The index is a commit whose
+//! tree has an entry for each object, named by object ID, which is a gitlink to the tip of the
+//! peer's change graph for that object. Every change to an object rewrites the tree and adds a
+//! commit on top of the previous index.
+//!
+//! cob's `RefsStorage` hands out a `git2::Reference` for each object, which this layout doesn't
+//! have, so the prototype can't be used to retrieve objects with cob as it is. It provides the
+//! same operations in terms of the commits the references would point at, which is all cob reads
+//! from them.
+//!
+//! Note that git doesn't follow gitlinks when working out what to send in a fetch, so a real
+//! implementation would have to make the tips reachable some other way.
+use std::{
+    collections::HashMap,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use cob::{ObjectId, RefsStorage, TypeName};
+use link_crypto::PeerId;
+use link_identities::git::Urn;
+use thiserror::Error;
+
+use crate::{
+    bench::Stats, identity_pins, lite_monorepo::LiteMonorepo, peer_refs_storage, ref_advertisement,
+    replication,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+    #[error(transparent)]
+    PeerRefs(#[from] peer_refs_storage::Error),
+}
+
+/// The mode of a gitlink tree entry
+const GITLINK_MODE: i32 = 0o160000;
+
+/// The name of the index ref of `peer` for `typename`
+fn index_ref(urn: &Urn, peer: &PeerId, typename: &TypeName) -> String {
+    format!(
+        "refs/namespaces/{}/refs/remotes/{}/cob/{}/index",
+        urn.encode_id(),
+        peer.default_encoding(),
+        typename
+    )
+}
+
+/// The cost of one update of an index
+pub struct IndexUpdate {
+    /// The size of the tree and commit written
+    pub bytes: u64,
+}
+
+pub struct IndexRefsStorage<'a> {
+    peer: PeerId,
+    repo: &'a git2::Repository,
+}
+
+impl<'a> IndexRefsStorage<'a> {
+    pub fn new(peer: PeerId, repo: &'a git2::Repository) -> IndexRefsStorage<'a> {
+        IndexRefsStorage { peer, repo }
+    }
+
+    /// Point this peer's index entry for `object_id` at `new_commit`
+    pub fn update_ref(
+        &self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+        object_id: ObjectId,
+        new_commit: git2::Oid,
+    ) -> Result<IndexUpdate, Error> {
+        let name = index_ref(identity_urn, &self.peer, typename);
+        let parent = match self.repo.find_reference(&name) {
+            Ok(r) => Some(r.peel_to_commit()?),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        let previous_tree = parent.as_ref().map(|p| p.tree()).transpose()?;
+        let mut builder = self.repo.treebuilder(previous_tree.as_ref())?;
+        builder.insert(object_id.to_string(), new_commit, GITLINK_MODE)?;
+        let tree_id = builder.write()?;
+        let tree = self.repo.find_tree(tree_id)?;
+        let signature = git2::Signature::now("index", "index@localhost")?;
+        let parents: Vec<&git2::Commit<'_>> = parent.iter().collect();
+        let commit_id = self.repo.commit(
+            None,
+            &signature,
+            &signature,
+            "update index",
+            &tree,
+            &parents,
+        )?;
+        self.repo
+            .reference(&name, commit_id, true, "update index")?;
+
+        let odb = self.repo.odb()?;
+        let (tree_len, _) = odb.read_header(tree_id)?;
+        let (commit_len, _) = odb.read_header(commit_id)?;
+        Ok(IndexUpdate {
+            bytes: (tree_len + commit_len) as u64,
+        })
+    }
+
+    /// The tips of every object of `typename`, from the index of each peer
+    pub fn type_tips(
+        &self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+    ) -> Result<HashMap<ObjectId, Vec<git2::Oid>>, Error> {
+        let glob = format!(
+            "refs/namespaces/{}/refs/remotes/*/cob/{}/index",
+            identity_urn.encode_id(),
+            typename
+        );
+        let mut tips: HashMap<ObjectId, Vec<git2::Oid>> = HashMap::new();
+        for reference in self.repo.references_glob(&glob)? {
+            let tree = reference?.peel_to_tree()?;
+            for entry in tree.iter() {
+                if let Some(oid) = entry.name().and_then(|n| ObjectId::from_str(n).ok()) {
+                    tips.entry(oid).or_default().push(entry.id());
+                }
+            }
+        }
+        Ok(tips)
+    }
+
+    /// The tips of `oid` in the index of each peer
+    pub fn object_tips(
+        &self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+        oid: &ObjectId,
+    ) -> Result<Vec<git2::Oid>, Error> {
+        let glob = format!(
+            "refs/namespaces/{}/refs/remotes/*/cob/{}/index",
+            identity_urn.encode_id(),
+            typename
+        );
+        let mut tips = Vec::new();
+        for reference in self.repo.references_glob(&glob)? {
+            let tree = reference?.peel_to_tree()?;
+            if let Some(entry) = tree.get_name(&oid.to_string()) {
+                tips.push(entry.id());
+            }
+        }
+        Ok(tips)
+    }
+}
+
+/// The outcome of building an index layout copy of a monorepo
+pub struct Report {
+    pub per_object: ref_advertisement::Sample,
+    pub index: ref_advertisement::Sample,
+    /// Listing the objects and their tips in each layout
+    pub enumerate_per_object: Duration,
+    pub enumerate_index: Duration,
+    /// Looking up the tips of a single object in each layout
+    pub lookup_per_object: Option<Stats>,
+    pub lookup_index: Option<Stats>,
+    pub index_updates: Option<Stats>,
+    /// The total size of the index trees and commits written
+    pub index_bytes: u64,
+    /// Objects whose tips differ between the layouts
+    pub mismatched: usize,
+}
+
+/// Build a copy of `source` at `root` which uses the index layout, by replaying every object ref
+/// of `source` as an index update in the order the tips were committed, and compare the layouts
+pub fn compare(source: &LiteMonorepo, root: &Path) -> Result<Report, Error> {
+    let typename = source.typename().clone();
+    let urn = source.project_urn();
+    let target = replication::create_replica(source, root)?;
+
+    let mut updates = Vec::new();
+    for reference in source.repo().references()? {
+        let reference = reference?;
+        let (name, tip) = match (reference.name(), reference.target()) {
+            (Some(name), Some(tip)) => (name, tip),
+            _ => continue,
+        };
+        if let (Some(peer), Some(object)) = (
+            identity_pins::peer_of_ref(name),
+            replication::object_of_ref(name),
+        ) {
+            let time = source.repo().find_commit(tip)?.time().seconds();
+            updates.push((time, peer, object, tip));
+        }
+    }
+    updates.sort_by_key(|(time, ..)| *time);
+
+    let mut update_times = Vec::new();
+    let mut index_bytes = 0;
+    for (_, peer, object, tip) in &updates {
+        replication::copy_objects(source.repo(), target.repo(), *tip)?;
+        let start = Instant::now();
+        let update = IndexRefsStorage::new(*peer, target.repo())
+            .update_ref(&urn, &typename, *object, *tip)?;
+        update_times.push(start.elapsed());
+        index_bytes += update.bytes;
+    }
+
+    let local = match source.peer_ids().next() {
+        Some(peer) => *peer,
+        None => return Err(git2::Error::from_str("the monorepo has no peers").into()),
+    };
+    let per_object = source.refs_storage(local);
+    let index = IndexRefsStorage::new(local, target.repo());
+
+    let start = Instant::now();
+    let per_object_refs = per_object.type_references(&urn, &typename)?;
+    let mut per_object_tips: HashMap<ObjectId, Vec<git2::Oid>> = HashMap::new();
+    for (oid, refs) in &per_object_refs {
+        let tips = per_object_tips.entry(*oid).or_default();
+        tips.extend(
+            refs.local
+                .iter()
+                .chain(&refs.remote)
+                .filter_map(|r| r.target()),
+        );
+    }
+    let enumerate_per_object = start.elapsed();
+    let start = Instant::now();
+    let index_tips = index.type_tips(&urn, &typename)?;
+    let enumerate_index = start.elapsed();
+
+    let mut mismatched = 0;
+    let mut lookup_per_object = Vec::new();
+    let mut lookup_index = Vec::new();
+    for (oid, tips) in &mut per_object_tips {
+        let start = Instant::now();
+        per_object.object_references(&urn, &typename, oid)?;
+        lookup_per_object.push(start.elapsed());
+        let start = Instant::now();
+        let mut indexed = index.object_tips(&urn, &typename, oid)?;
+        lookup_index.push(start.elapsed());
+        tips.sort();
+        indexed.sort();
+        if *tips != indexed || index_tips.get(oid).map(|t| t.len()) != Some(tips.len()) {
+            mismatched += 1;
+        }
+    }
+
+    Ok(Report {
+        per_object: ref_advertisement::measure(source.repo(), 1)?.total,
+        index: ref_advertisement::measure(target.repo(), 1)?.total,
+        enumerate_per_object,
+        enumerate_index,
+        lookup_per_object: Stats::from_samples(lookup_per_object),
+        lookup_index: Stats::from_samples(lookup_index),
+        index_updates: Stats::from_samples(update_times),
+        index_bytes,
+        mismatched,
+    })
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "ref per object: {} refs, {} byte advertisement",
+            self.per_object.refs, self.per_object.bytes
+        )?;
+        writeln!(
+            f,
+            "index per peer: {} refs, {} byte advertisement",
+            self.index.refs, self.index.bytes
+        )?;
+        writeln!(
+            f,
+            "enumerate: ref per object {:?}, index per peer {:?}",
+            self.enumerate_per_object, self.enumerate_index
+        )?;
+        if let (Some(per_object), Some(index)) = (&self.lookup_per_object, &self.lookup_index) {
+            writeln!(f, "lookup, ref per object: {}", per_object)?;
+            writeln!(f, "lookup, index per peer: {}", index)?;
+        }
+        if let Some(updates) = &self.index_updates {
+            writeln!(f, "index updates: {}", updates)?;
+            writeln!(
+                f,
+                "index objects written: {} bytes, {} bytes per update",
+                self.index_bytes,
+                self.index_bytes / updates.count as u64
+            )?;
+        }
+        if self.mismatched > 0 {
+            writeln!(
+                f,
+                "{} objects have different tips in the layouts",
+                self.mismatched
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+//! A benchmark which has a writer appending comments to a small set of objects while a number of
+//! readers retrieve those objects through the cache. Every write is acknowledged once
+//! `LiteMonorepo::add_comment` returns, a reader which then retrieves the object and doesn't see
+//! the comment has observed a stale cache entry.
+//!
+//! Before the writer starts the readers are run on their own so that the latency impact of
+//! concurrent cache invalidation can be seen by comparing the two phases.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+
+use super::bench::Stats;
+use super::downloaded_issue::DownloadedComment;
+use super::layout;
+use super::lite_monorepo::{error, LiteMonorepo};
+use super::GithubUserId;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Open(#[from] error::CreateOrOpen),
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error("not enough issues in the monorepo to benchmark")]
+    NoIssues,
+}
+
+pub struct Options {
+    pub readers: usize,
+    pub writes: usize,
+    /// The number of objects the writer appends to and the readers retrieve
+    pub hot_objects: usize,
+    pub seed: u64,
+}
+
+/// A read which did not see a comment which had already been acknowledged
+#[derive(Debug)]
+pub struct StaleRead {
+    pub object_id: cob::ObjectId,
+    pub acknowledged: usize,
+    pub observed: usize,
+}
+
+pub struct Report {
+    pub baseline_reads: Option<Stats>,
+    pub interleaved_reads: Option<Stats>,
+    pub writes: Option<Stats>,
+    pub stale_reads: Vec<StaleRead>,
+}
+
+/// The number of acknowledged comments on each hot object
+type Acknowledged = Arc<Mutex<HashMap<cob::ObjectId, usize>>>;
+
+pub fn run(root: &Path, options: &Options) -> Result<Report, Error> {
+    let monorepo = LiteMonorepo::create_or_open(root)?;
+    let hot: Vec<cob::ObjectId> = monorepo
+        .list_issue_ids()?
+        .into_iter()
+        .take(options.hot_objects)
+        .collect();
+    if hot.is_empty() {
+        return Err(Error::NoIssues);
+    }
+    let mut acknowledged = HashMap::new();
+    for id in &hot {
+        let comments = monorepo
+            .retrieve_issue(id, true)?
+            .map(|doc| comment_count(&doc))
+            .unwrap_or(0);
+        acknowledged.insert(*id, comments);
+    }
+    let acknowledged: Acknowledged = Arc::new(Mutex::new(acknowledged));
+    drop(monorepo);
+
+    // The baseline phase performs as many reads as the interleaved phase is likely to, roughly
+    let reads_per_reader = options.writes.max(1);
+    let baseline = read_phase(root, &hot, &acknowledged, options, || {
+        Some(reads_per_reader)
+    })?;
+
+    let writing = Arc::new(AtomicBool::new(true));
+    let writer = {
+        let root = root.to_path_buf();
+        let hot = hot.clone();
+        let acknowledged = acknowledged.clone();
+        let writing = writing.clone();
+        let writes = options.writes;
+        std::thread::spawn(move || {
+            let result = write_phase(root, hot, acknowledged, writes);
+            writing.store(false, Ordering::SeqCst);
+            result
+        })
+    };
+    let interleaved = read_phase(root, &hot, &acknowledged, options, || {
+        if writing.load(Ordering::SeqCst) {
+            None
+        } else {
+            Some(0)
+        }
+    })?;
+    let write_samples = writer.join().expect("writer thread panicked")?;
+
+    Ok(Report {
+        baseline_reads: Stats::from_samples(baseline.samples),
+        interleaved_reads: Stats::from_samples(interleaved.samples),
+        writes: Stats::from_samples(write_samples),
+        stale_reads: interleaved.stale,
+    })
+}
+
+fn write_phase(
+    root: PathBuf,
+    hot: Vec<cob::ObjectId>,
+    acknowledged: Acknowledged,
+    writes: usize,
+) -> Result<Vec<Duration>, Error> {
+    let mut monorepo = LiteMonorepo::create_or_open(root)?;
+    let mut samples = Vec::with_capacity(writes);
+    for i in 0..writes {
+        let object_id = hot[i % hot.len()];
+        let comment = DownloadedComment {
+            id: format!("interleaved-bench-{}", i),
+            author_id: Some(GithubUserId("interleaved-bench-writer".to_string())),
+            body: format!("comment {} from the interleaved benchmark", i),
+            created_at: chrono::Utc::now(),
+            updated_at: None,
+        };
+        let start = Instant::now();
+        monorepo.add_comment(&object_id, &comment)?;
+        samples.push(start.elapsed());
+        *acknowledged.lock().unwrap().entry(object_id).or_default() += 1;
+    }
+    Ok(samples)
+}
+
+struct ReadResults {
+    samples: Vec<Duration>,
+    stale: Vec<StaleRead>,
+}
+
+/// Run `options.readers` reader threads. Each reader keeps going until `limit` returns
+/// `Some(n)` and it has done at least `n` reads.
+fn read_phase<L>(
+    root: &Path,
+    hot: &[cob::ObjectId],
+    acknowledged: &Acknowledged,
+    options: &Options,
+    limit: L,
+) -> Result<ReadResults, Error>
+where
+    L: Fn() -> Option<usize> + Sync,
+{
+    let results: Vec<Result<ReadResults, Error>> = crossbeam_utils::thread::scope(|s| {
+        let handles: Vec<_> = (0..options.readers)
+            .map(|reader| {
+                let limit = &limit;
+                s.spawn(move |_| {
+                    let monorepo = LiteMonorepo::create_or_open(root)?;
+                    let mut rng = rand::rngs::StdRng::seed_from_u64(options.seed + reader as u64);
+                    let mut results = ReadResults {
+                        samples: Vec::new(),
+                        stale: Vec::new(),
+                    };
+                    loop {
+                        if let Some(n) = limit() {
+                            if results.samples.len() >= n {
+                                break;
+                            }
+                        }
+                        let object_id = hot[rng.gen_range(0..hot.len())];
+                        let expected = acknowledged.lock().unwrap()[&object_id];
+                        let start = Instant::now();
+                        let doc = monorepo.retrieve_issue(&object_id, true)?;
+                        results.samples.push(start.elapsed());
+                        let observed = doc.map(|d| comment_count(&d)).unwrap_or(0);
+                        if observed < expected {
+                            results.stale.push(StaleRead {
+                                object_id,
+                                acknowledged: expected,
+                                observed,
+                            });
+                        }
+                    }
+                    Ok(results)
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|h| h.join().expect("reader thread panicked"))
+            .collect()
+    })
+    .expect("reader thread panicked");
+
+    let mut combined = ReadResults {
+        samples: Vec::new(),
+        stale: Vec::new(),
+    };
+    for result in results {
+        let result = result?;
+        combined.samples.extend(result.samples);
+        combined.stale.extend(result.stale);
+    }
+    Ok(combined)
+}
+
+fn comment_count(doc: &serde_json::Value) -> usize {
+    layout::comments(doc).len()
+}
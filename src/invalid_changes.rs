@@ -0,0 +1,180 @@
+//! Deliberately invalid changes. With `--inject-invalid <ratio>` roughly that fraction of the
+//! comments of an import are each followed by a change, made by the same peer, which leaves the
+//! document violating the schema, either by giving the issue number a number rather than a
+//! string or by removing the author, which is required. Which comments are chosen, and which
+//! violation follows them, depends only on the ID of the comment, so the same comments are
+//! chosen from one import to the next.
+//!
+//! cob should leave such changes out when it evaluates an object, so each also sets a field of
+//! its own, a marker, which doesn't appear in the document unless the change was accepted. The
+//! changes are recorded in `invalid_changes.jsonl` in the root of the monorepo, and
+//! `verify-rejections` retrieves every object they were made to and checks that no marker made
+//! it into its document.
+//!
+//! The changes are made by a new automerge actor each and the import carries on from the object
+//! as it was before the change, so that nothing imported later depends on them.
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::lite_monorepo::{error, LiteMonorepo};
+
+pub const INVALID_CHANGES: &str = "invalid_changes.jsonl";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error("invalid object ID {0}")]
+    ObjectId(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Violation {
+    /// `github_issue_number` set to a number
+    WrongType,
+    /// `author_urn` removed
+    MissingField,
+}
+
+/// A change made to violate the schema
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Injected {
+    pub issue: u64,
+    pub object_id: String,
+    pub peer: String,
+    /// The comment the change follows
+    pub comment_id: String,
+    pub violation: Violation,
+    /// The field the change sets, which only appears in the document if it was accepted
+    pub marker: String,
+    /// The commit of the change, or `None` if cob refused to write it
+    pub commit: Option<String>,
+}
+
+/// The result of checking each injected change was left out of its object
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checked: usize,
+    pub rejected: usize,
+    /// Changes cob refused to write in the first place
+    pub not_written: usize,
+    /// Changes whose marker is in the document of their object
+    pub accepted: Vec<Injected>,
+    /// Changes whose object couldn't be retrieved
+    pub missing: Vec<Injected>,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.accepted.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// The violation to follow the comment `comment_id` with, if it is one of the `ratio` of comments
+/// chosen
+pub fn chosen(comment_id: &str, ratio: f64) -> Option<Violation> {
+    let digest = Sha256::digest(comment_id.as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    let roll = u64::from_be_bytes(bytes) as f64 / u64::MAX as f64;
+    if roll >= ratio {
+        return None;
+    }
+    if digest[8] % 2 == 0 {
+        Some(Violation::WrongType)
+    } else {
+        Some(Violation::MissingField)
+    }
+}
+
+/// The field the change following the comment `comment_id` sets
+pub fn marker(comment_id: &str) -> String {
+    format!("injected_invalid_{}", comment_id)
+}
+
+pub fn record<P: AsRef<Path>>(path: P, injected: &Injected) -> Result<(), std::io::Error> {
+    let mut line = serde_json::to_vec(injected)?;
+    line.push(b'\n');
+    crate::fs::append_line(path, &line)
+}
+
+/// The changes recorded in the log at `path`, in the order they were made
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Injected>, std::io::Error> {
+    if !crate::fs::exists(&path)? {
+        return Ok(Vec::new());
+    }
+    let mut injected = Vec::new();
+    for line in crate::fs::read_to_string(path)?.lines() {
+        if let Ok(change) = serde_json::from_str(line) {
+            injected.push(change);
+        }
+    }
+    Ok(injected)
+}
+
+/// Check that every change recorded in `monorepo` was left out of its object
+pub fn verify(monorepo: &LiteMonorepo) -> Result<Report, Error> {
+    let mut report = Report::default();
+    for injected in load(monorepo.root().join(INVALID_CHANGES))? {
+        report.checked += 1;
+        if injected.commit.is_none() {
+            report.not_written += 1;
+            continue;
+        }
+        let object_id = injected
+            .object_id
+            .parse()
+            .map_err(|_| Error::ObjectId(injected.object_id.clone()))?;
+        match monorepo.retrieve_issue(&object_id, false)? {
+            Some(document) if document.get(&injected.marker).is_some() => {
+                report.accepted.push(injected)
+            }
+            Some(_) => report.rejected += 1,
+            None => report.missing.push(injected),
+        }
+    }
+    Ok(report)
+}
+
+impl std::fmt::Display for Violation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Violation::WrongType => write!(f, "wrong-type"),
+            Violation::MissingField => write!(f, "missing-field"),
+        }
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} invalid changes: {} rejected, {} not written, {} accepted, {} objects missing",
+            self.checked,
+            self.rejected,
+            self.not_written,
+            self.accepted.len(),
+            self.missing.len()
+        )?;
+        for injected in &self.accepted {
+            writeln!(
+                f,
+                "accepted: {} change {} to #{} ({}) by {}",
+                injected.violation,
+                injected.commit.as_deref().unwrap_or("?"),
+                injected.issue,
+                injected.object_id,
+                injected.peer
+            )?;
+        }
+        for injected in &self.missing {
+            writeln!(f, "missing: #{} ({})", injected.issue, injected.object_id)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,119 @@
+//! The object each GitHub issue was imported as, and how far importing it got. The index is kept
+//! in `issue_index.jsonl` in the root of the monorepo, with a line appended whenever an issue is
+//! created or updated during an import, so that an import which crashes can be resumed without
+//! importing anything twice. The line is appended as soon as the object or change has been
+//! written, before it is recorded anywhere else, so an import which fails in between may leave
+//! the change out of the audit and import logs but never writes it again.
+//!
+//! The comments and events of an issue are applied in a fixed order (see
+//! `LiteMonorepo::import_issue`), so how far an import got is recorded as the number of them
+//! which have been applied. An incremental import of an issue which has gained comments and
+//! events since tells new comments by their ID, but events leave no ID in the document, so the
+//! time of the latest update applied is recorded too.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+pub const ISSUE_INDEX: &str = "issue_index.jsonl";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub number: u64,
+    pub object_id: String,
+    /// The number of comments and events which have been applied
+    pub applied: usize,
+    /// Whether every comment and event has been applied
+    pub complete: bool,
+    /// When the latest comment or event which has been applied was made. Missing from entries
+    /// recorded before this was.
+    #[serde(default)]
+    pub applied_until: Option<chrono::DateTime<chrono::Utc>>,
+}
+
+impl Entry {
+    pub fn object_id(&self) -> Option<cob::ObjectId> {
+        cob::ObjectId::from_str(&self.object_id).ok()
+    }
+}
+
+pub struct IssueIndex {
+    path: PathBuf,
+    entries: HashMap<u64, Entry>,
+}
+
+impl IssueIndex {
+    /// Load the index at `path`, compacting it if it's mostly made up of superseded entries
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<IssueIndex, Error> {
+        let (index, lines) = IssueIndex::read_lines(path)?;
+        if lines > 2 * index.entries.len() {
+            index.compact()?;
+        }
+        Ok(index)
+    }
+
+    /// Load the index at `path` without ever writing to it
+    pub fn read<P: AsRef<Path>>(path: P) -> Result<IssueIndex, Error> {
+        Ok(IssueIndex::read_lines(path)?.0)
+    }
+
+    fn read_lines<P: AsRef<Path>>(path: P) -> Result<(IssueIndex, usize), Error> {
+        let path = path.as_ref().to_path_buf();
+        let mut entries = HashMap::new();
+        let mut lines = 0;
+        if crate::fs::exists(&path)? {
+            for line in crate::fs::read_to_string(&path)?.lines() {
+                // The last line is incomplete if we crashed whilst writing it
+                if let Ok(entry) = serde_json::from_str::<Entry>(line) {
+                    lines += 1;
+                    entries.insert(entry.number, entry);
+                }
+            }
+        }
+        Ok((IssueIndex { path, entries }, lines))
+    }
+
+    fn compact(&self) -> Result<(), Error> {
+        let mut numbers: Vec<&u64> = self.entries.keys().collect();
+        numbers.sort();
+        let mut contents = Vec::new();
+        for number in numbers {
+            serde_json::to_writer(&mut contents, &self.entries[number])?;
+            contents.push(b'\n');
+        }
+        crate::fs::write_atomic(&self.path, contents)?;
+        Ok(())
+    }
+
+    pub fn get(&self, number: u64) -> Option<&Entry> {
+        self.entries.get(&number)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = &Entry> {
+        self.entries.values()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Record `entry`, replacing any previous entry for the same issue
+    pub fn record(&mut self, entry: Entry) -> Result<(), Error> {
+        let mut line = serde_json::to_vec(&entry)?;
+        line.push(b'\n');
+        crate::fs::append_line(&self.path, &line)?;
+        self.entries.insert(entry.number, entry);
+        Ok(())
+    }
+}
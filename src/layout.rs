@@ -0,0 +1,458 @@
+//! Alternative ways of laying out the comments of an issue in the automerge document. A layout is
+//! made up of
+//!
+//! * the container the comments are stored in: a list, or a map keyed by the github ID of the
+//!   comment
+//! * the type of comment bodies: automerge text, or plain strings
+//! * whether each comment is a map in a single `comments` container (nested), or each field of
+//!   the comments has its own container at the root of the document (flat)
+//!
+//! The default layout, `list-text-nested`, is the one issues have always been imported with. Other
+//! layouts are recorded in the `layout` field of the document so that readers know how to find the
+//! comments, which they should do using [`comments`].
+use std::{collections::HashMap, str::FromStr, time::Instant};
+
+use automerge::{LocalChange, Path};
+use thiserror::Error;
+
+use crate::downloaded_issue::DownloadedIssue;
+
+#[derive(Debug, Error)]
+#[error("invalid layout {0}, expected <list|map>-<text|string>-<nested|flat>")]
+pub struct ParseError(String);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Container {
+    List,
+    Map,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Body {
+    Text,
+    String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Nesting {
+    Nested,
+    Flat,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    pub container: Container,
+    pub body: Body,
+    pub nesting: Nesting,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Layout {
+            container: Container::List,
+            body: Body::Text,
+            nesting: Nesting::Nested,
+        }
+    }
+}
+
+impl Layout {
+    /// Every combination of container, body and nesting
+    pub fn all() -> Vec<Layout> {
+        let mut layouts = Vec::new();
+        for container in [Container::List, Container::Map].iter().copied() {
+            for body in [Body::Text, Body::String].iter().copied() {
+                for nesting in [Nesting::Nested, Nesting::Flat].iter().copied() {
+                    layouts.push(Layout {
+                        container,
+                        body,
+                        nesting,
+                    });
+                }
+            }
+        }
+        layouts
+    }
+
+    /// The layout of `doc`, which is the default layout for documents which don't record one
+    pub fn of(doc: &serde_json::Value) -> Layout {
+        doc.get("layout")
+            .and_then(|l| l.as_str())
+            .and_then(|l| l.parse().ok())
+            .unwrap_or_default()
+    }
+}
+
+impl std::fmt::Display for Layout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let container = match self.container {
+            Container::List => "list",
+            Container::Map => "map",
+        };
+        let body = match self.body {
+            Body::Text => "text",
+            Body::String => "string",
+        };
+        let nesting = match self.nesting {
+            Nesting::Nested => "nested",
+            Nesting::Flat => "flat",
+        };
+        write!(f, "{}-{}-{}", container, body, nesting)
+    }
+}
+
+impl FromStr for Layout {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let err = || ParseError(s.to_string());
+        let mut parts = s.split('-');
+        let container = match parts.next() {
+            Some("list") => Container::List,
+            Some("map") => Container::Map,
+            _ => return Err(err()),
+        };
+        let body = match parts.next() {
+            Some("text") => Body::Text,
+            Some("string") => Body::String,
+            _ => return Err(err()),
+        };
+        let nesting = match parts.next() {
+            Some("nested") => Nesting::Nested,
+            Some("flat") => Nesting::Flat,
+            _ => return Err(err()),
+        };
+        if parts.next().is_some() {
+            return Err(err());
+        }
+        Ok(Layout {
+            container,
+            body,
+            nesting,
+        })
+    }
+}
+
+/// The root container of the comments of nested layouts
+const COMMENTS: &str = "comments";
+
+/// The fields of a comment and, for flat layouts, the root container each is stored in
+const FIELDS: &[(&str, &str)] = &[
+    ("commenter_urn", "comment_authors"),
+    ("comment", "comment_bodies"),
+    ("github_id", "comment_github_ids"),
+    ("created_at", "comment_created_at"),
+];
+
+/// Fields which a comment only has once it has been moderated, see `crate::moderation`, and the
+/// root container each would be stored in
+const MODERATED_FIELDS: &[(&str, &str)] = &[("hidden", "comment_hidden")];
+
+fn container_of(field: &str) -> &'static str {
+    FIELDS
+        .iter()
+        .chain(MODERATED_FIELDS)
+        .find(|(f, _)| *f == field)
+        .map(|(_, container)| *container)
+        .expect("not a field of comments")
+}
+
+/// A comment to add to a document
+pub struct NewComment<'a> {
+    pub body: &'a str,
+    pub commenter_urn: String,
+    pub github_id: &'a str,
+    pub created_at: String,
+}
+
+/// A comment as read back from a document
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    pub body: Option<String>,
+    pub commenter_urn: Option<String>,
+    pub github_id: Option<String>,
+    pub created_at: Option<String>,
+    pub hidden: bool,
+}
+
+fn empty(container: Container) -> automerge::Value {
+    match container {
+        Container::List => automerge::Value::List(Vec::new()),
+        Container::Map => automerge::Value::Map(HashMap::new()),
+    }
+}
+
+fn string(s: &str) -> automerge::Value {
+    automerge::Value::Primitive(automerge::Primitive::Str(s.into()))
+}
+
+fn list_len(d: &mut dyn automerge::MutableDocument, path: &Path) -> usize {
+    match d.value_at_path(path) {
+        Some(automerge::Value::List(elems)) => elems.len(),
+        _ => panic!("comment containers of list layouts must be lists"),
+    }
+}
+
+/// The value of a comment body in `layout`
+pub fn body(layout: Layout, body: &str) -> automerge::Value {
+    match layout.body {
+        Body::Text => crate::lite_monorepo::to_text(body),
+        Body::String => string(body),
+    }
+}
+
+/// The path of the comment at `index` in a nested layout with a list container, or of the one
+/// with `github_id` in a nested layout with a map container
+pub fn comment_path(layout: Layout, index: u32, github_id: &str) -> Path {
+    let comments = Path::root().key(COMMENTS);
+    match layout.container {
+        Container::List => comments.index(index),
+        Container::Map => comments.key(github_id),
+    }
+}
+
+/// The path of `field` of the comment at `index` in a layout with list containers, or of the one
+/// with `github_id` in a layout with map containers
+pub fn field_path(layout: Layout, index: u32, github_id: &str, field: &str) -> Path {
+    match layout.nesting {
+        Nesting::Nested => comment_path(layout, index, github_id).key(field),
+        Nesting::Flat => {
+            let container = Path::root().key(container_of(field));
+            match layout.container {
+                Container::List => container.index(index),
+                Container::Map => container.key(github_id),
+            }
+        }
+    }
+}
+
+/// Create the empty comment containers of `layout` in a new document
+pub fn init(
+    d: &mut dyn automerge::MutableDocument,
+    layout: Layout,
+) -> Result<(), automerge::InvalidChangeRequest> {
+    if layout != Layout::default() {
+        d.add_change(LocalChange::set(
+            Path::root().key("layout"),
+            string(&layout.to_string()),
+        ))?;
+    }
+    match layout.nesting {
+        Nesting::Nested => {
+            d.add_change(LocalChange::set(
+                Path::root().key(COMMENTS),
+                empty(layout.container),
+            ))?;
+        }
+        Nesting::Flat => {
+            for (_, container) in FIELDS {
+                d.add_change(LocalChange::set(
+                    Path::root().key(*container),
+                    empty(layout.container),
+                ))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Add `comment` to the end of the comments of a document with `layout`
+pub fn add(
+    d: &mut dyn automerge::MutableDocument,
+    layout: Layout,
+    comment: &NewComment<'_>,
+) -> Result<(), automerge::InvalidChangeRequest> {
+    let values: HashMap<&str, automerge::Value> = vec![
+        ("commenter_urn", string(&comment.commenter_urn)),
+        ("comment", body(layout, comment.body)),
+        ("github_id", string(comment.github_id)),
+        ("created_at", string(&comment.created_at)),
+    ]
+    .into_iter()
+    .collect();
+
+    match layout.nesting {
+        Nesting::Nested => {
+            let comments = Path::root().key(COMMENTS);
+            let comment_path = match layout.container {
+                Container::List => {
+                    let path = comments.clone().index(list_len(d, &comments) as u32);
+                    d.add_change(LocalChange::insert(
+                        path.clone(),
+                        automerge::Value::Map(HashMap::new()),
+                    ))?;
+                    path
+                }
+                Container::Map => {
+                    let path = comments.key(comment.github_id);
+                    d.add_change(LocalChange::set(
+                        path.clone(),
+                        automerge::Value::Map(HashMap::new()),
+                    ))?;
+                    path
+                }
+            };
+            for (field, _) in FIELDS {
+                d.add_change(LocalChange::set(
+                    comment_path.clone().key(*field),
+                    values[field].clone(),
+                ))?;
+            }
+        }
+        Nesting::Flat => {
+            for (field, container) in FIELDS {
+                let container_path = Path::root().key(*container);
+                match layout.container {
+                    Container::List => {
+                        let index = list_len(d, &container_path) as u32;
+                        d.add_change(LocalChange::insert(
+                            container_path.index(index),
+                            values[field].clone(),
+                        ))?;
+                    }
+                    Container::Map => {
+                        d.add_change(LocalChange::set(
+                            container_path.key(comment.github_id),
+                            values[field].clone(),
+                        ))?;
+                    }
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn comment_from<'a>(get: impl Fn(&str) -> Option<&'a serde_json::Value>) -> Comment {
+    let as_string = |field| get(field).and_then(|v| v.as_str().map(String::from));
+    Comment {
+        body: as_string("comment"),
+        commenter_urn: as_string("commenter_urn"),
+        github_id: as_string("github_id"),
+        created_at: as_string("created_at"),
+        hidden: get("hidden").and_then(|v| v.as_bool()).unwrap_or(false),
+    }
+}
+
+/// The comments of a materialized document. Comments in lists are in list order, comments in maps
+/// are ordered by creation time.
+pub fn comments(doc: &serde_json::Value) -> Vec<Comment> {
+    let layout = Layout::of(doc);
+    let mut comments: Vec<Comment> = match layout.nesting {
+        Nesting::Nested => {
+            let entries: Vec<&serde_json::Value> = match doc.get(COMMENTS) {
+                Some(serde_json::Value::Array(c)) => c.iter().collect(),
+                Some(serde_json::Value::Object(c)) => c.values().collect(),
+                _ => Vec::new(),
+            };
+            entries
+                .into_iter()
+                .map(|c| comment_from(|field| c.get(field)))
+                .collect()
+        }
+        Nesting::Flat => {
+            let container = |field: &str| doc.get(container_of(field));
+            match container("comment") {
+                Some(serde_json::Value::Array(bodies)) => (0..bodies.len())
+                    .map(|i| comment_from(|field| container(field)?.get(i)))
+                    .collect(),
+                Some(serde_json::Value::Object(bodies)) => bodies
+                    .keys()
+                    .map(|k| comment_from(|field| container(field)?.get(k)))
+                    .collect(),
+                _ => Vec::new(),
+            }
+        }
+    };
+    if layout.container == Container::Map {
+        comments.sort_by(|a, b| a.created_at.cmp(&b.created_at));
+    }
+    comments
+}
+
+/// How a layout fared in [`compare`]
+pub struct LayoutReport {
+    pub layout: Layout,
+    /// The total size of the histories of every issue
+    pub history_bytes: usize,
+    /// The mean time taken to materialize an issue
+    pub materialize_time: std::time::Duration,
+    /// The number of concurrent comment pairs where both comments survived the merge intact
+    pub merges_intact: usize,
+    /// The number of concurrent comment pairs where a comment was lost or its fields were mixed up
+    /// with those of another comment
+    pub merges_broken: usize,
+}
+
+/// Build the histories of `issues` in every layout, without involving git, and compare them.
+/// `concurrent` issues additionally have a pair of comments added concurrently by two peers to
+/// see how each layout merges them.
+pub fn compare(
+    issues: &[DownloadedIssue],
+    author_urn: &link_identities::git::Urn,
+    concurrent: usize,
+) -> Vec<LayoutReport> {
+    use crate::lite_monorepo::{add_comment_change, concat, init_issue_change, materialize, Clock};
+
+    let mut reports = Vec::new();
+    for layout in Layout::all() {
+        let mut history_bytes = 0;
+        let mut materialize_time = std::time::Duration::default();
+        let mut merges_intact = 0;
+        let mut merges_broken = 0;
+        for (i, issue) in issues.iter().enumerate() {
+            let mut history =
+                init_issue_change(issue, author_urn, false, layout, Clock::default(), None);
+            for comment in &issue.comments {
+                let change =
+                    add_comment_change(comment, author_urn, &history, Clock::default(), None);
+                history = concat(&[&history, &change]);
+            }
+            history_bytes += history.as_ref().len();
+            let start = Instant::now();
+            let doc = materialize(&history);
+            materialize_time += start.elapsed();
+
+            if i < concurrent {
+                let first = concurrent_comment(issue, "first");
+                let second = concurrent_comment(issue, "second");
+                let a = add_comment_change(&first, author_urn, &history, Clock::default(), None);
+                let b = add_comment_change(&second, author_urn, &history, Clock::default(), None);
+                let merged = materialize(&concat(&[&history, &a, &b]));
+                let merged_comments = comments(&merged);
+                let intact = [&first, &second].iter().all(|c| {
+                    merged_comments.iter().any(|m| {
+                        m.github_id.as_deref() == Some(c.id.as_str())
+                            && m.body.as_deref() == Some(c.body.as_str())
+                    })
+                });
+                if intact && merged_comments.len() == comments(&doc).len() + 2 {
+                    merges_intact += 1;
+                } else {
+                    merges_broken += 1;
+                }
+            }
+        }
+        reports.push(LayoutReport {
+            layout,
+            history_bytes,
+            materialize_time: materialize_time / issues.len().max(1) as u32,
+            merges_intact,
+            merges_broken,
+        });
+    }
+    reports
+}
+
+fn concurrent_comment(
+    issue: &DownloadedIssue,
+    which: &str,
+) -> crate::downloaded_issue::DownloadedComment {
+    crate::downloaded_issue::DownloadedComment {
+        id: format!("concurrent-{}-{}", issue.number, which),
+        author_id: None,
+        body: format!("the {} of two concurrent comments", which),
+        created_at: chrono::Utc::now(),
+        updated_at: None,
+    }
+}
@@ -0,0 +1,203 @@
+//! The machinery behind the command line tool, for test harnesses which want to drive it
+//! directly rather than shelling out to the binary. The stable parts are:
+//!
+//! * `graphql`, which streams the issues of a GitHub repository
+//! * `download`, whose `Storage` keeps downloaded issues on disk
+//! * `lite_monorepo`, whose `LiteMonorepo` imports issues into a git repository as collaborative
+//!   objects and retrieves them
+//! * `import`, which imports everything in an `IssueStorage` into a `LiteMonorepo`
+//!
+//! The remaining modules are public so that the binary can use them, but they follow the needs of
+//! the commands and may change with them.
+#![feature(async_closure)]
+#![feature(path_try_exists)]
+#![feature(backtrace)]
+
+#[doc(hidden)]
+#[macro_use]
+pub mod output;
+
+pub mod cache;
+pub mod download;
+pub mod downloaded_issue;
+pub mod graphql;
+pub mod import;
+pub mod lite_monorepo;
+pub mod repo_name;
+
+#[doc(hidden)]
+pub mod access_pattern;
+#[doc(hidden)]
+pub mod acl;
+#[doc(hidden)]
+pub mod actor_ids;
+#[doc(hidden)]
+pub mod anomalies;
+#[doc(hidden)]
+pub mod archive;
+#[doc(hidden)]
+pub mod assertions;
+#[doc(hidden)]
+pub mod audit;
+#[doc(hidden)]
+pub mod authorship;
+#[doc(hidden)]
+pub mod batching;
+#[doc(hidden)]
+pub mod bench;
+#[doc(hidden)]
+pub mod bisect_perf;
+#[doc(hidden)]
+pub mod blame;
+#[doc(hidden)]
+pub mod bots;
+#[doc(hidden)]
+pub mod cache_backends;
+#[doc(hidden)]
+pub mod cache_stats;
+#[doc(hidden)]
+pub mod chaos;
+#[doc(hidden)]
+pub mod clock_skew;
+#[doc(hidden)]
+pub mod cob_api;
+#[doc(hidden)]
+pub mod delegate_only;
+#[doc(hidden)]
+pub mod deletion;
+#[doc(hidden)]
+pub mod determinism;
+#[doc(hidden)]
+pub mod devices;
+#[doc(hidden)]
+pub mod disk_full;
+#[doc(hidden)]
+pub mod download_stats;
+#[doc(hidden)]
+pub mod dry_run;
+#[doc(hidden)]
+pub mod duplicate_delivery;
+#[doc(hidden)]
+pub mod estimate;
+#[doc(hidden)]
+pub mod exec;
+#[doc(hidden)]
+pub mod export;
+#[doc(hidden)]
+pub mod failure_taxonomy;
+#[doc(hidden)]
+pub mod fs;
+#[doc(hidden)]
+pub mod fuzz;
+#[doc(hidden)]
+pub mod history_log;
+#[doc(hidden)]
+pub mod identity_pins;
+#[doc(hidden)]
+pub mod import_log;
+#[doc(hidden)]
+pub mod index_refs;
+#[doc(hidden)]
+pub mod interleaved;
+#[doc(hidden)]
+pub mod invalid_changes;
+#[doc(hidden)]
+pub mod issue_index;
+#[doc(hidden)]
+pub mod layout;
+#[doc(hidden)]
+pub mod maintain;
+#[doc(hidden)]
+pub mod memory_refs;
+#[doc(hidden)]
+pub mod migration_archive;
+#[doc(hidden)]
+pub mod minimize;
+#[doc(hidden)]
+pub mod moderation;
+#[doc(hidden)]
+pub mod monorepo_config;
+#[doc(hidden)]
+pub mod object_scaling;
+#[doc(hidden)]
+pub mod object_store;
+#[doc(hidden)]
+pub mod parallel;
+#[doc(hidden)]
+pub mod peer_assignments;
+#[doc(hidden)]
+pub mod peer_identities;
+#[doc(hidden)]
+pub mod peer_info;
+#[doc(hidden)]
+pub mod peer_refs_storage;
+#[doc(hidden)]
+pub mod peer_scaling;
+#[doc(hidden)]
+pub mod peers;
+#[doc(hidden)]
+pub mod profiles;
+#[doc(hidden)]
+pub mod project_info;
+#[doc(hidden)]
+pub mod projects;
+#[doc(hidden)]
+pub mod ref_advertisement;
+#[doc(hidden)]
+pub mod ref_enumeration;
+#[doc(hidden)]
+pub mod render;
+#[doc(hidden)]
+pub mod replay;
+#[doc(hidden)]
+pub mod replication;
+#[doc(hidden)]
+pub mod replication_simulation;
+#[doc(hidden)]
+pub mod repo_stats;
+#[doc(hidden)]
+pub mod repro;
+#[doc(hidden)]
+pub mod results;
+#[doc(hidden)]
+pub mod retention;
+#[doc(hidden)]
+pub mod retry;
+#[doc(hidden)]
+pub mod runs;
+#[doc(hidden)]
+pub mod scenario;
+#[doc(hidden)]
+pub mod schema_cost;
+#[doc(hidden)]
+pub mod schema_strictness;
+#[doc(hidden)]
+pub mod search;
+#[doc(hidden)]
+pub mod serve;
+#[doc(hidden)]
+pub mod signed_refs;
+#[doc(hidden)]
+pub mod snapshots;
+#[doc(hidden)]
+pub mod sqlite_storage;
+#[doc(hidden)]
+pub mod status;
+#[doc(hidden)]
+pub mod tampering;
+#[doc(hidden)]
+pub mod text_edits;
+#[doc(hidden)]
+pub mod tracking;
+#[doc(hidden)]
+pub mod verify;
+#[doc(hidden)]
+pub mod watchdog;
+#[doc(hidden)]
+pub mod window;
+
+#[derive(Debug, Clone, Hash, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct GithubUserId(pub String);
+
+/// The file in the storage root which records the issues whose import failed
+pub const FAILURE_LOG: &str = "failures.jsonl";
@@ -1,23 +1,56 @@
 use automerge::LocalChange;
+use cob::RefsStorage;
 use either::Either;
 use lazy_static::lazy_static;
 use link_identities::delegation::Indirect;
-use std::str::FromStr;
-use std::{collections::HashMap, path::PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    path::PathBuf,
+};
+
+use chrono::{DateTime, Utc};
 
 use link_identities::{
     git::Urn,
     payload::{Project as ProjectSubject, ProjectPayload},
-    Identities, Project,
+    Identities, Person, Project,
 };
 
-use crate::downloaded_issue::DownloadedComment;
+use crate::actor_ids::{self, ActorIds};
+use crate::anomalies::Detector;
+use crate::archive::ColdStore;
+use crate::audit::{self, AuditLog};
+use crate::bots::Bots;
+use crate::cache::Cache;
+use crate::chaos::Chaos;
+use crate::clock_skew::ClockSkew;
+use crate::cob_api;
+use crate::delegate_only;
+use crate::devices::{self, Devices};
+use crate::downloaded_issue::{DownloadedComment, DownloadedEvent, EventKind};
+use crate::import_log::{self, ImportLog};
+use crate::invalid_changes::{self, Violation};
+use crate::issue_index::{self, IssueIndex};
+use crate::layout::{self, Layout};
+use crate::monorepo_config::{self, Config};
+use crate::profiles::Profiles;
+use crate::projects::{self, Projects, DEFAULT_PROJECT};
+use crate::tampering::{self, Tampering};
+use crate::text_edits::TextEdit;
+use crate::watchdog::{Beat, Heartbeat};
+use crate::window;
+use crate::GithubUserId;
 
 use super::downloaded_issue::DownloadedIssue;
 use super::peer_assignments::PeerAssignments;
-use super::peer_identities::PeerIdentities;
-use super::peer_refs_storage::PeerRefsStorage;
+use super::peer_identities::{self, PeerIdentities};
+use super::peer_refs_storage::{PeerRefsStorage, RefLayout};
 use super::peers::Peers;
+use super::signed_refs;
+use super::sqlite_storage::MonorepoFiles;
+use super::tracking::{Error as TrackingError, Tracking};
 
 lazy_static! {
     static ref SCHEMA: serde_json::Value = {
@@ -26,21 +59,51 @@ lazy_static! {
         jsonschema::JSONSchema::compile(&as_json).unwrap();
         as_json
     };
-    static ref TYPENAME: cob::TypeName =
-        cob::TypeName::from_str("xyz.radicle.githubissue").unwrap();
+    static ref COMPILED_SCHEMA: jsonschema::JSONSchema<'static> =
+        jsonschema::JSONSchema::compile(&SCHEMA).unwrap();
+}
+
+/// The type name of the issue type, which objects are created as unless the monorepo is
+/// configured with another, see [`crate::monorepo_config`]
+pub const TYPENAME_STR: &str = "xyz.radicle.githubissue";
+
+/// The schema of the issue type, used unless the monorepo is configured with another
+pub fn schema() -> &'static serde_json::Value {
+    &SCHEMA
+}
+
+/// The schema compiled once, for validating documents ourselves. cob only accepts the schema as
+/// JSON and compiles it again each time it validates, see `schema_cost`.
+pub fn compiled_schema() -> &'static jsonschema::JSONSchema<'static> {
+    &COMPILED_SCHEMA
 }
 
-mod error {
+pub mod error {
     use thiserror::Error;
 
+    use super::super::archive::Error as ArchiveError;
+    use super::super::audit::Error as AuditError;
+    use super::super::cache::Error as CacheError;
+    use super::super::cob_api::{
+        CreateError as CobCreateError, RetrieveError as CobRetrieveError,
+        UpdateError as CobUpdateError,
+    };
+    use super::super::delegate_only::Error as DelegateOnlyError;
+    use super::super::devices::Error as DevicesError;
+    use super::super::import_log::Error as ImportLogError;
+    use super::super::issue_index::Error as IssueIndexError;
+    use super::super::monorepo_config::Error as ConfigError;
     use super::super::peer_assignments::Error as PeerAssignmentsError;
     use super::super::peer_identities::Error as PeerIdentitiesError;
     use super::super::peer_refs_storage::Error as PeerRefsError;
     use super::super::peers::Error as PeersError;
+    use super::super::projects::Error as ProjectsError;
+    use super::super::signed_refs::Error as SignedRefsError;
+    use super::super::tracking::Error as TrackingError;
     use link_identities::git::error::{Load as IdentityLoadError, Store as IdentityStoreError};
 
     #[derive(Debug, Error)]
-    pub(crate) enum CreateOrOpen {
+    pub enum CreateOrOpen {
         #[error(transparent)]
         Io(#[from] std::io::Error),
         #[error(transparent)]
@@ -57,10 +120,36 @@ mod error {
         IdentityLoad(#[from] IdentityLoadError),
         #[error(transparent)]
         IdentityStore(#[from] IdentityStoreError),
+        #[error(transparent)]
+        Cache(#[from] CacheError),
+        #[error(transparent)]
+        Tracking(#[from] TrackingError),
+        #[error(transparent)]
+        IssueIndex(#[from] IssueIndexError),
+        #[error(transparent)]
+        Config(#[from] ConfigError),
+        #[error(transparent)]
+        Devices(#[from] DevicesError),
+        #[error(transparent)]
+        Projects(#[from] ProjectsError),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Projects {
+        #[error(transparent)]
+        Registry(#[from] ProjectsError),
+        #[error(transparent)]
+        IdentityLoad(#[from] IdentityLoadError),
+        #[error(transparent)]
+        IdentityStore(#[from] IdentityStoreError),
+        #[error("no project called {0}")]
+        UnknownProject(String),
+        #[error("there is already a project called {0}")]
+        ProjectExists(String),
     }
 
     #[derive(Debug, Error)]
-    pub(crate) enum Import {
+    pub enum Import {
         #[error(transparent)]
         Io(#[from] std::io::Error),
         #[error(transparent)]
@@ -70,21 +159,128 @@ mod error {
         #[error(transparent)]
         PeerAssignments(#[from] PeerAssignmentsError),
         #[error(transparent)]
-        CobCreate(#[from] cob::error::Create<PeerRefsError>),
+        PeerIdentities(#[from] PeerIdentitiesError),
+        #[error(transparent)]
+        CobCreate(#[from] CobCreateError),
+        #[error(transparent)]
+        CobUpdate(#[from] CobUpdateError),
+        #[error(transparent)]
+        CobRetrieve(#[from] CobRetrieveError),
+        #[error("no object with ID {0}")]
+        MissingObject(cob::ObjectId),
+        #[error("no identity for peer {0}")]
+        UnknownPeer(link_crypto::PeerId),
+        #[error(transparent)]
+        IssueIndex(#[from] IssueIndexError),
+        #[error(transparent)]
+        PeerRefs(#[from] PeerRefsError),
+        #[error(transparent)]
+        ImportLog(#[from] ImportLogError),
+        #[error(transparent)]
+        Audit(#[from] AuditError),
+        #[error(transparent)]
+        Cache(#[from] CacheError),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum List {
+        #[error(transparent)]
+        CobRetrieve(#[from] CobRetrieveError),
+        #[error(transparent)]
+        Cache(#[from] CacheError),
+        #[error(transparent)]
+        PeerRefs(#[from] PeerRefsError),
+        #[error(transparent)]
+        DelegateOnly(#[from] DelegateOnlyError),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Reindex {
+        #[error(transparent)]
+        List(#[from] List),
+        #[error(transparent)]
+        IssueIndex(#[from] IssueIndexError),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Retrieve {
+        #[error(transparent)]
+        CobRetrieve(#[from] CobRetrieveError),
+        #[error(transparent)]
+        Cache(#[from] CacheError),
+        #[error(transparent)]
+        PeerRefs(#[from] PeerRefsError),
+        #[error(transparent)]
+        SignedRefs(#[from] SignedRefsError),
+        #[error(transparent)]
+        DelegateOnly(#[from] DelegateOnlyError),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Delete {
+        #[error(transparent)]
+        PeerRefs(#[from] PeerRefsError),
+    }
+
+    #[derive(Debug, Error)]
+    pub enum Pack {
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+        #[error("`git {command}` failed: {stderr}")]
+        GitCommand { command: String, stderr: String },
+    }
+
+    #[derive(Debug, Error)]
+    pub enum EditProject {
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+        #[error(transparent)]
+        Serde(#[from] serde_json::Error),
+        #[error(transparent)]
+        IdentityStore(#[from] IdentityStoreError),
         #[error(transparent)]
-        CobUpdate(#[from] cob::error::Update<PeerRefsError>),
+        Projects(#[from] ProjectsError),
+        #[error("none of the peers of the monorepo is a delegate of the project")]
+        NoSigner,
+        #[error("the project would have no delegates")]
+        NoDelegates,
     }
 
     #[derive(Debug, Error)]
-    pub(crate) enum List {
+    pub enum Bundle {
+        #[error(transparent)]
+        Io(#[from] std::io::Error),
+        #[error(transparent)]
+        Git(#[from] git2::Error),
         #[error(transparent)]
-        CobRetrieve(#[from] cob::error::Retrieve<PeerRefsError>),
+        PeerRefs(#[from] PeerRefsError),
+        #[error("`git {command}` failed: {stderr}")]
+        GitCommand { command: String, stderr: String },
     }
 
     #[derive(Debug, Error)]
-    pub(crate) enum Retrieve {
+    pub enum Archive {
+        #[error(transparent)]
+        ColdStore(#[from] ArchiveError),
         #[error(transparent)]
-        CobRetrieve(#[from] cob::error::Retrieve<PeerRefsError>),
+        Delete(#[from] Delete),
+    }
+}
+
+/// An issue as retrieved from the monorepo, along with the automerge history it was materialized
+/// from
+pub struct MaterializedIssue {
+    pub id: cob::ObjectId,
+    pub document: serde_json::Value,
+    pub history: Vec<u8>,
+}
+
+impl MaterializedIssue {
+    pub fn github_issue_number(&self) -> Option<u64> {
+        self.document
+            .get("github_issue_number")
+            .and_then(|n| n.as_str())
+            .and_then(|n| n.parse().ok())
     }
 }
 
@@ -104,295 +300,2477 @@ mod error {
 /// change for the initial issue creation and then a change for each comment. We use
 /// [`PeerRefsStorage]` to talk to `cob`, which saves refs at
 /// `refs/namespaces/<project urn>/refs/remotes/<peer URN>/cob/<typename>/<object ID>` which is
-/// essentially the same as the librad implementation.
+/// essentially the same as the librad implementation, or in the flat layout at
+/// `refs/namespaces/<project urn>/refs/cob/<typename>/<object ID>/<peer URN>`, see
+/// [`crate::peer_refs_storage`]. The identity of each peer is stored at
+/// `refs/namespaces/<project urn>/refs/remotes/<peer URN>/rad/self`, see
+/// [`crate::peer_identities`]. Peers can also sign their refs at
+/// `refs/namespaces/<project urn>/refs/remotes/<peer URN>/rad/signed_refs`, see
+/// [`crate::signed_refs`].
 ///
 /// The lite monorepo ends up looking like this on disk:
 ///
 /// ```
 /// ├── git <- the underlying storage
-/// ├── peer_identities <- a JSON file mapping peer IDs to the OID of their identity tree
 /// ├── peer_map <- A JSON file mapping github user IDs to peer IDs
 /// ├── peers  <- files containing secret keys for each peer ID (given by filename)
 /// │   ├── hyb1jukxajb5k1nf8mna4jpz1rdqsazybr3pm6tt5qacr66r64m9un
 /// │   ├── hybbnun8qz6znu71yfesn77tnjxggw1bgjc6x71fny9r1kofqykrja
 /// |   ...
+/// ├── cob_cache <- the `cob` cache
+/// ├── cache_access <- a JSON file recording when each cache entry was last used, see `crate::cache`
+/// ├── cache_stats <- a JSON file of how long retrievals with and without the cache took
+/// ├── cold_store <- documents and histories of archived issues, see `crate::archive`
+/// ├── tracking <- a JSON list of the peers the local peer tracks, see `crate::tracking`
+/// ├── audit.jsonl <- every object created or updated, see `crate::audit`
 /// └── project_oid <- The OID of the project identity tree
 /// ```
+///
+/// With `--single-file` everything but `git` is kept in the database of the downloads between
+/// runs, see [`MonorepoFiles`].
 pub struct LiteMonorepo {
     root: PathBuf,
     project: Project,
+    /// The name of `project` in the registry of projects, see `crate::projects`
+    project_name: String,
     peers: Peers,
     repo: git2::Repository,
-    peer_assignments: PeerAssignments,
+    /// Shared with the handles returned by [`LiteMonorepo::import_worker`]
+    peer_assignments: Arc<Mutex<PeerAssignments>>,
     peer_identities: PeerIdentities,
+    devices: Devices,
+    /// The device each user last made a change with
+    last_devices: HashMap<link_crypto::PeerId, link_crypto::PeerId>,
+    cache: Cache,
+    /// Where every create and update is recorded, see `crate::audit`
+    audit_log: AuditLog,
+    open_timings: OpenTimings,
+    /// How the refs of objects are laid out, from the monorepo's config
+    ref_layout: RefLayout,
+    /// The type name objects are created as, and the schema they are created with, from the
+    /// monorepo's config
+    typename: cob::TypeName,
+    schema: serde_json::Value,
+    /// The peers the local peer tracks, `None` if it tracks everyone
+    tracking: Option<Tracking>,
+    /// The settings workers on other threads are opened with too
+    settings: Settings,
+    /// The number of changes this `LiteMonorepo` has created
+    changes_created: u64,
+    /// The seed the keys of the peers were derived from, from which new actors are derived too
+    seed: Option<u64>,
+    /// Where to report the cob operation in progress, see `crate::watchdog`
+    heartbeat: Option<Heartbeat>,
+    /// The object each issue was imported as, shared like `peer_assignments`
+    issue_index: Arc<Mutex<IssueIndex>>,
+    /// The database the files of the monorepo are moved back into when it's dropped. Last, so
+    /// that it's dropped after the cache has saved its files.
+    files: Option<MonorepoFiles>,
+}
+
+/// The settings of a [`LiteMonorepo`] handle which aren't kept in the monorepo itself. They're
+/// held together so that [`LiteMonorepo::import_worker`] and [`LiteMonorepo::read_worker`] hand
+/// all of them to the handles they open, rather than each setting having to be copied across.
+#[derive(Clone, Default)]
+pub struct Settings {
+    /// When set, only the changes signed by these delegates are used to evaluate objects
+    delegate_only: Option<HashSet<link_crypto::PeerId>>,
+    /// Whether imported issues record which peer may change each field, see `crate::acl`
+    import_acl: bool,
+    /// How the comments of imported issues are laid out
+    import_layout: Layout,
+    /// Whether to check each peer's refs against its signed refs before retrieving an object
+    verify_signed_refs: bool,
+    clock_skew: Option<ClockSkew>,
+    /// Whether changes are made by a new automerge actor each or by an actor per peer
+    actor_ids: ActorIds,
+    /// Whether to skip or continue issues which the index says were imported before
+    resume_imports: bool,
+    /// Whether to add the new comments and events of issues which were completely imported before
+    incremental_imports: bool,
+    /// Where to record the changes each issue is imported as, if anywhere
+    import_log: Option<ImportLog>,
+    /// Whether to make consecutive comments by different peers concurrently
+    concurrent_comments: bool,
+    /// Write every this many changes of an object as a snapshot of the whole document
+    snapshot_every: Option<usize>,
+    /// Faults to inject while importing, see `crate::chaos`
+    chaos: Option<Arc<Chaos>>,
+    /// How to import the comments of bots, see `crate::bots`
+    bots: Option<Arc<Bots>>,
+    /// Import issues as they were just before this time, see `crate::window`
+    import_until: Option<DateTime<Utc>>,
+    /// The fraction of comments to follow with a change violating the schema, see
+    /// `crate::invalid_changes`
+    inject_invalid: Option<f64>,
+    /// The fraction of comments to follow with a change whose signature or author is forged, see
+    /// `crate::tampering`
+    tamper_signatures: Option<f64>,
+    /// Where to report the latency of each operation, see `crate::anomalies`
+    anomalies: Option<Arc<Detector>>,
+}
+
+/// See [`LiteMonorepo::import_worker`]
+pub struct ImportWorker {
+    root: PathBuf,
+    project: Project,
+    project_name: String,
+    peer_assignments: Arc<Mutex<PeerAssignments>>,
+    issue_index: Arc<Mutex<IssueIndex>>,
+    settings: Settings,
+    cache_max_size: Option<u64>,
+}
+
+impl ImportWorker {
+    pub fn open(self) -> Result<LiteMonorepo, error::CreateOrOpen> {
+        let mut monorepo = LiteMonorepo::create_or_open(&self.root)?;
+        monorepo.project = self.project;
+        monorepo.project_name = self.project_name;
+        monorepo.peer_assignments = self.peer_assignments;
+        monorepo.issue_index = self.issue_index;
+        monorepo.settings = self.settings;
+        monorepo.cache.set_max_size(self.cache_max_size)?;
+        Ok(monorepo)
+    }
+}
+
+/// See [`LiteMonorepo::read_worker`]
+#[derive(Clone)]
+pub struct ReadWorker {
+    root: PathBuf,
+    project: Project,
+    project_name: String,
+    settings: Settings,
+    cache_max_size: Option<u64>,
+}
+
+impl ReadWorker {
+    pub fn open(&self) -> Result<LiteMonorepo, error::CreateOrOpen> {
+        let mut monorepo = LiteMonorepo::create_or_open(&self.root)?;
+        monorepo.project = self.project.clone();
+        monorepo.project_name = self.project_name.clone();
+        monorepo.settings = self.settings.clone();
+        monorepo.cache.set_max_size(self.cache_max_size)?;
+        Ok(monorepo)
+    }
+}
+
+/// How long each step of `LiteMonorepo::create_or_open` took
+#[derive(Debug, Clone, Copy, Default)]
+pub struct OpenTimings {
+    pub peers: Duration,
+    pub repo: Duration,
+    pub peer_assignments: Duration,
+    pub peer_identities: Duration,
+    pub project: Duration,
+    pub cache: Duration,
+}
+
+impl OpenTimings {
+    pub fn total(&self) -> Duration {
+        self.peers
+            + self.repo
+            + self.peer_assignments
+            + self.peer_identities
+            + self.project
+            + self.cache
+    }
 }
 
 impl LiteMonorepo {
-    pub(crate) fn create_or_open<P: AsRef<std::path::Path>>(
+    pub fn create_or_open<P: AsRef<std::path::Path>>(
         root: P,
     ) -> Result<LiteMonorepo, error::CreateOrOpen> {
-        if !std::fs::try_exists(&root)? {
-            std::fs::create_dir_all(&root)?;
+        if !crate::fs::exists(&root)? {
+            crate::fs::create_dir_all(&root)?;
+        }
+        let mut timings = OpenTimings::default();
+        let mut start = Instant::now();
+        let mut lap = || {
+            let elapsed = start.elapsed();
+            start = Instant::now();
+            elapsed
+        };
+
+        let config = Config::load(&root)?;
+        let peers = Peers::create_or_read(&root.as_ref().join("peers"), config.peers, config.seed)?;
+        if !crate::fs::exists(root.as_ref().join(monorepo_config::CONFIG))? {
+            Config {
+                peers: peers.len(),
+                ..config.clone()
+            }
+            .save(&root)?;
         }
-        let peers = Peers::create_or_read(&root.as_ref().join("peers"))?;
+        timings.peers = lap();
+
         let repo_dir = &root.as_ref().join("git");
-        let repo = if !std::fs::try_exists(&repo_dir)? {
-            std::fs::create_dir_all(repo_dir)?;
+        let repo = if !crate::fs::exists(&repo_dir)? {
+            crate::fs::create_dir_all(repo_dir)?;
             git2::Repository::init_bare(repo_dir)?
         } else {
             git2::Repository::open_bare(repo_dir)?
         };
-        let peer_map_path = &root.as_ref().join("peer_map");
-        let peer_assignments = PeerAssignments::load(peer_map_path, peers.iter().map(|(p, _)| p))?;
+        timings.repo = lap();
 
-        let peer_identities_path = &root.as_ref().join("peer_identities");
-        let peer_identities = PeerIdentities::load(peer_identities_path, &repo, peers.iter())?;
+        let devices = Devices::load(
+            root.as_ref().join(devices::DEVICES),
+            peers.iter().map(|(p, _)| p),
+            config.devices,
+        )?;
+        let peer_map_path = &root.as_ref().join("peer_map");
+        let peer_assignments = PeerAssignments::load(peer_map_path, devices.users())?;
+        timings.peer_assignments = lap();
 
         let project_id_path = &root.as_ref().join("project_oid");
         let identities: Identities<'_, Project> = (&repo).into();
-        let project = if std::fs::try_exists(&project_id_path)? {
-            let project_oid_bytes: Vec<u8> = std::fs::read(&project_id_path)?;
+        let project = if crate::fs::exists(&project_id_path)? {
+            let project_oid_bytes: Vec<u8> = crate::fs::read(&project_id_path)?;
             let project_oid: radicle_git_ext::Oid = serde_json::from_slice(&project_oid_bytes)?;
             identities.get(project_oid.into())?
         } else {
-            let key = peer_identities.some_key();
+            let key = peers.iter().next().unwrap().1.clone();
             let project = identities.create(
                 ProjectPayload::new(ProjectSubject {
-                    name: "theproject".into(),
+                    name: DEFAULT_PROJECT.into(),
                     description: None,
                     default_branch: None,
                 }),
-                Indirect::try_from_iter(peer_identities.keys().map(|k| Either::Left(k.public())))
+                Indirect::try_from_iter(peers.iter().map(|(_, k)| Either::Left(k.public())))
                     .unwrap(),
                 &key,
             )?;
             let project_oid_bytes = serde_json::to_vec(&project.content_id)?;
-            std::fs::write(&project_id_path, project_oid_bytes)?;
+            crate::fs::write_atomic(&project_id_path, project_oid_bytes)?;
             project
         };
+        let mut projects = Projects::load(root.as_ref().join(projects::PROJECTS))?;
+        if !projects.contains(DEFAULT_PROJECT) {
+            projects.register(DEFAULT_PROJECT, &project)?;
+        }
+        timings.project = lap();
+
+        let legacy_identities_path = &root.as_ref().join(peer_identities::LEGACY_INDEX);
+        let peer_identities = PeerIdentities::load(
+            legacy_identities_path,
+            &repo,
+            &project.urn(),
+            peers.iter(),
+            &devices,
+        )?;
+        timings.peer_identities = lap();
+
+        let typename = config.typename()?;
+        let schema = config.schema(&root)?;
+
+        let tracking = Tracking::load(root.as_ref().join("tracking"))?;
+        let issue_index = IssueIndex::load(root.as_ref().join(issue_index::ISSUE_INDEX))?;
 
         let cob_cache_path = root.as_ref().join("cob_cache");
-        if !std::fs::try_exists(&cob_cache_path)? {
-            std::fs::create_dir_all(&cob_cache_path)?;
+        if !crate::fs::exists(&cob_cache_path)? {
+            crate::fs::create_dir_all(&cob_cache_path)?;
         }
+        let cache = Cache::open(
+            cob_cache_path,
+            root.as_ref().join("cache_access"),
+            root.as_ref().join("cache_stats"),
+        )?;
+        timings.cache = lap();
 
         Ok(LiteMonorepo {
             root: root.as_ref().to_path_buf(),
             peers,
             repo,
-            peer_assignments,
+            peer_assignments: Arc::new(Mutex::new(peer_assignments)),
             peer_identities,
+            devices,
+            last_devices: HashMap::new(),
             project,
+            project_name: DEFAULT_PROJECT.to_string(),
+            cache,
+            audit_log: AuditLog::new(root.as_ref().join(audit::AUDIT_LOG)),
+            open_timings: timings,
+            ref_layout: config.ref_layout,
+            typename,
+            schema,
+            tracking,
+            settings: Settings::default(),
+            changes_created: 0,
+            seed: config.seed,
+            heartbeat: None,
+            issue_index: Arc::new(Mutex::new(issue_index)),
+            files: None,
         })
     }
 
-    pub(crate) fn import_issue(&mut self, issue: &DownloadedIssue) -> Result<(), error::Import> {
-        if let Some(ref author) = issue.author_id {
-            let creator_id = self.peer_assignments.assign(author)?;
-            let (creator_person, creator_key) = self.peer_identities.get(creator_id).unwrap();
-            let init_change = init_issue_change(issue, &creator_person.urn());
-            let storage = PeerRefsStorage::new(*creator_id, &self.repo);
-            let mut object = cob::create_object(
-                &storage,
-                &self.repo,
-                &(creator_key.clone()).into(),
-                creator_person,
-                Either::Right(self.project.clone()),
-                cob::NewObjectSpec {
-                    history: init_change,
-                    message: None,
-                    typename: TYPENAME.clone(),
-                    schema_json: SCHEMA.clone(),
-                },
-                Some(self.cache_path()),
-            )?;
+    /// Import `issue` as a new object, followed by a change for each of its comments and events.
+    /// When resuming (see [`LiteMonorepo::set_resume_imports`]) an issue which was completely
+    /// imported before is skipped, and one which was partially imported is continued from where
+    /// it got to. Importing incrementally (see [`LiteMonorepo::set_incremental_imports`]) instead
+    /// adds whichever comments and events of an issue imported before are new.
+    pub fn import_issue(&mut self, issue: &DownloadedIssue) -> Result<(), error::Import> {
+        let filtered;
+        let issue = match &self.settings.bots {
+            Some(bots) => {
+                filtered = bots.apply(issue);
+                &filtered
+            }
+            None => issue,
+        };
+        let windowed;
+        let issue = match self.settings.import_until {
+            Some(until) => match window::as_of(issue, until) {
+                Some(issue) => {
+                    windowed = issue;
+                    &windowed
+                }
+                None => return Ok(()),
+            },
+            None => issue,
+        };
+        let author = match &issue.author_id {
+            Some(author) => author,
+            None => return Ok(()),
+        };
+        let previous = if self.settings.resume_imports || self.settings.incremental_imports {
+            self.issue_index.lock().unwrap().get(issue.number).cloned()
+        } else {
+            None
+        };
+        let resumed = match previous {
+            Some(entry) if entry.complete && !self.settings.incremental_imports => return Ok(()),
+            Some(entry) => match entry.object_id() {
+                Some(id) => self.retrieve_for_update(&id)?.map(|o| (o, entry)),
+                None => None,
+            },
+            None => None,
+        };
+        let (mut object, previous) = match resumed {
+            Some((object, entry)) => (object, Some(entry)),
+            None => (self.create_issue(issue, author)?, None),
+        };
+        let object_id = *object.id();
 
-            for comment in &issue.comments {
-                if let Some(commentor) = &comment.author_id {
-                    let commentor_id = self.peer_assignments.assign(commentor)?;
-                    let (commentor_person, commentor_key) =
-                        self.peer_identities.get(commentor_id).unwrap();
-                    let storage = PeerRefsStorage::new(*commentor_id, &self.repo);
-                    object = cob::update_object(
-                        &storage,
-                        &(commentor_key.clone()).into(),
-                        &self.repo,
-                        commentor_person,
-                        Either::Right(self.project.clone()),
-                        cob::UpdateObjectSpec {
-                            object_id: *object.id(),
-                            typename: TYPENAME.clone(),
-                            message: None,
-                            changes: add_comment_change(
-                                comment,
-                                &commentor_person.urn(),
-                                object.history(),
-                            ),
-                        },
-                        Some(self.cache_path()),
+        // Comments and events are applied in the order they happened, each as its own change
+        let mut updates: Vec<Either<&DownloadedComment, &DownloadedEvent>> = issue
+            .comments
+            .iter()
+            .map(Either::Left)
+            .chain(issue.events.iter().map(Either::Right))
+            .collect();
+        updates.sort_by_key(update_time);
+        let progress = |i: usize| Progress {
+            number: issue.number,
+            applied: i + 1,
+            applied_until: Some(update_time(&updates[i])),
+        };
+        let pending: Vec<usize> = match &previous {
+            Some(entry) if self.settings.incremental_imports => {
+                new_updates(&updates, &object, entry)
+            }
+            Some(entry) => (entry.applied.min(updates.len())..updates.len()).collect(),
+            None => (0..updates.len()).collect(),
+        };
+        if pending.is_empty() && previous.as_ref().map(|e| e.complete) == Some(true) {
+            return Ok(());
+        }
+        let mut pending = pending.into_iter().peekable();
+        while let Some(i) = pending.next() {
+            if let Some(&j) = pending.peek() {
+                if let Some((first, second)) = self.concurrent_pair(&updates[i], &updates[j])? {
+                    pending.next();
+                    object = self.append_concurrent_comments(
+                        object,
+                        (first, progress(i)),
+                        (second, progress(j)),
                     )?;
+                    for comment in &[first, second] {
+                        let id = comment.id.clone();
+                        self.log_change(
+                            issue.number,
+                            &object_id,
+                            comment.author_id.as_ref().unwrap(),
+                            import_log::Source::Comment { id },
+                        )?;
+                    }
+                    continue;
                 }
             }
+            let created = self.changes_created;
+            let (user, source) = match &updates[i] {
+                Either::Left(comment) => match &comment.author_id {
+                    Some(commentor) => {
+                        object =
+                            self.append_comment(Some(progress(i)), object, commentor, comment)?;
+                        self.inject_invalid(issue.number, &object, commentor, comment)?;
+                        self.tamper(issue.number, &object, commentor, comment)?;
+                        let id = comment.id.clone();
+                        (commentor, import_log::Source::Comment { id })
+                    }
+                    None => continue,
+                },
+                Either::Right(event) => match &event.actor_id {
+                    Some(actor) => {
+                        object = self.append_event(progress(i), object, actor, event)?;
+                        let id = event.id.clone();
+                        (actor, import_log::Source::Event { id })
+                    }
+                    None => continue,
+                },
+            };
+            // Events which change nothing don't create a change, so their progress hasn't been
+            // recorded yet
+            if self.changes_created > created {
+                self.log_change(issue.number, &object_id, user, source)?;
+            } else {
+                let p = progress(i);
+                self.record_progress(issue.number, &object_id, p.applied, p.applied_until, false)?;
+            }
         }
-        Ok(())
+        let applied_until = updates
+            .last()
+            .map(update_time)
+            .max(previous.and_then(|e| e.applied_until));
+        self.record_progress(issue.number, &object_id, updates.len(), applied_until, true)
     }
 
-    pub(crate) fn list_issues(&self) -> Result<usize, error::List> {
-        let some_peer = self.peers.some_peer();
-        let storage = PeerRefsStorage::new(*some_peer, &self.repo);
-        let objs = cob::retrieve_objects(
+    /// Create the object for `issue`, without any of its comments
+    fn create_issue(
+        &mut self,
+        issue: &DownloadedIssue,
+        author: &GithubUserId,
+    ) -> Result<cob::CollaborativeObject, error::Import> {
+        let creator_id = self.device_of(author)?;
+        let (creator_person, creator_key) =
+            self.peer_identities.get(&self.repo, &creator_id)?.unwrap();
+        let init_change = init_issue_change(
+            issue,
+            &creator_person.urn(),
+            self.settings.import_acl,
+            self.settings.import_layout,
+            self.clock_of(&creator_id, issue.created_at),
+            self.actor_of(&creator_id).as_ref(),
+        );
+        let storage = PeerRefsStorage::new(creator_id, &self.repo).with_layout(self.ref_layout);
+        self.chaos_before_change()?;
+        let beat = self.beat("create", None);
+        let started = Utc::now();
+        let object = cob_api::create_object(
             &storage,
             &self.repo,
-            Either::Right(self.project.clone()),
-            &TYPENAME,
+            creator_key,
+            creator_person,
+            &self.project,
+            &self.typename,
+            &self.schema,
+            init_change,
             Some(self.cache_path()),
+        );
+        drop(beat);
+        // The object is recorded before anything else which could fail, so that a resumed import
+        // continues it rather than creating another
+        if let Ok(object) = &object {
+            self.record_progress(issue.number, object.id(), 0, None, false)?;
+        }
+        let object = self.audit(
+            audit::Operation::Create,
+            Some(issue.number),
+            &creator_id,
+            None,
+            started,
+            object,
         )?;
-        Ok(objs.len())
+        self.changes_created += 1;
+        self.chaos_after_change()?;
+        self.log_change(issue.number, object.id(), author, import_log::Source::Issue)?;
+        Ok(object)
+    }
+
+    /// Record the create or update of `object_id` by `peer` which started at `started` and has
+    /// just finished with `result` in the audit log, returning the result
+    fn audit<E>(
+        &self,
+        operation: audit::Operation,
+        issue: Option<u64>,
+        peer: &link_crypto::PeerId,
+        object_id: Option<&cob::ObjectId>,
+        started: DateTime<Utc>,
+        result: Result<cob::CollaborativeObject, E>,
+    ) -> Result<cob::CollaborativeObject, error::Import>
+    where
+        E: std::fmt::Display,
+        error::Import: From<E>,
+    {
+        let duration = (Utc::now() - started).to_std().unwrap_or_default();
+        let object_id = match &result {
+            Ok(object) => Some(*object.id()),
+            Err(_) => object_id.copied(),
+        };
+        if let Some(detector) = &self.settings.anomalies {
+            let name = match operation {
+                audit::Operation::Create => "create",
+                audit::Operation::Update => "update",
+            };
+            detector.record(name, object_id.as_ref(), duration);
+        }
+        let commit = match (&result, &object_id) {
+            (Ok(_), Some(id)) => PeerRefsStorage::new(*peer, &self.repo)
+                .with_layout(self.ref_layout)
+                .local_tip(&self.project.urn(), &self.typename, id)?,
+            _ => None,
+        };
+        self.audit_log.append(&audit::Entry {
+            timestamp: started,
+            operation,
+            issue,
+            peer: peer.to_string(),
+            object_id: object_id.map(|id| id.to_string()),
+            commit: commit.map(|c| c.to_string()),
+            duration_millis: duration.as_millis() as u64,
+            error: result.as_ref().err().map(|e| e.to_string()),
+        })?;
+        if let Some(id) = &object_id {
+            // cob writes the cache entry of the object it was given the cache for
+            self.cache.after_access(id)?;
+        }
+        result.map_err(error::Import::from)
     }
 
-    pub(crate) fn retrieve_issue(
+    /// Record the change `user` just made to `object_id` in the import log, if it's enabled
+    fn log_change(
         &self,
+        number: u64,
         object_id: &cob::ObjectId,
-        use_cache: bool,
-    ) -> Result<Option<serde_json::Value>, error::Retrieve> {
-        let some_peer = self.peers.some_peer();
-        let storage = PeerRefsStorage::new(*some_peer, &self.repo);
-        let cache_path = if use_cache {
-            Some(self.cache_path())
-        } else {
-            None
+        user: &GithubUserId,
+        source: import_log::Source,
+    ) -> Result<(), error::Import> {
+        let log = match &self.settings.import_log {
+            Some(log) => log,
+            None => return Ok(()),
         };
-        if let Some(obj) = cob::retrieve_object(
-            &storage,
-            &self.repo,
-            Either::Right(self.project.clone()),
-            &TYPENAME,
-            object_id,
-            cache_path,
-        )? {
-            let backend = automerge::Backend::load(obj.history().as_ref().to_vec()).unwrap();
-            let mut frontend = automerge::Frontend::new();
-            frontend.apply_patch(backend.get_patch().unwrap()).unwrap();
-            Ok(Some(frontend.state().to_json()))
-        } else {
-            Ok(None)
+        let peer = self.last_device_of(user)?;
+        let commit = PeerRefsStorage::new(peer, &self.repo)
+            .with_layout(self.ref_layout)
+            .local_tip(&self.project.urn(), &self.typename, object_id)?;
+        log.append(&import_log::Change {
+            number,
+            object_id: object_id.to_string(),
+            commit: commit.map(|c| c.to_string()),
+            peer: peer.to_string(),
+            author_urn: self.peer_urn(&peer)?.map(|u| u.to_string()),
+            source,
+        })?;
+        Ok(())
+    }
+
+    /// Record `progress` once the change making it has been written to an object, before the
+    /// change is audited or logged, so that if either of those fails a resumed import doesn't
+    /// write the change again
+    fn record_written<E>(
+        &self,
+        progress: Option<Progress>,
+        written: &Result<cob::CollaborativeObject, E>,
+    ) -> Result<(), error::Import> {
+        match (progress, written) {
+            (Some(p), Ok(object)) => {
+                self.record_progress(p.number, object.id(), p.applied, p.applied_until, false)
+            }
+            _ => Ok(()),
         }
     }
 
-    pub(crate) fn issue_info(
+    fn record_progress(
         &self,
+        number: u64,
         object_id: &cob::ObjectId,
-    ) -> Result<Option<cob::ChangeGraphInfo>, error::Retrieve> {
+        applied: usize,
+        applied_until: Option<DateTime<Utc>>,
+        complete: bool,
+    ) -> Result<(), error::Import> {
+        self.issue_index
+            .lock()
+            .unwrap()
+            .record(issue_index::Entry {
+                number,
+                object_id: object_id.to_string(),
+                applied,
+                complete,
+                applied_until,
+            })?;
+        Ok(())
+    }
+
+    /// Retrieve an object in order to add changes to it
+    fn retrieve_for_update(
+        &self,
+        object_id: &cob::ObjectId,
+    ) -> Result<Option<cob::CollaborativeObject>, error::Import> {
         let some_peer = self.peers.some_peer();
-        let storage = PeerRefsStorage::new(*some_peer, &self.repo);
-        cob::changegraph_info_for_object(
+        let storage = PeerRefsStorage::new(*some_peer, &self.repo).with_layout(self.ref_layout);
+        let _beat = self.beat("retrieve", Some(object_id));
+        let object = cob_api::retrieve_object(
             &storage,
             &self.repo,
-            Either::Right(self.project.clone()),
-            &TYPENAME,
+            &self.project,
+            &self.typename,
             object_id,
-        )
-        .map_err(error::Retrieve::from)
+            Some(self.cache_path()),
+        )?;
+        self.cache.after_access(object_id)?;
+        Ok(object)
     }
 
-    fn cache_path(&self) -> std::path::PathBuf {
-        self.root.join("cob_cache")
+    /// Add `comment` to the end of the comments of an existing issue. Comments without an author
+    /// are ignored, as they are when importing.
+    pub fn add_comment(
+        &mut self,
+        object_id: &cob::ObjectId,
+        comment: &DownloadedComment,
+    ) -> Result<(), error::Import> {
+        let commentor = match &comment.author_id {
+            Some(c) => c,
+            None => return Ok(()),
+        };
+        let object = self
+            .retrieve_for_update(object_id)?
+            .ok_or(error::Import::MissingObject(*object_id))?;
+        self.append_comment(None, object, commentor, comment)?;
+        Ok(())
     }
-}
 
-impl std::fmt::Debug for LiteMonorepo {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "LiteMonorepo{{ {} }}", self.root.display())
+    /// The peer `user` is assigned to, assigning them one if they don't have one yet. With several
+    /// devices per user this is the peer standing for the user, see [`crate::devices`].
+    fn assign_peer(&self, user: &GithubUserId) -> Result<link_crypto::PeerId, error::Import> {
+        let mut assignments = self.peer_assignments.lock().unwrap();
+        Ok(*assignments.assign(user)?)
     }
-}
 
-fn init_issue_change(issue: &DownloadedIssue, author_urn: &Urn) -> cob::History {
-    let mut doc = automerge::Frontend::new();
-    let mut backend = automerge::Backend::new();
-    let (_, change) = doc
-        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
-            d.add_change(LocalChange::set(
-                automerge::Path::root().key("author_urn"),
-                automerge::Value::Primitive(automerge::Primitive::Str(
-                    author_urn.to_string().into(),
-                )),
-            ))?;
-            d.add_change(LocalChange::set(
-                automerge::Path::root().key("title"),
-                to_text(issue.title.as_str()),
-            ))?;
-            if let Some(body) = &issue.body {
-                d.add_change(LocalChange::set(
-                    automerge::Path::root().key("body"),
-                    to_text(body.as_str()),
-                ))?;
-            }
-            d.add_change(LocalChange::set(
-                automerge::Path::root().key("created_at"),
-                automerge::Value::Primitive(automerge::Primitive::Str(
-                    issue.created_at.to_rfc3339().into(),
-                )),
-            ))?;
-            d.add_change(LocalChange::set(
-                automerge::Path::root().key("comments"),
-                automerge::Value::List(Vec::new()),
-            ))?;
-            d.add_change(LocalChange::set(
-                automerge::Path::root().key("github_issue_number"),
-                automerge::Value::Primitive(automerge::Primitive::Str(
-                    issue.number.to_string().into(),
-                )),
-            ))?;
-            Ok(())
-        })
-        .unwrap();
-    let (_, change) = backend.apply_local_change(change.unwrap()).unwrap();
-    cob::History::Automerge(change.raw_bytes().to_vec())
-}
+    /// The peer which makes the next change of `user`, the next in turn of the devices of the user
+    /// they are assigned, see [`crate::devices`]
+    fn device_of(&mut self, user: &GithubUserId) -> Result<link_crypto::PeerId, error::Import> {
+        let peer = self.assign_peer(user)?;
+        let device = self.next_device_of(user)?;
+        self.last_devices.insert(peer, device);
+        Ok(device)
+    }
 
-fn add_comment_change(
-    comment: &DownloadedComment,
-    commentor_urn: &Urn,
-    previous_history: &cob::History,
-) -> cob::History {
-    let mut frontend = automerge::Frontend::new();
-    let mut backend = automerge::Backend::new();
-    let cob::History::Automerge(hist) = previous_history;
-    let changes: Vec<automerge::Change> = automerge::Change::load_document(hist).unwrap();
-    let patch = backend.apply_changes(changes).unwrap();
-    frontend.apply_patch(patch).unwrap();
+    /// The peer [`LiteMonorepo::device_of`] will make the next change of `user` with
+    fn next_device_of(&self, user: &GithubUserId) -> Result<link_crypto::PeerId, error::Import> {
+        let peer = self.assign_peer(user)?;
+        let devices = self.devices.of(&peer);
+        let next = match self.last_devices.get(&peer) {
+            Some(last) => devices.iter().position(|d| d == last).map_or(0, |i| i + 1),
+            None => 0,
+        };
+        Ok(devices[next % devices.len()])
+    }
 
-    let (_, change) = frontend
-        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
-            let comments_len = match d.value_at_path(&automerge::Path::root().key("comments")) {
-                Some(automerge::Value::List(elems)) => elems.len(),
-                _ => panic!("comments must be a list due to the schema"),
-            };
-            let comment_path = automerge::Path::root()
-                .key("comments")
-                .index(comments_len as u32);
-            let comment_map = automerge::Value::Map(HashMap::new());
-            d.add_change(LocalChange::insert(comment_path.clone(), comment_map))?;
+    /// The peer the last change of `user` was made with
+    fn last_device_of(&self, user: &GithubUserId) -> Result<link_crypto::PeerId, error::Import> {
+        let peer = self.assign_peer(user)?;
+        Ok(self.last_devices.get(&peer).copied().unwrap_or(peer))
+    }
 
-            d.add_change(LocalChange::set(
-                comment_path.clone().key("commenter_urn"),
-                automerge::Value::Primitive(automerge::Primitive::Str(
-                    commentor_urn.to_string().into(),
-                )),
-            ))?;
+    /// What's needed to open another handle on this monorepo on another thread, which imports
+    /// issues with the same settings and shares this handle's peer assignments so that a GitHub
+    /// user never ends up with more than one peer
+    pub fn import_worker(&self) -> ImportWorker {
+        ImportWorker {
+            root: self.root.clone(),
+            project: self.project.clone(),
+            project_name: self.project_name.clone(),
+            peer_assignments: self.peer_assignments.clone(),
+            issue_index: self.issue_index.clone(),
+            settings: self.settings.clone(),
+            cache_max_size: self.cache.max_size(),
+        }
+    }
 
-            d.add_change(LocalChange::set(
-                comment_path.clone().key("comment"),
-                to_text(comment.body.as_str()),
-            ))?;
+    /// What's needed to open another handle on this monorepo on another thread, which retrieves
+    /// issues from the same project with the same settings
+    pub fn read_worker(&self) -> ReadWorker {
+        ReadWorker {
+            root: self.root.clone(),
+            project: self.project.clone(),
+            project_name: self.project_name.clone(),
+            settings: self.settings.clone(),
+            cache_max_size: self.cache.max_size(),
+        }
+    }
 
-            d.add_change(LocalChange::set(
-                comment_path.key("created_at"),
-                automerge::Value::Primitive(automerge::Primitive::Str(
-                    comment.created_at.to_rfc3339().into(),
-                )),
-            ))?;
+    /// Report each cob operation of an import to `heartbeat`, see `crate::watchdog`
+    pub fn set_heartbeat(&mut self, heartbeat: Option<Heartbeat>) {
+        self.heartbeat = heartbeat;
+    }
 
+    fn beat(&self, operation: &'static str, object: Option<&cob::ObjectId>) -> Option<Beat> {
+        self.heartbeat
+            .as_ref()
+            .map(|heartbeat| heartbeat.start(operation, object))
+    }
+
+    /// Count changes created through other handles, see [`LiteMonorepo::import_worker`]
+    pub fn add_changes_created(&mut self, changes: u64) {
+        self.changes_created += changes;
+    }
+
+    fn append_comment(
+        &mut self,
+        progress: Option<Progress>,
+        object: cob::CollaborativeObject,
+        commentor: &GithubUserId,
+        comment: &DownloadedComment,
+    ) -> Result<cob::CollaborativeObject, error::Import> {
+        self.comment_on(progress, object.id(), object.history(), commentor, comment)
+    }
+
+    /// With concurrent comments enabled, the two updates if they are comments whose authors have
+    /// different peers
+    fn concurrent_pair<'u>(
+        &self,
+        first: &Either<&'u DownloadedComment, &'u DownloadedEvent>,
+        second: &Either<&'u DownloadedComment, &'u DownloadedEvent>,
+    ) -> Result<Option<(&'u DownloadedComment, &'u DownloadedComment)>, error::Import> {
+        if !self.settings.concurrent_comments {
+            return Ok(None);
+        }
+        match (first, second) {
+            (Either::Left(first), Either::Left(second)) => {
+                match (&first.author_id, &second.author_id) {
+                    (Some(a), Some(b)) if self.assign_peer(a)? != self.assign_peer(b)? => {
+                        Ok(Some((*first, *second)))
+                    }
+                    _ => Ok(None),
+                }
+            }
+            _ => Ok(None),
+        }
+    }
+
+    /// Add `first` and `second` as two changes which are both based on the history of `object`,
+    /// so that the change graph has a branch for each. The object is returned with both
+    /// branches, and the next change made to it merges them.
+    ///
+    /// cob bases a change on the tips of every peer's ref, so the ref of the first comment's peer
+    /// is put back where it was while the second comment is made, and then moved to the first
+    /// comment again.
+    fn append_concurrent_comments(
+        &mut self,
+        object: cob::CollaborativeObject,
+        (first, first_progress): (&DownloadedComment, Progress),
+        (second, second_progress): (&DownloadedComment, Progress),
+    ) -> Result<cob::CollaborativeObject, error::Import> {
+        let (first_author, second_author) = match (&first.author_id, &second.author_id) {
+            (Some(a), Some(b)) => (a, b),
+            _ => return Ok(object),
+        };
+        let object_id = *object.id();
+        let urn = self.project.urn();
+        let first_peer = self.next_device_of(first_author)?;
+        let first_storage =
+            PeerRefsStorage::new(first_peer, &self.repo).with_layout(self.ref_layout);
+        let before = first_storage.local_tip(&urn, &self.typename, &object_id)?;
+        self.comment_on(
+            Some(first_progress),
+            &object_id,
+            object.history(),
+            first_author,
+            first,
+        )?;
+        let after = first_storage.local_tip(&urn, &self.typename, &object_id)?;
+
+        first_storage.set_local_tip(&urn, &self.typename, &object_id, before)?;
+        let second_result = self.comment_on(
+            Some(second_progress),
+            &object_id,
+            object.history(),
+            second_author,
+            second,
+        );
+        first_storage.set_local_tip(&urn, &self.typename, &object_id, after)?;
+        second_result?;
+
+        self.retrieve_for_update(&object_id)?
+            .ok_or(error::Import::MissingObject(object_id))
+    }
+
+    /// Add `comment` as a change based on `history`, recording `progress` in the import of its
+    /// issue if it's being imported
+    fn comment_on(
+        &mut self,
+        progress: Option<Progress>,
+        object_id: &cob::ObjectId,
+        history: &cob::History,
+        commentor: &GithubUserId,
+        comment: &DownloadedComment,
+    ) -> Result<cob::CollaborativeObject, error::Import> {
+        let commentor_id = self.device_of(commentor)?;
+        let clock = self.clock_of(&commentor_id, comment.created_at);
+        let actor = self.actor_of(&commentor_id);
+        let (commentor_person, commentor_key) = self
+            .peer_identities
+            .get(&self.repo, &commentor_id)?
+            .unwrap();
+        let storage = PeerRefsStorage::new(commentor_id, &self.repo).with_layout(self.ref_layout);
+        self.chaos_before_change()?;
+        let beat = self.beat("update", Some(object_id));
+        let started = Utc::now();
+        let object = cob_api::update_object(
+            &storage,
+            &self.repo,
+            commentor_key,
+            commentor_person,
+            &self.project,
+            &self.typename,
+            object_id,
+            self.compact(
+                history,
+                add_comment_change(
+                    comment,
+                    &commentor_person.urn(),
+                    history,
+                    clock,
+                    actor.as_ref(),
+                ),
+            ),
+            Some(self.cache_path()),
+        );
+        drop(beat);
+        self.record_written(progress, &object)?;
+        let object = self.audit(
+            audit::Operation::Update,
+            progress.map(|p| p.number),
+            &commentor_id,
+            Some(object_id),
+            started,
+            object,
+        )?;
+        self.changes_created += 1;
+        self.chaos_after_change()?;
+        Ok(object)
+    }
+
+    /// Follow `comment` with a change by the same peer violating the schema, if it is one of the
+    /// comments chosen by `--inject-invalid`. The change is based on `object`, which is left as
+    /// it was, so that later changes don't depend on it.
+    fn inject_invalid(
+        &mut self,
+        number: u64,
+        object: &cob::CollaborativeObject,
+        commentor: &GithubUserId,
+        comment: &DownloadedComment,
+    ) -> Result<(), error::Import> {
+        let violation = match self
+            .inject_invalid
+            .and_then(|ratio| invalid_changes::chosen(&comment.id, ratio))
+        {
+            Some(violation) => violation,
+            None => return Ok(()),
+        };
+        let peer = self.last_device_of(commentor)?;
+        let (person, key) = self.peer_identities.get(&self.repo, &peer)?.unwrap();
+        let marker = invalid_changes::marker(&comment.id);
+        // A new actor, so that the actor of the peer doesn't skip a sequence number when the
+        // change is left out
+        let change = marker_change(
+            &marker,
+            Some(violation),
+            object.history(),
+            self.clock_of(&peer, comment.created_at),
+        );
+        let storage = PeerRefsStorage::new(peer, &self.repo).with_layout(self.ref_layout);
+        let beat = self.beat("update", Some(object.id()));
+        let started = Utc::now();
+        let written = cob_api::update_object(
+            &storage,
+            &self.repo,
+            key,
+            person,
+            &self.project,
+            &self.typename,
+            object.id(),
+            change,
+            Some(self.cache_path()),
+        );
+        drop(beat);
+        let written = self.audit(
+            audit::Operation::Update,
+            Some(number),
+            &peer,
+            Some(object.id()),
+            started,
+            written,
+        );
+        let commit = match written {
+            Ok(_) => {
+                self.changes_created += 1;
+                storage
+                    .local_tip(&self.project.urn(), &self.typename, object.id())?
+                    .map(|oid| oid.to_string())
+            }
+            Err(_) => None,
+        };
+        invalid_changes::record(
+            self.root.join(invalid_changes::INVALID_CHANGES),
+            &invalid_changes::Injected {
+                issue: number,
+                object_id: object.id().to_string(),
+                peer: peer.to_string(),
+                comment_id: comment.id.clone(),
+                violation,
+                marker,
+                commit,
+            },
+        )?;
+        Ok(())
+    }
+
+    /// Follow `comment` with a change by the same peer whose commit is rewritten with a forged
+    /// signature or author, if it is one of the comments chosen by `--tamper-signatures`. As with
+    /// [`LiteMonorepo::inject_invalid`], `object` is left as it was.
+    fn tamper(
+        &mut self,
+        number: u64,
+        object: &cob::CollaborativeObject,
+        commentor: &GithubUserId,
+        comment: &DownloadedComment,
+    ) -> Result<(), error::Import> {
+        let tampering = match self
+            .tamper_signatures
+            .and_then(|ratio| tampering::chosen(&comment.id, ratio))
+        {
+            Some(tampering) => tampering,
+            None => return Ok(()),
+        };
+        let peer = self.last_device_of(commentor)?;
+        let (person, key) = self.peer_identities.get(&self.repo, &peer)?.unwrap();
+        let author_urn = person.urn();
+        let marker = tampering::marker(&comment.id);
+        let clock = self.clock_of(&peer, comment.created_at);
+        let change = marker_change(&marker, None, object.history(), clock);
+        let storage = PeerRefsStorage::new(peer, &self.repo).with_layout(self.ref_layout);
+        let beat = self.beat("update", Some(object.id()));
+        let started = Utc::now();
+        let written = cob_api::update_object(
+            &storage,
+            &self.repo,
+            key,
+            person,
+            &self.project,
+            &self.typename,
+            object.id(),
+            change,
+            Some(self.cache_path()),
+        );
+        drop(beat);
+        self.audit(
+            audit::Operation::Update,
+            Some(number),
+            &peer,
+            Some(object.id()),
+            started,
+            written,
+        )?;
+        self.changes_created += 1;
+
+        // Rewrite the change cob wrote and point the peer's ref at the rewritten one instead
+        let urn = self.project.urn();
+        let original = storage
+            .local_tip(&urn, &self.typename, object.id())?
+            .ok_or(error::Import::MissingObject(*object.id()))?;
+        let commit = self.repo.find_commit(original)?;
+        let mut parents = Vec::new();
+        let mut author_replaced = false;
+        for parent in commit.parents() {
+            let is_identity = parent.tree()?.get_name("change").is_none();
+            if tampering == Tampering::WrongAuthor && is_identity && !author_replaced {
+                if let Some(other) = self.other_identity(&author_urn)? {
+                    parents.push(self.repo.find_commit(other)?);
+                    author_replaced = true;
+                    continue;
+                }
+            }
+            parents.push(parent);
+        }
+        let message = tampering::tamper_message(commit.message().unwrap_or_default(), tampering);
+        let parents: Vec<&git2::Commit<'_>> = parents.iter().collect();
+        let tampered = self.repo.commit(
+            None,
+            &commit.author(),
+            &commit.committer(),
+            &message,
+            &commit.tree()?,
+            &parents,
+        )?;
+        storage.set_local_tip(&urn, &self.typename, object.id(), Some(tampered))?;
+
+        tampering::record(
+            self.root.join(tampering::TAMPERED_CHANGES),
+            &tampering::Tampered {
+                issue: number,
+                object_id: object.id().to_string(),
+                peer: peer.to_string(),
+                comment_id: comment.id.clone(),
+                tampering,
+                marker,
+                original: original.to_string(),
+                commit: tampered.to_string(),
+            },
+        )?;
+        Ok(())
+    }
+
+    /// The commit of the identity of a peer whose identity isn't `urn`, if there is one
+    fn other_identity(&self, urn: &Urn) -> Result<Option<git2::Oid>, error::Import> {
+        for peer in self.peer_ids() {
+            if let Some((person, _)) = self.peer_identities.get(&self.repo, peer)? {
+                if person.urn() != *urn {
+                    return Ok(Some(*person.content_id));
+                }
+            }
+        }
+        Ok(None)
+    }
+
+    fn append_event(
+        &mut self,
+        progress: Progress,
+        object: cob::CollaborativeObject,
+        actor: &GithubUserId,
+        event: &DownloadedEvent,
+    ) -> Result<cob::CollaborativeObject, error::Import> {
+        let actor_id = self.device_of(actor)?;
+        let clock = self.clock_of(&actor_id, event.created_at);
+        let automerge_actor = self.actor_of(&actor_id);
+        let (actor_person, actor_key) = self.peer_identities.get(&self.repo, &actor_id)?.unwrap();
+        let changes = match event_change(
+            &event.kind,
+            &actor_person.urn(),
+            object.history(),
+            clock,
+            automerge_actor.as_ref(),
+        ) {
+            Some(changes) => changes,
+            None => return Ok(object),
+        };
+        let storage = PeerRefsStorage::new(actor_id, &self.repo).with_layout(self.ref_layout);
+        self.chaos_before_change()?;
+        let beat = self.beat("update", Some(object.id()));
+        let started = Utc::now();
+        let updated = cob_api::update_object(
+            &storage,
+            &self.repo,
+            actor_key,
+            actor_person,
+            &self.project,
+            &self.typename,
+            object.id(),
+            self.compact(object.history(), changes),
+            Some(self.cache_path()),
+        );
+        drop(beat);
+        self.record_written(Some(progress), &updated)?;
+        let object = self.audit(
+            audit::Operation::Update,
+            Some(progress.number),
+            &actor_id,
+            Some(object.id()),
+            started,
+            updated,
+        )?;
+        self.changes_created += 1;
+        self.chaos_after_change()?;
+        Ok(object)
+    }
+
+    /// Change the title of an existing issue as `editor`, regardless of whether the issue's ACL
+    /// allows it
+    pub fn edit_title(
+        &mut self,
+        object_id: &cob::ObjectId,
+        editor: &link_crypto::PeerId,
+        title: &str,
+    ) -> Result<(), error::Import> {
+        let storage = PeerRefsStorage::new(*editor, &self.repo).with_layout(self.ref_layout);
+        let object = cob_api::retrieve_object(
+            &storage,
+            &self.repo,
+            &self.project,
+            &self.typename,
+            object_id,
+            Some(self.cache_path()),
+        )?
+        .ok_or(error::Import::MissingObject(*object_id))?;
+        let (editor_person, editor_key) = self
+            .peer_identities
+            .get(&self.repo, editor)?
+            .ok_or(error::Import::UnknownPeer(*editor))?;
+        let started = Utc::now();
+        let updated = cob_api::update_object(
+            &storage,
+            &self.repo,
+            editor_key,
+            editor_person,
+            &self.project,
+            &self.typename,
+            object_id,
+            self.compact(
+                object.history(),
+                edit_title_change(
+                    title,
+                    &editor_person.urn(),
+                    object.history(),
+                    Clock::Skewed(self.skew_of(editor)),
+                    self.actor_of(editor).as_ref(),
+                ),
+            ),
+            Some(self.cache_path()),
+        );
+        self.audit(
+            audit::Operation::Update,
+            None,
+            editor,
+            Some(object_id),
+            started,
+            updated,
+        )?;
+        self.changes_created += 1;
+        Ok(())
+    }
+
+    /// Edit the text of `field`, `title` or `body`, of an existing issue as `editor` with a single
+    /// change, creating the field if the issue has none
+    pub fn edit_text(
+        &mut self,
+        object_id: &cob::ObjectId,
+        editor: &link_crypto::PeerId,
+        field: &str,
+        edits: &[TextEdit],
+    ) -> Result<(), error::Import> {
+        let storage = PeerRefsStorage::new(*editor, &self.repo).with_layout(self.ref_layout);
+        let object = cob_api::retrieve_object(
+            &storage,
+            &self.repo,
+            &self.project,
+            &self.typename,
+            object_id,
+            Some(self.cache_path()),
+        )?
+        .ok_or(error::Import::MissingObject(*object_id))?;
+        let (editor_person, editor_key) = self
+            .peer_identities
+            .get(&self.repo, editor)?
+            .ok_or(error::Import::UnknownPeer(*editor))?;
+        let started = Utc::now();
+        let updated = cob_api::update_object(
+            &storage,
+            &self.repo,
+            editor_key,
+            editor_person,
+            &self.project,
+            &self.typename,
+            object_id,
+            self.compact(
+                object.history(),
+                edit_text_change(
+                    field,
+                    edits,
+                    &editor_person.urn(),
+                    object.history(),
+                    Clock::Skewed(self.skew_of(editor)),
+                    self.actor_of(editor).as_ref(),
+                ),
+            ),
+            Some(self.cache_path()),
+        );
+        self.audit(
+            audit::Operation::Update,
+            None,
+            editor,
+            Some(object_id),
+            started,
+            updated,
+        )?;
+        self.changes_created += 1;
+        Ok(())
+    }
+
+    /// Create an object as `peer` from `history`, the payload of a create recorded in the audit
+    /// log of another monorepo for `issue`, see `crate::replay`
+    pub fn replay_create(
+        &mut self,
+        issue: Option<u64>,
+        peer: &link_crypto::PeerId,
+        history: cob::History,
+    ) -> Result<cob::CollaborativeObject, error::Import> {
+        let (person, key) = self
+            .peer_identities
+            .get(&self.repo, peer)?
+            .ok_or(error::Import::UnknownPeer(*peer))?;
+        let storage = PeerRefsStorage::new(*peer, &self.repo).with_layout(self.ref_layout);
+        let started = Utc::now();
+        let created = cob_api::create_object(
+            &storage,
+            &self.repo,
+            key,
+            person,
+            &self.project,
+            &self.typename,
+            &self.schema,
+            history,
+            Some(self.cache_path()),
+        );
+        let object = self.audit(
+            audit::Operation::Create,
+            issue,
+            peer,
+            None,
+            started,
+            created,
+        )?;
+        self.changes_created += 1;
+        Ok(object)
+    }
+
+    /// Add `history`, the payload of an update recorded in the audit log of another monorepo for
+    /// `issue`, to `object_id` as `peer`, see `crate::replay`
+    pub fn replay_update(
+        &mut self,
+        issue: Option<u64>,
+        peer: &link_crypto::PeerId,
+        object_id: &cob::ObjectId,
+        history: cob::History,
+    ) -> Result<cob::CollaborativeObject, error::Import> {
+        let (person, key) = self
+            .peer_identities
+            .get(&self.repo, peer)?
+            .ok_or(error::Import::UnknownPeer(*peer))?;
+        let storage = PeerRefsStorage::new(*peer, &self.repo).with_layout(self.ref_layout);
+        let started = Utc::now();
+        let updated = cob_api::update_object(
+            &storage,
+            &self.repo,
+            key,
+            person,
+            &self.project,
+            &self.typename,
+            object_id,
+            history,
+            Some(self.cache_path()),
+        );
+        let object = self.audit(
+            audit::Operation::Update,
+            issue,
+            peer,
+            Some(object_id),
+            started,
+            updated,
+        )?;
+        self.changes_created += 1;
+        Ok(object)
+    }
+
+    /// The URN of the identity of `peer`
+    pub fn peer_urn(&self, peer: &link_crypto::PeerId) -> Result<Option<Urn>, error::Import> {
+        Ok(self
+            .peer_identities
+            .get(&self.repo, peer)?
+            .map(|(person, _)| person.urn()))
+    }
+
+    /// The identity of `peer`
+    pub fn peer_identity(
+        &self,
+        peer: &link_crypto::PeerId,
+    ) -> Result<Option<Person>, error::Import> {
+        Ok(self
+            .peer_identities
+            .get(&self.repo, peer)?
+            .map(|(person, _)| person.clone()))
+    }
+
+    /// Name the identity of each peer which is still named after its peer ID after a GitHub user
+    /// assigned to it, the first by login of those whose profile is in `profiles`. Returns the
+    /// number of identities renamed.
+    pub fn apply_profiles(&mut self, profiles: &Profiles) -> Result<usize, error::Import> {
+        let mut users_by_peer: HashMap<link_crypto::PeerId, Vec<GithubUserId>> = HashMap::new();
+        for (user, peer) in self.peer_assignments.lock().unwrap().assignments() {
+            users_by_peer.entry(*peer).or_default().push(user.clone());
+        }
+        let project = self.project.urn();
+        let mut renamed = 0;
+        for (peer, mut users) in users_by_peer {
+            match self.peer_identities.get(&self.repo, &peer)? {
+                Some((person, _))
+                    if person.payload().subject.name.to_string() == peer.to_string() => {}
+                Some(_) => continue,
+                None => return Err(error::Import::UnknownPeer(peer)),
+            }
+            users.sort_by(|a, b| a.0.cmp(&b.0));
+            let profile = match users.iter().find_map(|u| profiles.get(u)) {
+                Some(profile) => profile,
+                None => continue,
+            };
+            if self
+                .peer_identities
+                .set_profile(&self.repo, &project, &peer, profile)?
+            {
+                verbose!("named the identity of {} after {}", peer, profile.login);
+                renamed += 1;
+            }
+        }
+        Ok(renamed)
+    }
+
+    pub fn list_issues(&self) -> Result<usize, error::List> {
+        if self.settings.delegate_only.is_some() {
+            return Ok(self.materialized_issues()?.len());
+        }
+        let storage = self.local_storage();
+        let objs = cob_api::retrieve_objects(
+            &storage,
+            &self.repo,
+            &self.project,
+            &self.typename,
+            Some(self.cache_path()),
+        )?;
+        self.cache.after_listing(objs.iter().map(|o| o.id()))?;
+        Ok(objs.len())
+    }
+
+    /// The IDs of every issue which can be retrieved from the point of view of the local peer
+    pub fn list_issue_ids(&self) -> Result<Vec<cob::ObjectId>, error::List> {
+        if self.settings.delegate_only.is_some() {
+            return Ok(self.materialized_issues()?.iter().map(|i| i.id).collect());
+        }
+        let storage = self.local_storage();
+        let objs = cob_api::retrieve_objects(
+            &storage,
+            &self.repo,
+            &self.project,
+            &self.typename,
+            Some(self.cache_path()),
+        )?;
+        self.cache.after_listing(objs.iter().map(|o| o.id()))?;
+        Ok(objs.iter().map(|o| *o.id()).collect())
+    }
+
+    /// Enumerate the refs of every issue, which cob does before retrieving all of them, returning
+    /// how long it took and the number of objects found
+    pub fn time_type_references(
+        &self,
+    ) -> Result<(Duration, usize), super::peer_refs_storage::Error> {
+        let storage = self.local_storage();
+        let start = Instant::now();
+        let refs = storage.type_references(&self.project.urn(), &self.typename)?;
+        Ok((start.elapsed(), refs.len()))
+    }
+
+    /// The number of issues in the issue index, complete or not
+    pub fn indexed_issue_count(&self) -> usize {
+        self.issue_index.lock().unwrap().entries().count()
+    }
+
+    /// The object GitHub issue `number` was imported as, if it has been imported
+    pub fn issue_object_id(&self, number: u64) -> Option<cob::ObjectId> {
+        self.issue_index
+            .lock()
+            .unwrap()
+            .get(number)
+            .and_then(|e| e.object_id())
+    }
+
+    /// Add every issue which is missing from the issue index to it, for monorepos which were
+    /// imported before the index was kept. Returns the number of issues added.
+    pub fn reindex_issues(&self) -> Result<usize, error::Reindex> {
+        let mut added = 0;
+        let issues = self.materialized_issues()?;
+        let mut index = self.issue_index.lock().unwrap();
+        for issue in issues {
+            let number = match issue.github_issue_number() {
+                Some(number) => number,
+                None => continue,
+            };
+            if index.get(number).is_none() {
+                index.record(issue_index::Entry {
+                    number,
+                    object_id: issue.id.to_string(),
+                    // How far the import got is only read for incomplete entries, and by
+                    // incremental imports, which without it apply every event of the issue again
+                    applied: 0,
+                    complete: true,
+                    applied_until: None,
+                })?;
+                added += 1;
+            }
+        }
+        Ok(added)
+    }
+
+    pub fn issue_index_is_empty(&self) -> bool {
+        self.issue_index.lock().unwrap().is_empty()
+    }
+
+    /// Remove the local peer's refs for `object_id`. If `all_peers` is true then the refs of every
+    /// other peer are removed as well, which is the only way to make the object disappear from
+    /// listings in the lite monorepo as the local peer will otherwise still see the remote refs.
+    ///
+    /// Returns the number of refs which were removed.
+    pub fn delete_issue(
+        &self,
+        object_id: &cob::ObjectId,
+        all_peers: bool,
+    ) -> Result<usize, error::Delete> {
+        let some_peer = self.peers.some_peer();
+        let storage = PeerRefsStorage::new(*some_peer, &self.repo).with_layout(self.ref_layout);
+        storage
+            .delete_object_refs(&self.project.urn(), &self.typename, object_id, all_peers)
+            .map_err(error::Delete::from)
+    }
+
+    /// The refs `delete_issue` would remove for `object_id`
+    pub fn issue_refs(
+        &self,
+        object_id: &cob::ObjectId,
+        all_peers: bool,
+    ) -> Result<Vec<String>, error::Delete> {
+        let some_peer = self.peers.some_peer();
+        let storage = PeerRefsStorage::new(*some_peer, &self.repo).with_layout(self.ref_layout);
+        storage
+            .object_ref_names(&self.project.urn(), &self.typename, object_id, all_peers)
+            .map_err(error::Delete::from)
+    }
+
+    pub fn retrieve_issue(
+        &self,
+        object_id: &cob::ObjectId,
+        use_cache: bool,
+    ) -> Result<Option<serde_json::Value>, error::Retrieve> {
+        let storage = self.local_storage();
+        if self.settings.verify_signed_refs {
+            let refs = storage.object_references(&self.project.urn(), &self.typename, object_id)?;
+            for reference in refs.local.iter().chain(refs.remote.iter()) {
+                signed_refs::verify(&self.repo, &self.project.urn(), reference)?;
+            }
+        }
+        if let Some(delegates) = &self.settings.delegate_only {
+            let history = self.delegate_history(object_id, delegates)?;
+            return Ok(history.map(|h| materialize(&h)));
+        }
+        let hit = if use_cache {
+            Some(self.cache.before_access(object_id)?)
+        } else {
+            None
+        };
+        let cache_path = hit.map(|_| self.cache_path());
+        let start = Instant::now();
+        let obj = cob_api::retrieve_object(
+            &storage,
+            &self.repo,
+            &self.project,
+            &self.typename,
+            object_id,
+            cache_path,
+        )?;
+        let elapsed = start.elapsed();
+        self.cache.record_retrieval(hit, elapsed);
+        if let Some(detector) = &self.settings.anomalies {
+            detector.record("retrieve", Some(object_id), elapsed);
+        }
+        if use_cache {
+            self.cache.after_access(object_id)?;
+        }
+        Ok(obj.map(|o| materialize(o.history())))
+    }
+
+    /// The raw automerge history of an issue
+    pub fn issue_history(
+        &self,
+        object_id: &cob::ObjectId,
+    ) -> Result<Option<Vec<u8>>, error::Retrieve> {
+        if let Some(delegates) = &self.settings.delegate_only {
+            let history = self.delegate_history(object_id, delegates)?;
+            return Ok(history.map(|h| h.as_ref().to_vec()));
+        }
+        let storage = self.local_storage();
+        let obj = cob_api::retrieve_object(
+            &storage,
+            &self.repo,
+            &self.project,
+            &self.typename,
+            object_id,
+            None,
+        )?;
+        Ok(obj.map(|o| o.history().as_ref().to_vec()))
+    }
+
+    /// Retrieve a single issue along with its history, without using the cache
+    pub fn materialized_issue(
+        &self,
+        object_id: &cob::ObjectId,
+    ) -> Result<Option<MaterializedIssue>, error::Retrieve> {
+        Ok(self.issue_history(object_id)?.map(|history| {
+            let history = cob::History::Automerge(history);
+            MaterializedIssue {
+                id: *object_id,
+                document: materialize(&history),
+                history: history.as_ref().to_vec(),
+            }
+        }))
+    }
+
+    /// Retrieve every issue along with its history
+    pub fn materialized_issues(&self) -> Result<Vec<MaterializedIssue>, error::List> {
+        let storage = self.local_storage();
+        if let Some(delegates) = &self.settings.delegate_only {
+            let mut issues = Vec::new();
+            for (id, refs) in storage.type_references(&self.project.urn(), &self.typename)? {
+                let tips = refs
+                    .local
+                    .iter()
+                    .chain(refs.remote.iter())
+                    .filter_map(|r| r.target());
+                if let Some(history) = delegate_only::evaluate(&self.repo, tips, delegates)? {
+                    issues.push(MaterializedIssue {
+                        id,
+                        document: materialize(&history),
+                        history: history.as_ref().to_vec(),
+                    });
+                }
+            }
+            return Ok(issues);
+        }
+        let objs = cob_api::retrieve_objects(
+            &storage,
+            &self.repo,
+            &self.project,
+            &self.typename,
+            Some(self.cache_path()),
+        )?;
+        self.cache.after_listing(objs.iter().map(|o| o.id()))?;
+        Ok(objs
+            .iter()
+            .map(|o| MaterializedIssue {
+                id: *o.id(),
+                document: materialize(o.history()),
+                history: o.history().as_ref().to_vec(),
+            })
+            .collect())
+    }
+
+    /// The IDs of every issue, newest (by github issue number) first
+    pub fn issue_ids_by_recency(&self) -> Result<Vec<cob::ObjectId>, error::List> {
+        let mut issues: Vec<(Option<u64>, cob::ObjectId)> = self
+            .materialized_issues()?
+            .iter()
+            .map(|i| (i.github_issue_number(), i.id))
+            .collect();
+        issues.sort_by(|a, b| b.cmp(a));
+        Ok(issues.into_iter().map(|(_, id)| id).collect())
+    }
+
+    /// Move `issue` into the cold store and remove the refs of every peer for it, after which it
+    /// can only be retrieved using `retrieve_archived_issue`
+    pub fn archive_issue(&self, issue: &MaterializedIssue) -> Result<(), error::Archive> {
+        self.cold_store()?.store(issue)?;
+        self.delete_issue(&issue.id, true)?;
+        Ok(())
+    }
+
+    pub fn retrieve_archived_issue(
+        &self,
+        object_id: &cob::ObjectId,
+    ) -> Result<Option<serde_json::Value>, error::Archive> {
+        Ok(self.cold_store()?.document(object_id)?)
+    }
+
+    pub fn cold_store(&self) -> Result<ColdStore, crate::archive::Error> {
+        ColdStore::open(self.cold_store_dir())
+    }
+
+    pub fn cold_store_dir(&self) -> PathBuf {
+        self.root.join("cold_store")
+    }
+
+    pub fn issue_info(
+        &self,
+        object_id: &cob::ObjectId,
+    ) -> Result<Option<cob::ChangeGraphInfo>, error::Retrieve> {
+        let storage = self.local_storage();
+        cob::changegraph_info_for_object(
+            &storage,
+            &self.repo,
+            Either::Right(self.project.clone()),
+            &self.typename,
+            object_id,
+        )
+        .map_err(error::Retrieve::from)
+    }
+
+    /// Pack every object in the underlying repository into a single packfile and every ref into
+    /// `packed-refs`, leaving the git directory as a handful of files rather than one file per
+    /// object and ref.
+    pub fn pack(&self) -> Result<(), error::Pack> {
+        for args in &[
+            &["repack", "-a", "-d", "-q"][..],
+            &["pack-refs", "--all"][..],
+        ] {
+            let output = std::process::Command::new("git")
+                .arg("--git-dir")
+                .arg(self.repo.path())
+                .args(args.iter())
+                .output()?;
+            if !output.status.success() {
+                return Err(error::Pack::GitCommand {
+                    command: args.join(" "),
+                    stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Write a git bundle to `path` containing the refs of every peer for `object_id` along with
+    /// the identities of the project and of the authors of the object's changes, which is
+    /// everything needed to retrieve the object. Returns the names of the refs in the bundle, or
+    /// `None` if there is no such object.
+    pub fn bundle_object(
+        &self,
+        object_id: &cob::ObjectId,
+        path: &std::path::Path,
+    ) -> Result<Option<Vec<String>>, error::Bundle> {
+        let project = self.project.urn();
+        let storage =
+            PeerRefsStorage::new(*self.peers.some_peer(), &self.repo).with_layout(self.ref_layout);
+        let object_refs = storage.object_references(&project, &self.typename, object_id)?;
+        let mut refs: BTreeMap<String, git2::Oid> = BTreeMap::new();
+        for reference in object_refs.local.iter().chain(object_refs.remote.iter()) {
+            if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+                refs.insert(name.to_string(), target);
+            }
+        }
+        if refs.is_empty() {
+            return Ok(None);
+        }
+
+        // Each change has the identity of its author as a parent, which is given the ref librad
+        // keeps the identity's history at
+        let identities: Identities<'_, Person> = (&self.repo).into();
+        let mut pending: Vec<git2::Oid> = refs.values().copied().collect();
+        let mut seen = HashSet::new();
+        let mut authors = BTreeMap::new();
+        while let Some(oid) = pending.pop() {
+            if !seen.insert(oid) {
+                continue;
+            }
+            let commit = self.repo.find_commit(oid)?;
+            if commit.tree()?.get_name("change").is_some() {
+                pending.extend(commit.parent_ids());
+            } else if let Ok(person) = identities.get(oid) {
+                let name = format!("refs/namespaces/{}/refs/rad/id", person.urn().encode_id());
+                let tip = match self.repo.find_reference(&name) {
+                    Ok(reference) => reference.target().unwrap_or(oid),
+                    Err(e) if e.code() == git2::ErrorCode::NotFound => oid,
+                    Err(e) => return Err(e.into()),
+                };
+                authors.insert(name, tip);
+            }
+        }
+        refs.extend(authors);
+        // The project identity has no ref here, as nothing looks it up by name
+        refs.insert(
+            format!("refs/namespaces/{}/refs/rad/id", project.encode_id()),
+            self.project.content_id.into(),
+        );
+
+        // The bundle is made from a scratch repository borrowing the objects of this one, so that
+        // the refs it needs aren't created here
+        let scratch =
+            std::env::temp_dir().join(format!("collab-stress-test-bundle-{}", std::process::id()));
+        let result = self.bundle_refs(&scratch, &refs, path);
+        crate::fs::remove_dir_all(&scratch).ok();
+        result?;
+        Ok(Some(refs.into_iter().map(|(name, _)| name).collect()))
+    }
+
+    fn bundle_refs(
+        &self,
+        scratch: &std::path::Path,
+        refs: &BTreeMap<String, git2::Oid>,
+        path: &std::path::Path,
+    ) -> Result<(), error::Bundle> {
+        git2::Repository::init_bare(scratch)?;
+        let info = scratch.join("objects").join("info");
+        crate::fs::create_dir_all(&info)?;
+        crate::fs::write(
+            info.join("alternates"),
+            format!("{}\n", self.repo.path().join("objects").display()),
+        )?;
+        // Opened again so that it reads the alternates
+        let repo = git2::Repository::open_bare(scratch)?;
+        for (name, target) in refs {
+            repo.reference(name, *target, true, "bundle")?;
+        }
+
+        let output = std::process::Command::new("git")
+            .arg("--git-dir")
+            .arg(scratch)
+            .args(&["bundle", "create"])
+            .arg(path)
+            .args(refs.keys())
+            .output()?;
+        if !output.status.success() {
+            return Err(error::Bundle::GitCommand {
+                command: "bundle create".to_string(),
+                stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+            });
+        }
+        Ok(())
+    }
+
+    /// The number of changes this `LiteMonorepo` has created since it was opened
+    pub fn changes_created(&self) -> u64 {
+        self.changes_created
+    }
+
+    /// The size on disk of the underlying git repository
+    pub fn git_size(&self) -> Result<u64, std::io::Error> {
+        crate::fs::dir_size(self.repo.path())
+    }
+
+    pub fn project(&self) -> &Project {
+        &self.project
+    }
+
+    pub fn project_urn(&self) -> Urn {
+        self.project.urn()
+    }
+
+    /// The refs storage of `peer`, which sees the refs of every peer
+    pub fn refs_storage(&self, peer: link_crypto::PeerId) -> PeerRefsStorage<'_> {
+        PeerRefsStorage::new(peer, &self.repo).with_layout(self.ref_layout)
+    }
+
+    pub fn ref_layout(&self) -> RefLayout {
+        self.ref_layout
+    }
+
+    /// The type name objects are created as
+    pub fn typename(&self) -> &cob::TypeName {
+        &self.typename
+    }
+
+    /// The schema objects are created with
+    pub fn object_schema(&self) -> &serde_json::Value {
+        &self.schema
+    }
+
+    /// The refs storage of the local peer, which only sees the refs of the peers it tracks
+    fn local_storage(&self) -> PeerRefsStorage<'_> {
+        self.refs_storage(*self.peers.some_peer())
+            .with_tracking(self.tracking.as_ref())
+    }
+
+    /// The history of `object_id` made of only the changes signed by `delegates`, see
+    /// [`crate::delegate_only`]
+    fn delegate_history(
+        &self,
+        object_id: &cob::ObjectId,
+        delegates: &HashSet<link_crypto::PeerId>,
+    ) -> Result<Option<cob::History>, error::Retrieve> {
+        let storage = self.local_storage();
+        let refs = storage.object_references(&self.project.urn(), &self.typename, object_id)?;
+        let tips = refs
+            .local
+            .iter()
+            .chain(refs.remote.iter())
+            .filter_map(|r| r.target());
+        Ok(delegate_only::evaluate(&self.repo, tips, delegates)?)
+    }
+
+    /// The peers whose keys are delegates of the project
+    pub fn delegates(&self) -> Vec<link_crypto::PeerId> {
+        let mut delegates = Vec::new();
+        for delegation in self.project.delegations().iter() {
+            match delegation {
+                Either::Left(key) => delegates.push(link_crypto::PeerId::from(*key)),
+                Either::Right(person) => delegates.extend(
+                    person
+                        .delegations()
+                        .iter()
+                        .map(|key| link_crypto::PeerId::from(*key)),
+                ),
+            }
+        }
+        delegates
+    }
+
+    /// Work with the project called `name_or_urn`, or with that URN, rather than the project the
+    /// monorepo was created with
+    pub fn select_project(&mut self, name_or_urn: &str) -> Result<(), error::Projects> {
+        let projects = Projects::load(self.root.join(projects::PROJECTS))?;
+        let (name, entry) = projects
+            .find(name_or_urn)
+            .ok_or_else(|| error::Projects::UnknownProject(name_or_urn.to_string()))?;
+        let identities: Identities<'_, Project> = (&self.repo).into();
+        self.project = identities.get(entry.oid.into())?;
+        self.project_name = name.to_string();
+        Ok(())
+    }
+
+    /// Create a project called `name` delegating to the key of every peer, as the project the
+    /// monorepo was created with does, and register it. Objects are still created in the current
+    /// project.
+    pub fn create_project(&mut self, name: &str) -> Result<Project, error::Projects> {
+        let mut projects = Projects::load(self.root.join(projects::PROJECTS))?;
+        if projects.contains(name) {
+            return Err(error::Projects::ProjectExists(name.to_string()));
+        }
+        let identities: Identities<'_, Project> = (&self.repo).into();
+        let key = self.peers.iter().next().unwrap().1.clone();
+        let project = identities.create(
+            ProjectPayload::new(ProjectSubject {
+                name: name.into(),
+                description: None,
+                default_branch: None,
+            }),
+            Indirect::try_from_iter(self.peers.iter().map(|(_, k)| Either::Left(k.public())))
+                .unwrap(),
+            &key,
+        )?;
+        projects.register(name, &project)?;
+        Ok(project)
+    }
+
+    /// The name of the project objects are created in and retrieved from
+    pub fn project_name(&self) -> &str {
+        &self.project_name
+    }
+
+    /// Revise the project identity, adding the keys of `add` to its delegations and removing those
+    /// of `remove`. The revision is made by a peer of the monorepo which is a delegate of the
+    /// current revision and signed by every other peer which is a delegate of either, so that
+    /// delegates can be rotated as long as one of them stays. Returns whether the delegations
+    /// changed.
+    pub fn edit_project_delegates(
+        &mut self,
+        add: &[link_crypto::PeerId],
+        remove: &[link_crypto::PeerId],
+    ) -> Result<bool, error::EditProject> {
+        let current = self.delegates();
+        let mut delegations: Vec<Either<link_crypto::PublicKey, Person>> = Vec::new();
+        for delegation in self.project.delegations().iter() {
+            match delegation {
+                Either::Left(key) if remove.contains(&link_crypto::PeerId::from(*key)) => {}
+                Either::Left(key) => delegations.push(Either::Left(*key)),
+                Either::Right(person) => delegations.push(Either::Right(person.clone())),
+            }
+        }
+        for peer in add {
+            if !current.contains(peer) {
+                delegations.push(Either::Left(*peer.as_public()));
+            }
+        }
+        if delegations.is_empty() {
+            return Err(error::EditProject::NoDelegates);
+        }
+        let changed =
+            add.iter().any(|p| !current.contains(p)) || remove.iter().any(|p| current.contains(p));
+        if !changed {
+            return Ok(false);
+        }
+
+        let mut signers = self.peers.iter().filter(|(p, _)| current.contains(p));
+        let (_, key) = signers.next().ok_or(error::EditProject::NoSigner)?;
+        let identities: Identities<'_, Project> = (&self.repo).into();
+        let mut project = identities.update(
+            self.project.clone(),
+            None::<ProjectPayload>,
+            Some(Indirect::try_from_iter(delegations.into_iter()).unwrap()),
+            key,
+        )?;
+        let added = self
+            .peers
+            .iter()
+            .filter(|(p, _)| add.contains(p) && !current.contains(p));
+        for (_, key) in signers.chain(added) {
+            project = identities.update(project, None::<ProjectPayload>, None::<Indirect>, key)?;
+        }
+        if self.project_name == DEFAULT_PROJECT {
+            let project_oid_bytes = serde_json::to_vec(&project.content_id)?;
+            crate::fs::write_atomic(self.root.join("project_oid"), project_oid_bytes)?;
+        }
+        Projects::load(self.root.join(projects::PROJECTS))?
+            .register(&self.project_name, &project)?;
+        self.project = project;
+        Ok(true)
+    }
+
+    /// Only evaluate objects using the changes signed by the keys of `delegates`, or every change
+    /// if `None`, see [`crate::delegate_only`]. The changes of the local peer are left out too
+    /// unless it is one of `delegates`. Objects are evaluated without the cache in this mode.
+    pub fn set_delegate_only(&mut self, delegates: Option<Vec<link_crypto::PeerId>>) {
+        self.settings.delegate_only = delegates.map(|d| d.into_iter().collect());
+    }
+
+    /// Each GitHub user who has been assigned a peer and their peer, ordered by user
+    pub fn peer_assignments(&self) -> Vec<(GithubUserId, link_crypto::PeerId)> {
+        let mut assignments: Vec<(GithubUserId, link_crypto::PeerId)> = self
+            .peer_assignments
+            .lock()
+            .unwrap()
+            .assignments()
+            .map(|(user, peer)| (user.clone(), *peer))
+            .collect();
+        assignments.sort_by(|(a, _), (b, _)| a.0.cmp(&b.0));
+        assignments
+    }
+
+    pub fn peer_ids(&self) -> impl Iterator<Item = &link_crypto::PeerId> {
+        self.peers.iter().map(|(p, _)| p)
+    }
+
+    pub fn tracking(&self) -> Option<&Tracking> {
+        self.tracking.as_ref()
+    }
+
+    /// Change the peers the local peer tracks for the lifetime of this `LiteMonorepo`, see
+    /// [`Self::save_tracking`] to make the change permanent
+    pub fn set_tracking(&mut self, tracking: Option<Tracking>) {
+        self.tracking = tracking;
+    }
+
+    pub fn save_tracking(&self) -> Result<(), TrackingError> {
+        let path = self.root.join("tracking");
+        match &self.tracking {
+            Some(tracking) => tracking.save(path),
+            None => {
+                if crate::fs::exists(&path)? {
+                    crate::fs::remove_file(&path)?;
+                }
+                Ok(())
+            }
+        }
+    }
+
+    /// Skew the clocks of the peers when creating changes from now on
+    pub fn set_clock_skew(&mut self, skew: Option<ClockSkew>) {
+        self.settings.clock_skew = skew;
+    }
+
+    fn skew_of(&self, peer: &link_crypto::PeerId) -> i64 {
+        self.settings
+            .clock_skew
+            .map(|s| s.offset_millis(peer))
+            .unwrap_or(0)
+    }
+
+    /// The clock of the change `peer` makes for something which happened on GitHub `at`. Seeded
+    /// monorepos stamp it with `at`, moved by the skew of the peer, rather than with the time it
+    /// is made, so that imports of the same data make the same changes.
+    fn clock_of(&self, peer: &link_crypto::PeerId, at: DateTime<Utc>) -> Clock {
+        let skew = self.skew_of(peer);
+        match self.seed {
+            Some(_) => Clock::Pinned(at + chrono::Duration::milliseconds(skew)),
+            None => Clock::Skewed(skew),
+        }
+    }
+
+    /// Make changes with a new automerge actor each, or with an actor per peer, from now on, see
+    /// `crate::actor_ids`
+    pub fn set_actor_ids(&mut self, actor_ids: ActorIds) {
+        self.settings.actor_ids = actor_ids;
+    }
+
+    /// The actor the changes of `peer` are made by, `None` for a new one. New actors are derived
+    /// from the seed of a seeded monorepo and the number of changes made so far, so that the
+    /// same changes are made by the same actors from one import to the next.
+    fn actor_of(&self, peer: &link_crypto::PeerId) -> Option<automerge::ActorId> {
+        match self.settings.actor_ids {
+            ActorIds::Fresh => self
+                .seed
+                .map(|seed| actor_ids::seeded_actor(seed, peer, self.changes_created)),
+            ActorIds::PerPeer => Some(actor_ids::actor_of(peer)),
+        }
+    }
+
+    /// Skip issues which were completely imported before and continue those which were partially
+    /// imported, rather than importing them again
+    pub fn set_resume_imports(&mut self, resume: bool) {
+        self.settings.resume_imports = resume;
+    }
+
+    /// Record the changes each issue is imported as from now on in `import_log/`, see
+    /// `crate::import_log`
+    pub fn set_import_log(&mut self, enabled: bool) {
+        self.settings.import_log = if enabled {
+            Some(ImportLog::new(&self.root))
+        } else {
+            None
+        };
+    }
+
+    /// The import log of this monorepo, whether or not imports are being recorded in it
+    pub fn import_log(&self) -> ImportLog {
+        ImportLog::new(&self.root)
+    }
+
+    /// Make each comment which is followed by a comment from another peer concurrently with that
+    /// comment, branching the change graph, rather than making every change on top of the last
+    pub fn set_concurrent_comments(&mut self, concurrent: bool) {
+        self.settings.concurrent_comments = concurrent;
+    }
+
+    /// Write every `every`th change of an object as a snapshot of the whole document, saved by
+    /// automerge with every change so far, rather than as just the change. `None` or 0 writes
+    /// every change as it is.
+    pub fn set_snapshot_every(&mut self, every: Option<usize>) {
+        self.settings.snapshot_every = every.filter(|e| *e > 0);
+    }
+
+    /// Move the files of the monorepo into the database of `files` when it's dropped, see
+    /// [`MonorepoFiles`]
+    pub fn set_files(&mut self, files: Option<MonorepoFiles>) {
+        self.files = files;
+    }
+
+    /// Inject faults while importing, see `crate::chaos`
+    pub fn set_chaos(&mut self, chaos: Option<Arc<Chaos>>) {
+        self.settings.chaos = chaos;
+    }
+
+    pub fn chaos(&self) -> Option<&Arc<Chaos>> {
+        self.settings.chaos.as_ref()
+    }
+
+    /// Exclude, collapse or reassign the comments of bots before importing each issue, see
+    /// `crate::bots`
+    pub fn set_bots(&mut self, bots: Option<Arc<Bots>>) {
+        self.settings.bots = bots;
+    }
+
+    /// Import issues as they were just before `until`, leaving out issues created since and the
+    /// comments and events made since, see `crate::window`
+    pub fn set_import_until(&mut self, until: Option<DateTime<Utc>>) {
+        self.settings.import_until = until;
+    }
+
+    /// Follow this fraction of comments with a change violating the schema, see
+    /// `crate::invalid_changes`
+    pub fn set_inject_invalid(&mut self, ratio: Option<f64>) {
+        self.settings.inject_invalid = ratio;
+    }
+
+    /// Follow this fraction of comments with a change whose signature or author is forged, see
+    /// `crate::tampering`
+    pub fn set_tamper_signatures(&mut self, ratio: Option<f64>) {
+        self.settings.tamper_signatures = ratio;
+    }
+
+    /// Report the latency of every create, update and retrieval to `detector`, see
+    /// `crate::anomalies`
+    pub fn set_anomaly_detector(&mut self, detector: Option<Arc<Detector>>) {
+        self.settings.anomalies = detector;
+    }
+
+    fn chaos_before_change(&self) -> Result<(), error::Import> {
+        if let Some(chaos) = &self.settings.chaos {
+            chaos.git_error("before writing a change")?;
+        }
+        Ok(())
+    }
+
+    fn chaos_after_change(&self) -> Result<(), error::Import> {
+        if let Some(chaos) = &self.settings.chaos {
+            chaos.delay_cache_write();
+            chaos.git_error("after writing a change")?;
+        }
+        Ok(())
+    }
+
+    /// `change`, or a snapshot of `previous` and `change` if snapshots are enabled and it's due
+    fn compact(&self, previous: &cob::History, change: cob::History) -> cob::History {
+        match self.settings.snapshot_every {
+            Some(every) => snapshot_if_due(previous, change, every),
+            None => change,
+        }
+    }
+
+    /// Add the comments and events which issues imported before have gained since to their
+    /// objects, rather than skipping or importing them again. Partially imported issues are
+    /// continued as when resuming.
+    pub fn set_incremental_imports(&mut self, incremental: bool) {
+        self.settings.incremental_imports = incremental;
+    }
+
+    /// Record in each issue imported from now on that only its creator may change the title and
+    /// body
+    pub fn set_import_acl(&mut self, acl: bool) {
+        self.settings.import_acl = acl;
+    }
+
+    /// Lay out the comments of issues imported from now on using `layout`
+    pub fn set_import_layout(&mut self, layout: Layout) {
+        self.settings.import_layout = layout;
+    }
+
+    /// Check the refs of an object against the signed refs of the peers which own them each time
+    /// it is retrieved
+    pub fn set_verify_signed_refs(&mut self, verify: bool) {
+        self.settings.verify_signed_refs = verify;
+    }
+
+    /// Regenerate the signed refs of every peer. Returns the total number of refs signed.
+    pub fn sign_refs(&self) -> Result<usize, signed_refs::Error> {
+        let project = self.project.urn();
+        let mut signed = 0;
+        for (peer, key) in self.peers.iter() {
+            signed += signed_refs::sign(&self.repo, &project, peer, key)?;
+        }
+        Ok(signed)
+    }
+
+    pub fn open_timings(&self) -> OpenTimings {
+        self.open_timings
+    }
+
+    /// The number of references in the underlying repository
+    pub fn ref_count(&self) -> Result<usize, git2::Error> {
+        let mut count = 0;
+        for reference in self.repo.references()? {
+            reference?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// The directory the monorepo is stored in
+    pub fn root(&self) -> &std::path::Path {
+        &self.root
+    }
+
+    /// The underlying git repository, for tools which work with refs and objects directly
+    pub fn repo(&self) -> &git2::Repository {
+        &self.repo
+    }
+
+    pub fn cache(&self) -> &Cache {
+        &self.cache
+    }
+
+    fn cache_path(&self) -> std::path::PathBuf {
+        self.cache.dir().to_path_buf()
+    }
+}
+
+impl std::fmt::Debug for LiteMonorepo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "LiteMonorepo{{ {} }}", self.root.display())
+    }
+}
+
+pub fn materialize(history: &cob::History) -> serde_json::Value {
+    let backend = automerge::Backend::load(history.as_ref().to_vec()).unwrap();
+    let mut frontend = automerge::Frontend::new();
+    frontend.apply_patch(backend.get_patch().unwrap()).unwrap();
+    frontend.state().to_json()
+}
+
+/// The history made up of the changes of each of `histories`
+pub fn concat(histories: &[&cob::History]) -> cob::History {
+    cob::History::Automerge(histories.iter().flat_map(|h| h.as_ref().to_vec()).collect())
+}
+
+/// The time the changes made by a frontend are stamped with
+#[derive(Debug, Clone, Copy)]
+pub enum Clock {
+    /// The real time, this many milliseconds away
+    Skewed(i64),
+    /// A fixed time, so that the same change is made from one import to the next
+    Pinned(DateTime<Utc>),
+}
+
+impl Default for Clock {
+    fn default() -> Clock {
+        Clock::Skewed(0)
+    }
+}
+
+/// A frontend whose changes are timestamped by `clock`, made by `actor` or by a new actor if it is
+/// `None`
+fn frontend(clock: Clock, actor: Option<&automerge::ActorId>) -> automerge::Frontend {
+    let mut frontend = match clock {
+        Clock::Skewed(0) => automerge::Frontend::new(),
+        Clock::Skewed(skew_millis) => {
+            automerge::Frontend::new_with_timestamper(Box::new(move || {
+                Some(chrono::Utc::now().timestamp_millis() + skew_millis)
+            }))
+        }
+        Clock::Pinned(at) => {
+            let millis = at.timestamp_millis();
+            automerge::Frontend::new_with_timestamper(Box::new(move || Some(millis)))
+        }
+    };
+    if let Some(actor) = actor {
+        frontend.actor_id = actor.clone();
+    }
+    frontend
+}
+
+pub fn init_issue_change(
+    issue: &DownloadedIssue,
+    author_urn: &Urn,
+    acl: bool,
+    layout: Layout,
+    clock: Clock,
+    actor: Option<&automerge::ActorId>,
+) -> cob::History {
+    let mut doc = frontend(clock, actor);
+    let mut backend = automerge::Backend::new();
+    let actor = doc.actor_id.to_hex_string();
+    let (_, change) = doc
+        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
+            if acl {
+                d.add_change(LocalChange::set(
+                    automerge::Path::root().key("acl"),
+                    automerge::Value::Map(HashMap::new()),
+                ))?;
+                for field in ACL_FIELDS {
+                    d.add_change(LocalChange::set(
+                        automerge::Path::root().key("acl").key(*field),
+                        automerge::Value::Primitive(automerge::Primitive::Str(
+                            author_urn.to_string().into(),
+                        )),
+                    ))?;
+                }
+                d.add_change(LocalChange::set(
+                    automerge::Path::root().key("actors"),
+                    automerge::Value::Map(HashMap::new()),
+                ))?;
+                record_actor(d, &actor, author_urn)?;
+            }
+            d.add_change(LocalChange::set(
+                automerge::Path::root().key("author_urn"),
+                automerge::Value::Primitive(automerge::Primitive::Str(
+                    author_urn.to_string().into(),
+                )),
+            ))?;
+            d.add_change(LocalChange::set(
+                automerge::Path::root().key("title"),
+                to_text(issue.title.as_str()),
+            ))?;
+            if let Some(body) = &issue.body {
+                d.add_change(LocalChange::set(
+                    automerge::Path::root().key("body"),
+                    to_text(body.as_str()),
+                ))?;
+            }
+            d.add_change(LocalChange::set(
+                automerge::Path::root().key("created_at"),
+                automerge::Value::Primitive(automerge::Primitive::Str(
+                    issue.created_at.to_rfc3339().into(),
+                )),
+            ))?;
+            // Issues are always opened, the events take them to their final state. Without
+            // events the final state is all there is.
+            let state = if issue.events.is_empty() {
+                issue.state.as_str()
+            } else {
+                "OPEN"
+            };
+            d.add_change(LocalChange::set(
+                automerge::Path::root().key("state"),
+                automerge::Value::Primitive(automerge::Primitive::Str(state.into())),
+            ))?;
+            layout::init(d, layout)?;
+            d.add_change(LocalChange::set(
+                automerge::Path::root().key("github_issue_number"),
+                automerge::Value::Primitive(automerge::Primitive::Str(
+                    issue.number.to_string().into(),
+                )),
+            ))?;
+            Ok(())
+        })
+        .unwrap();
+    let (_, change) = backend.apply_local_change(change.unwrap()).unwrap();
+    cob::History::Automerge(change.raw_bytes().to_vec())
+}
+
+/// If `change` makes the number of changes in `previous` a multiple of `every`, a document saved
+/// with every change of `previous` and `change`, otherwise `change`. Earlier snapshots in
+/// `previous` repeat changes, so changes are counted once each.
+pub fn snapshot_if_due(
+    previous: &cob::History,
+    change: cob::History,
+    every: usize,
+) -> cob::History {
+    let (cob::History::Automerge(previous), cob::History::Automerge(new)) = (previous, &change);
+    let mut bytes = previous.clone();
+    bytes.extend_from_slice(new);
+    let mut backend = automerge::Backend::new();
+    backend
+        .apply_changes(automerge::Change::load_document(&bytes).unwrap())
+        .unwrap();
+    if backend.get_changes(&[]).len() % every != 0 {
+        return change;
+    }
+    cob::History::Automerge(backend.save().unwrap())
+}
+
+pub fn add_comment_change(
+    comment: &DownloadedComment,
+    commentor_urn: &Urn,
+    previous_history: &cob::History,
+    clock: Clock,
+    actor: Option<&automerge::ActorId>,
+) -> cob::History {
+    let mut frontend = frontend(clock, actor);
+    let mut backend = automerge::Backend::new();
+    let cob::History::Automerge(hist) = previous_history;
+    let changes: Vec<automerge::Change> = automerge::Change::load_document(hist).unwrap();
+    let patch = backend.apply_changes(changes).unwrap();
+    frontend.apply_patch(patch).unwrap();
+    let actor = frontend.actor_id.to_hex_string();
+
+    let (_, change) = frontend
+        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
+            if has_acl(d) {
+                record_actor(d, &actor, commentor_urn)?;
+            }
+            let layout = match d.value_at_path(&automerge::Path::root().key("layout")) {
+                Some(automerge::Value::Primitive(automerge::Primitive::Str(l))) => {
+                    l.parse().unwrap_or_default()
+                }
+                _ => Layout::default(),
+            };
+            layout::add(
+                d,
+                layout,
+                &layout::NewComment {
+                    body: comment.body.as_str(),
+                    commenter_urn: commentor_urn.to_string(),
+                    github_id: comment.id.as_str(),
+                    created_at: comment.created_at.to_rfc3339(),
+                },
+            )?;
+            Ok(())
+        })
+        .unwrap();
+    let (_, change) = backend.apply_local_change(change.unwrap()).unwrap();
+    cob::History::Automerge(change.raw_bytes().to_vec())
+}
+
+fn edit_title_change(
+    title: &str,
+    editor_urn: &Urn,
+    previous_history: &cob::History,
+    clock: Clock,
+    actor: Option<&automerge::ActorId>,
+) -> cob::History {
+    let mut frontend = frontend(clock, actor);
+    let mut backend = automerge::Backend::new();
+    let cob::History::Automerge(hist) = previous_history;
+    let changes: Vec<automerge::Change> = automerge::Change::load_document(hist).unwrap();
+    let patch = backend.apply_changes(changes).unwrap();
+    frontend.apply_patch(patch).unwrap();
+    let actor = frontend.actor_id.to_hex_string();
+
+    let (_, change) = frontend
+        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
+            if has_acl(d) {
+                record_actor(d, &actor, editor_urn)?;
+            }
+            d.add_change(LocalChange::set(
+                automerge::Path::root().key("title"),
+                to_text(title),
+            ))?;
+            Ok(())
+        })
+        .unwrap();
+    let (_, change) = backend.apply_local_change(change.unwrap()).unwrap();
+    cob::History::Automerge(change.raw_bytes().to_vec())
+}
+
+/// A change setting `marker`, which also violates the schema in the way of `violation` if given
+fn marker_change(
+    marker: &str,
+    violation: Option<Violation>,
+    previous_history: &cob::History,
+    clock: Clock,
+) -> cob::History {
+    let mut frontend = frontend(clock, None);
+    let mut backend = automerge::Backend::new();
+    let cob::History::Automerge(hist) = previous_history;
+    let changes: Vec<automerge::Change> = automerge::Change::load_document(hist).unwrap();
+    let patch = backend.apply_changes(changes).unwrap();
+    frontend.apply_patch(patch).unwrap();
+
+    let (_, change) = frontend
+        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
+            d.add_change(LocalChange::set(
+                automerge::Path::root().key(marker),
+                automerge::Value::Primitive(automerge::Primitive::Boolean(true)),
+            ))?;
+            match violation {
+                Some(Violation::WrongType) => d.add_change(LocalChange::set(
+                    automerge::Path::root().key("github_issue_number"),
+                    automerge::Value::Primitive(automerge::Primitive::Int(0)),
+                ))?,
+                Some(Violation::MissingField) => d.add_change(LocalChange::delete(
+                    automerge::Path::root().key("author_urn"),
+                ))?,
+                None => {}
+            }
+            Ok(())
+        })
+        .unwrap();
+    let (_, change) = backend.apply_local_change(change.unwrap()).unwrap();
+    cob::History::Automerge(change.raw_bytes().to_vec())
+}
+
+fn edit_text_change(
+    field: &str,
+    edits: &[TextEdit],
+    editor_urn: &Urn,
+    previous_history: &cob::History,
+    clock: Clock,
+    actor: Option<&automerge::ActorId>,
+) -> cob::History {
+    let mut frontend = frontend(clock, actor);
+    let mut backend = automerge::Backend::new();
+    let cob::History::Automerge(hist) = previous_history;
+    let changes: Vec<automerge::Change> = automerge::Change::load_document(hist).unwrap();
+    let patch = backend.apply_changes(changes).unwrap();
+    frontend.apply_patch(patch).unwrap();
+    let actor = frontend.actor_id.to_hex_string();
+
+    let (_, change) = frontend
+        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
+            if has_acl(d) {
+                record_actor(d, &actor, editor_urn)?;
+            }
+            let path = automerge::Path::root().key(field);
+            if !matches!(d.value_at_path(&path), Some(automerge::Value::Text(_))) {
+                d.add_change(LocalChange::set(path.clone(), to_text("")))?;
+            }
+            for edit in edits {
+                match edit {
+                    TextEdit::Insert { at, text } => {
+                        for (i, c) in text.chars().enumerate() {
+                            d.add_change(LocalChange::insert(
+                                path.clone().index((at + i) as u32),
+                                automerge::Value::Primitive(automerge::Primitive::Str(
+                                    c.to_string().into(),
+                                )),
+                            ))?;
+                        }
+                    }
+                    TextEdit::Delete { at, len } => {
+                        for _ in 0..*len {
+                            d.add_change(LocalChange::delete(path.clone().index(*at as u32)))?;
+                        }
+                    }
+                }
+            }
             Ok(())
         })
         .unwrap();
@@ -400,6 +2778,135 @@ fn add_comment_change(
     cob::History::Automerge(change.raw_bytes().to_vec())
 }
 
-fn to_text(s: &str) -> automerge::Value {
+/// A change which applies a label or state change to the issue, or `None` if the event changes
+/// nothing, e.g. removing a label the issue doesn't have
+fn event_change(
+    kind: &EventKind,
+    actor_urn: &Urn,
+    previous_history: &cob::History,
+    clock: Clock,
+    actor: Option<&automerge::ActorId>,
+) -> Option<cob::History> {
+    let mut frontend = frontend(clock, actor);
+    let mut backend = automerge::Backend::new();
+    let cob::History::Automerge(hist) = previous_history;
+    let changes: Vec<automerge::Change> = automerge::Change::load_document(hist).unwrap();
+    let patch = backend.apply_changes(changes).unwrap();
+    frontend.apply_patch(patch).unwrap();
+    let actor = frontend.actor_id.to_hex_string();
+
+    let (_, change) = frontend
+        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
+            if has_acl(d) {
+                record_actor(d, &actor, actor_urn)?;
+            }
+            let labels = automerge::Path::root().key("labels");
+            match kind {
+                EventKind::Closed | EventKind::Reopened => {
+                    let state = if *kind == EventKind::Closed {
+                        "CLOSED"
+                    } else {
+                        "OPEN"
+                    };
+                    d.add_change(LocalChange::set(
+                        automerge::Path::root().key("state"),
+                        automerge::Value::Primitive(automerge::Primitive::Str(state.into())),
+                    ))?;
+                }
+                EventKind::Labeled { label } => {
+                    if d.value_at_path(&labels).is_none() {
+                        d.add_change(LocalChange::set(
+                            labels.clone(),
+                            automerge::Value::Map(HashMap::new()),
+                        ))?;
+                    }
+                    d.add_change(LocalChange::set(
+                        labels.key(label.as_str()),
+                        automerge::Value::Primitive(automerge::Primitive::Boolean(true)),
+                    ))?;
+                }
+                EventKind::Unlabeled { label } => {
+                    let path = labels.key(label.as_str());
+                    if d.value_at_path(&path).is_some() {
+                        d.add_change(LocalChange::delete(path))?;
+                    }
+                }
+            }
+            Ok(())
+        })
+        .unwrap();
+    let (_, change) = backend.apply_local_change(change?).unwrap();
+    Some(cob::History::Automerge(change.raw_bytes().to_vec()))
+}
+
+/// How far the import of issue `number` has got once a change for one of its comments or events
+/// has been written, as recorded in the issue index
+#[derive(Clone, Copy)]
+struct Progress {
+    number: u64,
+    applied: usize,
+    applied_until: Option<DateTime<Utc>>,
+}
+
+fn update_time(update: &Either<&DownloadedComment, &DownloadedEvent>) -> DateTime<Utc> {
+    match update {
+        Either::Left(comment) => comment.created_at,
+        Either::Right(event) => event.created_at,
+    }
+}
+
+/// The indices of the `updates` of an issue which haven't been applied to `object`, the object it
+/// was imported as with the index entry `entry`. A comment is new if no comment in the object has
+/// its ID. An event is new if it was made after the latest update applied, or after the update
+/// at the position the import got to for entries which don't record when that was.
+fn new_updates(
+    updates: &[Either<&DownloadedComment, &DownloadedEvent>],
+    object: &cob::CollaborativeObject,
+    entry: &issue_index::Entry,
+) -> Vec<usize> {
+    let doc = materialize(object.history());
+    let imported: HashSet<String> = layout::comments(&doc)
+        .into_iter()
+        .filter_map(|c| c.github_id)
+        .collect();
+    let applied_until = entry.applied_until.or_else(|| {
+        entry
+            .applied
+            .checked_sub(1)
+            .and_then(|i| updates.get(i))
+            .map(update_time)
+    });
+    updates
+        .iter()
+        .enumerate()
+        .filter(|(_, update)| match update {
+            Either::Left(comment) => !imported.contains(&comment.id),
+            Either::Right(event) => applied_until.map_or(true, |t| event.created_at > t),
+        })
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// The fields which only the creator of an issue may change when importing with an ACL
+const ACL_FIELDS: &[&str] = &["title", "body"];
+
+fn has_acl(d: &mut dyn automerge::MutableDocument) -> bool {
+    d.value_at_path(&automerge::Path::root().key("acl"))
+        .is_some()
+}
+
+/// Record that changes made by `actor` were made by the peer with identity `urn`
+fn record_actor(
+    d: &mut dyn automerge::MutableDocument,
+    actor: &str,
+    urn: &Urn,
+) -> Result<(), automerge::InvalidChangeRequest> {
+    d.add_change(LocalChange::set(
+        automerge::Path::root().key("actors").key(actor),
+        automerge::Value::Primitive(automerge::Primitive::Str(urn.to_string().into())),
+    ))
+}
+
+pub fn to_text(s: &str) -> automerge::Value {
     automerge::Value::Text(s.chars().map(|c| c.to_string().into()).collect())
 }
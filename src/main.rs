@@ -1,122 +1,1818 @@
 #![feature(async_closure)]
 #![feature(path_try_exists)]
 
-use std::path::PathBuf;
+use std::cell::Cell;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Instant;
 
 use clap::Clap;
 use cob::ObjectId;
 use indicatif::{ProgressBar, ProgressStyle};
 
-mod download;
-mod downloaded_issue;
-mod graphql;
-mod repo_name;
-use repo_name::RepoName;
-mod lite_monorepo;
-use lite_monorepo::LiteMonorepo;
-mod peer_assignments;
-mod peer_identities;
-mod peer_refs_storage;
-mod peers;
-
-#[derive(Debug, Clone, Hash, Eq, PartialEq, serde::Serialize, serde::Deserialize)]
-struct GithubUserId(String);
+use collab_stress_test::{
+    access_pattern, acl, actor_ids, anomalies, archive, audit, authorship, batching, bench,
+    bisect_perf, blame, bots,
+    cache::ByteSize,
+    cache_backends, cache_stats, chaos, clock_skew, deletion, determinism, disk_full,
+    download::{self, IssueStorage},
+    download_stats, downloaded_issue, dry_run, duplicate_delivery, estimate, exec, export,
+    failure_taxonomy, fs, fuzz, graphql, history_log, import, index_refs, interleaved,
+    invalid_changes, layout,
+    lite_monorepo::{self, LiteMonorepo},
+    maintain,
+    memory_refs::{self, MemoryRefs},
+    migration_archive, minimize, moderation, monorepo_config, object_scaling, object_store, output,
+    parallel, peer_info, peer_refs_storage, peer_scaling, profiles, project_info, projects,
+    ref_advertisement, ref_enumeration, render, replay, replication, replication_simulation,
+    repo_name::RepoName,
+    repo_stats, repro, results, retention, retry, runs, scenario, schema_cost, schema_strictness,
+    search, serve, snapshots,
+    sqlite_storage::{MonorepoFiles, SqliteStorage},
+    status, tampering, text_edits,
+    tracking::Tracking,
+    verbose, verify, watchdog, window, FAILURE_LOG,
+};
 
 #[derive(Clap)]
 struct Args {
     /// The directory
     #[clap(short, long, default_value = "./data")]
     data_dir: PathBuf,
+    /// Only print results
+    #[clap(short, long, conflicts_with = "verbose")]
+    quiet: bool,
+    /// Print the progress of each issue or object and how long it took. Pass twice to also show
+    /// debug logs.
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: u64,
+    #[clap(flatten)]
+    storage: StorageOptions,
+    #[clap(flatten)]
+    cache: CacheOptions,
     #[clap(subcommand)]
     command: Command,
 }
 
+#[derive(Clap)]
+struct StorageOptions {
+    /// Store downloaded issues, and the files and cob cache of the monorepo, in a single SQLite
+    /// database, and pack the git objects and refs of the monorepo after importing
+    #[clap(long)]
+    single_file: bool,
+    /// Store downloaded issues in an S3 compatible object store, e.g. s3://bucket/prefix
+    #[clap(long)]
+    object_store: Option<object_store::Location>,
+}
+
+#[derive(Clap)]
+struct CacheOptions {
+    /// Evict the least recently used entries from the cob cache when it grows beyond this size,
+    /// e.g. 500M
+    #[clap(long)]
+    cache_max_size: Option<ByteSize>,
+}
+
+#[derive(Clap)]
+struct GithubOptions {
+    #[clap(short, long)]
+    token_file: String,
+    /// The API URL of a GitHub Enterprise Server instance, e.g.
+    /// https://github.example.com/api/v3
+    #[clap(long)]
+    api_url: Option<String>,
+    /// Send requests through this proxy. Otherwise the HTTPS_PROXY, HTTP_PROXY and NO_PROXY
+    /// environment variables are used.
+    #[clap(long)]
+    proxy: Option<String>,
+    /// Save the raw GraphQL responses and reuse them when the same request is made again
+    #[clap(long)]
+    cache_responses: bool,
+    /// Make every request even if a saved response exists, replacing it
+    #[clap(long)]
+    refresh: bool,
+}
+
+impl GithubOptions {
+    fn client(&self, data_dir: &Path, repo: &RepoName) -> graphql::Client {
+        let token = fs::read_to_string(&self.token_file).unwrap();
+        let client =
+            graphql::Client::new(token.trim(), self.api_url.as_deref(), self.proxy.as_deref())
+                .unwrap();
+        if self.cache_responses {
+            let dir = storage_root(data_dir, repo).join("graphql_cache");
+            client.with_response_cache(dir, self.refresh).unwrap()
+        } else {
+            client
+        }
+    }
+}
+
+#[derive(Clap)]
+struct RetrievalOptions {
+    /// Check the refs of each object against the signed refs of the peers which own them before
+    /// retrieving it
+    #[clap(long)]
+    verify_signed_refs: bool,
+    /// Only evaluate the changes of project delegates
+    #[clap(long)]
+    delegate_only: bool,
+    #[clap(flatten)]
+    project: ProjectOptions,
+}
+
+impl RetrievalOptions {
+    fn apply(&self, monorepo: &mut LiteMonorepo) {
+        self.project.apply(monorepo);
+        monorepo.set_verify_signed_refs(self.verify_signed_refs);
+        if self.delegate_only {
+            let delegates = monorepo.delegates();
+            monorepo.set_delegate_only(Some(delegates));
+        }
+    }
+}
+
+#[derive(Clap)]
+struct ProjectOptions {
+    /// Work with this project, by name or URN, rather than the one the monorepo was created with
+    #[clap(long)]
+    project: Option<String>,
+}
+
+impl ProjectOptions {
+    fn apply(&self, monorepo: &mut LiteMonorepo) {
+        if let Some(project) = &self.project {
+            if let Err(e) = monorepo.select_project(project) {
+                eprintln!("Failed to select the project: {}", e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[derive(Clap)]
+struct ExecOptions {
+    /// Run this command for each object, e.g. 'analyze.py {object_id} {json_path}', where
+    /// `{json_path}` is a file containing the object's materialized document
+    #[clap(long)]
+    exec: Option<exec::Hook>,
+}
+
+impl ExecOptions {
+    /// Run the command on each of `issues`, writing the documents to `exec` in `storage_root`.
+    /// Returns false if it failed for any of them.
+    fn run(&self, storage_root: &Path, issues: &[lite_monorepo::MaterializedIssue]) -> bool {
+        let hook = match &self.exec {
+            Some(hook) => hook,
+            None => return true,
+        };
+        match hook.run_all(issues, &storage_root.join("exec")) {
+            Ok(summary) => {
+                print!("{}", summary);
+                summary.failed.is_empty()
+            }
+            Err(e) => {
+                eprintln!("Failed to run exec: {}", e);
+                false
+            }
+        }
+    }
+}
+
+#[derive(Clap)]
+struct NetworkOptions {
+    /// The round trip time of the simulated network between replicas, in milliseconds
+    #[clap(long, default_value = "0")]
+    rtt_ms: u64,
+    /// The bandwidth of the simulated network in megabits per second. Unlimited by default.
+    #[clap(long)]
+    bandwidth_mbit: Option<f64>,
+}
+
+impl NetworkOptions {
+    fn network(&self) -> replication::Network {
+        replication::Network {
+            rtt: std::time::Duration::from_millis(self.rtt_ms),
+            bits_per_sec: self.bandwidth_mbit.map(|m| (m * 1_000_000.0) as u64),
+        }
+    }
+}
+
+#[derive(Clap)]
+struct AccessPatternOptions {
+    /// How to choose the next issue to retrieve: zipf, uniform, or recent-biased
+    #[clap(long, default_value = "zipf")]
+    pattern: access_pattern::PatternName,
+    #[clap(long, default_value = "1.0")]
+    zipf_exponent: f64,
+    /// For recent-biased access, the fraction of the newest issues which receive half of the
+    /// requests
+    #[clap(long, default_value = "0.1")]
+    half_life: f64,
+    #[clap(long, default_value = "0")]
+    seed: u64,
+}
+
+impl AccessPatternOptions {
+    fn pattern(&self) -> access_pattern::Pattern {
+        match self.pattern {
+            access_pattern::PatternName::Uniform => access_pattern::Pattern::Uniform,
+            access_pattern::PatternName::Zipf => access_pattern::Pattern::Zipf {
+                exponent: self.zipf_exponent,
+            },
+            access_pattern::PatternName::RecentBiased => access_pattern::Pattern::RecentBiased {
+                half_life: self.half_life,
+            },
+        }
+    }
+}
+
+#[derive(Clap)]
+struct ResultsOptions {
+    /// Upload the results, and a description of this machine, as JSON to this URL
+    #[clap(long)]
+    push_results: Option<reqwest::Url>,
+}
+
+impl ResultsOptions {
+    /// Upload `results` if --push-results was given, exiting if that fails
+    fn push<R: serde::Serialize>(&self, benchmark: &str, repo: &RepoName, results: &R) {
+        let url = match &self.push_results {
+            Some(url) => url,
+            None => return,
+        };
+        let pushed = results::Submission::new(benchmark, &repo.to_string(), results)
+            .and_then(|submission| results::push(url, &submission));
+        match pushed {
+            Ok(()) => status!("Pushed results to {}", url),
+            Err(e) => {
+                eprintln!("Failed to push results to {}: {}", url, e);
+                std::process::exit(1);
+            }
+        }
+    }
+}
+
+#[derive(Clap)]
+struct AnomalyOptions {
+    /// Flag operations which take more than this many standard deviations longer than the mean
+    #[clap(long, default_value = "4")]
+    anomaly_sigma: f64,
+    /// The number of operations rolling statistics are kept over when flagging anomalies
+    #[clap(long, default_value = "200")]
+    anomaly_window: usize,
+}
+
+impl AnomalyOptions {
+    fn detector(&self, command: &str) -> Arc<anomalies::Detector> {
+        Arc::new(anomalies::Detector::new(
+            command,
+            anomalies::Options {
+                sigma: self.anomaly_sigma,
+                window: self.anomaly_window.max(1),
+            },
+        ))
+    }
+}
+
 #[derive(Clap)]
 enum Command {
     DownloadIssues {
-        #[clap(short, long)]
-        token_file: String,
         repo: RepoName,
+        /// Retry up to this many times after transient failures
+        #[clap(long, default_value = "0")]
+        auto_retry: u32,
+        #[clap(flatten)]
+        github: GithubOptions,
+    },
+    /// Download a single issue with all of its comments into the existing corpus, replacing it if
+    /// it was already downloaded
+    DownloadIssue {
+        repo: RepoName,
+        number: u64,
+        #[clap(flatten)]
+        github: GithubOptions,
+    },
+    /// Download the issues which were updated since the last sync and merge them into the stored
+    /// issues
+    SyncIssues {
+        repo: RepoName,
+        /// Sync issues updated since this time rather than since the last sync, e.g.
+        /// 2021-06-01T00:00:00Z
+        #[clap(long)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+        /// Retry up to this many times after transient failures
+        #[clap(long, default_value = "0")]
+        auto_retry: u32,
+        #[clap(flatten)]
+        github: GithubOptions,
+    },
+    /// Download the GitHub profiles of the authors of the downloaded issues, comments and events,
+    /// which import-issues names the identities of the peers after
+    DownloadProfiles {
+        repo: RepoName,
+        #[clap(flatten)]
+        github: GithubOptions,
+    },
+    /// Load the issues of a repository from a GitHub migration archive into the downloaded
+    /// issues, in place of downloading them
+    LoadMigrationArchive {
+        repo: RepoName,
+        /// The .tar.gz archive exported by the migrations API
+        archive: PathBuf,
     },
     ImportIssues {
         repo: RepoName,
+        /// Retry up to this many times after transient failures
+        #[clap(long, default_value = "0")]
+        auto_retry: u32,
+        /// Record in each issue that only its creator may change the title and body
+        #[clap(long)]
+        acl: bool,
+        /// How to lay out comments: <list|map>-<text|string>-<nested|flat>
+        #[clap(long, default_value = "list-text-nested")]
+        layout: layout::Layout,
+        /// Offset the clock of each peer by up to this many seconds, forwards or backwards, when
+        /// timestamping changes
+        #[clap(long)]
+        clock_skew: Option<u64>,
+        /// Chooses the offset of each peer's clock
+        #[clap(long, default_value = "0")]
+        clock_skew_seed: u64,
+        /// Make each change with a new automerge actor (fresh), or every change of a peer with the
+        /// same actor (per-peer)
+        #[clap(long, default_value = "fresh")]
+        actor_ids: actor_ids::ActorIds,
+        /// Import issues on this many threads
+        #[clap(long)]
+        jobs: Option<usize>,
+        /// Skip any issue which a cob operation takes longer than this many seconds on, recording
+        /// it in hung.jsonl, rather than waiting for the operation indefinitely
+        #[clap(long)]
+        watchdog: Option<u64>,
+        /// Continue an import which was interrupted: skip issues which were completely imported
+        /// and finish those which were partially imported
+        #[clap(long)]
+        resume: bool,
+        /// Add the comments and events which issues imported before have gained since, e.g. after
+        /// `sync-issues`, to their objects. Implies --resume for partially imported issues.
+        #[clap(long)]
+        incremental: bool,
+        /// Record the commit and peer of each change created, and the comment or event it came
+        /// from, in `import_log/` in the monorepo
+        #[clap(long)]
+        import_log: bool,
+        /// Make each comment followed by a comment from another peer concurrently with it, so
+        /// that the change graph branches and is merged by the next change
+        #[clap(long)]
+        concurrent_comments: bool,
+        /// Write every this many changes of an object as a snapshot of the whole automerge
+        /// document rather than as the change alone
+        #[clap(long)]
+        snapshot_every: Option<usize>,
+        /// Follow this fraction of comments, e.g. 0.05, with a change which violates the schema,
+        /// recording them in `invalid_changes.jsonl` in the monorepo for `verify-rejections`
+        #[clap(long)]
+        inject_invalid: Option<f64>,
+        /// Follow this fraction of comments, e.g. 0.05, with a change whose commit is rewritten
+        /// with a corrupted or missing signature or the wrong author, recording them in
+        /// `tampered_changes.jsonl` in the monorepo for `verify-tampering`
+        #[clap(long)]
+        tamper_signatures: Option<f64>,
+        /// Inject faults with this probability at each point an import can fail, then resume
+        /// without faults and verify the monorepo, e.g. `p=0.001` or `p=0.001,seed=7`. Implies
+        /// --resume.
+        #[clap(long)]
+        chaos: Option<chaos::Options>,
+        /// What to do with the comments of bots: exclude, collapse runs of them to the last or
+        /// import everything bots do as a single user on a dedicated-peer
+        #[clap(long)]
+        bots: Option<bots::Policy>,
+        /// Comma separated globs of the logins of bots, as well as any ending in [bot]. Defaults
+        /// to some common bots such as dependabot.
+        #[clap(long, use_delimiter = true)]
+        bot_logins: Vec<String>,
+        /// Import the repository as it was at the end of this window, e.g. 2019-01..2019-06, on top
+        /// of earlier windows, then benchmark it and record the metrics in windows.jsonl. Implies
+        /// --incremental.
+        #[clap(long)]
+        window: Option<window::Window>,
+        /// Print how many objects, changes and refs the import would create without importing
+        #[clap(long)]
+        dry_run: bool,
+        /// Spread issues and comments across this many peers. Peers can be added to an existing
+        /// monorepo but not removed. Defaults to 10 for new monorepos.
+        #[clap(long)]
+        peers: Option<usize>,
+        /// Lay out the refs of objects per peer, as librad does (per-peer), or per object with a
+        /// ref for each peer under it (flat). Can only be chosen when the monorepo is created.
+        #[clap(long)]
+        ref_layout: Option<peer_refs_storage::RefLayout>,
+        /// Derive the keys of the peers and the automerge actors of changes from this seed rather
+        /// than at random, and stamp changes with the time of what they were made for, so that
+        /// imports of the same data create the same peers and automerge changes. Commits are still
+        /// stamped with the time they are written. Can only be chosen when the monorepo is
+        /// created.
+        #[clap(long)]
+        seed: Option<u64>,
+        /// Give each user this many devices, each a peer with its own key delegated by the user's
+        /// identity, and make their changes with each in turn. --peers counts devices. Can only be
+        /// chosen when the monorepo is created.
+        #[clap(long)]
+        devices: Option<usize>,
+        /// Create objects as this type rather than as GitHub issues. Can only be chosen when the
+        /// monorepo is created.
+        #[clap(long)]
+        typename: Option<String>,
+        /// Create objects with the JSON schema in this file rather than the schema of GitHub
+        /// issues. It must accept the documents of imported issues. Can only be chosen when the
+        /// monorepo is created.
+        #[clap(long)]
+        schema: Option<PathBuf>,
+        #[clap(flatten)]
+        anomalies: AnomalyOptions,
+    },
+    /// Summarize the state of a repository's data: download and import progress, the size of the
+    /// monorepo and its cache, when each command last ran, and recorded failures
+    Status {
+        repo: RepoName,
+    },
+    /// Count the recorded failures of each category, such as rate-limit or git-error, for each
+    /// cob API they happened with
+    FailureTrends {
+        repo: RepoName,
+    },
+    /// Count the git objects of the monorepo, loose and packed, and attribute the objects
+    /// reachable from its refs to identities, signed refs and the objects of each type name
+    RepoStats {
+        repo: RepoName,
+    },
+    /// Run git maintenance on the monorepo, repack, gc or gc-aggressive, and compare its size
+    /// and the latencies `bench-metrics` measures before and after
+    Maintain {
+        repo: RepoName,
+        #[clap(long, default_value = "repack")]
+        operation: maintain::Operation,
+        /// The number of issues to retrieve before and after
+        #[clap(long, default_value = "200")]
+        requests: usize,
+        /// Print what the operation would prune without running it
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Summarize the downloaded issues of a repository
+    DownloadStats {
+        repo: RepoName,
+    },
+    /// Predict how long importing the downloaded issues will take and how large the monorepo will
+    /// be, based on previous imports
+    EstimateImport {
+        repo: RepoName,
     },
     CountImportedIssues {
         repo: RepoName,
+        #[clap(flatten)]
+        project: ProjectOptions,
     },
     RetrieveIssue {
         repo: RepoName,
         object_id: ObjectId,
         #[clap(long)]
         no_cache: bool,
+        #[clap(flatten)]
+        retrieval: RetrievalOptions,
+    },
+    /// Retrieve an issue by its GitHub issue number, using the index of imported issues
+    RetrieveIssueByNumber {
+        repo: RepoName,
+        number: u64,
+        #[clap(long)]
+        no_cache: bool,
+        #[clap(flatten)]
+        retrieval: RetrievalOptions,
     },
     IssueChangeGraphInfo {
         repo: RepoName,
-        object_id: ObjectId,
+        #[clap(required_unless_present = "all")]
+        object_id: Option<ObjectId>,
         #[clap(long)]
         just_graphviz: bool,
+        /// Render the change graph with graphviz's `dot` to a .png or .svg file, or with --all to
+        /// a directory
+        #[clap(long)]
+        render: Option<PathBuf>,
+        /// Render the change graph of every object into the --render directory
+        #[clap(long, requires = "render")]
+        all: bool,
+        /// The format of the images rendered with --all, png or svg
+        #[clap(long, default_value = "svg")]
+        format: render::Format,
+    },
+    /// Print each change of an object like `git log`: who made it, when, and what it changed
+    HistoryLog {
+        repo: RepoName,
+        object_id: ObjectId,
+        /// Print the oldest change first
+        #[clap(long)]
+        reverse: bool,
+    },
+    /// Print the changes an issue was imported as, from the log written by `import-issues
+    /// --import-log`
+    ShowImportLog {
+        repo: RepoName,
+        number: u64,
+    },
+    /// Print the identity of a peer, the keys it delegates to, its object refs and how much it has
+    /// authored
+    ShowPeer {
+        repo: RepoName,
+        peer: link_crypto::PeerId,
+    },
+    /// Print the project identity, its payload and its delegations
+    ShowProject {
+        repo: RepoName,
+        #[clap(flatten)]
+        project: ProjectOptions,
+    },
+    /// Create another project, delegating to every peer, and add it to the projects of the
+    /// monorepo
+    CreateProject {
+        repo: RepoName,
+        name: String,
+    },
+    /// List the projects of the monorepo
+    ListProjects {
+        repo: RepoName,
+    },
+    /// Search the text of every object of every project and type, reporting how long the query
+    /// took
+    SearchAll {
+        repo: RepoName,
+        query: String,
+        /// Show at most this many results
+        #[clap(long, default_value = "20")]
+        limit: usize,
+        /// Rebuild the search index rather than using the one built before
+        #[clap(long)]
+        reindex: bool,
+    },
+    /// Revise the delegations of the project identity
+    EditProject {
+        repo: RepoName,
+        #[clap(flatten)]
+        project: ProjectOptions,
+        /// Add the key of this peer as a delegate, may be repeated
+        #[clap(long)]
+        add_delegate: Vec<link_crypto::PeerId>,
+        /// Remove the key of this peer from the delegates, may be repeated
+        #[clap(long)]
+        remove_delegate: Vec<link_crypto::PeerId>,
     },
+    /// Report which changes wrote a field of an object, character by character for text, and how
+    /// long working it out took
+    Blame {
+        repo: RepoName,
+        object_id: ObjectId,
+        /// The field, as map keys and list indices separated by `/`, e.g. comments/5/comment
+        #[clap(long)]
+        path: blame::FieldPath,
+    },
+    /// Remove an object's refs and check that it no longer shows up in listings
+    DeleteObject {
+        repo: RepoName,
+        #[clap(required_unless_present = "count", conflicts_with = "count")]
+        object_id: Option<ObjectId>,
+        /// Remove the refs of every peer rather than just the local peer
+        #[clap(long)]
+        all_peers: bool,
+        /// Delete this many objects instead, timing each deletion and listing the objects before
+        /// and after
+        #[clap(long)]
+        count: Option<usize>,
+        /// Print the refs which would be removed without removing them
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Check the downloaded issues against the checksum manifest written while downloading
+    VerifyChecksums {
+        repo: RepoName,
+        /// Record checksums for issues which are not yet in the manifest
+        #[clap(long)]
+        record_missing: bool,
+    },
+    /// Export the imported issues, their comments and changes, and the measurements of previous
+    /// runs as tables for analysis elsewhere
+    Export {
+        repo: RepoName,
+        /// ndjson, parquet or csv
+        #[clap(long, default_value = "ndjson")]
+        format: export::Format,
+        /// The directory to write the tables to, `export` in the repository's directory by
+        /// default
+        #[clap(long)]
+        out: Option<PathBuf>,
+        /// Materialize the issues using this many threads and report how well it scaled
+        #[clap(long)]
+        jobs: Option<usize>,
+    },
+    /// Measure the changes, history size, refs and retrieval latency, with and without the cache,
+    /// of every object and write them as a table for charting scaling across repositories
+    ExportMetrics {
+        repo: RepoName,
+        /// parquet, csv or ndjson
+        #[clap(long, default_value = "parquet")]
+        format: export::Format,
+        /// The directory to write the table to, `export` in the repository's directory by
+        /// default
+        #[clap(long)]
+        out: Option<PathBuf>,
+        #[clap(flatten)]
+        project: ProjectOptions,
+    },
+    /// Write the imported issues, their comments and changes, the peer assignments, the timings
+    /// and sizes of each object and the measurements of previous runs into a SQLite database
+    ExportSqlite {
+        repo: RepoName,
+        /// The database to write to, `export.sqlite` in the repository's directory by default.
+        /// Tables already in it are replaced.
+        #[clap(long)]
+        out: Option<PathBuf>,
+    },
+    /// Write the document of every object as a line of JSON, with its object ID, to a file or to
+    /// stdout
+    ExportIssues {
+        repo: RepoName,
+        /// The file to write to, stdout if not given
+        #[clap(long)]
+        out: Option<PathBuf>,
+        /// Materialize the issues using this many threads and report how well it scaled. The
+        /// documents are written once every issue is materialized rather than as they are.
+        #[clap(long)]
+        jobs: Option<usize>,
+        #[clap(flatten)]
+        project: ProjectOptions,
+    },
+    /// Check that every downloaded issue and comment was imported exactly once
+    VerifyImport {
+        repo: RepoName,
+        /// Materialize the issues using this many threads and report how well it scaled
+        #[clap(long)]
+        jobs: Option<usize>,
+        #[clap(flatten)]
+        exec: ExecOptions,
+    },
+    /// Append comments to issues whilst other threads retrieve them through the cache, checking
+    /// that no reader sees a stale document. Note that this adds comments to the monorepo.
+    BenchInterleaved {
+        repo: RepoName,
+        #[clap(long, default_value = "4")]
+        readers: usize,
+        #[clap(long, default_value = "100")]
+        writes: usize,
+        /// The number of issues which are written to and read from
+        #[clap(long, default_value = "10")]
+        hot_objects: usize,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+    },
+    /// Report the cache hit rate, how much faster hits are than uncached retrievals, and the
+    /// sizes and staleness of the cache entries
+    CacheStats {
+        repo: RepoName,
+        /// Count entries which haven't been used for this many days as stale
+        #[clap(long, default_value = "7")]
+        stale_days: i64,
+        /// Time retrieving this many objects which have cache entries with and without the cache
+        #[clap(long, default_value = "0")]
+        sample: usize,
+    },
+    /// Measure the cache hit rate and retrieval latency for a range of cache sizes
+    BenchCacheSize {
+        repo: RepoName,
+        /// Comma separated cache sizes to try
+        #[clap(long, default_value = "1M,10M,100M", use_delimiter = true)]
+        sizes: Vec<ByteSize>,
+        /// The number of retrievals to perform for each size
+        #[clap(long, default_value = "1000")]
+        requests: usize,
+        #[clap(flatten)]
+        access: AccessPatternOptions,
+        #[clap(flatten)]
+        retrieval: RetrievalOptions,
+    },
+    /// Replay a skewed access pattern through the file and SQLite cache backends, each capped at
+    /// the same small size, and compare their hit rate, tail latency and IO. Empties the cache.
+    BenchCacheBackends {
+        repo: RepoName,
+        /// The size both caches are capped at
+        #[clap(long, default_value = "10M")]
+        size: ByteSize,
+        /// The number of retrievals to measure for each backend
+        #[clap(long, default_value = "1000")]
+        requests: usize,
+        /// The number of retrievals to warm each cache with before measuring
+        #[clap(long, default_value = "200")]
+        warmup: usize,
+        #[clap(flatten)]
+        access: AccessPatternOptions,
+        #[clap(flatten)]
+        retrieval: RetrievalOptions,
+        #[clap(flatten)]
+        results: ResultsOptions,
+    },
+    /// Retrieve issues following a skewed access pattern and report latency and cache hit rate
+    BenchAccess {
+        repo: RepoName,
+        #[clap(long, default_value = "1000")]
+        requests: usize,
+        #[clap(long)]
+        no_cache: bool,
+        #[clap(flatten)]
+        access: AccessPatternOptions,
+        #[clap(flatten)]
+        retrieval: RetrievalOptions,
+        #[clap(flatten)]
+        anomalies: AnomalyOptions,
+        #[clap(flatten)]
+        results: ResultsOptions,
+    },
+    /// Move closed, inactive issues into the cold store and time listing and retrieval afterwards
+    Archive {
+        repo: RepoName,
+        /// Issues with no activity for this many days are candidates for archiving
+        #[clap(long, default_value = "365")]
+        inactive_days: i64,
+        /// The maximum fraction of all issues to archive
+        #[clap(long, default_value = "0.9")]
+        max_fraction: f64,
+        /// Print the issues which would be archived and the refs which would be removed without
+        /// archiving them
+        #[clap(long)]
+        dry_run: bool,
+    },
+    /// Regenerate the signed refs of every peer, e.g. after adding comments to the monorepo
+    SignRefs {
+        repo: RepoName,
+    },
+    /// Set the number of peers the local peer tracks, or track every peer if no number is given
+    Track {
+        repo: RepoName,
+        #[clap(long)]
+        peers: Option<usize>,
+    },
+    /// Measure listing and retrieval when the local peer tracks different numbers of peers
+    BenchTracking {
+        repo: RepoName,
+        /// Comma separated tracking set sizes to try
+        #[clap(long, default_value = "5,50,500", use_delimiter = true)]
+        sizes: Vec<usize>,
+        /// The number of retrievals to perform for each size
+        #[clap(long, default_value = "1000")]
+        requests: usize,
+        #[clap(flatten)]
+        access: AccessPatternOptions,
+    },
+    /// Load mutated copies of the histories of imported issues, checking that every mutation is
+    /// either rejected with an error or loads cleanly. Crashing cases are saved to `fuzz` in the
+    /// repository's directory.
+    FuzzChanges {
+        repo: RepoName,
+        #[clap(long, default_value = "10000")]
+        iterations: usize,
+        /// Only use the histories of this many issues
+        #[clap(long)]
+        issues: Option<usize>,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+    },
+    /// Replicate the monorepo, delivering every ref update several times and out of order, and
+    /// check that the replica ends up with the same refs and documents
+    SimulateDuplicateDelivery {
+        repo: RepoName,
+        /// How many times each update is delivered
+        #[clap(long, default_value = "3")]
+        copies: usize,
+        /// How many earlier states of each object are delivered alongside its current state
+        #[clap(long, default_value = "2")]
+        history: usize,
+        /// Only replicate this many objects
+        #[clap(long)]
+        objects: Option<usize>,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+        #[clap(flatten)]
+        network: NetworkOptions,
+        /// Once everything has been delivered, have this many peers deliver their updates again
+        /// under a different identity, checking that the replica rejects them
+        #[clap(long, default_value = "0")]
+        impersonate: usize,
+    },
+    /// Import disjoint sets of issues into several replicas of the monorepo, merge their objects
+    /// into a hub and back, and check that every object has the same document everywhere
+    SimulateReplication {
+        repo: RepoName,
+        /// The number of monorepos to import into
+        #[clap(long, default_value = "3")]
+        nodes: usize,
+        /// The number of downloaded issues to deal out between the nodes
+        #[clap(long, default_value = "300")]
+        issues: usize,
+    },
+    /// Measure the size of the ref advertisement the monorepo would send when fetched from, and
+    /// how it grows with the number of peers and objects
+    RefAdvertisement {
+        repo: RepoName,
+        /// The number of subsets of the peers and objects to measure
+        #[clap(long, default_value = "4")]
+        steps: usize,
+    },
+    /// Build a copy of the monorepo in which each peer has one ref pointing at an index of its
+    /// objects instead of a ref per object, and compare the ref advertisement, enumeration and
+    /// lookup of the two layouts along with the cost of maintaining the indexes
+    CompareRefLayouts {
+        repo: RepoName,
+    },
+    /// Time enumerating the issues of the monorepo by reading every ref and matching it against a
+    /// regex, as was done before, and by reading only the refs under a glob
+    BenchTypeReferences {
+        repo: RepoName,
+        /// The number of times to enumerate the issues each way
+        #[clap(long, default_value = "20")]
+        iterations: usize,
+    },
+    /// Time reading the refs of the issues from git and from memory, and retrieving every issue
+    /// with its refs read from each, to tell ref I/O apart from evaluating change graphs
+    BenchMemoryRefs {
+        repo: RepoName,
+        /// The number of times to enumerate and retrieve the issues each way
+        #[clap(long, default_value = "10")]
+        iterations: usize,
+        /// A snapshot of the refs to read rather than the monorepo's refs. It is written from
+        /// the monorepo's refs if it doesn't exist.
+        #[clap(long)]
+        snapshot: Option<PathBuf>,
+    },
+    /// Replay the creation and comment times of the downloaded issues as a stream of events and
+    /// compare how many changes and how much delay each batching policy would produce
+    BenchBatching {
+        repo: RepoName,
+        /// Comma separated debounce windows to try, in seconds. An object is imported once no
+        /// event has arrived for it for this long.
+        #[clap(long, default_value = "0,60,600,3600", use_delimiter = true)]
+        windows: Vec<u64>,
+        /// Import an object once its oldest pending event is this many seconds old, even if events
+        /// are still arriving
+        #[clap(long)]
+        max_delay: Option<u64>,
+    },
+    /// Evaluate every issue using only the changes of project delegates and compare the documents
+    /// and timings with evaluating the changes of every peer
+    BenchDelegateOnly {
+        repo: RepoName,
+        /// Treat only this many of the project's delegates as delegates, as if the project
+        /// delegated to them alone
+        #[clap(long)]
+        delegates: Option<usize>,
+        #[clap(flatten)]
+        project: ProjectOptions,
+    },
+    /// Check that every change is signed by a key of the identity it names as its author, and
+    /// that each peer's refs point at changes made by the identity the peer presents
+    VerifyAuthorship {
+        repo: RepoName,
+    },
+    /// Check that every change made to violate the schema by an import with --inject-invalid
+    /// was left out of its object when it is retrieved
+    VerifyRejections {
+        repo: RepoName,
+    },
+    /// Check that every change forged by an import with --tamper-signatures was left out of its
+    /// object, and compare how long the objects with forged changes take to retrieve
+    VerifyTampering {
+        repo: RepoName,
+    },
+    /// Check that no change to an issue imported with `--acl` altered a field its author wasn't
+    /// allowed to
+    VerifyAcl {
+        repo: RepoName,
+        /// Before verifying, have other peers change the titles of this many issues. Note that
+        /// this modifies the monorepo.
+        #[clap(long, default_value = "0")]
+        inject: usize,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+        #[clap(flatten)]
+        exec: ExecOptions,
+    },
+    /// Compare the history size, retrieval speed and concurrent merges of every comment layout
+    /// using the downloaded issues
+    CompareLayouts {
+        repo: RepoName,
+        /// The number of downloaded issues to use
+        #[clap(long, default_value = "100")]
+        issues: usize,
+        /// The number of issues to add a pair of concurrent comments to
+        #[clap(long, default_value = "10")]
+        concurrent: usize,
+    },
+    /// Validate the changes of some downloaded issues against the import schema and a strict one,
+    /// comparing how long validation takes and which changes each rejects
+    CompareSchemas {
+        repo: RepoName,
+        /// The number of downloaded issues to use
+        #[clap(long, default_value = "100")]
+        issues: usize,
+        #[clap(long, default_value = "list-text-nested")]
+        layout: layout::Layout,
+    },
+    /// Build the histories of some downloaded issues with a new automerge actor for each change
+    /// and with an actor per peer, comparing the actors per document, their size and apply time
+    BenchActorIds {
+        repo: RepoName,
+        /// The number of downloaded issues to use
+        #[clap(long, default_value = "100")]
+        issues: usize,
+        /// The number of peers users are spread across
+        #[clap(long, default_value = "10")]
+        peers: usize,
+    },
+    /// Apply sessions of character level edits to the titles or bodies of some imported issues,
+    /// each session as a change, and compare the size of their histories and the time to
+    /// retrieve them before and after
+    SimulateTextEdits {
+        repo: RepoName,
+        /// The number of issues to edit
+        #[clap(long, default_value = "100")]
+        issues: usize,
+        /// The number of edit sessions to apply to each field of each issue
+        #[clap(long, default_value = "10")]
+        sessions: usize,
+        /// Comma separated fields to edit, title and body
+        #[clap(long, default_value = "body", use_delimiter = true)]
+        fields: Vec<text_edits::Field>,
+        /// Chooses the edits and the peers which make them
+        #[clap(long, default_value = "0")]
+        seed: u64,
+    },
+    /// Hide, edit and move the last comment of some downloaded issues and add comments, two of
+    /// these at a time concurrently, and report how automerge merged them
+    CheckModeration {
+        repo: RepoName,
+        /// The number of downloaded issues to use
+        #[clap(long, default_value = "100")]
+        issues: usize,
+    },
+    /// Import the downloaded issues twice, into temporary monorepos created with the same seed,
+    /// and check that the two have the same peers, assignments, changes and refs
+    CheckDeterminism {
+        repo: RepoName,
+        /// The number of downloaded issues to import
+        #[clap(long, default_value = "100")]
+        issues: usize,
+        #[clap(long, default_value = "10")]
+        peers: usize,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+    },
+    /// Find the smallest part of an object's history which still fails a check and export it as a
+    /// test case for cob
+    Minimize {
+        repo: RepoName,
+        object_id: ObjectId,
+        /// The failing check: materialize, schema, acl, or slow:<milliseconds>
+        #[clap(long, default_value = "materialize")]
+        check: minimize::Check,
+    },
+    /// Package everything needed to retrieve an object, along with a test which retrieves it, so
+    /// that it can be reproduced outside of the monorepo
+    ExportRepro {
+        repo: RepoName,
+        object_id: ObjectId,
+        #[clap(flatten)]
+        exec: ExecOptions,
+    },
+    /// Measure how long it takes to open the monorepo of each repository, broken down by phase
+    BenchOpen {
+        repos: Vec<RepoName>,
+        #[clap(long, default_value = "5")]
+        iterations: u32,
+    },
+    /// Import up to a million tiny synthetic issues into a monorepo of their own, measuring import
+    /// throughput, the time to enumerate every object's refs and the size of the monorepo at each
+    /// checkpoint. Running it again continues from where it got to.
+    ScaleObjects {
+        /// Where to keep the monorepo and the measurements, defaults to `object-scaling` in the
+        /// data directory
+        #[clap(long)]
+        dir: Option<PathBuf>,
+        /// Comma separated numbers of objects at which to take measurements
+        #[clap(
+            long,
+            default_value = "10000,100000,500000,1000000",
+            use_delimiter = true
+        )]
+        checkpoints: Vec<usize>,
+        /// The number of github users the issues and comments are spread across
+        #[clap(long, default_value = "1000")]
+        users: usize,
+        /// Stop once fewer than this many objects a second are being imported
+        #[clap(long)]
+        stop_below: Option<f64>,
+    },
+    /// Import the same downloaded issues into a fresh monorepo for each of a range of peer counts,
+    /// measuring identity creation, import, ref enumeration and retrieval latency
+    ScalePeers {
+        repo: RepoName,
+        /// Comma separated numbers of peers to try
+        #[clap(long, default_value = "10,100,1000,10000", use_delimiter = true)]
+        peers: Vec<usize>,
+        /// The number of downloaded issues to import
+        #[clap(long, default_value = "500")]
+        issues: usize,
+        /// The number of objects to retrieve with a cold cache
+        #[clap(long, default_value = "100")]
+        retrievals: usize,
+        /// Where to create the monorepos, defaults to `peer-scaling` in the repository's directory
+        #[clap(long)]
+        dir: Option<PathBuf>,
+    },
+    /// Generate a corpus with the age distribution of a years old project and time listing and
+    /// retrieval when only the newest objects are cached, for each of a range of percentages
+    BenchRetention {
+        /// Where to keep the monorepo, defaults to `retention` in the data directory
+        #[clap(long)]
+        dir: Option<PathBuf>,
+        #[clap(long, default_value = "10000")]
+        issues: usize,
+        /// How many years ago the first issue was created
+        #[clap(long, default_value = "5")]
+        years: f64,
+        /// How many times more issues are created each year than the year before
+        #[clap(long, default_value = "1.5")]
+        growth: f64,
+        /// The mean number of comments on each issue
+        #[clap(long, default_value = "4")]
+        comments: f64,
+        /// The number of github users the issues and comments are spread across
+        #[clap(long, default_value = "1000")]
+        users: usize,
+        #[clap(long, default_value = "0")]
+        seed: u64,
+        /// Comma separated percentages of the objects, newest first, to cache
+        #[clap(long, default_value = "100,50,20,5,0", use_delimiter = true)]
+        retain: Vec<f64>,
+        /// The number of objects to retrieve for each percentage, spread over their ages
+        #[clap(long, default_value = "200")]
+        retrievals: usize,
+    },
+    /// Import some downloaded issues into replicas of the monorepo with snapshots of the automerge
+    /// document written every K changes, for each K, and compare retrieval, listing and the size
+    /// of the repository
+    BenchSnapshots {
+        repo: RepoName,
+        /// The number of downloaded issues to use
+        #[clap(long, default_value = "100")]
+        issues: usize,
+        /// Comma separated values of K, 0 for no snapshots
+        #[clap(long, default_value = "0,5,20,100", use_delimiter = true)]
+        every: Vec<usize>,
+    },
+    /// Import downloaded issues into a replica of the monorepo on a small filesystem until it runs
+    /// out of space, then check that the metadata of the replica is intact and that the import
+    /// resumes once space is freed
+    TestDiskFull {
+        repo: RepoName,
+        /// A directory on a filesystem of its own, such as a small tmpfs, which the test may fill
+        #[clap(long)]
+        dir: PathBuf,
+        /// The space to hold back in a file which is deleted when the filesystem fills up
+        #[clap(long, default_value = "4M")]
+        reserve: ByteSize,
+    },
+    /// Measure how much time validating documents spends compiling the schema, by validating the
+    /// document after each change of some downloaded issues with the schema compiled each time and
+    /// compiled once
+    BenchSchema {
+        repo: RepoName,
+        /// The number of downloaded issues to use
+        #[clap(long, default_value = "100")]
+        issues: usize,
+        #[clap(long, default_value = "list-text-nested")]
+        layout: layout::Layout,
+    },
+    /// Retrieve the newest issues without the cache and list every issue with an empty cache,
+    /// printing the latencies in milliseconds as JSON. This is the benchmark `bisect-perf` runs.
+    BenchMetrics {
+        repo: RepoName,
+        /// The number of issues to retrieve
+        #[clap(long, default_value = "200")]
+        requests: usize,
+        #[clap(flatten)]
+        results: ResultsOptions,
+    },
+    /// Rebuild against each revision of a radicle-link checkout between --good and --bad, running
+    /// `bench-metrics` each time, to find the commit after which a metric exceeds a threshold
+    BisectPerf {
+        repo: RepoName,
+        /// The radicle-link checkout containing cob
+        #[clap(long)]
+        cob_repo: PathBuf,
+        /// A revision whose metric is within the threshold
+        #[clap(long)]
+        good: String,
+        /// A later revision whose metric exceeds the threshold
+        #[clap(long, default_value = "HEAD")]
+        bad: String,
+        /// retrieve_mean, retrieve_p50, retrieve_p95 or list
+        #[clap(long, default_value = "retrieve_p95")]
+        metric: bisect_perf::Metric,
+        /// The value of the metric in milliseconds above which a revision is bad
+        #[clap(long)]
+        threshold: f64,
+        #[clap(long, default_value = "200")]
+        requests: usize,
+        /// Run the benchmark this many times for each revision and use the median
+        #[clap(long, default_value = "3")]
+        runs: usize,
+        /// Where to keep the worktree and the builds, defaults to `bisect-perf` in the data
+        /// directory
+        #[clap(long)]
+        work_dir: Option<PathBuf>,
+    },
+    /// Serve a JSON API for browsing the objects of the monorepo: listing them, their documents
+    /// and their change graphs
+    Serve {
+        repo: RepoName,
+        /// The address to listen on
+        #[clap(long, default_value = "127.0.0.1:8080")]
+        addr: String,
+    },
+    /// Run scenarios, sequences of steps described in a YAML file
+    #[clap(subcommand)]
+    Scenario(ScenarioCommand),
+    /// Look into the record of every object created or updated in the monorepo
+    #[clap(subcommand)]
+    Audit(AuditCommand),
+}
+
+#[derive(Clap)]
+enum ScenarioCommand {
+    /// Run the steps of a scenario file in order, stopping at the first which fails
+    Run {
+        file: PathBuf,
+        /// Where to run the scenario, replacing anything left by an earlier run. Defaults to
+        /// `scenarios/<file name>` in the data directory
+        #[clap(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+#[derive(Clap)]
+enum AuditCommand {
+    /// Print the creates and updates recorded in the audit log, oldest first
+    Show {
+        repo: RepoName,
+        /// Only those made for this issue
+        #[clap(long)]
+        issue: Option<u64>,
+        /// Only those of this object
+        #[clap(long)]
+        object: Option<String>,
+        /// Only those made by this peer
+        #[clap(long)]
+        peer: Option<String>,
+        /// Only those which started at or after this time, e.g. 2021-06-01T00:00:00Z
+        #[clap(long)]
+        since: Option<chrono::DateTime<chrono::Utc>>,
+        /// Only those which failed
+        #[clap(long)]
+        failed: bool,
+        /// Print only this many of the slowest, slowest first
+        #[clap(long)]
+        slowest: Option<usize>,
+    },
+    /// Replay the creates and updates recorded in the audit log, in order, against a fresh
+    /// replica of the monorepo with the same peers, making each with the payload it was made with
+    Replay {
+        repo: RepoName,
+        /// Only replay those made for this issue
+        #[clap(long)]
+        issue: Option<u64>,
+        /// Only replay this many, from the start of the log
+        #[clap(long)]
+        limit: Option<usize>,
+        /// Where to create the replica, replacing anything there. Defaults to `replay` in the
+        /// repository's directory.
+        #[clap(long)]
+        dir: Option<PathBuf>,
+    },
+}
+
+/// The directory in which everything to do with `repo` is stored. GitHub owner and repository
+/// names are case-insensitive, so they are lower cased, unless the data directory has the
+/// repository under the names as they were given, as data directories made before did.
+fn storage_root(data_dir: &Path, repo: &RepoName) -> PathBuf {
+    let root = data_dir
+        .join(fs::file_name(&repo.owner.as_str().to_lowercase()))
+        .join(fs::file_name(&repo.name.as_str().to_lowercase()));
+    let given = data_dir.join(repo.owner.as_str()).join(repo.name.as_str());
+    if !fs::exists(&root).unwrap_or(false) && fs::exists(&given).unwrap_or(false) {
+        given
+    } else {
+        root
+    }
+}
+
+fn open_monorepo(
+    data_dir: &Path,
+    repo: &RepoName,
+    cache: &CacheOptions,
+    storage: &StorageOptions,
+) -> LiteMonorepo {
+    let storage_root = storage_root(data_dir, repo);
+    let root = storage_root.join("monorepo");
+    let files = if storage.single_file {
+        fs::create_dir_all(&root).unwrap();
+        let (files, restored) =
+            MonorepoFiles::restore(storage_root.join("data.sqlite"), &root).unwrap();
+        verbose!(
+            "restored {} files of the monorepo from data.sqlite",
+            restored
+        );
+        Some(files)
+    } else {
+        None
+    };
+    let mut monorepo = LiteMonorepo::create_or_open(&root).unwrap();
+    monorepo
+        .cache()
+        .set_max_size(cache.cache_max_size.map(|s| s.0))
+        .unwrap();
+    monorepo.set_files(files);
+    monorepo
+}
+
+/// Materialize every issue of `monorepo` using `jobs` threads, reporting how well it scaled
+fn materialize_parallel(
+    monorepo: &LiteMonorepo,
+    jobs: usize,
+) -> Vec<lite_monorepo::MaterializedIssue> {
+    let ids = monorepo.list_issue_ids().unwrap();
+    match parallel::materialize(&monorepo.read_worker(), &ids, jobs) {
+        Ok((issues, scaling)) => {
+            status!("{}", scaling.to_string().trim_end());
+            issues
+        }
+        Err(e) => {
+            eprintln!("Error retrieving issues {}", e);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Open the monorepo of `repo` only if it exists, for commands which mustn't create it
+fn open_existing_monorepo(
+    data_dir: &Path,
+    repo: &RepoName,
+    cache: &CacheOptions,
+    storage: &StorageOptions,
+) -> Option<LiteMonorepo> {
+    let root = storage_root(data_dir, repo).join("monorepo");
+    if fs::exists(&root).unwrap() {
+        Some(open_monorepo(data_dir, repo, cache, storage))
+    } else {
+        eprintln!("There is no monorepo at {}", root.display());
+        None
+    }
+}
+
+/// The storage for downloaded issues of `repo`
+fn issue_storage(
+    data_dir: &Path,
+    repo: &RepoName,
+    options: &StorageOptions,
+) -> Arc<dyn IssueStorage> {
+    let storage_root = storage_root(data_dir, repo);
+    if let Some(location) = &options.object_store {
+        Arc::new(object_store::ObjectStoreStorage::new(location, repo).unwrap())
+    } else if options.single_file {
+        if !fs::exists(&storage_root).unwrap() {
+            fs::create_dir_all(&storage_root).unwrap();
+        }
+        Arc::new(SqliteStorage::open(storage_root.join("data.sqlite")).unwrap())
+    } else {
+        Arc::new(download::Storage::new(storage_root.join("download")).unwrap())
+    }
+}
+
+/// Print the document of `object_id`, falling back to the cold store if it has been archived
+fn print_issue(monorepo: &LiteMonorepo, object_id: &ObjectId, use_cache: bool) {
+    match monorepo.retrieve_issue(object_id, use_cache) {
+        Ok(Some(json)) => {
+            println!("{}", json);
+        }
+        Ok(None) => match monorepo.retrieve_archived_issue(object_id) {
+            Ok(Some(json)) => println!("{}", json),
+            Ok(None) => println!("null"),
+            Err(e) => eprintln!("Error retrieving archived issue {}", e),
+        },
+        Err(e) => eprintln!("Error retrieving issue {}", e),
+    }
+}
+
+/// The peers of the monorepo by the URN of their identity
+fn peers_by_urn(monorepo: &LiteMonorepo) -> HashMap<String, link_crypto::PeerId> {
+    let mut peers = HashMap::new();
+    for peer in monorepo.peer_ids() {
+        if let Some(urn) = monorepo.peer_urn(peer).unwrap() {
+            peers.insert(urn.to_string(), *peer);
+        }
+    }
+    peers
+}
+
+/// Print the anomalies `detector` found, if any, and add them to the log in `storage_root`
+fn report_anomalies(storage_root: &Path, detector: &anomalies::Detector) {
+    let found = detector.anomalies();
+    if found.is_empty() {
+        return;
+    }
+    print!("{}", anomalies::report(&found));
+    anomalies::record(storage_root.join(anomalies::ANOMALIES_LOG), &found).unwrap();
+}
+
+/// A progress bar, which is hidden unless the verbosity is the default
+fn progress_bar(len: usize) -> ProgressBar {
+    if !output::show_progress_bars() {
+        return ProgressBar::hidden();
+    }
+    let bar = ProgressBar::new(len as u64);
+    bar.set_style(
+        ProgressStyle::default_bar()
+            .template("[{elapsed_precise}] {bar:40.yellow/blue} {pos:>7}/{len:7}"),
+    );
+    bar
 }
 
 #[tokio::main]
 async fn main() {
-    tracing_subscriber::fmt::init();
     let args = Args::parse();
+    output::init(args.quiet, args.verbose);
     match args.command {
-        Command::DownloadIssues { token_file, repo } => {
-            let token = std::fs::read_to_string(token_file).unwrap();
-            let repo_storage_dir = args
-                .data_dir
-                .join(repo.owner.as_str())
-                .join(repo.name.as_str())
-                .join("download");
-            if !std::fs::try_exists(&repo_storage_dir).unwrap() {
-                std::fs::create_dir_all(&repo_storage_dir).unwrap();
-            }
-            let storage = download::Storage::new(repo_storage_dir).unwrap();
-            let crab = octocrab::OctocrabBuilder::default()
-                .personal_token(token.trim().to_string())
-                .build()
+        Command::DownloadIssues {
+            repo,
+            auto_retry,
+            github,
+        } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let client = github.client(&args.data_dir, &repo);
+            let started = chrono::Utc::now();
+            let (result, summary) = retry::retry_async(
+                "download-issues",
+                auto_retry,
+                || download::download(client.clone(), repo.clone(), storage.clone()),
+                || {
+                    storage
+                        .load_cursor()
+                        .ok()
+                        .flatten()
+                        .map(retry::Checkpoint::Cursor)
+                },
+            )
+            .await;
+            summary.print();
+            summary
+                .persist(storage_root(&args.data_dir, &repo).join(FAILURE_LOG))
                 .unwrap();
-            match download::download(crab, repo, storage).await {
-                Ok(()) => println!("Done"),
+            let run = runs::Run::finished("download-issues", started, result.is_ok());
+            runs::record(
+                storage_root(&args.data_dir, &repo).join(runs::RUNS_LOG),
+                &run,
+            )
+            .unwrap();
+            match result {
+                Ok(()) => status!("Done"),
                 Err(e) => eprintln!("Failed: {}", e),
             }
         }
-        Command::ImportIssues { repo } => {
-            let storage_root = args
-                .data_dir
-                .join(repo.owner.as_str())
-                .join(repo.name.as_str());
-            let monorepo_root = storage_root.join("monorepo");
-            let mut monorepo = LiteMonorepo::create_or_open(monorepo_root).unwrap();
-            let issue_storage_dir = storage_root.join("download");
-            let storage = download::Storage::new(issue_storage_dir).unwrap();
-            let issues = storage.issues().unwrap();
-            let bar = ProgressBar::new(issues.len() as u64);
-            bar.set_style(
-                ProgressStyle::default_bar()
-                    .template("[{elapsed_precise}] {bar:40.yellow/blue} {pos:>7}/{len:7}"),
-            );
-            for issue in issues.iter() {
-                bar.inc(1);
-                match monorepo.import_issue(issue) {
-                    Ok(()) => {}
+        Command::DownloadIssue {
+            repo,
+            number,
+            github,
+        } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let client = github.client(&args.data_dir, &repo);
+            let started = chrono::Utc::now();
+            let result = download::download_one(client, repo.clone(), number, storage).await;
+            let run = runs::Run::finished("download-issue", started, result.is_ok());
+            runs::record(
+                storage_root(&args.data_dir, &repo).join(runs::RUNS_LOG),
+                &run,
+            )
+            .unwrap();
+            match result {
+                Ok(true) => status!("Downloaded issue {}", number),
+                Ok(false) => eprintln!("No issue {}", number),
+                Err(e) => eprintln!("Failed: {}", e),
+            }
+        }
+        Command::DownloadProfiles { repo, github } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let client = github.client(&args.data_dir, &repo);
+            let path = storage_root(&args.data_dir, &repo).join(profiles::PROFILES);
+            let mut profiles = profiles::Profiles::load(path).unwrap();
+            match profiles::download(&client, storage.as_ref(), &mut profiles).await {
+                Ok(downloaded) => status!(
+                    "Downloaded {} profiles, {} in total",
+                    downloaded,
+                    profiles.len()
+                ),
+                Err(e) => eprintln!("Failed: {}", e),
+            }
+        }
+        Command::LoadMigrationArchive { repo, archive } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            match migration_archive::load(&archive, &repo, storage.as_ref()) {
+                Ok(summary) => status!("{}", summary),
+                Err(e) => {
+                    eprintln!("Failed to load {}: {}", archive.display(), e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::SyncIssues {
+            repo,
+            since,
+            auto_retry,
+            github,
+        } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let client = github.client(&args.data_dir, &repo);
+            let started = chrono::Utc::now();
+            let (result, summary) = retry::retry_async(
+                "sync-issues",
+                auto_retry,
+                || download::sync(client.clone(), repo.clone(), storage.clone(), since),
+                || {
+                    storage
+                        .load_last_sync()
+                        .ok()
+                        .flatten()
+                        .map(retry::Checkpoint::SyncedAt)
+                },
+            )
+            .await;
+            summary.print();
+            summary
+                .persist(storage_root(&args.data_dir, &repo).join(FAILURE_LOG))
+                .unwrap();
+            let run = runs::Run::finished("sync-issues", started, result.is_ok());
+            runs::record(
+                storage_root(&args.data_dir, &repo).join(runs::RUNS_LOG),
+                &run,
+            )
+            .unwrap();
+            match result {
+                Ok(synced) => status!("{}", synced),
+                Err(e) => eprintln!("Failed: {}", e),
+            }
+        }
+        Command::ImportIssues {
+            repo,
+            auto_retry,
+            acl,
+            layout,
+            clock_skew,
+            clock_skew_seed,
+            actor_ids,
+            jobs,
+            watchdog,
+            resume,
+            incremental,
+            import_log,
+            concurrent_comments,
+            snapshot_every,
+            inject_invalid,
+            tamper_signatures,
+            chaos,
+            bots,
+            bot_logins,
+            window,
+            dry_run,
+            peers,
+            ref_layout,
+            seed,
+            devices,
+            typename,
+            schema,
+            anomalies,
+        } => {
+            let storage_root = storage_root(&args.data_dir, &repo);
+            if dry_run {
+                let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+                let issues = storage.issues().unwrap();
+                let monorepo = storage_root.join("monorepo");
+                match dry_run::plan_import(&monorepo, &issues, resume, incremental) {
+                    Ok(plan) => print!("{}", plan),
+                    Err(e) => eprintln!("Failed to plan import: {}", e),
+                }
+                return;
+            }
+            if let Some(peers) = peers {
+                let root = storage_root.join("monorepo");
+                if let Err(e) = monorepo_config::Config::set_peers(&root, peers) {
+                    eprintln!("Failed to set the number of peers: {}", e);
+                    return;
+                }
+            }
+            if let Some(layout) = ref_layout {
+                let root = storage_root.join("monorepo");
+                if let Err(e) = monorepo_config::Config::set_ref_layout(&root, layout) {
+                    eprintln!("Failed to set the ref layout: {}", e);
+                    return;
+                }
+            }
+            if let Some(seed) = seed {
+                let root = storage_root.join("monorepo");
+                if let Err(e) = monorepo_config::Config::set_seed(&root, seed) {
+                    eprintln!("Failed to set the seed: {}", e);
+                    return;
+                }
+            }
+            if let Some(devices) = devices {
+                let root = storage_root.join("monorepo");
+                if let Err(e) = monorepo_config::Config::set_devices(&root, devices) {
+                    eprintln!("Failed to set the number of devices: {}", e);
+                    return;
+                }
+            }
+            if let Some(typename) = typename {
+                let root = storage_root.join("monorepo");
+                if let Err(e) = monorepo_config::Config::set_typename(&root, &typename) {
+                    eprintln!("Failed to set the type name: {}", e);
+                    return;
+                }
+            }
+            if let Some(schema) = schema {
+                let root = storage_root.join("monorepo");
+                if let Err(e) = monorepo_config::Config::set_schema(&root, &schema) {
+                    eprintln!("Failed to set the schema: {}", e);
+                    return;
+                }
+            }
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            monorepo.set_import_acl(acl);
+            monorepo.set_import_layout(layout);
+            // A retry imports the issue which failed again from the start, which without resuming
+            // would create a second object for it
+            monorepo.set_resume_imports(resume || auto_retry > 0);
+            monorepo.set_incremental_imports(incremental);
+            monorepo.set_import_log(import_log);
+            monorepo.set_concurrent_comments(concurrent_comments);
+            monorepo.set_snapshot_every(snapshot_every);
+            monorepo.set_inject_invalid(inject_invalid);
+            monorepo.set_tamper_signatures(tamper_signatures);
+            if let Some(policy) = bots {
+                let result = if bot_logins.is_empty() {
+                    bots::Bots::new(policy, bots::DEFAULT_LOGINS)
+                } else {
+                    bots::Bots::new(policy, &bot_logins)
+                };
+                match result {
+                    Ok(bots) => monorepo.set_bots(Some(Arc::new(bots))),
                     Err(e) => {
-                        eprintln!("Failed to import issue: {:?}", e);
+                        eprintln!("Invalid --bot-logins: {}", e);
                         return;
                     }
                 }
             }
+            if let Some(window) = &window {
+                let log = storage_root.join(window::WINDOWS_LOG);
+                if let Some(last) = window::load(&log).unwrap().last() {
+                    if last.end < window.start {
+                        status!(
+                            "nothing has been imported between {} and {}, it is imported along with this window",
+                            last.end.date().naive_utc(),
+                            window.start.date().naive_utc()
+                        );
+                    }
+                }
+                monorepo.set_import_until(Some(window.end));
+                monorepo.set_incremental_imports(true);
+            }
+            if let Some(options) = chaos {
+                monorepo.set_chaos(Some(chaos::Chaos::new(options)));
+                monorepo.set_resume_imports(true);
+            }
+            monorepo.set_clock_skew(clock_skew.map(|secs| {
+                clock_skew::ClockSkew::new(std::time::Duration::from_secs(secs), clock_skew_seed)
+            }));
+            monorepo.set_actor_ids(actor_ids);
+            let detector = anomalies.detector("import-issues");
+            monorepo.set_anomaly_detector(Some(detector.clone()));
+            let watchdog = watchdog.map(|secs| import::Watchdog {
+                timeout: std::time::Duration::from_secs(secs),
+                log: storage_root.join(watchdog::HUNG_LOG),
+            });
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let numbers = storage.issue_numbers().unwrap();
+            let bar = progress_bar(numbers.len());
+            let checkpoint = Cell::new(None);
+            let imported = std::sync::Mutex::new(std::collections::HashSet::new());
+            let measurement = estimate::Measurement::start(&monorepo).unwrap();
+            let started = chrono::Utc::now();
+            let (result, summary) = retry::retry(
+                "import-issues",
+                auto_retry,
+                || match (jobs, &watchdog) {
+                    (jobs, Some(watchdog)) => import::import_issues_watched(
+                        &mut monorepo,
+                        storage.as_ref(),
+                        &numbers,
+                        &checkpoint,
+                        &imported,
+                        &bar,
+                        jobs.unwrap_or(1),
+                        watchdog,
+                    ),
+                    (Some(jobs), None) => import::import_issues_parallel(
+                        &mut monorepo,
+                        storage.as_ref(),
+                        &numbers,
+                        &checkpoint,
+                        &imported,
+                        &bar,
+                        jobs,
+                    ),
+                    (None, None) => import::import_issues(
+                        &mut monorepo,
+                        storage.as_ref(),
+                        &numbers,
+                        &checkpoint,
+                        &bar,
+                    ),
+                },
+                || checkpoint.get().map(retry::Checkpoint::Issue),
+            );
             bar.finish();
+            summary.print();
+            summary.persist(storage_root.join(FAILURE_LOG)).unwrap();
+            report_anomalies(&storage_root, &detector);
+            let run = runs::Run::finished("import-issues", started, result.is_ok());
+            runs::record(storage_root.join(runs::RUNS_LOG), &run).unwrap();
+            if let Some(chaos) = monorepo.chaos().cloned() {
+                if let Err(e) = &result {
+                    status!("the import stopped at: {}", e);
+                }
+                let failures = retry::load_failures(storage_root.join(FAILURE_LOG)).unwrap();
+                match chaos::resume_and_verify(&mut monorepo, &chaos, storage.as_ref(), &failures) {
+                    Ok(report) => {
+                        print!("{}", report);
+                        if !report.is_ok() {
+                            std::process::exit(1);
+                        }
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to resume the import without faults: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            } else if let Err(e) = result {
+                eprintln!("Failed to import issue: {:?}", e);
+                return;
+            }
+            let profiles = profiles::Profiles::load(storage_root.join(profiles::PROFILES)).unwrap();
+            if !profiles.is_empty() {
+                match monorepo.apply_profiles(&profiles) {
+                    Ok(0) => {}
+                    Ok(renamed) => status!("named {} peer identities after GitHub users", renamed),
+                    Err(e) => eprintln!("Failed to apply profiles: {}", e),
+                }
+            }
+            if let Err(e) = monorepo.sign_refs() {
+                eprintln!("Failed to sign refs: {}", e);
+            }
+            let run = measurement.finish(&repo, &monorepo).unwrap();
+            if run.changes > 0 {
+                estimate::record(args.data_dir.join(estimate::IMPORT_RUNS), &run).unwrap();
+                status!(
+                    "{}",
+                    schema_cost::ImportOverhead::estimate(
+                        run.changes,
+                        std::time::Duration::from_secs_f64(run.elapsed_secs)
+                    )
+                );
+            }
+            if let Some(window) = &window {
+                match window::checkpoint(&monorepo, window, run.changes) {
+                    Ok(checkpoint) => {
+                        println!("{}", checkpoint);
+                        window::record(storage_root.join(window::WINDOWS_LOG), &checkpoint)
+                            .unwrap();
+                    }
+                    Err(e) => eprintln!("Failed to benchmark the window: {}", e),
+                }
+            }
+            if args.storage.single_file {
+                if let Err(e) = monorepo.pack() {
+                    eprintln!("Failed to pack monorepo: {}", e);
+                }
+            }
+        }
+        Command::RepoStats { repo } => {
+            let monorepo =
+                match open_existing_monorepo(&args.data_dir, &repo, &args.cache, &args.storage) {
+                    Some(monorepo) => monorepo,
+                    None => return,
+                };
+            match repo_stats::stats(monorepo.repo()) {
+                Ok(report) => print!("{}", report),
+                Err(e) => eprintln!("Failed to collect repository stats: {}", e),
+            }
+        }
+        Command::Maintain {
+            repo,
+            operation,
+            requests,
+            dry_run,
+        } => {
+            if dry_run {
+                let root = storage_root(&args.data_dir, &repo).join("monorepo");
+                if !fs::exists(&root).unwrap() {
+                    eprintln!("There is no monorepo at {}", root.display());
+                    return;
+                }
+                match maintain::plan(&root, operation) {
+                    Ok(plan) => print!("{}", plan),
+                    Err(e) => {
+                        eprintln!("Failed to plan maintenance: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            let monorepo =
+                match open_existing_monorepo(&args.data_dir, &repo, &args.cache, &args.storage) {
+                    Some(monorepo) => monorepo,
+                    None => return,
+                };
+            match maintain::run(&monorepo, operation, requests) {
+                Ok(report) => print!("{}", report),
+                Err(e) => {
+                    eprintln!("Maintenance failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::FailureTrends { repo } => {
+            let log = storage_root(&args.data_dir, &repo).join(FAILURE_LOG);
+            let failures = retry::load_failures(log).unwrap();
+            print!("{}", failure_taxonomy::Trends::of(&failures));
+        }
+        Command::Status { repo } => {
+            let storage_root = storage_root(&args.data_dir, &repo);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let monorepo = if fs::exists(storage_root.join("monorepo")).unwrap() {
+                Some(open_monorepo(
+                    &args.data_dir,
+                    &repo,
+                    &args.cache,
+                    &args.storage,
+                ))
+            } else {
+                None
+            };
+            match status::collect(&storage_root, storage.as_ref(), monorepo.as_ref()) {
+                Ok(status) => print!("{}", status),
+                Err(e) => eprintln!("Failed to collect status: {}", e),
+            }
+        }
+        Command::DownloadStats { repo } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            match download_stats::collect(storage.as_ref()) {
+                Ok(stats) => print!("{}", stats),
+                Err(e) => eprintln!("Error loading downloaded issues: {}", e),
+            }
+        }
+        Command::EstimateImport { repo } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let stats = download_stats::collect(storage.as_ref()).unwrap();
+            let runs = estimate::load(args.data_dir.join(estimate::IMPORT_RUNS)).unwrap();
+            match estimate::estimate(&runs, &stats) {
+                Some(e) => {
+                    println!(
+                        "Based on {} previous imports, importing {} changes will take about \
+                         {:?} and add about {} bytes and {} refs to the monorepo",
+                        e.runs, e.changes, e.time, e.bytes, e.refs
+                    );
+                }
+                None => eprintln!("No previous imports to base an estimate on"),
+            }
         }
-        Command::CountImportedIssues { repo } => {
-            let storage_root = args
-                .data_dir
-                .join(repo.owner.as_str())
-                .join(repo.name.as_str());
-            let monorepo_root = storage_root.join("monorepo");
-            let monorepo = LiteMonorepo::create_or_open(monorepo_root).unwrap();
+        Command::CountImportedIssues { repo, project } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            project.apply(&mut monorepo);
             match monorepo.list_issues() {
                 Ok(n) => println!("There are {} issues", n),
                 Err(e) => eprintln!("Error retrieving issues {}", e),
@@ -126,13 +1822,41 @@ async fn main() {
             repo,
             object_id,
             just_graphviz,
+            render,
+            all,
+            format,
         } => {
-            let storage_root = args
-                .data_dir
-                .join(repo.owner.as_str())
-                .join(repo.name.as_str());
-            let monorepo_root = storage_root.join("monorepo");
-            let monorepo = LiteMonorepo::create_or_open(monorepo_root).unwrap();
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            if all {
+                if object_id.is_some() {
+                    eprintln!("--all renders every object, don't give an object ID as well");
+                    std::process::exit(1);
+                }
+                let dir = render.unwrap();
+                let result = render::render_all(&monorepo, &dir, format, |path| {
+                    verbose!("rendered {}", path.display());
+                });
+                match result {
+                    Ok(n) => status!("rendered {} change graphs to {}", n, dir.display()),
+                    Err(e) => {
+                        eprintln!("Failed to render change graphs: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
+            let object_id = object_id.unwrap();
+            if let Some(path) = render {
+                match render::render_object(&monorepo, &object_id, &path) {
+                    Ok(true) => status!("rendered the change graph to {}", path.display()),
+                    Ok(false) => println!("no such issue"),
+                    Err(e) => {
+                        eprintln!("Failed to render the change graph: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+                return;
+            }
             match monorepo.issue_info(&object_id) {
                 Ok(Some(i)) => {
                     if just_graphviz {
@@ -146,24 +1870,1727 @@ async fn main() {
                 Err(e) => eprintln!("Error retrieving issue {:?}", e),
             }
         }
+        Command::HistoryLog {
+            repo,
+            object_id,
+            reverse,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let history = match monorepo.issue_history(&object_id) {
+                Ok(Some(h)) => h,
+                Ok(None) => {
+                    eprintln!("no such issue");
+                    return;
+                }
+                Err(e) => {
+                    eprintln!("Error retrieving issue {}", e);
+                    return;
+                }
+            };
+            let start = Instant::now();
+            let mut entries = history_log::log(&history, &peers_by_urn(&monorepo));
+            status!(
+                "replayed {} changes in {:?}",
+                entries.len(),
+                start.elapsed()
+            );
+            if !reverse {
+                entries.reverse();
+            }
+            for entry in entries {
+                println!("{}", entry);
+            }
+        }
+        Command::ShowImportLog { repo, number } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match monorepo.import_log().load(number) {
+                Ok(Some(record)) => print!("{}", record),
+                Ok(None) => {
+                    eprintln!("issue #{} has no import log", number);
+                    std::process::exit(1);
+                }
+                Err(e) => eprintln!("Failed to read the import log: {}", e),
+            }
+        }
+        Command::ShowPeer { repo, peer } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match peer_info::peer_info(&monorepo, &peer) {
+                Ok(Some(info)) => print!("{}", info),
+                Ok(None) => {
+                    eprintln!("{} is not a peer of the monorepo", peer);
+                    std::process::exit(1);
+                }
+                Err(e) => eprintln!("Failed to read the peer: {}", e),
+            }
+        }
+        Command::ShowProject { repo, project } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            project.apply(&mut monorepo);
+            match project_info::ProjectInfo::of(&monorepo) {
+                Ok(info) => print!("{}", info),
+                Err(e) => eprintln!("Failed to read the project: {}", e),
+            }
+        }
+        Command::CreateProject { repo, name } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match monorepo.create_project(&name) {
+                Ok(project) => println!("{} {}", name, project.urn()),
+                Err(e) => {
+                    eprintln!("Failed to create the project: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::ListProjects { repo } => {
+            let storage_root = storage_root(&args.data_dir, &repo);
+            // Opening the monorepo registers the project it was created with
+            open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let projects =
+                projects::Projects::load(storage_root.join("monorepo").join(projects::PROJECTS))
+                    .unwrap();
+            for (name, entry) in projects.iter() {
+                println!("{} {}", name, entry.urn);
+            }
+        }
+        Command::SearchAll {
+            repo,
+            query,
+            limit,
+            reindex,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let path = monorepo.root().join(search::SEARCH_INDEX);
+            let index = match search::SearchIndex::load(&path).unwrap() {
+                Some(index) if !reindex => index,
+                _ => match search::SearchIndex::build(&monorepo) {
+                    Ok((index, report)) => {
+                        let bytes = index.save(&path).unwrap();
+                        status!("{}, {} bytes", report, bytes);
+                        index
+                    }
+                    Err(e) => {
+                        eprintln!("Failed to build the search index: {}", e);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            print!("{}", index.search(&query, limit));
+        }
+        Command::EditProject {
+            repo,
+            project,
+            add_delegate,
+            remove_delegate,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            project.apply(&mut monorepo);
+            match monorepo.edit_project_delegates(&add_delegate, &remove_delegate) {
+                Ok(true) => {
+                    status!("Revised the project");
+                    print!("{}", project_info::ProjectInfo::of(&monorepo).unwrap());
+                }
+                Ok(false) => status!("The project already has those delegates"),
+                Err(e) => {
+                    eprintln!("Failed to revise the project: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Blame {
+            repo,
+            object_id,
+            path,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match monorepo.issue_history(&object_id) {
+                Ok(Some(history)) => {
+                    print!("{}", blame::blame(&history, path, &peers_by_urn(&monorepo)));
+                }
+                Ok(None) => eprintln!("no such issue"),
+                Err(e) => eprintln!("Error retrieving issue {}", e),
+            }
+        }
         Command::RetrieveIssue {
             repo,
             object_id,
             no_cache,
+            retrieval,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            retrieval.apply(&mut monorepo);
+            print_issue(&monorepo, &object_id, !no_cache);
+        }
+        Command::RetrieveIssueByNumber {
+            repo,
+            number,
+            no_cache,
+            retrieval,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            retrieval.apply(&mut monorepo);
+            if monorepo.issue_index_is_empty() {
+                let added = monorepo.reindex_issues().unwrap();
+                status!("indexed {} previously imported issues", added);
+            }
+            match monorepo.issue_object_id(number) {
+                Some(object_id) => {
+                    status!("issue #{} is object {}", number, object_id);
+                    print_issue(&monorepo, &object_id, !no_cache);
+                }
+                None => {
+                    eprintln!("issue #{} has not been imported", number);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::DeleteObject {
+            repo,
+            object_id,
+            all_peers,
+            count,
+            dry_run,
         } => {
-            let storage_root = args
-                .data_dir
-                .join(repo.owner.as_str())
-                .join(repo.name.as_str());
-            let monorepo_root = storage_root.join("monorepo");
-            let monorepo = LiteMonorepo::create_or_open(monorepo_root).unwrap();
-            match monorepo.retrieve_issue(&object_id, !no_cache) {
-                Ok(Some(json)) => {
-                    println!("{}", json);
-                }
-                Ok(None) => println!("null"),
+            if dry_run {
+                let monorepo =
+                    match open_existing_monorepo(&args.data_dir, &repo, &args.cache, &args.storage)
+                    {
+                        Some(monorepo) => monorepo,
+                        None => return,
+                    };
+                let ids = match (object_id, count) {
+                    (Some(object_id), _) => vec![object_id],
+                    (None, count) => match monorepo.list_issue_ids() {
+                        Ok(ids) => ids.into_iter().take(count.unwrap_or(0)).collect(),
+                        Err(e) => {
+                            eprintln!("Error retrieving issues {}", e);
+                            std::process::exit(1);
+                        }
+                    },
+                };
+                let mut objects = Vec::new();
+                for id in ids {
+                    match monorepo.issue_refs(&id, all_peers) {
+                        Ok(refs) => objects.push((id, refs)),
+                        Err(e) => {
+                            eprintln!("Error finding refs {}", e);
+                            return;
+                        }
+                    }
+                }
+                print!(
+                    "{}",
+                    dry_run::RemovalPlan {
+                        objects,
+                        cold_store: None,
+                    }
+                );
+                return;
+            }
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let object_id = match (object_id, count) {
+                (Some(object_id), _) => object_id,
+                (None, count) => {
+                    match deletion::measure(&monorepo, count.unwrap_or(0), all_peers) {
+                        Ok(report) => print!("{}", report),
+                        Err(e) => {
+                            eprintln!("Failed to delete objects: {}", e);
+                            std::process::exit(1);
+                        }
+                    }
+                    return;
+                }
+            };
+            let start = Instant::now();
+            match monorepo.delete_issue(&object_id, all_peers) {
+                Ok(0) => {
+                    println!("no refs found for {}", object_id);
+                    return;
+                }
+                Ok(n) => println!("Deleted {} refs in {:?}", n, start.elapsed()),
+                Err(e) => {
+                    eprintln!("Error deleting issue {}", e);
+                    return;
+                }
+            }
+            let start = Instant::now();
+            match monorepo.list_issue_ids() {
+                Ok(ids) => {
+                    let elapsed = start.elapsed();
+                    if ids.contains(&object_id) {
+                        println!(
+                            "{} is still listed via other peers' refs (listing took {:?})",
+                            object_id, elapsed
+                        );
+                    } else {
+                        println!(
+                            "{} is no longer listed, {} issues remain (listing took {:?})",
+                            object_id,
+                            ids.len(),
+                            elapsed
+                        );
+                    }
+                }
+                Err(e) => eprintln!("Error retrieving issues {}", e),
+            }
+        }
+        Command::VerifyChecksums {
+            repo,
+            record_missing,
+        } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            match download::verify_checksums(storage.as_ref(), record_missing) {
+                Ok(report) => {
+                    println!("{} issues verified", report.verified);
+                    for number in &report.mismatched {
+                        println!("checksum mismatch: issue {}", number);
+                    }
+                    for number in &report.missing {
+                        println!("in manifest but not stored: issue {}", number);
+                    }
+                    if !report.unrecorded.is_empty() {
+                        println!(
+                            "{} issues had no checksum in the manifest{}",
+                            report.unrecorded.len(),
+                            if record_missing {
+                                " (now recorded)"
+                            } else {
+                                ""
+                            }
+                        );
+                    }
+                    if !report.is_ok() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Error verifying checksums {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Export {
+            repo,
+            format,
+            out,
+            jobs,
+        } => {
+            let storage_root = storage_root(&args.data_dir, &repo);
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let issues = match jobs {
+                Some(jobs) => materialize_parallel(&monorepo, jobs),
+                None => monorepo.materialized_issues().unwrap(),
+            };
+            let import_runs = estimate::load(args.data_dir.join(estimate::IMPORT_RUNS)).unwrap();
+            let runs = runs::load(storage_root.join(runs::RUNS_LOG)).unwrap();
+            let out = out.unwrap_or_else(|| storage_root.join("export"));
+            let mut tables = export::object_tables(&issues);
+            tables.extend(export::run_tables(&import_runs, &runs));
+            for table in tables {
+                match table.write(&out, format) {
+                    Ok(()) => status!("{}", table),
+                    Err(e) => {
+                        eprintln!("Failed to export {}: {}", table, e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+            println!("Exported to {}", out.display());
+        }
+        Command::ExportMetrics {
+            repo,
+            format,
+            out,
+            project,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            project.apply(&mut monorepo);
+            let out = out.unwrap_or_else(|| storage_root(&args.data_dir, &repo).join("export"));
+            let table = match export::retrieval_metrics(&monorepo, &repo.to_string()) {
+                Ok(table) => table,
+                Err(e) => {
+                    eprintln!("Failed to measure objects: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if let Err(e) = table.write(&out, format) {
+                eprintln!("Failed to export {}: {}", table, e);
+                std::process::exit(1);
+            }
+            status!("{}", table);
+            println!("Exported to {}", out.display());
+        }
+        Command::ExportSqlite { repo, out } => {
+            let storage_root = storage_root(&args.data_dir, &repo);
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let issues = monorepo.materialized_issues().unwrap();
+            let entries = audit::AuditLog::new(monorepo.root().join(audit::AUDIT_LOG))
+                .load()
+                .unwrap();
+            let import_runs = estimate::load(args.data_dir.join(estimate::IMPORT_RUNS)).unwrap();
+            let runs = runs::load(storage_root.join(runs::RUNS_LOG)).unwrap();
+            let out = out.unwrap_or_else(|| storage_root.join("export.sqlite"));
+            let mut tables = export::object_tables(&issues);
+            tables.push(export::peer_table(&monorepo.peer_assignments()));
+            tables.push(export::metrics_table(&issues, &entries));
+            tables.extend(export::run_tables(&import_runs, &runs));
+            if let Err(e) = export::write_sqlite(&out, &tables) {
+                eprintln!("Failed to export to {}: {}", out.display(), e);
+                std::process::exit(1);
+            }
+            for table in &tables {
+                status!("{}", table);
+            }
+            println!("Exported to {}", out.display());
+        }
+        Command::ExportIssues {
+            repo,
+            out,
+            jobs,
+            project,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            project.apply(&mut monorepo);
+            let issues = jobs.map(|jobs| materialize_parallel(&monorepo, jobs));
+            let write = |out: &mut dyn std::io::Write| match &issues {
+                Some(issues) => export::write_documents(issues, out),
+                None => export::stream_documents(&monorepo, out),
+            };
+            let result = match &out {
+                Some(path) => fs::create(path)
+                    .map_err(export::Error::from)
+                    .and_then(|file| write(&mut std::io::BufWriter::new(file))),
+                None => write(&mut std::io::stdout().lock()),
+            };
+            match result {
+                Ok(n) => status!("Exported {} documents", n),
+                Err(e) => {
+                    eprintln!("Failed to export documents: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::VerifyImport { repo, jobs, exec } => {
+            let storage_root = storage_root(&args.data_dir, &repo);
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded = storage.issues().unwrap();
+            let issues = match jobs {
+                Some(jobs) => {
+                    let ids = monorepo.list_issue_ids().unwrap();
+                    match parallel::materialize(&monorepo.read_worker(), &ids, jobs) {
+                        Ok((issues, scaling)) => {
+                            print!("{}", scaling);
+                            issues
+                        }
+                        Err(e) => {
+                            eprintln!("Error retrieving issues {}", e);
+                            retry::record_failure(
+                                storage_root.join(FAILURE_LOG),
+                                "verify-import",
+                                &e,
+                            )
+                            .unwrap();
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                None => match monorepo.materialized_issues() {
+                    Ok(i) => i,
+                    Err(e) => {
+                        eprintln!("Error retrieving issues {}", e);
+                        retry::record_failure(storage_root.join(FAILURE_LOG), "verify-import", &e)
+                            .unwrap();
+                        std::process::exit(1);
+                    }
+                },
+            };
+            let exec_ok = exec.run(&storage_root, &issues);
+            let failures = retry::load_failures(storage_root.join(FAILURE_LOG)).unwrap();
+            let report = verify::verify(&downloaded, &issues, &failures);
+            for affected in &report.affected {
+                let boundary = match &affected.resume_boundary {
+                    Some(b) => format!(
+                        " (first issue imported after resuming from issue {} at {})",
+                        b.after_issue,
+                        b.at.to_rfc3339()
+                    ),
+                    None => String::new(),
+                };
+                for problem in &affected.problems {
+                    println!("issue {}: {}{}", affected.number, problem, boundary);
+                }
+            }
+            println!(
+                "{} of {} issues have problems",
+                report.affected.len(),
+                report.checked
+            );
+            if !report.affected.is_empty() || !exec_ok {
+                std::process::exit(1);
+            }
+        }
+        Command::BenchInterleaved {
+            repo,
+            readers,
+            writes,
+            hot_objects,
+            seed,
+        } => {
+            let monorepo_root = storage_root(&args.data_dir, &repo).join("monorepo");
+            let options = interleaved::Options {
+                readers,
+                writes,
+                hot_objects,
+                seed,
+            };
+            match interleaved::run(&monorepo_root, &options) {
+                Ok(report) => {
+                    let print = |name: &str, stats: &Option<bench::Stats>| match stats {
+                        Some(s) => println!("{}: {}", name, s),
+                        None => println!("{}: no samples", name),
+                    };
+                    print("reads without writer", &report.baseline_reads);
+                    print("reads with writer", &report.interleaved_reads);
+                    print("writes", &report.writes);
+                    for stale in &report.stale_reads {
+                        println!(
+                            "stale read of {}: saw {} comments, {} acknowledged",
+                            stale.object_id, stale.observed, stale.acknowledged
+                        );
+                    }
+                    println!("{} stale reads", report.stale_reads.len());
+                }
+                Err(e) => eprintln!("Benchmark failed: {}", e),
+            }
+        }
+        Command::CacheStats {
+            repo,
+            stale_days,
+            sample,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match cache_stats::stats(&monorepo, chrono::Duration::days(stale_days), sample) {
+                Ok(report) => print!("{}", report),
+                Err(e) => {
+                    eprintln!("Failed to gather cache statistics: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::BenchCacheSize {
+            repo,
+            sizes,
+            requests,
+            access,
+            retrieval,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            retrieval.apply(&mut monorepo);
+            let ids = monorepo.issue_ids_by_recency().unwrap();
+            if ids.is_empty() {
+                eprintln!("no issues to retrieve");
+                return;
+            }
+            let pattern = access.pattern();
+            println!("access pattern: {}", pattern);
+            for size in sizes {
+                monorepo.cache().clear().unwrap();
+                monorepo.cache().set_max_size(Some(size.0)).unwrap();
+                // Same seed for each size so every size sees the same sequence of requests
+                let report =
+                    access_pattern::replay(&monorepo, &ids, pattern, requests, access.seed, true)
+                        .unwrap();
+                let (entries, bytes) = monorepo.cache().usage().unwrap();
+                println!(
+                    "{}: hit rate {:.1}% ({} evictions, {} entries using {} bytes) latency {}",
+                    size,
+                    100.0 * report.counters.hits as f64 / requests as f64,
+                    report.counters.evictions,
+                    entries,
+                    bytes,
+                    report.latency.unwrap()
+                );
+            }
+            // Don't leave the cache capped at the last size we tried
+            monorepo
+                .cache()
+                .set_max_size(args.cache.cache_max_size.map(|s| s.0))
+                .unwrap();
+        }
+        Command::BenchCacheBackends {
+            repo,
+            size,
+            requests,
+            warmup,
+            access,
+            retrieval,
+            results,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            retrieval.apply(&mut monorepo);
+            let ids = monorepo.issue_ids_by_recency().unwrap();
+            if ids.is_empty() {
+                eprintln!("no issues to retrieve");
+                return;
+            }
+            let pattern = access.pattern();
+            println!("access pattern: {}", pattern);
+            let sqlite_path = storage_root(&args.data_dir, &repo).join("cache_bench.sqlite");
+            let result = cache_backends::compare(
+                &monorepo,
+                &ids,
+                pattern,
+                warmup,
+                requests,
+                access.seed,
+                size.0,
+                &sqlite_path,
+            );
+            // Don't leave the cache capped at the size we tried
+            monorepo
+                .cache()
+                .set_max_size(args.cache.cache_max_size.map(|s| s.0))
+                .unwrap();
+            let _ = fs::remove_file(&sqlite_path);
+            match result {
+                Ok(report) => {
+                    print!("{}", report);
+                    results.push("bench-cache-backends", &repo, &report);
+                }
+                Err(e) => {
+                    eprintln!("Failed to compare cache backends: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::BenchAccess {
+            repo,
+            requests,
+            no_cache,
+            access,
+            retrieval,
+            anomalies,
+            results,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            retrieval.apply(&mut monorepo);
+            let detector = anomalies.detector("bench-access");
+            monorepo.set_anomaly_detector(Some(detector.clone()));
+            let ids = monorepo.issue_ids_by_recency().unwrap();
+            if ids.is_empty() {
+                eprintln!("no issues to retrieve");
+                return;
+            }
+            let pattern = access.pattern();
+            match access_pattern::replay(&monorepo, &ids, pattern, requests, access.seed, !no_cache)
+            {
+                Ok(report) => {
+                    println!("access pattern: {}", pattern);
+                    println!(
+                        "{} requests for {} distinct issues out of {}",
+                        requests,
+                        report.distinct,
+                        ids.len()
+                    );
+                    if !no_cache {
+                        println!(
+                            "cache hit rate {:.1}% ({} evictions)",
+                            100.0 * report.counters.hits as f64 / requests as f64,
+                            report.counters.evictions
+                        );
+                    }
+                    if let Some(latency) = &report.latency {
+                        println!("latency {}", latency);
+                    }
+                    report_anomalies(&storage_root(&args.data_dir, &repo), &detector);
+                    let cached = !no_cache;
+                    results.push(
+                        "bench-access",
+                        &repo,
+                        &serde_json::json!({
+                            "pattern": pattern.to_string(),
+                            "requests": requests,
+                            "issues": ids.len(),
+                            "distinct": report.distinct,
+                            "cache": cached,
+                            "counters": cached.then(|| report.counters),
+                            "latency": report.latency,
+                        }),
+                    );
+                }
                 Err(e) => eprintln!("Error retrieving issue {}", e),
             }
         }
+        Command::Archive {
+            repo,
+            inactive_days,
+            max_fraction,
+            dry_run,
+        } => {
+            let monorepo = if dry_run {
+                match open_existing_monorepo(&args.data_dir, &repo, &args.cache, &args.storage) {
+                    Some(monorepo) => monorepo,
+                    None => return,
+                }
+            } else {
+                open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage)
+            };
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: HashMap<u64, downloaded_issue::DownloadedIssue> = storage
+                .issues()
+                .unwrap()
+                .into_iter()
+                .map(|i| (i.number, i))
+                .collect();
+            let issues = match monorepo.materialized_issues() {
+                Ok(i) => i,
+                Err(e) => {
+                    eprintln!("Error retrieving issues {}", e);
+                    return;
+                }
+            };
+            let inactive_since = chrono::Utc::now() - chrono::Duration::days(inactive_days);
+            let to_archive = archive::select(&issues, &downloaded, inactive_since, max_fraction);
+            if dry_run {
+                let mut objects = Vec::new();
+                for issue in &to_archive {
+                    objects.push((issue.id, monorepo.issue_refs(&issue.id, true).unwrap()));
+                }
+                print!(
+                    "{}",
+                    dry_run::RemovalPlan {
+                        objects,
+                        cold_store: Some(monorepo.cold_store_dir()),
+                    }
+                );
+                return;
+            }
+            let bar = progress_bar(to_archive.len());
+            for issue in &to_archive {
+                bar.inc(1);
+                let start = Instant::now();
+                if let Err(e) = monorepo.archive_issue(issue) {
+                    eprintln!("Failed to archive issue {}: {}", issue.id, e);
+                    return;
+                }
+                verbose!("archived {} in {:?}", issue.id, start.elapsed());
+            }
+            bar.finish();
+            println!("Archived {} of {} issues", to_archive.len(), issues.len());
+
+            let start = Instant::now();
+            let hot_ids = monorepo.list_issue_ids().unwrap();
+            println!(
+                "Listing {} hot issues took {:?}",
+                hot_ids.len(),
+                start.elapsed()
+            );
+            if !hot_ids.is_empty() {
+                let start = Instant::now();
+                for id in &hot_ids {
+                    monorepo.retrieve_issue(id, true).unwrap();
+                }
+                println!(
+                    "Mean hot retrieval time {:?}",
+                    start.elapsed() / hot_ids.len() as u32
+                );
+            }
+            if !to_archive.is_empty() {
+                let start = Instant::now();
+                for issue in &to_archive {
+                    monorepo.retrieve_archived_issue(&issue.id).unwrap();
+                }
+                println!(
+                    "Mean cold retrieval time {:?}",
+                    start.elapsed() / to_archive.len() as u32
+                );
+            }
+            let (archived, bytes) = monorepo.cold_store().unwrap().usage().unwrap();
+            println!("Cold store holds {} issues in {} bytes", archived, bytes);
+        }
+        Command::SignRefs { repo } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match monorepo.sign_refs() {
+                Ok(n) => println!("Signed {} refs", n),
+                Err(e) => eprintln!("Failed to sign refs: {}", e),
+            }
+        }
+        Command::Track { repo, peers } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let tracking = peers.map(|n| Tracking::generate(n, monorepo.peer_ids()));
+            monorepo.set_tracking(tracking);
+            monorepo.save_tracking().unwrap();
+            match monorepo.tracking() {
+                Some(t) => println!("Tracking {} peers", t.len()),
+                None => println!("Tracking every peer"),
+            }
+        }
+        Command::BenchTracking {
+            repo,
+            sizes,
+            requests,
+            access,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            monorepo.set_tracking(None);
+            let ids = monorepo.issue_ids_by_recency().unwrap();
+            if ids.is_empty() {
+                eprintln!("no issues to retrieve");
+                return;
+            }
+            let pattern = access.pattern();
+            println!("access pattern: {}", pattern);
+            for size in sizes {
+                let tracking = Tracking::generate(size, monorepo.peer_ids());
+                monorepo.set_tracking(Some(tracking));
+                let start = Instant::now();
+                let visible = monorepo.list_issue_ids().unwrap().len();
+                let list_time = start.elapsed();
+                // Bypass the cache so that every retrieval has to look up the refs of the tracked
+                // peers
+                let report =
+                    access_pattern::replay(&monorepo, &ids, pattern, requests, access.seed, false)
+                        .unwrap();
+                println!(
+                    "{} peers: {} of {} issues visible, listed in {:?}, retrieval latency {}",
+                    size,
+                    visible,
+                    ids.len(),
+                    list_time,
+                    report.latency.unwrap()
+                );
+            }
+        }
+        Command::FuzzChanges {
+            repo,
+            iterations,
+            issues,
+            seed,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let mut ids = monorepo.list_issue_ids().unwrap();
+            if let Some(n) = issues {
+                ids.truncate(n);
+            }
+            let mut histories = Vec::with_capacity(ids.len());
+            for id in ids {
+                if let Some(history) = monorepo.issue_history(&id).unwrap() {
+                    histories.push((id, history));
+                }
+            }
+            let out_dir = storage_root(&args.data_dir, &repo).join("fuzz");
+            let report = fuzz::fuzz(&histories, iterations, seed, &out_dir).unwrap();
+            println!(
+                "{} cases: {} rejected, {} accepted, {} crashed",
+                report.cases,
+                report.rejected,
+                report.accepted,
+                report.crashes.len()
+            );
+            for crash in &report.crashes {
+                println!(
+                    "  {} ({}): {} -> {}",
+                    crash.object_id,
+                    crash.mutation,
+                    crash.message,
+                    crash.path.display()
+                );
+            }
+        }
+        Command::SimulateDuplicateDelivery {
+            repo,
+            copies,
+            history,
+            objects,
+            seed,
+            network,
+            impersonate,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let replica_root = replication::replicas_dir(&storage_root(&args.data_dir, &repo))
+                .join("duplicate-delivery");
+            let options = duplicate_delivery::Options {
+                copies,
+                history,
+                objects,
+                seed,
+                network: network.network(),
+                impersonate,
+            };
+            println!("network: {}", options.network);
+            match duplicate_delivery::run(&monorepo, &replica_root, &options) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.is_ok() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => eprintln!("Failed to replicate: {}", e),
+            }
+        }
+        Command::SimulateReplication {
+            repo,
+            nodes,
+            issues,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let dir = replication::replicas_dir(&storage_root(&args.data_dir, &repo))
+                .join("replication-simulation");
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            match replication_simulation::run(&monorepo, &dir, &downloaded, nodes) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.is_ok() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => eprintln!("Failed to simulate replication: {}", e),
+            }
+        }
+        Command::RefAdvertisement { repo, steps } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match ref_advertisement::measure(monorepo.repo(), steps) {
+                Ok(report) => print!("{}", report),
+                Err(e) => eprintln!("Failed to measure the ref advertisement: {}", e),
+            }
+        }
+        Command::CompareRefLayouts { repo } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let root = storage_root(&args.data_dir, &repo).join("index-refs");
+            match index_refs::compare(&monorepo, &root) {
+                Ok(report) => print!("{}", report),
+                Err(e) => eprintln!("Failed to compare ref layouts: {}", e),
+            }
+        }
+        Command::BenchTypeReferences { repo, iterations } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match ref_enumeration::bench(&monorepo, iterations) {
+                Ok(report) => print!("{}", report),
+                Err(e) => eprintln!("Failed to benchmark enumerating issues: {}", e),
+            }
+        }
+        Command::BenchMemoryRefs {
+            repo,
+            iterations,
+            snapshot,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let refs = match &snapshot {
+                Some(path) if path.exists() => MemoryRefs::open(path, monorepo.repo()),
+                _ => MemoryRefs::load(&monorepo),
+            };
+            let refs = match refs {
+                Ok(refs) => refs,
+                Err(e) => {
+                    eprintln!("Failed to load the refs: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(path) = snapshot.filter(|p| !p.exists()) {
+                refs.save(&path).unwrap();
+                println!(
+                    "Wrote a snapshot of {} refs to {}",
+                    refs.len(),
+                    path.display()
+                );
+            }
+            match memory_refs::bench(&monorepo, &refs, iterations) {
+                Ok(report) => print!("{}", report),
+                Err(e) => eprintln!("Failed to benchmark refs in memory: {}", e),
+            }
+        }
+        Command::BenchBatching {
+            repo,
+            windows,
+            max_delay,
+        } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let issues = storage.issues().unwrap();
+            for window in windows {
+                let policy = batching::Policy {
+                    window: std::time::Duration::from_secs(window),
+                    max_delay: max_delay.map(std::time::Duration::from_secs),
+                };
+                println!("{}", batching::replay(&issues, policy));
+            }
+        }
+        Command::BenchDelegateOnly {
+            repo,
+            delegates,
+            project,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            project.apply(&mut monorepo);
+            let mut delegate_peers = monorepo.delegates();
+            delegate_peers.sort_by_key(|p| p.to_string());
+            if let Some(n) = delegates {
+                delegate_peers.truncate(n);
+            }
+            let others = monorepo
+                .peer_ids()
+                .filter(|p| !delegate_peers.contains(p))
+                .count();
+            if others == 0 {
+                eprintln!(
+                    "Every peer is a delegate of the project, so evaluating only the changes of \
+                     delegates would change nothing. Remove some with edit-project, or pass \
+                     --delegates."
+                );
+                std::process::exit(1);
+            }
+
+            monorepo.cache().clear().unwrap();
+            let start = Instant::now();
+            let all: HashMap<ObjectId, serde_json::Value> = monorepo
+                .materialized_issues()
+                .unwrap()
+                .into_iter()
+                .map(|i| (i.id, i.document))
+                .collect();
+            let all_time = start.elapsed();
+
+            monorepo.set_delegate_only(Some(delegate_peers.clone()));
+            monorepo.cache().clear().unwrap();
+            let start = Instant::now();
+            let delegate_only: HashMap<ObjectId, serde_json::Value> = monorepo
+                .materialized_issues()
+                .unwrap()
+                .into_iter()
+                .map(|i| (i.id, i.document))
+                .collect();
+            let delegate_only_time = start.elapsed();
+
+            let mut same = 0;
+            let mut different = 0;
+            let mut missing = 0;
+            for (id, document) in &all {
+                match delegate_only.get(id) {
+                    Some(d) if d == document => same += 1,
+                    Some(_) => different += 1,
+                    None => missing += 1,
+                }
+            }
+            println!(
+                "{} delegates, {} peers which aren't",
+                delegate_peers.len(),
+                others
+            );
+            println!("all peers: {} issues in {:?}", all.len(), all_time);
+            println!(
+                "delegate only: {} issues in {:?}",
+                delegate_only.len(),
+                delegate_only_time
+            );
+            println!(
+                "{} identical, {} different, {} missing when evaluating delegates only",
+                same, different, missing
+            );
+        }
+        Command::VerifyAuthorship { repo } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match authorship::scan(&monorepo) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.anomalies.is_empty() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to check authorship: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::VerifyRejections { repo } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match invalid_changes::verify(&monorepo) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if report.checked == 0 {
+                        eprintln!(
+                            "No invalid changes recorded, import with --inject-invalid first"
+                        );
+                        std::process::exit(1);
+                    }
+                    if !report.passed() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to verify rejections: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::VerifyTampering { repo } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match tampering::verify(&monorepo) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if report.checked == 0 {
+                        eprintln!(
+                            "No tampered changes recorded, import with --tamper-signatures first"
+                        );
+                        std::process::exit(1);
+                    }
+                    if !report.passed() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to verify tampering: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::VerifyAcl {
+            repo,
+            inject,
+            seed,
+            exec,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            if inject > 0 {
+                let injected = acl::inject_violations(&mut monorepo, inject, seed).unwrap();
+                println!(
+                    "Changed the titles of {} issues without permission",
+                    injected
+                );
+            }
+            let issues = monorepo.materialized_issues().unwrap();
+            let mut with_acl = 0;
+            let mut violations = 0;
+            let mut failed = 0;
+            for issue in &issues {
+                if issue.document.get("acl").is_none() {
+                    continue;
+                }
+                with_acl += 1;
+                let found = match acl::check(&issue.history) {
+                    Ok(found) => found,
+                    Err(e) => {
+                        eprintln!("{}: failed to check: {}", issue.id, e);
+                        failed += 1;
+                        continue;
+                    }
+                };
+                for violation in found {
+                    violations += 1;
+                    println!(
+                        "{}: {} changed by {} but only {} may change it",
+                        issue.id,
+                        violation.field,
+                        violation.editor.as_deref().unwrap_or("an unknown identity"),
+                        violation.owner
+                    );
+                }
+            }
+            println!(
+                "{} of {} issues have an ACL, {} violations, {} couldn't be checked",
+                with_acl,
+                issues.len(),
+                violations,
+                failed
+            );
+            if !exec.run(&storage_root(&args.data_dir, &repo), &issues) || failed > 0 {
+                std::process::exit(1);
+            }
+        }
+        Command::CompareLayouts {
+            repo,
+            issues,
+            concurrent,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            let some_peer = *monorepo.peer_ids().next().unwrap();
+            let author = monorepo.peer_urn(&some_peer).unwrap().unwrap();
+            println!(
+                "{:<20} {:>14} {:>16} {:>8} {:>8}",
+                "layout", "history bytes", "materialize", "intact", "broken"
+            );
+            for report in layout::compare(&downloaded, &author, concurrent) {
+                println!(
+                    "{:<20} {:>14} {:>16?} {:>8} {:>8}",
+                    report.layout.to_string(),
+                    report.history_bytes,
+                    report.materialize_time,
+                    report.merges_intact,
+                    report.merges_broken
+                );
+            }
+        }
+        Command::CompareSchemas {
+            repo,
+            issues,
+            layout,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            let some_peer = *monorepo.peer_ids().next().unwrap();
+            let author = monorepo.peer_urn(&some_peer).unwrap().unwrap();
+            let reports = schema_strictness::compare(&downloaded, &author, layout);
+            println!(
+                "{:<12} {:>12} {:>8} {:>9} {:>8} {:>14} {:>14} {:>14}",
+                "schema",
+                "compile",
+                "changes",
+                "rejected",
+                "issues",
+                "validate",
+                "per change",
+                "materialize"
+            );
+            for report in &reports {
+                println!(
+                    "{:<12} {:>12?} {:>8} {:>9} {:>8} {:>14?} {:>14?} {:>14?}",
+                    report.strictness.to_string(),
+                    report.compile_time,
+                    report.changes,
+                    report.rejected,
+                    report.issues_rejected,
+                    report.validate_time,
+                    report.validate_per_change(),
+                    report.materialize_time
+                );
+            }
+            for report in &reports {
+                for (number, reason) in &report.examples {
+                    println!(
+                        "{} rejected a change to #{}: {}",
+                        report.strictness, number, reason
+                    );
+                }
+            }
+            if let Some(guidance) = schema_strictness::guidance(&reports) {
+                println!("{}", guidance);
+            }
+        }
+        Command::BenchActorIds {
+            repo,
+            issues,
+            peers,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            let some_peer = *monorepo.peer_ids().next().unwrap();
+            let author = monorepo.peer_urn(&some_peer).unwrap().unwrap();
+            for report in actor_ids::compare(&downloaded, &author, peers) {
+                println!("{}", report);
+            }
+        }
+        Command::SimulateTextEdits {
+            repo,
+            issues,
+            sessions,
+            fields,
+            seed,
+        } => {
+            let mut monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let options = text_edits::Options {
+                issues,
+                sessions,
+                fields,
+                seed,
+            };
+            match text_edits::simulate(&mut monorepo, &options) {
+                Ok(report) => print!("{}", report),
+                Err(e) => {
+                    eprintln!("Failed to simulate text edits: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::CheckDeterminism {
+            repo,
+            issues,
+            peers,
+            seed,
+        } => {
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            match determinism::check(&downloaded, peers, seed) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.is_deterministic() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to check determinism: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::CheckModeration { repo, issues } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            let some_peer = *monorepo.peer_ids().next().unwrap();
+            let author = monorepo.peer_urn(&some_peer).unwrap().unwrap();
+            let reports = moderation::check(&downloaded, &author);
+            println!(
+                "{:<27} {:>6} {:>6} {:>10} {:>5} {:>9} {:>9} {:>9}",
+                "operations",
+                "merges",
+                "intact",
+                "duplicated",
+                "lost",
+                "hide lost",
+                "edit lost",
+                "not moved"
+            );
+            for report in &reports {
+                println!("{}", report);
+            }
+            for report in &reports {
+                if let Some((number, problems)) = &report.example {
+                    println!(
+                        "{} + {} on #{}: {}",
+                        report.first, report.second, number, problems
+                    );
+                }
+            }
+        }
+        Command::Minimize {
+            repo,
+            object_id,
+            check,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let history = match monorepo.issue_history(&object_id).unwrap() {
+                Some(h) => h,
+                None => {
+                    eprintln!("no such issue");
+                    return;
+                }
+            };
+            let minimized = match minimize::minimize(&history, check) {
+                Some(m) => m,
+                None => {
+                    eprintln!("{} does not fail the {} check", object_id, check);
+                    return;
+                }
+            };
+            println!(
+                "{} of {} changes reproduce the failure (shortest failing prefix is {} changes)",
+                minimized.changes.len(),
+                minimized.original,
+                minimized.prefix
+            );
+            let dir = storage_root(&args.data_dir, &repo)
+                .join("minimized")
+                .join(object_id.to_string());
+            let test = minimize::export(&dir, &object_id, check, &minimized).unwrap();
+            println!("Wrote test case to {}", test.display());
+        }
+        Command::ExportRepro {
+            repo,
+            object_id,
+            exec,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let dir = storage_root(&args.data_dir, &repo)
+                .join("repro")
+                .join(object_id.to_string());
+            match repro::export(&monorepo, &object_id, &dir) {
+                Ok(Some(refs)) => {
+                    println!("Exported {} refs to {}", refs, dir.display());
+                    if let Some(issue) = monorepo.materialized_issue(&object_id).unwrap() {
+                        if !exec.run(&storage_root(&args.data_dir, &repo), &[issue]) {
+                            std::process::exit(1);
+                        }
+                    }
+                }
+                Ok(None) => eprintln!("no such issue"),
+                Err(e) => eprintln!("Failed to export reproducer: {}", e),
+            }
+        }
+        Command::BenchOpen { repos, iterations } => {
+            for repo in repos {
+                let mut total = lite_monorepo::OpenTimings::default();
+                let mut refs = 0;
+                for _ in 0..iterations {
+                    let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+                    let timings = monorepo.open_timings();
+                    total.peers += timings.peers;
+                    total.repo += timings.repo;
+                    total.peer_assignments += timings.peer_assignments;
+                    total.peer_identities += timings.peer_identities;
+                    total.project += timings.project;
+                    total.cache += timings.cache;
+                    refs = monorepo.ref_count().unwrap();
+                }
+                let n = iterations.max(1);
+                println!("{} ({} refs)", repo, refs);
+                println!("  peers            {:?}", total.peers / n);
+                println!("  repo             {:?}", total.repo / n);
+                println!("  peer assignments {:?}", total.peer_assignments / n);
+                println!("  peer identities  {:?}", total.peer_identities / n);
+                println!("  project          {:?}", total.project / n);
+                println!("  cache            {:?}", total.cache / n);
+                println!("  total            {:?}", total.total() / n);
+            }
+        }
+        Command::ScaleObjects {
+            dir,
+            mut checkpoints,
+            users,
+            stop_below,
+        } => {
+            let dir = dir.unwrap_or_else(|| args.data_dir.join("object-scaling"));
+            checkpoints.sort_unstable();
+            let options = object_scaling::Options {
+                checkpoints,
+                users,
+                stop_below,
+            };
+            let result = object_scaling::run(&dir, &options, |checkpoint| {
+                println!("{}", checkpoint);
+            });
+            if let Err(e) = result {
+                eprintln!("Scaling scenario failed: {}", e);
+            }
+        }
+        Command::ScalePeers {
+            repo,
+            peers,
+            issues,
+            retrievals,
+            dir,
+        } => {
+            let dir =
+                dir.unwrap_or_else(|| storage_root(&args.data_dir, &repo).join("peer-scaling"));
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            for count in peers {
+                match peer_scaling::run(&dir, &downloaded, count, retrievals) {
+                    Ok(report) => print!("{}", report),
+                    Err(e) => {
+                        eprintln!("Failed with {} peers: {}", count, e);
+                        return;
+                    }
+                }
+            }
+        }
+        Command::BenchRetention {
+            dir,
+            issues,
+            years,
+            growth,
+            comments,
+            users,
+            seed,
+            retain,
+            retrievals,
+        } => {
+            let dir = dir.unwrap_or_else(|| args.data_dir.join("retention"));
+            let corpus = retention::corpus(&retention::CorpusOptions {
+                issues,
+                years,
+                growth,
+                comments,
+                users,
+                seed,
+            });
+            if let Some((oldest, newest)) = retention::age_range(&corpus) {
+                status!(
+                    "generated {} issues created between {} and {}",
+                    corpus.len(),
+                    oldest,
+                    newest
+                );
+            }
+            let result = retention::run(&dir, &corpus, &retain, retrievals, |report| {
+                print!("{}", report);
+            });
+            if let Err(e) = result {
+                eprintln!("Retention benchmark failed: {}", e);
+            }
+        }
+        Command::BenchSnapshots {
+            repo,
+            issues,
+            every,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            let dir =
+                replication::replicas_dir(&storage_root(&args.data_dir, &repo)).join("snapshots");
+            let result = snapshots::run(&monorepo, &dir, &downloaded, &every, |report| {
+                print!("{}", report);
+            });
+            if let Err(e) = result {
+                eprintln!("Snapshot benchmark failed: {}", e);
+            }
+        }
+        Command::TestDiskFull { repo, dir, reserve } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            match disk_full::run(&monorepo, storage.as_ref(), &dir, reserve) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.is_ok() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Disk full test failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::BenchSchema {
+            repo,
+            issues,
+            layout,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let storage = issue_storage(&args.data_dir, &repo, &args.storage);
+            let downloaded: Vec<downloaded_issue::DownloadedIssue> = storage
+                .issue_numbers()
+                .unwrap()
+                .into_iter()
+                .take(issues)
+                .filter_map(|n| storage.issue(n).unwrap())
+                .collect();
+            let some_peer = *monorepo.peer_ids().next().unwrap();
+            let author = monorepo.peer_urn(&some_peer).unwrap().unwrap();
+            match schema_cost::bench(&downloaded, &author, layout) {
+                Some(report) => println!("{}", report),
+                None => eprintln!("No downloaded issues"),
+            }
+        }
+        Command::BenchMetrics {
+            repo,
+            requests,
+            results,
+        } => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            match bisect_perf::measure(&monorepo, requests) {
+                Ok(metrics) => {
+                    println!("{}", serde_json::to_string(&metrics).unwrap());
+                    results.push("bench-metrics", &repo, &metrics);
+                }
+                Err(e) => {
+                    eprintln!("Benchmark failed: {}", e);
+                    retry::record_failure(
+                        storage_root(&args.data_dir, &repo).join(FAILURE_LOG),
+                        "bench-metrics",
+                        &e,
+                    )
+                    .unwrap();
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::BisectPerf {
+            repo,
+            cob_repo,
+            good,
+            bad,
+            metric,
+            threshold,
+            requests,
+            runs,
+            work_dir,
+        } => {
+            let options = bisect_perf::Options {
+                cob_repo,
+                good,
+                bad,
+                metric,
+                threshold,
+                work_dir: work_dir.unwrap_or_else(|| args.data_dir.join("bisect-perf")),
+                data_dir: args.data_dir.clone(),
+                repo: repo.to_string(),
+                requests,
+                runs,
+            };
+            let result = bisect_perf::bisect(&options, |step| println!("{}", step));
+            match result {
+                Ok(bisect_perf::Outcome::Found { revision, summary }) => println!(
+                    "{} exceeds {}ms on {} first: {}",
+                    revision, threshold, metric, summary
+                ),
+                Ok(bisect_perf::Outcome::GoodRegressed) => {
+                    eprintln!("{} already exceeds the threshold", options.good)
+                }
+                Ok(bisect_perf::Outcome::BadWithinThreshold) => {
+                    eprintln!("{} is within the threshold", options.bad)
+                }
+                Err(e) => eprintln!("Bisection failed: {}", e),
+            }
+        }
+        Command::Serve { repo, addr } => {
+            let monorepo =
+                match open_existing_monorepo(&args.data_dir, &repo, &args.cache, &args.storage) {
+                    Some(monorepo) => monorepo,
+                    None => std::process::exit(1),
+                };
+            if monorepo.issue_index_is_empty() {
+                let added = monorepo.reindex_issues().unwrap();
+                status!("indexed {} previously imported issues", added);
+            }
+            if let Err(e) = serve::serve(&monorepo, &addr) {
+                eprintln!("Server failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Command::Scenario(ScenarioCommand::Run { file, dir }) => {
+            let scenario = match scenario::Scenario::load(&file) {
+                Ok(scenario) => scenario,
+                Err(e) => {
+                    eprintln!("Failed to load {}: {}", file.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            if let Some(description) = &scenario.description {
+                status!("{}", description);
+            }
+            let dir = dir.unwrap_or_else(|| {
+                let name = file.file_stem().unwrap_or_else(|| file.as_os_str());
+                args.data_dir.join("scenarios").join(name)
+            });
+            match scenario::run(&scenario, &dir) {
+                Ok(summary) => {
+                    print!("{}", summary);
+                    if !summary.is_ok() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Scenario failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Command::Audit(AuditCommand::Show {
+            repo,
+            issue,
+            object,
+            peer,
+            since,
+            failed,
+            slowest,
+        }) => {
+            let path = storage_root(&args.data_dir, &repo)
+                .join("monorepo")
+                .join(audit::AUDIT_LOG);
+            let filter = audit::Filter {
+                issue,
+                object_id: object,
+                peer,
+                since,
+                failed,
+            };
+            let mut entries: Vec<audit::Entry> = match audit::AuditLog::new(&path).load() {
+                Ok(entries) => entries.into_iter().filter(|e| filter.matches(e)).collect(),
+                Err(e) => {
+                    eprintln!("Failed to load {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            let total: std::time::Duration = entries.iter().map(|e| e.duration()).sum();
+            let count = entries.len();
+            if let Some(slowest) = slowest {
+                entries.sort_by_key(|e| std::cmp::Reverse(e.duration_millis));
+                entries.truncate(slowest);
+            }
+            for entry in &entries {
+                println!("{}", entry);
+            }
+            status!("{} operations taking {:?} in total", count, total);
+        }
+        Command::Audit(AuditCommand::Replay {
+            repo,
+            issue,
+            limit,
+            dir,
+        }) => {
+            let monorepo = open_monorepo(&args.data_dir, &repo, &args.cache, &args.storage);
+            let path = monorepo.root().join(audit::AUDIT_LOG);
+            let filter = audit::Filter {
+                issue,
+                ..Default::default()
+            };
+            let entries: Vec<audit::Entry> = match audit::AuditLog::new(&path).load() {
+                Ok(entries) => entries
+                    .into_iter()
+                    .filter(|e| filter.matches(e))
+                    .take(limit.unwrap_or(usize::MAX))
+                    .collect(),
+                Err(e) => {
+                    eprintln!("Failed to load {}: {}", path.display(), e);
+                    std::process::exit(1);
+                }
+            };
+            let dir = dir.unwrap_or_else(|| storage_root(&args.data_dir, &repo).join("replay"));
+            match replay::replay(&monorepo, &entries, &dir) {
+                Ok(report) => {
+                    print!("{}", report);
+                    if !report.failed.is_empty() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("Failed to replay the audit log: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
     };
 }
@@ -0,0 +1,227 @@
+//! Git maintenance of the monorepo's repository and what it does for reading collaborative
+//! objects. An import writes every change as loose objects, one file each, and cob reads each
+//! change of an object when evaluating it, so how the objects are stored matters at scale.
+//!
+//! The repository is measured with the benchmark `bench-metrics` runs, and its objects counted
+//! loose and packed, before and after running one of
+//!
+//! * `repack`: `git repack -a -d` and `git pack-refs --all`, everything into a single pack
+//! * `gc`: `git gc`, which also prunes unreachable objects older than two weeks
+//! * `gc-aggressive`: `git gc --aggressive --prune=now`, which spends much longer looking for
+//!   deltas and prunes every unreachable object
+//!
+//! [`plan`] works out what an operation would prune without running it, for `--dry-run`. It reads
+//! the repository directly, as opening the monorepo may migrate its peer identities.
+use std::{
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{
+    bisect_perf::{self, Metrics},
+    dry_run::MaintenancePlan,
+    lite_monorepo::{error, LiteMonorepo},
+    peer_identities::{self, PeerIdentities},
+    repo_stats::{self, Disk},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Pack(#[from] error::Pack),
+    #[error(transparent)]
+    Stats(#[from] repo_stats::Error),
+    #[error(transparent)]
+    Bench(#[from] bisect_perf::Error),
+    #[error(transparent)]
+    Open(#[from] git2::Error),
+    #[error(transparent)]
+    PeerIdentities(#[from] peer_identities::Error),
+    #[error("`git {command}` failed: {stderr}")]
+    Git { command: String, stderr: String },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Repack,
+    Gc,
+    GcAggressive,
+}
+
+#[derive(Debug, Error)]
+#[error("expected repack, gc or gc-aggressive")]
+pub struct ParseOperationError {}
+
+impl FromStr for Operation {
+    type Err = ParseOperationError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "repack" => Ok(Operation::Repack),
+            "gc" => Ok(Operation::Gc),
+            "gc-aggressive" => Ok(Operation::GcAggressive),
+            _ => Err(ParseOperationError {}),
+        }
+    }
+}
+
+/// The repository and the benchmark at one point
+pub struct Measurement {
+    pub disk: Disk,
+    pub metrics: Metrics,
+}
+
+pub struct Report {
+    pub operation: Operation,
+    pub took: Duration,
+    pub before: Measurement,
+    pub after: Measurement,
+}
+
+/// Measure `monorepo`, retrieving `requests` issues, run `operation` on it and measure it again
+pub fn run(
+    monorepo: &LiteMonorepo,
+    operation: Operation,
+    requests: usize,
+) -> Result<Report, Error> {
+    let before = measure(monorepo, requests)?;
+    let start = Instant::now();
+    match operation {
+        Operation::Repack => monorepo.pack()?,
+        Operation::Gc => {
+            git(monorepo.repo().path(), &["gc", "--quiet"])?;
+        }
+        Operation::GcAggressive => {
+            git(
+                monorepo.repo().path(),
+                &["gc", "--quiet", "--aggressive", "--prune=now"],
+            )?;
+        }
+    }
+    let took = start.elapsed();
+    let after = measure(monorepo, requests)?;
+    Ok(Report {
+        operation,
+        took,
+        before,
+        after,
+    })
+}
+
+fn measure(monorepo: &LiteMonorepo, requests: usize) -> Result<Measurement, Error> {
+    Ok(Measurement {
+        disk: repo_stats::disk(monorepo.repo())?,
+        metrics: bisect_perf::measure(monorepo, requests)?,
+    })
+}
+
+/// What running `operation` on the monorepo at `root` would do
+pub fn plan(root: &Path, operation: Operation) -> Result<MaintenancePlan, Error> {
+    let repo = git2::Repository::open_bare(root.join("git"))?;
+    // As `git gc` prunes by default and with `--prune=now`
+    let expire = match operation {
+        Operation::Repack => None,
+        Operation::Gc => Some("2.weeks.ago"),
+        Operation::GcAggressive => Some("now"),
+    };
+    let pruned = match expire {
+        Some(expire) => git(
+            repo.path(),
+            &["prune", "--dry-run", &format!("--expire={}", expire)],
+        )?
+        .lines()
+        .count(),
+        None => 0,
+    };
+    let legacy_index = root.join(peer_identities::LEGACY_INDEX);
+    let legacy_identities =
+        PeerIdentities::legacy_peers(&legacy_index, &repo)?.map(|peers| (legacy_index, peers));
+    Ok(MaintenancePlan {
+        operation,
+        disk: repo_stats::disk(&repo)?,
+        pruned,
+        legacy_identities,
+    })
+}
+
+/// Run git on the repository at `git_dir`, returning what it printed
+fn git(git_dir: &Path, args: &[&str]) -> Result<String, Error> {
+    let output = std::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(git_dir)
+        .args(args)
+        .output()?;
+    if !output.status.success() {
+        return Err(Error::Git {
+            command: args.join(" "),
+            stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        });
+    }
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Repack => write!(f, "repack"),
+            Operation::Gc => write!(f, "gc"),
+            Operation::GcAggressive => write!(f, "gc-aggressive"),
+        }
+    }
+}
+
+impl std::fmt::Display for Measurement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "  {} loose objects, {} packed in {} packs, {} bytes on disk",
+            self.disk.loose_objects,
+            self.disk.packed_objects,
+            self.disk.packs,
+            self.disk.total_bytes
+        )?;
+        writeln!(
+            f,
+            "  retrieve mean {:.2}ms p50 {:.2}ms p95 {:.2}ms, list {:.2}ms",
+            self.metrics.retrieve_mean,
+            self.metrics.retrieve_p50,
+            self.metrics.retrieve_p95,
+            self.metrics.list
+        )
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "before:")?;
+        write!(f, "{}", self.before)?;
+        writeln!(f, "{} took {:?}", self.operation, self.took)?;
+        writeln!(f, "after:")?;
+        write!(f, "{}", self.after)?;
+        let change = |before: f64, after: f64| {
+            if before > 0.0 {
+                format!("{:+.1}%", (after - before) / before * 100.0)
+            } else {
+                "n/a".to_string()
+            }
+        };
+        writeln!(
+            f,
+            "size {}, retrieve p50 {}, list {}",
+            change(
+                self.before.disk.total_bytes as f64,
+                self.after.disk.total_bytes as f64
+            ),
+            change(
+                self.before.metrics.retrieve_p50,
+                self.after.metrics.retrieve_p50
+            ),
+            change(self.before.metrics.list, self.after.metrics.list)
+        )
+    }
+}
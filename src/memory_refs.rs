@@ -0,0 +1,372 @@
+//! Refs held in memory, so that benchmarks can tell the time spent reading refs from git apart
+//! from the time cob spends loading and evaluating change graphs. [`MemoryRefs`] keeps the tip
+//! of every peer's ref to every object in a map, which can be loaded from a monorepo's refs and
+//! snapshotted to and from a file, and [`MemoryRefsStorage`] is the [`RefsStorage`] of a peer
+//! over it.
+//!
+//! cob's `RefsStorage` hands out a `git2::Reference` for each ref, and libgit2 can only make
+//! those by reading a refdb, so the map can't answer cob on its own. Enumerating and looking up
+//! refs is done in the map, and the handles cob needs are then made from a scratch repository
+//! with a loose ref per entry of the map, kept under `/dev/shm` where there is one so that
+//! reading it doesn't touch the disk. The scratch repository borrows the objects of the
+//! monorepo as alternates, so the handles resolve to the monorepo's commits.
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Instant,
+};
+
+use cob::{ObjectId, ObjectRefs, RefsStorage, TypeName};
+use link_crypto::PeerId;
+use link_identities::git::Urn;
+use thiserror::Error;
+
+use crate::{
+    bench::Stats, cob_api, identity_pins::peer_of_ref, lite_monorepo::LiteMonorepo,
+    peer_refs_storage,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    PeerRefs(#[from] peer_refs_storage::Error),
+    #[error(transparent)]
+    Retrieve(#[from] cob_api::RetrieveError),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+    #[error("bad snapshot entry for {0}")]
+    BadEntry(String),
+}
+
+/// The refs of an object, keyed by the encoded URN, the type name and the object
+type Key = (String, String, ObjectId);
+
+pub struct MemoryRefs {
+    tips: RefCell<HashMap<Key, HashMap<PeerId, git2::Oid>>>,
+    scratch: git2::Repository,
+    scratch_dir: PathBuf,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct Snapshot {
+    refs: Vec<SnapshotEntry>,
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SnapshotEntry {
+    urn: String,
+    typename: String,
+    object: String,
+    peer: String,
+    tip: String,
+}
+
+impl MemoryRefs {
+    /// No refs, over the objects of `repo`
+    pub fn new(repo: &git2::Repository) -> Result<MemoryRefs, Error> {
+        static SCRATCH_COUNT: AtomicUsize = AtomicUsize::new(0);
+        let shm = Path::new("/dev/shm");
+        let parent = if shm.is_dir() {
+            shm.to_path_buf()
+        } else {
+            std::env::temp_dir()
+        };
+        let scratch_dir = parent.join(format!(
+            "collab-stress-test-refs-{}-{}",
+            std::process::id(),
+            SCRATCH_COUNT.fetch_add(1, Ordering::SeqCst)
+        ));
+        git2::Repository::init_bare(&scratch_dir)?;
+        let objects = crate::fs::canonicalize(repo.path().join("objects"))?;
+        crate::fs::write_atomic(
+            scratch_dir.join("objects/info/alternates"),
+            format!("{}\n", objects.display()),
+        )?;
+        // Reopened so that the alternates are picked up
+        let scratch = git2::Repository::open_bare(&scratch_dir)?;
+        Ok(MemoryRefs {
+            tips: RefCell::new(HashMap::new()),
+            scratch,
+            scratch_dir,
+        })
+    }
+
+    /// The refs to the issues of `monorepo`
+    pub fn load(monorepo: &LiteMonorepo) -> Result<MemoryRefs, Error> {
+        let refs = MemoryRefs::new(monorepo.repo())?;
+        let urn = monorepo.project_urn();
+        let typename = monorepo.typename().clone();
+        let peer = match monorepo.peer_ids().next() {
+            Some(peer) => *peer,
+            None => return Ok(refs),
+        };
+        let storage = monorepo.refs_storage(peer);
+        for (object_id, object_refs) in storage.type_references(&urn, &typename)? {
+            for reference in object_refs.local.iter().chain(&object_refs.remote) {
+                let peer = reference.name().and_then(peer_of_ref);
+                if let (Some(peer), Some(tip)) = (peer, reference.target()) {
+                    refs.set(&urn, &typename, &object_id, peer, tip)?;
+                }
+            }
+        }
+        Ok(refs)
+    }
+
+    /// The refs snapshotted to `path` by [`MemoryRefs::save`], over the objects of `repo`
+    pub fn open<P: AsRef<Path>>(path: P, repo: &git2::Repository) -> Result<MemoryRefs, Error> {
+        let refs = MemoryRefs::new(repo)?;
+        let snapshot: Snapshot = serde_json::from_slice(&crate::fs::read(path)?)?;
+        for entry in snapshot.refs {
+            let bad = || Error::BadEntry(entry.object.clone());
+            let urn = Urn::try_from_id(&entry.urn).map_err(|_| bad())?;
+            let typename = TypeName::from_str(&entry.typename).map_err(|_| bad())?;
+            let object_id = ObjectId::from_str(&entry.object).map_err(|_| bad())?;
+            let peer = PeerId::from_str(&entry.peer).map_err(|_| bad())?;
+            let tip = git2::Oid::from_str(&entry.tip)?;
+            refs.set(&urn, &typename, &object_id, peer, tip)?;
+        }
+        Ok(refs)
+    }
+
+    /// Snapshot the refs to `path`
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let mut refs = Vec::new();
+        for ((urn, typename, object_id), peers) in self.tips.borrow().iter() {
+            for (peer, tip) in peers {
+                refs.push(SnapshotEntry {
+                    urn: urn.clone(),
+                    typename: typename.clone(),
+                    object: object_id.to_string(),
+                    peer: peer.to_string(),
+                    tip: tip.to_string(),
+                });
+            }
+        }
+        let contents = serde_json::to_vec(&Snapshot { refs })?;
+        crate::fs::write_atomic(path, contents)?;
+        Ok(())
+    }
+
+    /// The number of refs
+    pub fn len(&self) -> usize {
+        self.tips.borrow().values().map(|peers| peers.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The refs storage of `peer`, which sees the refs of every peer
+    pub fn storage(&self, peer: PeerId) -> MemoryRefsStorage<'_> {
+        MemoryRefsStorage { peer, refs: self }
+    }
+
+    fn set(
+        &self,
+        urn: &Urn,
+        typename: &TypeName,
+        object_id: &ObjectId,
+        peer: PeerId,
+        tip: git2::Oid,
+    ) -> Result<(), git2::Error> {
+        let key = (urn.encode_id(), typename.to_string(), *object_id);
+        self.scratch
+            .reference(&scratch_ref(&key, &peer), tip, true, "memory refs")?;
+        self.tips
+            .borrow_mut()
+            .entry(key)
+            .or_default()
+            .insert(peer, tip);
+        Ok(())
+    }
+}
+
+impl Drop for MemoryRefs {
+    fn drop(&mut self) {
+        crate::fs::remove_dir_all(&self.scratch_dir).ok();
+    }
+}
+
+/// The name of the ref to `key` of `peer` in the scratch repository
+fn scratch_ref((urn, typename, object_id): &Key, peer: &PeerId) -> String {
+    format!(
+        "refs/namespaces/{}/refs/remotes/{}/cob/{}/{}",
+        urn, peer, typename, object_id
+    )
+}
+
+pub struct MemoryRefsStorage<'a> {
+    peer: PeerId,
+    refs: &'a MemoryRefs,
+}
+
+impl<'a> MemoryRefsStorage<'a> {
+    fn object_refs(
+        &self,
+        key: &Key,
+        peers: &HashMap<PeerId, git2::Oid>,
+    ) -> Result<ObjectRefs<'a>, peer_refs_storage::Error> {
+        let scratch = &self.refs.scratch;
+        let mut local = None;
+        let mut remote = Vec::new();
+        for peer in peers.keys() {
+            let reference = scratch.find_reference(&scratch_ref(key, peer))?;
+            if *peer == self.peer {
+                local = Some(reference);
+            } else {
+                remote.push(reference);
+            }
+        }
+        Ok(ObjectRefs { local, remote })
+    }
+}
+
+impl<'a> RefsStorage for MemoryRefsStorage<'a> {
+    type Error = peer_refs_storage::Error;
+
+    fn update_ref(
+        &self,
+        urn: &Urn,
+        typename: &TypeName,
+        object_id: ObjectId,
+        new_commit: git2::Oid,
+    ) -> Result<(), Self::Error> {
+        Ok(self
+            .refs
+            .set(urn, typename, &object_id, self.peer, new_commit)?)
+    }
+
+    fn type_references<'b>(
+        &'b self,
+        project_urn: &Urn,
+        typename: &TypeName,
+    ) -> Result<HashMap<ObjectId, ObjectRefs<'b>>, Self::Error> {
+        let urn = project_urn.encode_id();
+        let typename = typename.to_string();
+        let mut result = HashMap::new();
+        for (key, peers) in self.refs.tips.borrow().iter() {
+            if key.0 == urn && key.1 == typename {
+                result.insert(key.2, self.object_refs(key, peers)?);
+            }
+        }
+        Ok(result)
+    }
+
+    fn object_references<'b>(
+        &'b self,
+        project_urn: &Urn,
+        typename: &TypeName,
+        oid: &ObjectId,
+    ) -> Result<ObjectRefs<'b>, Self::Error> {
+        let key = (project_urn.encode_id(), typename.to_string(), *oid);
+        match self.refs.tips.borrow().get(&key) {
+            Some(peers) => self.object_refs(&key, peers),
+            None => Ok(ObjectRefs {
+                local: None,
+                remote: Vec::new(),
+            }),
+        }
+    }
+}
+
+pub struct Report {
+    pub refs: usize,
+    pub objects: usize,
+    /// Enumerating the refs of the issues from git
+    pub git_refs: Option<Stats>,
+    /// Enumerating them from memory
+    pub memory_refs: Option<Stats>,
+    /// Retrieving every issue with its refs read from git
+    pub git_retrieve: Option<Stats>,
+    /// Retrieving every issue with its refs read from memory
+    pub memory_retrieve: Option<Stats>,
+    /// Whether the two found different numbers of objects
+    pub mismatched: bool,
+}
+
+/// Enumerate the refs of the issues of `monorepo`, and retrieve every issue without the cache,
+/// `iterations` times each with refs from git and from `refs`
+pub fn bench(
+    monorepo: &LiteMonorepo,
+    refs: &MemoryRefs,
+    iterations: usize,
+) -> Result<Report, Error> {
+    let repo = monorepo.repo();
+    let urn = monorepo.project_urn();
+    let typename = monorepo.typename().clone();
+    let peer = match monorepo.peer_ids().next() {
+        Some(peer) => *peer,
+        None => return Err(git2::Error::from_str("the monorepo has no peers").into()),
+    };
+    let git = monorepo.refs_storage(peer);
+    let memory = refs.storage(peer);
+
+    let mut objects = (0, 0);
+    let mut git_refs = Vec::with_capacity(iterations);
+    let mut memory_refs = Vec::with_capacity(iterations);
+    let mut git_retrieve = Vec::with_capacity(iterations);
+    let mut memory_retrieve = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        git.type_references(&urn, &typename)?;
+        git_refs.push(start.elapsed());
+
+        let start = Instant::now();
+        memory.type_references(&urn, &typename)?;
+        memory_refs.push(start.elapsed());
+
+        let start = Instant::now();
+        objects.0 =
+            cob_api::retrieve_objects(&git, repo, monorepo.project(), &typename, None)?.len();
+        git_retrieve.push(start.elapsed());
+
+        let start = Instant::now();
+        objects.1 =
+            cob_api::retrieve_objects(&memory, repo, monorepo.project(), &typename, None)?.len();
+        memory_retrieve.push(start.elapsed());
+    }
+    Ok(Report {
+        refs: refs.len(),
+        objects: objects.0,
+        git_refs: Stats::from_samples(git_refs),
+        memory_refs: Stats::from_samples(memory_refs),
+        git_retrieve: Stats::from_samples(git_retrieve),
+        memory_retrieve: Stats::from_samples(memory_retrieve),
+        mismatched: objects.0 != objects.1,
+    })
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} refs, {} objects", self.refs, self.objects)?;
+        if let (Some(git_refs), Some(memory_refs), Some(git_retrieve), Some(memory_retrieve)) = (
+            &self.git_refs,
+            &self.memory_refs,
+            &self.git_retrieve,
+            &self.memory_retrieve,
+        ) {
+            writeln!(f, "refs from git:        {}", git_refs)?;
+            writeln!(f, "refs from memory:     {}", memory_refs)?;
+            writeln!(f, "retrieve from git:    {}", git_retrieve)?;
+            writeln!(f, "retrieve from memory: {}", memory_retrieve)?;
+            let share =
+                git_refs.p50.as_secs_f64() / git_retrieve.p50.as_secs_f64().max(f64::EPSILON);
+            writeln!(
+                f,
+                "reading refs from git is {:.0}% of retrieving at p50",
+                share * 100.0
+            )?;
+        }
+        if self.mismatched {
+            writeln!(f, "the two retrieved different numbers of objects")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,254 @@
+//! Loading issues from a GitHub migration archive rather than downloading them. Organisation
+//! admins can export repositories with the migrations API, which produces a gzipped tarball of
+//! JSON files without spending any of the API rate limit, so a large corpus can be built from an
+//! archive in minutes rather than over days of downloads.
+//!
+//! The archive holds each kind of record in numbered files of JSON arrays, `issues_000001.json`,
+//! `issue_comments_000001.json`, `issue_events_000001.json` and so on. Records refer to each
+//! other and to users by their URL on GitHub, e.g. an issue is
+//! `https://github.com/<owner>/<name>/issues/<number>` and its author `https://github.com/<login>`.
+//! The issues of `repo` are assembled from these and stored as if they had been downloaded, so
+//! everything which works with downloaded issues works with them too. Pull requests and the
+//! records of other repositories in the archive are skipped.
+use std::{collections::BTreeMap, io::Read, path::Path};
+
+use chrono::{DateTime, Utc};
+use flate2::read::GzDecoder;
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    download::{IssueStorage, StoreError},
+    downloaded_issue::{DownloadedComment, DownloadedEvent, DownloadedIssue, EventKind},
+    repo_name::RepoName,
+    GithubUserId,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error("{file}: {source}")]
+    Parse {
+        file: String,
+        source: serde_json::Error,
+    },
+    #[error(transparent)]
+    Store(#[from] StoreError),
+}
+
+#[derive(Debug, Deserialize)]
+struct Issue {
+    url: String,
+    repository: String,
+    #[serde(default)]
+    user: Option<String>,
+    title: String,
+    #[serde(default)]
+    body: Option<String>,
+    #[serde(default)]
+    closed_at: Option<DateTime<Utc>>,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Comment {
+    url: String,
+    /// The issue commented on, missing for comments on pull requests
+    #[serde(default)]
+    issue: Option<String>,
+    #[serde(default)]
+    user: Option<String>,
+    body: String,
+    created_at: DateTime<Utc>,
+    #[serde(default)]
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Event {
+    url: String,
+    #[serde(default)]
+    issue: Option<String>,
+    #[serde(default)]
+    actor: Option<String>,
+    event: String,
+    /// The URL of the label for label events
+    #[serde(default)]
+    label: Option<String>,
+    created_at: DateTime<Utc>,
+}
+
+/// What was found in an archive
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub issues: usize,
+    pub comments: usize,
+    pub events: usize,
+    /// Comments and events of issues which aren't in the archive, or of pull requests
+    pub skipped: usize,
+}
+
+/// Read the issues of `repo` from the archive at `path` and store them in `storage`, replacing
+/// any already stored with the same number
+pub fn load(path: &Path, repo: &RepoName, storage: &dyn IssueStorage) -> Result<Summary, Error> {
+    let mut issues = Vec::new();
+    let mut comments = Vec::new();
+    let mut events = Vec::new();
+    let mut archive = tar::Archive::new(GzDecoder::new(crate::fs::open(path)?));
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let name = match entry.path()?.file_name().and_then(|n| n.to_str()) {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        if !name.ends_with(".json") {
+            continue;
+        }
+        let mut contents = Vec::new();
+        if name.starts_with("issues_") {
+            entry.read_to_end(&mut contents)?;
+            issues.extend(parse::<Issue>(&name, &contents)?);
+        } else if name.starts_with("issue_comments_") {
+            entry.read_to_end(&mut contents)?;
+            comments.extend(parse::<Comment>(&name, &contents)?);
+        } else if name.starts_with("issue_events_") {
+            entry.read_to_end(&mut contents)?;
+            events.extend(parse::<Event>(&name, &contents)?);
+        }
+    }
+    verbose!(
+        "read {} issues, {} comments and {} events",
+        issues.len(),
+        comments.len(),
+        events.len()
+    );
+
+    let mut summary = Summary::default();
+    let repo_url = format!("/{}/{}", repo.owner, repo.name);
+    let mut by_url: BTreeMap<String, DownloadedIssue> = BTreeMap::new();
+    for issue in issues {
+        if !issue.repository.ends_with(&repo_url) {
+            continue;
+        }
+        let number = match issue_number(&issue.url) {
+            Some(n) => n,
+            None => continue,
+        };
+        let state = if issue.closed_at.is_some() {
+            "CLOSED"
+        } else {
+            "OPEN"
+        };
+        by_url.insert(
+            issue.url.clone(),
+            DownloadedIssue {
+                id: issue.url,
+                number,
+                state: state.to_string(),
+                title: issue.title,
+                body: issue.body,
+                author_id: issue.user.as_deref().map(user),
+                comments: Vec::new(),
+                created_at: issue.created_at,
+                updated_at: issue.updated_at,
+                events: Vec::new(),
+            },
+        );
+    }
+    for comment in comments {
+        match comment.issue.as_ref().and_then(|url| by_url.get_mut(url)) {
+            Some(issue) => {
+                issue.comments.push(DownloadedComment {
+                    id: comment.url,
+                    author_id: comment.user.as_deref().map(user),
+                    body: comment.body,
+                    created_at: comment.created_at,
+                    updated_at: comment.updated_at,
+                });
+                summary.comments += 1;
+            }
+            None => summary.skipped += 1,
+        }
+    }
+    for event in events {
+        let kind = match (event.event.as_str(), &event.label) {
+            ("labeled", Some(label)) => EventKind::Labeled {
+                label: last_segment(label).to_string(),
+            },
+            ("unlabeled", Some(label)) => EventKind::Unlabeled {
+                label: last_segment(label).to_string(),
+            },
+            ("closed", _) => EventKind::Closed,
+            ("reopened", _) => EventKind::Reopened,
+            // Not an event which is imported
+            _ => continue,
+        };
+        match event.issue.as_ref().and_then(|url| by_url.get_mut(url)) {
+            Some(issue) => {
+                issue.events.push(DownloadedEvent {
+                    id: event.url,
+                    actor_id: event.actor.as_deref().map(user),
+                    created_at: event.created_at,
+                    kind,
+                });
+                summary.events += 1;
+            }
+            None => summary.skipped += 1,
+        }
+    }
+
+    for issue in by_url.values_mut() {
+        // The archive makes no promises about order, but imports expect the oldest first
+        issue.comments.sort_by_key(|c| c.created_at);
+        issue.events.sort_by_key(|e| e.created_at);
+        storage.store(issue)?;
+        summary.issues += 1;
+    }
+    Ok(summary)
+}
+
+fn parse<T: serde::de::DeserializeOwned>(file: &str, contents: &[u8]) -> Result<Vec<T>, Error> {
+    serde_json::from_slice(contents).map_err(|source| Error::Parse {
+        file: file.to_string(),
+        source,
+    })
+}
+
+/// The number of the issue at `url`, `https://github.com/<owner>/<name>/issues/<number>`
+fn issue_number(url: &str) -> Option<u64> {
+    let (rest, number) = url.trim_end_matches('/').rsplit_once('/')?;
+    if !rest.ends_with("/issues") {
+        return None;
+    }
+    number.parse().ok()
+}
+
+/// The user at `url`, `https://github.com/<login>`
+fn user(url: &str) -> GithubUserId {
+    GithubUserId(last_segment(url).to_string())
+}
+
+fn last_segment(url: &str) -> &str {
+    url.trim_end_matches('/').rsplit('/').next().unwrap_or(url)
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "loaded {} issues with {} comments and {} events",
+            self.issues, self.comments, self.events
+        )?;
+        if self.skipped > 0 {
+            write!(
+                f,
+                ", skipped {} comments and events of pull requests or other repositories",
+                self.skipped
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,205 @@
+//! Shrinking the history of an object which fails some check down to a handful of changes, so
+//! that the failure can be handed to the cob developers as a test case.
+//!
+//! The history is first cut down to the shortest prefix which still fails, using a binary search
+//! which assumes that once a prefix fails every longer prefix fails too. Then each remaining
+//! change is dropped in turn, keeping it out if the failure persists without it. Changes which
+//! depend on a dropped change are never applied by automerge, so dropping a change effectively
+//! drops everything which built on it.
+use std::{
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{acl, lite_monorepo};
+
+#[derive(Debug, Error)]
+#[error("invalid check {0}, expected materialize, schema, acl, or slow:<milliseconds>")]
+pub struct ParseCheckError(String);
+
+/// The check which the object fails
+#[derive(Debug, Clone, Copy)]
+pub enum Check {
+    /// Materializing the document panics
+    Materialize,
+    /// The materialized document doesn't match the schema of the issue type
+    Schema,
+    /// The history contains changes which violate the ACL of the document
+    Acl,
+    /// Materializing the document takes longer than this
+    Slow(Duration),
+}
+
+impl FromStr for Check {
+    type Err = ParseCheckError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "materialize" => Ok(Check::Materialize),
+            "schema" => Ok(Check::Schema),
+            "acl" => Ok(Check::Acl),
+            _ => s
+                .strip_prefix("slow:")
+                .and_then(|ms| ms.parse().ok())
+                .map(|ms| Check::Slow(Duration::from_millis(ms)))
+                .ok_or_else(|| ParseCheckError(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Check {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Check::Materialize => write!(f, "materialize"),
+            Check::Schema => write!(f, "schema"),
+            Check::Acl => write!(f, "acl"),
+            Check::Slow(d) => write!(f, "slow:{}", d.as_millis()),
+        }
+    }
+}
+
+fn concat(changes: &[Vec<u8>]) -> Vec<u8> {
+    changes.iter().flatten().copied().collect()
+}
+
+fn materialize(history: Vec<u8>) -> Option<serde_json::Value> {
+    std::panic::catch_unwind(|| lite_monorepo::materialize(&cob::History::Automerge(history))).ok()
+}
+
+impl Check {
+    /// Whether the history made up of `changes` fails this check
+    pub fn fails(&self, changes: &[Vec<u8>]) -> bool {
+        let history = concat(changes);
+        match self {
+            Check::Materialize => materialize(history).is_none(),
+            Check::Schema => match materialize(history) {
+                Some(doc) => !lite_monorepo::compiled_schema().is_valid(&doc),
+                None => false,
+            },
+            // A history which can't be replayed fails the materialize check rather than this one
+            Check::Acl => acl::check(&history).map_or(false, |v| !v.is_empty()),
+            Check::Slow(threshold) => {
+                let start = Instant::now();
+                materialize(history);
+                start.elapsed() > *threshold
+            }
+        }
+    }
+}
+
+pub struct Minimized {
+    /// The number of changes in the original history
+    pub original: usize,
+    /// The length of the shortest failing prefix
+    pub prefix: usize,
+    /// The raw bytes of each change which is needed to reproduce the failure
+    pub changes: Vec<Vec<u8>>,
+}
+
+/// Minimize `history` with respect to `check`. Returns `None` if `history` doesn't fail `check`.
+pub fn minimize(history: &[u8], check: Check) -> Option<Minimized> {
+    let changes: Vec<Vec<u8>> = automerge::Change::load_document(history)
+        .unwrap()
+        .iter()
+        .map(|c| c.raw_bytes().to_vec())
+        .collect();
+    if !check.fails(&changes) {
+        return None;
+    }
+
+    let (mut lo, mut hi) = (1, changes.len());
+    while lo < hi {
+        let mid = (lo + hi) / 2;
+        if check.fails(&changes[..mid]) {
+            hi = mid;
+        } else {
+            lo = mid + 1;
+        }
+    }
+    let prefix = hi;
+
+    let mut current: Vec<Vec<u8>> = changes[..prefix].to_vec();
+    for i in (0..current.len()).rev() {
+        let mut candidate = current.clone();
+        candidate.remove(i);
+        if !candidate.is_empty() && check.fails(&candidate) {
+            current = candidate;
+        }
+    }
+    Some(Minimized {
+        original: changes.len(),
+        prefix,
+        changes: current,
+    })
+}
+
+/// Write `minimized` into `dir` as a test case: the history, the schema of the issue type, and a
+/// test which loads the history and asserts that it fails the check. The ACL check is this
+/// crate's, so an ACL test case needs it as a dependency. Returns the path of the test.
+pub fn export(
+    dir: &Path,
+    object_id: &cob::ObjectId,
+    check: Check,
+    minimized: &Minimized,
+) -> Result<PathBuf, std::io::Error> {
+    crate::fs::create_dir_all(dir)?;
+    crate::fs::write_atomic(dir.join("history.automerge"), concat(&minimized.changes))?;
+    crate::fs::write_atomic(
+        dir.join("schema.json"),
+        serde_json::to_vec_pretty(lite_monorepo::schema()).unwrap(),
+    )?;
+    let test_path = dir.join("repro_test.rs");
+    crate::fs::write_atomic(&test_path, test_source(object_id, check, minimized))?;
+    Ok(test_path)
+}
+
+fn test_source(object_id: &cob::ObjectId, check: Check, minimized: &Minimized) -> String {
+    let assertion = match check {
+        Check::Materialize => String::new(),
+        Check::Schema => concat!(
+            "    let schema: serde_json::Value =\n",
+            "        serde_json::from_slice(include_bytes!(\"schema.json\")).unwrap();\n",
+            "    let schema = jsonschema::JSONSchema::compile(&schema).unwrap();\n",
+            "    assert!(!schema.is_valid(&doc), \"the document matches the schema\");\n",
+        )
+        .to_string(),
+        Check::Acl => concat!(
+            "    let violations = collab_stress_test::acl::check(history).unwrap();\n",
+            "    assert!(!violations.is_empty(), \"no change violates the ACL\");\n",
+        )
+        .to_string(),
+        Check::Slow(threshold) => format!(
+            "    assert!(start.elapsed() > std::time::Duration::from_millis({}));\n",
+            threshold.as_millis()
+        ),
+    };
+    let attributes = match check {
+        Check::Materialize => "#[test]\n#[should_panic]\n",
+        _ => "#[test]\n",
+    };
+    format!(
+        r#"//! Minimized from object {object_id}, whose history of {original} changes fails the
+//! `{check}` check. {kept} changes reproduce the failure, and the test passes for as long as they
+//! do.
+{attributes}#[allow(unused_variables)]
+fn repro_{short}() {{
+    let history: &[u8] = include_bytes!("history.automerge");
+    let start = std::time::Instant::now();
+    let backend = automerge::Backend::load(history.to_vec()).unwrap();
+    let mut frontend = automerge::Frontend::new();
+    frontend.apply_patch(backend.get_patch().unwrap()).unwrap();
+    let doc = frontend.state().to_json();
+{assertion}}}
+"#,
+        object_id = object_id,
+        original = minimized.original,
+        check = check,
+        kept = minimized.changes.len(),
+        attributes = attributes,
+        short = &object_id.to_string()[..8],
+        assertion = assertion,
+    )
+}
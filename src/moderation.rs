@@ -0,0 +1,309 @@
+//! Moderation of the comments of an issue, and what automerge makes of two moderators acting at
+//! once. A moderator can
+//!
+//! * hide a comment, setting `hidden` on it
+//! * edit a comment, replacing its body
+//! * move a comment to the top, which the list CRDT can only do by deleting the comment and
+//!   inserting a copy of it at the start of the list
+//! * or a user can add a new comment while the moderator is at work
+//!
+//! Each pair of these is made concurrently, on top of the same history, to the last comment of
+//! some downloaded issues, and the merged document is checked: every comment should be there once,
+//! a hidden comment should stay hidden, an edited comment should keep one of the edits and a moved
+//! comment should be at the top. Moving is where the list CRDT gives surprising results, as the
+//! copy is a new comment which the concurrent change knows nothing about: two concurrent moves
+//! leave two copies, and a concurrent hide or edit is applied to the deleted original and lost.
+//!
+//! Only issues in the default layout, with comments as maps in a `comments` list, can be moderated,
+//! as moving a comment means moving the map. Comments are found and read using the paths and
+//! readers of `crate::layout`.
+use link_identities::git::Urn;
+
+use crate::{
+    downloaded_issue::{DownloadedComment, DownloadedIssue},
+    layout::{self, Layout},
+    lite_monorepo::{add_comment_change, concat, init_issue_change, materialize, Clock},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Hide,
+    Edit,
+    MoveToTop,
+    NewComment,
+}
+
+impl Operation {
+    pub fn all() -> [Operation; 4] {
+        [
+            Operation::Hide,
+            Operation::Edit,
+            Operation::MoveToTop,
+            Operation::NewComment,
+        ]
+    }
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Operation::Hide => write!(f, "hide"),
+            Operation::Edit => write!(f, "edit"),
+            Operation::MoveToTop => write!(f, "move-to-top"),
+            Operation::NewComment => write!(f, "new-comment"),
+        }
+    }
+}
+
+/// How the merges of one pair of concurrent operations turned out
+#[derive(Debug)]
+pub struct PairReport {
+    pub first: Operation,
+    pub second: Operation,
+    pub merges: usize,
+    pub intact: usize,
+    /// A comment appears more than once
+    pub duplicated: usize,
+    /// A comment is missing
+    pub lost: usize,
+    pub hide_lost: usize,
+    pub edit_lost: usize,
+    /// The moved comment isn't at the top
+    pub not_moved: usize,
+    /// The first surprising merge, as the issue number and what went wrong
+    pub example: Option<(u64, String)>,
+}
+
+impl PairReport {
+    fn new(first: Operation, second: Operation) -> PairReport {
+        PairReport {
+            first,
+            second,
+            merges: 0,
+            intact: 0,
+            duplicated: 0,
+            lost: 0,
+            hide_lost: 0,
+            edit_lost: 0,
+            not_moved: 0,
+            example: None,
+        }
+    }
+}
+
+/// Apply every pair of operations concurrently to the last comment of each of `issues` with at
+/// least two comments
+pub fn check(issues: &[DownloadedIssue], author_urn: &Urn) -> Vec<PairReport> {
+    let mut reports = Vec::new();
+    let ops = Operation::all();
+    for (i, first) in ops.iter().enumerate() {
+        for second in &ops[i..] {
+            reports.push(PairReport::new(*first, *second));
+        }
+    }
+    for issue in issues.iter().filter(|i| i.comments.len() >= 2) {
+        let mut history = init_issue_change(
+            issue,
+            author_urn,
+            false,
+            Layout::default(),
+            Clock::default(),
+            None,
+        );
+        for comment in &issue.comments {
+            let change = add_comment_change(comment, author_urn, &history, Clock::default(), None);
+            history = concat(&[&history, &change]);
+        }
+        let target = issue.comments.len() - 1;
+        let target_id = issue.comments[target].id.as_str();
+        for report in &mut reports {
+            let a = operation_change(report.first, "a", issue, target, &history, author_urn);
+            let b = operation_change(report.second, "b", issue, target, &history, author_urn);
+            let merged = materialize(&concat(&[&history, &a, &b]));
+            let mut expected: Vec<String> = issue.comments.iter().map(|c| c.id.clone()).collect();
+            for (op, which) in [(report.first, "a"), (report.second, "b")].iter() {
+                if *op == Operation::NewComment {
+                    expected.push(new_comment(issue, which).id);
+                }
+            }
+            let problems = problems(
+                &merged,
+                &[report.first, report.second],
+                &expected,
+                target_id,
+            );
+            report.merges += 1;
+            for problem in &problems {
+                match problem {
+                    Problem::Duplicated => report.duplicated += 1,
+                    Problem::Lost => report.lost += 1,
+                    Problem::HideLost => report.hide_lost += 1,
+                    Problem::EditLost => report.edit_lost += 1,
+                    Problem::NotMoved => report.not_moved += 1,
+                }
+            }
+            if problems.is_empty() {
+                report.intact += 1;
+            } else if report.example.is_none() {
+                let described: Vec<String> = problems.iter().map(|p| p.to_string()).collect();
+                report.example = Some((issue.number, described.join(", ")));
+            }
+        }
+    }
+    reports
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Problem {
+    Duplicated,
+    Lost,
+    HideLost,
+    EditLost,
+    NotMoved,
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::Duplicated => write!(f, "a comment was duplicated"),
+            Problem::Lost => write!(f, "a comment was lost"),
+            Problem::HideLost => write!(f, "the hidden comment is visible"),
+            Problem::EditLost => write!(f, "the edit was lost"),
+            Problem::NotMoved => write!(f, "the moved comment isn't at the top"),
+        }
+    }
+}
+
+fn problems(
+    merged: &serde_json::Value,
+    ops: &[Operation],
+    expected: &[String],
+    target_id: &str,
+) -> Vec<Problem> {
+    let comments = layout::comments(merged);
+    let count = |id: &str| comments.iter().filter(|c| github_id(c) == Some(id)).count();
+    let targets: Vec<&layout::Comment> = comments
+        .iter()
+        .filter(|c| github_id(c) == Some(target_id))
+        .collect();
+
+    let mut problems = Vec::new();
+    if expected.iter().any(|id| count(id) > 1) {
+        problems.push(Problem::Duplicated);
+    }
+    if expected.iter().any(|id| count(id) == 0) {
+        problems.push(Problem::Lost);
+    }
+    if ops.contains(&Operation::Hide) && targets.iter().any(|c| !c.hidden) {
+        problems.push(Problem::HideLost);
+    }
+    if ops.contains(&Operation::Edit)
+        && targets.iter().any(|c| {
+            !["a", "b"]
+                .iter()
+                .any(|which| c.body.as_deref() == Some(edited(which).as_str()))
+        })
+    {
+        problems.push(Problem::EditLost);
+    }
+    if ops.contains(&Operation::MoveToTop)
+        && comments.first().and_then(github_id) != Some(target_id)
+    {
+        problems.push(Problem::NotMoved);
+    }
+    problems
+}
+
+fn github_id(comment: &layout::Comment) -> Option<&str> {
+    comment.github_id.as_deref()
+}
+
+/// The change made by a moderator `which` applying `op` to the comment at `index`
+fn operation_change(
+    op: Operation,
+    which: &str,
+    issue: &DownloadedIssue,
+    index: usize,
+    history: &cob::History,
+    moderator_urn: &Urn,
+) -> cob::History {
+    if op == Operation::NewComment {
+        return add_comment_change(
+            &new_comment(issue, which),
+            moderator_urn,
+            history,
+            Clock::default(),
+            None,
+        );
+    }
+    let mut backend = automerge::Backend::new();
+    let mut frontend = automerge::Frontend::new();
+    let cob::History::Automerge(hist) = history;
+    let changes = automerge::Change::load_document(hist).unwrap();
+    frontend
+        .apply_patch(backend.apply_changes(changes).unwrap())
+        .unwrap();
+    let layout = Layout::default();
+    let github_id = issue.comments[index].id.as_str();
+    let index = index as u32;
+    let (_, change) = frontend
+        .change::<_, _, automerge::InvalidChangeRequest>(None, |d| {
+            match op {
+                Operation::Hide => d.add_change(automerge::LocalChange::set(
+                    layout::field_path(layout, index, github_id, "hidden"),
+                    automerge::Value::Primitive(automerge::Primitive::Boolean(true)),
+                ))?,
+                Operation::Edit => d.add_change(automerge::LocalChange::set(
+                    layout::field_path(layout, index, github_id, "comment"),
+                    layout::body(layout, &edited(which)),
+                ))?,
+                Operation::MoveToTop => {
+                    let comment = layout::comment_path(layout, index, github_id);
+                    let value = d
+                        .value_at_path(&comment)
+                        .expect("the comment to move exists");
+                    d.add_change(automerge::LocalChange::delete(comment))?;
+                    d.add_change(automerge::LocalChange::insert(
+                        layout::comment_path(layout, 0, github_id),
+                        value,
+                    ))?;
+                }
+                Operation::NewComment => unreachable!(),
+            }
+            Ok(())
+        })
+        .unwrap();
+    let (_, change) = backend.apply_local_change(change.unwrap()).unwrap();
+    cob::History::Automerge(change.raw_bytes().to_vec())
+}
+
+fn edited(which: &str) -> String {
+    format!("edited by moderator {}", which)
+}
+
+fn new_comment(issue: &DownloadedIssue, which: &str) -> DownloadedComment {
+    DownloadedComment {
+        id: format!("moderation-{}-{}", issue.number, which),
+        author_id: None,
+        body: format!("a comment made while moderator {} was at work", which),
+        created_at: chrono::Utc::now(),
+        updated_at: None,
+    }
+}
+
+impl std::fmt::Display for PairReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<27} {:>6} {:>6} {:>10} {:>5} {:>9} {:>9} {:>9}",
+            format!("{} + {}", self.first, self.second),
+            self.merges,
+            self.intact,
+            self.duplicated,
+            self.lost,
+            self.hide_lost,
+            self.edit_lost,
+            self.not_moved
+        )
+    }
+}
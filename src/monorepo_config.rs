@@ -0,0 +1,223 @@
+//! Settings of a monorepo which are chosen when it's created, kept in `config.json` in its root.
+//! Monorepos created before the file existed have no config and get the defaults, which match
+//! what they were created with.
+use std::{path::Path, str::FromStr};
+
+use thiserror::Error;
+
+use crate::{
+    lite_monorepo::{self, TYPENAME_STR},
+    peer_refs_storage::RefLayout,
+};
+
+pub const CONFIG: &str = "config.json";
+
+/// The schema objects are created with, when it isn't the schema of the issue type
+pub const SCHEMA: &str = "schema.json";
+
+/// The number of peers a monorepo was created with before the count was configurable
+pub const DEFAULT_PEERS: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("the monorepo has {current} peers, which can't be reduced to {requested}")]
+    ShrinkPeers { current: usize, requested: usize },
+    #[error("the monorepo's refs are laid out {current}, which can't be changed to {requested}")]
+    ChangeRefLayout {
+        current: RefLayout,
+        requested: RefLayout,
+    },
+    #[error("the monorepo's keys were generated {current}, which can't be changed to {requested}")]
+    ChangeSeed { current: String, requested: String },
+    #[error("the monorepo's users have {current} devices, which can't be changed to {requested}")]
+    ChangeDevices { current: usize, requested: usize },
+    #[error("the monorepo's objects are of type {current}, which can't be changed to {requested}")]
+    ChangeTypename { current: String, requested: String },
+    #[error("the monorepo's objects already have a schema, which can't be changed")]
+    ChangeSchema,
+    #[error("invalid type name {0}")]
+    InvalidTypename(String),
+    #[error("invalid schema: {0}")]
+    InvalidSchema(String),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Config {
+    /// The number of peers, each with their own key and identity, which issues and comments are
+    /// spread across
+    pub peers: usize,
+    /// How the refs of collaborative objects are laid out, see [`crate::peer_refs_storage`]
+    #[serde(default)]
+    pub ref_layout: RefLayout,
+    /// The seed the keys of the peers are derived from, or none if they are random, see
+    /// [`crate::peers`]
+    #[serde(default)]
+    pub seed: Option<u64>,
+    /// The number of peers, each a device with its own key, of each user, see
+    /// [`crate::devices`]
+    #[serde(default = "one_device")]
+    pub devices: usize,
+    /// The type name objects are created as, or none for the issue type. Objects are always
+    /// imported GitHub issues, so their schema must accept them.
+    #[serde(default)]
+    pub typename: Option<String>,
+}
+
+fn one_device() -> usize {
+    1
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            peers: DEFAULT_PEERS,
+            ref_layout: RefLayout::default(),
+            seed: None,
+            devices: one_device(),
+            typename: None,
+        }
+    }
+}
+
+impl Config {
+    /// Load the config of the monorepo at `root`, or the defaults if it has none
+    pub fn load<P: AsRef<Path>>(root: P) -> Result<Config, Error> {
+        let path = root.as_ref().join(CONFIG);
+        if crate::fs::exists(&path)? {
+            Ok(serde_json::from_slice(&crate::fs::read(&path)?)?)
+        } else {
+            Ok(Config::default())
+        }
+    }
+
+    /// The type name objects are created as
+    pub fn typename(&self) -> Result<cob::TypeName, Error> {
+        let typename = self.typename.as_deref().unwrap_or(TYPENAME_STR);
+        cob::TypeName::from_str(typename).map_err(|_| Error::InvalidTypename(typename.to_string()))
+    }
+
+    /// The schema objects of the monorepo at `root` are created with, which is kept in
+    /// `schema.json` in its root if it isn't the schema of the issue type
+    pub fn schema<P: AsRef<Path>>(&self, root: P) -> Result<serde_json::Value, Error> {
+        let path = root.as_ref().join(SCHEMA);
+        if crate::fs::exists(&path)? {
+            Ok(serde_json::from_slice(&crate::fs::read(&path)?)?)
+        } else {
+            Ok(lite_monorepo::schema().clone())
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, root: P) -> Result<(), Error> {
+        crate::fs::create_dir_all(&root)?;
+        crate::fs::write_atomic(root.as_ref().join(CONFIG), serde_json::to_vec_pretty(self)?)?;
+        Ok(())
+    }
+
+    /// Record that the monorepo at `root` should have `peers` peers, which are created the next
+    /// time it is opened. Peers can be added to an existing monorepo but not removed, as the
+    /// objects they wrote would lose their authors.
+    pub fn set_peers<P: AsRef<Path>>(root: P, peers: usize) -> Result<Config, Error> {
+        let mut config = Config::load(&root)?;
+        if peers < config.peers {
+            return Err(Error::ShrinkPeers {
+                current: config.peers,
+                requested: peers,
+            });
+        }
+        config.peers = peers;
+        config.save(&root)?;
+        Ok(config)
+    }
+
+    /// Record that the monorepo at `root` should lay out its refs as `layout`. This can only be
+    /// chosen before the monorepo is created, as the refs it already has would no longer be found.
+    pub fn set_ref_layout<P: AsRef<Path>>(root: P, layout: RefLayout) -> Result<Config, Error> {
+        let mut config = Config::load(&root)?;
+        if config.ref_layout != layout && crate::fs::exists(root.as_ref().join("git"))? {
+            return Err(Error::ChangeRefLayout {
+                current: config.ref_layout,
+                requested: layout,
+            });
+        }
+        config.ref_layout = layout;
+        config.save(&root)?;
+        Ok(config)
+    }
+
+    /// Record that the keys of the monorepo at `root` should be derived from `seed`. This can
+    /// only be done before its peers are created.
+    pub fn set_seed<P: AsRef<Path>>(root: P, seed: u64) -> Result<Config, Error> {
+        let mut config = Config::load(&root)?;
+        if config.seed != Some(seed) && crate::fs::exists(root.as_ref().join("peers"))? {
+            let described = |seed: Option<u64>| match seed {
+                Some(seed) => format!("from seed {}", seed),
+                None => "at random".to_string(),
+            };
+            return Err(Error::ChangeSeed {
+                current: described(config.seed),
+                requested: described(Some(seed)),
+            });
+        }
+        config.seed = Some(seed);
+        config.save(&root)?;
+        Ok(config)
+    }
+
+    /// Record that the users of the monorepo at `root` should have `devices` devices each. This
+    /// can only be done before its peers are created, as the peers which are already users of
+    /// their own can't become devices of another.
+    pub fn set_devices<P: AsRef<Path>>(root: P, devices: usize) -> Result<Config, Error> {
+        let mut config = Config::load(&root)?;
+        if config.devices != devices && crate::fs::exists(root.as_ref().join("peers"))? {
+            return Err(Error::ChangeDevices {
+                current: config.devices,
+                requested: devices,
+            });
+        }
+        config.devices = devices;
+        config.save(&root)?;
+        Ok(config)
+    }
+
+    /// Record that objects in the monorepo at `root` should be created as `typename`. This can
+    /// only be chosen before the monorepo is created, as the objects it already has would no
+    /// longer be found.
+    pub fn set_typename<P: AsRef<Path>>(root: P, typename: &str) -> Result<Config, Error> {
+        cob::TypeName::from_str(typename)
+            .map_err(|_| Error::InvalidTypename(typename.to_string()))?;
+        let mut config = Config::load(&root)?;
+        let current = config.typename.as_deref().unwrap_or(TYPENAME_STR);
+        if current != typename && crate::fs::exists(root.as_ref().join("git"))? {
+            return Err(Error::ChangeTypename {
+                current: current.to_string(),
+                requested: typename.to_string(),
+            });
+        }
+        config.typename = Some(typename.to_string());
+        config.save(&root)?;
+        Ok(config)
+    }
+
+    /// Record that objects in the monorepo at `root` should be created with the JSON schema in
+    /// the file at `schema`, copying it into the root. This can only be chosen before the
+    /// monorepo is created, so that every object has the same schema.
+    pub fn set_schema<P: AsRef<Path>>(root: P, schema: &Path) -> Result<Config, Error> {
+        let schema: serde_json::Value = serde_json::from_slice(&crate::fs::read(schema)?)?;
+        jsonschema::JSONSchema::compile(&schema)
+            .map_err(|e| Error::InvalidSchema(e.to_string()))?;
+        let config = Config::load(&root)?;
+        if config.schema(&root)? != schema && crate::fs::exists(root.as_ref().join("git"))? {
+            return Err(Error::ChangeSchema);
+        }
+        crate::fs::create_dir_all(&root)?;
+        crate::fs::write_atomic(
+            root.as_ref().join(SCHEMA),
+            serde_json::to_vec_pretty(&schema)?,
+        )?;
+        Ok(config)
+    }
+}
@@ -0,0 +1,176 @@
+//! How the monorepo copes with very many objects. Tiny synthetic issues, each with a single
+//! comment, are imported into a monorepo of their own, and at each checkpoint we record the
+//! import throughput since the last checkpoint, how long enumerating the refs of every object
+//! with `type_references` takes, and the size of the monorepo. `type_references` reads only the
+//! refs under the object type's prefix (see `crate::peer_refs_storage::type_glob`), but that is
+//! still every ref of every issue, and cob calls it before retrieving all of them, so it grows
+//! with the number of objects rather than with the size of the rest of the repository.
+//!
+//! Each checkpoint is appended to `object_scaling.jsonl` next to the monorepo as soon as it is
+//! reached, as a run to a million objects takes many hours. Running the scenario again continues
+//! from the number of objects already in the monorepo.
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::{
+    downloaded_issue::{DownloadedComment, DownloadedIssue},
+    lite_monorepo::{error, LiteMonorepo},
+    peer_refs_storage, signed_refs, GithubUserId,
+};
+
+/// The name of the file in the scenario directory which checkpoints are recorded in
+pub const CHECKPOINTS: &str = "object_scaling.jsonl";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Open(#[from] error::CreateOrOpen),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error(transparent)]
+    PeerRefs(#[from] peer_refs_storage::Error),
+    #[error(transparent)]
+    SignRefs(#[from] signed_refs::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+pub struct Options {
+    /// The number of objects at which to take measurements, in ascending order. The scenario stops
+    /// after the last.
+    pub checkpoints: Vec<usize>,
+    /// The number of distinct github users the issues and comments are spread across
+    pub users: usize,
+    /// Stop early once fewer than this many objects a second were imported since the last
+    /// checkpoint
+    pub stop_below: Option<f64>,
+}
+
+/// The measurements taken at one checkpoint
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Checkpoint {
+    pub at: DateTime<Utc>,
+    pub objects: usize,
+    /// Objects imported per second since the previous checkpoint
+    pub objects_per_sec: f64,
+    /// Changes created per second since the previous checkpoint
+    pub changes_per_sec: f64,
+    pub type_references: Duration,
+    /// The number of objects `type_references` found
+    pub objects_found: usize,
+    pub bytes: u64,
+    pub refs: usize,
+}
+
+/// The issue synthesized for the `number`th object
+fn synthetic_issue(number: u64, users: usize) -> DownloadedIssue {
+    let user = |offset: u64| {
+        Some(GithubUserId(format!(
+            "scaling-user-{}",
+            (number + offset) % users.max(1) as u64
+        )))
+    };
+    let created_at = Utc::now();
+    DownloadedIssue {
+        id: format!("scaling-issue-{}", number),
+        number,
+        state: "OPEN".to_string(),
+        title: format!("issue {}", number),
+        body: None,
+        author_id: user(0),
+        comments: vec![DownloadedComment {
+            id: format!("scaling-comment-{}", number),
+            author_id: user(1),
+            body: format!("the only comment of issue {}", number),
+            created_at,
+            updated_at: None,
+        }],
+        created_at,
+        updated_at: None,
+        events: Vec::new(),
+    }
+}
+
+pub fn record<P: AsRef<Path>>(path: P, checkpoint: &Checkpoint) -> Result<(), Error> {
+    let mut line = serde_json::to_vec(checkpoint)?;
+    line.push(b'\n');
+    crate::fs::append_line(path, &line)?;
+    Ok(())
+}
+
+/// Import synthetic issues into the monorepo in `dir` until every checkpoint has been reached,
+/// calling `on_checkpoint` with each set of measurements
+pub fn run<F>(dir: &Path, options: &Options, mut on_checkpoint: F) -> Result<(), Error>
+where
+    F: FnMut(&Checkpoint),
+{
+    let mut monorepo = LiteMonorepo::create_or_open(monorepo_dir(dir))?;
+    monorepo.set_resume_imports(true);
+    let mut objects = monorepo.indexed_issue_count();
+    for &target in options.checkpoints.iter().filter(|c| **c > objects) {
+        let start = Instant::now();
+        let changes_before = monorepo.changes_created();
+        let objects_before = objects;
+        while objects < target {
+            objects += 1;
+            monorepo.import_issue(&synthetic_issue(objects as u64, options.users))?;
+        }
+        let elapsed = start.elapsed().as_secs_f64().max(f64::EPSILON);
+        let (type_references, objects_found) = monorepo.time_type_references()?;
+        let checkpoint = Checkpoint {
+            at: Utc::now(),
+            objects,
+            objects_per_sec: (objects - objects_before) as f64 / elapsed,
+            changes_per_sec: (monorepo.changes_created() - changes_before) as f64 / elapsed,
+            type_references,
+            objects_found,
+            bytes: monorepo.git_size()?,
+            refs: monorepo
+                .ref_count()
+                .map_err(peer_refs_storage::Error::from)?,
+        };
+        record(dir.join(CHECKPOINTS), &checkpoint)?;
+        on_checkpoint(&checkpoint);
+        if let Some(min) = options.stop_below {
+            if checkpoint.objects_per_sec < min {
+                status!(
+                    "stopping at {} objects, {:.1} objects a second is below {}",
+                    objects,
+                    checkpoint.objects_per_sec,
+                    min
+                );
+                break;
+            }
+        }
+    }
+    monorepo.sign_refs()?;
+    Ok(())
+}
+
+fn monorepo_dir(dir: &Path) -> PathBuf {
+    dir.join("monorepo")
+}
+
+impl std::fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>9} objects: {:>8.1} objects/s {:>8.1} changes/s, type_references {:?} ({} found), \
+             {} bytes, {} refs",
+            self.objects,
+            self.objects_per_sec,
+            self.changes_per_sec,
+            self.type_references,
+            self.objects_found,
+            self.bytes,
+            self.refs
+        )
+    }
+}
@@ -0,0 +1,457 @@
+//! An `IssueStorage` backed by an S3 compatible object store (AWS S3, MinIO etc.). This lets very
+//! large downloaded corpora live off the benchmark machine, issues are fetched one at a time as
+//! they are imported.
+//!
+//! Objects are laid out as
+//!
+//! ```
+//! <bucket>/<prefix>/<owner>/<name>/issues/<number>.json
+//! <bucket>/<prefix>/<owner>/<name>/checksums/<number>
+//! <bucket>/<prefix>/<owner>/<name>/last_cursor
+//! <bucket>/<prefix>/<owner>/<name>/last_sync
+//! ```
+//!
+//! Credentials and the endpoint are read from the environment: `AWS_ACCESS_KEY_ID`,
+//! `AWS_SECRET_ACCESS_KEY`, `AWS_REGION` (default `us-east-1`) and `S3_ENDPOINT` (default
+//! `https://s3.<region>.amazonaws.com`, set this to point at MinIO). Requests use path style
+//! addressing and are signed with AWS signature version 4.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac, NewMac};
+use lazy_static::lazy_static;
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use super::download::{parse_sync_time, IssueStorage, LoadError, StoreError};
+use super::RepoName;
+
+lazy_static! {
+    static ref KEY_REGEX: regex::Regex = regex::Regex::new("<Key>([^<]*)</Key>").unwrap();
+    static ref NEXT_TOKEN_REGEX: regex::Regex =
+        regex::Regex::new("<NextContinuationToken>([^<]*)</NextContinuationToken>").unwrap();
+}
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error("invalid object store endpoint {0}")]
+    BadEndpoint(String),
+    #[error("missing environment variable {0}")]
+    MissingEnv(&'static str),
+    #[error("object store URL must look like s3://<bucket>/<prefix>")]
+    BadLocation,
+    #[error("object store returned {status} for {key}: {body}")]
+    Status {
+        key: String,
+        status: reqwest::StatusCode,
+        body: String,
+    },
+}
+
+/// Where in an object store to put things, parsed from `s3://<bucket>/<prefix>`
+#[derive(Clone, Debug)]
+pub struct Location {
+    bucket: String,
+    prefix: String,
+}
+
+impl FromStr for Location {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let rest = s.strip_prefix("s3://").ok_or(Error::BadLocation)?;
+        let mut parts = rest.splitn(2, '/');
+        let bucket = parts
+            .next()
+            .filter(|b| !b.is_empty())
+            .ok_or(Error::BadLocation)?;
+        let prefix = parts.next().unwrap_or("").trim_matches('/');
+        Ok(Location {
+            bucket: bucket.to_string(),
+            prefix: prefix.to_string(),
+        })
+    }
+}
+
+struct Credentials {
+    access_key: String,
+    secret_key: String,
+    region: String,
+    endpoint: reqwest::Url,
+}
+
+impl Credentials {
+    fn from_env() -> Result<Credentials, Error> {
+        let access_key = std::env::var("AWS_ACCESS_KEY_ID")
+            .map_err(|_| Error::MissingEnv("AWS_ACCESS_KEY_ID"))?;
+        let secret_key = std::env::var("AWS_SECRET_ACCESS_KEY")
+            .map_err(|_| Error::MissingEnv("AWS_SECRET_ACCESS_KEY"))?;
+        let region = std::env::var("AWS_REGION").unwrap_or_else(|_| "us-east-1".to_string());
+        let endpoint = std::env::var("S3_ENDPOINT")
+            .unwrap_or_else(|_| format!("https://s3.{}.amazonaws.com", region));
+        Ok(Credentials {
+            access_key,
+            secret_key,
+            region,
+            endpoint: reqwest::Url::parse(&endpoint).map_err(|_| Error::BadEndpoint(endpoint))?,
+        })
+    }
+}
+
+pub struct ObjectStoreStorage {
+    client: reqwest::Client,
+    credentials: Credentials,
+    bucket: String,
+    /// The prefix of every key belonging to this repository, ends with a `/`
+    root: String,
+}
+
+impl ObjectStoreStorage {
+    pub fn new(location: &Location, repo: &RepoName) -> Result<ObjectStoreStorage, Error> {
+        let mut root = String::new();
+        if !location.prefix.is_empty() {
+            root.push_str(&location.prefix);
+            root.push('/');
+        }
+        root.push_str(&format!("{}/{}/", repo.owner, repo.name));
+        Ok(ObjectStoreStorage {
+            client: reqwest::Client::new(),
+            credentials: Credentials::from_env()?,
+            bucket: location.bucket.clone(),
+            root,
+        })
+    }
+
+    fn issue_key(&self, number: u64) -> String {
+        format!("{}issues/{}.json", self.root, number)
+    }
+
+    fn get(&self, key: &str) -> Result<Option<Vec<u8>>, Error> {
+        let response = self.request(reqwest::Method::GET, key, Vec::new(), Vec::new())?;
+        match response {
+            (status, _) if status == reqwest::StatusCode::NOT_FOUND => Ok(None),
+            (status, body) if status.is_success() => Ok(Some(body)),
+            (status, body) => Err(Error::Status {
+                key: key.to_string(),
+                status,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            }),
+        }
+    }
+
+    fn put(&self, key: &str, body: Vec<u8>) -> Result<(), Error> {
+        match self.request(reqwest::Method::PUT, key, Vec::new(), body)? {
+            (status, _) if status.is_success() => Ok(()),
+            (status, body) => Err(Error::Status {
+                key: key.to_string(),
+                status,
+                body: String::from_utf8_lossy(&body).into_owned(),
+            }),
+        }
+    }
+
+    /// Every key in the bucket which begins with `prefix`
+    fn list(&self, prefix: &str) -> Result<Vec<String>, Error> {
+        let mut keys = Vec::new();
+        let mut continuation: Option<String> = None;
+        loop {
+            let mut query = vec![
+                ("list-type".to_string(), "2".to_string()),
+                ("prefix".to_string(), prefix.to_string()),
+            ];
+            if let Some(token) = continuation.take() {
+                query.push(("continuation-token".to_string(), token));
+            }
+            let (status, body) = self.request(reqwest::Method::GET, "", query, Vec::new())?;
+            let body = String::from_utf8_lossy(&body).into_owned();
+            if !status.is_success() {
+                return Err(Error::Status {
+                    key: prefix.to_string(),
+                    status,
+                    body,
+                });
+            }
+            keys.extend(listed_keys(&body));
+            match NEXT_TOKEN_REGEX.captures(&body) {
+                Some(c) => continuation = Some(xml_unescape(&c[1])),
+                None => break,
+            }
+        }
+        Ok(keys)
+    }
+
+    /// Perform a signed request. We're called from synchronous code which is running on the tokio
+    /// runtime (the downloader) so we block in place rather than using the blocking client, which
+    /// would panic.
+    fn request(
+        &self,
+        method: reqwest::Method,
+        key: &str,
+        mut query: Vec<(String, String)>,
+        body: Vec<u8>,
+    ) -> Result<(reqwest::StatusCode, Vec<u8>), Error> {
+        let path = if key.is_empty() {
+            format!("/{}", self.bucket)
+        } else {
+            format!("/{}/{}", self.bucket, key)
+        };
+        let canonical_uri = uri_encode(&path, false);
+        query.sort();
+        let canonical_query = query
+            .iter()
+            .map(|(k, v)| format!("{}={}", uri_encode(k, true), uri_encode(v, true)))
+            .collect::<Vec<_>>()
+            .join("&");
+        let mut url = self.credentials.endpoint.clone();
+        url.set_path(&canonical_uri);
+        if !canonical_query.is_empty() {
+            url.set_query(Some(&canonical_query));
+        }
+
+        let now = chrono::Utc::now();
+        let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+        let date = now.format("%Y%m%d").to_string();
+        let payload_hash = hex::encode(Sha256::digest(&body));
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+            None => url.host_str().unwrap_or("").to_string(),
+        };
+        let signed_headers = "host;x-amz-content-sha256;x-amz-date";
+        let canonical_request = format!(
+            "{}\n{}\n{}\nhost:{}\nx-amz-content-sha256:{}\nx-amz-date:{}\n\n{}\n{}",
+            method,
+            canonical_uri,
+            canonical_query,
+            host,
+            payload_hash,
+            amz_date,
+            signed_headers,
+            payload_hash
+        );
+        let scope = format!("{}/{}/s3/aws4_request", date, self.credentials.region);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let signing_key = [
+            date.as_str(),
+            self.credentials.region.as_str(),
+            "s3",
+            "aws4_request",
+        ]
+        .iter()
+        .fold(
+            format!("AWS4{}", self.credentials.secret_key).into_bytes(),
+            |key, part| hmac_sha256(&key, part.as_bytes()),
+        );
+        let signature = hex::encode(hmac_sha256(&signing_key, string_to_sign.as_bytes()));
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.credentials.access_key, scope, signed_headers, signature
+        );
+
+        let request = self
+            .client
+            .request(method, url)
+            .header("x-amz-date", amz_date)
+            .header("x-amz-content-sha256", payload_hash)
+            .header("authorization", authorization)
+            .body(body);
+        tokio::task::block_in_place(|| {
+            tokio::runtime::Handle::current().block_on(async move {
+                let response = request.send().await?;
+                let status = response.status();
+                let body = response.bytes().await?.to_vec();
+                Ok::<_, Error>((status, body))
+            })
+        })
+    }
+}
+
+impl IssueStorage for ObjectStoreStorage {
+    fn issue_numbers(&self) -> Result<Vec<u64>, LoadError> {
+        let issues_prefix = format!("{}issues/", self.root);
+        let mut numbers: Vec<u64> = self
+            .list(&issues_prefix)?
+            .iter()
+            .filter_map(|k| {
+                k.strip_prefix(&issues_prefix)?
+                    .strip_suffix(".json")?
+                    .parse()
+                    .ok()
+            })
+            .collect();
+        numbers.sort_unstable();
+        Ok(numbers)
+    }
+
+    fn load_raw(&self, number: u64) -> Result<Option<Vec<u8>>, LoadError> {
+        Ok(self.get(&self.issue_key(number))?)
+    }
+
+    fn store_raw(&self, number: u64, bytes: &[u8]) -> Result<(), StoreError> {
+        self.put(&self.issue_key(number), bytes.to_vec())?;
+        Ok(())
+    }
+
+    fn checksums(&self) -> Result<HashMap<u64, String>, LoadError> {
+        let checksums_prefix = format!("{}checksums/", self.root);
+        let mut checksums = HashMap::new();
+        for key in self.list(&checksums_prefix)? {
+            let number = key
+                .strip_prefix(&checksums_prefix)
+                .and_then(|n| n.parse().ok());
+            if let (Some(number), Some(checksum)) = (number, self.get(&key)?) {
+                checksums.insert(number, String::from_utf8_lossy(&checksum).into_owned());
+            }
+        }
+        Ok(checksums)
+    }
+
+    fn record_checksum(&self, number: u64, checksum: &str) -> Result<(), StoreError> {
+        let key = format!("{}checksums/{}", self.root, number);
+        self.put(&key, checksum.as_bytes().to_vec())?;
+        Ok(())
+    }
+
+    fn save_cursor(&self, cursor: String) -> Result<(), std::io::Error> {
+        self.put(&format!("{}last_cursor", self.root), cursor.into_bytes())
+            .map_err(to_io)
+    }
+
+    fn load_cursor(&self) -> Result<Option<String>, std::io::Error> {
+        let cursor = self
+            .get(&format!("{}last_cursor", self.root))
+            .map_err(to_io)?;
+        Ok(cursor.map(|c| String::from_utf8_lossy(&c).trim().to_string()))
+    }
+
+    fn save_last_sync(&self, at: DateTime<Utc>) -> Result<(), std::io::Error> {
+        self.put(
+            &format!("{}last_sync", self.root),
+            at.to_rfc3339().into_bytes(),
+        )
+        .map_err(to_io)
+    }
+
+    fn load_last_sync(&self) -> Result<Option<DateTime<Utc>>, std::io::Error> {
+        let last_sync = self
+            .get(&format!("{}last_sync", self.root))
+            .map_err(to_io)?;
+        last_sync
+            .map(|s| parse_sync_time(&String::from_utf8_lossy(&s)))
+            .transpose()
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// URI encode `s` as described in the AWS signature version 4 documentation
+fn uri_encode(s: &str, encode_slash: bool) -> String {
+    let mut encoded = String::new();
+    for byte in s.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// The keys in the body of a ListObjectsV2 response
+fn listed_keys(body: &str) -> Vec<String> {
+    KEY_REGEX
+        .captures_iter(body)
+        .map(|c| xml_unescape(&c[1]))
+        .collect()
+}
+
+/// Replace the entity and character references in XML text with the characters they stand for.
+/// References which aren't well formed are left as they are.
+fn xml_unescape(s: &str) -> String {
+    let mut unescaped = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        unescaped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let reference = rest.find(';').and_then(|end| {
+            let c = match &rest[1..end] {
+                "amp" => Some('&'),
+                "lt" => Some('<'),
+                "gt" => Some('>'),
+                "quot" => Some('"'),
+                "apos" => Some('\''),
+                name => name
+                    .strip_prefix("#x")
+                    .map(|hex| u32::from_str_radix(hex, 16))
+                    .or_else(|| name.strip_prefix('#').map(|dec| dec.parse()))
+                    .and_then(|code| code.ok())
+                    .and_then(std::char::from_u32),
+            };
+            c.map(|c| (c, end))
+        });
+        match reference {
+            Some((c, end)) => {
+                unescaped.push(c);
+                rest = &rest[end + 1..];
+            }
+            None => {
+                unescaped.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    unescaped.push_str(rest);
+    unescaped
+}
+
+fn to_io(e: Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn listed_keys_are_unescaped() {
+        let body = "<ListBucketResult>\
+            <Contents><Key>repos/a&amp;b/issues/1.json</Key></Contents>\
+            <Contents><Key>repos/&lt;c&gt;/it&apos;s &quot;d&quot;.json</Key></Contents>\
+            <Contents><Key>repos/e&#38;f&#x26;g.json</Key></Contents>\
+            </ListBucketResult>";
+        assert_eq!(
+            listed_keys(body),
+            vec![
+                "repos/a&b/issues/1.json",
+                "repos/<c>/it's \"d\".json",
+                "repos/e&f&g.json",
+            ]
+        );
+    }
+
+    #[test]
+    fn xml_unescape_unescapes_once() {
+        assert_eq!(xml_unescape("a&amp;lt;b"), "a&lt;b");
+        assert_eq!(xml_unescape("plain"), "plain");
+    }
+
+    #[test]
+    fn xml_unescape_leaves_malformed_references() {
+        assert_eq!(xml_unescape("a & b"), "a & b");
+        assert_eq!(xml_unescape("&bogus;&#xZZ;&"), "&bogus;&#xZZ;&");
+    }
+}
@@ -0,0 +1,80 @@
+//! How much the commands print, set once from `-q` and `-v` before the command runs.
+//!
+//! * Results, the things a command is run to find out, are printed to stdout with `println!`
+//!   whatever the verbosity, so that `-q` leaves only them
+//! * `status!` reports what a command is doing or has done on stderr, unless `-q` was given
+//! * `verbose!` reports the progress of each issue or object and how long it took on stderr, only
+//!   with `-v`
+//! * Errors are printed to stderr with `eprintln!` whatever the verbosity
+//!
+//! Progress bars are only shown at the default verbosity: `-q` hides them and with `-v` the
+//! per-object lines take their place. Unless `RUST_LOG` is set the log output of the libraries we
+//! use follows the verbosity too, showing errors by default, info with `-v` and debug with `-vv`.
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use tracing_subscriber::EnvFilter;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Verbosity {
+    Quiet,
+    Normal,
+    Verbose,
+    Debug,
+}
+
+static VERBOSITY: AtomicU8 = AtomicU8::new(Verbosity::Normal as u8);
+
+/// Set the verbosity from the number of `-q` and `-v` flags and configure logging to match
+pub fn init(quiet: bool, verbose: u64) {
+    let verbosity = match (quiet, verbose) {
+        (true, _) => Verbosity::Quiet,
+        (false, 0) => Verbosity::Normal,
+        (false, 1) => Verbosity::Verbose,
+        (false, _) => Verbosity::Debug,
+    };
+    VERBOSITY.store(verbosity as u8, Ordering::SeqCst);
+    let default_filter = match verbosity {
+        Verbosity::Quiet | Verbosity::Normal => "error",
+        Verbosity::Verbose => "info",
+        Verbosity::Debug => "debug",
+    };
+    let filter =
+        EnvFilter::try_from_default_env().unwrap_or_else(|_| EnvFilter::new(default_filter));
+    tracing_subscriber::fmt()
+        .with_env_filter(filter)
+        .with_writer(std::io::stderr)
+        .init();
+}
+
+pub fn verbosity() -> Verbosity {
+    match VERBOSITY.load(Ordering::SeqCst) {
+        0 => Verbosity::Quiet,
+        1 => Verbosity::Normal,
+        2 => Verbosity::Verbose,
+        _ => Verbosity::Debug,
+    }
+}
+
+pub fn show_progress_bars() -> bool {
+    verbosity() == Verbosity::Normal
+}
+
+/// Print a line about what the command is doing to stderr, unless `-q` was given
+#[macro_export]
+macro_rules! status {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= $crate::output::Verbosity::Normal {
+            eprintln!($($arg)*);
+        }
+    };
+}
+
+/// Print a line about the progress of a single issue or object to stderr, if `-v` was given
+#[macro_export]
+macro_rules! verbose {
+    ($($arg:tt)*) => {
+        if $crate::output::verbosity() >= $crate::output::Verbosity::Verbose {
+            eprintln!($($arg)*);
+        }
+    };
+}
@@ -0,0 +1,122 @@
+//! Materializing every issue in the monorepo across several threads. Materializing is read-only,
+//! so each worker opens its own handle on the monorepo, with the project and retrieval settings of
+//! the handle it was started from, and retrieves a share of the issues without coordinating with
+//! the others. The results are merged once every worker is done.
+use std::time::{Duration, Instant};
+
+use thiserror::Error;
+
+use crate::lite_monorepo::{error, MaterializedIssue, ReadWorker};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    CreateOrOpen(#[from] error::CreateOrOpen),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+}
+
+pub struct WorkerReport {
+    pub issues: usize,
+    /// The time spent opening the monorepo and retrieving issues
+    pub busy: Duration,
+}
+
+/// How the work was spread across the workers and how well it scaled
+pub struct ScalingReport {
+    pub jobs: usize,
+    pub elapsed: Duration,
+    pub workers: Vec<WorkerReport>,
+}
+
+impl ScalingReport {
+    /// The total time the workers were busy divided by the elapsed time. With perfect scaling
+    /// this is the number of jobs.
+    pub fn speedup(&self) -> f64 {
+        let busy: Duration = self.workers.iter().map(|w| w.busy).sum();
+        busy.as_secs_f64() / self.elapsed.as_secs_f64().max(f64::EPSILON)
+    }
+}
+
+impl std::fmt::Display for ScalingReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let issues: usize = self.workers.iter().map(|w| w.issues).sum();
+        writeln!(
+            f,
+            "materialized {} issues with {} jobs in {:?} ({:.1} issues/s)",
+            issues,
+            self.jobs,
+            self.elapsed,
+            issues as f64 / self.elapsed.as_secs_f64().max(f64::EPSILON)
+        )?;
+        for (i, worker) in self.workers.iter().enumerate() {
+            writeln!(
+                f,
+                "  worker {}: {} issues in {:?}",
+                i, worker.issues, worker.busy
+            )?;
+        }
+        writeln!(
+            f,
+            "speedup {:.2}x, efficiency {:.0}%",
+            self.speedup(),
+            100.0 * self.speedup() / self.jobs as f64
+        )
+    }
+}
+
+/// Materialize the issues in `ids` using `jobs` threads, each with a handle opened by `worker`.
+/// Issues are returned in the order of `ids`, skipping any which don't exist.
+pub fn materialize(
+    worker: &ReadWorker,
+    ids: &[cob::ObjectId],
+    jobs: usize,
+) -> Result<(Vec<MaterializedIssue>, ScalingReport), Error> {
+    let jobs = jobs.max(1);
+    let chunk_size = ((ids.len() + jobs - 1) / jobs).max(1);
+    let start = Instant::now();
+    let results: Vec<Result<(Vec<MaterializedIssue>, WorkerReport), Error>> =
+        crossbeam_utils::thread::scope(|s| {
+            let handles: Vec<_> = ids
+                .chunks(chunk_size)
+                .map(|chunk| {
+                    s.spawn(move |_| {
+                        let start = Instant::now();
+                        let monorepo = worker.open()?;
+                        let mut issues = Vec::with_capacity(chunk.len());
+                        for id in chunk {
+                            if let Some(issue) = monorepo.materialized_issue(id)? {
+                                issues.push(issue);
+                            }
+                        }
+                        let report = WorkerReport {
+                            issues: issues.len(),
+                            busy: start.elapsed(),
+                        };
+                        Ok((issues, report))
+                    })
+                })
+                .collect();
+            handles
+                .into_iter()
+                .map(|h| h.join().expect("worker thread panicked"))
+                .collect()
+        })
+        .expect("worker thread panicked");
+
+    let mut issues = Vec::with_capacity(ids.len());
+    let mut workers = Vec::with_capacity(results.len());
+    for result in results {
+        let (worker_issues, report) = result?;
+        issues.extend(worker_issues);
+        workers.push(report);
+    }
+    Ok((
+        issues,
+        ScalingReport {
+            jobs,
+            elapsed: start.elapsed(),
+            workers,
+        },
+    ))
+}
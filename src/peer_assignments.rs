@@ -3,6 +3,7 @@ use std::path::{Path, PathBuf};
 use thiserror::Error;
 
 use super::GithubUserId;
+use crate::bots::BOTS_USER;
 use link_crypto::PeerId;
 
 #[derive(Debug, Error)]
@@ -20,44 +21,85 @@ pub struct PeerAssignments {
 }
 
 impl PeerAssignments {
-    pub(crate) fn load<'a, P: AsRef<Path>>(
+    pub fn load<'a, P: AsRef<Path>>(
         path: P,
         peers: impl Iterator<Item = &'a PeerId>,
     ) -> Result<PeerAssignments, Error> {
-        let assignments = if std::fs::try_exists(&path)? {
-            let bytes = std::fs::read(&path)?;
+        let assignments = if crate::fs::exists(&path)? {
+            let bytes = crate::fs::read(&path)?;
             serde_json::from_slice(&bytes)?
         } else {
             HashMap::new()
         };
+        // Ordered so that ties between peers with as many users as each other are broken the same
+        // way every time
+        let mut peers: Vec<PeerId> = peers.cloned().collect();
+        peers.sort_by_key(|peer| peer.to_string());
         Ok(PeerAssignments {
             assignments,
             path: path.as_ref().to_path_buf(),
-            peers: peers.cloned().collect(),
+            peers,
         })
     }
 
-    pub(crate) fn assign(&mut self, uid: &GithubUserId) -> Result<&PeerId, Error> {
+    /// The users assigned to each peer, in no particular order
+    pub fn assignments(&self) -> impl Iterator<Item = (&GithubUserId, &PeerId)> {
+        self.assignments.iter()
+    }
+
+    pub fn is_assigned(&self, uid: &GithubUserId) -> bool {
+        self.assignments.contains_key(uid)
+    }
+
+    pub fn assign(&mut self, uid: &GithubUserId) -> Result<&PeerId, Error> {
         if self.assignments.contains_key(uid) {
             return Ok(self.assignments.get(uid).unwrap());
         }
-        let next_peer = next_assignment(&self.peers, self.assignments.iter_mut());
+        // The peer of bots, with `--bots dedicated-peer`, is theirs alone
+        let reserved = if uid.0 == BOTS_USER {
+            None
+        } else {
+            self.assignments
+                .get(&GithubUserId(BOTS_USER.to_string()))
+                .copied()
+        };
+        let next_peer = next_assignment(&self.peers, self.assignments.iter_mut(), reserved);
         self.assignments.insert(uid.clone(), next_peer);
-        let bytes = serde_json::to_vec(&self.assignments)?;
-        std::fs::write(&self.path, bytes)?;
+        let saved = serde_json::to_vec(&self.assignments)
+            .map_err(Error::from)
+            .and_then(|bytes| crate::fs::write_atomic(&self.path, bytes).map_err(Error::from));
+        if let Err(e) = saved {
+            // Forget the assignment so that it is made again, and saved, next time
+            self.assignments.remove(uid);
+            return Err(e);
+        }
         Ok(self.assignments.get(uid).unwrap())
     }
 }
 
+/// The peer with the fewest users assigned to it, other than `reserved` unless it's the only one.
+/// Peers added to an existing monorepo start with none, so they are given every new user until
+/// they have caught up with the others.
 fn next_assignment<'a>(
     peers: &[PeerId],
     assignments: impl Iterator<Item = (&'a GithubUserId, &'a mut PeerId)>,
+    reserved: Option<PeerId>,
 ) -> PeerId {
     let assignment_counts: HashMap<PeerId, u64> =
         assignments.fold(HashMap::new(), |mut acc, (_, peer_id)| {
             acc.entry(*peer_id).and_modify(|e| *e += 1).or_insert(1);
             acc
         });
+    let candidates: Vec<PeerId> = peers
+        .iter()
+        .copied()
+        .filter(|p| Some(*p) != reserved)
+        .collect();
+    let peers = if candidates.is_empty() {
+        peers
+    } else {
+        &candidates[..]
+    };
     let mut assigned_peer = peers[0];
     let mut min_count = *assignment_counts.get(&assigned_peer).unwrap_or(&0);
     for peer in peers {
@@ -1,76 +1,342 @@
 use std::collections::HashMap;
 
+use lazy_static::lazy_static;
+use once_cell::unsync::OnceCell;
 use thiserror::Error;
 
 use link_crypto::{PeerId, PublicKey, SecretKey};
 use link_identities::{
     delegation::Direct,
-    git::error::{Load, Store},
-    payload::{Person as PersonSubject, PersonPayload},
+    git::{
+        error::{Load, Store},
+        Urn,
+    },
+    payload::{self, Person as PersonSubject, PersonPayload},
     Person,
 };
+use url::Url;
+
+use crate::{devices::Devices, graphql::GithubProfile};
+
+use std::str::FromStr;
 
 #[derive(Debug, Error)]
-pub(crate) enum Error {
+pub enum Error {
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
     Serde(#[from] serde_json::Error),
     #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
     LoadIdentity(#[from] Load),
     #[error(transparent)]
     StoreIdentity(#[from] Store),
     #[error("missing peer identity in monorepo for {peer}")]
     MissingPeer { peer: PeerId },
+    #[error("unable to parse peer ID from identity ref {0}")]
+    BadRef(String),
+}
+
+/// The JSON file monorepos created before identities were stored in refs record them in, relative
+/// to the monorepo root
+pub const LEGACY_INDEX: &str = "peer_identities";
+
+lazy_static! {
+    static ref GITHUB_EXT: Url =
+        Url::parse("https://radicle.xyz/collab-stress-test/github/v1").unwrap();
+}
+
+/// The GitHub user a peer's identity is named after, as an extension of its payload
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct GithubExt {
+    pub login: String,
+    pub avatar_url: Option<String>,
+}
+
+impl payload::HasNamespace for GithubExt {
+    fn namespace() -> &'static Url {
+        &GITHUB_EXT
+    }
 }
 
-pub(crate) struct PeerIdentities(HashMap<PeerId, (Person, SecretKey)>);
+struct Entry {
+    oid: radicle_git_ext::Oid,
+    key: SecretKey,
+    /// Loaded from `oid` the first time it is needed
+    person: OnceCell<Person>,
+}
+
+/// The `Person` identity of each peer. Loading an identity from git is relatively expensive and
+/// most operations only need the identities of a few peers, so identities are loaded lazily.
+///
+/// As in librad, the identities are stored as refs in the namespace of the project. Each peer's
+/// identity is at `refs/namespaces/<project>/refs/remotes/<peer>/rad/self` and is also listed as a
+/// delegate at `refs/namespaces/<project>/refs/rad/ids/<person>`. The history of each identity
+/// lives in its own namespace at `refs/namespaces/<person>/refs/rad/id`.
+pub struct PeerIdentities(HashMap<PeerId, Entry>);
 
 impl PeerIdentities {
-    pub(crate) fn load<'a, P: AsRef<std::path::Path>>(
-        index_path: P,
+    /// The number of peers [`PeerIdentities::load`] would migrate from the legacy JSON file at
+    /// `legacy_index_path` before removing it, or `None` if it wouldn't. The file is ignored once
+    /// identities are stored in refs, which this takes to be the case if `repo` has the identity
+    /// of a peer in any namespace.
+    pub fn legacy_peers<P: AsRef<std::path::Path>>(
+        legacy_index_path: P,
+        repo: &git2::Repository,
+    ) -> Result<Option<usize>, Error> {
+        if !crate::fs::exists(&legacy_index_path)? {
+            return Ok(None);
+        }
+        if repo
+            .references_glob("refs/namespaces/*/refs/remotes/*/rad/self")?
+            .next()
+            .is_some()
+        {
+            return Ok(None);
+        }
+        let mapping: HashMap<PeerId, radicle_git_ext::Oid> =
+            serde_json::from_slice(&crate::fs::read(&legacy_index_path)?)?;
+        Ok(Some(mapping.len()))
+    }
+
+    /// Load the identities of `peers` from the refs of `project`, creating those which don't
+    /// exist, e.g. for peers added to an existing monorepo, with an identity for each user of
+    /// `devices` delegating to the key of each of its peers. Monorepos created before identities
+    /// were stored in refs record them in a JSON file at `legacy_index_path`; if it exists the
+    /// refs are created from it and the file removed, reporting both first unless `-q` was given.
+    pub fn load<'a, P: AsRef<std::path::Path>>(
+        legacy_index_path: P,
         repo: &git2::Repository,
+        project: &Urn,
         peers: impl Iterator<Item = (&'a PeerId, &'a SecretKey)>,
+        devices: &Devices,
     ) -> Result<PeerIdentities, Error> {
+        let key_by_peer: HashMap<PeerId, SecretKey> = peers.map(|(p, s)| (*p, s.clone())).collect();
         let identities: link_identities::Identities<'_, Person> = repo.into();
-        let mut ids: HashMap<PeerId, (Person, SecretKey)> = HashMap::new();
-        if std::fs::try_exists(&index_path)? {
-            let key_by_peer: HashMap<PeerId, SecretKey> =
-                peers.map(|(p, s)| (*p, s.clone())).collect();
-            let bytes = std::fs::read(&index_path)?;
+        let mut ids: HashMap<PeerId, Entry> = HashMap::new();
+
+        let self_refs = self_refs(repo, project)?;
+        if !self_refs.is_empty() {
+            for (peer, oid) in self_refs {
+                let key = key_by_peer.get(&peer).ok_or(Error::MissingPeer { peer })?;
+                ids.insert(
+                    peer,
+                    Entry {
+                        oid,
+                        key: key.clone(),
+                        person: OnceCell::new(),
+                    },
+                );
+            }
+        } else if crate::fs::exists(&legacy_index_path)? {
+            let bytes = crate::fs::read(&legacy_index_path)?;
             let mapping: HashMap<PeerId, radicle_git_ext::Oid> = serde_json::from_slice(&bytes)?;
+            let legacy_index_path = legacy_index_path.as_ref();
+            status!(
+                "Migrating the identities of {} peers from {} to refs",
+                mapping.len(),
+                legacy_index_path.display()
+            );
             for (peer, oid) in mapping {
-                let identity = identities.get(oid.into())?;
                 let key = key_by_peer.get(&peer).ok_or(Error::MissingPeer { peer })?;
-                ids.insert(peer, (identity, key.clone()));
+                let person = identities.get(oid.into())?;
+                write_refs(repo, project, &peer, &person)?;
+                ids.insert(
+                    peer,
+                    Entry {
+                        oid,
+                        key: key.clone(),
+                        person: OnceCell::from(person),
+                    },
+                );
             }
-        } else {
-            for (peer, key) in peers {
+            status!(
+                "Removing {}, which the refs replace",
+                legacy_index_path.display()
+            );
+            crate::fs::remove_file(legacy_index_path)?;
+        }
+        for group in devices.groups() {
+            let missing: Vec<PeerId> = group
+                .iter()
+                .filter(|p| !ids.contains_key(p))
+                .copied()
+                .collect();
+            // The devices of a new user share an identity, but a device added to a user which
+            // already has one gets its own
+            let size = if missing.len() == group.len() {
+                group.len()
+            } else {
+                1
+            };
+            for members in missing.chunks(size) {
+                let keys = members
+                    .iter()
+                    .map(|peer| {
+                        key_by_peer
+                            .get(peer)
+                            .ok_or(Error::MissingPeer { peer: *peer })
+                    })
+                    .collect::<Result<Vec<&SecretKey>, Error>>()?;
                 let payload: PersonPayload = PersonPayload::new(PersonSubject {
-                    name: peer.to_string().into(),
+                    name: members[0].to_string().into(),
                 });
-                let pubkey: PublicKey = key.public();
-                let delegations: Direct = Direct::new(pubkey);
-                let identity = identities.create(payload, delegations, key)?;
-                ids.insert(*peer, (identity, key.clone()));
+                let delegations: Direct = if keys.len() == 1 {
+                    let pubkey: PublicKey = keys[0].public();
+                    Direct::new(pubkey)
+                } else {
+                    Direct::try_from_iter(keys.iter().map(|key| key.public()))
+                        .expect("the keys of the devices are distinct")
+                };
+                let identity = identities.create(payload, delegations, keys[0])?;
+                let identity = sign_by_devices(&identities, identity, &keys[1..])?;
+                for (peer, key) in members.iter().zip(keys) {
+                    write_refs(repo, project, peer, &identity)?;
+                    ids.insert(
+                        *peer,
+                        Entry {
+                            oid: identity.content_id,
+                            key: key.clone(),
+                            person: OnceCell::from(identity.clone()),
+                        },
+                    );
+                }
             }
-            let oid_mapping: HashMap<&PeerId, radicle_git_ext::Oid> =
-                ids.iter().map(|(p, (id, _))| (p, id.content_id)).collect();
-            let bytes = serde_json::to_vec(&oid_mapping)?;
-            std::fs::write(&index_path, &bytes)?;
         }
         Ok(PeerIdentities(ids))
     }
 
-    pub(crate) fn some_key(&self) -> SecretKey {
-        self.0.values().next().unwrap().1.clone()
+    /// Name the identity of `peer` after the GitHub user of `profile`, recording their login and
+    /// avatar in an extension of the payload, unless it is already. Updating an identity makes
+    /// a new revision of it, which the refs of the peer, and of the other devices sharing the
+    /// identity, are moved to. Returns whether the identity was updated.
+    pub fn set_profile(
+        &mut self,
+        repo: &git2::Repository,
+        project: &Urn,
+        peer: &PeerId,
+        profile: &GithubProfile,
+    ) -> Result<bool, Error> {
+        let name = profile.name.as_deref().unwrap_or(&profile.login);
+        let (person, key) = match self.get(repo, peer)? {
+            Some((person, key)) => (person.clone(), key.clone()),
+            None => return Err(Error::MissingPeer { peer: *peer }),
+        };
+        if person.payload().subject.name.to_string() == name {
+            return Ok(false);
+        }
+        let payload =
+            PersonPayload::new(PersonSubject { name: name.into() }).with_ext(GithubExt {
+                login: profile.login.clone(),
+                avatar_url: profile.avatar_url.clone(),
+            })?;
+        let identities: link_identities::Identities<'_, Person> = repo.into();
+        let previous = person.content_id;
+        let updated = identities.update(person, Some(payload), None::<Direct>, &key)?;
+        let sharing: Vec<PeerId> = self
+            .0
+            .iter()
+            .filter(|(p, entry)| entry.oid == previous && *p != peer)
+            .map(|(p, _)| *p)
+            .collect();
+        let keys: Vec<SecretKey> = sharing.iter().map(|p| self.0[p].key.clone()).collect();
+        let updated = sign_by_devices(&identities, updated, &keys.iter().collect::<Vec<_>>())?;
+        for device in sharing.iter().chain(std::iter::once(peer)) {
+            write_refs(repo, project, device, &updated)?;
+            let entry = self.0.get_mut(device).unwrap();
+            entry.oid = updated.content_id;
+            entry.person = OnceCell::from(updated.clone());
+        }
+        Ok(true)
+    }
+
+    /// The identity and key of `peer_id`, loading the identity from `repo` if it hasn't been
+    /// loaded yet
+    pub fn get(
+        &self,
+        repo: &git2::Repository,
+        peer_id: &PeerId,
+    ) -> Result<Option<(&Person, &SecretKey)>, Error> {
+        match self.0.get(peer_id) {
+            Some(entry) => {
+                let person = entry.person.get_or_try_init(|| {
+                    let identities: link_identities::Identities<'_, Person> = repo.into();
+                    identities.get(entry.oid.into())
+                })?;
+                Ok(Some((person, &entry.key)))
+            }
+            None => Ok(None),
+        }
     }
+}
 
-    pub(crate) fn get(&self, peer_id: &PeerId) -> Option<&(Person, SecretKey)> {
-        self.0.get(peer_id)
+/// Sign `identity` with the keys of the other devices of its user, so that it is signed by all
+/// of its delegations
+fn sign_by_devices(
+    identities: &link_identities::Identities<'_, Person>,
+    mut identity: Person,
+    keys: &[&SecretKey],
+) -> Result<Person, Error> {
+    for key in keys {
+        identity = identities.update(identity, None::<PersonPayload>, None::<Direct>, *key)?;
     }
+    Ok(identity)
+}
 
-    pub(crate) fn keys(&self) -> impl Iterator<Item = &SecretKey> {
-        self.0.values().map(|v| &v.1)
+/// The identity each peer has published in the namespace of `project`
+fn self_refs(
+    repo: &git2::Repository,
+    project: &Urn,
+) -> Result<HashMap<PeerId, radicle_git_ext::Oid>, Error> {
+    let prefix = format!("refs/namespaces/{}/refs/remotes/", project.encode_id());
+    let glob = format!("{}*/rad/self", prefix);
+    let mut result = HashMap::new();
+    for reference in repo.references_glob(&glob)? {
+        let reference = reference?;
+        let name = reference.name().unwrap_or_default();
+        let peer = name
+            .strip_prefix(&prefix)
+            .and_then(|rest| rest.strip_suffix("/rad/self"))
+            .and_then(|peer| PeerId::from_str(peer).ok())
+            .ok_or_else(|| Error::BadRef(name.to_string()))?;
+        if let Some(oid) = reference.target() {
+            result.insert(peer, oid.into());
+        }
     }
+    Ok(result)
+}
+
+fn write_refs(
+    repo: &git2::Repository,
+    project: &Urn,
+    peer: &PeerId,
+    person: &Person,
+) -> Result<(), Error> {
+    let oid: git2::Oid = person.content_id.into();
+    let person_id = person.urn().encode_id();
+    let project_id = project.encode_id();
+    repo.reference(
+        &format!("refs/namespaces/{}/refs/rad/id", person_id),
+        oid,
+        true,
+        "create identity",
+    )?;
+    repo.reference(
+        &format!(
+            "refs/namespaces/{}/refs/remotes/{}/rad/self",
+            project_id, peer
+        ),
+        oid,
+        true,
+        "peer identity",
+    )?;
+    repo.reference(
+        &format!("refs/namespaces/{}/refs/rad/ids/{}", project_id, person_id),
+        oid,
+        true,
+        "project delegate",
+    )?;
+    Ok(())
 }
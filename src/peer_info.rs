@@ -0,0 +1,133 @@
+//! Everything the monorepo holds for a single peer, for debugging why the changes of a particular
+//! peer are rejected: the `Person` identity it presents, the keys delegated by it, the object refs
+//! it owns and how much it has written. The bytes authored are the sizes of the changes reachable
+//! from the peer's refs which are signed by the peer's key, so changes of other peers which the
+//! peer has merged aren't counted.
+use std::{collections::HashSet, convert::TryFrom};
+
+use link_crypto::PeerId;
+use link_identities::{sign::Signatures, Person};
+use thiserror::Error;
+
+use crate::{
+    identity_pins,
+    lite_monorepo::{error, LiteMonorepo},
+    replication,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Identity(#[from] error::Import),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+pub struct PeerInfo {
+    pub peer: PeerId,
+    pub person: Person,
+    /// The identity document, as JSON
+    pub document: String,
+    pub delegations: Vec<PeerId>,
+    /// The names of the object refs of the peer
+    pub refs: Vec<String>,
+    /// The number of changes signed by the peer
+    pub changes: usize,
+    /// The total size of those changes
+    pub bytes_authored: u64,
+}
+
+/// The identity and refs of `peer`, or `None` if it isn't a peer of `monorepo`
+pub fn peer_info(monorepo: &LiteMonorepo, peer: &PeerId) -> Result<Option<PeerInfo>, Error> {
+    let person = match monorepo.peer_identity(peer)? {
+        Some(person) => person,
+        None => return Ok(None),
+    };
+    let document = serde_json::to_string_pretty(&person.doc)?;
+    let delegations = person
+        .delegations()
+        .iter()
+        .map(|key| PeerId::from(*key))
+        .collect();
+
+    let repo = monorepo.repo();
+    let mut refs = Vec::new();
+    let mut pending = Vec::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        let name = match reference.name() {
+            Some(name) => name,
+            None => continue,
+        };
+        if identity_pins::peer_of_ref(name).as_ref() != Some(peer)
+            || replication::object_of_ref(name).is_none()
+        {
+            continue;
+        }
+        refs.push(name.to_string());
+        pending.extend(reference.target());
+    }
+    refs.sort();
+
+    let mut seen = HashSet::new();
+    let mut changes = 0;
+    let mut bytes_authored = 0;
+    while let Some(oid) = pending.pop() {
+        if !seen.insert(oid) {
+            continue;
+        }
+        let commit = repo.find_commit(oid)?;
+        let change = match commit.tree()?.get_name("change") {
+            Some(entry) => entry.id(),
+            // Identities aren't changes, and nor are their parents
+            None => continue,
+        };
+        pending.extend(commit.parent_ids());
+        let signed = Signatures::try_from(&commit)
+            .map(|signatures| {
+                signatures
+                    .iter()
+                    .any(|(key, _)| PeerId::from(*key) == *peer)
+            })
+            .unwrap_or(false);
+        if signed {
+            changes += 1;
+            bytes_authored += repo.find_blob(change)?.size() as u64;
+        }
+    }
+
+    Ok(Some(PeerInfo {
+        peer: *peer,
+        person,
+        document,
+        delegations,
+        refs,
+        changes,
+        bytes_authored,
+    }))
+}
+
+impl std::fmt::Display for PeerInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "peer: {}", self.peer)?;
+        writeln!(f, "identity: {}", self.person.urn())?;
+        writeln!(f, "revision: {}", self.person.content_id)?;
+        writeln!(f, "document:\n{}", self.document)?;
+        writeln!(f, "delegations:")?;
+        for delegation in &self.delegations {
+            writeln!(f, "  {}", delegation)?;
+        }
+        writeln!(
+            f,
+            "authored {} changes, {} bytes",
+            self.changes, self.bytes_authored
+        )?;
+        writeln!(f, "{} object refs:", self.refs.len())?;
+        for name in &self.refs {
+            writeln!(f, "  {}", name)?;
+        }
+        Ok(())
+    }
+}
@@ -1,27 +1,232 @@
+//! The refs `cob` reads and writes collaborative objects through. Each peer has a ref per object
+//! pointing at the tip of its change graph for the object, laid out in one of two ways:
+//!
+//! * [`RefLayout::PerPeer`], `refs/namespaces/<urn>/refs/remotes/<peer>/cob/<typename>/<oid>`,
+//!   as librad lays them out, so that the refs of a peer can be fetched with a single refspec
+//! * [`RefLayout::Flat`], `refs/namespaces/<urn>/refs/cob/<typename>/<oid>/<peer>`, which puts
+//!   the refs of each object next to each other, so that looking up or enumerating the refs of
+//!   a type name only has to read the directory of that type name, rather than those of every
+//!   peer
+//!
+//! The layout is chosen when a monorepo is created, see [`crate::monorepo_config`].
 use cob::{ObjectId, ObjectRefs, RefsStorage, TypeName};
+use lazy_static::lazy_static;
 use link_crypto::PeerId;
 use link_identities::git::Urn;
 use thiserror::Error;
 
 use std::{collections::HashMap, str::FromStr};
 
+use crate::{replication::RefUpdate, tracking::Tracking};
+
 #[derive(Debug, Error)]
 pub enum Error {
     #[error(transparent)]
     Git(#[from] git2::Error),
 }
 
-pub(crate) struct PeerRefsStorage<'a> {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RefLayout {
+    PerPeer,
+    Flat,
+}
+
+impl Default for RefLayout {
+    fn default() -> Self {
+        RefLayout::PerPeer
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("expected per-peer or flat")]
+pub struct ParseRefLayoutError {}
+
+impl FromStr for RefLayout {
+    type Err = ParseRefLayoutError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "per-peer" => Ok(RefLayout::PerPeer),
+            "flat" => Ok(RefLayout::Flat),
+            _ => Err(ParseRefLayoutError {}),
+        }
+    }
+}
+
+impl std::fmt::Display for RefLayout {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RefLayout::PerPeer => write!(f, "per-peer"),
+            RefLayout::Flat => write!(f, "flat"),
+        }
+    }
+}
+
+pub struct PeerRefsStorage<'a> {
     peer: link_crypto::PeerId,
     repo: &'a git2::Repository,
+    tracking: Option<&'a Tracking>,
+    layout: RefLayout,
 }
 
 impl<'a> PeerRefsStorage<'a> {
-    pub(crate) fn new(
-        peer: link_crypto::PeerId,
-        repo: &'a git2::Repository,
-    ) -> PeerRefsStorage<'a> {
-        PeerRefsStorage { peer, repo }
+    pub fn new(peer: link_crypto::PeerId, repo: &'a git2::Repository) -> PeerRefsStorage<'a> {
+        PeerRefsStorage {
+            peer,
+            repo,
+            tracking: None,
+            layout: RefLayout::default(),
+        }
+    }
+
+    /// Read and write refs laid out as `layout`
+    pub fn with_layout(mut self, layout: RefLayout) -> PeerRefsStorage<'a> {
+        self.layout = layout;
+        self
+    }
+
+    /// Only consider the refs of remote peers in `tracking`. If `tracking` is `None` every peer is
+    /// considered.
+    pub fn with_tracking(mut self, tracking: Option<&'a Tracking>) -> PeerRefsStorage<'a> {
+        self.tracking = tracking;
+        self
+    }
+
+    fn tracks(&self, peer: &PeerId) -> bool {
+        self.tracking.map(|t| t.tracks(peer)).unwrap_or(true)
+    }
+
+    /// The change this peer's ref for `oid` points at, if it has one
+    pub fn local_tip(
+        &self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+        oid: &ObjectId,
+    ) -> Result<Option<git2::Oid>, Error> {
+        let literef = LiteRef {
+            peer: &self.peer,
+            urn: identity_urn,
+            typename,
+            object_id: *oid,
+            layout: self.layout,
+        };
+        match self.repo.refname_to_id(literef.to_string().as_str()) {
+            Ok(oid) => Ok(Some(oid)),
+            Err(e) if e.code() == git2::ErrorCode::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Point this peer's ref for `oid` at `tip`, or remove it if `tip` is `None`
+    pub fn set_local_tip(
+        &self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+        oid: &ObjectId,
+        tip: Option<git2::Oid>,
+    ) -> Result<(), Error> {
+        let name = LiteRef {
+            peer: &self.peer,
+            urn: identity_urn,
+            typename,
+            object_id: *oid,
+            layout: self.layout,
+        }
+        .to_string();
+        match tip {
+            Some(tip) => {
+                self.repo.reference(&name, tip, true, "set tip")?;
+            }
+            None => match self.repo.find_reference(&name) {
+                Ok(mut reference) => reference.delete()?,
+                Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+                Err(e) => return Err(e.into()),
+            },
+        }
+        Ok(())
+    }
+
+    /// Point this peer's ref for `oid` at `commit`, as a fetch would: the ref is created if it
+    /// doesn't exist and otherwise only moved if `commit` is a descendant of its target. The
+    /// commit and everything it references must already be in the repository.
+    pub fn receive_ref(
+        &self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+        oid: &ObjectId,
+        commit: git2::Oid,
+    ) -> Result<RefUpdate, Error> {
+        let literef = LiteRef {
+            peer: &self.peer,
+            urn: identity_urn,
+            typename,
+            object_id: *oid,
+            layout: self.layout,
+        };
+        let name = literef.to_string();
+        match self.repo.find_reference(&name) {
+            Ok(mut reference) => match reference.target() {
+                Some(current) if current == commit => Ok(RefUpdate::AlreadyCurrent),
+                Some(current) if self.repo.graph_descendant_of(commit, current)? => {
+                    reference.set_target(commit, "receive")?;
+                    Ok(RefUpdate::FastForwarded)
+                }
+                _ => Ok(RefUpdate::Stale),
+            },
+            Err(e) if e.code() == git2::ErrorCode::NotFound => {
+                self.repo.reference(&name, commit, false, "receive")?;
+                Ok(RefUpdate::Created)
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// The names of the references `delete_object_refs` would delete
+    pub fn object_ref_names(
+        &self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+        oid: &ObjectId,
+        all_peers: bool,
+    ) -> Result<Vec<String>, Error> {
+        let ObjectRefs { local, remote } = self.object_references(identity_urn, typename, oid)?;
+        let mut names: Vec<String> = local
+            .iter()
+            .filter_map(|r| r.name().map(|n| n.to_string()))
+            .collect();
+        if all_peers {
+            names.extend(
+                remote
+                    .iter()
+                    .filter_map(|r| r.name().map(|n| n.to_string())),
+            );
+        }
+        Ok(names)
+    }
+
+    /// Delete the references this peer holds for `oid`, and if `all_peers` is set the references
+    /// of every other peer as well. Returns the number of references which were deleted.
+    pub fn delete_object_refs(
+        &self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+        oid: &ObjectId,
+        all_peers: bool,
+    ) -> Result<usize, Error> {
+        let ObjectRefs { local, remote } = self.object_references(identity_urn, typename, oid)?;
+        let mut deleted = 0;
+        if let Some(mut local) = local {
+            local.delete()?;
+            deleted += 1;
+        }
+        if all_peers {
+            for mut reference in remote {
+                reference.delete()?;
+                deleted += 1;
+            }
+        }
+        Ok(deleted)
     }
 }
 
@@ -40,6 +245,7 @@ impl<'a> RefsStorage for PeerRefsStorage<'a> {
             urn: identity_urn,
             typename,
             object_id,
+            layout: self.layout,
         };
         self.repo
             .reference(literef.to_string().as_str(), new_commit, true, "new change")?;
@@ -51,30 +257,28 @@ impl<'a> RefsStorage for PeerRefsStorage<'a> {
         identity_urn: &Urn,
         typename: &TypeName,
     ) -> Result<HashMap<ObjectId, ObjectRefs<'b>>, Self::Error> {
-        let peer_regex_str = format!(
-            r"refs/namespaces/{}/refs/remotes/([0-9a-zA-Z]+)/cob/{}/([0-9a-f]{{40}})",
-            identity_urn.encode_id(),
-            typename.to_string(),
-        );
-        let peer_regex = regex::Regex::new(peer_regex_str.as_str()).unwrap();
         let mut result = HashMap::new();
-
-        for reference in self.repo.references().into_iter().flatten() {
+        let glob = type_glob(self.layout, identity_urn, typename);
+        for reference in self.repo.references_glob(&glob)? {
             let reference = reference?;
-            if let Some(name) = reference.name() {
-                if let Some(caps) = peer_regex.captures(name) {
-                    let oid = ObjectId::from_str(&caps[2]).unwrap();
-                    let mut refs = result.entry(oid).or_insert_with(|| ObjectRefs {
-                        local: None,
-                        remote: Vec::new(),
-                    });
-                    let peer = PeerId::from_str(&caps[1]).unwrap();
-                    if peer == self.peer {
-                        refs.local = Some(reference);
-                    } else {
-                        refs.remote.push(reference);
-                    }
-                }
+            let (peer, oid) = match reference
+                .name()
+                .and_then(|n| parse_object_ref(self.layout, n))
+            {
+                Some(parsed) => parsed,
+                None => continue,
+            };
+            if peer != self.peer && !self.tracks(&peer) {
+                continue;
+            }
+            let refs = result.entry(oid).or_insert_with(|| ObjectRefs {
+                local: None,
+                remote: Vec::new(),
+            });
+            if peer == self.peer {
+                refs.local = Some(reference);
+            } else {
+                refs.remote.push(reference);
             }
         }
         Ok(result)
@@ -86,52 +290,143 @@ impl<'a> RefsStorage for PeerRefsStorage<'a> {
         typename: &TypeName,
         oid: &ObjectId,
     ) -> Result<ObjectRefs<'b>, Self::Error> {
-        let local_str = format!(
-            "refs/namespaces/{}/refs/remotes/{}/cob/{}/{}",
-            identity_urn.encode_id(),
-            self.peer.default_encoding(),
-            typename.to_string(),
-            oid.to_string()
-        );
+        let ref_name = |peer: &PeerId| {
+            LiteRef {
+                peer,
+                urn: identity_urn,
+                typename,
+                object_id: *oid,
+                layout: self.layout,
+            }
+            .to_string()
+        };
+        let local_str = ref_name(&self.peer);
         let local = match self.repo.find_reference(local_str.as_str()) {
             Ok(r) => Some(r),
             Err(e) if e.code() == git2::ErrorCode::NotFound => None,
             Err(e) => return Err(e.into()),
         };
-        let remote_glob = globset::Glob::new(
-            format!(
-                "refs/namespaces/{}/refs/remotes/**/cob/{}/{}",
-                identity_urn.encode_id(),
-                typename.to_string(),
-                oid.to_string(),
-            )
-            .as_str(),
-        )
-        .unwrap()
-        .compile_matcher();
-        let remote = references_glob(self.repo, local_str, remote_glob)?
-            .collect::<Result<Vec<git2::Reference<'_>>, Self::Error>>()?;
+        if let Some(tracking) = self.tracking {
+            // Look up the ref of each tracked peer, which is roughly what fetching from the
+            // tracked peers does
+            let mut remote = Vec::new();
+            for peer in tracking.iter().filter(|p| **p != self.peer) {
+                match self.repo.find_reference(ref_name(peer).as_str()) {
+                    Ok(r) => remote.push(r),
+                    Err(e) if e.code() == git2::ErrorCode::NotFound => {}
+                    Err(e) => return Err(e.into()),
+                }
+            }
+            return Ok(ObjectRefs { local, remote });
+        }
+        let remote = match self.layout {
+            RefLayout::PerPeer => {
+                let remote_glob = globset::Glob::new(
+                    format!(
+                        "refs/namespaces/{}/refs/remotes/**/cob/{}/{}",
+                        identity_urn.encode_id(),
+                        typename.to_string(),
+                        oid.to_string(),
+                    )
+                    .as_str(),
+                )
+                .unwrap()
+                .compile_matcher();
+                references_glob(self.repo, local_str, remote_glob)?
+                    .collect::<Result<Vec<git2::Reference<'_>>, Self::Error>>()?
+            }
+            RefLayout::Flat => {
+                let glob = format!(
+                    "refs/namespaces/{}/refs/cob/{}/{}/*",
+                    identity_urn.encode_id(),
+                    typename.to_string(),
+                    oid.to_string(),
+                );
+                let mut remote = Vec::new();
+                for reference in self.repo.references_glob(&glob)? {
+                    let reference = reference?;
+                    if reference.name() != Some(local_str.as_str()) {
+                        remote.push(reference);
+                    }
+                }
+                remote
+            }
+        };
         Ok(ObjectRefs { local, remote })
     }
 }
 
+lazy_static! {
+    static ref PER_PEER_REF: regex::Regex = regex::Regex::new(
+        r"^refs/namespaces/[^/]+/refs/remotes/(?P<peer>[0-9a-zA-Z]+)/cob/[^/]+/(?P<oid>[0-9a-f]{40})$"
+    )
+    .unwrap();
+    static ref FLAT_REF: regex::Regex = regex::Regex::new(
+        r"^refs/namespaces/[^/]+/refs/cob/[^/]+/(?P<oid>[0-9a-f]{40})/(?P<peer>[0-9a-zA-Z]+)$"
+    )
+    .unwrap();
+}
+
+/// A glob matching the refs of every object of `typename` in `layout`. libgit2 only walks the
+/// loose refs under the part of a glob before its first wildcard and matches packed refs against
+/// it without handing them out, so no more refs are read than have to be.
+pub fn type_glob(layout: RefLayout, urn: &Urn, typename: &TypeName) -> String {
+    match layout {
+        RefLayout::PerPeer => format!(
+            "refs/namespaces/{}/refs/remotes/*/cob/{}/*",
+            urn.encode_id(),
+            typename
+        ),
+        RefLayout::Flat => format!(
+            "refs/namespaces/{}/refs/cob/{}/*",
+            urn.encode_id(),
+            typename
+        ),
+    }
+}
+
+/// The peer and object of `name`, if it is an object ref in `layout`. The namespace and type name
+/// aren't checked, as the refs come from [`type_glob`].
+fn parse_object_ref(layout: RefLayout, name: &str) -> Option<(PeerId, ObjectId)> {
+    let regex = match layout {
+        RefLayout::PerPeer => &*PER_PEER_REF,
+        RefLayout::Flat => &*FLAT_REF,
+    };
+    let caps = regex.captures(name)?;
+    Some((
+        PeerId::from_str(&caps["peer"]).ok()?,
+        ObjectId::from_str(&caps["oid"]).ok()?,
+    ))
+}
+
 struct LiteRef<'a> {
     peer: &'a link_crypto::PeerId,
     urn: &'a Urn,
     typename: &'a TypeName,
     object_id: ObjectId,
+    layout: RefLayout,
 }
 
 impl<'a> std::fmt::Display for LiteRef<'a> {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "refs/namespaces/{}/refs/remotes/{}/cob/{}/{}",
-            self.urn.encode_id(),
-            self.peer,
-            self.typename,
-            self.object_id
-        )
+        match self.layout {
+            RefLayout::PerPeer => write!(
+                f,
+                "refs/namespaces/{}/refs/remotes/{}/cob/{}/{}",
+                self.urn.encode_id(),
+                self.peer,
+                self.typename,
+                self.object_id
+            ),
+            RefLayout::Flat => write!(
+                f,
+                "refs/namespaces/{}/refs/cob/{}/{}/{}",
+                self.urn.encode_id(),
+                self.typename,
+                self.object_id,
+                self.peer
+            ),
+        }
     }
 }
 
@@ -0,0 +1,152 @@
+//! How the monorepo copes with many peers. Large open source projects have thousands of
+//! contributors, far more than the 10 peers a monorepo has by default, and every contributor who
+//! comments becomes a peer with their own identity and refs. The same downloaded issues are
+//! imported into a fresh monorepo for each peer count and we measure
+//!
+//! * how long creating the keys and identities of the peers took when the monorepo was created
+//! * how long the import took
+//! * how long enumerating the refs of every object with `type_references` takes
+//! * the latency of retrieving objects with a cold cache
+//!
+//! Users are assigned peers as they are first seen, so a corpus with fewer users than peers
+//! leaves the extra peers without any refs. The number of peers which ended up with refs is
+//! reported alongside.
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use link_crypto::PeerId;
+use thiserror::Error;
+
+use crate::{
+    bench::Stats,
+    cache,
+    downloaded_issue::DownloadedIssue,
+    identity_pins,
+    lite_monorepo::{error, LiteMonorepo},
+    monorepo_config::{self, Config},
+    peer_refs_storage,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("{0} already exists, remove it or choose another directory")]
+    Exists(PathBuf),
+    #[error(transparent)]
+    Config(#[from] monorepo_config::Error),
+    #[error(transparent)]
+    Open(#[from] error::CreateOrOpen),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    PeerRefs(#[from] peer_refs_storage::Error),
+    #[error(transparent)]
+    Cache(#[from] cache::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The measurements for one peer count
+pub struct Report {
+    pub peers: usize,
+    /// Creating the keys and identities of every peer
+    pub identity_creation: Duration,
+    pub import: Duration,
+    pub changes: u64,
+    /// The number of peers which have a ref to any object
+    pub peers_with_refs: usize,
+    pub type_references: Duration,
+    pub refs: usize,
+    pub retrieve: Option<Stats>,
+}
+
+/// The directory the monorepo with `peers` peers is kept in under `dir`
+pub fn monorepo_dir(dir: &Path, peers: usize) -> PathBuf {
+    dir.join(format!("{}-peers", peers)).join("monorepo")
+}
+
+/// Import `issues` into a new monorepo with `peers` peers in `dir` and take measurements,
+/// retrieving up to `retrievals` of the imported objects
+pub fn run(
+    dir: &Path,
+    issues: &[DownloadedIssue],
+    peers: usize,
+    retrievals: usize,
+) -> Result<Report, Error> {
+    let root = monorepo_dir(dir, peers);
+    if crate::fs::exists(&root)? {
+        return Err(Error::Exists(root));
+    }
+    Config {
+        peers,
+        ..Config::default()
+    }
+    .save(&root)?;
+    let mut monorepo = LiteMonorepo::create_or_open(&root)?;
+    let timings = monorepo.open_timings();
+    let identity_creation = timings.peers + timings.peer_identities;
+
+    let start = Instant::now();
+    for issue in issues {
+        monorepo.import_issue(issue)?;
+    }
+    let import = start.elapsed();
+
+    let mut with_refs: HashSet<PeerId> = HashSet::new();
+    for reference in monorepo.repo().references()? {
+        if let Some(peer) = reference?.name().and_then(identity_pins::peer_of_ref) {
+            with_refs.insert(peer);
+        }
+    }
+    let (type_references, _) = monorepo.time_type_references()?;
+
+    monorepo.cache().clear()?;
+    let mut samples = Vec::new();
+    for id in issues
+        .iter()
+        .filter_map(|i| monorepo.issue_object_id(i.number))
+        .take(retrievals)
+    {
+        let start = Instant::now();
+        monorepo.materialized_issue(&id)?;
+        samples.push(start.elapsed());
+    }
+
+    Ok(Report {
+        peers,
+        identity_creation,
+        import,
+        changes: monorepo.changes_created(),
+        peers_with_refs: with_refs.len(),
+        type_references,
+        refs: monorepo.ref_count()?,
+        retrieve: Stats::from_samples(samples),
+    })
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} peers ({} with refs, {} refs)",
+            self.peers, self.peers_with_refs, self.refs
+        )?;
+        writeln!(f, "  identity creation {:?}", self.identity_creation)?;
+        writeln!(
+            f,
+            "  import            {:?} for {} changes",
+            self.import, self.changes
+        )?;
+        writeln!(f, "  type_references   {:?}", self.type_references)?;
+        match &self.retrieve {
+            Some(stats) => writeln!(f, "  retrieve          {}", stats),
+            None => writeln!(f, "  retrieve          no objects"),
+        }
+    }
+}
@@ -1,10 +1,19 @@
-use std::collections::HashMap;
+//! The peers of a monorepo, each a secret key kept in a file of its own. Keys are random unless
+//! the monorepo was created with a seed, in which case the key of the nth peer is derived from
+//! the seed and n, so that two monorepos created with the same seed have the same peers.
+//!
+//! Peers are kept ordered by their IDs, so that everything which goes through them in order,
+//! such as the delegations of the project and the assignment of users to peers, is the same
+//! from one monorepo to the next.
+use sha2::{Digest, Sha256};
 use thiserror::Error;
 
 use link_crypto::{keystore::SecretKeyExt, PeerId, SecStr, SecretKey};
 
+use super::fs;
+
 #[derive(Debug, Error)]
-pub(crate) enum Error {
+pub enum Error {
     #[error(transparent)]
     Io(#[from] std::io::Error),
     #[error(transparent)]
@@ -12,44 +21,70 @@ pub(crate) enum Error {
 }
 
 #[derive(Debug, Error)]
-pub(crate) enum WriteError {
+pub enum WriteError {
     #[error(transparent)]
     Io(#[from] std::io::Error),
 }
 
-pub struct Peers(HashMap<link_crypto::PeerId, link_crypto::SecretKey>);
+pub struct Peers(Vec<(link_crypto::PeerId, link_crypto::SecretKey)>);
 
 impl Peers {
-    pub(crate) fn create_or_read<P: AsRef<std::path::Path>>(keydir: P) -> Result<Self, Error> {
-        if std::fs::try_exists(&keydir)? {
-            let mut keys = HashMap::new();
-            for file in std::fs::read_dir(keydir)? {
-                let bytes = std::fs::read(file?.path())?;
+    /// Read the keys in `keydir`, generating new keys, from `seed` if there is one, until there
+    /// are at least `count` of them
+    pub fn create_or_read<P: AsRef<std::path::Path>>(
+        keydir: P,
+        count: usize,
+        seed: Option<u64>,
+    ) -> Result<Self, Error> {
+        let mut keys = Vec::new();
+        if fs::exists(&keydir)? {
+            for file in fs::files(&keydir)? {
+                let bytes = fs::read(file)?;
                 let secbytes = SecStr::new(bytes);
                 let key = SecretKey::from_bytes_and_meta(secbytes, &())?;
                 let peer_id = PeerId::from(&key);
-                keys.insert(peer_id, key);
+                keys.push((peer_id, key));
             }
-            Ok(Peers(keys))
         } else {
-            std::fs::create_dir_all(&keydir)?;
-            let mut keys = HashMap::new();
-            for _ in 0..10 {
-                let key = SecretKey::new();
-                let peer_id = link_crypto::PeerId::from(&key);
-                let filename = keydir.as_ref().join(peer_id.to_string());
-                std::fs::write(filename, &key)?;
-                keys.insert(peer_id, key);
-            }
-            Ok(Peers(keys))
+            fs::create_dir_all(&keydir)?;
+        }
+        while keys.len() < count {
+            let key = match seed {
+                Some(seed) => seeded_key(seed, keys.len())?,
+                None => SecretKey::new(),
+            };
+            let peer_id = link_crypto::PeerId::from(&key);
+            let filename = keydir.as_ref().join(fs::file_name(&peer_id.to_string()));
+            fs::write(filename, &key)?;
+            keys.push((peer_id, key));
         }
+        keys.sort_by_key(|(peer_id, _)| peer_id.to_string());
+        Ok(Peers(keys))
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
     }
 
-    pub(crate) fn iter(&self) -> impl Iterator<Item = (&PeerId, &SecretKey)> {
-        self.0.iter()
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
     }
 
-    pub(crate) fn some_peer(&self) -> &PeerId {
-        self.0.iter().next().unwrap().0
+    /// The peers in the order of their IDs
+    pub fn iter(&self) -> impl Iterator<Item = (&PeerId, &SecretKey)> {
+        self.0.iter().map(|(peer_id, key)| (peer_id, key))
     }
+
+    pub fn some_peer(&self) -> &PeerId {
+        &self.0[0].0
+    }
+}
+
+/// The key of the `index`th peer of a monorepo created with `seed`
+fn seeded_key(seed: u64, index: usize) -> Result<SecretKey, Error> {
+    let mut hasher = Sha256::new();
+    hasher.update(seed.to_le_bytes());
+    hasher.update((index as u64).to_le_bytes());
+    let bytes = SecStr::new(hasher.finalize().to_vec());
+    Ok(SecretKey::from_bytes_and_meta(bytes, &())?)
 }
@@ -0,0 +1,111 @@
+//! The GitHub profiles of the users who wrote the downloaded issues, comments and events, kept in
+//! `profiles.json` in the repository's storage root. They are downloaded separately from the
+//! issues, by `download-profiles`, as each user is fetched once however many issues they wrote,
+//! and are used to give the identities of the peers of the monorepo the names of the users
+//! assigned to them, see [`crate::lite_monorepo::LiteMonorepo::apply_profiles`].
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+use crate::{
+    download::{IssueStorage, LoadError},
+    graphql::{self, GithubProfile},
+    GithubUserId,
+};
+
+pub const PROFILES: &str = "profiles.json";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error("failed to download the profile of {login}: {source}")]
+    Download {
+        login: String,
+        source: graphql::Error,
+    },
+}
+
+pub struct Profiles {
+    path: PathBuf,
+    by_login: HashMap<String, GithubProfile>,
+}
+
+impl Profiles {
+    /// The profiles saved at `path`, or none if there is nothing there
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Profiles, Error> {
+        let by_login = if crate::fs::exists(&path)? {
+            serde_json::from_slice(&crate::fs::read(&path)?)?
+        } else {
+            HashMap::new()
+        };
+        Ok(Profiles {
+            path: path.as_ref().to_path_buf(),
+            by_login,
+        })
+    }
+
+    pub fn get(&self, user: &GithubUserId) -> Option<&GithubProfile> {
+        self.by_login.get(&user.0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_login.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_login.is_empty()
+    }
+
+    fn save(&self) -> Result<(), Error> {
+        crate::fs::write_atomic(&self.path, serde_json::to_vec(&self.by_login)?)?;
+        Ok(())
+    }
+}
+
+/// Download the profile of every user of the issues in `storage` which hasn't been downloaded
+/// yet, saving them as they arrive so that an interrupted download picks up where it left off.
+/// Returns the number of profiles downloaded.
+pub async fn download(
+    client: &graphql::Client,
+    storage: &dyn IssueStorage,
+    profiles: &mut Profiles,
+) -> Result<usize, Error> {
+    let mut logins = BTreeSet::new();
+    for issue in storage.issues()? {
+        logins.extend(issue.author_id.iter().map(|a| a.0.clone()));
+        for comment in &issue.comments {
+            logins.extend(comment.author_id.iter().map(|a| a.0.clone()));
+        }
+        for event in &issue.events {
+            logins.extend(event.actor_id.iter().map(|a| a.0.clone()));
+        }
+    }
+    let mut downloaded = 0;
+    for login in logins {
+        if profiles.by_login.contains_key(&login) {
+            continue;
+        }
+        let profile = graphql::profile(client, &login)
+            .await
+            .map_err(|source| Error::Download {
+                login: login.clone(),
+                source,
+            })?;
+        verbose!("downloaded the profile of {}", login);
+        profiles.by_login.insert(login, profile);
+        downloaded += 1;
+        if downloaded % 100 == 0 {
+            profiles.save()?;
+        }
+    }
+    profiles.save()?;
+    Ok(downloaded)
+}
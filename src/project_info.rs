@@ -0,0 +1,66 @@
+//! A project identity of the monorepo, by default "theproject", the one it was created with and
+//! which every imported object belongs to. Projects delegate to the key of every peer the
+//! monorepo had when they were created, until delegates are added or removed with `edit-project`.
+use either::Either;
+use link_crypto::PeerId;
+use link_identities::Project;
+
+use crate::lite_monorepo::LiteMonorepo;
+
+pub struct ProjectInfo {
+    /// The name of the project among the projects of the monorepo
+    pub name: String,
+    pub project: Project,
+    /// The identity document, as JSON
+    pub document: String,
+    /// The key of each delegation which is a key, and whether it is one of the monorepo's peers
+    pub keys: Vec<(PeerId, bool)>,
+    /// The URN of each delegation which is a person
+    pub persons: Vec<String>,
+}
+
+impl ProjectInfo {
+    pub fn of(monorepo: &LiteMonorepo) -> Result<ProjectInfo, serde_json::Error> {
+        let project = monorepo.project().clone();
+        let document = serde_json::to_string_pretty(&project.doc)?;
+        let peers: Vec<&PeerId> = monorepo.peer_ids().collect();
+        let mut keys = Vec::new();
+        let mut persons = Vec::new();
+        for delegation in project.delegations().iter() {
+            match delegation {
+                Either::Left(key) => {
+                    let peer = PeerId::from(*key);
+                    keys.push((peer, peers.contains(&&peer)));
+                }
+                Either::Right(person) => persons.push(person.urn().to_string()),
+            }
+        }
+        Ok(ProjectInfo {
+            name: monorepo.project_name().to_string(),
+            project,
+            document,
+            keys,
+            persons,
+        })
+    }
+}
+
+impl std::fmt::Display for ProjectInfo {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "project: {} {}", self.name, self.project.urn())?;
+        writeln!(f, "revision: {}", self.project.content_id)?;
+        writeln!(f, "document:\n{}", self.document)?;
+        writeln!(f, "{} delegations:", self.keys.len() + self.persons.len())?;
+        for (peer, local) in &self.keys {
+            if *local {
+                writeln!(f, "  {}", peer)?;
+            } else {
+                writeln!(f, "  {} (not a peer of the monorepo)", peer)?;
+            }
+        }
+        for person in &self.persons {
+            writeln!(f, "  {}", person)?;
+        }
+        Ok(())
+    }
+}
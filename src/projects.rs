@@ -0,0 +1,86 @@
+//! The projects of a monorepo, by name, kept in `projects.json` in its root. Every monorepo has
+//! the project it was created with, "theproject", whose revision is also kept in `project_oid`;
+//! others are added with `create-project`. Commands which retrieve or list objects work with
+//! the first project unless they are given another with `--project`, by name or by URN.
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use thiserror::Error;
+
+pub const PROJECTS: &str = "projects.json";
+
+/// The name of the project every monorepo is created with
+pub const DEFAULT_PROJECT: &str = "theproject";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Entry {
+    pub urn: String,
+    /// The latest revision of the project identity
+    pub oid: radicle_git_ext::Oid,
+}
+
+pub struct Projects {
+    path: PathBuf,
+    by_name: BTreeMap<String, Entry>,
+}
+
+impl Projects {
+    /// The projects registered at `path`, or none if there is nothing there
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Projects, Error> {
+        let by_name = if crate::fs::exists(&path)? {
+            serde_json::from_slice(&crate::fs::read(&path)?)?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Projects {
+            path: path.as_ref().to_path_buf(),
+            by_name,
+        })
+    }
+
+    /// The name and entry of the project called `name_or_urn`, or with that URN
+    pub fn find(&self, name_or_urn: &str) -> Option<(&str, &Entry)> {
+        self.by_name
+            .iter()
+            .find(|(name, entry)| *name == name_or_urn || entry.urn == name_or_urn)
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.by_name.contains_key(name)
+    }
+
+    /// Register the latest revision of the project called `name`, replacing any earlier one
+    pub fn register(
+        &mut self,
+        name: &str,
+        project: &link_identities::Project,
+    ) -> Result<(), Error> {
+        self.by_name.insert(
+            name.to_string(),
+            Entry {
+                urn: project.urn().to_string(),
+                oid: project.content_id,
+            },
+        );
+        crate::fs::write_atomic(&self.path, serde_json::to_vec_pretty(&self.by_name)?)?;
+        Ok(())
+    }
+
+    /// The projects in the order of their names
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Entry)> {
+        self.by_name
+            .iter()
+            .map(|(name, entry)| (name.as_str(), entry))
+    }
+}
@@ -0,0 +1,166 @@
+//! The size of the ref advertisement a node sends at the start of every fetch. In the git protocol
+//! every ref is advertised as a pkt-line holding its target and name, so with a ref per peer per
+//! object the advertisement grows with the product of the number of peers and objects.
+//!
+//! The growth is estimated from the monorepo by measuring the advertisement of subsets of it:
+//! keeping the refs of the first N peers (or objects) and dropping the rest. Refs which belong
+//! to neither a peer nor an object, such as the project identity, are always advertised.
+use std::collections::BTreeSet;
+
+use crate::{identity_pins, replication};
+
+/// The size of the pkt-line advertising `name`: a four byte length, the hex object ID, a space,
+/// the name and a newline
+fn pkt_line_len(name: &str) -> u64 {
+    (4 + 40 + 1 + name.len() + 1) as u64
+}
+
+struct Ref {
+    name: String,
+    peer: Option<String>,
+    object: Option<String>,
+}
+
+/// The advertisement of one subset of the refs
+#[derive(Debug, Clone, Copy)]
+pub struct Sample {
+    pub peers: usize,
+    pub objects: usize,
+    pub refs: usize,
+    pub cob_refs: usize,
+    pub bytes: u64,
+}
+
+impl Sample {
+    fn of<'a, I: Iterator<Item = &'a Ref>>(refs: I, peers: usize, objects: usize) -> Sample {
+        let mut sample = Sample {
+            peers,
+            objects,
+            refs: 0,
+            cob_refs: 0,
+            bytes: 0,
+        };
+        for r in refs {
+            sample.refs += 1;
+            if r.object.is_some() {
+                sample.cob_refs += 1;
+            }
+            sample.bytes += pkt_line_len(&r.name);
+        }
+        sample
+    }
+}
+
+pub struct Report {
+    /// The whole advertisement
+    pub total: Sample,
+    /// The advertisement with every object and a growing number of peers
+    pub by_peers: Vec<Sample>,
+    /// The advertisement with every peer and a growing number of objects
+    pub by_objects: Vec<Sample>,
+}
+
+/// Measure the ref advertisement of `repo`, and of `steps` evenly sized subsets of its peers and
+/// objects
+pub fn measure(repo: &git2::Repository, steps: usize) -> Result<Report, git2::Error> {
+    let mut refs = Vec::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        let name = match reference.name() {
+            Some(name) => name.to_string(),
+            None => continue,
+        };
+        refs.push(Ref {
+            peer: identity_pins::peer_of_ref(&name).map(|p| p.to_string()),
+            object: replication::object_of_ref(&name).map(|o| o.to_string()),
+            name,
+        });
+    }
+    let peers: Vec<&String> = refs
+        .iter()
+        .filter_map(|r| r.peer.as_ref())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+    let objects: Vec<&String> = refs
+        .iter()
+        .filter_map(|r| r.object.as_ref())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    let counts = |len: usize| -> Vec<usize> {
+        let steps = steps.max(1);
+        let mut counts: Vec<usize> = (1..=steps).map(|i| (len * i + steps - 1) / steps).collect();
+        counts.dedup();
+        counts
+    };
+    let by_peers = counts(peers.len())
+        .into_iter()
+        .map(|n| {
+            let kept: BTreeSet<&String> = peers[..n].iter().copied().collect();
+            let advertised = refs
+                .iter()
+                .filter(|r| r.peer.as_ref().map_or(true, |p| kept.contains(p)));
+            Sample::of(advertised, n, objects.len())
+        })
+        .collect();
+    let by_objects = counts(objects.len())
+        .into_iter()
+        .map(|n| {
+            let kept: BTreeSet<&String> = objects[..n].iter().copied().collect();
+            let advertised = refs
+                .iter()
+                .filter(|r| r.object.as_ref().map_or(true, |o| kept.contains(o)));
+            Sample::of(advertised, peers.len(), n)
+        })
+        .collect();
+    Ok(Report {
+        total: Sample::of(refs.iter(), peers.len(), objects.len()),
+        by_peers,
+        by_objects,
+    })
+}
+
+impl std::fmt::Display for Sample {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:>8} {:>8} {:>10} {:>10} {:>14}",
+            self.peers, self.objects, self.refs, self.cob_refs, self.bytes
+        )
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let header = format!(
+            "{:>8} {:>8} {:>10} {:>10} {:>14}",
+            "peers", "objects", "refs", "cob refs", "bytes"
+        );
+        writeln!(f, "whole advertisement:")?;
+        writeln!(f, "{}", header)?;
+        writeln!(f, "{}", self.total)?;
+        writeln!(f, "growing the number of peers:")?;
+        writeln!(f, "{}", header)?;
+        for sample in &self.by_peers {
+            writeln!(f, "{}", sample)?;
+        }
+        writeln!(f, "growing the number of objects:")?;
+        writeln!(f, "{}", header)?;
+        for sample in &self.by_objects {
+            writeln!(f, "{}", sample)?;
+        }
+        if self.total.cob_refs > 0 && self.total.objects > 0 {
+            let per_ref = self.total.bytes as f64 / self.total.refs as f64;
+            let refs_per_object = self.total.cob_refs as f64 / self.total.objects as f64;
+            writeln!(
+                f,
+                "each object adds {:.1} refs and {:.0} bytes to the advertisement",
+                refs_per_object,
+                refs_per_object * per_ref
+            )?;
+        }
+        Ok(())
+    }
+}
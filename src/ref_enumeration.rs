@@ -0,0 +1,135 @@
+//! A microbenchmark of enumerating the objects of a type, which every listing of issues starts
+//! with. `PeerRefsStorage::type_references` used to read every ref in the repository and match
+//! its name against a regex compiled on each call, which dominates listing once there are
+//! hundreds of thousands of object refs. It now asks libgit2 for only the refs under the
+//! namespace and type name with a glob, and parses them with regexes compiled once.
+//!
+//! Both are run against the monorepo's refs in turn, the old way reimplemented here, and each is
+//! timed over a number of iterations. The objects and the number of refs found for each are
+//! compared, to check that the two agree.
+use std::{collections::HashMap, str::FromStr, time::Instant};
+
+use cob::{ObjectId, RefsStorage, TypeName};
+use link_identities::git::Urn;
+use thiserror::Error;
+
+use crate::{
+    bench::Stats,
+    lite_monorepo::LiteMonorepo,
+    peer_refs_storage::{self, RefLayout},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    PeerRefs(#[from] peer_refs_storage::Error),
+}
+
+pub struct Report {
+    /// Every ref in the repository
+    pub refs: usize,
+    pub objects: usize,
+    /// Reading every ref and matching it against a regex
+    pub scan: Option<Stats>,
+    /// Reading the refs under a glob
+    pub glob: Option<Stats>,
+    /// Whether the two found different objects or refs
+    pub mismatched: bool,
+}
+
+/// Enumerate the issues of `monorepo` `iterations` times each way
+pub fn bench(monorepo: &LiteMonorepo, iterations: usize) -> Result<Report, Error> {
+    let repo = monorepo.repo();
+    let urn = monorepo.project_urn();
+    let typename = monorepo.typename().clone();
+    let peer = match monorepo.peer_ids().next() {
+        Some(peer) => *peer,
+        None => return Err(git2::Error::from_str("the monorepo has no peers").into()),
+    };
+    let storage = monorepo.refs_storage(peer);
+
+    let mut scanned = HashMap::new();
+    let mut scan_times = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        scanned = scan(repo, monorepo.ref_layout(), &urn, &typename)?;
+        scan_times.push(start.elapsed());
+    }
+
+    let mut globbed = HashMap::new();
+    let mut glob_times = Vec::with_capacity(iterations);
+    for _ in 0..iterations {
+        let start = Instant::now();
+        let refs = storage.type_references(&urn, &typename)?;
+        glob_times.push(start.elapsed());
+        globbed = refs
+            .into_iter()
+            .map(|(oid, refs)| (oid, refs.local.iter().count() + refs.remote.len()))
+            .collect();
+    }
+
+    let mut refs = 0;
+    for reference in repo.references()? {
+        reference?;
+        refs += 1;
+    }
+    Ok(Report {
+        refs,
+        objects: globbed.len(),
+        scan: Stats::from_samples(scan_times),
+        glob: Stats::from_samples(glob_times),
+        mismatched: iterations > 0 && scanned != globbed,
+    })
+}
+
+/// The number of refs of each object of `typename`, found as `type_references` used to find them
+fn scan(
+    repo: &git2::Repository,
+    layout: RefLayout,
+    urn: &Urn,
+    typename: &TypeName,
+) -> Result<HashMap<ObjectId, usize>, Error> {
+    let regex_str = match layout {
+        RefLayout::PerPeer => format!(
+            r"refs/namespaces/{}/refs/remotes/([0-9a-zA-Z]+)/cob/{}/(?P<oid>[0-9a-f]{{40}})",
+            urn.encode_id(),
+            typename
+        ),
+        RefLayout::Flat => format!(
+            r"refs/namespaces/{}/refs/cob/{}/(?P<oid>[0-9a-f]{{40}})/([0-9a-zA-Z]+)",
+            urn.encode_id(),
+            typename
+        ),
+    };
+    let regex = regex::Regex::new(&regex_str).unwrap();
+    let mut result = HashMap::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        if let Some(caps) = reference.name().and_then(|n| regex.captures(n)) {
+            let oid = ObjectId::from_str(&caps["oid"]).unwrap();
+            *result.entry(oid).or_insert(0) += 1;
+        }
+    }
+    Ok(result)
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "{} refs, {} objects", self.refs, self.objects)?;
+        if let (Some(scan), Some(glob)) = (&self.scan, &self.glob) {
+            writeln!(f, "scan every ref: {}", scan)?;
+            writeln!(f, "glob:           {}", glob)?;
+            writeln!(
+                f,
+                "glob is {:.1}x faster at p50",
+                scan.p50.as_secs_f64() / glob.p50.as_secs_f64().max(f64::EPSILON)
+            )?;
+        }
+        if self.mismatched {
+            writeln!(f, "the two found different objects or refs")?;
+        }
+        Ok(())
+    }
+}
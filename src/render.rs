@@ -0,0 +1,137 @@
+//! Rendering change graphs to images with graphviz's `dot`, which has to be installed, for
+//! including in reports. The graphs come from `cob::changegraph_info_for_object` in dot format,
+//! as `issue-change-graph-info --just-graphviz` prints them.
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+use crate::{
+    lite_monorepo::{error, LiteMonorepo},
+    replication,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("could not run dot, is graphviz installed? {0}")]
+    NoDot(std::io::Error),
+    #[error("dot failed: {0}")]
+    Dot(String),
+    #[error("can't tell the format to render {0} in, expected a .png or .svg file")]
+    UnknownFormat(String),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Png,
+    Svg,
+}
+
+#[derive(Debug, Error)]
+#[error("expected png or svg")]
+pub struct ParseFormatError {}
+
+impl FromStr for Format {
+    type Err = ParseFormatError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "png" => Ok(Format::Png),
+            "svg" => Ok(Format::Svg),
+            _ => Err(ParseFormatError {}),
+        }
+    }
+}
+
+impl Format {
+    /// The format of an image at `path`, from its extension
+    pub fn of_path(path: &Path) -> Result<Format, Error> {
+        path.extension()
+            .and_then(|e| e.to_str())
+            .and_then(|e| e.parse().ok())
+            .ok_or_else(|| Error::UnknownFormat(path.display().to_string()))
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Format::Png => "png",
+            Format::Svg => "svg",
+        }
+    }
+}
+
+/// Render the graph in `dot` format to an image at `path`
+pub fn render(dot: &str, format: Format, path: &Path) -> Result<(), Error> {
+    let mut child = Command::new("dot")
+        .arg(format!("-T{}", format.extension()))
+        .arg("-o")
+        .arg(path)
+        .stdin(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(Error::NoDot)?;
+    // Dropped straight away so that dot sees the end of its input
+    child.stdin.take().unwrap().write_all(dot.as_bytes())?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        return Err(Error::Dot(
+            String::from_utf8_lossy(&output.stderr).trim().to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Render the change graph of `object_id` to an image at `path`, returning false if there is no
+/// such object
+pub fn render_object(
+    monorepo: &LiteMonorepo,
+    object_id: &cob::ObjectId,
+    path: &Path,
+) -> Result<bool, Error> {
+    let format = Format::of_path(path)?;
+    match monorepo.issue_info(object_id)? {
+        Some(info) => {
+            render(&info.dotviz, format, path)?;
+            Ok(true)
+        }
+        None => Ok(false),
+    }
+}
+
+/// Render the change graph of every object to `<object id>.<format>` in `dir`, calling
+/// `on_rendered` with the path of each image, returning how many were rendered
+pub fn render_all<F>(
+    monorepo: &LiteMonorepo,
+    dir: &Path,
+    format: Format,
+    mut on_rendered: F,
+) -> Result<usize, Error>
+where
+    F: FnMut(&Path),
+{
+    crate::fs::create_dir_all(dir)?;
+    let mut ids: Vec<cob::ObjectId> = replication::object_ids(monorepo.repo())?
+        .into_iter()
+        .collect();
+    ids.sort_by_key(|id| id.to_string());
+    let mut rendered = 0;
+    for id in ids {
+        if let Some(info) = monorepo.issue_info(&id)? {
+            let path = dir.join(format!("{}.{}", id, format.extension()));
+            render(&info.dotviz, format, &path)?;
+            on_rendered(&path);
+            rendered += 1;
+        }
+    }
+    Ok(rendered)
+}
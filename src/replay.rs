@@ -0,0 +1,174 @@
+//! Replaying the creates and updates recorded in the audit log of a monorepo (see
+//! `crate::audit`) against a fresh replica of it, to reproduce a performance anomaly or a bug
+//! seen during an import without rerunning the import. The replica has the same peers,
+//! identities and project as the source, and each operation is made by the same peer with the
+//! same payload, the change the source wrote, read back from the source's git. Objects get new
+//! IDs in the replica, so updates are made to whichever object their object's create became.
+//!
+//! Operations which failed in the source, and updates to objects whose create isn't in the log,
+//! are skipped. The replica records its own audit log, so the two can be compared with
+//! `audit show`, and the report lists the operations which took longest to replay beside how
+//! long they took originally.
+use std::{
+    collections::HashMap,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use link_crypto::PeerId;
+use thiserror::Error;
+
+use crate::{
+    audit::{Entry, Operation},
+    lite_monorepo::LiteMonorepo,
+    replication,
+};
+
+/// The number of slowest operations reported
+const SLOWEST: usize = 10;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error("invalid peer ID {0}")]
+    PeerId(String),
+}
+
+/// One operation replayed
+pub struct Step {
+    /// The position of the operation in the audit log
+    pub index: usize,
+    pub entry: Entry,
+    pub replayed: Duration,
+    /// Why the operation failed when it was replayed, if it did
+    pub error: Option<String>,
+}
+
+#[derive(Default)]
+pub struct Report {
+    pub replayed: usize,
+    /// Operations which failed in the source or whose object wasn't created in the log
+    pub skipped: usize,
+    /// Operations which succeeded in the source but failed when replayed
+    pub failed: Vec<Step>,
+    /// How long the replayed operations took in the source and in the replica
+    pub original: Duration,
+    pub replay: Duration,
+    pub slowest: Vec<Step>,
+}
+
+/// Replay `entries`, from the audit log of `source`, against a new replica of `source` at
+/// `replica_root`, replacing anything already there
+pub fn replay(
+    source: &LiteMonorepo,
+    entries: &[Entry],
+    replica_root: &Path,
+) -> Result<Report, Error> {
+    let mut replica = replication::create_replica(source, replica_root)?;
+    let mut report = Report::default();
+    let mut steps = Vec::new();
+    // The object each object of the source became in the replica
+    let mut objects: HashMap<String, cob::ObjectId> = HashMap::new();
+    for (index, entry) in entries.iter().enumerate() {
+        let commit = match (&entry.commit, &entry.error) {
+            (Some(commit), None) => git2::Oid::from_str(commit)?,
+            _ => {
+                report.skipped += 1;
+                continue;
+            }
+        };
+        let target = match entry.operation {
+            Operation::Create => None,
+            Operation::Update => match entry.object_id.as_ref().and_then(|id| objects.get(id)) {
+                Some(id) => Some(*id),
+                None => {
+                    report.skipped += 1;
+                    continue;
+                }
+            },
+        };
+        let peer = PeerId::from_str(&entry.peer).map_err(|_| Error::PeerId(entry.peer.clone()))?;
+        let history = payload(source.repo(), commit)?;
+
+        let start = Instant::now();
+        let result = match target {
+            None => replica.replay_create(entry.issue, &peer, history),
+            Some(id) => replica.replay_update(entry.issue, &peer, &id, history),
+        };
+        let step = Step {
+            index,
+            entry: entry.clone(),
+            replayed: start.elapsed(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+        };
+        report.replayed += 1;
+        report.original += entry.duration();
+        report.replay += step.replayed;
+        match result {
+            Ok(object) => {
+                if let (None, Some(id)) = (target, &entry.object_id) {
+                    objects.insert(id.clone(), *object.id());
+                }
+                steps.push(step);
+            }
+            Err(_) => report.failed.push(step),
+        }
+    }
+    steps.sort_by_key(|step| std::cmp::Reverse(step.replayed));
+    steps.truncate(SLOWEST);
+    report.slowest = steps;
+    Ok(report)
+}
+
+/// The change written by the commit `oid`
+fn payload(repo: &git2::Repository, oid: git2::Oid) -> Result<cob::History, git2::Error> {
+    let tree = repo.find_commit(oid)?.tree()?;
+    let entry = tree
+        .get_name("change")
+        .ok_or_else(|| git2::Error::from_str(&format!("commit {} is not a change", oid)))?;
+    let blob = repo.find_blob(entry.id())?;
+    Ok(cob::History::Automerge(blob.content().to_vec()))
+}
+
+impl std::fmt::Display for Step {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}: {:?} replayed, {:?} originally: {}",
+            self.index,
+            self.replayed,
+            self.entry.duration(),
+            self.entry
+        )?;
+        if let Some(error) = &self.error {
+            write!(f, "\n    replay failed: {}", error)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "replayed {} operations in {:?}, originally {:?}; skipped {}, {} failed",
+            self.replayed,
+            self.replay,
+            self.original,
+            self.skipped,
+            self.failed.len()
+        )?;
+        for step in &self.failed {
+            writeln!(f, "failed {}", step)?;
+        }
+        writeln!(f, "slowest:")?;
+        for step in &self.slowest {
+            writeln!(f, "  {}", step)?;
+        }
+        Ok(())
+    }
+}
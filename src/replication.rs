@@ -0,0 +1,297 @@
+//! A rough simulation of replicating collaborative objects between monorepos. A replica starts
+//! out with the same peers, project and identities as the monorepo it replicates but none of its
+//! objects. Objects then arrive as deliveries of ref updates: the commit a peer's ref for an
+//! object points at, along with every git object reachable from it which the replica doesn't
+//! have yet, much like a fetch. As in librad a delivered ref only ever fast-forwards, so updates
+//! which arrive late or more than once are ignored.
+//!
+//! Replicas are on the local disk, so deliveries can be slowed down to the speed of a real
+//! network: each delivery waits for one round trip to negotiate what to send and then for the
+//! copied objects to cross a link of limited bandwidth.
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use thiserror::Error;
+
+use crate::lite_monorepo::{error, LiteMonorepo};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    CreateOrOpen(#[from] error::CreateOrOpen),
+}
+
+/// The files in the root of a monorepo which describe its peers and project
+const NODE_FILES: &[&str] = &[
+    "peer_map",
+    "project_oid",
+    "tracking",
+    crate::devices::DEVICES,
+    crate::monorepo_config::CONFIG,
+    crate::monorepo_config::SCHEMA,
+    crate::projects::PROJECTS,
+];
+
+pub fn is_cob_ref(name: &str) -> bool {
+    name.contains("/cob/")
+}
+
+/// Create a replica of `source` at `root`, replacing anything which is already there. The replica
+/// has every ref of `source` except the refs of objects and the signed refs which describe them.
+pub fn create_replica(source: &LiteMonorepo, root: &Path) -> Result<LiteMonorepo, Error> {
+    if crate::fs::exists(root)? {
+        crate::fs::remove_dir_all(root)?;
+    }
+    let peers_dir = root.join("peers");
+    crate::fs::create_dir_all(&peers_dir)?;
+    for file in crate::fs::files(source.root().join("peers"))? {
+        if let Some(name) = file.file_name() {
+            crate::fs::copy(&file, peers_dir.join(name))?;
+        }
+    }
+    for name in NODE_FILES {
+        let path = source.root().join(name);
+        if crate::fs::exists(&path)? {
+            crate::fs::copy(&path, root.join(name))?;
+        }
+    }
+
+    let repo_dir = root.join("git");
+    crate::fs::create_dir_all(&repo_dir)?;
+    let target = git2::Repository::init_bare(&repo_dir)?;
+    for reference in source.repo().references()? {
+        let reference = reference?;
+        let (name, oid) = match (reference.name(), reference.target()) {
+            (Some(name), Some(oid)) => (name, oid),
+            _ => continue,
+        };
+        if is_cob_ref(name) || name.ends_with("/rad/signed_refs") {
+            continue;
+        }
+        copy_objects(source.repo(), &target, oid)?;
+        target.reference(name, oid, true, "create replica")?;
+    }
+    Ok(LiteMonorepo::create_or_open(root)?)
+}
+
+/// The conditions of the simulated network between a monorepo and its replicas
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Network {
+    pub rtt: Duration,
+    /// `None` for unlimited bandwidth
+    pub bits_per_sec: Option<u64>,
+}
+
+impl Network {
+    /// How long a delivery of `bytes` takes over this network
+    pub fn delivery_time(&self, bytes: u64) -> Duration {
+        let transfer = match self.bits_per_sec {
+            Some(bps) if bps > 0 => Duration::from_secs_f64(bytes as f64 * 8.0 / bps as f64),
+            _ => Duration::from_secs(0),
+        };
+        self.rtt + transfer
+    }
+}
+
+impl std::fmt::Display for Network {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "rtt {:?}", self.rtt)?;
+        match self.bits_per_sec {
+            Some(bps) => write!(f, ", {:.1} Mbit/s", bps as f64 / 1_000_000.0),
+            None => write!(f, ", unlimited bandwidth"),
+        }
+    }
+}
+
+/// An update of one peer's ref for an object
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Delivery {
+    pub reference: String,
+    pub commit: git2::Oid,
+}
+
+impl Delivery {
+    pub fn object_id(&self) -> Option<cob::ObjectId> {
+        object_of_ref(&self.reference)
+    }
+}
+
+/// The object `reference` is a ref of, if it is an object ref: `.../cob/<typename>/<oid>`, or
+/// `.../cob/<typename>/<oid>/<peer>` in the flat layout
+pub fn object_of_ref(reference: &str) -> Option<cob::ObjectId> {
+    let (_, rest) = reference.split_once("/cob/")?;
+    rest.split('/')
+        .nth(1)
+        .and_then(|id| cob::ObjectId::from_str(id).ok())
+}
+
+/// Every object with a ref in `repo`
+pub fn object_ids(repo: &git2::Repository) -> Result<HashSet<cob::ObjectId>, git2::Error> {
+    let mut ids = HashSet::new();
+    for reference in repo.references()? {
+        if let Some(id) = reference?.name().and_then(object_of_ref) {
+            ids.insert(id);
+        }
+    }
+    Ok(ids)
+}
+
+/// The current state of every object ref in `repo`, each followed by up to `history` earlier
+/// states of the object, which are the updates a replica which is behind might still receive
+pub fn deliveries(repo: &git2::Repository, history: usize) -> Result<Vec<Delivery>, Error> {
+    let mut deliveries = Vec::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        let (name, tip) = match (reference.name(), reference.target()) {
+            (Some(name), Some(tip)) if is_cob_ref(name) => (name, tip),
+            _ => continue,
+        };
+        deliveries.push(Delivery {
+            reference: name.to_string(),
+            commit: tip,
+        });
+        if history > 0 {
+            let mut walk = repo.revwalk()?;
+            walk.set_sorting(git2::Sort::TOPOLOGICAL)?;
+            walk.push(tip)?;
+            for commit in walk.skip(1).take(history) {
+                deliveries.push(Delivery {
+                    reference: name.to_string(),
+                    commit: commit?,
+                });
+            }
+        }
+    }
+    Ok(deliveries)
+}
+
+/// What a delivery did to the replica's ref
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RefUpdate {
+    Created,
+    FastForwarded,
+    /// The ref already pointed at the delivered commit
+    AlreadyCurrent,
+    /// The ref already pointed at a descendant of (or a commit concurrent with) the delivered
+    /// commit, so the delivery was ignored
+    Stale,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct CopyStats {
+    pub copied: usize,
+    pub bytes: u64,
+    /// Objects which were looked up but which the replica already had
+    pub present: usize,
+}
+
+impl std::ops::AddAssign for CopyStats {
+    fn add_assign(&mut self, other: CopyStats) {
+        self.copied += other.copied;
+        self.bytes += other.bytes;
+        self.present += other.present;
+    }
+}
+
+pub struct Delivered {
+    pub update: RefUpdate,
+    pub objects: CopyStats,
+}
+
+/// Copy the objects of `delivery` from `source` into `target` over `network` and update the ref
+/// if it fast forwards
+pub fn deliver(
+    source: &git2::Repository,
+    target: &git2::Repository,
+    delivery: &Delivery,
+    network: &Network,
+) -> Result<Delivered, Error> {
+    let objects = copy_objects(source, target, delivery.commit)?;
+    std::thread::sleep(network.delivery_time(objects.bytes));
+    let update = match target.find_reference(&delivery.reference) {
+        Ok(mut reference) => match reference.target() {
+            Some(current) if current == delivery.commit => RefUpdate::AlreadyCurrent,
+            Some(current) if target.graph_descendant_of(delivery.commit, current)? => {
+                reference.set_target(delivery.commit, "replicate")?;
+                RefUpdate::FastForwarded
+            }
+            _ => RefUpdate::Stale,
+        },
+        Err(e) if e.code() == git2::ErrorCode::NotFound => {
+            target.reference(&delivery.reference, delivery.commit, false, "replicate")?;
+            RefUpdate::Created
+        }
+        Err(e) => return Err(e.into()),
+    };
+    Ok(Delivered { update, objects })
+}
+
+/// Copy `oid` and everything reachable from it which `target` doesn't have from `source`. An
+/// object which `target` already has is assumed to come with everything reachable from it, as
+/// it would after a fetch.
+pub fn copy_objects(
+    source: &git2::Repository,
+    target: &git2::Repository,
+    oid: git2::Oid,
+) -> Result<CopyStats, git2::Error> {
+    let source_odb = source.odb()?;
+    let target_odb = target.odb()?;
+    let mut stats = CopyStats::default();
+    let mut seen = HashSet::new();
+    // Each object is pushed a second time beneath its dependencies so that it is written after
+    // them
+    let mut stack = vec![(oid, false)];
+    while let Some((oid, dependencies_copied)) = stack.pop() {
+        if dependencies_copied {
+            let object = source_odb.read(oid)?;
+            target_odb.write(object.kind(), object.data())?;
+            stats.copied += 1;
+            stats.bytes += object.len() as u64;
+            continue;
+        }
+        if !seen.insert(oid) {
+            continue;
+        }
+        if target_odb.exists(oid) {
+            stats.present += 1;
+            continue;
+        }
+        stack.push((oid, true));
+        let (_, kind) = source_odb.read_header(oid)?;
+        match kind {
+            git2::ObjectType::Commit => {
+                let commit = source.find_commit(oid)?;
+                stack.push((commit.tree_id(), false));
+                stack.extend(commit.parent_ids().map(|p| (p, false)));
+            }
+            git2::ObjectType::Tree => {
+                let tree = source.find_tree(oid)?;
+                stack.extend(
+                    tree.iter()
+                        .filter(|e| {
+                            matches!(
+                                e.kind(),
+                                Some(git2::ObjectType::Tree) | Some(git2::ObjectType::Blob)
+                            )
+                        })
+                        .map(|e| (e.id(), false)),
+                );
+            }
+            _ => {}
+        }
+    }
+    Ok(stats)
+}
+
+/// The directory in a repository's storage root which replicas of its monorepo are kept in
+pub fn replicas_dir(storage_root: &Path) -> PathBuf {
+    storage_root.join("replicas")
+}
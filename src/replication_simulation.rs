@@ -0,0 +1,231 @@
+//! Replicating between several monorepos which each imported different issues. Every node is a
+//! replica of the repository's monorepo, so they share its peers, project and identities but
+//! start without objects. The downloaded issues are dealt out between the nodes and each imports
+//! its share. The cob refs of every node are then merged into a hub, copying the git objects
+//! reachable from each ref much like a fetch and creating the ref with
+//! `PeerRefsStorage::receive_ref`, and the hub's refs are merged back into every node.
+//!
+//! Afterwards every node and the hub should have every object, and retrieving an object should
+//! give the same document everywhere as it did on the node which imported it.
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{
+    downloaded_issue::DownloadedIssue,
+    identity_pins,
+    lite_monorepo::{error, LiteMonorepo},
+    peer_refs_storage,
+    replication::{self, CopyStats, RefUpdate},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    PeerRefs(#[from] peer_refs_storage::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+}
+
+/// The work done merging the refs of one monorepo into another
+#[derive(Debug, Default)]
+pub struct Merge {
+    pub refs: usize,
+    pub time: Duration,
+    pub objects: CopyStats,
+    pub updates: HashMap<RefUpdate, usize>,
+}
+
+impl Merge {
+    fn add(&mut self, other: Merge) {
+        self.refs += other.refs;
+        self.time += other.time;
+        self.objects += other.objects;
+        for (update, count) in other.updates {
+            *self.updates.entry(update).or_default() += count;
+        }
+    }
+}
+
+pub struct Report {
+    pub nodes: usize,
+    pub issues: usize,
+    pub import: Duration,
+    /// Merging every node into the hub
+    pub into_hub: Merge,
+    /// Merging the hub back into every node
+    pub from_hub: Merge,
+    /// The number of objects imported across the nodes
+    pub objects: usize,
+    /// Objects which a node or the hub couldn't retrieve, with the node, `None` for the hub
+    pub missing: Vec<(Option<usize>, cob::ObjectId)>,
+    /// Objects whose document on a node or the hub differs from the one on the node which
+    /// imported it
+    pub mismatched: Vec<(Option<usize>, cob::ObjectId)>,
+}
+
+impl Report {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.mismatched.is_empty()
+    }
+}
+
+/// The directory the node `n` is kept in under `dir`
+fn node_dir(dir: &Path, n: usize) -> PathBuf {
+    dir.join(format!("node-{}", n))
+}
+
+/// Import `issues` into `nodes` replicas of `source` in `dir`, replacing any left by an earlier
+/// run, and replicate between them through a hub
+pub fn run(
+    source: &LiteMonorepo,
+    dir: &Path,
+    issues: &[DownloadedIssue],
+    nodes: usize,
+) -> Result<Report, Error> {
+    let nodes = nodes.max(1);
+    let mut replicas = Vec::new();
+    for n in 0..nodes {
+        replicas.push(replication::create_replica(source, &node_dir(dir, n))?);
+    }
+    let start = Instant::now();
+    for (i, issue) in issues.iter().enumerate() {
+        replicas[i % nodes].import_issue(issue)?;
+    }
+    let import = start.elapsed();
+
+    let mut originals = Vec::new();
+    for (n, replica) in replicas.iter().enumerate() {
+        for id in replica.list_issue_ids()? {
+            originals.push((n, id, replica.retrieve_issue(&id, false)?));
+        }
+    }
+
+    let hub = replication::create_replica(source, &dir.join("hub"))?;
+    let mut into_hub = Merge::default();
+    for replica in &replicas {
+        into_hub.add(merge(replica, &hub)?);
+    }
+    let mut from_hub = Merge::default();
+    for replica in &replicas {
+        from_hub.add(merge(&hub, replica)?);
+    }
+
+    let mut report = Report {
+        nodes,
+        issues: issues.len(),
+        import,
+        into_hub,
+        from_hub,
+        objects: originals.len(),
+        missing: Vec::new(),
+        mismatched: Vec::new(),
+    };
+    let everywhere =
+        std::iter::once((None, &hub)).chain(replicas.iter().enumerate().map(|(n, r)| (Some(n), r)));
+    for (node, monorepo) in everywhere {
+        for (origin, id, original) in &originals {
+            if node == Some(*origin) {
+                continue;
+            }
+            match monorepo.retrieve_issue(id, false)? {
+                None => report.missing.push((node, *id)),
+                Some(doc) if Some(&doc) != original.as_ref() => report.mismatched.push((node, *id)),
+                Some(_) => {}
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// Copy every object ref of `from`, along with the git objects it references, into `into`
+fn merge(from: &LiteMonorepo, into: &LiteMonorepo) -> Result<Merge, Error> {
+    let typename = into.typename().clone();
+    let urn = into.project_urn();
+    let mut merge = Merge::default();
+    let start = Instant::now();
+    for reference in from.repo().references()? {
+        let reference = reference?;
+        let (name, tip) = match (reference.name(), reference.target()) {
+            (Some(name), Some(tip)) => (name, tip),
+            _ => continue,
+        };
+        let (peer, object) = match (
+            identity_pins::peer_of_ref(name),
+            replication::object_of_ref(name),
+        ) {
+            (Some(peer), Some(object)) => (peer, object),
+            _ => continue,
+        };
+        merge.objects += replication::copy_objects(from.repo(), into.repo(), tip)?;
+        let update = into
+            .refs_storage(peer)
+            .receive_ref(&urn, &typename, &object, tip)?;
+        *merge.updates.entry(update).or_default() += 1;
+        merge.refs += 1;
+    }
+    merge.time = start.elapsed();
+    Ok(merge)
+}
+
+impl std::fmt::Display for Merge {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} refs in {:?}, {} objects copied ({} bytes), {} already present",
+            self.refs, self.time, self.objects.copied, self.objects.bytes, self.objects.present
+        )?;
+        for update in &[
+            RefUpdate::Created,
+            RefUpdate::FastForwarded,
+            RefUpdate::AlreadyCurrent,
+            RefUpdate::Stale,
+        ] {
+            write!(
+                f,
+                ", {:?}: {}",
+                update,
+                self.updates.get(update).copied().unwrap_or(0)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "imported {} issues as {} objects across {} nodes in {:?}",
+            self.issues, self.objects, self.nodes, self.import
+        )?;
+        writeln!(f, "into hub:   {}", self.into_hub)?;
+        writeln!(f, "from hub:   {}", self.from_hub)?;
+        let node = |n: &Option<usize>| match n {
+            Some(n) => format!("node {}", n),
+            None => "hub".to_string(),
+        };
+        for (n, id) in &self.missing {
+            writeln!(f, "missing on {}: {}", node(n), id)?;
+        }
+        for (n, id) in &self.mismatched {
+            writeln!(f, "mismatched on {}: {}", node(n), id)?;
+        }
+        if self.is_ok() {
+            writeln!(f, "every object converged on every node")?;
+        }
+        Ok(())
+    }
+}
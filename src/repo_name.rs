@@ -6,9 +6,9 @@ use thiserror::Error;
 pub struct ParseError {}
 
 #[derive(Clone)]
-pub(crate) struct RepoName {
-    pub(crate) owner: String,
-    pub(crate) name: String,
+pub struct RepoName {
+    pub owner: String,
+    pub name: String,
 }
 
 impl FromStr for RepoName {
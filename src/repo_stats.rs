@@ -0,0 +1,246 @@
+//! How much the git repository of a monorepo holds and what it holds it for, to put a number on
+//! the storage overhead of collaborative objects per issue and per change.
+//!
+//! The objects of the object database are counted loose and packed, along with the bytes they
+//! take on disk. The objects reachable from the refs are then attributed to what the refs are
+//! for: identities (`rad/self` and `rad/id`), signed refs, or the collaborative objects of each
+//! type name (`cob/<typename>/<object id>`). An object reachable from refs of more than one kind
+//! is attributed to the first of those, in that order, so that the identity commits each change
+//! refers to count as identities rather than as changes. The bytes attributed are the
+//! uncompressed size of the objects, as git reports them, which is more than they take packed.
+use std::{
+    collections::{BTreeMap, HashSet},
+    path::Path,
+};
+
+use thiserror::Error;
+
+use crate::replication;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// The objects attributed to one kind of ref
+#[derive(Debug, Default)]
+pub struct Category {
+    pub objects: u64,
+    pub commits: u64,
+    /// Uncompressed
+    pub bytes: u64,
+    /// The collaborative objects the refs are for, for collaborative object refs
+    pub cobs: HashSet<cob::ObjectId>,
+}
+
+/// The objects in the object database and the space they take on disk
+#[derive(Debug, Default, Clone)]
+pub struct Disk {
+    pub loose_objects: u64,
+    pub loose_bytes: u64,
+    pub packs: u64,
+    pub packed_objects: u64,
+    pub pack_bytes: u64,
+    /// Everything in the git directory, including refs and indexes
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub disk: Disk,
+    /// Keyed by what the refs are for, e.g. `identities` or `cob xyz.radicle.githubissue`
+    pub categories: BTreeMap<String, Category>,
+}
+
+/// The kinds of ref, in the order objects are attributed to them
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+enum Kind {
+    Identity,
+    SignedRefs,
+    Cob(String),
+    Other,
+}
+
+impl Kind {
+    fn of(reference: &str) -> Kind {
+        if reference.ends_with("/rad/self")
+            || reference.ends_with("/rad/id")
+            || reference.contains("/rad/ids/")
+        {
+            Kind::Identity
+        } else if reference.ends_with("/rad/signed_refs") {
+            Kind::SignedRefs
+        } else if let Some((_, rest)) = reference.split_once("/cob/") {
+            match rest.split_once('/') {
+                Some((typename, _)) => Kind::Cob(typename.to_string()),
+                None => Kind::Other,
+            }
+        } else {
+            Kind::Other
+        }
+    }
+
+    fn name(&self) -> String {
+        match self {
+            Kind::Identity => "identities".to_string(),
+            Kind::SignedRefs => "signed refs".to_string(),
+            Kind::Cob(typename) => format!("cob {}", typename),
+            Kind::Other => "other refs".to_string(),
+        }
+    }
+}
+
+/// Count the objects in `repo` and the space they take, without looking at what they are
+pub fn disk(repo: &git2::Repository) -> Result<Disk, Error> {
+    let mut report = Disk::default();
+    let objects = repo.path().join("objects");
+    for entry in crate::fs::read_dir(&objects)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let name = name.to_string_lossy();
+        // Loose objects live in directories named by the first two hex digits of their ID
+        if name.len() == 2 && name.chars().all(|c| c.is_ascii_hexdigit()) {
+            for object in crate::fs::read_dir(entry.path())? {
+                report.loose_objects += 1;
+                report.loose_bytes += object?.metadata()?.len();
+            }
+        }
+    }
+    let pack_dir = objects.join("pack");
+    if crate::fs::exists(&pack_dir)? {
+        for entry in crate::fs::read_dir(&pack_dir)? {
+            let path = entry?.path();
+            match path.extension().and_then(|e| e.to_str()) {
+                Some("pack") => {
+                    report.packs += 1;
+                    report.pack_bytes += crate::fs::metadata(&path)?.len();
+                }
+                Some("idx") => report.packed_objects += pack_index_count(&path)?,
+                _ => {}
+            }
+        }
+    }
+    report.total_bytes = crate::fs::dir_size(repo.path())?;
+    Ok(report)
+}
+
+/// Count and attribute the objects in `repo`
+pub fn stats(repo: &git2::Repository) -> Result<Report, Error> {
+    let mut report = Report {
+        disk: disk(repo)?,
+        categories: BTreeMap::new(),
+    };
+    let mut tips: BTreeMap<Kind, Vec<(String, git2::Oid)>> = BTreeMap::new();
+    for reference in repo.references()? {
+        let reference = reference?;
+        let (name, target) = match (reference.name(), reference.target()) {
+            (Some(name), Some(target)) => (name, target),
+            _ => continue,
+        };
+        tips.entry(Kind::of(name))
+            .or_default()
+            .push((name.to_string(), target));
+    }
+    let odb = repo.odb()?;
+    let mut seen = HashSet::new();
+    for (kind, refs) in tips {
+        let category = report.categories.entry(kind.name()).or_default();
+        for (name, tip) in refs {
+            if let Some(id) = replication::object_of_ref(&name) {
+                category.cobs.insert(id);
+            }
+            let mut stack = vec![tip];
+            while let Some(oid) = stack.pop() {
+                if !seen.insert(oid) {
+                    continue;
+                }
+                let (len, object_type) = odb.read_header(oid)?;
+                category.objects += 1;
+                category.bytes += len as u64;
+                match object_type {
+                    git2::ObjectType::Commit => {
+                        category.commits += 1;
+                        let commit = repo.find_commit(oid)?;
+                        stack.push(commit.tree_id());
+                        stack.extend(commit.parent_ids());
+                    }
+                    git2::ObjectType::Tree => {
+                        let tree = repo.find_tree(oid)?;
+                        // Gitlinks point at commits which needn't be in this repository
+                        stack.extend(
+                            tree.iter()
+                                .filter(|e| {
+                                    matches!(
+                                        e.kind(),
+                                        Some(git2::ObjectType::Tree) | Some(git2::ObjectType::Blob)
+                                    )
+                                })
+                                .map(|e| e.id()),
+                        );
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+    Ok(report)
+}
+
+/// The number of objects in the pack index at `path`. Both versions of the format start with a
+/// table of 256 cumulative counts, after an 8 byte header in version 2, and the last is the total.
+fn pack_index_count(path: &Path) -> Result<u64, Error> {
+    let bytes = crate::fs::read(path)?;
+    let fanout = if bytes.starts_with(b"\xfftOc") { 8 } else { 0 };
+    let last = fanout + 255 * 4;
+    match bytes.get(last..last + 4) {
+        Some(count) => Ok(u32::from_be_bytes([count[0], count[1], count[2], count[3]]) as u64),
+        None => Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            format!("{} is too short to be a pack index", path.display()),
+        )
+        .into()),
+    }
+}
+
+impl std::fmt::Display for Disk {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} objects: {} loose in {} bytes, {} packed in {} packs of {} bytes",
+            self.loose_objects + self.packed_objects,
+            self.loose_objects,
+            self.loose_bytes,
+            self.packed_objects,
+            self.packs,
+            self.pack_bytes
+        )?;
+        writeln!(f, "{} bytes on disk in total", self.total_bytes)
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.disk)?;
+        writeln!(f, "reachable from refs, uncompressed:")?;
+        for (name, category) in &self.categories {
+            write!(
+                f,
+                "  {:<40} {:>8} objects {:>8} commits {:>12} bytes",
+                name, category.objects, category.commits, category.bytes
+            )?;
+            if !category.cobs.is_empty() {
+                write!(
+                    f,
+                    ", {} per object, {} per commit",
+                    category.bytes / category.cobs.len() as u64,
+                    category.bytes / category.commits.max(1)
+                )?;
+            }
+            writeln!(f)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,210 @@
+//! Packaging a single object into a bundle which can be handed to the cob developers, so that bugs
+//! found at stress scale can be reproduced without the rest of the monorepo. A reproducer is a
+//! directory containing
+//!
+//! ```
+//! ├── objects.bundle <- a git bundle of the object's refs and the identities of the project and authors
+//! ├── schema.json <- the schema of the issue type
+//! ├── manifest.json <- the object ID, type name, project URN, and the refs in the bundle
+//! └── repro_test.rs <- an integration test which unbundles, retrieves and checks the object
+//! ```
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+use crate::{
+    fs,
+    lite_monorepo::{error, LiteMonorepo},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Bundle(#[from] error::Bundle),
+}
+
+#[derive(serde::Serialize)]
+struct Manifest<'a> {
+    object_id: String,
+    typename: String,
+    project_urn: String,
+    refs: &'a [String],
+}
+
+/// Export everything needed to retrieve `object_id` into `dir`. Returns the number of refs in the
+/// bundle, or `None` if there is no such object.
+pub fn export(
+    monorepo: &LiteMonorepo,
+    object_id: &cob::ObjectId,
+    dir: &Path,
+) -> Result<Option<usize>, Error> {
+    fs::create_dir_all(dir)?;
+    let bundle_path: PathBuf = dir.join("objects.bundle");
+    let refs = match monorepo.bundle_object(object_id, &bundle_path)? {
+        Some(refs) => refs,
+        None => return Ok(None),
+    };
+    let project_urn = monorepo.project_urn();
+    fs::write(
+        dir.join("schema.json"),
+        serde_json::to_vec_pretty(monorepo.object_schema())?,
+    )?;
+    let manifest = Manifest {
+        object_id: object_id.to_string(),
+        typename: monorepo.typename().to_string(),
+        project_urn: project_urn.to_string(),
+        refs: &refs,
+    };
+    fs::write(
+        dir.join("manifest.json"),
+        serde_json::to_vec_pretty(&manifest)?,
+    )?;
+    fs::write(
+        dir.join("repro_test.rs"),
+        test_source(
+            object_id,
+            &project_urn.encode_id(),
+            monorepo.typename(),
+            &refs,
+        ),
+    )?;
+    Ok(Some(refs.len()))
+}
+
+fn test_source(
+    object_id: &cob::ObjectId,
+    project_id: &str,
+    typename: &cob::TypeName,
+    refs: &[String],
+) -> String {
+    // Every ref in the bundle other than those of identities is a ref for the object
+    let object_refs: String = refs
+        .iter()
+        .filter(|name| !name.ends_with("/rad/id"))
+        .map(|name| format!("    {:?},\n", name))
+        .collect();
+    format!(
+        r#"//! Reproducer for object {object_id}, exported from the cob stress test. `objects.bundle` next
+//! to this file contains the refs of every peer for the object along with the identities of the
+//! project and of the authors of its changes, and `schema.json` the schema of the issue type.
+use std::{{collections::HashMap, str::FromStr}};
+
+use cob::{{ObjectId, ObjectRefs, RefsStorage, TypeName}};
+use either::Either;
+use link_identities::{{git::Urn, Identities, Project}};
+
+const OBJECT_ID: &str = "{object_id}";
+
+/// The refs of every peer for the object in the bundle
+const OBJECT_REFS: &[&str] = &[
+{object_refs}];
+
+/// Refs storage which returns the bundled refs of the object, all of them as remote
+struct BundledRefs<'a> {{
+    repo: &'a git2::Repository,
+}}
+
+impl<'a> RefsStorage for BundledRefs<'a> {{
+    type Error = git2::Error;
+
+    fn update_ref(
+        &self,
+        _identity_urn: &Urn,
+        _typename: &TypeName,
+        _object_id: ObjectId,
+        _new_commit: git2::Oid,
+    ) -> Result<(), Self::Error> {{
+        Err(git2::Error::from_str("the reproducer only retrieves"))
+    }}
+
+    fn type_references<'b>(
+        &'b self,
+        identity_urn: &Urn,
+        typename: &TypeName,
+    ) -> Result<HashMap<ObjectId, ObjectRefs<'b>>, Self::Error> {{
+        let object_id = ObjectId::from_str(OBJECT_ID).unwrap();
+        let mut result = HashMap::new();
+        result.insert(
+            object_id,
+            self.object_references(identity_urn, typename, &object_id)?,
+        );
+        Ok(result)
+    }}
+
+    fn object_references<'b>(
+        &'b self,
+        _identity_urn: &Urn,
+        _typename: &TypeName,
+        oid: &ObjectId,
+    ) -> Result<ObjectRefs<'b>, Self::Error> {{
+        let mut remote = Vec::new();
+        if *oid == ObjectId::from_str(OBJECT_ID).unwrap() {{
+            for name in OBJECT_REFS {{
+                remote.push(self.repo.find_reference(name)?);
+            }}
+        }}
+        Ok(ObjectRefs {{ local: None, remote }})
+    }}
+}}
+
+#[test]
+fn repro_{short}() {{
+    let tmp = tempfile::tempdir().unwrap();
+    let repo = git2::Repository::init_bare(tmp.path().join("git")).unwrap();
+    let bundle = tmp.path().join("objects.bundle");
+    std::fs::write(&bundle, &include_bytes!("objects.bundle")[..]).unwrap();
+    let status = std::process::Command::new("git")
+        .arg("--git-dir")
+        .arg(repo.path())
+        .arg("fetch")
+        .arg(&bundle)
+        .arg("refs/*:refs/*")
+        .status()
+        .unwrap();
+    assert!(status.success());
+
+    let project_ref = repo
+        .find_reference("refs/namespaces/{project_id}/refs/rad/id")
+        .unwrap();
+    let identities: Identities<'_, Project> = (&repo).into();
+    let project = identities.get(project_ref.target().unwrap()).unwrap();
+    let typename = TypeName::from_str("{typename}").unwrap();
+    let object_id = ObjectId::from_str(OBJECT_ID).unwrap();
+
+    let storage = BundledRefs {{ repo: &repo }};
+    let object = cob::retrieve_object(
+        &storage,
+        &repo,
+        Either::Right(project),
+        &typename,
+        &object_id,
+        None,
+    )
+    .unwrap()
+    .expect("the object wasn't retrieved");
+    assert_eq!(object.id(), &object_id);
+
+    let backend = automerge::Backend::load(object.history().as_ref().to_vec()).unwrap();
+    let mut frontend = automerge::Frontend::new();
+    frontend.apply_patch(backend.get_patch().unwrap()).unwrap();
+    let document = frontend.state().to_json();
+    let schema: serde_json::Value =
+        serde_json::from_slice(include_bytes!("schema.json")).unwrap();
+    let schema = jsonschema::JSONSchema::compile(&schema).unwrap();
+    assert!(
+        schema.is_valid(&document),
+        "the document doesn't match the schema"
+    );
+}}
+"#,
+        object_id = object_id,
+        object_refs = object_refs,
+        project_id = project_id,
+        typename = typename,
+        short = &object_id.to_string()[..8],
+    )
+}
@@ -0,0 +1,117 @@
+//! Uploading benchmark results to a shared results server, so that the numbers measured on
+//! different contributors' machines accumulate in one place. A benchmark run with
+//! `--push-results <url>` POSTs its results as JSON to the URL once it has printed them, along
+//! with what is needed to compare them with results from elsewhere: the benchmark and repository,
+//! when it ran, the cob API it was built with (see `crate::cob_api`), and the machine it ran on.
+//! The server is expected to accept any JSON body and reply with a success status; nothing else
+//! about it is assumed.
+//!
+//! The machine is described by its OS, architecture, kernel, number of CPUs and memory, read from
+//! `/proc` where it is available. Nothing which identifies the machine or its user, such as the
+//! hostname, is sent.
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::cob_api;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("results server returned {status}: {body}")]
+    Status {
+        status: reqwest::StatusCode,
+        body: String,
+    },
+}
+
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Environment {
+    pub os: &'static str,
+    pub arch: &'static str,
+    pub kernel: Option<String>,
+    pub cpus: Option<usize>,
+    pub memory_bytes: Option<u64>,
+    pub cob_api: &'static str,
+    /// The version of this crate
+    pub version: &'static str,
+}
+
+impl Environment {
+    pub fn current() -> Environment {
+        Environment {
+            os: std::env::consts::OS,
+            arch: std::env::consts::ARCH,
+            kernel: crate::fs::read_to_string("/proc/sys/kernel/osrelease")
+                .ok()
+                .map(|k| k.trim().to_string()),
+            cpus: crate::fs::read_to_string("/proc/cpuinfo")
+                .ok()
+                .map(|info| info.lines().filter(|l| l.starts_with("processor")).count())
+                .filter(|n| *n > 0),
+            memory_bytes: memory_bytes(),
+            cob_api: cob_api::VERSION,
+            version: env!("CARGO_PKG_VERSION"),
+        }
+    }
+}
+
+/// The total memory of the machine, from `/proc/meminfo`
+fn memory_bytes() -> Option<u64> {
+    let info = crate::fs::read_to_string("/proc/meminfo").ok()?;
+    let line = info.lines().find(|l| l.starts_with("MemTotal:"))?;
+    let kib: u64 = line.split_whitespace().nth(1)?.parse().ok()?;
+    Some(kib * 1024)
+}
+
+/// What is uploaded for one run of a benchmark
+#[derive(Debug, Clone, serde::Serialize)]
+pub struct Submission {
+    /// The command which was run, e.g. `bench-metrics`
+    pub benchmark: String,
+    /// The GitHub repository whose issues were benchmarked
+    pub repo: String,
+    pub at: DateTime<Utc>,
+    pub environment: Environment,
+    pub results: serde_json::Value,
+}
+
+impl Submission {
+    pub fn new<R: serde::Serialize>(
+        benchmark: &str,
+        repo: &str,
+        results: &R,
+    ) -> Result<Submission, Error> {
+        Ok(Submission {
+            benchmark: benchmark.to_string(),
+            repo: repo.to_string(),
+            at: Utc::now(),
+            environment: Environment::current(),
+            results: serde_json::to_value(results)?,
+        })
+    }
+}
+
+/// POST `submission` to `url`. Called from synchronous code running on the tokio runtime, so it
+/// blocks in place as `crate::object_store` does.
+pub fn push(url: &reqwest::Url, submission: &Submission) -> Result<(), Error> {
+    let request = reqwest::Client::new()
+        .post(url.clone())
+        .header("content-type", "application/json")
+        .body(serde_json::to_vec(submission)?);
+    let (status, body) = tokio::task::block_in_place(|| {
+        tokio::runtime::Handle::current().block_on(async move {
+            let response = request.send().await?;
+            let status = response.status();
+            let body = response.text().await?;
+            Ok::<_, Error>((status, body))
+        })
+    })?;
+    if status.is_success() {
+        Ok(())
+    } else {
+        Err(Error::Status { status, body })
+    }
+}
@@ -0,0 +1,254 @@
+//! Steady state performance of a project which is years old. A freshly imported monorepo has
+//! either every object cached or none, whereas a long running one keeps the recent objects which
+//! are still being read cached and lets the rest go. This generates a corpus whose ages follow a
+//! growing project, with more issues created each year than the last and older issues more
+//! likely to be closed, imports it, and then for each retention policy caches only the newest
+//! fraction of the objects before timing listing and retrieval.
+//!
+//! The cob cache is the only per object index cob keeps, so "retaining" an object means it has a
+//! cache entry. Retrievals are spread evenly over the ages of the objects and each object is
+//! retrieved once, so that every retrieval of an object outside the retained fraction is a miss.
+//! Listing retrieves every object, filling the cache, so the cache is rebuilt for the policy
+//! before listing.
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+use rand::{Rng, SeedableRng};
+use thiserror::Error;
+
+use crate::{
+    bench::Stats,
+    cache,
+    downloaded_issue::{DownloadedComment, DownloadedIssue},
+    lite_monorepo::{error, LiteMonorepo},
+    signed_refs, GithubUserId,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Open(#[from] error::CreateOrOpen),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Cache(#[from] cache::Error),
+    #[error(transparent)]
+    SignRefs(#[from] signed_refs::Error),
+}
+
+/// The shape of the generated corpus
+pub struct CorpusOptions {
+    pub issues: usize,
+    /// How many years ago the first issue was created
+    pub years: f64,
+    /// How many times more issues are created each year than the year before
+    pub growth: f64,
+    /// The mean number of comments on each issue
+    pub comments: f64,
+    /// The number of distinct github users the issues and comments are spread across
+    pub users: usize,
+    pub seed: u64,
+}
+
+/// How listing and retrieval fared with one fraction of the objects cached
+pub struct Report {
+    /// The percentage of the objects, newest first, which were cached
+    pub retained: f64,
+    pub cached_objects: usize,
+    pub cache_bytes: u64,
+    /// Retrieving the retained objects to fill the cache
+    pub warm: Duration,
+    pub list: Duration,
+    pub retrieve_cached: Option<Stats>,
+    pub retrieve_uncached: Option<Stats>,
+}
+
+/// The issues of a project created `years` ago, numbered in the order they were created.
+/// Creation times are spread so that the number created each year grows by `growth`, comments
+/// follow their issue by days to weeks and issues older than two months are mostly closed.
+pub fn corpus(options: &CorpusOptions) -> Vec<DownloadedIssue> {
+    let mut rng = rand::rngs::StdRng::seed_from_u64(options.seed);
+    let now = Utc::now();
+    let span = chrono::Duration::seconds((options.years * 365.25 * 24.0 * 3600.0) as i64);
+    let start = now - span;
+    let rate = options.growth.max(1.0 + f64::EPSILON).ln();
+    let span_years = options.years.max(f64::EPSILON);
+    let user = |n: usize| {
+        Some(GithubUserId(format!(
+            "retention-user-{}",
+            n % options.users.max(1)
+        )))
+    };
+    (0..options.issues)
+        .map(|i| {
+            // The inverse of the CDF of issue creation, where the rate of creation grows
+            // exponentially, at evenly spaced quantiles so that issue numbers follow time
+            let q = (i as f64 + 0.5) / options.issues as f64;
+            let years = (1.0 + q * ((rate * span_years).exp() - 1.0)).ln() / rate;
+            let created_at = start + fraction_of(span, years / span_years);
+            let number = i as u64 + 1;
+            let comment_count = rng.gen_range(0.0..=2.0 * options.comments).round() as usize;
+            let mut comment_at = created_at;
+            let comments = (0..comment_count)
+                .filter_map(|c| {
+                    let gap = -rng.gen_range(f64::EPSILON..1.0f64).ln() * 3.0;
+                    comment_at = comment_at + chrono::Duration::seconds((gap * 86400.0) as i64);
+                    if comment_at > now {
+                        return None;
+                    }
+                    Some(DownloadedComment {
+                        id: format!("retention-comment-{}-{}", number, c),
+                        author_id: user(rng.gen_range(0..options.users.max(1))),
+                        body: format!("comment {} on issue {}", c, number),
+                        created_at: comment_at,
+                        updated_at: None,
+                    })
+                })
+                .collect();
+            let closed_chance = if now - created_at > chrono::Duration::days(60) {
+                0.9
+            } else {
+                0.3
+            };
+            DownloadedIssue {
+                id: format!("retention-issue-{}", number),
+                number,
+                state: if rng.gen_bool(closed_chance) {
+                    "CLOSED".to_string()
+                } else {
+                    "OPEN".to_string()
+                },
+                title: format!("issue {}", number),
+                body: Some(format!("created {}", created_at)),
+                author_id: user(rng.gen_range(0..options.users.max(1))),
+                comments,
+                created_at,
+                updated_at: None,
+                events: Vec::new(),
+            }
+        })
+        .collect()
+}
+
+fn fraction_of(span: chrono::Duration, fraction: f64) -> chrono::Duration {
+    chrono::Duration::seconds((span.num_seconds() as f64 * fraction) as i64)
+}
+
+/// The age of the oldest and newest issues of `issues`
+pub fn age_range(issues: &[DownloadedIssue]) -> Option<(DateTime<Utc>, DateTime<Utc>)> {
+    let oldest = issues.iter().map(|i| i.created_at).min()?;
+    let newest = issues.iter().map(|i| i.created_at).max()?;
+    Some((oldest, newest))
+}
+
+/// Import `issues` into the monorepo in `dir`, continuing an earlier import of the same corpus,
+/// then measure each of the `retained` percentages, calling `on_report` with each
+pub fn run<F>(
+    dir: &Path,
+    issues: &[DownloadedIssue],
+    retained: &[f64],
+    retrievals: usize,
+    mut on_report: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&Report),
+{
+    let mut monorepo = LiteMonorepo::create_or_open(monorepo_dir(dir))?;
+    monorepo.set_resume_imports(true);
+    let start = Instant::now();
+    for issue in issues {
+        monorepo.import_issue(issue)?;
+    }
+    status!(
+        "imported {} changes in {:?}",
+        monorepo.changes_created(),
+        start.elapsed()
+    );
+    monorepo.sign_refs()?;
+
+    let newest_first: Vec<cob::ObjectId> = issues
+        .iter()
+        .rev()
+        .filter_map(|i| monorepo.issue_object_id(i.number))
+        .collect();
+    let step = (newest_first.len() / retrievals.max(1)).max(1);
+    for percent in retained {
+        let cutoff = (newest_first.len() as f64 * percent / 100.0).round() as usize;
+        let retained_ids = &newest_first[..cutoff.min(newest_first.len())];
+
+        let warm = fill_cache(&monorepo, retained_ids)?;
+        let (cached_objects, cache_bytes) = monorepo.cache().usage()?;
+        let mut cached = Vec::new();
+        let mut uncached = Vec::new();
+        for (i, id) in newest_first
+            .iter()
+            .enumerate()
+            .step_by(step)
+            .take(retrievals)
+        {
+            let start = Instant::now();
+            monorepo.retrieve_issue(id, true)?;
+            if i < cutoff {
+                cached.push(start.elapsed());
+            } else {
+                uncached.push(start.elapsed());
+            }
+        }
+
+        fill_cache(&monorepo, retained_ids)?;
+        let start = Instant::now();
+        monorepo.list_issues()?;
+        let list = start.elapsed();
+
+        on_report(&Report {
+            retained: *percent,
+            cached_objects,
+            cache_bytes,
+            warm,
+            list,
+            retrieve_cached: Stats::from_samples(cached),
+            retrieve_uncached: Stats::from_samples(uncached),
+        });
+    }
+    Ok(())
+}
+
+/// Empty the cache and retrieve `ids` through it, returning how long that took
+fn fill_cache(monorepo: &LiteMonorepo, ids: &[cob::ObjectId]) -> Result<Duration, Error> {
+    monorepo.cache().clear()?;
+    let start = Instant::now();
+    for id in ids {
+        monorepo.retrieve_issue(id, true)?;
+    }
+    Ok(start.elapsed())
+}
+
+fn monorepo_dir(dir: &Path) -> PathBuf {
+    dir.join("monorepo")
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "newest {}% cached: {} objects, {} bytes, filled in {:?}",
+            self.retained, self.cached_objects, self.cache_bytes, self.warm
+        )?;
+        writeln!(f, "  list              {:?}", self.list)?;
+        match &self.retrieve_cached {
+            Some(stats) => writeln!(f, "  retrieve cached   {}", stats)?,
+            None => writeln!(f, "  retrieve cached   no objects")?,
+        }
+        match &self.retrieve_uncached {
+            Some(stats) => writeln!(f, "  retrieve uncached {}", stats),
+            None => writeln!(f, "  retrieve uncached no objects"),
+        }
+    }
+}
@@ -0,0 +1,290 @@
+//! Retrying of long running operations which fail for transient reasons (network errors, another
+//! process holding a git lock). Each failed attempt is recorded so that a summary can be printed
+//! at the end of an unattended run and persisted to the failure log in the repository's storage
+//! root.
+
+use std::path::Path;
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{cob_api, failure_taxonomy::Category};
+
+/// The longest we will wait between two attempts
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Errors which know whether retrying the operation which produced them might succeed
+pub trait Transient {
+    fn is_transient(&self) -> bool;
+}
+
+/// Where an operation which was retried resumed from
+#[derive(Debug, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Checkpoint {
+    /// The GraphQL cursor of a download
+    Cursor(String),
+    /// The last sync time of a sync
+    SyncedAt(DateTime<Utc>),
+    /// The last issue which was completely imported
+    Issue(u64),
+}
+
+impl Checkpoint {
+    /// A checkpoint as it was recorded before checkpoints were typed, a string of the form
+    /// `issue <number>`, an RFC 3339 time, or a cursor
+    fn parse_legacy(s: &str) -> Checkpoint {
+        if let Some(number) = s.strip_prefix("issue ").and_then(|n| n.parse().ok()) {
+            return Checkpoint::Issue(number);
+        }
+        match DateTime::parse_from_rfc3339(s) {
+            Ok(t) => Checkpoint::SyncedAt(t.with_timezone(&Utc)),
+            Err(_) => Checkpoint::Cursor(s.to_string()),
+        }
+    }
+}
+
+impl std::fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Checkpoint::Cursor(cursor) => write!(f, "cursor {}", cursor),
+            Checkpoint::SyncedAt(at) => write!(f, "sync at {}", at.to_rfc3339()),
+            Checkpoint::Issue(number) => write!(f, "issue {}", number),
+        }
+    }
+}
+
+/// Deserialize a checkpoint, or one recorded as a string before checkpoints were typed
+fn checkpoint_or_legacy<'de, D>(deserializer: D) -> Result<Option<Checkpoint>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(serde::Deserialize)]
+    #[serde(untagged)]
+    enum Recorded {
+        Typed(Checkpoint),
+        Legacy(String),
+    }
+    Ok(
+        Option::<Recorded>::deserialize(deserializer)?.map(|recorded| match recorded {
+            Recorded::Typed(checkpoint) => checkpoint,
+            Recorded::Legacy(s) => Checkpoint::parse_legacy(&s),
+        }),
+    )
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Failure {
+    pub at: DateTime<Utc>,
+    pub command: String,
+    pub error: String,
+    /// Whether the operation was retried after this failure
+    pub retried: bool,
+    /// For operations which resume from a checkpoint, the checkpoint the retry resumed from
+    #[serde(default, deserialize_with = "checkpoint_or_legacy")]
+    pub resumed_from: Option<Checkpoint>,
+    /// See [`crate::failure_taxonomy`], missing from failures recorded before there were
+    /// categories
+    #[serde(default)]
+    pub category: Option<Category>,
+    /// The cob API the failure happened with, see [`crate::cob_api::VERSION`]
+    #[serde(default)]
+    pub cob_api: Option<String>,
+}
+
+impl Failure {
+    /// A failure of `command` which has just happened
+    pub fn new(
+        command: &str,
+        error: String,
+        retried: bool,
+        resumed_from: Option<Checkpoint>,
+    ) -> Self {
+        Failure {
+            at: Utc::now(),
+            command: command.to_string(),
+            category: Some(Category::classify(&error)),
+            cob_api: Some(cob_api::VERSION.to_string()),
+            error,
+            retried,
+            resumed_from,
+        }
+    }
+
+    pub fn category(&self) -> Category {
+        self.category
+            .unwrap_or_else(|| Category::classify(&self.error))
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub failures: Vec<Failure>,
+}
+
+impl Summary {
+    pub fn retries(&self) -> usize {
+        self.failures.iter().filter(|f| f.retried).count()
+    }
+
+    /// Append the failures to the failure log at `path`
+    pub fn persist<P: AsRef<Path>>(&self, path: P) -> Result<(), std::io::Error> {
+        if self.failures.is_empty() {
+            return Ok(());
+        }
+        if let Some(parent) = path.as_ref().parent() {
+            crate::fs::create_dir_all(parent)?;
+        }
+        let mut lines = Vec::new();
+        for failure in &self.failures {
+            serde_json::to_writer(&mut lines, failure)?;
+            lines.push(b'\n');
+        }
+        crate::fs::append_line(path, &lines)?;
+        Ok(())
+    }
+
+    /// Record that an attempt of `command` failed with `error`, returning how long to wait before
+    /// the next attempt, or `None` if the error isn't transient or `max_retries` is used up
+    fn next_attempt<E, C>(
+        &mut self,
+        command: &str,
+        error: &E,
+        attempt: &mut u32,
+        max_retries: u32,
+        checkpoint: &C,
+    ) -> Option<Duration>
+    where
+        E: Transient + std::fmt::Display,
+        C: Fn() -> Option<Checkpoint>,
+    {
+        let retry = *attempt < max_retries && error.is_transient();
+        self.failures.push(Failure::new(
+            command,
+            error.to_string(),
+            retry,
+            if retry { checkpoint() } else { None },
+        ));
+        if !retry {
+            return None;
+        }
+        *attempt += 1;
+        status!(
+            "transient failure, retrying in {:?} ({}/{}): {}",
+            backoff(*attempt),
+            attempt,
+            max_retries,
+            error
+        );
+        Some(backoff(*attempt))
+    }
+
+    pub fn print(&self) {
+        if self.failures.is_empty() {
+            return;
+        }
+        status!(
+            "{} failures, {} retries:",
+            self.failures.len(),
+            self.retries()
+        );
+        for failure in &self.failures {
+            let resumed = match &failure.resumed_from {
+                Some(c) => format!(" (resumed from {})", c),
+                None => String::new(),
+            };
+            status!("  {} {}{}", failure.at.to_rfc3339(), failure.error, resumed);
+        }
+    }
+}
+
+/// Record a failure of `command` which isn't retried in the failure log at `path`
+pub fn record_failure<P: AsRef<Path>>(
+    path: P,
+    command: &str,
+    error: &dyn std::fmt::Display,
+) -> Result<(), std::io::Error> {
+    Summary {
+        failures: vec![Failure::new(command, error.to_string(), false, None)],
+    }
+    .persist(path)
+}
+
+/// Load the failures recorded in the failure log at `path`
+pub fn load_failures<P: AsRef<Path>>(path: P) -> Result<Vec<Failure>, std::io::Error> {
+    if !crate::fs::exists(&path)? {
+        return Ok(Vec::new());
+    }
+    let mut failures = Vec::new();
+    for line in crate::fs::read_to_string(path)?.lines() {
+        if let Ok(failure) = serde_json::from_str(line) {
+            failures.push(failure);
+        }
+    }
+    Ok(failures)
+}
+
+fn backoff(attempt: u32) -> Duration {
+    std::cmp::min(
+        Duration::from_secs(2u64.saturating_pow(attempt)),
+        MAX_BACKOFF,
+    )
+}
+
+/// Run `op` until it succeeds, fails with an error which is not transient, or has been retried
+/// `max_retries` times. `checkpoint` is called before each retry to describe where the retry will
+/// resume from.
+pub fn retry<T, E, F, C>(
+    command: &str,
+    max_retries: u32,
+    mut op: F,
+    checkpoint: C,
+) -> (Result<T, E>, Summary)
+where
+    E: Transient + std::fmt::Display,
+    F: FnMut() -> Result<T, E>,
+    C: Fn() -> Option<Checkpoint>,
+{
+    let mut summary = Summary::default();
+    let mut attempt = 0;
+    loop {
+        match op() {
+            Ok(t) => return (Ok(t), summary),
+            Err(e) => {
+                match summary.next_attempt(command, &e, &mut attempt, max_retries, &checkpoint) {
+                    Some(wait) => std::thread::sleep(wait),
+                    None => return (Err(e), summary),
+                }
+            }
+        }
+    }
+}
+
+/// The same as `retry` but for asynchronous operations
+pub async fn retry_async<T, E, F, Fut, C>(
+    command: &str,
+    max_retries: u32,
+    mut op: F,
+    checkpoint: C,
+) -> (Result<T, E>, Summary)
+where
+    E: Transient + std::fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: std::future::Future<Output = Result<T, E>>,
+    C: Fn() -> Option<Checkpoint>,
+{
+    let mut summary = Summary::default();
+    let mut attempt = 0;
+    loop {
+        match op().await {
+            Ok(t) => return (Ok(t), summary),
+            Err(e) => {
+                match summary.next_attempt(command, &e, &mut attempt, max_retries, &checkpoint) {
+                    Some(wait) => tokio::time::sleep(wait).await,
+                    None => return (Err(e), summary),
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,63 @@
+//! A log of when the long running commands were run against a repository and whether they
+//! finished, kept in `runs.jsonl` in the repository's storage root.
+use std::path::Path;
+
+use chrono::{DateTime, Utc};
+
+pub const RUNS_LOG: &str = "runs.jsonl";
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Run {
+    pub command: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub succeeded: bool,
+}
+
+impl Run {
+    /// A run of `command` which started at `started_at` and has just finished
+    pub fn finished(command: &str, started_at: DateTime<Utc>, succeeded: bool) -> Run {
+        Run {
+            command: command.to_string(),
+            started_at,
+            finished_at: Utc::now(),
+            succeeded,
+        }
+    }
+}
+
+pub fn record<P: AsRef<Path>>(path: P, run: &Run) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.as_ref().parent() {
+        crate::fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_vec(run)?;
+    line.push(b'\n');
+    crate::fs::append_line(path, &line)
+}
+
+/// Load the runs recorded in the log at `path`, oldest first
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Run>, std::io::Error> {
+    if !crate::fs::exists(&path)? {
+        return Ok(Vec::new());
+    }
+    let mut runs = Vec::new();
+    for line in crate::fs::read_to_string(path)?.lines() {
+        if let Ok(run) = serde_json::from_str(line) {
+            runs.push(run);
+        }
+    }
+    Ok(runs)
+}
+
+/// The most recent run of each command
+pub fn latest(runs: &[Run]) -> Vec<&Run> {
+    let mut latest: Vec<&Run> = Vec::new();
+    for run in runs {
+        match latest.iter_mut().find(|r| r.command == run.command) {
+            Some(r) if r.started_at <= run.started_at => *r = run,
+            Some(_) => {}
+            None => latest.push(run),
+        }
+    }
+    latest
+}
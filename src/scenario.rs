@@ -0,0 +1,396 @@
+//! Scenarios, sequences of steps described in a YAML file, so that a stress test made of several
+//! steps can be shared and reviewed like any other file rather than as a shell script. A scenario
+//! runs in a directory of its own, starting from an empty monorepo, and each step works on what
+//! the steps before it left behind:
+//!
+//! * `generate` adds a corpus of issues, as `bench-retention` generates them
+//! * `import` imports the issues generated so far which haven't been imported yet
+//! * `replicate` imports the generated issues into several replicas of the monorepo and merges
+//!   them, as `simulate-replication` does
+//! * `corrupt` deletes the change at the tip of some objects from the object database
+//! * `bench` runs the benchmark `bench-metrics` runs
+//! * `snapshot` records the document of every object under a name, for `documents_equal`
+//! * `assert` checks the state left by the earlier steps, see `assertions`. A failed assertion
+//!   doesn't stop the scenario, so that one run reports every assertion which fails, but the
+//!   scenario fails at the end.
+//!
+//! For example
+//!
+//! ```yaml
+//! steps:
+//!   - generate: {issues: 500, seed: 1}
+//!   - import: {}
+//!   - bench: {requests: 100}
+//!   - assert: {metric: {name: retrieve_p95, below: 50}}
+//!   - snapshot: imported
+//!   - replicate: {nodes: 3}
+//!   - assert: converged
+//!   - assert: {documents_equal: imported}
+//!   - corrupt: {objects: 3}
+//!   - assert: {unretrievable: 3}
+//! ```
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use rand::{seq::SliceRandom, SeedableRng};
+use serde::Deserialize;
+use thiserror::Error;
+
+use crate::{
+    assertions::{self, Assertion, Failure, Snapshot},
+    bisect_perf::{self, Metrics},
+    cache,
+    downloaded_issue::DownloadedIssue,
+    lite_monorepo::{error, LiteMonorepo},
+    replication, replication_simulation, retention,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Yaml(#[from] serde_yaml::Error),
+    #[error(transparent)]
+    Open(#[from] error::CreateOrOpen),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error(transparent)]
+    Cache(#[from] cache::Error),
+    #[error(transparent)]
+    Replication(#[from] replication_simulation::Error),
+    #[error(transparent)]
+    Bench(#[from] bisect_perf::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error("step {step}: {message}")]
+    Step { step: usize, message: String },
+    #[error("step {step}: {source}")]
+    Assertion {
+        step: usize,
+        source: assertions::Error,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    #[serde(default)]
+    pub description: Option<String>,
+    pub steps: Vec<Step>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Step {
+    Generate(Generate),
+    Import(Import),
+    Replicate(Replicate),
+    Corrupt(Corrupt),
+    Bench(Bench),
+    Snapshot(String),
+    Assert(Assertion),
+}
+
+/// The options of `bench-retention`'s corpus
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Generate {
+    pub issues: usize,
+    pub years: f64,
+    pub growth: f64,
+    pub comments: f64,
+    pub users: usize,
+    pub seed: u64,
+}
+
+impl Default for Generate {
+    fn default() -> Self {
+        Generate {
+            issues: 100,
+            years: 3.0,
+            growth: 1.5,
+            comments: 4.0,
+            users: 100,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Import {
+    /// Import consecutive comments as concurrent changes, see `--concurrent-comments`
+    pub concurrent_comments: bool,
+    /// Write every this many changes as a snapshot of the document, see `--snapshot-every`
+    pub snapshot_every: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Replicate {
+    pub nodes: usize,
+}
+
+impl Default for Replicate {
+    fn default() -> Self {
+        Replicate { nodes: 3 }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Corrupt {
+    /// The number of objects to corrupt
+    pub objects: usize,
+    pub seed: u64,
+}
+
+impl Default for Corrupt {
+    fn default() -> Self {
+        Corrupt {
+            objects: 1,
+            seed: 0,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Bench {
+    /// The number of issues to retrieve
+    pub requests: usize,
+}
+
+impl Default for Bench {
+    fn default() -> Self {
+        Bench { requests: 200 }
+    }
+}
+
+impl Scenario {
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Scenario, Error> {
+        let file = crate::fs::open(path)?;
+        Ok(serde_yaml::from_reader(file)?)
+    }
+}
+
+impl Step {
+    fn name(&self) -> &'static str {
+        match self {
+            Step::Generate(_) => "generate",
+            Step::Import(_) => "import",
+            Step::Replicate(_) => "replicate",
+            Step::Corrupt(_) => "corrupt",
+            Step::Bench(_) => "bench",
+            Step::Snapshot(_) => "snapshot",
+            Step::Assert(_) => "assert",
+        }
+    }
+}
+
+/// What the steps so far have left behind
+struct State {
+    dir: PathBuf,
+    monorepo: LiteMonorepo,
+    issues: Vec<DownloadedIssue>,
+    /// The number of the issues which have been imported
+    imported: usize,
+    replication: Option<replication_simulation::Report>,
+    metrics: Option<Metrics>,
+    snapshots: HashMap<String, Snapshot>,
+    summary: Summary,
+}
+
+/// The assertions a scenario made
+#[derive(Debug, Default)]
+pub struct Summary {
+    pub assertions: usize,
+    /// The assertions which failed, with their step
+    pub failures: Vec<(usize, Failure)>,
+}
+
+impl Summary {
+    pub fn is_ok(&self) -> bool {
+        self.failures.is_empty()
+    }
+}
+
+/// Run `scenario` in `dir`, replacing anything an earlier run left there. Stops at the first step
+/// which can't be carried out, but carries on past failed assertions.
+pub fn run(scenario: &Scenario, dir: &Path) -> Result<Summary, Error> {
+    if crate::fs::exists(dir)? {
+        crate::fs::remove_dir_all(dir)?;
+    }
+    let mut state = State {
+        dir: dir.to_path_buf(),
+        monorepo: LiteMonorepo::create_or_open(dir.join("monorepo"))?,
+        issues: Vec::new(),
+        imported: 0,
+        replication: None,
+        metrics: None,
+        snapshots: HashMap::new(),
+        summary: Summary::default(),
+    };
+    for (i, step) in scenario.steps.iter().enumerate() {
+        let number = i + 1;
+        status!("step {}/{}: {}", number, scenario.steps.len(), step.name());
+        let start = Instant::now();
+        run_step(&mut state, number, step)?;
+        verbose!("step {} took {:?}", number, start.elapsed());
+    }
+    Ok(state.summary)
+}
+
+fn run_step(state: &mut State, step: usize, kind: &Step) -> Result<(), Error> {
+    let fail = |message: String| Error::Step { step, message };
+    match kind {
+        Step::Generate(options) => {
+            // Numbers carry on from the issues generated so far, so that generating twice grows
+            // the corpus
+            let offset = state.issues.len() as u64;
+            let corpus = retention::corpus(&retention::CorpusOptions {
+                issues: options.issues,
+                years: options.years,
+                growth: options.growth,
+                comments: options.comments,
+                users: options.users,
+                seed: options.seed,
+            });
+            for mut issue in corpus {
+                issue.number += offset;
+                issue.id = format!("{}-{}", issue.id, step);
+                for comment in &mut issue.comments {
+                    comment.id = format!("{}-{}", comment.id, step);
+                }
+                state.issues.push(issue);
+            }
+            status!("generated {} issues", options.issues);
+        }
+        Step::Import(options) => {
+            if state.imported == state.issues.len() {
+                return Err(fail("there are no generated issues to import".to_string()));
+            }
+            state
+                .monorepo
+                .set_concurrent_comments(options.concurrent_comments);
+            state.monorepo.set_snapshot_every(options.snapshot_every);
+            for issue in &state.issues[state.imported..] {
+                state.monorepo.import_issue(issue)?;
+            }
+            status!(
+                "imported {} issues, {} changes created so far",
+                state.issues.len() - state.imported,
+                state.monorepo.changes_created()
+            );
+            state.imported = state.issues.len();
+        }
+        Step::Replicate(options) => {
+            let dir = state.dir.join("replicas");
+            let report =
+                replication_simulation::run(&state.monorepo, &dir, &state.issues, options.nodes)?;
+            print!("{}", report);
+            state.replication = Some(report);
+        }
+        Step::Corrupt(options) => {
+            let corrupted = corrupt(&state.monorepo, options)?;
+            if corrupted < options.objects {
+                return Err(fail(format!(
+                    "only {} of {} objects could be corrupted",
+                    corrupted, options.objects
+                )));
+            }
+            status!("corrupted {} objects", corrupted);
+        }
+        Step::Bench(options) => {
+            let metrics = bisect_perf::measure(&state.monorepo, options.requests)?;
+            println!("{}", serde_json::to_string(&metrics).unwrap());
+            state.metrics = Some(metrics);
+        }
+        Step::Snapshot(name) => {
+            let snapshot = assertions::snapshot(&state.monorepo)
+                .map_err(|source| Error::Assertion { step, source })?;
+            status!("snapshot {} of {} documents", name, snapshot.len());
+            state.snapshots.insert(name.clone(), snapshot);
+        }
+        Step::Assert(assertion) => {
+            let context = assertions::Context {
+                monorepo: &state.monorepo,
+                replication: state.replication.as_ref(),
+                metrics: state.metrics.as_ref(),
+                snapshots: &state.snapshots,
+            };
+            let failure = assertions::check(&context, assertion)
+                .map_err(|source| Error::Assertion { step, source })?;
+            state.summary.assertions += 1;
+            match failure {
+                None => println!("step {}: ok", step),
+                Some(failure) => {
+                    print!("step {}: FAILED: {}", step, failure);
+                    state.summary.failures.push((step, failure));
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Delete the loose change blob at the tip of every ref of `options.objects` randomly chosen
+/// objects, returning how many objects were corrupted. Objects whose changes are packed are
+/// skipped. The cache is emptied so that retrieval sees the damage.
+fn corrupt(monorepo: &LiteMonorepo, options: &Corrupt) -> Result<usize, Error> {
+    let repo = monorepo.repo();
+    let mut ids: Vec<cob::ObjectId> = replication::object_ids(repo)?.into_iter().collect();
+    // Sorted so that the seed alone decides which objects are chosen
+    ids.sort_by_key(|id| id.to_string());
+    ids.shuffle(&mut rand::rngs::StdRng::seed_from_u64(options.seed));
+    let mut corrupted = 0;
+    for id in ids {
+        if corrupted == options.objects {
+            break;
+        }
+        let mut deleted = false;
+        for reference in repo.references()? {
+            let reference = reference?;
+            let name = match reference.name() {
+                Some(name) => name,
+                None => continue,
+            };
+            if replication::object_of_ref(name) != Some(id) {
+                continue;
+            }
+            let tree = reference.peel_to_commit()?.tree()?;
+            if let Some(change) = tree.get_name("change") {
+                let hex = change.id().to_string();
+                let path = repo.path().join("objects").join(&hex[..2]).join(&hex[2..]);
+                if crate::fs::exists(&path)? {
+                    crate::fs::remove_file(path)?;
+                    deleted = true;
+                }
+            }
+        }
+        if deleted {
+            verbose!("corrupted {}", id);
+            corrupted += 1;
+        }
+    }
+    monorepo.cache().clear()?;
+    Ok(corrupted)
+}
+
+impl std::fmt::Display for Summary {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (step, failure) in &self.failures {
+            write!(f, "step {}: {}", step, failure)?;
+        }
+        writeln!(
+            f,
+            "{} of {} assertions passed",
+            self.assertions - self.failures.len(),
+            self.assertions
+        )
+    }
+}
@@ -0,0 +1,124 @@
+//! What compiling the issue schema costs. cob takes the schema as JSON in `NewObjectSpec` and
+//! compiles it whenever it validates a document, so an import compiles it at least once for every
+//! change it creates. cob's API gives us no way to hand it a compiled schema, so the cost is
+//! measured here to tell whether that is worth changing in cob. Our own validation uses
+//! `lite_monorepo::compiled_schema`, which is compiled once.
+use std::time::{Duration, Instant};
+
+use link_identities::git::Urn;
+
+use crate::{
+    bench::Stats,
+    downloaded_issue::DownloadedIssue,
+    layout::Layout,
+    lite_monorepo::{self, materialize},
+    schema_strictness,
+};
+
+/// The number of compilations averaged over by [`mean_compile_time`] after an import
+pub const IMPORT_SAMPLES: u32 = 100;
+
+/// The mean time taken to compile the issue schema
+pub fn mean_compile_time(iterations: u32) -> Duration {
+    let schema = lite_monorepo::schema();
+    let start = Instant::now();
+    for _ in 0..iterations {
+        jsonschema::JSONSchema::compile(schema).unwrap();
+    }
+    start.elapsed() / iterations.max(1)
+}
+
+/// An estimate of how much of an import was spent compiling the schema
+pub struct ImportOverhead {
+    /// The number of changes created, each of which cob validated
+    pub compilations: u64,
+    pub per_compilation: Duration,
+    pub import_time: Duration,
+}
+
+impl ImportOverhead {
+    pub fn estimate(changes: u64, import_time: Duration) -> ImportOverhead {
+        ImportOverhead {
+            compilations: changes,
+            per_compilation: mean_compile_time(IMPORT_SAMPLES),
+            import_time,
+        }
+    }
+
+    pub fn total(&self) -> Duration {
+        self.per_compilation * self.compilations as u32
+    }
+}
+
+impl std::fmt::Display for ImportOverhead {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "schema compilation: at least {} compilations at {:?} each, {:?} or {:.2}% of the import",
+            self.compilations,
+            self.per_compilation,
+            self.total(),
+            100.0 * self.total().as_secs_f64() / self.import_time.as_secs_f64().max(f64::EPSILON)
+        )
+    }
+}
+
+/// Validating the same documents by compiling the schema each time, as cob does, and with the
+/// schema compiled once
+pub struct BenchReport {
+    pub compile_each: Stats,
+    pub cached: Stats,
+}
+
+impl BenchReport {
+    /// The time saved on each validation by compiling the schema once
+    pub fn saving(&self) -> Duration {
+        self.compile_each
+            .mean
+            .checked_sub(self.cached.mean)
+            .unwrap_or_default()
+    }
+}
+
+/// Validate the document after each change of `issues`, built without involving git, both ways.
+/// Returns `None` if there are no issues.
+pub fn bench(issues: &[DownloadedIssue], author_urn: &Urn, layout: Layout) -> Option<BenchReport> {
+    let cached = lite_monorepo::compiled_schema();
+    let mut compile_each_samples = Vec::new();
+    let mut cached_samples = Vec::new();
+    for (_, changes) in schema_strictness::issue_changes(issues, author_urn, layout) {
+        let mut history = Vec::new();
+        for change in changes {
+            history.extend_from_slice(change.as_ref());
+            let doc = materialize(&cob::History::Automerge(history.clone()));
+
+            let start = Instant::now();
+            jsonschema::JSONSchema::compile(lite_monorepo::schema())
+                .unwrap()
+                .is_valid(&doc);
+            compile_each_samples.push(start.elapsed());
+
+            let start = Instant::now();
+            cached.is_valid(&doc);
+            cached_samples.push(start.elapsed());
+        }
+    }
+    Some(BenchReport {
+        compile_each: Stats::from_samples(compile_each_samples)?,
+        cached: Stats::from_samples(cached_samples)?,
+    })
+}
+
+impl std::fmt::Display for BenchReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(f, "compiling each time: {}", self.compile_each)?;
+        writeln!(f, "compiled once:       {}", self.cached)?;
+        write!(
+            f,
+            "compiling once saves {:?} per validation, {:?} over these {} validations",
+            self.saving(),
+            self.saving() * self.cached.count as u32,
+            self.cached.count
+        )
+    }
+}
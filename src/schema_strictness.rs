@@ -0,0 +1,199 @@
+//! Measuring what a stricter schema costs. The same issues are replayed under the schema imports
+//! use, which only checks the types of the fields it knows about and allows any others, and under
+//! `schema_strict.json`, which also requires every field an import writes, bounds the lengths of
+//! strings, checks URNs and states against patterns and allows no other fields.
+//!
+//! Changes are validated the way cob evaluates a change graph: each change is applied to the
+//! document made by the changes accepted so far, and rejected if the result doesn't validate. A
+//! rejected change is left out of the document, so a change which builds on it is never applied
+//! and adds nothing, but it is still validated and counted. Only the validation is timed, the
+//! time taken to materialize each document is reported alongside it for scale.
+use std::time::{Duration, Instant};
+
+use link_identities::git::Urn;
+
+use crate::{
+    downloaded_issue::DownloadedIssue,
+    layout::Layout,
+    lite_monorepo::{self, add_comment_change, init_issue_change, materialize, Clock},
+};
+
+/// The number of example rejections kept for each schema
+const EXAMPLES: usize = 5;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strictness {
+    /// `schema.json`, which imports are validated against
+    Permissive,
+    /// `schema_strict.json`
+    Strict,
+}
+
+impl Strictness {
+    pub fn all() -> [Strictness; 2] {
+        [Strictness::Permissive, Strictness::Strict]
+    }
+
+    fn schema(&self) -> serde_json::Value {
+        match self {
+            Strictness::Permissive => lite_monorepo::schema().clone(),
+            Strictness::Strict => {
+                serde_json::from_slice(include_bytes!("./schema_strict.json")).unwrap()
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for Strictness {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Strictness::Permissive => write!(f, "permissive"),
+            Strictness::Strict => write!(f, "strict"),
+        }
+    }
+}
+
+/// How the issues fared under one schema
+pub struct StrictnessReport {
+    pub strictness: Strictness,
+    pub compile_time: Duration,
+    /// The number of changes validated
+    pub changes: usize,
+    pub rejected: usize,
+    /// The number of issues whose first change was rejected, so that nothing of them was kept
+    pub issues_rejected: usize,
+    pub validate_time: Duration,
+    pub materialize_time: Duration,
+    /// The first few rejections, as the issue number and the reason
+    pub examples: Vec<(u64, String)>,
+}
+
+impl StrictnessReport {
+    /// The mean time spent validating each change
+    pub fn validate_per_change(&self) -> Duration {
+        self.validate_time / self.changes.max(1) as u32
+    }
+}
+
+/// The changes which an import would create for each of `issues`, built without involving git,
+/// along with the issue number
+pub fn issue_changes(
+    issues: &[DownloadedIssue],
+    author_urn: &Urn,
+    layout: Layout,
+) -> Vec<(u64, Vec<cob::History>)> {
+    issues
+        .iter()
+        .map(|issue| {
+            let init = init_issue_change(issue, author_urn, false, layout, Clock::default(), None);
+            let mut history = init.as_ref().to_vec();
+            let mut changes = vec![init];
+            for comment in &issue.comments {
+                let change = add_comment_change(
+                    comment,
+                    author_urn,
+                    &cob::History::Automerge(history.clone()),
+                    Clock::default(),
+                    None,
+                );
+                history.extend_from_slice(change.as_ref());
+                changes.push(change);
+            }
+            (issue.number, changes)
+        })
+        .collect()
+}
+
+/// Replay the changes of `issues`, built without involving git, under each schema
+pub fn compare(
+    issues: &[DownloadedIssue],
+    author_urn: &Urn,
+    layout: Layout,
+) -> Vec<StrictnessReport> {
+    let changes = issue_changes(issues, author_urn, layout);
+    Strictness::all()
+        .iter()
+        .map(|strictness| {
+            let json = strictness.schema();
+            let start = Instant::now();
+            let schema = jsonschema::JSONSchema::compile(&json).unwrap();
+            let mut report = StrictnessReport {
+                strictness: *strictness,
+                compile_time: start.elapsed(),
+                changes: 0,
+                rejected: 0,
+                issues_rejected: 0,
+                validate_time: Duration::default(),
+                materialize_time: Duration::default(),
+                examples: Vec::new(),
+            };
+            for (number, issue_changes) in &changes {
+                let mut accepted: Vec<u8> = Vec::new();
+                for (i, change) in issue_changes.iter().enumerate() {
+                    let mut proposed = accepted.clone();
+                    proposed.extend_from_slice(change.as_ref());
+                    let proposed = cob::History::Automerge(proposed);
+
+                    let start = Instant::now();
+                    let doc = materialize(&proposed);
+                    report.materialize_time += start.elapsed();
+
+                    let start = Instant::now();
+                    let result = schema.validate(&doc);
+                    report.validate_time += start.elapsed();
+
+                    report.changes += 1;
+                    match result {
+                        Ok(()) => accepted = proposed.as_ref().to_vec(),
+                        Err(errors) => {
+                            report.rejected += 1;
+                            if i == 0 {
+                                report.issues_rejected += 1;
+                            }
+                            if report.examples.len() < EXAMPLES {
+                                let reasons: Vec<String> = errors.map(|e| e.to_string()).collect();
+                                report.examples.push((*number, reasons.join("; ")));
+                            }
+                        }
+                    }
+                }
+            }
+            report
+        })
+        .collect()
+}
+
+/// What the strict schema costs over the permissive one, in words
+pub fn guidance(reports: &[StrictnessReport]) -> Option<String> {
+    let find = |s: Strictness| reports.iter().find(|r| r.strictness == s);
+    let (permissive, strict) = (find(Strictness::Permissive)?, find(Strictness::Strict)?);
+    let extra = strict
+        .validate_time
+        .checked_sub(permissive.validate_time)
+        .unwrap_or_default();
+    let of_materialize =
+        100.0 * extra.as_secs_f64() / strict.materialize_time.as_secs_f64().max(f64::EPSILON);
+    let mut guidance = format!(
+        "strict validation costs {:?} more per change than permissive, {:.1}% of the time spent \
+         materializing",
+        strict
+            .validate_per_change()
+            .checked_sub(permissive.validate_per_change())
+            .unwrap_or_default(),
+        of_materialize
+    );
+    let newly_rejected = strict.rejected.saturating_sub(permissive.rejected);
+    if newly_rejected == 0 {
+        guidance.push_str(", and rejects nothing the permissive schema accepts");
+    } else {
+        guidance.push_str(&format!(
+            ", and rejects {} more changes ({} whole issues) which real data contains, so its \
+             constraints would need loosening before use",
+            newly_rejected,
+            strict
+                .issues_rejected
+                .saturating_sub(permissive.issues_rejected)
+        ));
+    }
+    Some(guidance)
+}
@@ -0,0 +1,311 @@
+//! Full text search across every project and type name in a monorepo, as the UI of a seed node
+//! would serve it. The text of every object is indexed, a word at a time, into an inverted index
+//! kept in `search_index.json` in the root of the monorepo. Objects are found by scanning the refs
+//! of each registered project (see [`crate::projects`]) for the type names they have objects of,
+//! so objects of types other than the configured one are indexed too.
+//!
+//! The index is built the first time it is searched and rebuilt on request, so it doesn't see
+//! objects imported since. Building it retrieves every object, which is what a seed node would do
+//! on startup, and its cost is reported along with the cost of each query.
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use link_identities::{Identities, Project};
+use thiserror::Error;
+
+use crate::{
+    cob_api,
+    lite_monorepo::{self, LiteMonorepo},
+    projects::{self, Projects},
+};
+
+pub const SEARCH_INDEX: &str = "search_index.json";
+
+/// How many characters of text are shown either side of the first match
+const SNIPPET_CONTEXT: usize = 40;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Projects(#[from] projects::Error),
+    #[error(transparent)]
+    IdentityLoad(#[from] link_identities::git::error::Load),
+    #[error(transparent)]
+    Retrieve(#[from] cob_api::RetrieveError),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Document {
+    pub project: String,
+    pub typename: String,
+    pub object_id: String,
+    /// Every string in the object's document, separated by spaces
+    pub text: String,
+}
+
+#[derive(Default, serde::Serialize, serde::Deserialize)]
+pub struct SearchIndex {
+    documents: Vec<Document>,
+    /// The documents each word appears in, by their index in `documents`
+    postings: BTreeMap<String, Vec<u32>>,
+}
+
+/// What building the index took
+pub struct BuildReport {
+    pub projects: usize,
+    pub typenames: usize,
+    pub documents: usize,
+    pub words: usize,
+    pub elapsed: Duration,
+}
+
+pub struct Hit<'a> {
+    pub project: &'a str,
+    pub typename: &'a str,
+    pub object_id: &'a str,
+    pub snippet: String,
+}
+
+pub struct SearchResult<'a> {
+    /// The number of documents which match, of which the first `hits` are returned
+    pub matches: usize,
+    pub hits: Vec<Hit<'a>>,
+    pub elapsed: Duration,
+}
+
+impl SearchIndex {
+    /// Index every object of every project of `monorepo`
+    pub fn build(monorepo: &LiteMonorepo) -> Result<(SearchIndex, BuildReport), Error> {
+        let start = Instant::now();
+        let repo = monorepo.repo();
+        let peer = match monorepo.peer_ids().next() {
+            Some(peer) => *peer,
+            None => return Err(git2::Error::from_str("the monorepo has no peers").into()),
+        };
+        let storage = monorepo.refs_storage(peer);
+        let projects = Projects::load(monorepo.root().join(projects::PROJECTS))?;
+        let identities: Identities<'_, Project> = repo.into();
+        let mut index = SearchIndex::default();
+        let mut all_typenames = BTreeSet::new();
+        let mut project_count = 0;
+        for (_, entry) in projects.iter() {
+            let project = identities.get(entry.oid.into())?;
+            project_count += 1;
+            for typename in typenames(repo, &project)? {
+                let objects = cob_api::retrieve_objects(&storage, repo, &project, &typename, None)?;
+                for object in objects {
+                    let document = lite_monorepo::materialize(object.history());
+                    let mut strings = Vec::new();
+                    collect_strings(&document, &mut strings);
+                    index.add(Document {
+                        project: entry.urn.clone(),
+                        typename: typename.to_string(),
+                        object_id: object.id().to_string(),
+                        text: strings.join(" "),
+                    });
+                }
+                all_typenames.insert(typename.to_string());
+            }
+        }
+        let report = BuildReport {
+            projects: project_count,
+            typenames: all_typenames.len(),
+            documents: index.documents.len(),
+            words: index.postings.len(),
+            elapsed: start.elapsed(),
+        };
+        Ok((index, report))
+    }
+
+    /// The index saved at `path`, if there is one
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<SearchIndex>, Error> {
+        if !crate::fs::exists(&path)? {
+            return Ok(None);
+        }
+        Ok(Some(serde_json::from_slice(&crate::fs::read(&path)?)?))
+    }
+
+    /// Save the index to `path`, returning its size in bytes
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<u64, Error> {
+        let bytes = serde_json::to_vec(self)?;
+        crate::fs::write_atomic(&path, &bytes)?;
+        Ok(bytes.len() as u64)
+    }
+
+    pub fn len(&self) -> usize {
+        self.documents.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.documents.is_empty()
+    }
+
+    fn add(&mut self, document: Document) {
+        let id = self.documents.len() as u32;
+        let words: BTreeSet<String> = words(&document.text).collect();
+        for word in words {
+            self.postings.entry(word).or_default().push(id);
+        }
+        self.documents.push(document);
+    }
+
+    /// The documents containing every word of `query`, with at most `limit` hits
+    pub fn search(&self, query: &str, limit: usize) -> SearchResult<'_> {
+        let start = Instant::now();
+        let query: Vec<String> = words(query).collect();
+        let mut matching: Option<Vec<u32>> = None;
+        for word in &query {
+            let postings = self.postings.get(word).map(|p| p.as_slice()).unwrap_or(&[]);
+            matching = Some(match matching {
+                None => postings.to_vec(),
+                // Postings are in the order documents were added, so both lists are sorted
+                Some(previous) => intersect(&previous, postings),
+            });
+        }
+        let matching = matching.unwrap_or_default();
+        let hits = matching
+            .iter()
+            .take(limit)
+            .map(|id| {
+                let document = &self.documents[*id as usize];
+                Hit {
+                    project: &document.project,
+                    typename: &document.typename,
+                    object_id: &document.object_id,
+                    snippet: snippet(&document.text, &query),
+                }
+            })
+            .collect();
+        SearchResult {
+            matches: matching.len(),
+            hits,
+            elapsed: start.elapsed(),
+        }
+    }
+}
+
+/// The type names `project` has objects of in `repo`
+fn typenames(repo: &git2::Repository, project: &Project) -> Result<Vec<cob::TypeName>, Error> {
+    let glob = format!(
+        "refs/namespaces/{}/refs/**/cob/*",
+        project.urn().encode_id()
+    );
+    let mut names = BTreeSet::new();
+    for reference in repo.references_glob(&glob)? {
+        let reference = reference?;
+        let typename = reference
+            .name()
+            .and_then(|name| name.split_once("/cob/"))
+            .and_then(|(_, rest)| rest.split('/').next());
+        if let Some(typename) = typename {
+            names.insert(typename.to_string());
+        }
+    }
+    Ok(names
+        .iter()
+        .filter_map(|name| cob::TypeName::from_str(name).ok())
+        .collect())
+}
+
+fn collect_strings(value: &serde_json::Value, strings: &mut Vec<String>) {
+    match value {
+        serde_json::Value::String(s) => strings.push(s.clone()),
+        serde_json::Value::Array(values) => {
+            for value in values {
+                collect_strings(value, strings);
+            }
+        }
+        serde_json::Value::Object(fields) => {
+            for value in fields.values() {
+                collect_strings(value, strings);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// The lowercased words of `text`, ignoring single characters
+fn words(text: &str) -> impl Iterator<Item = String> + '_ {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| word.chars().count() > 1)
+        .map(|word| word.to_lowercase())
+}
+
+fn intersect(a: &[u32], b: &[u32]) -> Vec<u32> {
+    let (mut i, mut j) = (0, 0);
+    let mut result = Vec::new();
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            std::cmp::Ordering::Less => i += 1,
+            std::cmp::Ordering::Greater => j += 1,
+            std::cmp::Ordering::Equal => {
+                result.push(a[i]);
+                i += 1;
+                j += 1;
+            }
+        }
+    }
+    result
+}
+
+/// The text around the first occurrence of a word of `query` in `text`
+fn snippet(text: &str, query: &[String]) -> String {
+    let lower = text.to_lowercase();
+    let found = query
+        .iter()
+        .filter_map(|word| lower.find(word.as_str()))
+        .min();
+    let chars: Vec<char> = text.chars().collect();
+    // Lowercasing can change the length of the text, so fall back to its start
+    let at = match found {
+        Some(at) if lower.len() == text.len() => text.get(..at).map_or(0, |s| s.chars().count()),
+        _ => 0,
+    };
+    let from = at.saturating_sub(SNIPPET_CONTEXT);
+    let to = (at + SNIPPET_CONTEXT).min(chars.len());
+    let mut snippet: String = chars[from..to].iter().collect();
+    snippet = snippet.split_whitespace().collect::<Vec<_>>().join(" ");
+    if from > 0 {
+        snippet.insert_str(0, "...");
+    }
+    if to < chars.len() {
+        snippet.push_str("...");
+    }
+    snippet
+}
+
+impl std::fmt::Display for BuildReport {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "indexed {} objects of {} types in {} projects, {} distinct words, in {:?}",
+            self.documents, self.typenames, self.projects, self.words, self.elapsed
+        )
+    }
+}
+
+impl<'a> std::fmt::Display for SearchResult<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for hit in &self.hits {
+            writeln!(f, "{} {} {}", hit.project, hit.typename, hit.object_id)?;
+            writeln!(f, "    {}", hit.snippet)?;
+        }
+        writeln!(
+            f,
+            "{} matches, showing {}, in {:?}",
+            self.matches,
+            self.hits.len(),
+            self.elapsed
+        )
+    }
+}
@@ -0,0 +1,130 @@
+//! A small HTTP API over a monorepo, for inspecting the results of a run from a browser or a
+//! script rather than one command at a time. Every response is JSON:
+//!
+//! * `GET /objects` lists the ids of every object with a ref, without retrieving them
+//! * `GET /objects/<id>` is the document of an object, from the cold store if it was archived.
+//!   `?cache=false` retrieves it without the cache.
+//! * `GET /objects/<id>/changes` is the change graph info of an object: its tips, the number of
+//!   changes and the graph in graphviz format
+//! * `GET /issues/<number>` is the document of the object a GitHub issue was imported as
+//!
+//! Requests are handled one at a time on the calling thread, as the monorepo can't be shared
+//! between threads. That's plenty for poking around, but it isn't a benchmark.
+use std::str::FromStr;
+
+use serde_json::json;
+use thiserror::Error;
+
+use crate::{lite_monorepo::LiteMonorepo, replication};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error("could not listen on {addr}: {reason}")]
+    Bind { addr: String, reason: String },
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A response: the status code and the JSON body
+type Response = (u16, serde_json::Value);
+
+/// Serve `monorepo` on `addr` until the process is stopped
+pub fn serve(monorepo: &LiteMonorepo, addr: &str) -> Result<(), Error> {
+    let server = tiny_http::Server::http(addr).map_err(|e| Error::Bind {
+        addr: addr.to_string(),
+        reason: e.to_string(),
+    })?;
+    status!("serving on http://{}", addr);
+    let content_type =
+        tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..]).unwrap();
+    for request in server.incoming_requests() {
+        let (code, body) = if *request.method() == tiny_http::Method::Get {
+            handle(monorepo, request.url())
+        } else {
+            error(405, "only GET is supported")
+        };
+        verbose!("{} {} {}", request.method(), request.url(), code);
+        let response = tiny_http::Response::from_string(body.to_string())
+            .with_status_code(code)
+            .with_header(content_type.clone());
+        request.respond(response)?;
+    }
+    Ok(())
+}
+
+fn handle(monorepo: &LiteMonorepo, url: &str) -> Response {
+    let (path, query) = match url.split_once('?') {
+        Some((path, query)) => (path, query),
+        None => (url, ""),
+    };
+    let use_cache = !query.split('&').any(|param| param == "cache=false");
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    match segments.as_slice() {
+        [""] => (
+            200,
+            json!({
+                "endpoints": [
+                    "/objects",
+                    "/objects/<id>",
+                    "/objects/<id>/changes",
+                    "/issues/<number>"
+                ]
+            }),
+        ),
+        ["objects"] => match replication::object_ids(monorepo.repo()) {
+            Ok(ids) => {
+                let mut ids: Vec<String> = ids.iter().map(|id| id.to_string()).collect();
+                ids.sort();
+                (200, json!({ "objects": ids }))
+            }
+            Err(e) => error(500, &e.to_string()),
+        },
+        ["objects", id] => match cob::ObjectId::from_str(id) {
+            Ok(id) => document(monorepo, &id, use_cache),
+            Err(_) => error(400, &format!("{} is not an object id", id)),
+        },
+        ["objects", id, "changes"] => match cob::ObjectId::from_str(id) {
+            Ok(id) => changes(monorepo, &id),
+            Err(_) => error(400, &format!("{} is not an object id", id)),
+        },
+        ["issues", number] => match number.parse() {
+            Ok(number) => match monorepo.issue_object_id(number) {
+                Some(id) => document(monorepo, &id, use_cache),
+                None => error(404, &format!("issue #{} has not been imported", number)),
+            },
+            Err(_) => error(400, &format!("{} is not an issue number", number)),
+        },
+        _ => error(404, &format!("no such endpoint {}", path)),
+    }
+}
+
+fn document(monorepo: &LiteMonorepo, id: &cob::ObjectId, use_cache: bool) -> Response {
+    match monorepo.retrieve_issue(id, use_cache) {
+        Ok(Some(doc)) => (200, doc),
+        Ok(None) => match monorepo.retrieve_archived_issue(id) {
+            Ok(Some(doc)) => (200, doc),
+            Ok(None) => error(404, &format!("no such object {}", id)),
+            Err(e) => error(500, &e.to_string()),
+        },
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+fn changes(monorepo: &LiteMonorepo, id: &cob::ObjectId) -> Response {
+    match monorepo.issue_info(id) {
+        Ok(Some(info)) => (
+            200,
+            json!({
+                "tips": info.tips.iter().map(|tip| tip.to_string()).collect::<Vec<_>>(),
+                "changes": info.number_of_nodes,
+                "graphviz": info.dotviz,
+            }),
+        ),
+        Ok(None) => error(404, &format!("no such object {}", id)),
+        Err(e) => error(500, &e.to_string()),
+    }
+}
+
+fn error(code: u16, message: &str) -> Response {
+    (code, json!({ "error": message }))
+}
@@ -0,0 +1,110 @@
+//! An emulation of librad's `rad/signed_refs`. Each peer signs the list of its cob refs in the
+//! project namespace and stores the result as a blob at
+//! `refs/namespaces/<project>/refs/remotes/<peer>/rad/signed_refs`. When replicating, librad checks
+//! the refs it fetches from a peer against that peer's signed refs, so verifying them here gives an
+//! idea of what that costs.
+use std::{collections::BTreeMap, str::FromStr};
+
+use link_crypto::{PeerId, SecretKey, Signature};
+use link_identities::git::Urn;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+    #[error("{peer} has no signed refs")]
+    Missing { peer: PeerId },
+    #[error("the signature on the signed refs of {peer} is invalid")]
+    BadSignature { peer: PeerId },
+    #[error("{name} is not covered by the signed refs of {peer}")]
+    Unsigned { peer: PeerId, name: String },
+    #[error("{0} is not a peer ref")]
+    NotPeerRef(String),
+}
+
+#[derive(serde::Serialize, serde::Deserialize)]
+struct SignedRefs {
+    /// Ref names, relative to `refs/remotes/<peer>/`, and the commits they point at
+    refs: BTreeMap<String, String>,
+    signature: Signature,
+}
+
+fn peer_prefix(project: &Urn, peer: &PeerId) -> String {
+    format!(
+        "refs/namespaces/{}/refs/remotes/{}/",
+        project.encode_id(),
+        peer
+    )
+}
+
+/// Sign the cob refs of `peer` in the namespace of `project`, replacing any previous signed refs.
+/// Returns the number of refs which were signed.
+pub fn sign(
+    repo: &git2::Repository,
+    project: &Urn,
+    peer: &PeerId,
+    key: &SecretKey,
+) -> Result<usize, Error> {
+    let prefix = peer_prefix(project, peer);
+    let mut refs = BTreeMap::new();
+    for reference in repo.references_glob(&format!("{}cob/*", prefix))? {
+        let reference = reference?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            refs.insert(name[prefix.len()..].to_string(), target.to_string());
+        }
+    }
+    let signature = key.sign(&serde_json::to_vec(&refs)?);
+    let signed = SignedRefs { refs, signature };
+    let blob = repo.blob(&serde_json::to_vec(&signed)?)?;
+    repo.reference(
+        &format!("{}rad/signed_refs", prefix),
+        blob,
+        true,
+        "sign refs",
+    )?;
+    Ok(signed.refs.len())
+}
+
+/// Check that `reference`, which must be a ref in the namespace of `project` belonging to some
+/// peer, is covered by that peer's signed refs
+pub fn verify(
+    repo: &git2::Repository,
+    project: &Urn,
+    reference: &git2::Reference<'_>,
+) -> Result<(), Error> {
+    let name = reference.name().unwrap_or_default();
+    let remotes = format!("refs/namespaces/{}/refs/remotes/", project.encode_id());
+    let peer = name
+        .strip_prefix(&remotes)
+        .and_then(|rest| rest.split('/').next())
+        .and_then(|peer| PeerId::from_str(peer).ok())
+        .ok_or_else(|| Error::NotPeerRef(name.to_string()))?;
+    let prefix = peer_prefix(project, &peer);
+
+    let signed_ref = match repo.find_reference(&format!("{}rad/signed_refs", prefix)) {
+        Ok(r) => r,
+        Err(e) if e.code() == git2::ErrorCode::NotFound => return Err(Error::Missing { peer }),
+        Err(e) => return Err(e.into()),
+    };
+    let blob = signed_ref.peel_to_blob()?;
+    let signed: SignedRefs = serde_json::from_slice(blob.content())?;
+    if !peer
+        .as_public_key()
+        .verify(&signed.signature, &serde_json::to_vec(&signed.refs)?)
+    {
+        return Err(Error::BadSignature { peer });
+    }
+
+    let relative = &name[prefix.len()..];
+    let target = reference.target().map(|t| t.to_string());
+    if signed.refs.get(relative) != target.as_ref() {
+        return Err(Error::Unsigned {
+            peer,
+            name: name.to_string(),
+        });
+    }
+    Ok(())
+}
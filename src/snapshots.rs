@@ -0,0 +1,117 @@
+//! How writing automerge snapshots into the history of objects affects retrieval and the size of
+//! the repository. With `--snapshot-every K` every Kth change of an object is written as the
+//! whole document saved by automerge rather than as the change alone. The saved document is
+//! compressed and columnar, so evaluating an object whose latest changes include a snapshot might
+//! decode faster than replaying many small changes, at the cost of storing the history again in
+//! every snapshot.
+//!
+//! The same issues are imported into a replica of the repository's monorepo for each K, where 0
+//! means no snapshots, and each replica is measured in the same way.
+use std::{
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use thiserror::Error;
+
+use crate::{
+    bench::Stats,
+    downloaded_issue::DownloadedIssue,
+    lite_monorepo::{error, LiteMonorepo},
+    replication,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Replication(#[from] replication::Error),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Cache(#[from] crate::cache::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub struct Report {
+    /// Changes were written as snapshots every this many changes, 0 for never
+    pub every: usize,
+    pub import: Duration,
+    pub changes: u64,
+    /// The size of the git directory
+    pub repo_bytes: u64,
+    /// Retrieving each object without the cache
+    pub retrieve: Option<Stats>,
+    /// Listing every object with an empty cache
+    pub list: Duration,
+}
+
+/// Import `issues` into a replica of `source` in `dir` for each of `every`, replacing any left by
+/// an earlier run, and measure each, calling `on_report` with each
+pub fn run<F>(
+    source: &LiteMonorepo,
+    dir: &Path,
+    issues: &[DownloadedIssue],
+    every: &[usize],
+    mut on_report: F,
+) -> Result<(), Error>
+where
+    F: FnMut(&Report),
+{
+    for k in every {
+        let mut monorepo = replication::create_replica(source, &dir.join(format!("every-{}", k)))?;
+        monorepo.set_snapshot_every(Some(*k));
+        let start = Instant::now();
+        for issue in issues {
+            monorepo.import_issue(issue)?;
+        }
+        let import = start.elapsed();
+
+        let mut samples = Vec::new();
+        for id in replication::object_ids(monorepo.repo())? {
+            let start = Instant::now();
+            monorepo.retrieve_issue(&id, false)?;
+            samples.push(start.elapsed());
+        }
+        monorepo.cache().clear()?;
+        let start = Instant::now();
+        monorepo.list_issues()?;
+        let list = start.elapsed();
+
+        on_report(&Report {
+            every: *k,
+            import,
+            changes: monorepo.changes_created(),
+            repo_bytes: crate::fs::dir_size(monorepo.repo().path())?,
+            retrieve: Stats::from_samples(samples),
+            list,
+        });
+    }
+    Ok(())
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let every = if self.every == 0 {
+            "no snapshots".to_string()
+        } else {
+            format!("snapshot every {} changes", self.every)
+        };
+        writeln!(
+            f,
+            "{}: imported {} changes in {:?}, repository {} bytes",
+            every, self.changes, self.import, self.repo_bytes
+        )?;
+        match &self.retrieve {
+            Some(stats) => writeln!(f, "  retrieve {}", stats)?,
+            None => writeln!(f, "  retrieve no objects")?,
+        }
+        writeln!(f, "  list     {:?}", self.list)
+    }
+}
@@ -0,0 +1,314 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use rusqlite::OptionalExtension;
+use thiserror::Error;
+
+use chrono::{DateTime, Utc};
+
+use super::download::{parse_sync_time, IssueStorage, LoadError, StoreError};
+
+/// The directory of the monorepo kept as files, packed by `LiteMonorepo::pack` instead
+const GIT_DIR: &str = "git";
+/// The directory of the cob cache, whose entries get a table of their own
+const COB_CACHE_DIR: &str = "cob_cache";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Sqlite(#[from] rusqlite::Error),
+}
+
+/// An `IssueStorage` which keeps every downloaded issue, their checksums, the download cursor and
+/// the last sync time in a single SQLite database. This is used by the `--single-file` mode so
+/// that a downloaded corpus is one file rather than one file per issue. The rest of the monorepo
+/// the corpus is imported into goes in the same database, see [`MonorepoFiles`].
+pub struct SqliteStorage {
+    conn: Mutex<rusqlite::Connection>,
+}
+
+impl SqliteStorage {
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<SqliteStorage, rusqlite::Error> {
+        let conn = rusqlite::Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS issues (
+                number INTEGER PRIMARY KEY,
+                json BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS checksums (
+                number INTEGER PRIMARY KEY,
+                sha256 TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS meta (
+                key TEXT PRIMARY KEY,
+                value TEXT NOT NULL
+            );",
+        )?;
+        Ok(SqliteStorage {
+            conn: Mutex::new(conn),
+        })
+    }
+}
+
+impl IssueStorage for SqliteStorage {
+    fn issue_numbers(&self) -> Result<Vec<u64>, LoadError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT number FROM issues ORDER BY number")?;
+        let rows = stmt.query_map(rusqlite::params![], |row| row.get::<_, i64>(0))?;
+        let mut numbers = Vec::new();
+        for row in rows {
+            numbers.push(row? as u64);
+        }
+        Ok(numbers)
+    }
+
+    fn load_raw(&self, number: u64) -> Result<Option<Vec<u8>>, LoadError> {
+        Ok(self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT json FROM issues WHERE number = ?1",
+                rusqlite::params![number as i64],
+                |row| row.get(0),
+            )
+            .optional()?)
+    }
+
+    fn store_raw(&self, number: u64, bytes: &[u8]) -> Result<(), StoreError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO issues (number, json) VALUES (?1, ?2)",
+            rusqlite::params![number as i64, bytes],
+        )?;
+        Ok(())
+    }
+
+    fn checksums(&self) -> Result<HashMap<u64, String>, LoadError> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT number, sha256 FROM checksums")?;
+        let rows = stmt.query_map(rusqlite::params![], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?;
+        let mut checksums = HashMap::new();
+        for row in rows {
+            let (number, checksum) = row?;
+            checksums.insert(number as u64, checksum);
+        }
+        Ok(checksums)
+    }
+
+    fn record_checksum(&self, number: u64, checksum: &str) -> Result<(), StoreError> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO checksums (number, sha256) VALUES (?1, ?2)",
+            rusqlite::params![number as i64, checksum],
+        )?;
+        Ok(())
+    }
+
+    fn save_cursor(&self, cursor: String) -> Result<(), std::io::Error> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('last_cursor', ?1)",
+                rusqlite::params![cursor],
+            )
+            .map_err(to_io)?;
+        Ok(())
+    }
+
+    fn load_cursor(&self) -> Result<Option<String>, std::io::Error> {
+        self.conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'last_cursor'",
+                rusqlite::params![],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(to_io)
+    }
+
+    fn save_last_sync(&self, at: DateTime<Utc>) -> Result<(), std::io::Error> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute(
+                "INSERT OR REPLACE INTO meta (key, value) VALUES ('last_sync', ?1)",
+                rusqlite::params![at.to_rfc3339()],
+            )
+            .map_err(to_io)?;
+        Ok(())
+    }
+
+    fn load_last_sync(&self) -> Result<Option<DateTime<Utc>>, std::io::Error> {
+        let last_sync: Option<String> = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT value FROM meta WHERE key = 'last_sync'",
+                rusqlite::params![],
+                |row| row.get(0),
+            )
+            .optional()
+            .map_err(to_io)?;
+        last_sync.map(|s| parse_sync_time(&s)).transpose()
+    }
+}
+
+fn to_io(e: rusqlite::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, e)
+}
+
+/// The files of a monorepo other than its git directory, kept in the database of the downloads by
+/// `--single-file`: the metadata (the keys in `peers/`, `peer_map`, `project_oid`, `config.json`,
+/// `issue_index.jsonl`, `cache_access`, `cache_stats` and the rest) in `monorepo_files`, and the
+/// entries of the cob cache in `cob_cache`, each keyed by its path relative to the monorepo root
+/// or to the cache directory.
+///
+/// cob and the monorepo only work with files, so [`MonorepoFiles::restore`] writes them out before
+/// the monorepo is opened, and they are moved back into the database when the `MonorepoFiles` is
+/// dropped. A file left behind by a run which exited early is newer than its copy in the
+/// database, so restoring never replaces one, and the next run to finish moves it back.
+pub struct MonorepoFiles {
+    db: PathBuf,
+    root: PathBuf,
+}
+
+impl MonorepoFiles {
+    /// Write out the files of the monorepo at `root` kept in the database at `db` which aren't on
+    /// disk already, returning how many were written
+    pub fn restore<P: AsRef<Path>, Q: AsRef<Path>>(
+        db: P,
+        root: Q,
+    ) -> Result<(MonorepoFiles, usize), Error> {
+        let files = MonorepoFiles {
+            db: db.as_ref().to_path_buf(),
+            root: root.as_ref().to_path_buf(),
+        };
+        let conn = files.open()?;
+        let mut restored = 0;
+        for (table, dir) in files.tables() {
+            let mut stmt = conn.prepare(&format!("SELECT path, contents FROM {}", table))?;
+            let rows = stmt.query_map(rusqlite::params![], |row| {
+                Ok((row.get::<_, String>(0)?, row.get::<_, Vec<u8>>(1)?))
+            })?;
+            for row in rows {
+                let (path, contents) = row?;
+                let path = dir.join(path);
+                if crate::fs::exists(&path)? {
+                    continue;
+                }
+                if let Some(parent) = path.parent() {
+                    crate::fs::create_dir_all(parent)?;
+                }
+                crate::fs::write_atomic(&path, contents)?;
+                restored += 1;
+            }
+        }
+        Ok((files, restored))
+    }
+
+    /// Replace the files kept in the database with those on disk and remove them from disk,
+    /// returning how many were stored
+    pub fn store(&self) -> Result<usize, Error> {
+        let mut conn = self.open()?;
+        let tx = conn.transaction()?;
+        let mut stored = 0;
+        // The files and directories directly beneath the root or the cache directory, removed
+        // once they're stored
+        let mut stored_entries = Vec::new();
+        for (table, dir) in self.tables() {
+            tx.execute(&format!("DELETE FROM {}", table), rusqlite::params![])?;
+            if !crate::fs::exists(&dir)? {
+                continue;
+            }
+            for entry in crate::fs::read_dir(&dir)? {
+                let entry = entry?;
+                if dir == self.root
+                    && (entry.file_name() == GIT_DIR || entry.file_name() == COB_CACHE_DIR)
+                {
+                    continue;
+                }
+                let mut paths = Vec::new();
+                walk(&entry.path(), &mut paths)?;
+                for path in paths {
+                    let key: Vec<String> = path
+                        .strip_prefix(&dir)
+                        .unwrap()
+                        .components()
+                        .map(|c| c.as_os_str().to_string_lossy().into_owned())
+                        .collect();
+                    tx.execute(
+                        &format!("INSERT INTO {} (path, contents) VALUES (?1, ?2)", table),
+                        rusqlite::params![key.join("/"), crate::fs::read(&path)?],
+                    )?;
+                    stored += 1;
+                }
+                stored_entries.push(entry.path());
+            }
+        }
+        tx.commit()?;
+        // Only once everything is safely in the database
+        for path in stored_entries {
+            if path.is_dir() {
+                crate::fs::remove_dir_all(path)?;
+            } else {
+                crate::fs::remove_file(path)?;
+            }
+        }
+        Ok(stored)
+    }
+
+    fn open(&self) -> Result<rusqlite::Connection, Error> {
+        let conn = rusqlite::Connection::open(&self.db)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS monorepo_files (
+                path TEXT PRIMARY KEY,
+                contents BLOB NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS cob_cache (
+                path TEXT PRIMARY KEY,
+                contents BLOB NOT NULL
+            );",
+        )?;
+        Ok(conn)
+    }
+
+    /// Each table and the directory its paths are relative to
+    fn tables(&self) -> [(&'static str, PathBuf); 2] {
+        [
+            ("monorepo_files", self.root.clone()),
+            ("cob_cache", self.root.join(COB_CACHE_DIR)),
+        ]
+    }
+}
+
+impl Drop for MonorepoFiles {
+    fn drop(&mut self) {
+        if let Err(e) = self.store() {
+            eprintln!(
+                "failed to move the monorepo's files into {}: {}",
+                self.db.display(),
+                e
+            );
+        }
+    }
+}
+
+/// `path` if it is a file, or every file beneath it if it is a directory
+fn walk(path: &Path, paths: &mut Vec<PathBuf>) -> Result<(), std::io::Error> {
+    if !path.is_dir() {
+        paths.push(path.to_path_buf());
+        return Ok(());
+    }
+    for entry in crate::fs::read_dir(path)? {
+        walk(&entry?.path(), paths)?;
+    }
+    Ok(())
+}
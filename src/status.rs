@@ -0,0 +1,160 @@
+//! A summary of the state of a repository's data directory, to check on before and after long
+//! runs: how far downloading and importing have got, how large the monorepo and its cache are,
+//! when each command last ran, and what has failed.
+use std::{collections::BTreeMap, path::Path};
+
+use chrono::{DateTime, Utc};
+use thiserror::Error;
+
+use crate::{
+    cache,
+    download::{IssueStorage, LoadError},
+    failure_taxonomy::Category,
+    lite_monorepo::{error, LiteMonorepo},
+    retry::{self, Failure},
+    runs::{self, Run},
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Load(#[from] LoadError),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Cache(#[from] cache::Error),
+}
+
+pub struct MonorepoStatus {
+    pub imported: usize,
+    pub bytes: u64,
+    pub refs: usize,
+    pub cache_entries: usize,
+    pub cache_bytes: u64,
+    pub cache_last_accessed: Option<DateTime<Utc>>,
+}
+
+pub struct Status {
+    pub downloaded: usize,
+    /// The cursor a resumed download would start from
+    pub cursor: Option<String>,
+    /// When `sync-issues` last finished
+    pub last_sync: Option<DateTime<Utc>>,
+    /// `None` if nothing has been imported yet
+    pub monorepo: Option<MonorepoStatus>,
+    /// The most recent run of each command
+    pub runs: Vec<Run>,
+    pub failures: Vec<Failure>,
+}
+
+/// The status of the repository whose data is in `storage_root` and whose downloaded issues are
+/// in `storage`. `monorepo` should be `None` if there is no monorepo yet, so that checking the
+/// status doesn't create one.
+pub fn collect(
+    storage_root: &Path,
+    storage: &dyn IssueStorage,
+    monorepo: Option<&LiteMonorepo>,
+) -> Result<Status, Error> {
+    let monorepo = match monorepo {
+        Some(monorepo) => {
+            let (cache_entries, cache_bytes) = monorepo.cache().usage()?;
+            Some(MonorepoStatus {
+                imported: monorepo.list_issue_ids()?.len(),
+                bytes: monorepo.git_size()?,
+                refs: monorepo.ref_count()?,
+                cache_entries,
+                cache_bytes,
+                cache_last_accessed: monorepo.cache().last_accessed(),
+            })
+        }
+        None => None,
+    };
+    let runs = runs::load(storage_root.join(runs::RUNS_LOG))?;
+    Ok(Status {
+        downloaded: storage.issue_numbers()?.len(),
+        cursor: storage.load_cursor()?,
+        last_sync: storage.load_last_sync()?,
+        monorepo,
+        runs: runs::latest(&runs).into_iter().cloned().collect(),
+        failures: retry::load_failures(storage_root.join(crate::FAILURE_LOG))?,
+    })
+}
+
+impl std::fmt::Display for Status {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "download: {} issues stored", self.downloaded)?;
+        match &self.cursor {
+            Some(cursor) => writeln!(f, ", resumes after {}", cursor)?,
+            None => writeln!(f)?,
+        }
+        if let Some(last_sync) = &self.last_sync {
+            writeln!(f, "sync: issues updated up to {}", last_sync.to_rfc3339())?;
+        }
+        match &self.monorepo {
+            Some(m) => {
+                writeln!(
+                    f,
+                    "import: {} of {} downloaded issues imported",
+                    m.imported, self.downloaded
+                )?;
+                writeln!(f, "monorepo: {} bytes, {} refs", m.bytes, m.refs)?;
+                write!(
+                    f,
+                    "cache: {} entries, {} bytes",
+                    m.cache_entries, m.cache_bytes
+                )?;
+                match m.cache_last_accessed {
+                    Some(at) => writeln!(f, ", last used {}", at.to_rfc3339())?,
+                    None => writeln!(f, ", never used")?,
+                }
+            }
+            None => writeln!(f, "import: nothing imported yet")?,
+        }
+        if !self.runs.is_empty() {
+            writeln!(f, "last runs:")?;
+            for run in &self.runs {
+                writeln!(
+                    f,
+                    "  {} {} to {} {}",
+                    run.command,
+                    run.started_at.to_rfc3339(),
+                    run.finished_at.to_rfc3339(),
+                    if run.succeeded { "succeeded" } else { "failed" }
+                )?;
+            }
+        }
+        let mut categories: BTreeMap<Category, usize> = BTreeMap::new();
+        for failure in &self.failures {
+            *categories.entry(failure.category()).or_default() += 1;
+        }
+        let categories: Vec<String> = categories
+            .iter()
+            .map(|(category, count)| format!("{} {}", count, category))
+            .collect();
+        if categories.is_empty() {
+            writeln!(f, "0 recorded failures")?;
+        } else {
+            writeln!(
+                f,
+                "{} recorded failures ({})",
+                self.failures.len(),
+                categories.join(", ")
+            )?;
+        }
+        if let Some(last) = self.failures.last() {
+            writeln!(
+                f,
+                "  most recent: {} {} {}: {}",
+                last.at.to_rfc3339(),
+                last.command,
+                last.category(),
+                last.error
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,246 @@
+//! Changes with forged signatures or authors. With `--tamper-signatures <ratio>` roughly that
+//! fraction of the comments of an import are each followed by a change, made by the same peer,
+//! whose commit is then rewritten in one of three ways before the peer's ref is pointed at it:
+//!
+//! * corrupted: a character of each signature in the commit message is changed, so the
+//!   signatures don't verify
+//! * unsigned: the signatures are removed from the commit message
+//! * wrong author: the parent naming the author's identity is replaced by the identity of
+//!   another user, whose keys didn't sign the change
+//!
+//! Which comments are chosen, and how their change is tampered with, depends only on the ID of
+//! the comment. As with `crate::invalid_changes`, each change sets a marker field of its own and
+//! is made by a new automerge actor, the import carries on from the object as it was before it,
+//! and `verify-tampering` checks that no marker made it into a document. Each change is recorded
+//! in `tampered_changes.jsonl` in the root of the monorepo.
+//!
+//! cob verifies the signatures of every change each time it evaluates an object, so
+//! `verify-tampering` also times retrieving the objects with tampered changes against retrieving
+//! as many without any, to see what verifying and rejecting them costs.
+use std::{
+    collections::HashSet,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use sha2::{Digest, Sha256};
+use thiserror::Error;
+
+use crate::lite_monorepo::{error, LiteMonorepo};
+
+pub const TAMPERED_CHANGES: &str = "tampered_changes.jsonl";
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error("invalid object ID {0}")]
+    ObjectId(String),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Tampering {
+    Corrupted,
+    Unsigned,
+    WrongAuthor,
+}
+
+/// A change whose commit was tampered with
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Tampered {
+    pub issue: u64,
+    pub object_id: String,
+    pub peer: String,
+    /// The comment the change follows
+    pub comment_id: String,
+    pub tampering: Tampering,
+    /// The field the change sets, which only appears in the document if it was accepted
+    pub marker: String,
+    /// The commit cob wrote
+    pub original: String,
+    /// The commit the peer's ref was pointed at instead
+    pub commit: String,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checked: usize,
+    pub rejected: usize,
+    /// Changes whose marker is in the document of their object
+    pub accepted: Vec<Tampered>,
+    /// Changes whose object couldn't be retrieved
+    pub missing: Vec<Tampered>,
+    /// How many objects with tampered changes were retrieved, and how long it took
+    pub tampered_objects: usize,
+    pub tampered_elapsed: Duration,
+    /// How many objects without any were retrieved, and how long it took
+    pub clean_objects: usize,
+    pub clean_elapsed: Duration,
+}
+
+impl Report {
+    pub fn passed(&self) -> bool {
+        self.accepted.is_empty() && self.missing.is_empty()
+    }
+}
+
+/// How to tamper with the change following the comment `comment_id`, if it is one of the `ratio`
+/// of comments chosen
+pub fn chosen(comment_id: &str, ratio: f64) -> Option<Tampering> {
+    // Salted, so that the comments chosen don't coincide with those of `--inject-invalid`
+    let digest = Sha256::digest(format!("tamper:{}", comment_id).as_bytes());
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&digest[..8]);
+    let roll = u64::from_be_bytes(bytes) as f64 / u64::MAX as f64;
+    if roll >= ratio {
+        return None;
+    }
+    match digest[8] % 3 {
+        0 => Some(Tampering::Corrupted),
+        1 => Some(Tampering::Unsigned),
+        _ => Some(Tampering::WrongAuthor),
+    }
+}
+
+/// The field the change following the comment `comment_id` sets
+pub fn marker(comment_id: &str) -> String {
+    format!("tampered_{}", comment_id)
+}
+
+/// The message of a change commit, tampered with as `tampering` says. Signatures are trailers of
+/// the message, so the author is left as it is.
+pub fn tamper_message(message: &str, tampering: Tampering) -> String {
+    let mut lines = Vec::new();
+    for line in message.lines() {
+        let is_signature = line.to_lowercase().contains("signature");
+        match tampering {
+            Tampering::Unsigned if is_signature => continue,
+            Tampering::Corrupted if is_signature => lines.push(corrupt(line)),
+            _ => lines.push(line.to_string()),
+        }
+    }
+    let mut tampered = lines.join("\n");
+    if message.ends_with('\n') {
+        tampered.push('\n');
+    }
+    tampered
+}
+
+/// `line` with its last alphanumeric character replaced by another
+fn corrupt(line: &str) -> String {
+    let mut chars: Vec<char> = line.chars().collect();
+    if let Some(c) = chars.iter_mut().rev().find(|c| c.is_ascii_alphanumeric()) {
+        *c = if *c == 'a' { 'b' } else { 'a' };
+    }
+    chars.into_iter().collect()
+}
+
+pub fn record<P: AsRef<Path>>(path: P, tampered: &Tampered) -> Result<(), std::io::Error> {
+    let mut line = serde_json::to_vec(tampered)?;
+    line.push(b'\n');
+    crate::fs::append_line(path, &line)
+}
+
+/// The changes recorded in the log at `path`, in the order they were made
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Tampered>, std::io::Error> {
+    if !crate::fs::exists(&path)? {
+        return Ok(Vec::new());
+    }
+    let mut tampered = Vec::new();
+    for line in crate::fs::read_to_string(path)?.lines() {
+        if let Ok(change) = serde_json::from_str(line) {
+            tampered.push(change);
+        }
+    }
+    Ok(tampered)
+}
+
+/// Check that every change recorded in `monorepo` was left out of its object, and time
+/// retrieving the objects with tampered changes against as many objects without
+pub fn verify(monorepo: &LiteMonorepo) -> Result<Report, Error> {
+    let mut report = Report::default();
+    let mut tampered_objects = HashSet::new();
+    for tampered in load(monorepo.root().join(TAMPERED_CHANGES))? {
+        report.checked += 1;
+        let object_id: cob::ObjectId = tampered
+            .object_id
+            .parse()
+            .map_err(|_| Error::ObjectId(tampered.object_id.clone()))?;
+        tampered_objects.insert(object_id);
+        match monorepo.retrieve_issue(&object_id, false)? {
+            Some(document) if document.get(&tampered.marker).is_some() => {
+                report.accepted.push(tampered)
+            }
+            Some(_) => report.rejected += 1,
+            None => report.missing.push(tampered),
+        }
+    }
+
+    let start = Instant::now();
+    for object_id in &tampered_objects {
+        monorepo.retrieve_issue(object_id, false)?;
+    }
+    report.tampered_objects = tampered_objects.len();
+    report.tampered_elapsed = start.elapsed();
+
+    let clean: Vec<cob::ObjectId> = monorepo
+        .list_issue_ids()?
+        .into_iter()
+        .filter(|id| !tampered_objects.contains(id))
+        .take(tampered_objects.len())
+        .collect();
+    let start = Instant::now();
+    for object_id in &clean {
+        monorepo.retrieve_issue(object_id, false)?;
+    }
+    report.clean_objects = clean.len();
+    report.clean_elapsed = start.elapsed();
+    Ok(report)
+}
+
+impl std::fmt::Display for Tampering {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Tampering::Corrupted => write!(f, "corrupted"),
+            Tampering::Unsigned => write!(f, "unsigned"),
+            Tampering::WrongAuthor => write!(f, "wrong-author"),
+        }
+    }
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} tampered changes: {} rejected, {} accepted, {} objects missing",
+            self.checked,
+            self.rejected,
+            self.accepted.len(),
+            self.missing.len()
+        )?;
+        for tampered in &self.accepted {
+            writeln!(
+                f,
+                "accepted: {} change {} to #{} ({}) by {}",
+                tampered.tampering,
+                tampered.commit,
+                tampered.issue,
+                tampered.object_id,
+                tampered.peer
+            )?;
+        }
+        for tampered in &self.missing {
+            writeln!(f, "missing: #{} ({})", tampered.issue, tampered.object_id)?;
+        }
+        writeln!(
+            f,
+            "retrieved {} objects with tampered changes in {:?}, {} without in {:?}",
+            self.tampered_objects, self.tampered_elapsed, self.clean_objects, self.clean_elapsed
+        )
+    }
+}
@@ -0,0 +1,318 @@
+//! Editing the text of imported issues a character at a time. Imports write each title, body and
+//! comment once, as a run of characters inserted in order, which is the cheapest thing a text CRDT
+//! can be asked to do: nothing is ever deleted and no insert lands in the middle of existing text.
+//! People editing an issue fix typos, add a paragraph at the end, reword a sentence and delete
+//! another, and every deleted character stays in the document as a tombstone.
+//!
+//! `simulate-text-edits` applies edit sessions like these to some of the imported issues, each
+//! session as one change by a randomly chosen peer. A session is a handful of edits, each one of
+//!
+//! * a typo fix, replacing one or two characters with others
+//! * an addition to the end of the text, as if typed out
+//! * a phrase inserted at a random position
+//! * a run of characters deleted from a random position
+//! * a rewrite, deleting a run of characters and inserting new ones in their place
+//!
+//! The size of the issues' histories and the time taken to retrieve them are measured before and
+//! after, and each edited text is checked against the result of applying the same edits to a
+//! plain string.
+use std::{
+    str::FromStr,
+    time::{Duration, Instant},
+};
+
+use rand::{rngs::StdRng, seq::SliceRandom, Rng, SeedableRng};
+use thiserror::Error;
+
+use crate::lite_monorepo::{error, LiteMonorepo};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    List(#[from] error::List),
+    #[error(transparent)]
+    Retrieve(#[from] error::Retrieve),
+    #[error(transparent)]
+    Import(#[from] error::Import),
+    #[error("the monorepo has no peers")]
+    NoPeers,
+}
+
+/// An edit of a text, in characters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextEdit {
+    Insert { at: usize, text: String },
+    Delete { at: usize, len: usize },
+}
+
+impl TextEdit {
+    /// Apply the edit to `text`
+    pub fn apply(&self, text: &mut Vec<char>) {
+        match self {
+            TextEdit::Insert { at, text: inserted } => {
+                let at = (*at).min(text.len());
+                text.splice(at..at, inserted.chars());
+            }
+            TextEdit::Delete { at, len } => {
+                let at = (*at).min(text.len());
+                let end = (at + len).min(text.len());
+                text.drain(at..end);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Title,
+    Body,
+}
+
+impl Field {
+    pub fn key(&self) -> &'static str {
+        match self {
+            Field::Title => "title",
+            Field::Body => "body",
+        }
+    }
+}
+
+#[derive(Debug, Error)]
+#[error("expected title or body")]
+pub struct ParseFieldError {}
+
+impl FromStr for Field {
+    type Err = ParseFieldError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "title" => Ok(Field::Title),
+            "body" => Ok(Field::Body),
+            _ => Err(ParseFieldError {}),
+        }
+    }
+}
+
+pub struct Options {
+    /// The number of issues to edit
+    pub issues: usize,
+    /// The number of edit sessions per issue
+    pub sessions: usize,
+    pub fields: Vec<Field>,
+    pub seed: u64,
+}
+
+/// The edited issues before or after editing
+#[derive(Debug, Default)]
+pub struct Measurement {
+    pub history_bytes: usize,
+    /// In characters, across the edited fields
+    pub text_len: usize,
+    pub retrieve: Duration,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub issues: usize,
+    pub sessions: usize,
+    pub inserted: usize,
+    pub deleted: usize,
+    pub before: Measurement,
+    pub after: Measurement,
+    /// Fields whose text differs from applying the edits to a plain string
+    pub mismatched: usize,
+}
+
+/// Apply `options.sessions` edit sessions to each field of the first `options.issues` issues
+pub fn simulate(monorepo: &mut LiteMonorepo, options: &Options) -> Result<Report, Error> {
+    let mut rng = StdRng::seed_from_u64(options.seed);
+    let peers: Vec<link_crypto::PeerId> = monorepo.peer_ids().copied().collect();
+    if peers.is_empty() {
+        return Err(Error::NoPeers);
+    }
+    let mut ids = monorepo.list_issue_ids()?;
+    ids.sort_by_key(|id| id.to_string());
+    ids.truncate(options.issues);
+
+    let mut report = Report {
+        issues: ids.len(),
+        before: measure(monorepo, &ids, &options.fields)?,
+        ..Report::default()
+    };
+    for id in &ids {
+        let doc = match monorepo.retrieve_issue(id, false)? {
+            Some(doc) => doc,
+            None => continue,
+        };
+        for field in &options.fields {
+            let mut expected: Vec<char> = text_of(&doc, *field).chars().collect();
+            for _ in 0..options.sessions {
+                let edits = session(&mut rng, &expected);
+                for edit in &edits {
+                    match edit {
+                        TextEdit::Insert { text, .. } => report.inserted += text.chars().count(),
+                        TextEdit::Delete { len, .. } => report.deleted += len,
+                    }
+                    edit.apply(&mut expected);
+                }
+                let editor = peers.choose(&mut rng).unwrap();
+                monorepo.edit_text(id, editor, field.key(), &edits)?;
+                report.sessions += 1;
+            }
+            let edited = monorepo.retrieve_issue(id, false)?.unwrap_or_default();
+            if text_of(&edited, *field) != expected.iter().collect::<String>() {
+                report.mismatched += 1;
+            }
+        }
+    }
+    report.after = measure(monorepo, &ids, &options.fields)?;
+    Ok(report)
+}
+
+fn measure(
+    monorepo: &LiteMonorepo,
+    ids: &[cob::ObjectId],
+    fields: &[Field],
+) -> Result<Measurement, Error> {
+    let mut measurement = Measurement::default();
+    for id in ids {
+        if let Some(history) = monorepo.issue_history(id)? {
+            measurement.history_bytes += history.len();
+        }
+        let start = Instant::now();
+        let doc = monorepo.retrieve_issue(id, false)?;
+        measurement.retrieve += start.elapsed();
+        if let Some(doc) = doc {
+            for field in fields {
+                measurement.text_len += text_of(&doc, *field).chars().count();
+            }
+        }
+    }
+    Ok(measurement)
+}
+
+fn text_of(doc: &serde_json::Value, field: Field) -> &str {
+    doc[field.key()].as_str().unwrap_or_default()
+}
+
+/// A session of one to four edits of `text`, with positions relative to the text as it is after
+/// the edits before them
+fn session(rng: &mut StdRng, text: &[char]) -> Vec<TextEdit> {
+    let mut text = text.to_vec();
+    let mut edits = Vec::new();
+    for _ in 0..rng.gen_range(1..=4) {
+        let len = text.len();
+        let at = rng.gen_range(0..=len);
+        let mut new = Vec::new();
+        match rng.gen_range(0..100) {
+            // Typo fix
+            0..=39 if len > 0 => {
+                let at = at.min(len - 1);
+                new.push(TextEdit::Delete {
+                    at,
+                    len: rng.gen_range(1..=2).min(len - at),
+                });
+                new.push(TextEdit::Insert {
+                    at,
+                    text: (0..rng.gen_range(1..=2))
+                        .map(|_| rng.gen_range(b'a'..=b'z') as char)
+                        .collect(),
+                });
+            }
+            // Addition at the end
+            40..=64 => new.push(TextEdit::Insert {
+                at: len,
+                text: format!(" {}", words(rng, 20..=200)),
+            }),
+            // Phrase
+            65..=79 => new.push(TextEdit::Insert {
+                at,
+                text: format!("{} ", words(rng, 5..=40)),
+            }),
+            // Deletion
+            80..=89 if at < len => new.push(TextEdit::Delete {
+                at,
+                len: rng.gen_range(5..=60).min(len - at),
+            }),
+            // Rewrite
+            _ if at < len => {
+                new.push(TextEdit::Delete {
+                    at,
+                    len: rng.gen_range(5..=60).min(len - at),
+                });
+                new.push(TextEdit::Insert {
+                    at,
+                    text: words(rng, 5..=60),
+                });
+            }
+            // Nothing to delete from, so type something instead
+            _ => new.push(TextEdit::Insert {
+                at,
+                text: words(rng, 5..=40),
+            }),
+        }
+        for edit in new {
+            edit.apply(&mut text);
+            edits.push(edit);
+        }
+    }
+    edits
+}
+
+/// Random lowercase words separated by spaces, about `len` characters in all
+fn words(rng: &mut StdRng, len: std::ops::RangeInclusive<usize>) -> String {
+    let target = rng.gen_range(len);
+    let mut text = String::new();
+    while text.len() < target {
+        if !text.is_empty() {
+            text.push(' ');
+        }
+        for _ in 0..rng.gen_range(2..=9) {
+            text.push(rng.gen_range(b'a'..=b'z') as char);
+        }
+    }
+    text
+}
+
+impl std::fmt::Display for Report {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{} sessions on {} issues: {} characters inserted, {} deleted",
+            self.sessions, self.issues, self.inserted, self.deleted
+        )?;
+        let per_issue = |d: Duration| d / self.issues.max(1) as u32;
+        writeln!(
+            f,
+            "before: history {} bytes, text {} characters, retrieve {:?} per issue",
+            self.before.history_bytes,
+            self.before.text_len,
+            per_issue(self.before.retrieve)
+        )?;
+        writeln!(
+            f,
+            "after:  history {} bytes, text {} characters, retrieve {:?} per issue",
+            self.after.history_bytes,
+            self.after.text_len,
+            per_issue(self.after.retrieve)
+        )?;
+        let growth = self
+            .after
+            .history_bytes
+            .saturating_sub(self.before.history_bytes);
+        writeln!(
+            f,
+            "history grew {} bytes, {:.1} per character edited",
+            growth,
+            growth as f64 / (self.inserted + self.deleted).max(1) as f64
+        )?;
+        if self.mismatched > 0 {
+            writeln!(
+                f,
+                "{} edited texts differ from the edits applied to a plain string",
+                self.mismatched
+            )?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+//! Which peers the local peer tracks. In librad a peer only replicates, and so only sees the
+//! changes of, the peers it tracks. Seed nodes typically track many peers whereas clients track a
+//! handful. When no tracking configuration exists every peer in the monorepo is tracked.
+use std::{collections::HashSet, path::Path};
+
+use link_crypto::{PeerId, SecretKey};
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+    #[error(transparent)]
+    Serde(#[from] serde_json::Error),
+}
+
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Tracking(HashSet<PeerId>);
+
+impl Tracking {
+    /// Load the tracking configuration at `path`, if there is one
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Option<Tracking>, Error> {
+        if crate::fs::exists(&path)? {
+            let bytes = crate::fs::read(&path)?;
+            Ok(Some(serde_json::from_slice(&bytes)?))
+        } else {
+            Ok(None)
+        }
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Error> {
+        let bytes = serde_json::to_vec(self)?;
+        crate::fs::write_atomic(&path, bytes)?;
+        Ok(())
+    }
+
+    /// A tracking set of `size` peers. As many of `existing` as fit are tracked and the rest of
+    /// the set is made up of peers which have never published anything to the monorepo, as is
+    /// the case for most of the peers a seed node tracks.
+    pub fn generate<'a>(size: usize, existing: impl Iterator<Item = &'a PeerId>) -> Tracking {
+        let mut existing: Vec<PeerId> = existing.cloned().collect();
+        existing.sort_by_key(|p| p.to_string());
+        let mut peers: HashSet<PeerId> = existing.into_iter().take(size).collect();
+        while peers.len() < size {
+            peers.insert(PeerId::from(&SecretKey::new()));
+        }
+        Tracking(peers)
+    }
+
+    pub fn tracks(&self, peer: &PeerId) -> bool {
+        self.0.contains(peer)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &PeerId> {
+        self.0.iter()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
@@ -0,0 +1,214 @@
+//! Checks that the issues in a monorepo are a faithful copy of the downloaded issues they were
+//! imported from. This is mostly useful for catching bugs in resumed imports, where an issue or
+//! comment which was imported just before a failure is imported again after resuming.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+
+use super::downloaded_issue::{DownloadedComment, DownloadedIssue};
+use super::layout;
+use super::lite_monorepo::MaterializedIssue;
+use super::retry::{Checkpoint, Failure};
+
+#[derive(Debug)]
+pub enum Problem {
+    /// There is no object for the issue
+    MissingIssue,
+    /// There is more than one object for the issue
+    DuplicateObjects(Vec<cob::ObjectId>),
+    /// Comments (identified by their github ID) which appear more than once
+    DuplicateComments(Vec<String>),
+    /// Comments which are in the downloaded issue but not in the object
+    MissingComments(Vec<String>),
+    /// The comments are in a different order than in the downloaded issue, starting with this one
+    CommentsOutOfOrder(String),
+}
+
+impl std::fmt::Display for Problem {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Problem::MissingIssue => write!(f, "not imported"),
+            Problem::DuplicateObjects(ids) => write!(
+                f,
+                "imported {} times: {}",
+                ids.len(),
+                ids.iter()
+                    .map(|i| i.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            Problem::DuplicateComments(c) => write!(f, "duplicated comments: {}", c.join(", ")),
+            Problem::MissingComments(c) => write!(f, "missing comments: {}", c.join(", ")),
+            Problem::CommentsOutOfOrder(c) => write!(f, "comments out of order from {}", c),
+        }
+    }
+}
+
+/// A resumed import, identified by the last issue which was imported before the failure
+#[derive(Debug, Clone)]
+pub struct ResumeBoundary {
+    pub after_issue: u64,
+    pub at: DateTime<Utc>,
+}
+
+#[derive(Debug)]
+pub struct AffectedIssue {
+    pub number: u64,
+    pub problems: Vec<Problem>,
+    /// If the issue was the first to be imported after a resume this is the resume in question
+    pub resume_boundary: Option<ResumeBoundary>,
+}
+
+#[derive(Debug, Default)]
+pub struct Report {
+    pub checked: usize,
+    pub affected: Vec<AffectedIssue>,
+}
+
+/// Compare `issues` with the `downloaded` issues they were imported from. `failures` are the
+/// entries of the failure log, which are used to attribute problems to resumed imports.
+pub fn verify(
+    downloaded: &[DownloadedIssue],
+    issues: &[MaterializedIssue],
+    failures: &[Failure],
+) -> Report {
+    let mut by_number: HashMap<u64, Vec<&MaterializedIssue>> = HashMap::new();
+    for issue in issues {
+        if let Some(n) = issue.github_issue_number() {
+            by_number.entry(n).or_default().push(issue);
+        }
+    }
+    let boundaries = resume_boundaries(downloaded, failures);
+
+    let mut report = Report::default();
+    for issue in downloaded {
+        // Issues without an author are never imported
+        if issue.author_id.is_none() {
+            continue;
+        }
+        report.checked += 1;
+        let problems = match by_number.get(&issue.number).map(|v| &v[..]) {
+            None | Some([]) => vec![Problem::MissingIssue],
+            Some([object]) => comment_problems(issue, object),
+            Some(objects) => {
+                let mut problems = vec![Problem::DuplicateObjects(
+                    objects.iter().map(|o| o.id).collect(),
+                )];
+                for object in objects.iter() {
+                    problems.extend(comment_problems(issue, object));
+                }
+                problems
+            }
+        };
+        if !problems.is_empty() {
+            report.affected.push(AffectedIssue {
+                number: issue.number,
+                problems,
+                resume_boundary: boundaries.get(&issue.number).cloned(),
+            });
+        }
+    }
+    report.affected.sort_by_key(|a| a.number);
+    report
+}
+
+fn comment_problems(issue: &DownloadedIssue, object: &MaterializedIssue) -> Vec<Problem> {
+    let mut expected: BTreeMap<String, usize> = BTreeMap::new();
+    for comment in issue.comments.iter().filter(|c| c.author_id.is_some()) {
+        *expected.entry(comment.id.clone()).or_default() += 1;
+    }
+    let by_fallback_key: HashMap<String, &DownloadedComment> = issue
+        .comments
+        .iter()
+        .map(|c| (fallback_key(&c.created_at.to_rfc3339(), &c.body), c))
+        .collect();
+
+    let mut actual: BTreeMap<String, usize> = BTreeMap::new();
+    let mut actual_order: Vec<String> = Vec::new();
+    for comment in layout::comments(&object.document) {
+        let id = match comment.github_id {
+            Some(id) => Some(id),
+            // Objects imported before comments recorded their github ID
+            None => {
+                let created_at = comment.created_at.as_deref();
+                let body = comment.body.as_deref();
+                match (created_at, body) {
+                    (Some(created_at), Some(body)) => by_fallback_key
+                        .get(&fallback_key(created_at, body))
+                        .map(|c| c.id.clone()),
+                    _ => None,
+                }
+            }
+        };
+        if let Some(id) = id {
+            *actual.entry(id.clone()).or_default() += 1;
+            actual_order.push(id);
+        }
+    }
+
+    let mut problems = Vec::new();
+    let duplicates: Vec<String> = actual
+        .iter()
+        .filter(|(id, count)| **count > *expected.get(*id).unwrap_or(&1))
+        .map(|(id, _)| id.clone())
+        .collect();
+    if !duplicates.is_empty() {
+        problems.push(Problem::DuplicateComments(duplicates));
+    }
+    let missing: Vec<String> = expected
+        .keys()
+        .filter(|id| !actual.contains_key(*id))
+        .cloned()
+        .collect();
+    if !missing.is_empty() {
+        problems.push(Problem::MissingComments(missing));
+    }
+    // Compare the order of the comments which made it into the object, ignoring duplicates
+    let mut seen = HashSet::new();
+    let expected_order = issue
+        .comments
+        .iter()
+        .filter(|c| c.author_id.is_some() && actual.contains_key(&c.id))
+        .map(|c| &c.id)
+        .filter(|id| seen.insert(*id));
+    let mut seen = HashSet::new();
+    let actual_order = actual_order
+        .iter()
+        .filter(|id| expected.contains_key(*id))
+        .filter(|id| seen.insert(*id));
+    if let Some((_, id)) = expected_order.zip(actual_order).find(|(e, a)| e != a) {
+        problems.push(Problem::CommentsOutOfOrder(id.clone()));
+    }
+    problems
+}
+
+fn fallback_key(created_at: &str, body: &str) -> String {
+    format!("{}\n{}", created_at, body)
+}
+
+/// The resume boundaries recorded in `failures`, keyed by the number of the first issue which was
+/// imported after resuming
+fn resume_boundaries(
+    downloaded: &[DownloadedIssue],
+    failures: &[Failure],
+) -> HashMap<u64, ResumeBoundary> {
+    let mut numbers: Vec<u64> = downloaded.iter().map(|i| i.number).collect();
+    numbers.sort_unstable();
+    let mut boundaries = HashMap::new();
+    for failure in failures.iter().filter(|f| f.command == "import-issues") {
+        if let Some(Checkpoint::Issue(after_issue)) = failure.resumed_from {
+            let first_after = numbers.iter().find(|n| **n > after_issue);
+            if let Some(first_after) = first_after {
+                boundaries.insert(
+                    *first_after,
+                    ResumeBoundary {
+                        after_issue,
+                        at: failure.at,
+                    },
+                );
+            }
+        }
+    }
+    boundaries
+}
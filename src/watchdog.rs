@@ -0,0 +1,117 @@
+//! A watchdog for cob operations which never return. Overnight imports have occasionally hung
+//! in a single create, update or retrieve, holding up the rest of the run until morning. With
+//! `import-issues --watchdog <secs>` each worker reports the operation it is in through a
+//! [`Heartbeat`], and an issue one of whose operations takes longer than the timeout is given up
+//! on: the operation is logged and recorded in `hung.jsonl` in the storage root, and the issue
+//! is skipped.
+//!
+//! A thread can't be interrupted, so the worker stuck in the operation is abandoned and a new one
+//! takes its place. If the operation does return, the abandoned worker finishes the issue and
+//! exits, so a skipped issue may be partly or completely imported. The backtrace of where the
+//! operation was called from is recorded when backtraces are enabled with `RUST_BACKTRACE=1`,
+//! as capturing one for every operation is too slow to do otherwise.
+use std::{
+    backtrace::{Backtrace, BacktraceStatus},
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Utc};
+
+pub const HUNG_LOG: &str = "hung.jsonl";
+
+/// The operation a worker is in, if any
+#[derive(Clone, Default)]
+pub struct Heartbeat(Arc<Mutex<Option<Operation>>>);
+
+struct Operation {
+    name: &'static str,
+    object: Option<cob::ObjectId>,
+    started: Instant,
+    backtrace: Backtrace,
+}
+
+/// An operation in progress, which is over when this is dropped
+pub struct Beat(Heartbeat);
+
+impl Drop for Beat {
+    fn drop(&mut self) {
+        *(self.0).0.lock().unwrap() = None;
+    }
+}
+
+impl Heartbeat {
+    /// Start the operation `name` on `object`
+    pub fn start(&self, name: &'static str, object: Option<&cob::ObjectId>) -> Beat {
+        *self.0.lock().unwrap() = Some(Operation {
+            name,
+            object: object.copied(),
+            started: Instant::now(),
+            backtrace: Backtrace::capture(),
+        });
+        Beat(self.clone())
+    }
+
+    /// The operation in progress, if it started more than `timeout` ago
+    pub fn overdue(&self, timeout: Duration, issue: u64) -> Option<Hung> {
+        let operation = self.0.lock().unwrap();
+        let operation = operation.as_ref()?;
+        let elapsed = operation.started.elapsed();
+        if elapsed <= timeout {
+            return None;
+        }
+        let backtrace = match operation.backtrace.status() {
+            BacktraceStatus::Captured => Some(operation.backtrace.to_string()),
+            _ => None,
+        };
+        Some(Hung {
+            at: Utc::now(),
+            issue,
+            operation: operation.name.to_string(),
+            object: operation.object.map(|o| o.to_string()),
+            elapsed_secs: elapsed.as_secs_f64(),
+            backtrace,
+        })
+    }
+}
+
+/// An issue which was skipped because an operation on it hung
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Hung {
+    pub at: DateTime<Utc>,
+    pub issue: u64,
+    pub operation: String,
+    pub object: Option<String>,
+    /// How long the operation had been running when it was given up on
+    pub elapsed_secs: f64,
+    /// Where the operation was called from
+    pub backtrace: Option<String>,
+}
+
+/// Append `hung` to the log at `path`
+pub fn record<P: AsRef<Path>>(path: P, hung: &Hung) -> Result<(), std::io::Error> {
+    if let Some(parent) = path.as_ref().parent() {
+        crate::fs::create_dir_all(parent)?;
+    }
+    let mut line = serde_json::to_vec(hung)?;
+    line.push(b'\n');
+    crate::fs::append_line(path, &line)
+}
+
+impl std::fmt::Display for Hung {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "skipping issue {}: {} of {} has been running for {:.0}s",
+            self.issue,
+            self.operation,
+            self.object.as_deref().unwrap_or("a new object"),
+            self.elapsed_secs
+        )?;
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, "\n{}", backtrace)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,157 @@
+//! Importing a repository's history a window of time at a time, to chart how the metrics of
+//! `bench-metrics` change as the collaborative objects of a project accumulate over the years.
+//!
+//! `import-issues --window 2019-01..2019-06` imports the repository as it was at the end of June
+//! 2019: the issues created by then, with the comments and events made by then. Running it again
+//! for the next window adds the issues, comments and events of that window to the same monorepo,
+//! as an incremental import does. After each window the benchmark is run and a checkpoint with
+//! the metrics and the size of the monorepo is appended to `windows.jsonl` in the repository's
+//! storage root.
+use std::{path::Path, str::FromStr};
+
+use chrono::{DateTime, Datelike, NaiveDate, TimeZone, Utc};
+use thiserror::Error;
+
+use crate::{
+    bisect_perf::{self, Metrics},
+    downloaded_issue::DownloadedIssue,
+    lite_monorepo::LiteMonorepo,
+    replication,
+};
+
+#[derive(Debug, Error)]
+pub enum Error {
+    #[error(transparent)]
+    Bench(#[from] bisect_perf::Error),
+    #[error(transparent)]
+    Git(#[from] git2::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub const WINDOWS_LOG: &str = "windows.jsonl";
+
+/// The number of issues `bench-metrics` retrieves after each window
+pub const REQUESTS: usize = 200;
+
+/// From the start of the first month or day to the end of the last, e.g. `2019-01..2019-06` or
+/// `2019-01-01..2019-06-30`
+#[derive(Debug, Clone)]
+pub struct Window {
+    pub start: DateTime<Utc>,
+    /// Exclusive
+    pub end: DateTime<Utc>,
+}
+
+#[derive(Debug, Error)]
+#[error("expected <from>..<to> with each a YYYY-MM month or YYYY-MM-DD day")]
+pub struct ParseWindowError {}
+
+impl FromStr for Window {
+    type Err = ParseWindowError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (from, to) = s.split_once("..").ok_or(ParseWindowError {})?;
+        let (start, _) = parse_bound(from)?;
+        let (_, end) = parse_bound(to)?;
+        if end <= start {
+            return Err(ParseWindowError {});
+        }
+        Ok(Window {
+            start: Utc.from_utc_date(&start).and_hms(0, 0, 0),
+            end: Utc.from_utc_date(&end).and_hms(0, 0, 0),
+        })
+    }
+}
+
+/// The first day of a month or day and the day after it ends
+fn parse_bound(s: &str) -> Result<(NaiveDate, NaiveDate), ParseWindowError> {
+    if let Ok(day) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Ok((day, day.succ()));
+    }
+    let first = NaiveDate::parse_from_str(&format!("{}-01", s), "%Y-%m-%d")
+        .map_err(|_| ParseWindowError {})?;
+    let next = if first.month() == 12 {
+        NaiveDate::from_ymd(first.year() + 1, 1, 1)
+    } else {
+        NaiveDate::from_ymd(first.year(), first.month() + 1, 1)
+    };
+    Ok((first, next))
+}
+
+/// `issue` as it was just before `end`: `None` if it hadn't been created, otherwise without the
+/// comments and events made since
+pub fn as_of(issue: &DownloadedIssue, end: DateTime<Utc>) -> Option<DownloadedIssue> {
+    if issue.created_at >= end {
+        return None;
+    }
+    let mut issue = issue.clone();
+    issue.comments.retain(|c| c.created_at < end);
+    issue.events.retain(|e| e.created_at < end);
+    Some(issue)
+}
+
+/// The state of the monorepo after importing a window
+#[derive(Debug, Clone, serde::Serialize, serde::Deserialize)]
+pub struct Checkpoint {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub objects: usize,
+    /// Changes created importing this window
+    pub changes: u64,
+    pub repo_bytes: u64,
+    pub metrics: Metrics,
+}
+
+/// Benchmark `monorepo` after importing `window`, in which `changes` changes were created
+pub fn checkpoint(
+    monorepo: &LiteMonorepo,
+    window: &Window,
+    changes: u64,
+) -> Result<Checkpoint, Error> {
+    Ok(Checkpoint {
+        start: window.start,
+        end: window.end,
+        objects: replication::object_ids(monorepo.repo())?.len(),
+        changes,
+        repo_bytes: crate::fs::dir_size(monorepo.repo().path())?,
+        metrics: bisect_perf::measure(monorepo, REQUESTS)?,
+    })
+}
+
+pub fn record<P: AsRef<Path>>(path: P, checkpoint: &Checkpoint) -> Result<(), std::io::Error> {
+    let mut line = serde_json::to_vec(checkpoint)?;
+    line.push(b'\n');
+    crate::fs::append_line(path, &line)
+}
+
+/// The checkpoints recorded in the log at `path`, oldest first
+pub fn load<P: AsRef<Path>>(path: P) -> Result<Vec<Checkpoint>, std::io::Error> {
+    if !crate::fs::exists(&path)? {
+        return Ok(Vec::new());
+    }
+    let mut checkpoints = Vec::new();
+    for line in crate::fs::read_to_string(path)?.lines() {
+        if let Ok(checkpoint) = serde_json::from_str(line) {
+            checkpoints.push(checkpoint);
+        }
+    }
+    Ok(checkpoints)
+}
+
+impl std::fmt::Display for Checkpoint {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}..{}: {} objects, {} changes, repository {} bytes, retrieve p50 {:.2}ms p95 {:.2}ms, list {:.2}ms",
+            self.start.date().naive_utc(),
+            self.end.date().naive_utc().pred(),
+            self.objects,
+            self.changes,
+            self.repo_bytes,
+            self.metrics.retrieve_p50,
+            self.metrics.retrieve_p95,
+            self.metrics.list
+        )
+    }
+}